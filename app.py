@@ -93,7 +93,7 @@ def delete_index():
 @st.cache_resource
 def get_engine():
     """Get or create the search engine singleton"""
-    engine = PySearchEngine()
+    engine = PySearchEngine(path="./lmdb_data")
     
     metadata_path = Path("./lmdb_data/metadata.bin")
     
@@ -271,7 +271,9 @@ with tab1:
                     search_log_start = len(log_handler.logs)
                     
                     start_s = time.time()
-                    results = engine.search_complex(active_query, int(top_k), int(blocking_k))
+                    results, diagnostics = engine.search_complex(
+                        active_query, int(top_k), int(blocking_k), diagnostics=True
+                    )
                     search_time_ms = (time.time() - start_s) * 1000
                     
                     # Display timing breakdown
@@ -281,6 +283,19 @@ with tab1:
                     if len(results) > 0:
                         col3.metric("Avg Score", f"{sum(s for _, s in results) / len(results):.2f}")
                     
+                    with st.expander("🩺 Query Diagnostics", expanded=len(results) == 0):
+                        dcol1, dcol2, dcol3, dcol4 = st.columns(4)
+                        dcol1.metric("Candidates", diagnostics["candidates"])
+                        dcol2.metric("Round 1", f"{diagnostics['round1_ms']:.2f}ms")
+                        dcol3.metric("Round 2", f"{diagnostics['round2_ms']:.2f}ms")
+                        dcol4.metric("Fallback", "yes" if diagnostics["fallback"] else "no")
+                        if diagnostics["round1_tokens"]:
+                            st.write("Round 1 tokens: " + ", ".join(
+                                f"{field}:{token}" for field, token in diagnostics["round1_tokens"]
+                            ))
+                        else:
+                            st.write("No query token matched the index.")
+                    
                     # Show detailed timing logs from this search
                     search_logs = [log for log in log_handler.logs[search_log_start:] 
                                   if 'TIMING' in log or 'SEARCH' in log]
@@ -3,13 +3,13 @@ use std::sync::Arc;
 use std::thread;
 use lfas::engine::SearchEngine;
 use lfas::storage::InMemoryStorage;
-use lfas::{RecordField, StructuredQuery};
+use lfas::{DocId, RecordField, StructuredQuery};
 
 fn setup_engine_for_concurrency(size: usize) -> SearchEngine<RecordField, InMemoryStorage<RecordField>> {
     let storage = InMemoryStorage::new();
     let mut engine = SearchEngine::with_storage(storage);
     for i in 0..size {
-        engine.index.add_term(i, RecordField::Rua, "street".to_string());
+        engine.index.add_term(DocId::new(i as u32), RecordField::Rua, "street".to_string()).unwrap();
         engine.metadata.total_docs += 1;
     }
     engine
@@ -30,6 +30,7 @@ fn bench_parallel_queries(c: &mut Criterion) {
                             fields: vec![(RecordField::Rua, "street".to_string())],
                             top_k: 5,
                             blocking_k: 10_000,
+                            ..Default::default()
                         };
                         engine_share.execute(query, 50)
                     }));
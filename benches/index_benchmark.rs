@@ -1,6 +1,6 @@
 use criterion::{criterion_group, criterion_main, Criterion, BatchSize};
 use std::hint::black_box;
-use lfas::RecordField; 
+use lfas::{DocId, RecordField}; 
 use lfas::index::InvertedIndex;
 use lfas::storage::InMemoryStorage;
 
@@ -9,9 +9,9 @@ fn setup_dense_index(size: usize) -> InvertedIndex<RecordField, InMemoryStorage<
     let mut idx = InvertedIndex::new(storage);
     for i in 0..size {
         // Simulate common and rare terms
-        idx.add_term(i, RecordField::Municipio, "belem".to_string());
+        idx.add_term(DocId::new(i as u32), RecordField::Municipio, "belem".to_string()).unwrap();
         if i % 10 == 0 {
-            idx.add_term(i, RecordField::Rua, format!("rua_{}", i));
+            idx.add_term(DocId::new(i as u32), RecordField::Rua, format!("rua_{}", i)).unwrap();
         }
     }
     idx
@@ -23,7 +23,7 @@ fn bench_indexing_operations(c: &mut Criterion) {
     group.bench_function("add_term_single", |b| {
         b.iter_batched(
             || InvertedIndex::new(InMemoryStorage::new()),
-            |mut idx| idx.add_term(black_box(1), RecordField::Rua, black_box("mauriti".to_string())),
+            |mut idx| idx.add_term(black_box(DocId::new(1)), RecordField::Rua, black_box("mauriti".to_string())),
             BatchSize::SmallInput,
         )
     });
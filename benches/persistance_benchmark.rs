@@ -1,7 +1,7 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use lfas::storage::{LmdbStorage, InMemoryStorage, PostingsStorage};
 use lfas::postings::Postings;
-use lfas::RecordField;
+use lfas::{DocId, RecordField};
 use tempfile::tempdir;
 
 fn bench_storage_io(c: &mut Criterion) {
@@ -16,7 +16,7 @@ fn bench_storage_io(c: &mut Criterion) {
 
     let mut postings = Postings::new();
     for i in 0..100 { 
-        postings.add_doc(i); 
+        postings.add_occurrence(DocId::new(i)); 
     }
 
     group.bench_function("lmdb_put_flush", |b| {
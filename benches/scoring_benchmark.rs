@@ -0,0 +1,99 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use std::hint::black_box;
+
+use lfas::{DocId, RecordField};
+use lfas::engine::SearchEngine;
+use lfas::postings::Postings;
+use lfas::scorer::{TermParams, bm25f_contributions};
+use lfas::storage::{InMemoryStorage, PostingsStorage};
+use roaring::RoaringBitmap;
+
+const PARAMS: TermParams = TermParams {
+    weight: 2.0,
+    b: 0.75,
+    avgdl: 4.0,
+    k1: 1.2,
+    scale: 1.7,
+};
+
+/// Term frequencies and field lengths of `n` candidates, struct of arrays.
+fn batch(n: usize) -> (Vec<f32>, Vec<f32>) {
+    let tfs = (0..n).map(|i| (i % 3 + 1) as f32).collect();
+    let dls = (0..n).map(|i| (i % 7 + 1) as f32).collect();
+    (tfs, dls)
+}
+
+/// The per-document loop the scorer ran before batching.
+fn scalar_contributions(tfs: &[f32], dls: &[f32], params: &TermParams, out: &mut [f32]) {
+    for i in 0..tfs.len() {
+        let weighted_tf =
+            (tfs[i] * params.weight) / (1.0 + params.b * (dls[i] / params.avgdl - 1.0));
+        out[i] = params.scale * (weighted_tf / (params.k1 + weighted_tf));
+    }
+}
+
+fn bench_contributions(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Score Accumulation");
+
+    for n in [1_000, 100_000] {
+        let (tfs, dls) = batch(n);
+        let mut out = vec![0.0; n];
+
+        group.bench_with_input(BenchmarkId::new("scalar", n), &n, |b, _| {
+            b.iter(|| scalar_contributions(black_box(&tfs), black_box(&dls), &PARAMS, &mut out))
+        });
+        group.bench_with_input(BenchmarkId::new("batched", n), &n, |b, _| {
+            b.iter(|| bm25f_contributions(black_box(&tfs), black_box(&dls), &PARAMS, &mut out))
+        });
+    }
+    group.finish();
+}
+
+fn bench_score_candidates(c: &mut Criterion) {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let size = 100_000;
+    let postings = Postings::from_sorted((0..size as u32).map(|doc_id| (DocId::new(doc_id), doc_id % 3 + 1)));
+    engine
+        .index
+        .storage
+        .put(RecordField::Rua, "street".to_string(), postings)
+        .unwrap();
+    for doc_id in 0..size {
+        let length = doc_id % 7 + 1;
+        engine
+            .metadata
+            .lengths
+            .entry(DocId::new(doc_id as u32))
+            .or_default()
+            .insert(RecordField::Rua, length);
+        *engine
+            .metadata
+            .total_field_lengths
+            .entry(RecordField::Rua)
+            .or_insert(0) += length;
+    }
+    engine
+        .metadata
+        .term_df
+        .insert((RecordField::Rua, "street".to_string()), size);
+    engine.metadata.total_docs = size;
+    let candidates: RoaringBitmap = (0..size as u32).collect();
+    let tokens = vec![(RecordField::Rua, "street".to_string(), 1.0)];
+
+    let mut group = c.benchmark_group("Score Accumulation");
+    group.sample_size(20);
+    group.bench_function("score_weighted_100k_candidates", |b| {
+        b.iter(|| {
+            engine.scorer.score_weighted(
+                black_box(candidates.clone()),
+                &tokens,
+                &engine.index,
+                &engine.metadata,
+            )
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_contributions, bench_score_candidates);
+criterion_main!(benches);
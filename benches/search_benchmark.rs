@@ -1,38 +1,46 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::hint::black_box;
-use rand::{SeedableRng, rngs::StdRng};
-use fake::{Fake, faker::address::raw::*, locales::*};
+use lfas::testing::AddressGenerator;
 
 use lfas::engine::SearchEngine;
 use lfas::storage::InMemoryStorage;
-use lfas::tokenizer::tokenize;
-use lfas::{RecordField, StructuredQuery};
+use lfas::tokenizer::{TokenizerConfig, tokenize_with};
+use lfas::{DocId, Record, RecordField, StructuredQuery};
 
-fn build_bench_engine(size: usize) -> SearchEngine<RecordField, InMemoryStorage<RecordField>> {
+type BenchEngine = SearchEngine<RecordField, InMemoryStorage<RecordField>>;
+
+fn build_bench_engine(size: usize) -> BenchEngine {
+    build_bench_engine_with(size, TokenizerConfig::default())
+}
+
+fn build_bench_engine_with(size: usize, tokenizer: TokenizerConfig) -> BenchEngine {
     let storage = InMemoryStorage::new();
     let mut engine = SearchEngine::with_storage(storage);
-    let mut rng = StdRng::seed_from_u64(42);
+    engine.tokenizer = tokenizer.clone();
+    let mut generator = AddressGenerator::new(42);
     
     // Default weight configuration for benchmark
     engine.scorer.field_weights.insert(RecordField::Rua, 1.0);
     engine.scorer.field_weights.insert(RecordField::Municipio, 0.5);
 
     for i in 0..size {
-        let municipio: String = CityName(EN).fake_with_rng(&mut rng);
-        let rua: String = StreetName(EN).fake_with_rng(&mut rng);
+        let record = generator.record();
+        let municipio = record.municipio;
+        let rua = format!("{} {}", record.tipo_logradouro, record.rua);
 
         engine.metadata.total_docs += 1;
         
         let fields = [(RecordField::Municipio, municipio), (RecordField::Rua, rua)];
-        let doc_entry = engine.metadata.lengths.entry(i).or_default();
+        let doc_id = DocId::new(i as u32);
+        let doc_entry = engine.metadata.lengths.entry(doc_id).or_default();
 
         for (field, text) in fields {
-            let tokens = tokenize(&text);
+            let tokens = tokenize_with(&text, &tokenizer);
             doc_entry.insert(field, tokens.len());
             *engine.metadata.total_field_lengths.entry(field).or_insert(0) += tokens.len();
             
             for token in tokens {
-                engine.index.add_term(i, field, token.clone());
+                engine.index.add_term(doc_id, field, token.clone()).unwrap();
                 *engine.metadata.term_df.entry((field, token)).or_insert(0) += 1;
             }
         }
@@ -51,6 +59,7 @@ fn bench_search_scenarios(c: &mut Criterion) {
             fields: vec![(RecordField::Rua, "unique_path_777".to_string())],
             top_k: 10,
             blocking_k: 10_000,
+            ..Default::default()
         };
         b.iter(|| engine.execute(black_box(query.clone()), 100))
     });
@@ -58,11 +67,12 @@ fn bench_search_scenarios(c: &mut Criterion) {
     group.bench_function("multi_field_common_terms", |b| {
         let query = StructuredQuery {
             fields: vec![
-                (RecordField::Rua, "Street".to_string()),
-                (RecordField::Municipio, "London".to_string()),
+                (RecordField::Rua, "Rua".to_string()),
+                (RecordField::Municipio, "Belém".to_string()),
             ],
             top_k: 10,
             blocking_k: 10_000,
+            ..Default::default()
         };
         b.iter(|| engine.execute(black_box(query.clone()), 100))
     });
@@ -70,5 +80,120 @@ fn bench_search_scenarios(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, bench_search_scenarios);
+/// Swaps two adjacent characters in the middle of each word, the typo chunked
+/// n-grams handle worst.
+fn transpose(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| {
+            let mut chars: Vec<char> = word.chars().collect();
+            if chars.len() >= 4 {
+                let mid = chars.len() / 2;
+                chars.swap(mid - 1, mid);
+            }
+            chars.into_iter().collect::<String>()
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fraction of typo'd street queries whose source doc lands in the top 10.
+fn typo_recall(engine: &BenchEngine, streets: &[(usize, String)]) -> f64 {
+    let found = streets
+        .iter()
+        .filter(|(doc_id, rua)| {
+            let query = StructuredQuery {
+                fields: vec![(RecordField::Rua, transpose(rua))],
+                top_k: 10,
+                ..Default::default()
+            };
+            engine
+                .execute(query, 10_000)
+                .map(|hits| hits.iter().any(|hit| hit.doc_id.index() == *doc_id))
+                .unwrap_or(false)
+        })
+        .count();
+    found as f64 / streets.len() as f64
+}
+
+fn bench_ngram_modes(c: &mut Criterion) {
+    const SIZE: usize = 5_000;
+    let chunked = build_bench_engine_with(SIZE, TokenizerConfig::default());
+    let sliding = build_bench_engine_with(SIZE, TokenizerConfig::sliding(3, 1));
+
+    // Same seed as the builder, so doc i has the i-th generated street
+    let mut generator = AddressGenerator::new(42);
+    let streets: Vec<(usize, String)> = (0..SIZE)
+        .map(|i| {
+            let record = generator.record();
+            (i, format!("{} {}", record.tipo_logradouro, record.rua))
+        })
+        .step_by(25)
+        .collect();
+
+    println!(
+        "typo recall@10 over {} queries: chunked {:.3}, sliding {:.3}",
+        streets.len(),
+        typo_recall(&chunked, &streets),
+        typo_recall(&sliding, &streets)
+    );
+
+    let mut group = c.benchmark_group("N-gram Modes");
+    group.sample_size(20);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, transpose(&streets[0].1))],
+        top_k: 10,
+        blocking_k: 10_000,
+        ..Default::default()
+    };
+    for (name, engine) in [("chunked", &chunked), ("sliding", &sliding)] {
+        group.bench_function(name, |b| {
+            b.iter(|| engine.execute(black_box(query.clone()), 100))
+        });
+    }
+
+    group.finish();
+}
+
+/// A CEP shared by a few documents, queried with a street number that is in
+/// every document, with and without the CEP shortcut.
+fn bench_cep_shortcut(c: &mut Criterion) {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let mut generator = AddressGenerator::new(42);
+    for i in 0..20_000 {
+        let record = Record {
+            rua: generator.record().rua,
+            numero: "31".into(),
+            cep: format!("{:05}-000", 10_000 + i / 5),
+            ..Default::default()
+        };
+        engine.index_record(i, &record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Cep, "10100-000".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        top_k: 10,
+        ..Default::default()
+    };
+
+    let mut group = c.benchmark_group("CEP Shortcut");
+    group.sample_size(20);
+    for (name, shortcut) in [("full_pipeline", None), ("cep_shortcut", Some(50))] {
+        engine.cep_shortcut = shortcut;
+        group.bench_function(name, |b| {
+            b.iter(|| engine.execute(black_box(query.clone()), 100))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_search_scenarios,
+    bench_ngram_modes,
+    bench_cep_shortcut
+);
 criterion_main!(benches);
\ No newline at end of file
@@ -0,0 +1,4 @@
+fn main() {
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/lfas.proto").expect("failed to compile proto/lfas.proto");
+}
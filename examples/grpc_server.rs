@@ -0,0 +1,24 @@
+//! Serves an LMDB index over gRPC.
+//!
+//! cargo run --example grpc_server --no-default-features --features grpc -- ./lmdb_data 0.0.0.0:50051
+
+use lfas::engine::SearchEngine;
+use lfas::grpc::serve;
+use lfas::storage::LmdbStorage;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let path = args.next().unwrap_or_else(|| "./lmdb_data".to_string());
+    let addr = args.next().unwrap_or_else(|| "127.0.0.1:50051".to_string()).parse()?;
+
+    let mut engine = SearchEngine::with_storage(LmdbStorage::open(Path::new(&path))?);
+    engine.rebuild_metadata(|_| {})?;
+
+    serve(Arc::new(RwLock::new(engine)), addr).await?;
+    Ok(())
+}
@@ -0,0 +1,107 @@
+//! Load shedding for searches under high QPS: at most `max_concurrent`
+//! searches run at once, at most `max_queued` more wait for a slot, and any
+//! search past that is rejected right away with [`LfasError::Overloaded`].
+
+use crate::error::LfasError;
+use std::sync::{Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Concurrency and queue depth limits for [`AdmissionControl`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyLimits {
+    /// Searches running at the same time.
+    pub max_concurrent: usize,
+    /// Searches waiting for a slot; 0 rejects whenever every slot is taken.
+    pub max_queued: usize,
+}
+
+#[derive(Debug, Default)]
+struct Slots {
+    running: usize,
+    queued: usize,
+}
+
+/// Counting semaphore with a bounded wait queue, shared by every search of
+/// an engine through `&self`.
+#[derive(Debug)]
+pub struct AdmissionControl {
+    limits: ConcurrencyLimits,
+    slots: Mutex<Slots>,
+    freed: Condvar,
+}
+
+impl AdmissionControl {
+    pub fn new(limits: ConcurrencyLimits) -> Self {
+        Self {
+            limits: ConcurrencyLimits {
+                max_concurrent: limits.max_concurrent.max(1),
+                ..limits
+            },
+            slots: Mutex::new(Slots::default()),
+            freed: Condvar::new(),
+        }
+    }
+
+    pub fn limits(&self) -> ConcurrencyLimits {
+        self.limits
+    }
+
+    /// Takes a slot, waiting in the queue while all are busy. Fails without
+    /// waiting when the queue is already full.
+    pub fn acquire(&self) -> Result<Permit<'_>, LfasError> {
+        let started = Instant::now();
+        let mut slots = self.slots.lock()?;
+        if slots.running >= self.limits.max_concurrent {
+            if slots.queued >= self.limits.max_queued {
+                return Err(LfasError::Overloaded(format!(
+                    "{} searches running and {} queued",
+                    slots.running, slots.queued
+                )));
+            }
+            slots.queued += 1;
+            while slots.running >= self.limits.max_concurrent {
+                slots = self.freed.wait(slots)?;
+            }
+            slots.queued -= 1;
+        }
+        slots.running += 1;
+        Ok(Permit {
+            control: self,
+            waited: started.elapsed(),
+        })
+    }
+
+    /// Searches holding a slot right now.
+    pub fn running(&self) -> usize {
+        self.slots.lock().map(|slots| slots.running).unwrap_or(0)
+    }
+
+    /// Searches waiting for a slot right now.
+    pub fn queued(&self) -> usize {
+        self.slots.lock().map(|slots| slots.queued).unwrap_or(0)
+    }
+}
+
+/// A held search slot, given back on drop.
+#[derive(Debug)]
+pub struct Permit<'a> {
+    control: &'a AdmissionControl,
+    waited: Duration,
+}
+
+impl Permit<'_> {
+    /// Time spent in the queue before the slot was free.
+    pub fn waited(&self) -> Duration {
+        self.waited
+    }
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        // Release even if a panicking search poisoned the lock
+        let mut slots = self.control.slots.lock().unwrap_or_else(|e| e.into_inner());
+        slots.running -= 1;
+        drop(slots);
+        self.control.freed.notify_one();
+    }
+}
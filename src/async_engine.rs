@@ -0,0 +1,121 @@
+//! Async front for a shared [`SearchEngine`]. Every call runs on tokio's
+//! blocking pool, so storage reads and scoring never stall the runtime's
+//! worker threads.
+
+use crate::DocId;
+use crate::engine::{SearchEngine, TokenizedDoc};
+use crate::error::LfasError;
+use crate::storage::PostingsStorage;
+use crate::{Record, RecordField, SearchHit, StructuredQuery};
+use std::sync::{Arc, RwLock};
+use tokio::task::spawn_blocking;
+
+pub type SharedEngine<S> = Arc<RwLock<SearchEngine<RecordField, S>>>;
+
+/// Cheap to clone; clones share the same engine.
+pub struct AsyncSearchEngine<S>
+where
+    S: PostingsStorage<RecordField>,
+{
+    engine: SharedEngine<S>,
+}
+
+impl<S> Clone for AsyncSearchEngine<S>
+where
+    S: PostingsStorage<RecordField>,
+{
+    fn clone(&self) -> Self {
+        Self {
+            engine: Arc::clone(&self.engine),
+        }
+    }
+}
+
+impl<S> AsyncSearchEngine<S>
+where
+    S: PostingsStorage<RecordField> + Send + Sync + 'static,
+{
+    pub fn new(engine: SearchEngine<RecordField, S>) -> Self {
+        Self::from_shared(Arc::new(RwLock::new(engine)))
+    }
+
+    /// Wraps an engine already shared with sync code (e.g. the gRPC service).
+    pub fn from_shared(engine: SharedEngine<S>) -> Self {
+        Self { engine }
+    }
+
+    pub fn shared(&self) -> SharedEngine<S> {
+        Arc::clone(&self.engine)
+    }
+
+    /// Runs `f` under the engine's read lock on the blocking pool.
+    pub async fn read<R, Op>(&self, f: Op) -> Result<R, LfasError>
+    where
+        Op: FnOnce(&SearchEngine<RecordField, S>) -> Result<R, LfasError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let engine = Arc::clone(&self.engine);
+        spawn_blocking(move || f(&*engine.read()?)).await?
+    }
+
+    /// Runs `f` under the engine's write lock on the blocking pool.
+    pub async fn write<R, Op>(&self, f: Op) -> Result<R, LfasError>
+    where
+        Op: FnOnce(&mut SearchEngine<RecordField, S>) -> Result<R, LfasError> + Send + 'static,
+        R: Send + 'static,
+    {
+        let engine = Arc::clone(&self.engine);
+        spawn_blocking(move || f(&mut *engine.write()?)).await?
+    }
+
+    pub async fn search(
+        &self,
+        query: StructuredQuery<RecordField>,
+    ) -> Result<Vec<SearchHit>, LfasError> {
+        self.read(move |engine| {
+            let blocking_k = query.blocking_k;
+            engine.execute(query, blocking_k)
+        })
+        .await
+    }
+
+    /// Runs several queries in one blocking task under a single read lock,
+    /// cheaper than one `search` per query when a request fans out. Results
+    /// follow the order of `queries`; the first failing query fails the batch.
+    pub async fn search_batch(
+        &self,
+        queries: Vec<StructuredQuery<RecordField>>,
+    ) -> Result<Vec<Vec<SearchHit>>, LfasError> {
+        self.read(move |engine| {
+            queries
+                .into_iter()
+                .map(|query| {
+                    let blocking_k = query.blocking_k;
+                    engine.execute(query, blocking_k)
+                })
+                .collect()
+        })
+        .await
+    }
+
+    /// Tokenizes outside the write lock, then indexes the whole batch at once.
+    /// Call [`flush`](Self::flush) to make it durable.
+    pub async fn index_batch(&self, records: Vec<(usize, Record)>) -> Result<(), LfasError> {
+        let analyzer = self.read(|engine| Ok(engine.analyzer())).await?;
+        let docs = spawn_blocking(move || {
+            records
+                .iter()
+                .map(|(doc_id, record)| {
+                    let doc_id = DocId::try_from(*doc_id)?;
+                    Ok(TokenizedDoc::from_record_with(doc_id, record, &analyzer))
+                })
+                .collect::<Result<Vec<_>, LfasError>>()
+        })
+        .await??;
+        self.write(move |engine| engine.index_tokenized(docs)).await
+    }
+
+    pub async fn flush(&self) -> Result<(), LfasError> {
+        self.write(|engine| engine.flush()).await
+    }
+}
@@ -0,0 +1,330 @@
+//! Offline index builder for corpora whose postings don't fit in RAM.
+//!
+//! Documents are broken into `(field, term, doc, tf)` tuples that are buffered
+//! up to a memory budget, sorted and written to spill files ("runs"). A k-way
+//! merge over the runs then produces each posting list complete and in key
+//! order, which goes straight into [`LmdbStorage::bulk_load`].
+//!
+//! Long builds report where they stand through a [`ProgressReporter`], which
+//! [`ingest`](crate::ingest) and the Python bindings use as well.
+
+use crate::DocId;
+use crate::engine::TokenizedDoc;
+use crate::error::LfasError;
+use crate::postings::Postings;
+use crate::storage::LmdbStorage;
+use serde::{Serialize, de::DeserializeOwned};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{File, create_dir_all, remove_file};
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Bytes of tuples buffered before a run is spilled.
+pub const DEFAULT_MEMORY_BUDGET: usize = 256 * 1024 * 1024;
+
+/// Documents between two progress reports unless the caller picks another
+/// interval.
+pub const DEFAULT_PROGRESS_INTERVAL: usize = 10_000;
+
+type Tuple<F> = (F, String, DocId, u32);
+
+/// Where a long build stands, handed to a [`ProgressReporter`] callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BuildProgress {
+    pub docs: usize,
+    /// Tokens indexed so far, one per (field, term, doc).
+    pub tokens: u64,
+    /// Spill files written by an [`IndexBuilder`], or storage commits made
+    /// while ingesting through the engine.
+    pub flushes: usize,
+    pub elapsed: Duration,
+    /// Time left at the rate so far; `None` unless the total is known.
+    pub eta: Option<Duration>,
+}
+
+/// Calls back every `every` documents, and once more when the build ends.
+pub struct ProgressReporter<'a> {
+    every: usize,
+    expected_docs: Option<usize>,
+    started: Instant,
+    next_report: usize,
+    callback: Box<dyn FnMut(&BuildProgress) + 'a>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    pub fn new(every: usize, callback: impl FnMut(&BuildProgress) + 'a) -> Self {
+        let every = every.max(1);
+        Self {
+            every,
+            expected_docs: None,
+            started: Instant::now(),
+            next_report: every,
+            callback: Box::new(callback),
+        }
+    }
+
+    /// Documents the whole build will add, for the ETA.
+    pub fn expected_docs(mut self, docs: usize) -> Self {
+        self.expected_docs = Some(docs);
+        self
+    }
+
+    /// Reports once `docs` reaches the next multiple of the interval. A batch
+    /// crossing several of them reports once.
+    pub fn observe(&mut self, docs: usize, tokens: u64, flushes: usize) {
+        if docs >= self.next_report {
+            self.next_report = (docs / self.every + 1) * self.every;
+            self.report(docs, tokens, flushes);
+        }
+    }
+
+    /// Reports regardless of the interval, e.g. when the build ends.
+    pub fn report(&mut self, docs: usize, tokens: u64, flushes: usize) {
+        let elapsed = self.started.elapsed();
+        let eta = self.expected_docs.filter(|_| docs > 0).map(|expected| {
+            let remaining = expected.saturating_sub(docs);
+            elapsed.mul_f64(remaining as f64 / docs as f64)
+        });
+        (self.callback)(&BuildProgress {
+            docs,
+            tokens,
+            flushes,
+            elapsed,
+            eta,
+        });
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildReport {
+    pub docs: usize,
+    /// Distinct (field, term) posting lists written.
+    pub terms: usize,
+    /// (field, term, doc) entries across all posting lists.
+    pub tuples: u64,
+    /// Spill files written, including the final one.
+    pub runs: usize,
+}
+
+/// Sort-based builder: add documents, then [`finish`](Self::finish) into an
+/// empty index. Only postings are built; stored values and external ids stay
+/// with the caller, and the engine needs `rebuild_metadata` once it opens
+/// the result.
+pub struct IndexBuilder<F> {
+    spill_dir: PathBuf,
+    memory_budget: usize,
+    buffer: Vec<Tuple<F>>,
+    buffered_bytes: usize,
+    runs: Vec<PathBuf>,
+    docs: usize,
+    tokens: u64,
+    progress: Option<ProgressReporter<'static>>,
+}
+
+impl<F> IndexBuilder<F>
+where
+    F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned,
+{
+    /// Spill files go to `spill_dir`, which is created if missing.
+    pub fn new(spill_dir: &Path) -> Result<Self, LfasError> {
+        create_dir_all(spill_dir)?;
+        Ok(Self {
+            spill_dir: spill_dir.to_path_buf(),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            buffer: Vec::new(),
+            buffered_bytes: 0,
+            runs: Vec::new(),
+            docs: 0,
+            tokens: 0,
+            progress: None,
+        })
+    }
+
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Reports documents added, tokens buffered and runs spilled through
+    /// `reporter`, and once more after the merge.
+    pub fn progress(mut self, reporter: ProgressReporter<'static>) -> Self {
+        self.progress = Some(reporter);
+        self
+    }
+
+    pub fn add(&mut self, doc: &TokenizedDoc<F>) -> Result<(), LfasError> {
+        for (field, tfs) in &doc.fields {
+            for (token, tf) in tfs {
+                self.buffered_bytes += std::mem::size_of::<Tuple<F>>() + token.len();
+                self.buffer.push((*field, token.clone(), doc.doc_id, *tf));
+            }
+            self.tokens += tfs.len() as u64;
+        }
+        self.docs += 1;
+
+        if self.buffered_bytes >= self.memory_budget {
+            self.spill()?;
+        }
+        if let Some(progress) = &mut self.progress {
+            progress.observe(self.docs, self.tokens, self.runs.len());
+        }
+        Ok(())
+    }
+
+    /// Adds a whole posting list of `term` in `field`, e.g. one read from an
+    /// existing index. Lists of the same term are unioned by the merge, so
+    /// their doc ids must not overlap. Doesn't count towards `docs`.
+    pub fn add_postings(
+        &mut self,
+        field: F,
+        term: &str,
+        entries: impl IntoIterator<Item = (DocId, u32)>,
+    ) -> Result<(), LfasError> {
+        for (doc_id, tf) in entries {
+            self.buffered_bytes += std::mem::size_of::<Tuple<F>>() + term.len();
+            self.buffer.push((field, term.to_string(), doc_id, tf));
+            self.tokens += 1;
+        }
+
+        if self.buffered_bytes >= self.memory_budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Sorts the buffer and writes it out as one run.
+    fn spill(&mut self) -> Result<(), LfasError> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        self.buffer
+            .sort_unstable_by(|a, b| (a.0, &a.1, a.2).cmp(&(b.0, &b.1, b.2)));
+
+        let path = self
+            .spill_dir
+            .join(format!("run-{:05}.bin", self.runs.len()));
+        let mut writer = BufWriter::new(File::create(&path)?);
+        for tuple in self.buffer.drain(..) {
+            bincode::serialize_into(&mut writer, &tuple)?;
+        }
+        writer.into_inner().map_err(|e| e.into_error())?;
+
+        info!(
+            "[BUILD] Spilled run {} ({} bytes buffered)",
+            self.runs.len(),
+            self.buffered_bytes
+        );
+        self.buffered_bytes = 0;
+        self.runs.push(path);
+        Ok(())
+    }
+
+    /// Merges every run into `storage` and removes the spill files.
+    pub fn finish(mut self, storage: &mut LmdbStorage<F>) -> Result<BuildReport, LfasError> {
+        self.spill()?;
+        let mut merger = RunMerger::open(&self.runs)?;
+        let mut failed = None;
+        let mut tuples = 0;
+
+        let entries = std::iter::from_fn(|| match merger.next_postings() {
+            Ok(Some((field, term, postings))) => {
+                tuples += postings.len() as u64;
+                Some((field, term, postings))
+            }
+            Ok(None) => None,
+            Err(e) => {
+                failed = Some(e);
+                None
+            }
+        });
+        let terms = storage.bulk_load(entries)?;
+        if let Some(e) = failed {
+            return Err(e);
+        }
+
+        let report = BuildReport {
+            docs: self.docs,
+            terms,
+            tuples,
+            runs: self.runs.len(),
+        };
+        if let Some(progress) = &mut self.progress {
+            progress.report(self.docs, self.tokens, self.runs.len());
+        }
+        info!(
+            "[BUILD] Merged {} runs into {} terms ({} docs)",
+            report.runs, report.terms, report.docs
+        );
+        Ok(report)
+    }
+}
+
+impl<F> Drop for IndexBuilder<F> {
+    fn drop(&mut self) {
+        for run in &self.runs {
+            let _ = remove_file(run);
+        }
+    }
+}
+
+fn is_end_of_run(e: &bincode::ErrorKind) -> bool {
+    matches!(e, bincode::ErrorKind::Io(io) if io.kind() == ErrorKind::UnexpectedEof)
+}
+
+/// k-way merge over sorted runs.
+struct RunMerger<F> {
+    readers: Vec<BufReader<File>>,
+    heap: BinaryHeap<Reverse<(Tuple<F>, usize)>>,
+}
+
+impl<F> RunMerger<F>
+where
+    F: Ord + Copy + DeserializeOwned,
+{
+    fn open(runs: &[PathBuf]) -> Result<Self, LfasError> {
+        let mut merger = Self {
+            readers: Vec::with_capacity(runs.len()),
+            heap: BinaryHeap::with_capacity(runs.len()),
+        };
+        for path in runs {
+            merger.readers.push(BufReader::new(File::open(path)?));
+            merger.advance(merger.readers.len() - 1)?;
+        }
+        Ok(merger)
+    }
+
+    /// Pushes the next tuple of run `run` onto the heap, if any.
+    fn advance(&mut self, run: usize) -> Result<(), LfasError> {
+        match bincode::deserialize_from::<_, Tuple<F>>(&mut self.readers[run]) {
+            Ok(tuple) => self.heap.push(Reverse((tuple, run))),
+            Err(e) if is_end_of_run(&e) => {}
+            Err(e) => return Err(e.into()),
+        }
+        Ok(())
+    }
+
+    /// The next complete posting list in (field, term) order.
+    fn next_postings(&mut self) -> Result<Option<(F, String, Postings)>, LfasError> {
+        let Some(Reverse(((field, term, doc_id, tf), run))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        self.advance(run)?;
+
+        let mut entries = vec![(doc_id, tf)];
+        while let Some(Reverse(((next_field, next_term, _, _), _))) = self.heap.peek() {
+            if *next_field != field || *next_term != term {
+                break;
+            }
+            let Some(Reverse(((_, _, doc_id, tf), run))) = self.heap.pop() else {
+                break;
+            };
+            entries.push((doc_id, tf));
+            self.advance(run)?;
+        }
+        Ok(Some((field, term, Postings::from_sorted(entries))))
+    }
+}
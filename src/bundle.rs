@@ -0,0 +1,241 @@
+//! Single-file `.lfas` bundles for shipping an LMDB index between machines.
+//! A bundle is a zstd-compressed tar holding `data.mdb` (postings, committed
+//! metadata and config), the external id map and a [`MANIFEST_FILE`] of
+//! checksums. Opening one unpacks it once into a local cache, checks it
+//! against the manifest and opens it read-only from there.
+
+use crate::RecordField;
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::provenance::SourceFile;
+use crate::shard::stable_hash;
+use crate::storage::{LmdbOptions, LmdbStorage};
+use crate::timing::Timer;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+/// Checksums of every archived file, one `<fnv-1a hex> <bytes> <name>` line each.
+pub const MANIFEST_FILE: &str = "MANIFEST";
+
+/// The engine's `id_map`, bincode-encoded.
+pub const ID_MAP_FILE: &str = "id_map.bin";
+
+/// Overrides the cache directory bundles are unpacked into.
+pub const CACHE_DIR_ENV: &str = "LFAS_CACHE_DIR";
+
+const LMDB_DATA_FILE: &str = "data.mdb";
+
+/// Written by every reader that opens the env, so never part of an archive.
+const LMDB_LOCK_FILE: &str = "lock.mdb";
+
+fn bundle_error(e: impl std::fmt::Display) -> LfasError {
+    LfasError::Storage(format!("index bundle: {}", e))
+}
+
+/// `$LFAS_CACHE_DIR`, or `lfas-cache` under the system temp dir.
+pub fn default_cache_dir() -> PathBuf {
+    std::env::var_os(CACHE_DIR_ENV)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| std::env::temp_dir().join("lfas-cache"))
+}
+
+/// Writes the manifest of an index directory before it is archived. The
+/// directory must be flat, as LMDB envs are.
+pub fn write_manifest(dir: &Path) -> Result<(), LfasError> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if entry.file_type()?.is_file() && name != MANIFEST_FILE && name != LMDB_LOCK_FILE {
+            names.push(name);
+        }
+    }
+    names.sort();
+
+    let mut manifest = BufWriter::new(File::create(dir.join(MANIFEST_FILE))?);
+    for name in names {
+        let file = SourceFile::from_path(&dir.join(&name))?;
+        writeln!(manifest, "{:016x} {} {}", file.hash, file.bytes, name)?;
+    }
+    manifest.flush()?;
+    Ok(())
+}
+
+/// Checks every file listed in the manifest of `dir` against its size and hash.
+pub fn verify_manifest(dir: &Path) -> Result<(), LfasError> {
+    let manifest = fs::read_to_string(dir.join(MANIFEST_FILE))
+        .map_err(|e| bundle_error(format!("no readable {}: {}", MANIFEST_FILE, e)))?;
+
+    let mut files = 0;
+    for line in manifest.lines().filter(|line| !line.trim().is_empty()) {
+        let mut parts = line.splitn(3, ' ');
+        let (Some(hash), Some(bytes), Some(name)) = (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(bundle_error(format!("malformed manifest line '{}'", line)));
+        };
+        let hash = u64::from_str_radix(hash, 16)
+            .map_err(|_| bundle_error(format!("malformed manifest line '{}'", line)))?;
+        let bytes: u64 = bytes
+            .parse()
+            .map_err(|_| bundle_error(format!("malformed manifest line '{}'", line)))?;
+        // Names only: a manifest must not point outside the directory
+        if Path::new(name).file_name() != Some(OsStr::new(name)) {
+            return Err(bundle_error(format!("invalid manifest entry '{}'", name)));
+        }
+
+        let actual = SourceFile::from_path(&dir.join(name))?;
+        if actual.bytes != bytes || actual.hash != hash {
+            return Err(bundle_error(format!("checksum mismatch for {}", name)));
+        }
+        files += 1;
+    }
+    if files == 0 {
+        return Err(bundle_error(format!("{} lists no files", MANIFEST_FILE)));
+    }
+    Ok(())
+}
+
+/// Writes the manifest of the index at `dir` and archives both into
+/// `archive`. Close any writer on `dir` first.
+pub fn pack(dir: &Path, archive: &Path) -> Result<(), LfasError> {
+    write_manifest(dir)?;
+    let encoder = zstd::Encoder::new(File::create(archive)?, 0)?;
+    let mut builder = tar::Builder::new(encoder);
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() && entry.file_name() != LMDB_LOCK_FILE {
+            builder.append_path_with_name(entry.path(), entry.file_name())?;
+        }
+    }
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+fn unpack(archive: &Path, target: &Path) -> Result<(), LfasError> {
+    let decoder = zstd::Decoder::new(File::open(archive)?)?;
+    tar::Archive::new(decoder).unpack(target)?;
+    Ok(())
+}
+
+/// Cache directory name for one version of the archive at `location`.
+pub(crate) fn cache_entry(location: &str, version: &str) -> String {
+    format!(
+        "{:016x}",
+        stable_hash(format!("{}\n{}", location, version).as_bytes())
+    )
+}
+
+/// Size and mtime of a local file, so a replaced bundle gets a fresh cache entry.
+pub(crate) fn local_version(path: &Path) -> Result<String, LfasError> {
+    let meta = fs::metadata(path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos())
+        .unwrap_or_default();
+    Ok(format!("{}:{}", meta.len(), modified))
+}
+
+/// Unpacks `archive` into `cache_dir/entry` once its manifest checks out;
+/// nothing is left behind when it doesn't.
+pub(crate) fn install(archive: &Path, cache_dir: &Path, entry: &str) -> Result<PathBuf, LfasError> {
+    let target = cache_dir.join(entry);
+    let staging = cache_dir.join(format!("{}.partial", entry));
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    let unpacked = unpack(archive, &staging).and_then(|_| verify_manifest(&staging));
+    if let Err(e) = unpacked {
+        warn!("[BUNDLE] Discarding {}: {}", archive.display(), e);
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    // Another process may have finished the same entry first; theirs is as good
+    if let Err(e) = fs::rename(&staging, &target) {
+        if !target.is_dir() {
+            return Err(e.into());
+        }
+        let _ = fs::remove_dir_all(&staging);
+    }
+    info!(
+        "[BUNDLE] Unpacked {} to {}",
+        archive.display(),
+        target.display()
+    );
+    Ok(target)
+}
+
+impl SearchEngine<RecordField, LmdbStorage<RecordField>> {
+    /// Writes the index to a single bundle file at `path`. A writable engine
+    /// saves its config and flushes first, so the bundle matches the engine.
+    pub fn export_bundle(&mut self, path: &Path) -> Result<(), LfasError> {
+        let timer = Timer::new("SearchEngine::export_bundle");
+        if !self.index.storage.is_read_only() {
+            self.save_config()?;
+        }
+
+        // Staged next to the bundle: data.mdb may not fit in a tmpfs
+        let parent = path
+            .parent()
+            .filter(|parent| !parent.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let staging = tempfile::Builder::new()
+            .prefix(".lfas-bundle")
+            .tempdir_in(parent)?;
+        fs::copy(
+            self.index.storage.path().join(LMDB_DATA_FILE),
+            staging.path().join(LMDB_DATA_FILE),
+        )?;
+        fs::write(
+            staging.path().join(ID_MAP_FILE),
+            bincode::serialize(&self.id_map)?,
+        )?;
+        pack(staging.path(), path)?;
+
+        drop(timer);
+        info!(
+            "[BUNDLE] Exported {} docs to {}",
+            self.metadata.total_docs,
+            path.display()
+        );
+        Ok(())
+    }
+
+    /// Opens the bundle at `path` read-only, unpacked into
+    /// [`default_cache_dir`].
+    pub fn open_bundle(path: &Path) -> Result<Self, LfasError> {
+        Self::open_bundle_with_cache(path, &default_cache_dir())
+    }
+
+    /// Like [`open_bundle`](Self::open_bundle), unpacking under `cache_dir`.
+    /// A bundle already unpacked there is reused until the file changes.
+    pub fn open_bundle_with_cache(path: &Path, cache_dir: &Path) -> Result<Self, LfasError> {
+        let location = fs::canonicalize(path)?;
+        let entry = cache_entry(&location.to_string_lossy(), &local_version(path)?);
+        let mut dir = cache_dir.join(&entry);
+        if !dir.is_dir() {
+            fs::create_dir_all(cache_dir)?;
+            dir = install(path, cache_dir, &entry)?;
+        }
+        Self::open_unpacked(&dir)
+    }
+
+    /// Opens a directory unpacked from a bundle or archive, read-only.
+    pub(crate) fn open_unpacked(dir: &Path) -> Result<Self, LfasError> {
+        let storage = LmdbStorage::open_with_options(dir, LmdbOptions::new().read_only(true))?;
+        let mut engine = SearchEngine::with_storage(storage);
+        engine.load_config()?;
+        engine.refresh()?;
+        // Plain index archives carry no id map
+        let id_map = dir.join(ID_MAP_FILE);
+        if id_map.is_file() {
+            engine.set_id_map(bincode::deserialize(&fs::read(id_map)?)?);
+        }
+        Ok(engine)
+    }
+}
@@ -0,0 +1,204 @@
+//! Round 1 candidate generation as a plug-in point. A [`CandidateGenerator`]
+//! turns the analyzed field clauses of a query into a set of documents worth
+//! scoring; an engine with generators set unions their blocks instead of
+//! running its built-in [`BlockingStrategy`](crate::engine::BlockingStrategy).
+
+use crate::engine::DEFAULT_FALLBACK_TOKENS;
+use crate::index::InvertedIndex;
+use crate::postings::Postings;
+use crate::storage::InMemoryStorage;
+use crate::tokenizer::TokenSet;
+use roaring::RoaringBitmap;
+use std::hash::Hash;
+
+/// What a generator sees of the index.
+pub trait TermLookup<F> {
+    /// Postings of `term` in `field`, `None` when it isn't indexed.
+    fn postings(&self, field: F, term: &str) -> Option<Postings>;
+    /// Documents holding `term` in `field`, from the metadata.
+    fn df(&self, field: F, term: &str) -> usize;
+    fn total_docs(&self) -> usize;
+}
+
+/// One field clause of the query, tokenized like the index.
+pub struct Clause<F> {
+    pub field: F,
+    pub tokens: TokenSet,
+}
+
+/// Documents proposed by a generator, with the (field, token) pairs whose
+/// postings found them (reported in the query diagnostics).
+#[derive(Debug, Clone)]
+pub struct Block<F> {
+    pub docs: RoaringBitmap,
+    pub tokens: Vec<(F, String)>,
+}
+
+impl<F> Default for Block<F> {
+    fn default() -> Self {
+        Self {
+            docs: RoaringBitmap::new(),
+            tokens: Vec::new(),
+        }
+    }
+}
+
+impl<F> Block<F> {
+    fn add(&mut self, field: F, token: &str, postings: &Postings) {
+        self.docs |= postings.bitmap();
+        self.tokens.push((field, token.to_string()));
+    }
+}
+
+/// A Round 1 blocker. `blocking_k` is the query's candidate budget, which a
+/// generator may stop at.
+pub trait CandidateGenerator<F>: Send + Sync {
+    fn generate(
+        &self,
+        clauses: &[Clause<F>],
+        index: &dyn TermLookup<F>,
+        blocking_k: usize,
+    ) -> Block<F>;
+}
+
+/// Documents holding any distinctive token (CEPs, house numbers, UFs).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DistinctiveUnion;
+
+impl<F: Copy> CandidateGenerator<F> for DistinctiveUnion {
+    fn generate(
+        &self,
+        clauses: &[Clause<F>],
+        index: &dyn TermLookup<F>,
+        _blocking_k: usize,
+    ) -> Block<F> {
+        let mut block = Block::default();
+        for clause in clauses {
+            for token in &clause.tokens.distinctive {
+                if let Some(postings) = index.postings(clause.field, token) {
+                    block.add(clause.field, token, &postings);
+                }
+            }
+        }
+        block
+    }
+}
+
+/// Documents holding every known full token of some clause; tokens that
+/// aren't indexed (typos) don't veto the clause.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FieldIntersection;
+
+impl<F: Copy> CandidateGenerator<F> for FieldIntersection {
+    fn generate(
+        &self,
+        clauses: &[Clause<F>],
+        index: &dyn TermLookup<F>,
+        _blocking_k: usize,
+    ) -> Block<F> {
+        let mut block = Block::default();
+        for clause in clauses {
+            let mut docs: Option<RoaringBitmap> = None;
+            for token in clause.tokens.all.difference(&clause.tokens.weak) {
+                let Some(postings) = index.postings(clause.field, token) else {
+                    continue;
+                };
+                block.tokens.push((clause.field, token.clone()));
+                docs = Some(match docs {
+                    Some(docs) => docs & postings.bitmap(),
+                    None => postings.bitmap().clone(),
+                });
+            }
+            if let Some(docs) = docs {
+                block.docs |= docs;
+            }
+        }
+        block
+    }
+}
+
+/// Documents holding the rarest full tokens of the query, rarest first,
+/// until `tokens` of them are used or `blocking_k` documents are found.
+#[derive(Debug, Clone, Copy)]
+pub struct RareTokens {
+    pub tokens: usize,
+    /// Tokens in more documents than this are never used.
+    pub max_df: Option<usize>,
+}
+
+impl Default for RareTokens {
+    fn default() -> Self {
+        Self {
+            tokens: DEFAULT_FALLBACK_TOKENS,
+            max_df: None,
+        }
+    }
+}
+
+impl<F: Copy> CandidateGenerator<F> for RareTokens {
+    fn generate(
+        &self,
+        clauses: &[Clause<F>],
+        index: &dyn TermLookup<F>,
+        blocking_k: usize,
+    ) -> Block<F> {
+        let mut by_df: Vec<(usize, F, &String)> = Vec::new();
+        for clause in clauses {
+            for token in clause.tokens.all.difference(&clause.tokens.weak) {
+                let df = index.df(clause.field, token);
+                if df > 0 && self.max_df.is_none_or(|max_df| df <= max_df) {
+                    by_df.push((df, clause.field, token));
+                }
+            }
+        }
+        // Ties broken by token so the block doesn't depend on HashSet order
+        by_df.sort_by(|a, b| a.0.cmp(&b.0).then_with(|| a.2.cmp(b.2)));
+
+        let mut block = Block::default();
+        for (_, field, token) in by_df.into_iter().take(self.tokens) {
+            if block.docs.len() >= blocking_k as u64 {
+                break;
+            }
+            if let Some(postings) = index.postings(field, token) {
+                block.add(field, token, &postings);
+            }
+        }
+        block
+    }
+}
+
+/// Documents sharing at least `min_shared` n-grams with some clause, for
+/// misspelled values no full token matches.
+#[derive(Debug, Clone, Copy)]
+pub struct NgramOverlap {
+    pub min_shared: usize,
+}
+
+impl Default for NgramOverlap {
+    fn default() -> Self {
+        Self { min_shared: 2 }
+    }
+}
+
+impl<F: Hash + Eq + Clone + Ord + Copy> CandidateGenerator<F> for NgramOverlap {
+    fn generate(
+        &self,
+        clauses: &[Clause<F>],
+        index: &dyn TermLookup<F>,
+        _blocking_k: usize,
+    ) -> Block<F> {
+        let mut block = Block::default();
+        for clause in clauses {
+            let mut bitmaps = Vec::new();
+            for token in &clause.tokens.weak {
+                if let Some(postings) = index.postings(clause.field, token) {
+                    bitmaps.push(postings.bitmap().clone());
+                    block.tokens.push((clause.field, token.clone()));
+                }
+            }
+            let min_shared = self.min_shared.min(clause.tokens.weak.len()).max(1);
+            block.docs |= InvertedIndex::<F, InMemoryStorage<F>>::at_least(&bitmaps, min_shared);
+        }
+        block
+    }
+}
@@ -0,0 +1,271 @@
+//! Shadow-read A/B comparison of two engines, e.g. a scorer change against
+//! the settings in production. Every query runs on both: the control's hits
+//! are returned, the candidate's ranking is compared with them and the
+//! differences are tallied into a [`ComparisonReport`].
+//!
+//! Comparing two configs of one LMDB index needs no second copy of it: pin
+//! two [`searcher`](SearchEngine::searcher)s and apply the new config to one.
+
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::storage::PostingsStorage;
+use crate::{DocId, SearchHit, StructuredQuery};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Queries ranked less alike than this Kendall tau count as divergences.
+pub const DEFAULT_DIVERGENCE_TAU: f64 = 0.9;
+
+/// Divergent queries kept in a report; later ones are only counted.
+pub const DEFAULT_MAX_DIVERGENCES: usize = 100;
+
+/// One query run on both engines, with the doc ids each returned in rank order.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QueryComparison<F> {
+    pub fields: Vec<(F, String)>,
+    pub control: Vec<DocId>,
+    pub candidate: Vec<DocId>,
+    pub kendall_tau: f64,
+    pub top1_agrees: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ComparisonReport<F> {
+    /// Queries compared; candidate failures are not among them.
+    pub queries: usize,
+    pub mean_kendall_tau: f64,
+    /// Fraction of queries whose best hit is the same on both engines.
+    pub top1_agreement: f64,
+    /// Queries whose best hit differs or whose tau is below the threshold.
+    pub divergent: usize,
+    /// The first divergent queries, at most
+    /// [`ComparisonEngine::max_divergences`].
+    pub divergences: Vec<QueryComparison<F>>,
+    pub candidate_errors: usize,
+}
+
+impl<F> Default for ComparisonReport<F> {
+    fn default() -> Self {
+        Self {
+            queries: 0,
+            mean_kendall_tau: 0.0,
+            top1_agreement: 0.0,
+            divergent: 0,
+            divergences: Vec::new(),
+            candidate_errors: 0,
+        }
+    }
+}
+
+/// Running sums behind a [`ComparisonReport`].
+struct Tally<F> {
+    queries: usize,
+    tau_sum: f64,
+    top1_agreements: usize,
+    divergent: usize,
+    divergences: Vec<QueryComparison<F>>,
+    candidate_errors: usize,
+}
+
+impl<F> Default for Tally<F> {
+    fn default() -> Self {
+        Self {
+            queries: 0,
+            tau_sum: 0.0,
+            top1_agreements: 0,
+            divergent: 0,
+            divergences: Vec::new(),
+            candidate_errors: 0,
+        }
+    }
+}
+
+/// Kendall tau-b between two rankings of doc ids, best first. Documents only
+/// one ranking returned tie below its last hit in the other, so a candidate
+/// that swaps in new documents scores lower than one that only reorders.
+/// 1.0 for identical rankings, -1.0 for reversed ones.
+pub fn kendall_tau(control: &[DocId], candidate: &[DocId]) -> f64 {
+    if control == candidate {
+        return 1.0;
+    }
+    let ranks = |ranking: &[DocId]| -> HashMap<DocId, usize> {
+        ranking
+            .iter()
+            .enumerate()
+            .map(|(rank, doc_id)| (*doc_id, rank))
+            .collect()
+    };
+    let (control_ranks, candidate_ranks) = (ranks(control), ranks(candidate));
+    let rank = |ranks: &HashMap<DocId, usize>, doc_id: DocId| {
+        ranks.get(&doc_id).copied().unwrap_or(ranks.len())
+    };
+
+    let mut docs: Vec<DocId> = control.iter().chain(candidate).copied().collect();
+    docs.sort_unstable();
+    docs.dedup();
+
+    let (mut concordant, mut discordant) = (0i64, 0i64);
+    let (mut control_ties, mut candidate_ties) = (0i64, 0i64);
+    for (i, &first) in docs.iter().enumerate() {
+        for &second in &docs[i + 1..] {
+            let a = rank(&control_ranks, first).cmp(&rank(&control_ranks, second));
+            let b = rank(&candidate_ranks, first).cmp(&rank(&candidate_ranks, second));
+            match (a.is_eq(), b.is_eq()) {
+                (true, true) => {
+                    control_ties += 1;
+                    candidate_ties += 1;
+                }
+                (true, false) => control_ties += 1,
+                (false, true) => candidate_ties += 1,
+                (false, false) if a == b => concordant += 1,
+                (false, false) => discordant += 1,
+            }
+        }
+    }
+
+    let pairs = (docs.len() * docs.len().saturating_sub(1) / 2) as i64;
+    let denominator = (((pairs - control_ties) * (pairs - candidate_ties)) as f64).sqrt();
+    if denominator == 0.0 {
+        return 0.0;
+    }
+    (concordant - discordant) as f64 / denominator
+}
+
+/// Runs every query on a control and a candidate engine and records how far
+/// the candidate's ranking strays. Searches take `&self`, so it can stand in
+/// for the control wherever queries are served.
+pub struct ComparisonEngine<F, A, B>
+where
+    F: Hash + Eq + Clone + Ord + Copy,
+    A: PostingsStorage<F>,
+    B: PostingsStorage<F>,
+{
+    pub control: SearchEngine<F, A>,
+    pub candidate: SearchEngine<F, B>,
+    pub divergence_tau: f64,
+    pub max_divergences: usize,
+    tally: Mutex<Tally<F>>,
+}
+
+impl<F, A, B> ComparisonEngine<F, A, B>
+where
+    F: Hash + Eq + Clone + Ord + Copy + std::fmt::Debug,
+    A: PostingsStorage<F>,
+    B: PostingsStorage<F>,
+{
+    pub fn new(control: SearchEngine<F, A>, candidate: SearchEngine<F, B>) -> Self {
+        Self {
+            control,
+            candidate,
+            divergence_tau: DEFAULT_DIVERGENCE_TAU,
+            max_divergences: DEFAULT_MAX_DIVERGENCES,
+            tally: Mutex::new(Tally::default()),
+        }
+    }
+
+    pub fn with_divergence_tau(mut self, tau: f64) -> Self {
+        self.divergence_tau = tau;
+        self
+    }
+
+    pub fn with_max_divergences(mut self, max_divergences: usize) -> Self {
+        self.max_divergences = max_divergences;
+        self
+    }
+
+    /// Runs `query` on both engines and returns the control's hits. A failing
+    /// candidate is counted, never returned: the shadow must not break reads.
+    pub fn execute(&self, query: StructuredQuery<F>) -> Result<Vec<SearchHit>, LfasError> {
+        let fields = query.fields.clone();
+        let control = self.control.execute_interruptible(query.clone())?.hits;
+        match self.candidate.execute_interruptible(query) {
+            Ok(candidate) => {
+                self.record(fields, &control, &candidate.hits);
+            }
+            Err(e) => {
+                warn!("[COMPARE] Candidate failed on {:?}: {}", fields, e);
+                self.lock().candidate_errors += 1;
+            }
+        }
+        Ok(control)
+    }
+
+    /// Runs all of `queries` and returns the report so far.
+    pub fn run<I>(&self, queries: I) -> Result<ComparisonReport<F>, LfasError>
+    where
+        I: IntoIterator<Item = StructuredQuery<F>>,
+    {
+        for query in queries {
+            self.execute(query)?;
+        }
+        let report = self.report();
+        info!(
+            "[COMPARE] {} queries, mean tau {:.4}, top-1 agreement {:.4}, {} divergent",
+            report.queries, report.mean_kendall_tau, report.top1_agreement, report.divergent
+        );
+        Ok(report)
+    }
+
+    /// Comparison of every query run since creation or the last [`reset`](Self::reset).
+    pub fn report(&self) -> ComparisonReport<F> {
+        let tally = self.lock();
+        let mean = |total: f64| {
+            if tally.queries == 0 {
+                0.0
+            } else {
+                total / tally.queries as f64
+            }
+        };
+        ComparisonReport {
+            queries: tally.queries,
+            mean_kendall_tau: mean(tally.tau_sum),
+            top1_agreement: mean(tally.top1_agreements as f64),
+            divergent: tally.divergent,
+            divergences: tally.divergences.clone(),
+            candidate_errors: tally.candidate_errors,
+        }
+    }
+
+    pub fn reset(&self) {
+        *self.lock() = Tally::default();
+    }
+
+    fn record(&self, fields: Vec<(F, String)>, control: &[SearchHit], candidate: &[SearchHit]) {
+        let control: Vec<DocId> = control.iter().map(|hit| hit.doc_id).collect();
+        let candidate: Vec<DocId> = candidate.iter().map(|hit| hit.doc_id).collect();
+        let kendall_tau = kendall_tau(&control, &candidate);
+        let top1_agrees = control.first() == candidate.first();
+
+        let mut tally = self.lock();
+        tally.queries += 1;
+        tally.tau_sum += kendall_tau;
+        if top1_agrees {
+            tally.top1_agreements += 1;
+        }
+        if top1_agrees && kendall_tau >= self.divergence_tau {
+            return;
+        }
+
+        debug!(
+            "[COMPARE] Divergence on {:?}: tau {:.4}, control {:?}, candidate {:?}",
+            fields, kendall_tau, control, candidate
+        );
+        tally.divergent += 1;
+        if tally.divergences.len() < self.max_divergences {
+            tally.divergences.push(QueryComparison {
+                fields,
+                control,
+                candidate,
+                kendall_tau,
+                top1_agrees,
+            });
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Tally<F>> {
+        self.tally.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
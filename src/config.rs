@@ -0,0 +1,125 @@
+//! Engine settings persisted alongside the postings, so an index carries the
+//! scoring and tokenization it was built with.
+
+use crate::engine::{BlockingStrategy, CommonTerms, FallbackPolicy};
+use crate::error::LfasError;
+use crate::scorer::{FieldGroup, RecencyDecay, TfOptions};
+use crate::tokenizer::{FieldTokenRules, Synonyms, TokenizerConfig};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "F: Serialize + Hash + Eq",
+    deserialize = "F: Deserialize<'de> + Hash + Eq"
+))]
+pub struct EngineConfig<F> {
+    /// Fields the index was built with, in sorted order.
+    pub schema: Vec<F>,
+    pub k1: f32,
+    pub field_weights: HashMap<F, f32>,
+    pub field_b: HashMap<F, f32>,
+    pub field_tf: HashMap<F, TfOptions>,
+    pub recency: Option<RecencyDecay>,
+    pub coverage_boost: f32,
+    pub field_groups: Vec<FieldGroup<F>>,
+    pub length_clip: Option<f32>,
+    pub tokenizer: TokenizerConfig,
+    pub field_rules: HashMap<F, FieldTokenRules>,
+    #[serde(default)]
+    pub synonyms: Synonyms,
+    pub ngram_weight: f32,
+    pub fallback: FallbackPolicy,
+    pub blocking: BlockingStrategy,
+    pub collapse_fields: Vec<F>,
+    pub common_terms: Option<CommonTerms>,
+}
+
+impl<F> EngineConfig<F>
+where
+    F: Serialize + for<'de> Deserialize<'de> + Hash + Eq,
+{
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// Query-time settings swapped into a live engine by
+/// [`SearchEngine::reload_config`](crate::engine::SearchEngine::reload_config),
+/// e.g. while tuning relevance. `None`, and fields missing from the maps,
+/// keep their current value. Tokenization can't be reloaded, since the
+/// postings were built with it, but query-time synonyms can.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigReload<F>
+where
+    F: Hash + Eq,
+{
+    pub k1: Option<f32>,
+    pub field_weights: HashMap<F, f32>,
+    pub field_b: HashMap<F, f32>,
+    pub fallback: Option<FallbackPolicy>,
+    /// Replaces every synonym when set
+    pub synonyms: Option<Synonyms>,
+}
+
+impl<F> Default for ConfigReload<F>
+where
+    F: Hash + Eq,
+{
+    fn default() -> Self {
+        Self {
+            k1: None,
+            field_weights: HashMap::new(),
+            field_b: HashMap::new(),
+            fallback: None,
+            synonyms: None,
+        }
+    }
+}
+
+impl<F> ConfigReload<F>
+where
+    F: Hash + Eq + std::fmt::Debug,
+{
+    /// Rejects a non-positive `k1`, negative weights, b-values outside
+    /// [0, 1] and synonyms that aren't a single token.
+    pub fn validate(&self) -> Result<(), LfasError> {
+        if let Some(k1) = self.k1.filter(|k1| !(k1.is_finite() && *k1 > 0.0)) {
+            return Err(LfasError::Schema(format!(
+                "k1 must be positive, got {}",
+                k1
+            )));
+        }
+        for (field, weight) in &self.field_weights {
+            if !(weight.is_finite() && *weight >= 0.0) {
+                return Err(LfasError::Schema(format!(
+                    "Weight of {:?} must be non-negative, got {}",
+                    field, weight
+                )));
+            }
+        }
+        for (field, b) in &self.field_b {
+            if !(0.0..=1.0).contains(b) {
+                return Err(LfasError::Schema(format!(
+                    "b-value of {:?} must be in [0, 1], got {}",
+                    field, b
+                )));
+            }
+        }
+        for (token, synonyms) in self.synonyms.iter().flat_map(Synonyms::iter) {
+            let single = |token: &String| !token.is_empty() && !token.contains(char::is_whitespace);
+            if !single(token) || !synonyms.iter().all(single) {
+                return Err(LfasError::Schema(format!(
+                    "Synonyms must be single tokens, got '{}' -> {:?}",
+                    token, synonyms
+                )));
+            }
+        }
+        Ok(())
+    }
+}
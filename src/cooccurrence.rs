@@ -0,0 +1,125 @@
+//! Terms that occur together in the same field of a document, counted as
+//! documents are indexed. Query expansion adds the strongest partners of each
+//! query term, which helps sparse bairro and street names find their records.
+
+use crate::tokenizer::is_ngram_key;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// Field values with more full tokens than this are not counted; their pairs
+/// grow quadratically and say little about each other.
+pub const MAX_COUNTED_TOKENS: usize = 16;
+
+/// How many related terms each query token brings in, and how much they count.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QueryExpansion {
+    /// Related terms added per query token.
+    pub terms: usize,
+    /// Scoring weight of an added term (full tokens weigh 1.0).
+    pub weight: f32,
+    /// Pairs seen in fewer documents are ignored as noise.
+    pub min_count: u32,
+}
+
+impl Default for QueryExpansion {
+    fn default() -> Self {
+        Self {
+            terms: 3,
+            weight: 0.3,
+            min_count: 2,
+        }
+    }
+}
+
+/// Per-field co-occurrence counts of full tokens.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CooccurrenceIndex<F>
+where
+    F: Hash + Eq,
+{
+    /// field -> term -> co-occurring term -> documents holding both
+    counts: HashMap<F, HashMap<String, HashMap<String, u32>>>,
+}
+
+impl<F> CooccurrenceIndex<F>
+where
+    F: Hash + Eq + Clone + Copy,
+{
+    pub fn new() -> Self {
+        Self {
+            counts: HashMap::new(),
+        }
+    }
+
+    /// Counts every pair of full tokens in one document's `field`.
+    pub fn observe(&mut self, field: F, tokens: &HashSet<String>) {
+        self.adjust(field, tokens, true);
+    }
+
+    /// Takes back an earlier [`observe`](Self::observe) of the same tokens,
+    /// e.g. when the field's value is replaced.
+    pub fn forget(&mut self, field: F, tokens: &HashSet<String>) {
+        self.adjust(field, tokens, false);
+    }
+
+    fn adjust(&mut self, field: F, tokens: &HashSet<String>, add: bool) {
+        let full: Vec<&String> = tokens.iter().filter(|t| !is_ngram_key(t)).collect();
+        if full.len() < 2 || full.len() > MAX_COUNTED_TOKENS {
+            return;
+        }
+
+        let terms = self.counts.entry(field).or_default();
+        for &term in &full {
+            let related = terms.entry(term.clone()).or_default();
+            for &other in full.iter().filter(|&&other| other != term) {
+                if add {
+                    *related.entry(other.clone()).or_insert(0) += 1;
+                } else if let Some(count) = related.get_mut(other) {
+                    *count = count.saturating_sub(1);
+                    if *count == 0 {
+                        related.remove(other);
+                    }
+                }
+            }
+            if related.is_empty() {
+                terms.remove(term);
+            }
+        }
+    }
+
+    /// Up to `n` terms seen with `term` in `field` in at least `min_count`
+    /// documents, most frequent first.
+    pub fn related(&self, field: F, term: &str, n: usize, min_count: u32) -> Vec<(String, u32)> {
+        let Some(related) = self.counts.get(&field).and_then(|terms| terms.get(term)) else {
+            return Vec::new();
+        };
+        let mut related: Vec<(String, u32)> = related
+            .iter()
+            .filter(|&(_, &count)| count >= min_count)
+            .map(|(other, &count)| (other.clone(), count))
+            .collect();
+        // Ties broken by term so expansion doesn't depend on HashMap order
+        related.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        related.truncate(n);
+        related
+    }
+
+    /// Terms with at least one partner, over all fields.
+    pub fn len(&self) -> usize {
+        self.counts.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<F> Default for CooccurrenceIndex<F>
+where
+    F: Hash + Eq + Clone + Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
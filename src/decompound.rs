@@ -0,0 +1,159 @@
+//! Splitting of run-together query words ("ruamauriti") into indexed terms.
+//!
+//! A query word with no document frequency in its field is tried as every
+//! concatenation of two up to `max_parts` terms of the field's dictionary.
+//! Splits are ranked by the geometric mean of their parts' df, so a split
+//! into common, well-attested words beats one that leans on a rare
+//! fragment; ties go to the split with fewer parts.
+
+use crate::metadata::FieldMetadata;
+use crate::tokenizer::is_ngram_key;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub const DEFAULT_MAX_PARTS: usize = 3;
+pub const DEFAULT_MIN_PART_LEN: usize = 2;
+/// Longer words are left alone; the splits to try grow with the square of
+/// the length.
+pub const MAX_SPLIT_CHARS: usize = 48;
+
+/// A split of a query word into indexed terms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Split {
+    pub parts: Vec<String>,
+    /// Geometric mean of the parts' df.
+    pub score: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decompounder {
+    max_parts: usize,
+    min_part_len: usize,
+}
+
+impl Default for Decompounder {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_PARTS, DEFAULT_MIN_PART_LEN)
+    }
+}
+
+impl Decompounder {
+    /// Splits into at most `max_parts` terms (at least 2) of at least
+    /// `min_part_len` characters (at least 1) each.
+    pub fn new(max_parts: usize, min_part_len: usize) -> Self {
+        Self {
+            max_parts: max_parts.max(2),
+            min_part_len: min_part_len.max(1),
+        }
+    }
+
+    pub fn max_parts(&self) -> usize {
+        self.max_parts
+    }
+
+    pub fn min_part_len(&self) -> usize {
+        self.min_part_len
+    }
+
+    /// Only plain words are split: n-grams, numbers and composite tokens
+    /// are left alone, like spell correction does.
+    pub fn is_splittable(&self, token: &str) -> bool {
+        let chars = token.chars().count();
+        !is_ngram_key(token)
+            && token.chars().all(|c| c.is_alphabetic())
+            && chars >= 2 * self.min_part_len
+            && chars <= MAX_SPLIT_CHARS
+    }
+
+    /// Best split of `token` into terms indexed in `field`, when `token`
+    /// itself isn't.
+    pub fn split<F>(&self, field: F, token: &str, metadata: &FieldMetadata<F>) -> Option<Split>
+    where
+        F: Hash + Eq + Clone + Copy,
+    {
+        if !self.is_splittable(token) || metadata.get_df(&field, token) > 0 {
+            return None;
+        }
+
+        // Char boundaries, so accented words split between letters
+        let bounds: Vec<usize> = token
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([token.len()])
+            .collect();
+        let mut dfs: HashMap<(usize, usize), usize> = HashMap::new();
+        let mut df = |start: usize, end: usize| -> usize {
+            if end - start < self.min_part_len {
+                return 0;
+            }
+            *dfs.entry((start, end))
+                .or_insert_with(|| metadata.get_df(&field, &token[bounds[start]..bounds[end]]))
+        };
+
+        let mut search = SplitSearch {
+            chars: bounds.len() - 1,
+            max_parts: self.max_parts,
+            parts: Vec::new(),
+            best: None,
+        };
+        search.walk(0, &mut df);
+
+        let (score, parts) = search.best?;
+        Some(Split {
+            parts: parts
+                .into_iter()
+                .map(|(start, end)| token[bounds[start]..bounds[end]].to_string())
+                .collect(),
+            score,
+        })
+    }
+}
+
+/// Depth-first walk over the splits whose parts are all indexed.
+struct SplitSearch {
+    chars: usize,
+    max_parts: usize,
+    /// (start, end, ln df) of the parts taken so far, in chars
+    parts: Vec<(usize, usize, f64)>,
+    best: Option<(f64, Vec<(usize, usize)>)>,
+}
+
+impl SplitSearch {
+    fn walk(&mut self, start: usize, df: &mut impl FnMut(usize, usize) -> usize) {
+        let last = self.parts.len() + 1 == self.max_parts;
+        for end in start + 1..=self.chars {
+            // The whole word is never its own split, and the last part ends it
+            if (start == 0 && end == self.chars) || (last && end < self.chars) {
+                continue;
+            }
+            let part_df = df(start, end);
+            if part_df == 0 {
+                continue;
+            }
+            self.parts.push((start, end, (part_df as f64).ln()));
+            if end == self.chars {
+                self.consider();
+            } else {
+                self.walk(end, df);
+            }
+            self.parts.pop();
+        }
+    }
+
+    fn consider(&mut self) {
+        let n = self.parts.len();
+        let score = (self.parts.iter().map(|(_, _, ln_df)| ln_df).sum::<f64>() / n as f64).exp();
+        let better = match &self.best {
+            None => true,
+            Some((best, parts)) => score > *best || (score == *best && n < parts.len()),
+        };
+        if better {
+            let parts = self
+                .parts
+                .iter()
+                .map(|&(start, end, _)| (start, end))
+                .collect();
+            self.best = Some((score, parts));
+        }
+    }
+}
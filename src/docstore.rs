@@ -0,0 +1,88 @@
+use crate::DocId;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Joins the values of a multi-value field in the stored text.
+pub const VALUE_SEPARATOR: char = '\u{1f}';
+
+/// Raw field values of indexed documents, kept for reranking and display.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocStore<F>
+where
+    F: Hash + Eq,
+{
+    /// doc_id -> field -> original text
+    docs: HashMap<DocId, HashMap<F, String>>,
+}
+
+impl<F> DocStore<F>
+where
+    F: Hash + Eq + Copy,
+{
+    pub fn new() -> Self {
+        Self {
+            docs: HashMap::new(),
+        }
+    }
+
+    /// Stores the given fields, replacing previous values of the same fields.
+    /// A field given several times keeps all its non-empty values.
+    pub fn put(&mut self, doc_id: DocId, fields: impl IntoIterator<Item = (F, String)>) {
+        let mut grouped: HashMap<F, Vec<String>> = HashMap::new();
+        for (field, value) in fields {
+            let values = grouped.entry(field).or_default();
+            if !value.is_empty() {
+                values.push(value);
+            }
+        }
+
+        let doc = self.docs.entry(doc_id).or_default();
+        for (field, values) in grouped {
+            if values.is_empty() {
+                doc.remove(&field);
+            } else {
+                doc.insert(field, values.join(&VALUE_SEPARATOR.to_string()));
+            }
+        }
+    }
+
+    pub fn get(&self, doc_id: DocId) -> Option<&HashMap<F, String>> {
+        self.docs.get(&doc_id)
+    }
+
+    pub fn field(&self, doc_id: DocId, field: F) -> Option<&str> {
+        self.docs
+            .get(&doc_id)
+            .and_then(|doc| doc.get(&field))
+            .map(String::as_str)
+    }
+
+    /// Each value of a (possibly multi-value) field.
+    pub fn values(&self, doc_id: DocId, field: F) -> Vec<&str> {
+        self.field(doc_id, field)
+            .map(|text| text.split(VALUE_SEPARATOR).collect())
+            .unwrap_or_default()
+    }
+
+    pub fn remove(&mut self, doc_id: DocId) -> Option<HashMap<F, String>> {
+        self.docs.remove(&doc_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+}
+
+impl<F> Default for DocStore<F>
+where
+    F: Hash + Eq + Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
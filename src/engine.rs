@@ -1,14 +1,55 @@
+use crate::admission::{AdmissionControl, ConcurrencyLimits, Permit};
+use crate::candidates::{CandidateGenerator, Clause, TermLookup};
+use crate::config::{ConfigReload, EngineConfig};
+use crate::cooccurrence::{CooccurrenceIndex, QueryExpansion};
+use crate::decompound::Decompounder;
+use crate::docstore::{DocStore, VALUE_SEPARATOR};
+use crate::error::LfasError;
+use crate::impact::ImpactIndex;
 use crate::index::InvertedIndex;
-use crate::metadata::FieldMetadata;
+use crate::metadata::{FieldMetadata, TermStats};
+use crate::metrics::{MetricsRegistry, MetricsSnapshot};
+use crate::postings::Postings;
+use crate::provenance::{Provenance, SourceFile, unix_now};
+use crate::score_cache::ScoreCache;
 use crate::scorer::BM25FScorer;
-use crate::storage::PostingsStorage;
+use crate::shard::stable_hash;
+use crate::similarity::SimilarityReranker;
+use crate::slowlog::{FieldTokenStats, SlowQuery, SlowQueryLog};
+use crate::spelling::{SpellIndex, Suggestion};
+use crate::storage::{LmdbSnapshot, LmdbStorage, PostingsStorage};
 use crate::timing::Timer;
-use crate::tokenizer::tokenize_structured;
-use crate::{RecordField, SearchHit, StructuredQuery};
-use log::{debug, info};
+use crate::tokenizer::{
+    DEFAULT_NGRAM_WEIGHT, FieldAnalyzer, FieldTokenRules, Synonyms, TermPolicy, TokenFilter,
+    TokenSet, TokenTrace, TokenizerConfig, is_cep, is_ngram_key, normalize, tokenize_debug,
+    tokenize_field,
+};
+use crate::vectors::VectorReranker;
+use crate::verbosity::{self, Verbosity, query_debug, query_info};
+use crate::{
+    DocId, FieldPresence, MinShouldMatch, Occur, QueryDiagnostics, QueryLimits, Record,
+    RecordField, SearchHit, SearchResults, StructuredQuery,
+};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use roaring::RoaringBitmap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, field, info, info_span, warn};
+
+/// An engine searching one pinned generation of an LMDB index; see
+/// [`SearchEngine::searcher`].
+pub type Searcher<F> = SearchEngine<F, LmdbSnapshot<F>>;
+
+/// A hit with the stored values of the fields asked for, see
+/// [`SearchEngine::search_with_fields`].
+pub type HitWithFields<F> = (SearchHit, HashMap<F, String>);
+
+/// The (field, term, tf)s of each document of a batch, as
+/// [`InvertedIndex::add_batch`] takes them.
+pub type TermBatch<F> = Vec<(DocId, Vec<(F, String, u32)>)>;
 
 pub struct SearchEngine<F, S>
 where
@@ -18,6 +59,1202 @@ where
     pub index: InvertedIndex<F, S>,
     pub metadata: FieldMetadata<F>,
     pub scorer: BM25FScorer<F>,
+    /// External record id -> internal doc id
+    pub id_map: HashMap<String, DocId>,
+    /// Internal doc id -> external record id, the inverse of `id_map`
+    pub external_ids: HashMap<DocId, String>,
+    pub limits: QueryLimits,
+    /// Concurrent search slots and wait queue, shared with the searchers
+    /// taken off the engine; unlimited when unset
+    pub admission: Option<Arc<AdmissionControl>>,
+    /// Original field values, handed to rerankers
+    pub docs: DocStore<F>,
+    /// Scoring weight of weak n-gram query tokens (full tokens weigh 1.0)
+    pub ngram_weight: f32,
+    /// Round 1 behaviour when no distinctive token finds candidates
+    pub fallback: FallbackPolicy,
+    /// Must match between indexing and querying
+    pub tokenizer: TokenizerConfig,
+    /// Per-field stopword/address-type handling; fields without rules use the default
+    pub field_rules: HashMap<F, FieldTokenRules>,
+    /// Searched along with the query tokens they belong to; indexing ignores them
+    pub synonyms: Synonyms,
+    /// Deletion dictionary for spell correction, built on demand
+    pub spelling: Option<SpellIndex<F>>,
+    /// Replace query tokens with zero df by their closest indexed term
+    pub auto_correct: bool,
+    /// Score query words with zero df as the indexed terms they run
+    /// together; off by default
+    pub decompounder: Option<Decompounder>,
+    /// Per-field term co-occurrence counts, built on demand
+    pub cooccurrence: Option<CooccurrenceIndex<F>>,
+    /// Add the top co-occurring terms of each query token, discounted
+    pub expansion: Option<QueryExpansion>,
+    pub blocking: BlockingStrategy,
+    /// Round 1 blockers whose blocks are unioned in place of `blocking`
+    pub generators: Vec<Arc<dyn CandidateGenerator<F>>>,
+    /// Demote terms too frequent to tell documents apart; off by default
+    pub common_terms: Option<CommonTerms>,
+    /// Break each hit's score down by field in [`SearchHit::field_scores`]
+    pub field_scores: bool,
+    /// Precomputed BM25F contributions, scored in place of BM25F while their
+    /// parameters still match; see [`SearchEngine::rebuild_impacts`]
+    pub impacts: Option<ImpactIndex<F>>,
+    /// Memoized idfs and length normalizations for Round 2; off by default
+    pub score_cache: Option<ScoreCache<F>>,
+    /// Rank the documents of a full CEP in the query without BM25F when it
+    /// matches at most this many; off by default
+    pub cep_shortcut: Option<usize>,
+    /// Blends query/document embedding similarity into the ranking of
+    /// queries carrying a vector; off by default
+    pub vectors: Option<VectorReranker>,
+    /// Fields whose normalized values identify one address; hits sharing
+    /// them collapse into the best-scoring one. Keys are taken at index time.
+    pub collapse_fields: Vec<F>,
+    /// Source files recorded since the last `save_provenance`
+    pub sources: Vec<SourceFile>,
+    /// Storage generation the metadata was last rebuilt or loaded from
+    pub synced_generation: Option<u64>,
+    pub metrics: MetricsRegistry,
+    /// Searches past a latency threshold, plus sampled ones; off by default
+    pub slow_log: Option<SlowQueryLog>,
+    /// How much searches log, unless the query sets its own
+    pub verbosity: Verbosity,
+}
+
+/// Default candidate budget used when the caller doesn't provide one.
+pub const DEFAULT_BLOCKING_K: usize = 10_000;
+pub const DEFAULT_TOP_K: usize = 10;
+
+/// Score reported for hits resolved by exact external id lookup.
+pub const EXACT_MATCH_SCORE: f32 = 1.0;
+
+/// How many terms are scanned between two progress callbacks in `rebuild_metadata`.
+pub const REBUILD_PROGRESS_INTERVAL: usize = 10_000;
+
+/// Progress report emitted while rebuilding metadata from storage.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebuildProgress {
+    pub terms_scanned: usize,
+    pub docs_seen: usize,
+}
+
+/// How Round 1 turns query tokens into a candidate set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum BlockingStrategy {
+    /// Union of every distinctive token across all clauses.
+    #[default]
+    Union,
+    /// Intersection of the full tokens within each clause, then union across
+    /// clauses. Much smaller candidate sets on long queries; `min_should_match`
+    /// then counts matching clauses instead of tokens.
+    FieldIntersection,
+    /// Distinctive tokens of all clauses in increasing df order, stopping
+    /// once the union holds `blocking_k` documents. The rarest token always
+    /// contributes and common ones can't flood the set; `min_should_match`
+    /// counts only the tokens used before stopping.
+    RarestFirst,
+}
+
+/// Treats terms found in a large share of the documents like stopwords: they
+/// don't select candidates and score at most `weight`. Judged from the
+/// current `term_df` and `total_docs` on every query, so the set follows the
+/// corpus as it grows.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct CommonTerms {
+    /// Terms in more than this fraction of the documents are common.
+    pub max_df_ratio: f32,
+    /// Scoring weight of a common term (full tokens weigh 1.0).
+    pub weight: f32,
+    /// Smaller indexes have no common terms; their dfs say too little.
+    pub min_docs: usize,
+}
+
+impl Default for CommonTerms {
+    fn default() -> Self {
+        Self {
+            max_df_ratio: 0.2,
+            weight: 0.3,
+            min_docs: 1000,
+        }
+    }
+}
+
+/// Rarest query tokens used by the default [`FallbackPolicy`].
+pub const DEFAULT_FALLBACK_TOKENS: usize = 5;
+
+/// What Round 1 does when no distinctive token finds a candidate: seed the
+/// candidate set from the rarest query tokens, or give up with no hits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct FallbackPolicy {
+    /// When false, queries without distinctive matches return nothing.
+    pub enabled: bool,
+    /// How many of the rarest tokens seed the candidate set.
+    pub rare_tokens: usize,
+    /// Tokens in more documents than this are too common to seed candidates.
+    pub max_df: Option<usize>,
+    /// Whether n-gram postings may seed candidates.
+    pub ngrams: bool,
+}
+
+impl Default for FallbackPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            rare_tokens: DEFAULT_FALLBACK_TOKENS,
+            max_df: None,
+            ngrams: true,
+        }
+    }
+}
+
+impl FallbackPolicy {
+    /// Never fall back: an empty Round 1 means an empty result.
+    pub fn disabled() -> Self {
+        Self {
+            enabled: false,
+            ..Self::default()
+        }
+    }
+}
+
+/// Named bundle of Round 1 and scoring settings, picked per query through
+/// [`StructuredQuery::preset`] instead of the engine-wide settings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum QueryPreset {
+    /// Address validation: every clause must match all its known tokens,
+    /// no spelling correction, fallback or n-gram scoring.
+    Strict,
+    /// The engine defaults.
+    Balanced,
+    /// Messy user input: spelling correction, fallback and heavier n-grams.
+    Fuzzy,
+}
+
+impl QueryPreset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "strict" => Some(QueryPreset::Strict),
+            "balanced" => Some(QueryPreset::Balanced),
+            "fuzzy" => Some(QueryPreset::Fuzzy),
+            _ => None,
+        }
+    }
+
+    pub fn profile(&self) -> QueryProfile {
+        match self {
+            QueryPreset::Strict => QueryProfile {
+                blocking: BlockingStrategy::FieldIntersection,
+                min_should_match: Some(MinShouldMatch::Percent(100.0)),
+                auto_correct: false,
+                fallback: false,
+                ngram_weight: 0.0,
+            },
+            QueryPreset::Balanced => QueryProfile {
+                blocking: BlockingStrategy::Union,
+                min_should_match: None,
+                auto_correct: false,
+                fallback: true,
+                ngram_weight: DEFAULT_NGRAM_WEIGHT,
+            },
+            QueryPreset::Fuzzy => QueryProfile {
+                blocking: BlockingStrategy::Union,
+                min_should_match: None,
+                auto_correct: true,
+                fallback: true,
+                ngram_weight: 2.0 * DEFAULT_NGRAM_WEIGHT,
+            },
+        }
+    }
+}
+
+/// Settings one query runs with, from its preset or the engine.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QueryProfile {
+    pub blocking: BlockingStrategy,
+    /// Used when the query doesn't set its own.
+    pub min_should_match: Option<MinShouldMatch>,
+    /// Needs a spell index, see [`SearchEngine::build_spell_index`].
+    pub auto_correct: bool,
+    /// Whether the rarest-token fallback may run; the rest of the
+    /// [`FallbackPolicy`] comes from the engine.
+    pub fallback: bool,
+    pub ngram_weight: f32,
+}
+
+/// Hot terms preloaded by [`SearchEngine::warm`] when the caller doesn't say.
+pub const DEFAULT_WARM_TERMS: usize = 10_000;
+
+/// Result of [`SearchEngine::warm`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WarmReport {
+    /// Terms whose postings are now held in memory.
+    pub cached_terms: usize,
+    /// Serialized size of every posting read while touching pages (0 if skipped).
+    pub touched_bytes: u64,
+}
+
+/// Bytes per posting entry: a roaring array slot plus its term frequency.
+const POSTING_ENTRY_BYTES: f64 = 6.0;
+/// Bytes per posting list on top of its key: bincode lengths, the roaring
+/// header and container descriptor, and the LMDB node header.
+const POSTING_LIST_BYTES: f64 = 40.0;
+/// Metadata bytes per term on top of its text: field, df and term stats.
+const METADATA_TERM_BYTES: f64 = 32.0;
+/// Metadata bytes per (document, field) length entry.
+const METADATA_LENGTH_BYTES: f64 = 12.0;
+/// Map size over the estimated data: B-tree pages are rarely full, and the
+/// metadata is written copy-on-write next to its previous version.
+pub const ESTIMATE_HEADROOM: f64 = 2.0;
+
+/// Result of [`SearchEngine::estimate`]: the projected size of an index of
+/// `total_docs` records shaped like the sample.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct IndexEstimate {
+    pub sample_docs: usize,
+    pub total_docs: usize,
+    /// Distinct (field, term) pairs, i.e. posting lists.
+    pub unique_terms: u64,
+    /// (document, term) pairs over all posting lists.
+    pub postings: u64,
+    /// Heaps' law exponent fitted on the sample (1.0 means every new record
+    /// brings new terms at the same rate).
+    pub vocabulary_growth: f64,
+    pub postings_bytes: u64,
+    pub metadata_bytes: u64,
+    /// LMDB map size to open the index with, headroom included.
+    pub map_size: u64,
+}
+
+/// Outcome of [`SearchEngine::match_one`]. Hit scores are normalized to
+/// [0, 1] by the query's [self score](BM25FScorer::self_score).
+#[derive(Debug)]
+pub enum MatchDecision {
+    /// The only hit at or above the accept threshold.
+    Accepted(SearchHit),
+    /// Hits at or above the review threshold, best first, for a person to
+    /// decide: none clears the accept threshold, or several do.
+    Review(Vec<SearchHit>),
+    NoMatch,
+}
+
+/// Score summary over the candidates scored by a preview.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ScoreDistribution {
+    pub min: f32,
+    pub median: f32,
+    pub p90: f32,
+    pub max: f32,
+}
+
+/// Result of [`SearchEngine::execute_preview`].
+#[derive(Debug)]
+pub struct PreviewResult {
+    pub hits: Vec<SearchHit>,
+    /// True when only a sample of the candidates was scored.
+    pub approximate: bool,
+    pub total_candidates: u64,
+    pub sampled_candidates: u64,
+    /// Scored documents extrapolated from the sample to the full candidate set.
+    pub estimated_matches: u64,
+    pub distribution: ScoreDistribution,
+}
+
+/// Hits of [`SearchEngine::execute_iter`], in score order. The scores are
+/// computed up front; each [`SearchHit`] is only built when reached.
+#[derive(Debug)]
+pub struct HitIter<F> {
+    scored: std::vec::IntoIter<(DocId, f32)>,
+    field_sums: HashMap<DocId, Vec<(F, f32)>>,
+    /// Set when the query was resolved by exact external id lookup
+    exact: bool,
+    /// Of the search, which has returned by the time hits are built
+    verbosity: Verbosity,
+}
+
+impl<F> HitIter<F> {
+    fn new(scored: Vec<(DocId, f32)>, field_sums: HashMap<DocId, Vec<(F, f32)>>) -> Self {
+        Self {
+            scored: scored.into_iter(),
+            field_sums,
+            exact: false,
+            verbosity: verbosity::current(),
+        }
+    }
+
+    fn exact(hit: &SearchHit) -> Self {
+        Self {
+            exact: true,
+            ..Self::new(vec![(hit.doc_id, hit.score)], HashMap::new())
+        }
+    }
+}
+
+impl<F: std::fmt::Debug> Iterator for HitIter<F> {
+    type Item = SearchHit;
+
+    fn next(&mut self) -> Option<SearchHit> {
+        let (doc_id, score) = self.scored.next()?;
+        if self.verbosity.details() {
+            debug!("[SEARCH] Result: doc_id={}, score={}", doc_id, score);
+        }
+        let mut field_scores: Vec<(String, f32)> = self
+            .field_sums
+            .remove(&doc_id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|(field, score)| (format!("{:?}", field), score))
+            .collect();
+        field_scores.sort_by(|a, b| b.1.total_cmp(&a.1));
+        Some(SearchHit {
+            doc_id,
+            score,
+            exact: self.exact,
+            field_scores,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.scored.size_hint()
+    }
+}
+
+impl<F: std::fmt::Debug> ExactSizeIterator for HitIter<F> {}
+
+/// Output of Round 1.
+struct CandidateSet<F> {
+    candidates: RoaringBitmap,
+    /// Every weighted (field, token) of the query, to score with
+    query_tokens: Vec<(F, String, f32)>,
+    /// (field, token) pairs whose postings fed `candidates`
+    round1_tokens: Vec<(F, String)>,
+    fallback: bool,
+}
+
+/// A first-stage hit handed to a reranker, with the stored document and the
+/// query tokens it matched per field.
+#[derive(Debug)]
+pub struct RerankCandidate<'a, F>
+where
+    F: Hash + Eq,
+{
+    pub hit: SearchHit,
+    pub doc: Option<&'a HashMap<F, String>>,
+    pub matched: HashMap<F, Vec<String>>,
+}
+
+/// A document whose fields already went through the tokenizer, ready for
+/// [`SearchEngine::index_tokenized`]. Lets callers tokenize off the write path.
+#[derive(Debug, Clone)]
+pub struct TokenizedDoc<F> {
+    pub doc_id: DocId,
+    pub external_id: Option<String>,
+    /// Each field's tokens with their term frequencies
+    pub fields: Vec<(F, HashMap<String, u32>)>,
+    /// Original field values kept in the doc store
+    pub stored: Vec<(F, String)>,
+}
+
+impl TokenizedDoc<RecordField> {
+    pub fn from_record(doc_id: DocId, record: &Record) -> Self {
+        Self::from_record_with(doc_id, record, &FieldAnalyzer::default())
+    }
+
+    /// Multi-value fields are tokenized per value and merged, so a token
+    /// shared by two aliases counts once toward the field length, with the
+    /// larger of its two term frequencies.
+    pub fn from_record_with(
+        doc_id: DocId,
+        record: &Record,
+        analyzer: &FieldAnalyzer<RecordField>,
+    ) -> Self {
+        let mut fields: Vec<(RecordField, HashMap<String, u32>)> = Vec::new();
+        for (field, text) in record.values() {
+            let tfs = analyzer.term_frequencies(&field, text);
+            match fields.iter_mut().find(|(f, _)| *f == field) {
+                Some((_, existing)) => {
+                    for (token, tf) in tfs {
+                        let merged = existing.entry(token).or_insert(0);
+                        *merged = (*merged).max(tf);
+                    }
+                }
+                None => fields.push((field, tfs)),
+            }
+        }
+
+        Self {
+            doc_id,
+            external_id: Some(record.id.clone()).filter(|id| !id.is_empty()),
+            fields,
+            stored: record
+                .values()
+                .into_iter()
+                .map(|(field, text)| (field, text.to_string()))
+                .collect(),
+        }
+    }
+}
+
+impl<F, S> SearchEngine<F, S>
+where
+    F: Hash + Eq + Clone + Ord + Copy,
+    S: PostingsStorage<F>,
+{
+    pub fn new(index: InvertedIndex<F, S>, metadata: FieldMetadata<F>, scorer: BM25FScorer<F>) -> Self {
+        Self {
+            index,
+            metadata,
+            scorer,
+            id_map: HashMap::new(),
+            external_ids: HashMap::new(),
+            limits: QueryLimits::default(),
+            admission: None,
+            docs: DocStore::new(),
+            ngram_weight: DEFAULT_NGRAM_WEIGHT,
+            fallback: FallbackPolicy::default(),
+            tokenizer: TokenizerConfig::default(),
+            field_rules: HashMap::new(),
+            synonyms: Synonyms::default(),
+            spelling: None,
+            auto_correct: false,
+            decompounder: None,
+            cooccurrence: None,
+            expansion: None,
+            blocking: BlockingStrategy::default(),
+            generators: Vec::new(),
+            common_terms: None,
+            field_scores: false,
+            impacts: None,
+            score_cache: None,
+            cep_shortcut: None,
+            vectors: None,
+            collapse_fields: Vec::new(),
+            sources: Vec::new(),
+            synced_generation: None,
+            metrics: MetricsRegistry::new(),
+            slow_log: None,
+            verbosity: Verbosity::default(),
+        }
+    }
+
+    /// Tokenizes a field value with the engine's tokenizer config and the field's rules.
+    pub fn analyze(&self, field: F, text: &str) -> TokenSet {
+        let rules = self.field_rules.get(&field).cloned().unwrap_or_default();
+        tokenize_field(text, &self.tokenizer, &rules)
+    }
+
+    /// Tokenizes query text like [`Self::analyze`], adding the synonyms of its tokens.
+    pub fn analyze_query(&self, field: F, text: &str) -> TokenSet {
+        let mut tokens = self.analyze(field, text);
+        self.synonyms.expand(&mut tokens);
+        tokens
+    }
+
+    /// Every token `text` yields in `field`, with its span, class and the
+    /// rule behind it. See [`tokenize_debug`].
+    pub fn tokenize_debug(&self, field: F, text: &str) -> Vec<TokenTrace> {
+        let rules = self.field_rules.get(&field).cloned().unwrap_or_default();
+        tokenize_debug(text, &self.tokenizer, &rules)
+    }
+
+    /// Snapshot of the tokenization settings, for tokenizing off the engine.
+    pub fn analyzer(&self) -> FieldAnalyzer<F> {
+        FieldAnalyzer {
+            config: self.tokenizer.clone(),
+            rules: self.field_rules.clone(),
+            synonyms: self.synonyms.clone(),
+        }
+    }
+
+    /// Indexes a batch of pre-tokenized documents with one storage
+    /// read-modify-write per distinct (field, term).
+    pub fn index_tokenized(&mut self, docs: Vec<TokenizedDoc<F>>) -> Result<(), LfasError> {
+        let _span = info_span!("index", docs = docs.len()).entered();
+        let started = std::time::Instant::now();
+        let doc_count = docs.len() as u64;
+        let batch = self.record_tokenized(docs)?;
+        self.index.add_batch(batch)?;
+        self.metrics.record_index_batch(doc_count, started.elapsed());
+        Ok(())
+    }
+
+    /// Everything [`index_tokenized`](Self::index_tokenized) does but the
+    /// postings: lengths, df and term stats, empty fields, co-occurrence,
+    /// spelling, id maps, collapse keys and stored fields. Returns the
+    /// (field, term, tf)s of each document for the caller to write.
+    pub fn record_tokenized(
+        &mut self,
+        docs: Vec<TokenizedDoc<F>>,
+    ) -> Result<TermBatch<F>, LfasError> {
+        let mut batch = Vec::with_capacity(docs.len());
+
+        // Weighted fields a document doesn't carry count as empty
+        let schema: Vec<F> = self.scorer.field_weights.keys().copied().collect();
+
+        for doc in docs {
+            self.metadata.total_docs += 1;
+            let mut doc_lengths = HashMap::new();
+            let mut terms = Vec::new();
+
+            for field in &schema {
+                if !doc.fields.iter().any(|(f, _)| f == field) {
+                    self.metadata.set_field_empty(doc.doc_id, field, true);
+                }
+            }
+
+            for (field, tfs) in doc.fields {
+                self.metadata.set_field_empty(doc.doc_id, &field, tfs.is_empty());
+                self.metadata.record_length(&field, 0, tfs.len());
+                doc_lengths.insert(field, tfs.len());
+                if let Some(cooccurrence) = &mut self.cooccurrence {
+                    cooccurrence.observe(field, &tfs.keys().cloned().collect());
+                }
+                *self.metadata.total_field_lengths.entry(field).or_insert(0) += tfs.len();
+
+                let avgdl = self.metadata.total_field_lengths[&field] as f32
+                    / self.metadata.total_docs as f32;
+                let length = tfs.len() as f32;
+                for (token, tf) in tfs {
+                    let weighted_tf = self.scorer.weighted_tf(field, tf, length, avgdl);
+                    self.metadata
+                        .term_stats
+                        .entry((field, token.clone()))
+                        .or_default()
+                        .observe(tf, weighted_tf);
+
+                    let df = self.metadata.df_entry(field, token.clone());
+                    *df += 1;
+                    if *df == 1 {
+                        if let Some(spelling) = &mut self.spelling {
+                            spelling.insert(field, &token);
+                        }
+                    }
+                    terms.push((field, token, tf));
+                }
+            }
+            self.metadata
+                .lengths
+                .entry(doc.doc_id)
+                .or_default()
+                .extend(doc_lengths);
+
+            if let Some(external_id) = doc.external_id {
+                self.map_external_id(external_id, doc.doc_id)?;
+            }
+            self.set_collapse_key(doc.doc_id, &doc.stored);
+            self.store_fields(doc.doc_id, doc.stored)?;
+            batch.push((doc.doc_id, terms));
+        }
+        Ok(batch)
+    }
+
+    /// Maps `external_id` to `doc_id` both ways and stages the pair for the
+    /// storage's id map. An id mapped before to another doc, or a doc mapped
+    /// before to another id, loses its old counterpart.
+    pub fn map_external_id(&mut self, external_id: String, doc_id: DocId) -> Result<(), LfasError> {
+        match self.external_ids.insert(doc_id, external_id.clone()) {
+            Some(previous) if previous != external_id => {
+                self.id_map.remove(&previous);
+                self.index
+                    .storage
+                    .write_external_id(&previous, None)
+                    .map_err(LfasError::storage)?;
+            }
+            _ => {}
+        }
+        match self.id_map.insert(external_id.clone(), doc_id) {
+            Some(previous) if previous != doc_id => {
+                self.external_ids.remove(&previous);
+            }
+            _ => {}
+        }
+        self.index
+            .storage
+            .write_external_id(&external_id, Some(doc_id))
+            .map_err(LfasError::storage)
+    }
+
+    /// Puts field values of a document in the doc store, like
+    /// [`DocStore::put`], and stages the resulting values for the storage's
+    /// stored-field columns.
+    pub fn store_fields(
+        &mut self,
+        doc_id: DocId,
+        stored: Vec<(F, String)>,
+    ) -> Result<(), LfasError> {
+        let fields: HashSet<F> = stored.iter().map(|(field, _)| *field).collect();
+        self.docs.put(doc_id, stored);
+        for field in fields {
+            self.index
+                .storage
+                .write_stored(doc_id, field, self.docs.field(doc_id, field))
+                .map_err(LfasError::storage)?;
+        }
+        Ok(())
+    }
+
+    /// Replaces both id maps, e.g. with one kept outside the index.
+    pub fn set_id_map(&mut self, id_map: HashMap<String, DocId>) {
+        self.external_ids = id_map
+            .iter()
+            .map(|(external_id, doc_id)| (*doc_id, external_id.clone()))
+            .collect();
+        self.id_map = id_map;
+    }
+
+    /// Loads the id map stored with the index. Returns the number of ids.
+    pub fn load_id_map(&mut self) -> Result<usize, LfasError> {
+        let ids = self
+            .index
+            .storage
+            .read_id_map()
+            .map_err(LfasError::storage)?;
+        let count = ids.len();
+        self.set_id_map(ids.into_iter().collect());
+        if count > 0 {
+            info!("[INDEX] Loaded {} external ids", count);
+        }
+        Ok(count)
+    }
+
+    pub fn doc_id_for(&self, external_id: &str) -> Option<DocId> {
+        self.id_map.get(external_id).copied()
+    }
+
+    pub fn external_id_for(&self, doc_id: DocId) -> Option<&str> {
+        self.external_ids.get(&doc_id).map(String::as_str)
+    }
+
+    /// Removes the document indexed under `external_id` from the postings,
+    /// the metadata, the doc store and both id maps. Like in
+    /// [`update_field`](Self::update_field), its terms come from the doc
+    /// store or a scan of the postings. Returns false when the id is unknown.
+    pub fn delete_by_external_id(&mut self, external_id: &str) -> Result<bool, LfasError> {
+        let Some(doc_id) = self.id_map.remove(external_id) else {
+            return Ok(false);
+        };
+        self.external_ids.remove(&doc_id);
+        self.index
+            .storage
+            .write_external_id(external_id, None)
+            .map_err(LfasError::storage)?;
+
+        let mut terms: HashMap<F, HashSet<String>> = HashMap::new();
+        match self.docs.get(doc_id) {
+            Some(stored) => {
+                for field in stored.keys() {
+                    let tokens = self
+                        .docs
+                        .values(doc_id, *field)
+                        .into_iter()
+                        .flat_map(|value| self.analyze(*field, value).all);
+                    terms.entry(*field).or_default().extend(tokens);
+                }
+            }
+            None => self
+                .index
+                .storage
+                .scan(|field, term, bytes| {
+                    let postings: Postings = bincode::deserialize(bytes)?;
+                    if postings.contains(doc_id) {
+                        terms.entry(field).or_default().insert(term.to_string());
+                    }
+                    Ok::<_, bincode::Error>(())
+                })
+                .map_err(LfasError::storage)?,
+        }
+        for (field, tokens) in &terms {
+            for token in tokens {
+                if self.index.remove_term(doc_id, *field, token)? {
+                    let df = self.metadata.df_entry(*field, token.clone());
+                    *df = df.saturating_sub(1);
+                }
+            }
+            if let Some(cooccurrence) = &mut self.cooccurrence {
+                cooccurrence.forget(*field, tokens);
+            }
+        }
+
+        let fields: Vec<F> = self.metadata.total_field_lengths.keys().copied().collect();
+        for field in fields {
+            let length = self.metadata.doc_length(doc_id, &field);
+            if let Some(total) = self.metadata.total_field_lengths.get_mut(&field) {
+                *total = total.saturating_sub(length);
+            }
+            self.metadata.record_length(&field, length, 0);
+            self.metadata.set_field_empty(doc_id, &field, false);
+            self.index
+                .storage
+                .write_stored(doc_id, field, None)
+                .map_err(LfasError::storage)?;
+        }
+        self.metadata.lengths.remove(&doc_id);
+        self.metadata.timestamps.remove(&doc_id);
+        self.metadata.collapse_keys.remove(&doc_id);
+        self.metadata.total_docs = self.metadata.total_docs.saturating_sub(1);
+        self.docs.remove(doc_id);
+        info!(
+            "[INDEX] Deleted '{}' (doc_id={}, {} terms)",
+            external_id,
+            doc_id,
+            terms.values().map(HashSet::len).sum::<usize>()
+        );
+        Ok(true)
+    }
+
+    /// Replaces the value of one field of an indexed document without
+    /// reindexing the rest: tokens the new value drops leave their postings,
+    /// new ones are added, kept ones take their new tf, and dfs, the field length and its empty flag are
+    /// adjusted. The old tokens come from the doc store, or from a scan of
+    /// the postings when the document isn't stored (e.g. a reopened LMDB
+    /// index). Term stats only grow, so they stay valid upper bounds.
+    pub fn update_field(&mut self, doc_id: DocId, field: F, text: &str) -> Result<(), LfasError> {
+        let old_tokens: HashSet<String> = match self.docs.get(doc_id) {
+            Some(_) => self
+                .docs
+                .values(doc_id, field)
+                .into_iter()
+                .flat_map(|value| self.analyze(field, value).all)
+                .collect(),
+            None => {
+                let mut tokens = HashSet::new();
+                self.index
+                    .storage
+                    .scan(|f, term, bytes| {
+                        if f == field {
+                            let postings: Postings = bincode::deserialize(bytes)?;
+                            if postings.contains(doc_id) {
+                                tokens.insert(term.to_string());
+                            }
+                        }
+                        Ok::<_, bincode::Error>(())
+                    })
+                    .map_err(LfasError::storage)?;
+                tokens
+            }
+        };
+        let new_tfs = self.analyze(field, text).tfs;
+        let new_tokens: HashSet<String> = new_tfs.keys().cloned().collect();
+        if let Some(cooccurrence) = &mut self.cooccurrence {
+            cooccurrence.forget(field, &old_tokens);
+            cooccurrence.observe(field, &new_tokens);
+        }
+
+        for token in old_tokens.difference(&new_tokens) {
+            if self.index.remove_term(doc_id, field, token)? {
+                let df = self.metadata.df_entry(field, token.clone());
+                *df = df.saturating_sub(1);
+            }
+        }
+        // Re-added below with their new tf, their dfs unchanged
+        for token in old_tokens.intersection(&new_tokens) {
+            self.index.remove_term(doc_id, field, token)?;
+        }
+
+        let old_length = self.metadata.doc_length(doc_id, &field);
+        let total = self.metadata.total_field_lengths.entry(field).or_insert(0);
+        *total = (*total + new_tokens.len()).saturating_sub(old_length);
+        self.metadata
+            .record_length(&field, old_length, new_tokens.len());
+        self.metadata
+            .lengths
+            .entry(doc_id)
+            .or_default()
+            .insert(field, new_tokens.len());
+        self.metadata
+            .set_field_empty(doc_id, &field, new_tokens.is_empty());
+
+        let avgdl = self.metadata.avg_field_length(&field);
+        let mut terms = Vec::new();
+        for (token, tf) in new_tfs {
+            let weighted_tf = self
+                .scorer
+                .weighted_tf(field, tf, new_tokens.len() as f32, avgdl);
+            self.metadata
+                .term_stats
+                .entry((field, token.clone()))
+                .or_default()
+                .observe(tf, weighted_tf);
+            if !old_tokens.contains(&token) {
+                let df = self.metadata.df_entry(field, token.clone());
+                *df += 1;
+                if *df == 1 {
+                    if let Some(spelling) = &mut self.spelling {
+                        spelling.insert(field, &token);
+                    }
+                }
+            }
+            terms.push((field, token, tf));
+        }
+        let added = new_tokens.difference(&old_tokens).count();
+        self.index.add_batch(vec![(doc_id, terms)])?;
+        // dfs and lengths changed without the document count
+        if let Some(cache) = &self.score_cache {
+            cache.clear();
+        }
+
+        if self.docs.get(doc_id).is_some() {
+            self.docs.put(doc_id, [(field, text.to_string())]);
+            let stored: Vec<(F, String)> = self
+                .docs
+                .get(doc_id)
+                .map(|doc| doc.iter().map(|(f, value)| (*f, value.clone())).collect())
+                .unwrap_or_default();
+            self.metadata.collapse_keys.remove(&doc_id);
+            self.set_collapse_key(doc_id, &stored);
+        }
+        self.index
+            .storage
+            .write_stored(doc_id, field, Some(text).filter(|text| !text.is_empty()))
+            .map_err(LfasError::storage)?;
+
+        debug!(
+            "[INDEX] Updated doc {}: {} tokens removed, {} added",
+            doc_id,
+            old_tokens.difference(&new_tokens).count(),
+            added
+        );
+        Ok(())
+    }
+
+    /// Records when a document was last updated (unix seconds), used by
+    /// [`RecencyDecay`](crate::scorer::RecencyDecay) scoring.
+    pub fn set_doc_timestamp(&mut self, doc_id: DocId, timestamp: i64) {
+        self.metadata.timestamps.insert(doc_id, timestamp);
+    }
+
+    /// Remembers the collapse key of a document from its field values. Does
+    /// nothing without `collapse_fields`, or when all of them are empty.
+    pub fn set_collapse_key(&mut self, doc_id: DocId, stored: &[(F, String)]) {
+        if self.collapse_fields.is_empty() {
+            return;
+        }
+        let values: Vec<String> = self
+            .collapse_fields
+            .iter()
+            .map(|field| {
+                stored
+                    .iter()
+                    .find(|(stored_field, _)| stored_field == field)
+                    .map(|(_, value)| {
+                        normalize(value).split_whitespace().collect::<Vec<_>>().join(" ")
+                    })
+                    .unwrap_or_default()
+            })
+            .collect();
+        if values.iter().all(String::is_empty) {
+            return;
+        }
+        let key = stable_hash(values.join("\u{1f}").as_bytes());
+        self.metadata.collapse_keys.insert(doc_id, key);
+    }
+
+    /// Current query, candidate and indexing metrics.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// Searches recorded by the slow-query log, oldest first; empty when the
+    /// log is off.
+    pub fn slow_queries(&self) -> Vec<SlowQuery> {
+        self.slow_log
+            .as_ref()
+            .map(SlowQueryLog::entries)
+            .unwrap_or_default()
+    }
+
+    /// Caps concurrent searches; past `max_queued` waiting ones, searches
+    /// fail fast with [`LfasError::Overloaded`]. `None` lifts the cap.
+    pub fn set_concurrency_limits(&mut self, limits: Option<ConcurrencyLimits>) {
+        self.admission = limits.map(|limits| Arc::new(AdmissionControl::new(limits)));
+    }
+
+    /// Takes a search slot when concurrency is capped, recording the wait
+    /// or the rejection.
+    fn admit(&self) -> Result<Option<Permit<'_>>, LfasError> {
+        let Some(admission) = &self.admission else {
+            return Ok(None);
+        };
+        match admission.acquire() {
+            Ok(permit) => {
+                self.metrics.record_queue_wait(permit.waited());
+                Ok(Some(permit))
+            }
+            Err(e) => {
+                self.metrics.record_rejection();
+                warn!("[SEARCH] Rejected: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Preloads the postings of the `top_n` most frequent terms into the index
+    /// cache so the first queries after open don't pay for cold pages. With
+    /// `touch_pages`, every stored posting is also read once to pull the whole
+    /// index into the OS page cache. Needs metadata (`rebuild_metadata`).
+    pub fn warm(&mut self, top_n: usize, touch_pages: bool) -> Result<WarmReport, LfasError> {
+        let timer = Timer::new("SearchEngine::warm");
+
+        let mut terms: Vec<(&(F, String), &usize)> = self.metadata.term_df.iter().collect();
+        terms.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        let hot: Vec<(F, String)> = terms
+            .into_iter()
+            .take(top_n)
+            .map(|(key, _)| key.clone())
+            .collect();
+
+        let postings = self
+            .index
+            .storage
+            .get_batch(&hot)
+            .map_err(LfasError::storage)?;
+        for ((field, term), postings) in hot.into_iter().zip(postings) {
+            if let Some(postings) = postings {
+                self.index.cache_postings(field, term, postings);
+            }
+        }
+
+        let mut touched_bytes = 0u64;
+        if touch_pages {
+            self.index
+                .storage
+                .scan(|_, _, bytes| {
+                    touched_bytes += bytes.len() as u64;
+                    Ok::<_, String>(())
+                })
+                .map_err(LfasError::storage)?;
+        }
+
+        let report = WarmReport {
+            cached_terms: self.index.cached_terms(),
+            touched_bytes,
+        };
+        drop(timer);
+        info!(
+            "[WARM] {} terms cached, {} bytes touched",
+            report.cached_terms, report.touched_bytes
+        );
+        Ok(report)
+    }
+
+    /// (Re)builds the spell correction dictionary from the current term_df.
+    pub fn build_spell_index(&mut self, max_distance: usize) {
+        let timer = Timer::new("SearchEngine::build_spell_index");
+        self.spelling = Some(SpellIndex::build(&self.metadata, max_distance));
+        drop(timer);
+    }
+
+    /// Indexed terms close to `token` in `field`. Empty until
+    /// [`build_spell_index`](Self::build_spell_index) has run.
+    pub fn suggest_corrections(&self, field: F, token: &str) -> Vec<Suggestion> {
+        let token = normalize(token);
+        self.spelling
+            .as_ref()
+            .map(|spelling| spelling.suggest(field, &token, &self.metadata))
+            .unwrap_or_default()
+    }
+
+    /// Terms indexed in `field` that start with `prefix` (normalized like
+    /// queries), with the number of documents holding each, in alphabetical
+    /// order and at most `limit` of them. Read from the postings, so it works
+    /// without metadata; n-grams are left out. Meant for filter dropdowns,
+    /// e.g. every municipio in the index.
+    pub fn field_values(
+        &self,
+        field: F,
+        prefix: &str,
+        limit: usize,
+    ) -> Result<Vec<(String, usize)>, LfasError> {
+        let prefix = normalize(prefix.trim());
+        let mut values = Vec::new();
+        self.index
+            .storage
+            .scan(|f, term, bytes| {
+                if f != field || is_ngram_key(term) || !term.starts_with(prefix.as_str()) {
+                    return Ok(());
+                }
+                let postings: Postings = bincode::deserialize(bytes)?;
+                // Updates leave emptied posting lists behind
+                if !postings.is_empty() {
+                    values.push((term.to_string(), postings.len()));
+                }
+                Ok::<_, bincode::Error>(())
+            })
+            .map_err(LfasError::storage)?;
+        values.sort();
+        values.truncate(limit);
+        Ok(values)
+    }
+
+    /// (Re)builds the co-occurrence counts used by query expansion from a
+    /// scan of the postings. Once built, they follow later indexing.
+    pub fn build_cooccurrence(&mut self) -> Result<(), LfasError> {
+        let timer = Timer::new("SearchEngine::build_cooccurrence");
+        let mut doc_tokens: HashMap<(DocId, F), HashSet<String>> = HashMap::new();
+        self.index
+            .storage
+            .scan(|field, term, bytes| {
+                if is_ngram_key(term) {
+                    return Ok(());
+                }
+                let postings: Postings = bincode::deserialize(bytes)?;
+                for doc_id in postings.bitmap().iter() {
+                    doc_tokens
+                        .entry((DocId::new(doc_id), field))
+                        .or_default()
+                        .insert(term.to_string());
+                }
+                Ok::<_, bincode::Error>(())
+            })
+            .map_err(LfasError::storage)?;
+
+        let mut cooccurrence = CooccurrenceIndex::new();
+        for ((_, field), tokens) in &doc_tokens {
+            cooccurrence.observe(*field, tokens);
+        }
+        drop(timer);
+        info!(
+            "[INDEX] Co-occurrence counts built for {} terms",
+            cooccurrence.len()
+        );
+        self.cooccurrence = Some(cooccurrence);
+        Ok(())
+    }
+
+    /// (Re)computes the quantized contribution of every posting with the
+    /// current scorer and metadata. Searches use them until a parameter or
+    /// the corpus changes, then fall back to BM25F until the next rebuild.
+    pub fn rebuild_impacts(&mut self) -> Result<usize, LfasError> {
+        let timer = Timer::new("SearchEngine::rebuild_impacts");
+        let impacts = ImpactIndex::build(&self.scorer, &self.index.storage, &self.metadata)?;
+        drop(timer);
+        let postings = impacts.len();
+        info!(
+            "[INDEX] Impacts built for {} postings of {} terms",
+            postings,
+            impacts.terms()
+        );
+        self.impacts = Some(impacts);
+        Ok(postings)
+    }
+
+    /// Recomputes term_df, document lengths and field totals by streaming every
+    /// posting list from storage, then term stats with a second scan. Document
+    /// lengths count distinct terms per field, which matches how the indexer
+    /// measures them; docs without any token are lost. A doc counts as empty
+    /// in every field it has no posting in. The result lives in
+    /// memory; spill it again if the metadata was backed by a store.
+    pub fn rebuild_metadata(
+        &mut self,
+        mut progress: impl FnMut(RebuildProgress),
+    ) -> Result<(), LfasError> {
+        info!("[METADATA] Rebuilding metadata from storage scan");
+        let timer = Timer::new("SearchEngine::rebuild_metadata");
+
+        let mut metadata = FieldMetadata::new();
+        let mut all_docs = RoaringBitmap::new();
+        let mut present: HashMap<F, RoaringBitmap> = HashMap::new();
+        let mut terms_scanned = 0;
+
+        self.index
+            .storage
+            .scan(|field, term, bytes| {
+                let postings: Postings = bincode::deserialize(bytes)?;
+
+                metadata
+                    .term_df
+                    .insert((field, term.to_string()), postings.len());
+                *metadata.total_field_lengths.entry(field).or_insert(0) += postings.len();
+
+                for doc_id in postings.bitmap().iter() {
+                    *metadata
+                        .lengths
+                        .entry(DocId::new(doc_id))
+                        .or_default()
+                        .entry(field)
+                        .or_insert(0) += 1;
+                }
+                all_docs |= postings.bitmap();
+                *present.entry(field).or_default() |= postings.bitmap();
+
+                terms_scanned += 1;
+                if terms_scanned % REBUILD_PROGRESS_INTERVAL == 0 {
+                    progress(RebuildProgress {
+                        terms_scanned,
+                        docs_seen: all_docs.len() as usize,
+                    });
+                }
+                Ok::<_, bincode::Error>(())
+            })
+            .map_err(LfasError::storage)?;
+
+        metadata.total_docs = all_docs.len() as usize;
+        for lengths in metadata.lengths.values() {
+            for (field, length) in lengths {
+                metadata
+                    .length_histograms
+                    .entry(*field)
+                    .or_default()
+                    .add(*length);
+            }
+        }
+        for field in self.scorer.field_weights.keys() {
+            present.entry(*field).or_default();
+        }
+        metadata.empty_fields = present
+            .into_iter()
+            .map(|(field, docs)| (field, &all_docs - docs))
+            .filter(|(_, empty)| !empty.is_empty())
+            .collect();
+        // Timestamps and collapse keys aren't in the postings, keep the ones we have
+        metadata.timestamps = std::mem::take(&mut self.metadata.timestamps);
+        metadata.collapse_keys = std::mem::take(&mut self.metadata.collapse_keys);
+        progress(RebuildProgress {
+            terms_scanned,
+            docs_seen: metadata.total_docs,
+        });
+
+        drop(timer);
+        info!(
+            "[METADATA] Rebuilt metadata: {} terms, {} docs",
+            terms_scanned, metadata.total_docs
+        );
+
+        self.metadata = metadata;
+        self.recompute_term_stats()
+    }
+
+    /// Recomputes every term's [`TermStats`] from the stored postings against
+    /// the current document lengths and scorer weights. Run after changing
+    /// field weights, b or tf options, or after bulk loads that skew the
+    /// incrementally tracked maxima. One full storage scan.
+    pub fn recompute_term_stats(&mut self) -> Result<(), LfasError> {
+        let timer = Timer::new("SearchEngine::recompute_term_stats");
+        let mut term_stats = HashMap::new();
+        let metadata = &self.metadata;
+        let scorer = &self.scorer;
+
+        self.index
+            .storage
+            .scan(|field, term, bytes| {
+                let postings: Postings = bincode::deserialize(bytes)?;
+                let avgdl = metadata.avg_field_length(&field);
+                let mut stats = TermStats::default();
+                for (doc_id, tf) in postings.iter() {
+                    let dl = metadata.doc_length(doc_id, &field) as f32;
+                    stats.observe(tf, scorer.weighted_tf(field, tf, dl, avgdl));
+                }
+                term_stats.insert((field, term.to_string()), stats);
+                Ok::<_, bincode::Error>(())
+            })
+            .map_err(LfasError::storage)?;
+
+        drop(timer);
+        info!("[METADATA] Recomputed stats for {} terms", term_stats.len());
+        self.metadata.term_stats = term_stats;
+        Ok(())
+    }
 }
 
 impl<S> SearchEngine<RecordField, S>
@@ -40,25 +1277,522 @@ where
 
         let mut field_b = HashMap::new();
 
-        field_b.insert(RecordField::Numero, 0.0_f32);
-        field_b.insert(RecordField::Cep, 0.0_f32);
-        field_b.insert(RecordField::Estado, 0.0_f32);
-        field_b.insert(RecordField::Rua, 0.75_f32);
-        field_b.insert(RecordField::Municipio, 0.5_f32);
-        field_b.insert(RecordField::Bairro, 0.75_f32);
-        field_b.insert(RecordField::Complemento, 0.5_f32);
-        field_b.insert(RecordField::TipoLogradouro, 0.0_f32);
-        field_b.insert(RecordField::Nome, 0.75_f32);
+        field_b.insert(RecordField::Numero, 0.0_f32);
+        field_b.insert(RecordField::Cep, 0.0_f32);
+        field_b.insert(RecordField::Estado, 0.0_f32);
+        field_b.insert(RecordField::Rua, 0.75_f32);
+        field_b.insert(RecordField::Municipio, 0.5_f32);
+        field_b.insert(RecordField::Bairro, 0.75_f32);
+        field_b.insert(RecordField::Complemento, 0.5_f32);
+        field_b.insert(RecordField::TipoLogradouro, 0.0_f32);
+        field_b.insert(RecordField::Nome, 0.75_f32);
+
+        let mut engine = Self::new(
+            InvertedIndex::new(storage),
+            FieldMetadata::new(),
+            BM25FScorer {
+                k1: 1.2_f32,
+                field_weights,
+                field_b,
+                field_tf: HashMap::new(),
+                recency: None,
+                coverage_boost: 0.0,
+                field_groups: Vec::new(),
+                length_clip: None,
+            },
+        );
+
+        // Place names keep their stopwords ("Alto da Serra"); street types say little in Rua,
+        // and their abbreviations less
+        let place = FieldTokenRules::new(TermPolicy::Keep, TermPolicy::Keep);
+        engine
+            .field_rules
+            .insert(RecordField::Municipio, place.clone());
+        engine.field_rules.insert(RecordField::Bairro, place);
+        engine.field_rules.insert(
+            RecordField::Rua,
+            FieldTokenRules::new(TermPolicy::Drop, TermPolicy::Demote)
+                .with_filter(TokenFilter::address()),
+        );
+
+        // A saved config describes how the index was built, it wins over the defaults
+        if let Err(e) = engine.load_config() {
+            warn!("[CONFIG] Ignoring unreadable engine config: {}", e);
+        }
+        if let Err(e) = engine.load_id_map() {
+            warn!("[INDEX] Ignoring unreadable id map: {}", e);
+        }
+        engine
+    }
+
+    /// Indexes a whole record, updating metadata and the external id map.
+    /// Fails with [`LfasError::DocIdOverflow`] for ids past [`DocId::MAX`].
+    pub fn index_record(&mut self, doc_id: usize, record: &Record) -> Result<(), LfasError> {
+        let doc_id = DocId::try_from(doc_id)?;
+        let doc = TokenizedDoc::from_record_with(doc_id, record, &self.analyzer());
+        self.index_tokenized(vec![doc])
+    }
+
+    /// Projects the postings and metadata size of an index of `total_count`
+    /// records from a representative sample of them, and the LMDB map size
+    /// it needs, so a load doesn't stop halfway on `MDB_MAP_FULL`. Unique
+    /// terms are extrapolated with Heaps' law fitted on the two halves of
+    /// the sample; everything else scales linearly.
+    pub fn estimate(&self, sample: &[Record], total_count: usize) -> IndexEstimate {
+        let analyzer = self.analyzer();
+        let mut vocabulary: HashSet<(RecordField, String)> = HashSet::new();
+        let mut half_vocabulary = 0;
+        let (mut postings, mut lengths) = (0u64, 0u64);
+        for (i, record) in sample.iter().enumerate() {
+            if i == sample.len() / 2 {
+                half_vocabulary = vocabulary.len();
+            }
+            let doc = TokenizedDoc::from_record_with(DocId::default(), record, &analyzer);
+            for (field, tfs) in doc.fields {
+                lengths += 1;
+                postings += tfs.len() as u64;
+                vocabulary.extend(tfs.into_keys().map(|token| (field, token)));
+            }
+        }
+        if sample.is_empty() || vocabulary.is_empty() {
+            return IndexEstimate {
+                sample_docs: sample.len(),
+                total_docs: total_count,
+                ..Default::default()
+            };
+        }
+
+        let scale = total_count.max(sample.len()) as f64 / sample.len() as f64;
+        // Too small a sample to fit the curve: assume the vocabulary keeps growing
+        let growth = if sample.len() >= 2 && half_vocabulary > 0 {
+            let doubling = sample.len() as f64 / (sample.len() / 2) as f64;
+            ((vocabulary.len() as f64 / half_vocabulary as f64).ln() / doubling.ln())
+                .clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let postings = (postings as f64 * scale).ceil();
+        let unique_terms = (vocabulary.len() as f64 * scale.powf(growth))
+            .ceil()
+            .min(postings);
+
+        // "ffff:" field prefix of every LMDB key
+        let term_text = vocabulary
+            .iter()
+            .map(|(_, term)| term.len() + 5)
+            .sum::<usize>() as f64
+            / vocabulary.len() as f64;
+        let postings_bytes =
+            unique_terms * (term_text + POSTING_LIST_BYTES) + postings * POSTING_ENTRY_BYTES;
+        let metadata_bytes = unique_terms * (term_text + METADATA_TERM_BYTES)
+            + lengths as f64 * scale * METADATA_LENGTH_BYTES;
+        let map_size = ((postings_bytes + metadata_bytes) * ESTIMATE_HEADROOM).ceil() as u64;
+
+        let estimate = IndexEstimate {
+            sample_docs: sample.len(),
+            total_docs: total_count,
+            unique_terms: unique_terms as u64,
+            postings: postings as u64,
+            vocabulary_growth: growth,
+            postings_bytes: postings_bytes.ceil() as u64,
+            metadata_bytes: metadata_bytes.ceil() as u64,
+            // Whole megabytes
+            map_size: map_size.div_ceil(1 << 20) << 20,
+        };
+        info!(
+            "[INDEX] Estimate for {} docs from {}: {} terms, {} postings, map size {} bytes",
+            estimate.total_docs,
+            estimate.sample_docs,
+            estimate.unique_terms,
+            estimate.postings,
+            estimate.map_size
+        );
+        estimate
+    }
+
+    /// Record-to-record matching: resolves the record id through the id map
+    /// first, then falls back to a fuzzy search over all non-empty fields.
+    pub fn search_record(&self, record: &Record, top_k: usize) -> Result<Vec<SearchHit>, LfasError> {
+        let fields: Vec<(RecordField, String)> = record
+            .values()
+            .into_iter()
+            .filter(|(_, text)| !text.trim().is_empty())
+            .map(|(field, text)| (field, text.to_string()))
+            .collect();
+
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            external_id: Some(record.id.clone()).filter(|id| !id.is_empty()),
+            ..Default::default()
+        };
+        self.execute(query, DEFAULT_BLOCKING_K)
+    }
+}
+
+impl<F, S> SearchEngine<F, S>
+where
+    F: Hash + Eq + Clone + Ord + Copy + serde::Serialize + serde::de::DeserializeOwned,
+    S: PostingsStorage<F>,
+{
+    /// Current scoring and tokenization settings.
+    pub fn config(&self) -> EngineConfig<F> {
+        let mut schema: Vec<F> = self
+            .scorer
+            .field_weights
+            .keys()
+            .chain(self.scorer.field_b.keys())
+            .copied()
+            .collect::<HashSet<F>>()
+            .into_iter()
+            .collect();
+        schema.sort();
+
+        EngineConfig {
+            schema,
+            k1: self.scorer.k1,
+            field_weights: self.scorer.field_weights.clone(),
+            field_b: self.scorer.field_b.clone(),
+            field_tf: self.scorer.field_tf.clone(),
+            recency: self.scorer.recency,
+            coverage_boost: self.scorer.coverage_boost,
+            field_groups: self.scorer.field_groups.clone(),
+            length_clip: self.scorer.length_clip,
+            tokenizer: self.tokenizer.clone(),
+            field_rules: self.field_rules.clone(),
+            synonyms: self.synonyms.clone(),
+            ngram_weight: self.ngram_weight,
+            fallback: self.fallback,
+            blocking: self.blocking,
+            collapse_fields: self.collapse_fields.clone(),
+            common_terms: self.common_terms,
+        }
+    }
+
+    pub fn apply_config(&mut self, config: EngineConfig<F>) {
+        self.scorer.k1 = config.k1;
+        self.scorer.field_weights = config.field_weights;
+        self.scorer.field_b = config.field_b;
+        self.scorer.field_tf = config.field_tf;
+        self.scorer.recency = config.recency;
+        self.scorer.coverage_boost = config.coverage_boost;
+        self.scorer.field_groups = config.field_groups;
+        self.scorer.length_clip = config.length_clip;
+        self.tokenizer = config.tokenizer;
+        self.field_rules = config.field_rules;
+        self.synonyms = config.synonyms;
+        self.ngram_weight = config.ngram_weight;
+        self.fallback = config.fallback;
+        self.blocking = config.blocking;
+        self.collapse_fields = config.collapse_fields;
+        self.common_terms = config.common_terms;
+    }
+
+    /// Swaps in the settings of `reload`, all or none of them: nothing
+    /// changes when it doesn't validate. Callers sharing the engine behind a
+    /// lock see searches run entirely before or after the reload. Upper
+    /// bounds of term scores follow new weights and b-values only after
+    /// `recompute_term_stats`.
+    pub fn reload_config(&mut self, reload: ConfigReload<F>) -> Result<(), LfasError>
+    where
+        F: std::fmt::Debug,
+    {
+        reload.validate()?;
+
+        if let Some(k1) = reload.k1 {
+            self.scorer.k1 = k1;
+        }
+        self.scorer.field_weights.extend(reload.field_weights);
+        self.scorer.field_b.extend(reload.field_b);
+        if let Some(fallback) = reload.fallback {
+            self.fallback = fallback;
+        }
+        if let Some(synonyms) = reload.synonyms {
+            self.synonyms = synonyms;
+        }
+        info!(
+            "[CONFIG] Reloaded scoring (k1 {}, {} weighted fields, fallback {:?}, {} synonyms)",
+            self.scorer.k1,
+            self.scorer.field_weights.len(),
+            self.fallback,
+            self.synonyms.len()
+        );
+        Ok(())
+    }
+
+    /// Stores the current config with the index and flushes, committing it
+    /// together with any buffered postings.
+    pub fn save_config(&mut self) -> Result<(), LfasError> {
+        let bytes = self.config().to_bytes()?;
+        self.index
+            .storage
+            .write_config(bytes)
+            .map_err(LfasError::storage)?;
+        self.flush()
+    }
+
+    /// Commits buffered postings and a snapshot of the metadata in one
+    /// storage transaction. The snapshot is stamped with the generation of
+    /// that commit; see `load_committed_metadata`. Spilled dfs and lengths
+    /// stay in their store and are not part of the snapshot.
+    pub fn flush(&mut self) -> Result<(), LfasError> {
+        let _span = info_span!("flush", docs = self.metadata.total_docs).entered();
+        let snapshot = bincode::serialize(&self.metadata)?;
+        self.index
+            .storage
+            .write_metadata(snapshot)
+            .map_err(LfasError::storage)?;
+        self.index.storage.flush().map_err(LfasError::storage)
+    }
+
+    /// Replaces the metadata with the snapshot committed by the last `flush`.
+    /// A snapshot stamped with an older generation than the storage's means
+    /// postings were committed without it (a storage-level flush, an
+    /// automatic flush of a full write buffer, `bulk_load`), so it no longer
+    /// matches them: it is discarded and false returned, as when there is no
+    /// snapshot. Run `rebuild_metadata` in that case.
+    pub fn load_committed_metadata(&mut self) -> Result<bool, LfasError> {
+        let storage = &self.index.storage;
+        let Some((committed, snapshot)) = storage.read_metadata().map_err(LfasError::storage)?
+        else {
+            return Ok(false);
+        };
+        let generation = storage.generation().map_err(LfasError::storage)?;
+        if committed != generation {
+            warn!(
+                "[METADATA] Discarding metadata committed at generation {}, index is at {}",
+                committed, generation
+            );
+            return Ok(false);
+        }
+
+        self.metadata = bincode::deserialize(&snapshot)?;
+        self.synced_generation = Some(generation);
+        info!(
+            "[METADATA] Loaded metadata committed at generation {} ({} docs)",
+            generation, self.metadata.total_docs
+        );
+        Ok(true)
+    }
+
+    /// Applies the config stored with the index. Returns false, leaving the
+    /// current settings untouched, when none was saved.
+    pub fn load_config(&mut self) -> Result<bool, LfasError> {
+        let Some(bytes) = self.index.storage.read_config().map_err(LfasError::storage)? else {
+            return Ok(false);
+        };
+        let config = EngineConfig::from_bytes(&bytes)?;
+        info!("[CONFIG] Loaded engine config ({} fields)", config.schema.len());
+        self.apply_config(config);
+        Ok(true)
+    }
+
+    /// For read-only replicas: reloads metadata when the writer has committed
+    /// since the last sync, from the committed snapshot when it matches the
+    /// postings and by rebuilding otherwise. Returns whether anything was
+    /// reloaded. Postings need no refresh, every read transaction already
+    /// sees the latest commit.
+    pub fn refresh(&mut self) -> Result<bool, LfasError> {
+        // Read before rebuilding: a commit landing mid-rebuild is caught next time
+        let generation = self
+            .index
+            .storage
+            .generation()
+            .map_err(LfasError::storage)?;
+        if self.synced_generation == Some(generation) {
+            return Ok(false);
+        }
+
+        info!(
+            "[REPLICA] Storage generation {:?} -> {}, reloading metadata",
+            self.synced_generation, generation
+        );
+        self.index.clear_cache();
+        if !self.load_committed_metadata()? {
+            self.rebuild_metadata(|_| {})?;
+            self.synced_generation = Some(generation);
+        }
+        Ok(true)
+    }
+
+    /// Hashes `path` and records it as a source of the current build,
+    /// replacing an earlier entry for the same path.
+    pub fn record_source(&mut self, path: &Path) -> Result<(), LfasError> {
+        let source = SourceFile::from_path(path)?;
+        self.sources.retain(|existing| existing.path != source.path);
+        self.sources.push(source);
+        Ok(())
+    }
+
+    /// Hash of the tokenizer config and per-field rules. Changes whenever
+    /// queries would be tokenized differently from the indexed text.
+    pub fn tokenizer_hash(&self) -> Result<u64, LfasError> {
+        let mut rules: Vec<_> = self.field_rules.iter().collect();
+        rules.sort_by(|a, b| a.0.cmp(b.0));
+        let bytes = bincode::serialize(&(&self.tokenizer, rules))?;
+        Ok(stable_hash(&bytes))
+    }
+
+    /// Stamps the build provenance, stores it with the index and flushes.
+    /// Sources recorded by earlier builds are kept, so incremental builds
+    /// accumulate them.
+    pub fn save_provenance(&mut self) -> Result<Provenance, LfasError> {
+        let mut sources = self
+            .provenance()?
+            .map(|provenance| provenance.sources)
+            .unwrap_or_default();
+        for source in &self.sources {
+            sources.retain(|existing| existing.path != source.path);
+            sources.push(source.clone());
+        }
+
+        let provenance = Provenance {
+            sources,
+            record_count: self.metadata.total_docs,
+            built_at: unix_now(),
+            crate_version: env!("CARGO_PKG_VERSION").to_string(),
+            tokenizer_hash: self.tokenizer_hash()?,
+        };
+        self.index
+            .storage
+            .write_provenance(provenance.to_bytes()?)
+            .map_err(LfasError::storage)?;
+        self.flush()?;
+        self.sources.clear();
+
+        info!(
+            "[PROVENANCE] Saved provenance ({} sources, {} records)",
+            provenance.sources.len(),
+            provenance.record_count
+        );
+        Ok(provenance)
+    }
+
+    /// Build provenance stored with the index, if any was saved.
+    pub fn provenance(&self) -> Result<Option<Provenance>, LfasError> {
+        let Some(bytes) = self
+            .index
+            .storage
+            .read_provenance()
+            .map_err(LfasError::storage)?
+        else {
+            return Ok(None);
+        };
+        Ok(Some(Provenance::from_bytes(&bytes)?))
+    }
+}
+
+impl<F, S> TermLookup<F> for SearchEngine<F, S>
+where
+    F: Hash + Eq + Clone + Ord + Copy,
+    S: PostingsStorage<F>,
+{
+    fn postings(&self, field: F, term: &str) -> Option<Postings> {
+        self.index.get_postings(field, term)
+    }
+
+    fn df(&self, field: F, term: &str) -> usize {
+        self.metadata.get_df(&field, term)
+    }
+
+    fn total_docs(&self) -> usize {
+        self.metadata.total_docs
+    }
+}
+
+impl<F> SearchEngine<F, LmdbStorage<F>>
+where
+    F: Hash
+        + Eq
+        + Clone
+        + Ord
+        + Copy
+        + serde::Serialize
+        + serde::de::DeserializeOwned
+        + Send
+        + Sync
+        + 'static,
+{
+    /// Writes the in-memory document lengths to the index as bitpacked
+    /// columns, replacing any written before. Returns the number of columns.
+    pub fn persist_length_columns(&self) -> Result<usize, LfasError> {
+        let columns = self
+            .index
+            .storage
+            .write_length_columns(&self.metadata.lengths)
+            .map_err(LfasError::storage)?;
+        info!(
+            "[METADATA] Persisted {} length columns for {} docs",
+            columns,
+            self.metadata.lengths.len()
+        );
+        Ok(columns)
+    }
+
+    /// Reads document lengths from the persisted columns and drops the
+    /// in-memory map. Documents indexed afterwards keep their lengths in
+    /// memory. Returns false, changing nothing, when no columns were
+    /// persisted. Call again after `rebuild_metadata` or loading metadata.
+    pub fn use_length_columns(&mut self) -> Result<bool, LfasError> {
+        let Some(columns) = self
+            .index
+            .storage
+            .length_columns()
+            .map_err(LfasError::storage)?
+        else {
+            return Ok(false);
+        };
+        self.metadata.length_columns = Some(Arc::new(columns));
+        self.metadata.lengths = HashMap::new();
+        info!("[METADATA] Reading document lengths from persisted columns");
+        Ok(true)
+    }
+
+    /// Flushes, then pins the committed generation: the returned searcher
+    /// sees the postings, metadata and stored fields of that commit with all
+    /// of this engine's settings, whatever is indexed meanwhile. It shares
+    /// the engine's search slots and candidate generators. The generation is
+    /// retired once the searcher is dropped, so keep it only for the job.
+    pub fn searcher(&mut self) -> Result<Searcher<F>, LfasError> {
+        self.flush()?;
+        let snapshot = self.index.storage.snapshot().map_err(LfasError::storage)?;
+        let mut searcher = SearchEngine::new(
+            InvertedIndex::new(snapshot),
+            FieldMetadata::new(),
+            self.scorer.clone(),
+        );
+        searcher.apply_config(self.config());
+        searcher.limits = self.limits;
+        searcher.field_scores = self.field_scores;
+        searcher.cep_shortcut = self.cep_shortcut;
+        searcher.decompounder = self.decompounder;
+        searcher.vectors = self.vectors.clone();
+        searcher.spelling = self.spelling.clone();
+        searcher.auto_correct = self.auto_correct;
+        searcher.cooccurrence = self.cooccurrence.clone();
+        searcher.expansion = self.expansion;
+        searcher.generators = self.generators.clone();
+        // Impacts are skipped by the searcher once its metadata no longer matches them
+        searcher.impacts = self.impacts.clone();
+        // Its values follow the pinned metadata, so the cache isn't shared
+        searcher.score_cache = self
+            .score_cache
+            .as_ref()
+            .map(|cache| ScoreCache::new(cache.capacity()));
+        searcher.admission = self.admission.clone();
+        searcher.verbosity = self.verbosity;
+        // Matches the committed stored fields right after the flush
+        searcher.docs = self.docs.clone();
+        searcher.id_map = self.id_map.clone();
+        searcher.external_ids = self.external_ids.clone();
 
-        Self {
-            index: InvertedIndex::new(storage),
-            metadata: FieldMetadata::new(),
-            scorer: BM25FScorer {
-                k1: 1.2_f32,
-                field_weights,
-                field_b,
-            },
+        // Spilled dfs and length columns aren't in the committed snapshot
+        if self.metadata.store.is_some() || self.metadata.length_columns.is_some() {
+            searcher.rebuild_metadata(|_| {})?;
+        } else {
+            searcher.refresh()?;
         }
+        Ok(searcher)
     }
 }
 
@@ -67,59 +1801,1171 @@ where
     F: Hash + Eq + Clone + Ord + Copy + std::fmt::Debug,
     S: PostingsStorage<F>,
 {
-    pub fn execute(&self, query: StructuredQuery<F>, _blocking_k: usize) -> Vec<SearchHit> {
-        info!("[SEARCH] Starting search execution");
-        let search_timer = Timer::new("SearchEngine::execute");
+    /// Runs the two-round search. Hits are ordered by descending score with ties
+    /// broken by ascending doc_id, so the same index and query always produce
+    /// the same ranking.
+    pub fn execute(
+        &self,
+        query: StructuredQuery<F>,
+        _blocking_k: usize,
+    ) -> Result<Vec<SearchHit>, LfasError> {
+        Ok(self.execute_interruptible(query)?.hits)
+    }
+
+    /// Record-linkage decision for `query`: accept its best hit, send the
+    /// close ones to review, or report no match. Scores are divided by the
+    /// score a document holding exactly the query's values would get, so
+    /// one pair of thresholds works across queries. An exact external id hit
+    /// is always accepted.
+    pub fn match_one(
+        &self,
+        query: StructuredQuery<F>,
+        accept_threshold: f32,
+        review_threshold: f32,
+    ) -> Result<MatchDecision, LfasError> {
+        if !(0.0..=1.0).contains(&accept_threshold)
+            || !(0.0..=accept_threshold).contains(&review_threshold)
+        {
+            return Err(LfasError::InvalidQuery(format!(
+                "need 0 <= review_threshold <= accept_threshold <= 1, got {} and {}",
+                review_threshold, accept_threshold
+            )));
+        }
+
+        let _verbosity = self.query_verbosity(&query);
+        let profile = self.query_profile(&query);
+        let mut query_tokens = Vec::new();
+        let mut field_lengths: HashMap<F, usize> = HashMap::new();
+        for (field, text) in query.scored_fields() {
+            let token_set = self.analyze(*field, text);
+            *field_lengths.entry(*field).or_insert(0) += token_set.all.len();
+            for (token, weight) in token_set.weighted(profile.ngram_weight) {
+                let weight = match self.common_terms {
+                    Some(policy)
+                        if !token_set.weak.contains(&token)
+                            && self.is_common_term(*field, &token) =>
+                    {
+                        weight.min(policy.weight)
+                    }
+                    _ => weight,
+                };
+                query_tokens.push((*field, token, weight));
+            }
+        }
+        let self_score = self
+            .scorer
+            .self_score(&query_tokens, &field_lengths, &self.metadata);
+
+        let mut hits = self.execute(query, DEFAULT_BLOCKING_K)?;
+        for hit in &mut hits {
+            hit.score = if hit.exact {
+                1.0
+            } else if self_score > 0.0 {
+                (hit.score / self_score).min(1.0)
+            } else {
+                0.0
+            };
+        }
+
+        let accepted = hits
+            .iter()
+            .filter(|hit| hit.score >= accept_threshold)
+            .count();
+        hits.retain(|hit| hit.score >= review_threshold);
+        let decision = match (accepted, hits.is_empty()) {
+            (1, _) => MatchDecision::Accepted(hits.swap_remove(0)),
+            (_, true) => MatchDecision::NoMatch,
+            _ => MatchDecision::Review(hits),
+        };
+        query_debug!(
+            "[SEARCH] Match decision against self score {:.4}: {:?}",
+            self_score, decision
+        );
+        Ok(decision)
+    }
+
+    /// [`execute`](Self::execute) honouring the query's deadline and cancel
+    /// token: past either, the search stops and reports the hits scored so far.
+    pub fn execute_interruptible(
+        &self,
+        query: StructuredQuery<F>,
+    ) -> Result<SearchResults, LfasError> {
+        let started = std::time::Instant::now();
+        let _verbosity = self.query_verbosity(&query);
+        let fields = self.slow_log.as_ref().map(|_| query.fields.clone());
+        let mut results = self.run_query(query)?;
+        results.diagnostics.total = started.elapsed();
+        self.metrics.record_query(results.diagnostics.total, results.interrupted);
+        self.log_summary(
+            &results.diagnostics,
+            results.interrupted,
+            results.hits.len(),
+        );
+        if let Some(fields) = fields {
+            self.log_query(
+                &fields,
+                &results.diagnostics,
+                results.interrupted,
+                results.hits.len(),
+            );
+        }
+        Ok(results)
+    }
+
+    /// [`execute`](Self::execute) for large `top_k`: the same hits in the same
+    /// order, but each [`SearchHit`] is built as the iterator reaches it
+    /// rather than all at once.
+    pub fn execute_iter(&self, query: StructuredQuery<F>) -> Result<HitIter<F>, LfasError> {
+        let started = std::time::Instant::now();
+        let _verbosity = self.query_verbosity(&query);
+        let fields = self.slow_log.as_ref().map(|_| query.fields.clone());
+        let (hits, interrupted, mut diagnostics) = self.run_query_lazy(query)?;
+        diagnostics.total = started.elapsed();
+        self.metrics.record_query(diagnostics.total, interrupted);
+        self.log_summary(&diagnostics, interrupted, hits.len());
+        if let Some(fields) = fields {
+            self.log_query(&fields, &diagnostics, interrupted, hits.len());
+        }
+        Ok(hits)
+    }
+
+    /// The line summing up a search, with its counts and timings as fields;
+    /// all that is logged at [`Verbosity::Summary`].
+    fn log_summary(&self, diagnostics: &QueryDiagnostics, interrupted: bool, hits: usize) {
+        if !verbosity::current().summary() {
+            return;
+        }
+        let ms = |elapsed: std::time::Duration| elapsed.as_secs_f64() * 1000.0;
+        info!(
+            hits,
+            candidates = diagnostics.candidates,
+            total_ms = ms(diagnostics.total),
+            round1_ms = ms(diagnostics.round1),
+            round2_ms = ms(diagnostics.round2),
+            fallback = diagnostics.fallback,
+            exact = diagnostics.exact,
+            cep_shortcut = diagnostics.cep_shortcut,
+            interrupted,
+            "[SEARCH] {} hits from {} candidates in {:.2}ms",
+            hits,
+            diagnostics.candidates,
+            ms(diagnostics.total)
+        );
+    }
+
+    /// Hands a finished search to the slow-query log. Token stats are only
+    /// computed for the searches it keeps.
+    fn log_query(
+        &self,
+        fields: &[(F, String)],
+        diagnostics: &QueryDiagnostics,
+        interrupted: bool,
+        hits: usize,
+    ) {
+        let Some(log) = &self.slow_log else {
+            return;
+        };
+        let Some(sampled) = log.should_record(diagnostics.total) else {
+            return;
+        };
+
+        let fields = fields
+            .iter()
+            .map(|(field, text)| {
+                let token_set = self.analyze(*field, text);
+                let max_df = token_set
+                    .all
+                    .difference(&token_set.weak)
+                    .map(|token| self.metadata.get_df(field, token))
+                    .max()
+                    .unwrap_or(0);
+                FieldTokenStats {
+                    field: format!("{:?}", field),
+                    chars: text.chars().count(),
+                    tokens: token_set.all.len() - token_set.weak.len(),
+                    ngrams: token_set.weak.len(),
+                    max_df,
+                }
+            })
+            .collect();
+        let ms = |elapsed: std::time::Duration| elapsed.as_secs_f64() * 1000.0;
+        if !sampled && verbosity::current().summary() {
+            warn!(
+                "[SEARCH] Slow query: {:.1} ms, {} candidates",
+                ms(diagnostics.total),
+                diagnostics.candidates
+            );
+        }
+        log.push(SlowQuery {
+            finished_at: unix_now(),
+            sampled,
+            total_ms: ms(diagnostics.total),
+            round1_ms: ms(diagnostics.round1),
+            round2_ms: ms(diagnostics.round2),
+            candidates: diagnostics.candidates,
+            round1_tokens: diagnostics.round1_tokens.len(),
+            hits,
+            fallback: diagnostics.fallback,
+            exact: diagnostics.exact,
+            cep_shortcut: diagnostics.cep_shortcut,
+            interrupted,
+            fields,
+        });
+    }
+
+    /// Sets the query's verbosity, or the engine's, for this thread until
+    /// the guard is dropped.
+    fn query_verbosity(&self, query: &StructuredQuery<F>) -> verbosity::VerbosityGuard {
+        verbosity::scoped(query.verbosity.unwrap_or(self.verbosity))
+    }
+
+    fn run_query(&self, query: StructuredQuery<F>) -> Result<SearchResults, LfasError> {
+        let (hits, interrupted, diagnostics) = self.run_query_lazy(query)?;
+        let hits: Vec<SearchHit> = hits.collect();
+        query_info!(
+            "[SEARCH] Returning {} results{}",
+            hits.len(),
+            if interrupted { " (partial)" } else { "" }
+        );
+        Ok(SearchResults {
+            hits,
+            interrupted,
+            diagnostics,
+        })
+    }
+
+    /// Both rounds of the search, with the top-k hits left unbuilt.
+    fn run_query_lazy(
+        &self,
+        query: StructuredQuery<F>,
+    ) -> Result<(HitIter<F>, bool, QueryDiagnostics), LfasError> {
+        query_info!("[SEARCH] Starting search execution");
+        query.validate(&self.limits)?;
+        let _permit = self.admit()?;
+        let search_timer = Timer::in_span(
+            "SearchEngine::execute",
+            info_span!(
+                "search",
+                candidates = field::Empty,
+                elapsed_ms = field::Empty
+            ),
+        );
+
+        // ROUND 0: The caller already has the key, skip fuzzy matching entirely
+        if let Some(hit) = self.exact_hit(&query) {
+            let diagnostics = QueryDiagnostics {
+                candidates: 1,
+                exact: true,
+                ..Default::default()
+            };
+            return Ok((HitIter::exact(&hit), false, diagnostics));
+        }
+
+        // ROUND 0: a full CEP matching a handful of documents ranks them directly
+        let shortcut_started = std::time::Instant::now();
+        if let Some(ranked) = self.cep_hits(&query) {
+            query_info!(
+                "[SEARCH] CEP shortcut: ranking {} documents without BM25F",
+                ranked.len()
+            );
+            self.metrics.record_candidates(ranked.len() as u64);
+            let mut diagnostics = QueryDiagnostics {
+                candidates: ranked.len() as u64,
+                cep_shortcut: true,
+                round1: shortcut_started.elapsed(),
+                ..Default::default()
+            };
+            let (mut ranked, collapsed) = self.collapse(ranked);
+            diagnostics.collapsed = collapsed;
+            ranked.truncate(query.top_k);
+            return Ok((HitIter::new(ranked, HashMap::new()), false, diagnostics));
+        }
+
+        let round1_started = std::time::Instant::now();
+        let CandidateSet {
+            candidates,
+            query_tokens: all_query_tokens,
+            round1_tokens,
+            fallback,
+        } = self.find_candidates(&query);
+        self.metrics.record_candidates(candidates.len());
+        search_timer.span().record("candidates", candidates.len());
+        let mut diagnostics = QueryDiagnostics {
+            candidates: candidates.len(),
+            round1_tokens: round1_tokens
+                .into_iter()
+                .map(|(field, token)| (format!("{:?}", field), token))
+                .collect(),
+            fallback,
+            round1: round1_started.elapsed(),
+            ..Default::default()
+        };
+
+        if query.is_interrupted() {
+            query_info!("[SEARCH] Interrupted after candidate generation");
+            return Ok((HitIter::new(vec![], HashMap::new()), true, diagnostics));
+        }
+
+        if candidates.is_empty() {
+            query_info!("[SEARCH] No candidates found, returning empty results");
+            return Ok((HitIter::new(vec![], HashMap::new()), false, diagnostics));
+        }
+
+        // ROUND 2: Score candidates using ALL tokens (including weak n-grams)
+        query_info!(
+            "[SEARCH] ROUND 2: Scoring {} candidates with {} query tokens",
+            candidates.len(),
+            all_query_tokens.len()
+        );
+
+        let round2_timer = Timer::in_span(
+            "Round2::ScoreCandidates",
+            info_span!(
+                "scoring",
+                candidates = candidates.len(),
+                tokens = all_query_tokens.len(),
+                elapsed_ms = field::Empty
+            ),
+        );
+        let round2_started = std::time::Instant::now();
+        let impacts = self.impacts.as_ref().filter(|impacts| {
+            let current = impacts.params.matches(&self.scorer, &self.metadata);
+            if !current {
+                query_debug!("[SEARCH] Impacts are stale, scoring with BM25F");
+            }
+            !self.field_scores && current
+        });
+        let (scored_results, interrupted, field_sums) = match impacts {
+            Some(impacts) => {
+                let (scored, interrupted) =
+                    impacts.score(&candidates, &all_query_tokens, &|| query.is_interrupted());
+                (scored, interrupted, HashMap::new())
+            }
+            None if self.field_scores => self.scorer.score_fields_until(
+                candidates,
+                &all_query_tokens,
+                &self.index,
+                &self.metadata,
+                &|| query.is_interrupted(),
+            ),
+            None => {
+                let (scored, interrupted) = match &self.score_cache {
+                    Some(cache) => {
+                        let generation = self
+                            .index
+                            .storage
+                            .generation()
+                            .map_err(LfasError::storage)?;
+                        cache.sync(generation, &self.scorer, &self.metadata);
+                        self.scorer.score_cached_until(
+                            candidates,
+                            &all_query_tokens,
+                            &self.index,
+                            &self.metadata,
+                            cache,
+                            &|| query.is_interrupted(),
+                        )
+                    }
+                    None => self.scorer.score_weighted_until(
+                        candidates,
+                        &all_query_tokens,
+                        &self.index,
+                        &self.metadata,
+                        &|| query.is_interrupted(),
+                    ),
+                };
+                (scored, interrupted, HashMap::new())
+            }
+        };
+        diagnostics.round2 = round2_started.elapsed();
+        drop(round2_timer);
+
+        query_info!("[SEARCH] Scored {} documents", scored_results.len());
+
+        let (mut scored_results, collapsed) = self.collapse(scored_results);
+        diagnostics.collapsed = collapsed;
+
+        if let (Some(reranker), Some(vector)) = (&self.vectors, &query.vector) {
+            scored_results = reranker.rerank(&vector.0, scored_results, query.top_k)?;
+        }
+
+        // Take top-k results
+        scored_results.truncate(query.top_k);
+
+        drop(search_timer);
+        Ok((
+            HitIter::new(scored_results, field_sums),
+            interrupted,
+            diagnostics,
+        ))
+    }
+
+    /// Round 1 only: the doc ids the query would score, for external blocking
+    /// in record linkage pipelines. Honours the blocking strategy,
+    /// `min_should_match` and the rarest-token fallback; `top_k` is ignored.
+    pub fn candidates(&self, query: &StructuredQuery<F>) -> Result<RoaringBitmap, LfasError> {
+        query.validate(&self.limits)?;
+        let _verbosity = self.query_verbosity(query);
+
+        if let Some(hit) = self.exact_hit(query) {
+            return Ok(std::iter::once(hit.doc_id.get()).collect());
+        }
+
+        let candidates = self.find_candidates(query).candidates;
+        self.metrics.record_candidates(candidates.len());
+        Ok(candidates)
+    }
+
+    /// Documents whose `field` holds `term`, for composing blocking logic
+    /// outside of [`candidates`](Self::candidates). `term` is looked up as
+    /// indexed, i.e. as [`analyze`](Self::analyze) emits it.
+    pub fn bitmap_for(&self, field: F, term: &str) -> RoaringBitmap {
+        self.index.term_bitmap(field, term)
+    }
+
+    /// Value -> count of `field` over the query's candidates, from the stored
+    /// field values, most frequent first. A multi-value field counts each of
+    /// its values once; documents without a stored value are left out.
+    pub fn facets(
+        &self,
+        query: &StructuredQuery<F>,
+        field: F,
+    ) -> Result<Vec<(String, usize)>, LfasError> {
+        let candidates = self.candidates(query)?;
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for doc_id in candidates.iter() {
+            let Some(stored) = self.docs.field(DocId::new(doc_id), field) else {
+                continue;
+            };
+            let values: HashSet<&str> = stored.split(VALUE_SEPARATOR).collect();
+            for value in values {
+                *counts.entry(value).or_insert(0) += 1;
+            }
+        }
+
+        let mut facets: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(value, count)| (value.to_string(), count))
+            .collect();
+        facets.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        Ok(facets)
+    }
+
+    /// Runs the BM25F search, then lets `rerank` reorder (or rescore, or drop)
+    /// the top-k hits. The closure receives each hit with its stored fields and
+    /// the query tokens it matched, and returns the final hit list.
+    pub fn execute_with_rerank<R, E>(
+        &self,
+        query: StructuredQuery<F>,
+        rerank: R,
+    ) -> Result<Vec<SearchHit>, E>
+    where
+        R: FnOnce(Vec<RerankCandidate<'_, F>>) -> Result<Vec<SearchHit>, E>,
+        E: From<LfasError>,
+    {
+        let query_tokens = self.query_tokens(&query);
+        let blocking_k = query.blocking_k;
+        let hits = self.execute(query, blocking_k)?;
+
+        let postings = self
+            .index
+            .get_postings_batch(&query_tokens)
+            .map_err(LfasError::storage)?;
+
+        let candidates = hits
+            .into_iter()
+            .map(|hit| {
+                let mut matched: HashMap<F, Vec<String>> = HashMap::new();
+                for ((field, token), postings) in query_tokens.iter().zip(&postings) {
+                    if postings.as_ref().is_some_and(|p| p.contains(hit.doc_id)) {
+                        matched.entry(*field).or_default().push(token.clone());
+                    }
+                }
+                RerankCandidate {
+                    doc: self.docs.get(hit.doc_id),
+                    hit,
+                    matched,
+                }
+            })
+            .collect();
+
+        rerank(candidates)
+    }
+
+    /// [`execute`](Self::execute), with the values of `fields` for each hit.
+    /// See [`fetch_fields`](Self::fetch_fields).
+    pub fn search_with_fields(
+        &self,
+        query: StructuredQuery<F>,
+        fields: &[F],
+    ) -> Result<Vec<HitWithFields<F>>, LfasError> {
+        let blocking_k = query.blocking_k;
+        let hits = self.execute(query, blocking_k)?;
+        let doc_ids: Vec<DocId> = hits.iter().map(|hit| hit.doc_id).collect();
+        let docs = self.fetch_fields(&doc_ids, fields)?;
+        Ok(hits.into_iter().zip(docs).collect())
+    }
+
+    /// Values of `fields` for each of `doc_ids`, in order. They come from
+    /// the doc store, or for documents it doesn't hold (e.g. in a reopened
+    /// LMDB index) from the storage's stored-field columns, of which only
+    /// the requested ones are read. Missing values are left out.
+    pub fn fetch_fields(
+        &self,
+        doc_ids: &[DocId],
+        fields: &[F],
+    ) -> Result<Vec<HashMap<F, String>>, LfasError> {
+        let mut docs = Vec::with_capacity(doc_ids.len());
+        let mut missing = Vec::new();
+        for (i, doc_id) in doc_ids.iter().enumerate() {
+            let doc = match self.docs.get(*doc_id) {
+                Some(doc) => fields
+                    .iter()
+                    .filter_map(|field| doc.get(field).map(|value| (*field, value.clone())))
+                    .collect(),
+                None => {
+                    missing.push(i);
+                    HashMap::new()
+                }
+            };
+            docs.push(doc);
+        }
+
+        if !missing.is_empty() {
+            let ids: Vec<DocId> = missing.iter().map(|&i| doc_ids[i]).collect();
+            let stored = self
+                .index
+                .storage
+                .read_stored(&ids, fields)
+                .map_err(LfasError::storage)?;
+            for (i, doc) in missing.into_iter().zip(stored.into_iter().flatten()) {
+                docs[i] = doc;
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Every (field, token) a query scores with, sorted and deduplicated.
+    fn query_tokens(&self, query: &StructuredQuery<F>) -> Vec<(F, String)> {
+        let mut tokens: Vec<(F, String)> = query
+            .scored_fields()
+            .flat_map(|(field, text)| {
+                self.analyze_query(*field, text)
+                    .all
+                    .into_iter()
+                    .map(move |token| (*field, token))
+            })
+            .collect();
+        tokens.sort();
+        tokens.dedup();
+        tokens
+    }
+
+    /// Runs the search and reranks the top-k by string similarity between the
+    /// query text and the stored fields, blended with the BM25F score.
+    pub fn execute_similar(
+        &self,
+        query: StructuredQuery<F>,
+        reranker: &SimilarityReranker,
+    ) -> Result<Vec<SearchHit>, LfasError> {
+        let fields: Vec<(F, String)> = query.scored_fields().cloned().collect();
+        self.execute_with_rerank(query, |candidates| {
+            Ok::<_, LfasError>(reranker.rerank(&fields, candidates))
+        })
+    }
+
+    /// Fast approximate search: scores a random sample of at most `sample_size`
+    /// candidates instead of the whole set. Hits are the top-k of the sample and
+    /// counts are scaled back to the full candidate set. Same seed, same sample.
+    pub fn execute_preview(
+        &self,
+        query: StructuredQuery<F>,
+        sample_size: usize,
+        seed: u64,
+    ) -> Result<PreviewResult, LfasError> {
+        query.validate(&self.limits)?;
+        let _permit = self.admit()?;
+        let _verbosity = self.query_verbosity(&query);
+        let _timer = Timer::new("SearchEngine::execute_preview");
+
+        if let Some(hit) = self.exact_hit(&query) {
+            return Ok(PreviewResult {
+                distribution: ScoreDistribution {
+                    min: hit.score,
+                    median: hit.score,
+                    p90: hit.score,
+                    max: hit.score,
+                },
+                hits: vec![hit],
+                approximate: false,
+                total_candidates: 1,
+                sampled_candidates: 1,
+                estimated_matches: 1,
+            });
+        }
 
-        // ROUND 1: Use DISTINCTIVE tokens to find candidates
-        info!("[SEARCH] ROUND 1: Finding candidates using distinctive tokens");
-        let round1_timer = Timer::new("Round1::FindCandidates");
+        let CandidateSet {
+            candidates,
+            query_tokens: all_query_tokens,
+            ..
+        } = self.find_candidates(&query);
+        let total_candidates = candidates.len();
+
+        let (sample, approximate) = if total_candidates as usize <= sample_size {
+            (candidates, false)
+        } else {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let sample: RoaringBitmap =
+                rand::seq::index::sample(&mut rng, total_candidates as usize, sample_size)
+                    .into_iter()
+                    .filter_map(|rank| candidates.select(rank as u32))
+                    .collect();
+            (sample, true)
+        };
+        let sampled_candidates = sample.len();
+
+        query_info!(
+            "[SEARCH] PREVIEW: scoring {} of {} candidates",
+            sampled_candidates, total_candidates
+        );
+
+        let scored = if sample.is_empty() {
+            Vec::new()
+        } else {
+            self.scorer
+                .score_weighted(sample, &all_query_tokens, &self.index, &self.metadata)
+        };
+
+        let estimated_matches = if sampled_candidates == 0 {
+            0
+        } else {
+            (scored.len() as f64 * total_candidates as f64 / sampled_candidates as f64).round()
+                as u64
+        };
+
+        // Scores come back sorted descending
+        let score_at = |i: usize| scored.get(i).map(|(_, s)| *s).unwrap_or(0.0);
+        let distribution = ScoreDistribution {
+            min: scored.last().map(|(_, s)| *s).unwrap_or(0.0),
+            median: score_at(scored.len() / 2),
+            p90: score_at(scored.len() / 10),
+            max: score_at(0),
+        };
+
+        let hits = scored
+            .into_iter()
+            .take(query.top_k)
+            .map(|(doc_id, score)| SearchHit {
+                doc_id,
+                score,
+                exact: false,
+                field_scores: Vec::new(),
+            })
+            .collect();
+
+        Ok(PreviewResult {
+            hits,
+            approximate,
+            total_candidates,
+            sampled_candidates,
+            estimated_matches,
+            distribution,
+        })
+    }
+
+    /// ROUND 0: resolves the query's external id through the id map.
+    fn exact_hit(&self, query: &StructuredQuery<F>) -> Option<SearchHit> {
+        let external_id = query.external_id.as_ref()?;
+        match self.id_map.get(external_id) {
+            Some(&doc_id) => {
+                query_info!("[SEARCH] Exact id match for '{}': doc_id={}", external_id, doc_id);
+                Some(SearchHit {
+                    doc_id,
+                    score: EXACT_MATCH_SCORE,
+                    exact: true,
+                    field_scores: Vec::new(),
+                })
+            }
+            None => {
+                query_debug!("[SEARCH] Unknown external id '{}', using fuzzy search", external_id);
+                None
+            }
+        }
+    }
+
+    /// Whether `term` is in more of the documents than [`CommonTerms`] allows.
+    /// Always false while `common_terms` is unset.
+    pub fn is_common_term(&self, field: F, term: &str) -> bool {
+        let Some(policy) = self.common_terms else {
+            return false;
+        };
+        let total_docs = self.metadata.total_docs;
+        total_docs >= policy.min_docs
+            && self.metadata.get_df(&field, term) as f32 > policy.max_df_ratio * total_docs as f32
+    }
+
+    /// Settings `query` runs with: its preset's, or the engine's. A
+    /// `min_should_match` on the query itself always wins.
+    pub fn query_profile(&self, query: &StructuredQuery<F>) -> QueryProfile {
+        let profile = match query.preset {
+            Some(preset) => preset.profile(),
+            None => QueryProfile {
+                blocking: self.blocking,
+                min_should_match: None,
+                auto_correct: self.auto_correct,
+                fallback: self.fallback.enabled,
+                ngram_weight: self.ngram_weight,
+            },
+        };
+        QueryProfile {
+            min_should_match: query.min_should_match.or(profile.min_should_match),
+            ..profile
+        }
+    }
+
+    /// Docs holding every full token of `text` in `field`; `None` when the
+    /// text has no full token.
+    fn clause_docs(&self, field: F, text: &str) -> Option<RoaringBitmap> {
+        let token_set = self.analyze(field, text);
+        token_set
+            .all
+            .difference(&token_set.weak)
+            .map(|token| self.index.term_bitmap(field, token))
+            .reduce(|docs, bitmap| docs & bitmap)
+    }
+
+    /// Keeps the best-scoring hit of each collapse key from `scored`, which
+    /// is sorted best first. Also returns how many hits were dropped.
+    fn collapse(&self, scored: Vec<(DocId, f32)>) -> (Vec<(DocId, f32)>, u64) {
+        let collapse_keys = &self.metadata.collapse_keys;
+        let mut seen_keys = HashSet::new();
+        let scored_count = scored.len();
+        let kept: Vec<(DocId, f32)> = scored
+            .into_iter()
+            .filter(|(doc_id, _)| match collapse_keys.get(doc_id) {
+                Some(key) => seen_keys.insert(*key),
+                None => true,
+            })
+            .collect();
+        let collapsed = (scored_count - kept.len()) as u64;
+        (kept, collapsed)
+    }
+
+    /// The documents of the query's rarest full CEP when `cep_shortcut` is
+    /// set and it matches no more than that many, after the query's filters,
+    /// Must/MustNot clauses and presence checks. Each scores the share of the
+    /// query's full tokens it holds; sorted best first, ties by ascending
+    /// doc_id. `None` sends the query through both rounds as usual.
+    fn cep_hits(&self, query: &StructuredQuery<F>) -> Option<Vec<(DocId, f32)>> {
+        let max_docs = self.cep_shortcut?;
+        let mut full_tokens: Vec<(F, String)> = query
+            .scored_fields()
+            .flat_map(|(field, text)| {
+                let token_set = self.analyze(*field, text);
+                token_set
+                    .all
+                    .difference(&token_set.weak)
+                    .map(|token| (*field, token.clone()))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        full_tokens.sort();
+        full_tokens.dedup();
+
+        let mut docs = full_tokens
+            .iter()
+            .filter(|(_, token)| is_cep(token))
+            .map(|(field, token)| self.index.term_bitmap(*field, token))
+            .filter(|docs| !docs.is_empty())
+            .min_by_key(RoaringBitmap::len)?;
+        if docs.len() > max_docs as u64 {
+            query_debug!(
+                "[SEARCH] CEP matches {} documents, over the shortcut's {}",
+                docs.len(),
+                max_docs
+            );
+            return None;
+        }
+        self.narrow(query, &mut docs);
+        if docs.is_empty() {
+            return None;
+        }
+
+        let bitmaps: Vec<RoaringBitmap> = full_tokens
+            .iter()
+            .map(|(field, token)| self.index.term_bitmap(*field, token))
+            .collect();
+        let mut ranked: Vec<(DocId, f32)> = docs
+            .iter()
+            .map(|doc_id| {
+                let matched = bitmaps
+                    .iter()
+                    .filter(|bitmap| bitmap.contains(doc_id))
+                    .count();
+                (DocId::new(doc_id), matched as f32 / full_tokens.len() as f32)
+            })
+            .collect();
+        ranked.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        Some(ranked)
+    }
+
+    /// Applies the query's filters, Must/MustNot clauses and presence
+    /// checks to `candidates`; none of them adds a document.
+    fn narrow(&self, query: &StructuredQuery<F>, candidates: &mut RoaringBitmap) {
+        // Filters only narrow the candidates; their tokens are never scored
+        for (field, text) in &query.filters {
+            if let Some(docs) = self.clause_docs(*field, text) {
+                *candidates &= docs;
+            }
+            query_info!(
+                "[SEARCH]   Filter {:?} = '{}': {} candidates left",
+                field,
+                text,
+                candidates.len()
+            );
+        }
+
+        // Must clauses narrow like filters, MustNot clauses drop what one would keep
+        for (clause, (field, text)) in query.fields.iter().enumerate() {
+            let occur = query.occur(clause);
+            if occur == Occur::Should {
+                continue;
+            }
+            let Some(docs) = self.clause_docs(*field, text) else {
+                continue;
+            };
+            match occur {
+                Occur::Must => *candidates &= docs,
+                Occur::MustNot => *candidates -= docs,
+                Occur::Should => {}
+            }
+            query_info!(
+                "[SEARCH]   {:?} {:?} = '{}': {} candidates left",
+                occur,
+                field,
+                text,
+                candidates.len()
+            );
+        }
+
+        for (field, presence) in &query.presence {
+            let empty = self.metadata.empty_fields.get(field);
+            match (presence, empty) {
+                (FieldPresence::Empty, Some(empty)) => *candidates &= empty,
+                (FieldPresence::Empty, None) => candidates.clear(),
+                (FieldPresence::NotEmpty, Some(empty)) => *candidates -= empty,
+                (FieldPresence::NotEmpty, None) => {}
+            }
+            query_info!(
+                "[SEARCH]   Presence {:?} {:?}: {} candidates left",
+                field,
+                presence,
+                candidates.len()
+            );
+        }
+    }
+
+    /// ROUND 1: builds the candidate set from distinctive tokens (falling back to
+    /// the rarest tokens) and returns it with every query token for scoring.
+    fn find_candidates(&self, query: &StructuredQuery<F>) -> CandidateSet<F> {
+        query_info!("[SEARCH] ROUND 1: Finding candidates using distinctive tokens");
+        let round1_timer = Timer::in_span(
+            "Round1::FindCandidates",
+            info_span!(
+                "round1",
+                candidates = field::Empty,
+                elapsed_ms = field::Empty
+            ),
+        );
+        let profile = self.query_profile(query);
+        if let Some(preset) = query.preset {
+            query_info!("[SEARCH]   Preset: {:?}", preset);
+        }
 
         let mut candidates = RoaringBitmap::new();
-        let mut all_query_tokens: Vec<(F, String)> = Vec::new();
+        // (field, token) -> weight; a token seen both full and as an n-gram keeps the full weight
+        let mut token_weights: HashMap<(F, String), f32> = HashMap::new();
+        let (mut postings_hits, mut postings_misses) = (0u64, 0u64);
+        // Only kept when min_should_match needs per-token counts
+        let mut distinctive_bitmaps: Vec<RoaringBitmap> = Vec::new();
+        let mut distinctive_total = 0;
+        let mut round1_tokens: Vec<(F, String)> = Vec::new();
+        // (df, field, token) of every distinctive token, for RarestFirst
+        let mut by_df: Vec<(usize, F, String)> = Vec::new();
+        // Co-occurring terms added by query expansion
+        let mut expanded: Vec<(F, String)> = Vec::new();
+        // Analyzed clauses handed to the generators, when any are set
+        let mut clauses: Vec<Clause<F>> = Vec::new();
+        // Generators take over Round 1, so per-token counts don't apply
+        let min_should_match = profile
+            .min_should_match
+            .filter(|_| self.generators.is_empty());
 
-        for (field, text) in &query.fields {
-            debug!("[SEARCH] Processing field {:?}: '{}'", field, text);
-            let token_set = tokenize_structured(text);
+        for (field, text) in query.scored_fields() {
+            query_debug!("[SEARCH] Processing field {:?}: '{}'", field, text);
+            let mut token_set = self.analyze_query(*field, text);
+            let common: HashSet<String> = token_set
+                .all
+                .difference(&token_set.weak)
+                .filter(|token| self.is_common_term(*field, token))
+                .cloned()
+                .collect();
+            if !common.is_empty() {
+                query_debug!("[SEARCH]     Common terms demoted: {:?}", common);
+                token_set
+                    .distinctive
+                    .retain(|token| !common.contains(token));
+            }
 
-            info!(
+            query_info!(
                 "[SEARCH]   Field {:?} - Distinctive tokens: {}, All tokens: {}",
                 field,
                 token_set.distinctive.len(),
                 token_set.all.len()
             );
 
-            // Round 1: Union of distinctive tokens (any match qualifies)
-            for token in &token_set.distinctive {
-                if let Some(postings) = self.index.get_postings(*field, token) {
-                    let before = candidates.len();
-                    candidates |= postings.bitmap();
-                    let after = candidates.len();
-                    debug!(
-                        "[SEARCH]     Token '{}' added {} candidates (total: {} -> {})",
+            match profile.blocking {
+                // Round 1: left to the generators once the clauses are collected
+                _ if !self.generators.is_empty() => {}
+                // Round 1: Union of distinctive tokens (any match qualifies)
+                BlockingStrategy::Union => {
+                    for token in &token_set.distinctive {
+                        distinctive_total += 1;
+                        let Some(postings) = self.index.get_postings(*field, token) else {
+                            postings_misses += 1;
+                            continue;
+                        };
+                        postings_hits += 1;
+                        round1_tokens.push((*field, token.clone()));
+                        if min_should_match.is_some() {
+                            distinctive_bitmaps.push(postings.bitmap().clone());
+                        }
+
+                        let before = candidates.len();
+                        candidates |= postings.bitmap();
+                        let after = candidates.len();
+                        query_debug!(
+                            "[SEARCH]     Token '{}' added {} candidates (total: {} -> {})",
+                            token,
+                            after - before,
+                            before,
+                            after
+                        );
+                    }
+                }
+                // Round 1: every known full token of the clause must co-occur
+                BlockingStrategy::FieldIntersection => {
+                    let mut block: Option<RoaringBitmap> = None;
+                    let known = token_set.all.difference(&token_set.weak);
+                    for token in known.filter(|token| !common.contains(*token)) {
+                        let Some(postings) = self.index.get_postings(*field, token) else {
+                            // Unknown tokens (typos) don't veto the clause
+                            postings_misses += 1;
+                            continue;
+                        };
+                        postings_hits += 1;
+                        round1_tokens.push((*field, token.clone()));
+                        block = Some(match block {
+                            Some(block) => block & postings.bitmap(),
+                            None => postings.bitmap().clone(),
+                        });
+                    }
+
+                    if let Some(block) = block {
+                        distinctive_total += 1;
+                        query_debug!(
+                            "[SEARCH]     Field {:?} block has {} candidates",
+                            field,
+                            block.len()
+                        );
+                        candidates |= &block;
+                        if min_should_match.is_some() {
+                            distinctive_bitmaps.push(block);
+                        }
+                    }
+                }
+                // Round 1: ranked here, unioned once every clause is seen
+                BlockingStrategy::RarestFirst => {
+                    for token in &token_set.distinctive {
+                        let df = self.metadata.get_df(field, token);
+                        by_df.push((df, *field, token.clone()));
+                    }
+                }
+            }
+
+            // Unknown words get their closest indexed spelling as an extra full token
+            if profile.auto_correct {
+                if let Some(spelling) = &self.spelling {
+                    for token in &token_set.all {
+                        if let Some(corrected) = spelling.correct(*field, token, &self.metadata) {
+                            query_debug!("[SEARCH]     Corrected '{}' -> '{}'", token, corrected);
+                            token_weights.insert((*field, corrected), 1.0);
+                        }
+                    }
+                }
+            }
+
+            // Run-together words ("ruamauriti") are scored as their parts, analyzed as if typed apart
+            if let Some(decompounder) = &self.decompounder {
+                for token in token_set.all.difference(&token_set.weak) {
+                    let Some(split) = decompounder.split(*field, token, &self.metadata) else {
+                        continue;
+                    };
+                    query_debug!(
+                        "[SEARCH]     Split '{}' -> {:?} (df mean {:.1})",
                         token,
-                        after - before,
-                        before,
-                        after
+                        split.parts,
+                        split.score
                     );
+                    let parts = self.analyze(*field, &split.parts.join(" "));
+                    for (part, weight) in parts.weighted(profile.ngram_weight) {
+                        if parts.weak.contains(&part) {
+                            continue;
+                        }
+                        let entry = token_weights.entry((*field, part)).or_insert(weight);
+                        *entry = entry.max(weight);
+                    }
                 }
             }
 
             // Collect ALL tokens for Round 2 scoring
-            for token in token_set.all {
-                all_query_tokens.push((*field, token));
+            for (token, weight) in token_set.weighted(profile.ngram_weight) {
+                let weight = match self.common_terms {
+                    Some(policy) if common.contains(&token) => weight.min(policy.weight),
+                    _ => weight,
+                };
+                let entry = token_weights.entry((*field, token)).or_insert(weight);
+                *entry = entry.max(weight);
+            }
+
+            // Top co-occurring terms of each full token, scored at a discount
+            if let (Some(expansion), Some(cooccurrence)) = (&self.expansion, &self.cooccurrence) {
+                for token in token_set.all.difference(&token_set.weak) {
+                    let related =
+                        cooccurrence.related(*field, token, expansion.terms, expansion.min_count);
+                    for (term, count) in related {
+                        query_debug!(
+                            "[SEARCH]     Expanded '{}' -> '{}' (seen together {} times)",
+                            token, term, count
+                        );
+                        token_weights
+                            .entry((*field, term.clone()))
+                            .or_insert(expansion.weight);
+                        expanded.push((*field, term));
+                    }
+                }
+            }
+
+            if !self.generators.is_empty() {
+                clauses.push(Clause {
+                    field: *field,
+                    tokens: token_set,
+                });
+            }
+        }
+
+        by_df.sort_by_key(|(df, _, _)| *df);
+        for (df, field, token) in by_df {
+            if candidates.len() >= query.blocking_k as u64 {
+                query_info!(
+                    "[SEARCH]   Reached blocking_k={} before token '{}' (df={})",
+                    query.blocking_k, token, df
+                );
+                break;
+            }
+            distinctive_total += 1;
+            let Some(postings) = self.index.get_postings(field, &token) else {
+                postings_misses += 1;
+                continue;
+            };
+            postings_hits += 1;
+            if min_should_match.is_some() {
+                distinctive_bitmaps.push(postings.bitmap().clone());
+            }
+            candidates |= postings.bitmap();
+            query_debug!(
+                "[SEARCH]     Token '{}' (df={}) brings the total to {}",
+                token,
+                df,
+                candidates.len()
+            );
+            round1_tokens.push((field, token));
+        }
+
+        // Generators are ORed: a document proposed by any of them is a candidate
+        for generator in &self.generators {
+            let block = generator.generate(&clauses, self, query.blocking_k);
+            let before = candidates.len();
+            candidates |= &block.docs;
+            query_debug!(
+                "[SEARCH]     Generator blocked {} docs ({} new, {} tokens)",
+                block.docs.len(),
+                candidates.len() - before,
+                block.tokens.len()
+            );
+            round1_tokens.extend(block.tokens);
+        }
+
+        // A min_should_match that rejects every candidate is an answer, not a miss
+        let distinctive_matched = !candidates.is_empty();
+
+        // Expanded terms widen the candidates, but never count as matches
+        if min_should_match.is_none() && !expanded.is_empty() {
+            let before = candidates.len();
+            for (field, term) in &expanded {
+                candidates |= self.index.term_bitmap(*field, term);
             }
+            query_info!(
+                "[SEARCH]   Query expansion: {} terms added {} candidates",
+                expanded.len(),
+                candidates.len() - before
+            );
+        }
+        if let Some(min_should_match) = min_should_match {
+            let required = min_should_match.required(distinctive_total);
+            candidates = InvertedIndex::<F, S>::at_least(&distinctive_bitmaps, required);
+            query_info!(
+                "[SEARCH]   min_should_match: {} of {} distinctive tokens, {} candidates left",
+                required,
+                distinctive_total,
+                candidates.len()
+            );
         }
 
+        // Fixed token order keeps float accumulation (and the fallback) reproducible
+        let mut all_query_tokens: Vec<(F, String, f32)> = token_weights
+            .into_iter()
+            .map(|((field, token), weight)| (field, token, weight))
+            .collect();
+        all_query_tokens.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
         // FALLBACK: If no distinctive tokens found candidates, use rarest tokens
-        if candidates.is_empty() && !all_query_tokens.is_empty() {
-            info!("[SEARCH] FALLBACK: No distinctive tokens found candidates, using rarest tokens");
+        let fallback = FallbackPolicy {
+            enabled: profile.fallback,
+            ..self.fallback
+        };
+        let needs_fallback = !distinctive_matched && !all_query_tokens.is_empty();
+        if needs_fallback && !fallback.enabled {
+            query_info!("[SEARCH] FALLBACK disabled: no distinctive tokens found candidates");
+        } else if needs_fallback {
+            query_info!("[SEARCH] FALLBACK: No distinctive tokens found candidates, using rarest tokens");
 
             // Use pre-computed document frequency from metadata
             let mut token_rareness: Vec<(&F, &String, usize)> = Vec::new();
 
-            for (field, token) in &all_query_tokens {
-                if let Some(&df) = self.metadata.term_df.get(&(*field, token.clone())) {
+            for (field, token, _) in &all_query_tokens {
+                if !fallback.ngrams && is_ngram_key(token) {
+                    continue;
+                }
+                let df = self.metadata.get_df(field, token);
+                if df > 0 {
+                    if fallback.max_df.is_some_and(|max_df| df > max_df) {
+                        continue;
+                    }
                     token_rareness.push((field, token, df));
                 }
             }
@@ -127,16 +2973,23 @@ where
             // Sort by rarity (smallest document frequency = most selective)
             token_rareness.sort_by_key(|(_, _, df)| *df);
 
-            // Use up to 5 rarest tokens to build candidate set
-            let k_rarest = 5.min(token_rareness.len());
-            info!("[SEARCH] Using {} rarest tokens for fallback", k_rarest);
+            // Use the policy's rarest tokens to build candidate set
+            let k_rarest = fallback.rare_tokens.min(token_rareness.len());
+            query_info!("[SEARCH] Using {} rarest tokens for fallback", k_rarest);
 
             for (field, token, df) in token_rareness.iter().take(k_rarest) {
-                if let Some(postings) = self.index.get_postings(**field, token) {
+                let postings = self.index.get_postings(**field, token);
+                if postings.is_some() {
+                    postings_hits += 1;
+                } else {
+                    postings_misses += 1;
+                }
+                if let Some(postings) = postings {
+                    round1_tokens.push((**field, token.to_string()));
                     let before = candidates.len();
                     candidates |= postings.bitmap();
                     let after = candidates.len();
-                    info!(
+                    query_info!(
                         "[SEARCH]   Fallback token '{}' (df={}) added {} candidates (total: {})",
                         token,
                         df,
@@ -147,45 +3000,21 @@ where
             }
         }
 
+        self.narrow(query, &mut candidates);
+
+        round1_timer.span().record("candidates", candidates.len());
         drop(round1_timer);
-        info!(
+        self.metrics.record_postings_lookups(postings_hits, postings_misses);
+        query_info!(
             "[SEARCH] ROUND 1 Complete: {} candidates found",
             candidates.len()
         );
 
-        if candidates.is_empty() {
-            info!("[SEARCH] No candidates found, returning empty results");
-            return vec![];
+        CandidateSet {
+            candidates,
+            query_tokens: all_query_tokens,
+            round1_tokens,
+            fallback: needs_fallback && fallback.enabled,
         }
-
-        // ROUND 2: Score candidates using ALL tokens (including weak n-grams)
-        info!(
-            "[SEARCH] ROUND 2: Scoring {} candidates with {} query tokens",
-            candidates.len(),
-            all_query_tokens.len()
-        );
-
-        let round2_timer = Timer::new("Round2::ScoreCandidates");
-        let scored_results =
-            self.scorer
-                .score(candidates, &all_query_tokens, &self.index, &self.metadata);
-        drop(round2_timer);
-
-        info!("[SEARCH] Scored {} documents", scored_results.len());
-
-        // Take top-k results
-        let final_results: Vec<SearchHit> = scored_results
-            .into_iter()
-            .take(query.top_k)
-            .map(|(doc_id, score)| {
-                debug!("[SEARCH] Result: doc_id={}, score={}", doc_id, score);
-                SearchHit { doc_id, score }
-            })
-            .collect();
-
-        drop(search_timer);
-        info!("[SEARCH] Returning {} results", final_results.len());
-
-        final_results
     }
 }
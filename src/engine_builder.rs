@@ -0,0 +1,190 @@
+//! Opening an LMDB engine from one description of it: where the index
+//! lives, which fields it weighs and how, and how it tokenizes. The config
+//! saved with the index is applied first and the builder's settings on top,
+//! so a builder only needs to name what it changes.
+
+use crate::RecordField;
+use crate::config::ConfigReload;
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::manager::LmdbEngine;
+use crate::storage::{LmdbOptions, LmdbStorage};
+use crate::tokenizer::{FieldTokenRules, TokenizerConfig};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tracing::info;
+
+#[derive(Debug, Clone)]
+pub struct EngineBuilder {
+    path: PathBuf,
+    options: LmdbOptions,
+    schema: Option<Vec<RecordField>>,
+    k1: Option<f32>,
+    field_weights: HashMap<RecordField, f32>,
+    field_b: HashMap<RecordField, f32>,
+    tokenizer: Option<TokenizerConfig>,
+    field_rules: HashMap<RecordField, FieldTokenRules>,
+}
+
+impl EngineBuilder {
+    /// Opens (or creates) the index at `path`.
+    pub fn new(path: &Path) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            options: LmdbOptions::new(),
+            schema: None,
+            k1: None,
+            field_weights: HashMap::new(),
+            field_b: HashMap::new(),
+            tokenizer: None,
+            field_rules: HashMap::new(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn options(mut self, options: LmdbOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Fields the engine weighs. Weights and b-values are cut down to
+    /// these, each getting a weight of 1.0 unless it has one, and settings
+    /// for other fields are rejected.
+    pub fn schema(mut self, fields: impl IntoIterator<Item = RecordField>) -> Self {
+        let mut fields: Vec<RecordField> = fields.into_iter().collect();
+        fields.sort();
+        fields.dedup();
+        self.schema = Some(fields);
+        self
+    }
+
+    pub fn k1(mut self, k1: f32) -> Self {
+        self.k1 = Some(k1);
+        self
+    }
+
+    /// Replaces the weights of the given fields; the others keep theirs.
+    pub fn field_weights(mut self, weights: impl IntoIterator<Item = (RecordField, f32)>) -> Self {
+        self.field_weights.extend(weights);
+        self
+    }
+
+    /// Replaces the b-values of the given fields; the others keep theirs.
+    pub fn field_b(mut self, b_values: impl IntoIterator<Item = (RecordField, f32)>) -> Self {
+        self.field_b.extend(b_values);
+        self
+    }
+
+    /// Tokenization of the whole engine. Changing it on an existing index
+    /// requires reindexing.
+    pub fn tokenizer(mut self, tokenizer: TokenizerConfig) -> Self {
+        self.tokenizer = Some(tokenizer);
+        self
+    }
+
+    pub fn field_rules(mut self, field: RecordField, rules: FieldTokenRules) -> Self {
+        self.field_rules.insert(field, rules);
+        self
+    }
+
+    /// Opens the index, applies its saved config and then this builder's
+    /// settings, and loads the metadata committed with the index when it
+    /// matches the postings. Invalid settings fail before anything is opened,
+    /// and a tokenizer other than the one the index was built with fails
+    /// before anything is changed.
+    pub fn build(self) -> Result<LmdbEngine, LfasError> {
+        self.validate()?;
+
+        let storage = LmdbStorage::open_with_options(&self.path, self.options.clone())?;
+        // Opening applies the saved config already
+        let mut engine = SearchEngine::with_storage(storage);
+        let saved = engine
+            .index
+            .storage
+            .read_config()
+            .map_err(LfasError::storage)?
+            .is_some();
+        let retokenized = self
+            .tokenizer
+            .as_ref()
+            .is_some_and(|tokenizer| *tokenizer != engine.tokenizer);
+        if saved && retokenized {
+            return Err(LfasError::Schema(format!(
+                "{:?} was built with the tokenizer {:?}, not {:?}; reindex it to change it",
+                self.path, engine.tokenizer, self.tokenizer
+            )));
+        }
+        self.configure(&mut engine)?;
+        if engine.load_committed_metadata()? {
+            info!("[CONFIG] Restored metadata committed with {:?}", self.path);
+        }
+        Ok(engine)
+    }
+
+    /// Applies this builder's scoring and tokenizer settings to an open
+    /// engine, all or none of them. The index and its options are left as
+    /// they are.
+    pub fn configure(&self, engine: &mut LmdbEngine) -> Result<(), LfasError> {
+        self.validate()?;
+
+        engine.reload_config(self.scoring())?;
+        if let Some(tokenizer) = &self.tokenizer {
+            engine.tokenizer = tokenizer.clone();
+        }
+        engine.field_rules.extend(self.field_rules.clone());
+
+        if let Some(schema) = &self.schema {
+            let scorer = &mut engine.scorer;
+            scorer
+                .field_weights
+                .retain(|field, _| schema.contains(field));
+            scorer.field_b.retain(|field, _| schema.contains(field));
+            for field in schema {
+                scorer.field_weights.entry(*field).or_insert(1.0);
+            }
+        }
+        info!(
+            "[CONFIG] Engine at {:?} configured ({} weighted fields)",
+            self.path,
+            engine.scorer.field_weights.len()
+        );
+        Ok(())
+    }
+
+    fn scoring(&self) -> ConfigReload<RecordField> {
+        ConfigReload {
+            k1: self.k1,
+            field_weights: self.field_weights.clone(),
+            field_b: self.field_b.clone(),
+            fallback: None,
+            synonyms: None,
+        }
+    }
+
+    fn validate(&self) -> Result<(), LfasError> {
+        self.scoring().validate()?;
+
+        let Some(schema) = &self.schema else {
+            return Ok(());
+        };
+        if schema.is_empty() {
+            return Err(LfasError::Schema("the schema has no fields".into()));
+        }
+        let outside = self
+            .field_weights
+            .keys()
+            .chain(self.field_b.keys())
+            .chain(self.field_rules.keys())
+            .find(|field| !schema.contains(field));
+        match outside {
+            Some(field) => Err(LfasError::Schema(format!(
+                "{:?} has settings but is not in the schema",
+                field
+            ))),
+            None => Ok(()),
+        }
+    }
+}
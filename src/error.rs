@@ -0,0 +1,93 @@
+use crate::storage::LmdbError;
+use std::sync::PoisonError;
+
+/// Crate-wide error type returned by the engine APIs.
+#[derive(Debug)]
+pub enum LfasError {
+    /// Failure reading or writing the postings storage.
+    Storage(String),
+    /// Failure (de)serializing postings, metadata or keys.
+    Serialization(String),
+    /// Unknown field or otherwise invalid record/query shape.
+    Schema(String),
+    /// Query parameters outside the configured limits.
+    InvalidQuery(String),
+    /// Search rejected by load shedding: every slot busy and the queue full.
+    Overloaded(String),
+    /// Document id past the largest a [`DocId`](crate::DocId) can hold.
+    DocIdOverflow(u64),
+    /// A lock was poisoned by a panicking thread.
+    LockPoisoned,
+    /// The engine was used before being initialized.
+    NotInitialized,
+}
+
+pub type LfasResult<T> = Result<T, LfasError>;
+
+impl LfasError {
+    /// Wraps any storage backend error (used by generic `PostingsStorage` code).
+    pub fn storage(e: impl std::fmt::Display) -> Self {
+        LfasError::Storage(e.to_string())
+    }
+}
+
+impl std::fmt::Display for LfasError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LfasError::Storage(e) => write!(f, "Storage error: {}", e),
+            LfasError::Serialization(e) => write!(f, "Serialization error: {}", e),
+            LfasError::Schema(e) => write!(f, "Schema error: {}", e),
+            LfasError::InvalidQuery(e) => write!(f, "Invalid query: {}", e),
+            LfasError::Overloaded(e) => write!(f, "Overloaded: {}", e),
+            LfasError::DocIdOverflow(id) => {
+                write!(f, "Document id {} exceeds the maximum of {}", id, u32::MAX)
+            }
+            LfasError::LockPoisoned => write!(f, "Lock poisoned"),
+            LfasError::NotInitialized => write!(f, "Engine not initialized"),
+        }
+    }
+}
+
+impl std::error::Error for LfasError {}
+
+impl From<LmdbError> for LfasError {
+    fn from(e: LmdbError) -> Self {
+        match e {
+            LmdbError::SerializationError(e) => LfasError::Serialization(e.to_string()),
+            LmdbError::LockPoisoned => LfasError::LockPoisoned,
+            LmdbError::VersionMismatch { .. } => LfasError::Schema(e.to_string()),
+            other => LfasError::Storage(other.to_string()),
+        }
+    }
+}
+
+impl From<heed::Error> for LfasError {
+    fn from(e: heed::Error) -> Self {
+        LfasError::Storage(e.to_string())
+    }
+}
+
+impl From<bincode::Error> for LfasError {
+    fn from(e: bincode::Error) -> Self {
+        LfasError::Serialization(e.to_string())
+    }
+}
+
+impl From<std::io::Error> for LfasError {
+    fn from(e: std::io::Error) -> Self {
+        LfasError::Storage(e.to_string())
+    }
+}
+
+impl<T> From<PoisonError<T>> for LfasError {
+    fn from(_: PoisonError<T>) -> Self {
+        LfasError::LockPoisoned
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl From<tokio::task::JoinError> for LfasError {
+    fn from(e: tokio::task::JoinError) -> Self {
+        LfasError::Storage(format!("blocking task failed: {}", e))
+    }
+}
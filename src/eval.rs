@@ -0,0 +1,184 @@
+//! Offline relevance evaluation on labeled match/non-match pairs.
+//!
+//! The pairs file is a CSV with a `query_id`, a `candidate_id` (external id of
+//! an indexed record), a `label` (1 = match, 0 = non-match) and any of the
+//! snake_case field columns (`rua`, `municipio`, ...) describing the query.
+//! Rows sharing a `query_id` describe the same query.
+
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::storage::PostingsStorage;
+use crate::{DocId, Record, RecordField};
+use serde::Serialize;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use tracing::info;
+
+pub const DEFAULT_EVAL_K: usize = 10;
+
+#[derive(Debug, Clone, Default)]
+pub struct EvalQuery {
+    pub query_id: String,
+    pub query: Record,
+    /// External ids labeled as matches
+    pub relevant: HashSet<String>,
+    /// External ids labeled as non-matches
+    pub non_relevant: HashSet<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EvalReport {
+    pub k: usize,
+    pub queries: usize,
+    pub precision_at_k: f64,
+    pub recall_at_k: f64,
+    pub mrr: f64,
+    /// Fraction of labeled non-matches that still made it into the top k
+    pub non_match_rate_at_k: f64,
+}
+
+/// Builds a record from the field columns of a CSV row; unknown columns are ignored.
+pub fn record_from_row(headers: &csv::StringRecord, row: &csv::StringRecord) -> Record {
+    let mut record = Record::default();
+    for (header, value) in headers.iter().zip(row.iter()) {
+        if header == "id" {
+            record.id = value.to_string();
+        } else if let Some(field) = RecordField::from_name(header) {
+            *record.field_mut(field) = value.to_string();
+        }
+    }
+    record
+}
+
+fn csv_error(e: csv::Error) -> LfasError {
+    LfasError::Schema(e.to_string())
+}
+
+/// Reads a corpus CSV (an `id` column plus field columns) into records.
+pub fn load_records(path: &Path) -> Result<Vec<Record>, LfasError> {
+    let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+    let headers = reader.headers().map_err(csv_error)?.clone();
+
+    reader
+        .records()
+        .map(|row| Ok(record_from_row(&headers, &row.map_err(csv_error)?)))
+        .collect()
+}
+
+/// Groups the labeled pairs file into queries, in first-seen order.
+pub fn load_pairs(path: &Path) -> Result<Vec<EvalQuery>, LfasError> {
+    let mut reader = csv::Reader::from_path(path).map_err(csv_error)?;
+    let headers = reader.headers().map_err(csv_error)?.clone();
+
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h == name)
+            .ok_or_else(|| LfasError::Schema(format!("pairs file has no '{}' column", name)))
+    };
+    let query_col = column("query_id")?;
+    let candidate_col = column("candidate_id")?;
+    let label_col = column("label")?;
+
+    let mut queries: Vec<EvalQuery> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+
+    for row in reader.records() {
+        let row = row.map_err(csv_error)?;
+        let query_id = row[query_col].to_string();
+        let candidate = row[candidate_col].to_string();
+        let is_match = match row[label_col].trim() {
+            "1" | "true" | "match" => true,
+            "0" | "false" | "non_match" => false,
+            other => {
+                return Err(LfasError::Schema(format!("invalid label '{}'", other)));
+            }
+        };
+
+        let position = *positions.entry(query_id.clone()).or_insert_with(|| {
+            let mut query = record_from_row(&headers, &row);
+            query.id.clear();
+            queries.push(EvalQuery {
+                query_id,
+                query,
+                ..Default::default()
+            });
+            queries.len() - 1
+        });
+
+        let entry = &mut queries[position];
+        if is_match {
+            entry.relevant.insert(candidate);
+        } else {
+            entry.non_relevant.insert(candidate);
+        }
+    }
+
+    Ok(queries)
+}
+
+/// Runs every query through [`SearchEngine::search_record`] and averages the
+/// metrics over queries. Queries without any labeled match are skipped for
+/// recall and MRR but still count toward precision.
+pub fn evaluate<S>(
+    engine: &SearchEngine<RecordField, S>,
+    queries: &[EvalQuery],
+    k: usize,
+) -> Result<EvalReport, LfasError>
+where
+    S: PostingsStorage<RecordField>,
+{
+    let external_ids: HashMap<DocId, &str> = engine
+        .id_map
+        .iter()
+        .map(|(external_id, doc_id)| (*doc_id, external_id.as_str()))
+        .collect();
+
+    let mut precision = 0.0;
+    let mut recall = 0.0;
+    let mut reciprocal_rank = 0.0;
+    let mut with_relevant = 0usize;
+    let mut non_matches = 0usize;
+    let mut non_matches_retrieved = 0usize;
+
+    for query in queries {
+        let retrieved: Vec<&str> = engine
+            .search_record(&query.query, k)?
+            .iter()
+            .filter_map(|hit| external_ids.get(&hit.doc_id).copied())
+            .collect();
+
+        let hits = retrieved
+            .iter()
+            .filter(|id| query.relevant.contains(**id))
+            .count();
+        precision += hits as f64 / k as f64;
+
+        if !query.relevant.is_empty() {
+            with_relevant += 1;
+            recall += hits as f64 / query.relevant.len() as f64;
+            if let Some(rank) = retrieved.iter().position(|id| query.relevant.contains(*id)) {
+                reciprocal_rank += 1.0 / (rank + 1) as f64;
+            }
+        }
+
+        non_matches += query.non_relevant.len();
+        non_matches_retrieved += retrieved
+            .iter()
+            .filter(|id| query.non_relevant.contains(**id))
+            .count();
+    }
+
+    let mean = |total: f64, count: usize| if count == 0 { 0.0 } else { total / count as f64 };
+    let report = EvalReport {
+        k,
+        queries: queries.len(),
+        precision_at_k: mean(precision, queries.len()),
+        recall_at_k: mean(recall, with_relevant),
+        mrr: mean(reciprocal_rank, with_relevant),
+        non_match_rate_at_k: mean(non_matches_retrieved as f64, non_matches),
+    };
+
+    info!("[EVAL] {:?}", report);
+    Ok(report)
+}
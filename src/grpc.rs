@@ -0,0 +1,289 @@
+//! gRPC service over a shared [`SearchEngine`], see `proto/lfas.proto`.
+
+pub mod proto {
+    tonic::include_proto!("lfas");
+}
+
+use crate::engine::{DEFAULT_BLOCKING_K, DEFAULT_TOP_K, SearchEngine, TokenizedDoc};
+use crate::error::LfasError;
+use crate::storage::PostingsStorage;
+use crate::{DocId, Record, RecordField, SearchHit, StructuredQuery};
+use proto::address_search_server::{AddressSearch, AddressSearchServer};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use tokio_stream::{Stream, StreamExt};
+use tonic::{Request, Response, Status, Streaming};
+use tracing::info;
+
+/// Records tokenized before taking the engine write lock.
+pub const INDEX_BATCH_SIZE: usize = 1_000;
+
+pub use crate::async_engine::SharedEngine;
+
+impl From<LfasError> for Status {
+    fn from(e: LfasError) -> Self {
+        match e {
+            LfasError::InvalidQuery(_) | LfasError::Schema(_) | LfasError::DocIdOverflow(_) => {
+                Status::invalid_argument(e.to_string())
+            }
+            LfasError::NotInitialized => Status::failed_precondition(e.to_string()),
+            LfasError::Overloaded(_) => Status::resource_exhausted(e.to_string()),
+            _ => Status::internal(e.to_string()),
+        }
+    }
+}
+
+fn parse_field(name: &str) -> Result<RecordField, Status> {
+    RecordField::from_name(name)
+        .ok_or_else(|| Status::invalid_argument(format!("Unknown field: {}", name)))
+}
+
+impl TryFrom<proto::Record> for Record {
+    type Error = Status;
+
+    fn try_from(record: proto::Record) -> Result<Self, Status> {
+        let aliases = record
+            .aliases
+            .into_iter()
+            .map(|alias| Ok((parse_field(&alias.field)?, alias.value)))
+            .collect::<Result<_, Status>>()?;
+
+        Ok(Record {
+            id: record.id,
+            estado: record.estado,
+            municipio: record.municipio,
+            bairro: record.bairro,
+            cep: record.cep,
+            tipo_logradouro: record.tipo_logradouro,
+            rua: record.rua,
+            numero: record.numero,
+            complemento: record.complemento,
+            nome: record.nome,
+            aliases,
+        })
+    }
+}
+
+impl TryFrom<proto::SearchRequest> for StructuredQuery<RecordField> {
+    type Error = Status;
+
+    fn try_from(request: proto::SearchRequest) -> Result<Self, Status> {
+        let fields = request
+            .fields
+            .into_iter()
+            .map(|clause| Ok((parse_field(&clause.field)?, clause.text)))
+            .collect::<Result<_, Status>>()?;
+
+        let or_default = |value: u32, default: usize| match value {
+            0 => default,
+            value => value as usize,
+        };
+
+        Ok(StructuredQuery {
+            fields,
+            top_k: or_default(request.top_k, DEFAULT_TOP_K),
+            blocking_k: or_default(request.blocking_k, DEFAULT_BLOCKING_K),
+            external_id: request.external_id.filter(|id| !id.is_empty()),
+            ..Default::default()
+        })
+    }
+}
+
+impl From<SearchHit> for proto::Hit {
+    fn from(hit: SearchHit) -> Self {
+        proto::Hit {
+            doc_id: u64::from(hit.doc_id.get()),
+            score: hit.score,
+            exact: hit.exact,
+        }
+    }
+}
+
+pub struct SearchService<S>
+where
+    S: PostingsStorage<RecordField>,
+{
+    engine: SharedEngine<S>,
+}
+
+impl<S> SearchService<S>
+where
+    S: PostingsStorage<RecordField> + Send + Sync + 'static,
+{
+    pub fn new(engine: SharedEngine<S>) -> Self {
+        Self { engine }
+    }
+
+    pub fn into_server(self) -> AddressSearchServer<Self> {
+        AddressSearchServer::new(self)
+    }
+
+    /// Indexes a stream of records in batches of [`INDEX_BATCH_SIZE`], then flushes.
+    pub async fn index_stream<St>(&self, mut stream: St) -> Result<proto::IndexSummary, Status>
+    where
+        St: Stream<Item = Result<proto::IndexRequest, Status>> + Unpin,
+    {
+        let mut indexed = 0u64;
+        let mut batch = Vec::with_capacity(INDEX_BATCH_SIZE);
+        let analyzer = self.read()?.analyzer();
+
+        while let Some(request) = stream.next().await {
+            let request = request?;
+            let record = Record::try_from(request.record.unwrap_or_default())?;
+            batch.push(TokenizedDoc::from_record_with(
+                DocId::try_from(request.doc_id)?,
+                &record,
+                &analyzer,
+            ));
+
+            if batch.len() >= INDEX_BATCH_SIZE {
+                indexed += batch.len() as u64;
+                self.write()?.index_tokenized(std::mem::take(&mut batch))?;
+            }
+        }
+
+        indexed += batch.len() as u64;
+        let mut engine = self.write()?;
+        engine.index_tokenized(batch)?;
+        engine.flush()?;
+
+        info!("[GRPC] Indexed {} records from stream", indexed);
+        Ok(proto::IndexSummary {
+            indexed,
+            total_docs: engine.metadata.total_docs as u64,
+        })
+    }
+
+    fn read(
+        &self,
+    ) -> Result<std::sync::RwLockReadGuard<'_, SearchEngine<RecordField, S>>, LfasError> {
+        Ok(self.engine.read()?)
+    }
+
+    fn write(
+        &self,
+    ) -> Result<std::sync::RwLockWriteGuard<'_, SearchEngine<RecordField, S>>, LfasError> {
+        Ok(self.engine.write()?)
+    }
+}
+
+#[tonic::async_trait]
+impl<S> AddressSearch for SearchService<S>
+where
+    S: PostingsStorage<RecordField> + Send + Sync + 'static,
+{
+    async fn index(
+        &self,
+        request: Request<Streaming<proto::IndexRequest>>,
+    ) -> Result<Response<proto::IndexSummary>, Status> {
+        let summary = self.index_stream(request.into_inner()).await?;
+        Ok(Response::new(summary))
+    }
+
+    async fn search(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::SearchResponse>, Status> {
+        let query = StructuredQuery::try_from(request.into_inner())?;
+        let blocking_k = query.blocking_k;
+        let hits = self.read()?.execute(query, blocking_k)?;
+
+        Ok(Response::new(proto::SearchResponse {
+            hits: hits.into_iter().map(proto::Hit::from).collect(),
+        }))
+    }
+
+    async fn explain(
+        &self,
+        request: Request<proto::SearchRequest>,
+    ) -> Result<Response<proto::ExplainResponse>, Status> {
+        let query = StructuredQuery::try_from(request.into_inner())?;
+        let engine = self.read()?;
+
+        let results = engine.execute_with_rerank(query, |candidates| {
+            Ok::<_, LfasError>(
+                candidates
+                    .into_iter()
+                    .map(|candidate| proto::Explanation {
+                        matched: candidate
+                            .matched
+                            .into_iter()
+                            .map(|(field, tokens)| proto::FieldMatch {
+                                field: field.name().to_string(),
+                                tokens,
+                            })
+                            .collect(),
+                        doc: candidate
+                            .doc
+                            .map(|doc| {
+                                doc.iter()
+                                    .map(|(field, value)| (field.name().to_string(), value.clone()))
+                                    .collect()
+                            })
+                            .unwrap_or_default(),
+                        hit: Some(candidate.hit.into()),
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        });
+
+        Ok(Response::new(proto::ExplainResponse { results: results? }))
+    }
+
+    async fn stats(
+        &self,
+        _request: Request<proto::StatsRequest>,
+    ) -> Result<Response<proto::StatsResponse>, Status> {
+        let engine = self.read()?;
+        let metadata = &engine.metadata;
+
+        let avg_field_lengths: HashMap<String, f64> = metadata
+            .total_field_lengths
+            .iter()
+            .filter(|_| metadata.total_docs > 0)
+            .map(|(field, total)| {
+                (field.name().to_string(), *total as f64 / metadata.total_docs as f64)
+            })
+            .collect();
+        let field_lengths = metadata
+            .length_stats()
+            .into_iter()
+            .map(|(field, stats)| {
+                let stats = proto::LengthStats {
+                    docs: stats.docs as u64,
+                    mean: stats.mean,
+                    p50: stats.p50 as u64,
+                    p90: stats.p90 as u64,
+                    p99: stats.p99 as u64,
+                    max: stats.max as u64,
+                };
+                (field.name().to_string(), stats)
+            })
+            .collect();
+
+        Ok(Response::new(proto::StatsResponse {
+            total_docs: metadata.total_docs as u64,
+            total_terms: metadata.term_df.len() as u64,
+            avg_field_lengths,
+            field_lengths,
+            generation: engine
+                .index
+                .storage
+                .generation()
+                .map_err(LfasError::storage)?,
+        }))
+    }
+}
+
+/// Serves the engine on `addr` until the server fails.
+pub async fn serve<S>(engine: SharedEngine<S>, addr: SocketAddr) -> Result<(), LfasError>
+where
+    S: PostingsStorage<RecordField> + Send + Sync + 'static,
+{
+    info!("[GRPC] Listening on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(SearchService::new(engine).into_server())
+        .serve(addr)
+        .await
+        .map_err(LfasError::storage)
+}
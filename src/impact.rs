@@ -0,0 +1,238 @@
+//! Impact-ordered postings for read-only deployments. Each posting carries
+//! its BM25F contribution, computed once with frozen scorer parameters and
+//! quantized to a byte, so Round 2 adds up bytes instead of evaluating BM25F
+//! per document. Stale impacts (parameters or corpus changed since the
+//! build) are never used; rebuild them with
+//! [`SearchEngine::rebuild_impacts`](crate::engine::SearchEngine::rebuild_impacts).
+
+use crate::DocId;
+use crate::error::LfasError;
+use crate::metadata::FieldMetadata;
+use crate::scorer::{BM25FScorer, FieldGroup, TfOptions};
+use crate::storage::PostingsStorage;
+use roaring::RoaringBitmap;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Quantization steps of an impact; the largest contribution in the index
+/// maps to the top step.
+pub const IMPACT_LEVELS: f32 = 255.0;
+
+/// One term's documents, highest impact first (ties by ascending doc id).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImpactPostings {
+    docs: Vec<u32>,
+    impacts: Vec<u8>,
+}
+
+impl ImpactPostings {
+    /// `(doc_id, impact)` pairs, highest impact first.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, u8)> + '_ {
+        self.docs.iter().copied().zip(self.impacts.iter().copied())
+    }
+
+    pub fn max_impact(&self) -> u8 {
+        self.impacts.first().copied().unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+}
+
+/// Scorer settings and corpus statistics the impacts were computed from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "F: Serialize + Hash + Eq",
+    deserialize = "F: Deserialize<'de> + Hash + Eq"
+))]
+pub struct ImpactParams<F> {
+    pub k1: f32,
+    pub field_weights: HashMap<F, f32>,
+    pub field_b: HashMap<F, f32>,
+    pub field_tf: HashMap<F, TfOptions>,
+    pub field_groups: Vec<FieldGroup<F>>,
+    pub length_clip: Option<f32>,
+    pub total_docs: usize,
+    pub total_field_lengths: HashMap<F, usize>,
+}
+
+impl<F> ImpactParams<F>
+where
+    F: Hash + Eq + Clone + Copy + Ord,
+{
+    pub fn of(scorer: &BM25FScorer<F>, metadata: &FieldMetadata<F>) -> Self {
+        Self {
+            k1: scorer.k1,
+            field_weights: scorer.field_weights.clone(),
+            field_b: scorer.field_b.clone(),
+            field_tf: scorer.field_tf.clone(),
+            field_groups: scorer.field_groups.clone(),
+            length_clip: scorer.length_clip,
+            total_docs: metadata.total_docs,
+            total_field_lengths: metadata.total_field_lengths.clone(),
+        }
+    }
+
+    /// Whether impacts built with these params still equal what `scorer`
+    /// would compute. Recency decay and the coverage boost depend on the
+    /// query, so a scorer using either never matches.
+    pub fn matches(&self, scorer: &BM25FScorer<F>, metadata: &FieldMetadata<F>) -> bool {
+        scorer.recency.is_none()
+            && scorer.coverage_boost == 0.0
+            && self.k1 == scorer.k1
+            && self.field_weights == scorer.field_weights
+            && self.field_b == scorer.field_b
+            && self.field_tf == scorer.field_tf
+            && self.field_groups == scorer.field_groups
+            && self.length_clip == scorer.length_clip
+            && self.total_docs == metadata.total_docs
+            && self.total_field_lengths == metadata.total_field_lengths
+    }
+}
+
+/// Quantized BM25F contributions of every posting in an index.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "F: Serialize + Hash + Eq",
+    deserialize = "F: Deserialize<'de> + Hash + Eq"
+))]
+pub struct ImpactIndex<F> {
+    pub params: ImpactParams<F>,
+    /// Score of one quantization step.
+    pub scale: f32,
+    postings: HashMap<(F, String), ImpactPostings>,
+}
+
+impl<F> ImpactIndex<F>
+where
+    F: Hash + Eq + Clone + Copy + Ord,
+{
+    /// Scores every posting in `storage` with `scorer` and the corpus stats
+    /// in `metadata`.
+    pub fn build<S>(
+        scorer: &BM25FScorer<F>,
+        storage: &S,
+        metadata: &FieldMetadata<F>,
+    ) -> Result<Self, LfasError>
+    where
+        S: PostingsStorage<F>,
+    {
+        let mut contributions: Vec<((F, String), Vec<(u32, f32)>)> = Vec::new();
+        let mut max_contribution = 0.0f32;
+        for entry in storage.iter() {
+            let ((field, term), postings) = entry.map_err(LfasError::storage)?;
+            let scored: Vec<(u32, f32)> = postings
+                .iter()
+                .map(|(doc_id, tf)| {
+                    let dl = metadata.doc_length(doc_id, &field) as f32;
+                    (doc_id.get(), scorer.contribution(field, &term, tf, dl, metadata))
+                })
+                .collect();
+            max_contribution = scored
+                .iter()
+                .fold(max_contribution, |max, (_, contribution)| {
+                    max.max(*contribution)
+                });
+            contributions.push(((field, term), scored));
+        }
+
+        let scale = if max_contribution > 0.0 {
+            max_contribution / IMPACT_LEVELS
+        } else {
+            1.0
+        };
+        let postings = contributions
+            .into_iter()
+            .map(|(key, scored)| {
+                let mut quantized: Vec<(u32, u8)> = scored
+                    .into_iter()
+                    .map(|(doc_id, contribution)| {
+                        let level = (contribution / scale).round().clamp(0.0, IMPACT_LEVELS);
+                        (doc_id, level as u8)
+                    })
+                    .collect();
+                quantized.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+                let (docs, impacts) = quantized.into_iter().unzip();
+                (key, ImpactPostings { docs, impacts })
+            })
+            .collect();
+
+        Ok(Self {
+            params: ImpactParams::of(scorer, metadata),
+            scale,
+            postings,
+        })
+    }
+
+    pub fn postings(&self, field: F, term: &str) -> Option<&ImpactPostings> {
+        self.postings.get(&(field, term.to_string()))
+    }
+
+    /// Postings across all terms.
+    pub fn len(&self) -> usize {
+        self.postings.values().map(ImpactPostings::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.postings.is_empty()
+    }
+
+    pub fn terms(&self) -> usize {
+        self.postings.len()
+    }
+
+    /// Sums the impacts of `query_tokens`, scaled by each token's weight, over
+    /// `candidates`: the impact counterpart of
+    /// [`BM25FScorer::score_weighted_until`], sorted the same way.
+    pub fn score(
+        &self,
+        candidates: &RoaringBitmap,
+        query_tokens: &[(F, String, f32)],
+        interrupted: &dyn Fn() -> bool,
+    ) -> (Vec<(DocId, f32)>, bool) {
+        let mut accumulators: HashMap<DocId, f32> = HashMap::new();
+        let mut stopped = false;
+        for (field, term, token_weight) in query_tokens {
+            if interrupted() {
+                stopped = true;
+                break;
+            }
+            let Some(postings) = self.postings(*field, term) else {
+                continue;
+            };
+            let step = token_weight * self.scale;
+            for (doc_id, impact) in postings.iter() {
+                if candidates.contains(doc_id) {
+                    *accumulators.entry(DocId::new(doc_id)).or_insert(0.0) += step * impact as f32;
+                }
+            }
+        }
+
+        let mut scores: Vec<_> = accumulators.into_iter().collect();
+        scores.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        (scores, stopped)
+    }
+}
+
+impl<F> ImpactIndex<F>
+where
+    F: Serialize + for<'de> Deserialize<'de> + Hash + Eq,
+{
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
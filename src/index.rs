@@ -1,4 +1,6 @@
 use crate::DocId;
+use crate::engine::TermBatch;
+use crate::error::LfasError;
 use crate::postings::Postings;
 use crate::storage::PostingsStorage;
 use roaring::RoaringBitmap;
@@ -12,6 +14,8 @@ where
     S: PostingsStorage<F>,
 {
     pub storage: S,
+    /// Postings pinned in memory by `SearchEngine::warm`, served before storage
+    cache: HashMap<(F, String), Postings>,
     _phantom: PhantomData<F>,
 }
 
@@ -23,56 +27,129 @@ where
     pub fn new(storage: S) -> Self {
         Self {
             storage,
+            cache: HashMap::new(),
             _phantom: PhantomData,
         }
     }
 
-    pub fn add_term(&mut self, id: DocId, field: F, term: String) {
+    /// Keeps `postings` in memory; writes to the term keep the copy current.
+    pub fn cache_postings(&mut self, field: F, term: String, postings: Postings) {
+        self.cache.insert((field, term), postings);
+    }
+
+    pub fn cached_terms(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Drops every pinned posting (e.g. after another process wrote the index).
+    pub fn clear_cache(&mut self) {
+        self.cache.clear();
+    }
+
+    fn refresh_cached(&mut self, field: F, term: &str, postings: &Postings) {
+        if let Some(cached) = self.cache.get_mut(&(field, term.to_string())) {
+            *cached = postings.clone();
+        }
+    }
+
+    pub fn add_term(&mut self, id: DocId, field: F, term: String) -> Result<(), LfasError> {
         let mut postings = self
             .storage
             .get(field, &term)
-            .unwrap_or_default()
+            .map_err(LfasError::storage)?
             .unwrap_or_else(Postings::new);
 
         postings.add_occurrence(id);
+        self.refresh_cached(field, &term, &postings);
 
-        self.storage.put(field, term, postings).unwrap();
+        self.storage
+            .put(field, term, postings)
+            .map_err(LfasError::storage)
     }
 
-    pub fn add_batch(&mut self, batch: Vec<(DocId, Vec<(F, String)>)>) {
+    /// Drops `id` from the postings of `term`; false when it wasn't there.
+    /// Emptied postings stay stored, with no documents.
+    pub fn remove_term(&mut self, id: DocId, field: F, term: &str) -> Result<bool, LfasError> {
+        let Some(mut postings) = self.storage.get(field, term).map_err(LfasError::storage)? else {
+            return Ok(false);
+        };
+        if postings.remove(id) == 0 {
+            return Ok(false);
+        }
+        self.refresh_cached(field, term, &postings);
+
+        self.storage
+            .put(field, term.to_string(), postings)
+            .map_err(LfasError::storage)?;
+        Ok(true)
+    }
+
+    pub fn add_batch(&mut self, batch: TermBatch<F>) -> Result<(), LfasError> {
         // We aggregate all the terms of the batch into memory first.
         // This avoids the constant Get-Modify-Put in LMDB.
         let mut temp_map: HashMap<(F, String), Postings> = HashMap::new();
 
         for (id, fields) in batch {
-            for (field, term) in fields {
+            for (field, term, tf) in fields {
                 temp_map.entry((field, term))
                     .or_insert_with(Postings::new)
-                    .add_occurrence(id);
+                    .add_occurrences(id, tf);
             }
         }
 
         for ((field, term), batch_postings) in temp_map {
             let mut existing_postings = self.storage
                 .get(field, &term)
-                .unwrap_or_default()
+                .map_err(LfasError::storage)?
                 .unwrap_or_else(Postings::new);
                 
             existing_postings.merge(batch_postings);
-            
-            self.storage.put(field, term, existing_postings).unwrap();
+            self.refresh_cached(field, &term, &existing_postings);
+
+            self.storage
+                .put(field, term, existing_postings)
+                .map_err(LfasError::storage)?;
         }
+        Ok(())
     }
 
     pub fn get_postings(&self, field: F, term: &str) -> Option<Postings> {
-        use log::debug;
+        use crate::verbosity::query_debug;
+        if let Some(cached) = self.cache.get(&(field, term.to_string())) {
+            return Some(cached.clone());
+        }
         let result = self.storage.get(field, term).ok().flatten();
         if let Some(ref postings) = result {
-            debug!("[INDEX] Found {} docs for term '{}'", postings.len(), term);
+            query_debug!("[INDEX] Found {} docs for term '{}'", postings.len(), term);
         }
         result
     }
 
+    /// Batch lookup: cached postings first, the rest in one storage batch.
+    pub fn get_postings_batch(
+        &self,
+        queries: &[(F, String)],
+    ) -> Result<Vec<Option<Postings>>, S::Error> {
+        if self.cache.is_empty() {
+            return self.storage.get_batch(queries);
+        }
+
+        let missing: Vec<(F, String)> = queries
+            .iter()
+            .filter(|key| !self.cache.contains_key(*key))
+            .cloned()
+            .collect();
+        let mut fetched = self.storage.get_batch(&missing)?.into_iter();
+
+        Ok(queries
+            .iter()
+            .map(|key| match self.cache.get(key) {
+                Some(cached) => Some(cached.clone()),
+                None => fetched.next().flatten(),
+            })
+            .collect())
+    }
+
     pub fn term_bitmap(&self, field: F, term: &str) -> RoaringBitmap {
         self.get_postings(field, term)
             .map(|p| p.bitmap().clone())
@@ -92,6 +169,27 @@ where
         result
     }
 
+    /// Docs present in at least `k` of the bitmaps. `levels[i]` holds the docs
+    /// seen in at least `i + 1` bitmaps so far, so this stays O(k * n) bitmap ops.
+    pub fn at_least(bitmaps: &[RoaringBitmap], k: usize) -> RoaringBitmap {
+        if k == 0 {
+            return Self::union(bitmaps);
+        }
+        if k > bitmaps.len() {
+            return RoaringBitmap::new();
+        }
+
+        let mut levels = vec![RoaringBitmap::new(); k];
+        for bm in bitmaps {
+            for i in (1..k).rev() {
+                let promoted = &levels[i - 1] & bm;
+                levels[i] |= promoted;
+            }
+            levels[0] |= bm;
+        }
+        levels.pop().unwrap_or_default()
+    }
+
     pub fn union(bitmaps: &[RoaringBitmap]) -> RoaringBitmap {
         let mut result = RoaringBitmap::new();
         for bm in bitmaps {
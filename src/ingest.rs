@@ -0,0 +1,174 @@
+//! Bulk ingestion from Arrow record batches and Parquet files.
+
+use crate::builder::ProgressReporter;
+use crate::engine::{SearchEngine, TokenizedDoc};
+use crate::error::LfasError;
+use crate::storage::PostingsStorage;
+use crate::{DocId, RecordField};
+use arrow::array::{Array, AsArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::DataType;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::path::Path;
+use tracing::info;
+
+pub const DEFAULT_READ_BATCH_SIZE: usize = 8192;
+
+/// Maps Arrow column names to record fields.
+#[derive(Debug, Clone)]
+pub struct ColumnMapping {
+    pub columns: HashMap<String, RecordField>,
+    /// Column holding the external record id, if any.
+    pub id_column: Option<String>,
+}
+
+impl Default for ColumnMapping {
+    /// Columns named after the snake_case field names (`rua`, `tipo_logradouro`, ...) plus `id`.
+    fn default() -> Self {
+        let columns = RecordField::ALL
+            .into_iter()
+            .map(|field| (field.name().to_string(), field))
+            .collect();
+
+        Self {
+            columns,
+            id_column: Some("id".to_string()),
+        }
+    }
+}
+
+fn string_column(batch: &RecordBatch, name: &str) -> Result<Option<Vec<Option<String>>>, LfasError> {
+    let Some(column) = batch.column_by_name(name) else {
+        return Ok(None);
+    };
+    let column = cast(column, &DataType::Utf8).map_err(|e| LfasError::Schema(e.to_string()))?;
+    let values = column.as_string::<i32>();
+
+    Ok(Some(
+        (0..values.len())
+            .map(|i| (!values.is_null(i)).then(|| values.value(i).to_string()))
+            .collect(),
+    ))
+}
+
+/// Tokenizes one record batch in parallel and feeds it to the batch indexer.
+/// Rows get consecutive doc ids starting at `first_doc_id`; fails with
+/// [`LfasError::DocIdOverflow`] if the last one is past [`DocId::MAX`].
+/// Returns the row count.
+pub fn index_record_batch<S>(
+    engine: &mut SearchEngine<RecordField, S>,
+    batch: &RecordBatch,
+    mapping: &ColumnMapping,
+    first_doc_id: DocId,
+) -> Result<usize, LfasError>
+where
+    S: PostingsStorage<RecordField>,
+{
+    let mut columns = Vec::new();
+    for (name, field) in &mapping.columns {
+        if let Some(values) = string_column(batch, name)? {
+            columns.push((*field, values));
+        }
+    }
+
+    if columns.is_empty() {
+        return Err(LfasError::Schema(
+            "record batch has none of the mapped columns".to_string(),
+        ));
+    }
+
+    let ids = match &mapping.id_column {
+        Some(name) => string_column(batch, name)?,
+        None => None,
+    };
+
+    if let Some(last_row) = batch.num_rows().checked_sub(1) {
+        DocId::try_from(first_doc_id.index() + last_row)?;
+    }
+
+    let analyzer = engine.analyzer();
+    let docs: Vec<TokenizedDoc<RecordField>> = (0..batch.num_rows())
+        .into_par_iter()
+        .map(|row| TokenizedDoc {
+            doc_id: DocId::new(first_doc_id.get() + row as u32),
+            external_id: ids
+                .as_ref()
+                .and_then(|ids| ids[row].clone())
+                .filter(|id| !id.is_empty()),
+            fields: columns
+                .iter()
+                .map(|(field, values)| {
+                    let tfs = values[row]
+                        .as_deref()
+                        .map(|text| analyzer.term_frequencies(field, text))
+                        .unwrap_or_default();
+                    (*field, tfs)
+                })
+                .collect(),
+            stored: columns
+                .iter()
+                .filter_map(|(field, values)| values[row].clone().map(|value| (*field, value)))
+                .collect(),
+        })
+        .collect();
+
+    engine.index_tokenized(docs)?;
+    Ok(batch.num_rows())
+}
+
+/// Streams a Parquet file into the engine. Returns the number of indexed rows.
+/// `progress` is told the file's row count, so its reports carry an ETA;
+/// flushes are the storage commits made meanwhile.
+pub fn index_parquet<S>(
+    engine: &mut SearchEngine<RecordField, S>,
+    path: &Path,
+    mapping: &ColumnMapping,
+    first_doc_id: DocId,
+    progress: Option<ProgressReporter<'_>>,
+) -> Result<usize, LfasError>
+where
+    S: PostingsStorage<RecordField>,
+{
+    info!("[INGEST] Reading parquet file {:?}", path);
+
+    let file = File::open(path)?;
+    let builder = ParquetRecordBatchReaderBuilder::try_new(file).map_err(LfasError::storage)?;
+    let rows = builder.metadata().file_metadata().num_rows().max(0) as usize;
+    let reader = builder
+        .with_batch_size(DEFAULT_READ_BATCH_SIZE)
+        .build()
+        .map_err(LfasError::storage)?;
+
+    let mut progress = progress.map(|progress| progress.expected_docs(rows));
+    // (tokens, commits) so far; tokens are what the field lengths grew by
+    let counts = |engine: &SearchEngine<RecordField, S>| {
+        let tokens: usize = engine.metadata.total_field_lengths.values().sum();
+        let generation = engine.index.storage.generation().unwrap_or(0);
+        (tokens as u64, generation)
+    };
+    let (first_tokens, first_generation) = counts(engine);
+
+    let mut indexed = 0;
+    for batch in reader {
+        let batch = batch.map_err(LfasError::storage)?;
+        let batch_first = DocId::try_from(first_doc_id.index() + indexed)?;
+        indexed += index_record_batch(engine, &batch, mapping, batch_first)?;
+        info!("[INGEST] Indexed {} rows", indexed);
+        if let Some(progress) = &mut progress {
+            let (tokens, generation) = counts(engine);
+            let flushes = (generation - first_generation) as usize;
+            progress.observe(indexed, tokens - first_tokens, flushes);
+        }
+    }
+    if let Some(progress) = &mut progress {
+        let (tokens, generation) = counts(engine);
+        let flushes = (generation - first_generation) as usize;
+        progress.report(indexed, tokens - first_tokens, flushes);
+    }
+
+    engine.record_source(path)?;
+    Ok(indexed)
+}
@@ -1,18 +1,138 @@
 use pyo3::pyclass;
 
+pub mod admission;
+#[cfg(feature = "tokio")]
+pub mod async_engine;
+pub mod builder;
+pub mod candidates;
+#[cfg(feature = "bundle")]
+pub mod bundle;
+pub mod cancel;
+pub mod compare;
+pub mod config;
+pub mod cooccurrence;
+pub mod decompound;
+pub mod docstore;
 pub mod engine;
+pub mod engine_builder;
+pub mod error;
+pub mod eval;
+pub mod impact;
+#[cfg(feature = "grpc")]
+pub mod grpc;
 pub mod index;
+#[cfg(feature = "parquet")]
+pub mod ingest;
+pub mod manager;
+pub mod merge;
 pub mod metadata;
+pub mod metrics;
 pub mod postings;
+pub mod provenance;
+pub mod query;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod score_cache;
 pub mod scorer;
+pub mod shard;
+pub mod similarity;
+pub mod slowlog;
+pub mod spelling;
 pub mod storage;
 pub mod timing;
 pub mod tokenizer;
+pub mod validation;
+pub mod vectors;
+pub mod verbosity;
 
 #[cfg(feature = "python")]
 pub mod python;
 
-pub type DocId = usize;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+
+/// Internal document id. Roaring bitmaps hold `u32`s, so an id is checked
+/// once when its document comes in ([`DocId::try_from`]) rather than
+/// truncated wherever it reaches a bitmap.
+///
+/// Serialized as a `u64`, like the `usize` ids of earlier versions, so
+/// existing metadata snapshots and doc stores still load.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DocId(u32);
+
+impl DocId {
+    pub const MAX: DocId = DocId(u32::MAX);
+
+    pub const fn new(id: u32) -> Self {
+        DocId(id)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// The id as a row of per-document arrays.
+    pub const fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+impl From<u32> for DocId {
+    fn from(id: u32) -> Self {
+        DocId(id)
+    }
+}
+
+impl From<DocId> for u32 {
+    fn from(id: DocId) -> Self {
+        id.0
+    }
+}
+
+impl TryFrom<usize> for DocId {
+    type Error = error::LfasError;
+
+    fn try_from(id: usize) -> Result<Self, Self::Error> {
+        u32::try_from(id)
+            .map(DocId)
+            .map_err(|_| error::LfasError::DocIdOverflow(id as u64))
+    }
+}
+
+impl TryFrom<u64> for DocId {
+    type Error = error::LfasError;
+
+    fn try_from(id: u64) -> Result<Self, Self::Error> {
+        u32::try_from(id)
+            .map(DocId)
+            .map_err(|_| error::LfasError::DocIdOverflow(id))
+    }
+}
+
+impl PartialEq<u32> for DocId {
+    fn eq(&self, other: &u32) -> bool {
+        self.0 == *other
+    }
+}
+
+impl std::fmt::Display for DocId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl serde::Serialize for DocId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(u64::from(self.0))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for DocId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = u64::deserialize(deserializer)?;
+        DocId::try_from(id).map_err(serde::de::Error::custom)
+    }
+}
 
 #[pyclass]
 #[derive(
@@ -30,7 +150,42 @@ pub enum RecordField {
     Nome,
 }
 
-#[derive(Hash, Eq, PartialEq, Clone, Ord, PartialOrd, Debug, serde::Deserialize)]
+impl RecordField {
+    pub const ALL: [RecordField; 9] = [
+        RecordField::Estado,
+        RecordField::Municipio,
+        RecordField::Bairro,
+        RecordField::Cep,
+        RecordField::TipoLogradouro,
+        RecordField::Rua,
+        RecordField::Numero,
+        RecordField::Complemento,
+        RecordField::Nome,
+    ];
+
+    /// snake_case name used by the Python dicts and file columns.
+    pub fn name(&self) -> &'static str {
+        match self {
+            RecordField::Estado => "estado",
+            RecordField::Municipio => "municipio",
+            RecordField::Bairro => "bairro",
+            RecordField::Cep => "cep",
+            RecordField::TipoLogradouro => "tipo_logradouro",
+            RecordField::Rua => "rua",
+            RecordField::Numero => "numero",
+            RecordField::Complemento => "complemento",
+            RecordField::Nome => "nome",
+        }
+    }
+
+    /// Case-insensitive inverse of [`RecordField::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_lowercase();
+        Self::ALL.into_iter().find(|field| field.name() == name)
+    }
+}
+
+#[derive(Hash, Eq, PartialEq, Clone, Ord, PartialOrd, Debug, Default, serde::Deserialize)]
 pub struct Record {
     pub id: String,
     pub estado: String,
@@ -42,6 +197,10 @@ pub struct Record {
     pub numero: String,
     pub complemento: String,
     pub nome: String,
+    /// Extra values for any field (popular names, old street names, ...),
+    /// indexed into the same field as the primary value.
+    #[serde(default)]
+    pub aliases: Vec<(RecordField, String)>,
 }
 
 impl Record {
@@ -58,22 +217,313 @@ impl Record {
             (RecordField::Nome, &self.nome),
         ]
     }
+
+    pub fn field(&self, field: RecordField) -> &str {
+        match field {
+            RecordField::Estado => &self.estado,
+            RecordField::Municipio => &self.municipio,
+            RecordField::Bairro => &self.bairro,
+            RecordField::Cep => &self.cep,
+            RecordField::TipoLogradouro => &self.tipo_logradouro,
+            RecordField::Rua => &self.rua,
+            RecordField::Numero => &self.numero,
+            RecordField::Complemento => &self.complemento,
+            RecordField::Nome => &self.nome,
+        }
+    }
+
+    pub fn field_mut(&mut self, field: RecordField) -> &mut String {
+        match field {
+            RecordField::Estado => &mut self.estado,
+            RecordField::Municipio => &mut self.municipio,
+            RecordField::Bairro => &mut self.bairro,
+            RecordField::Cep => &mut self.cep,
+            RecordField::TipoLogradouro => &mut self.tipo_logradouro,
+            RecordField::Rua => &mut self.rua,
+            RecordField::Numero => &mut self.numero,
+            RecordField::Complemento => &mut self.complemento,
+            RecordField::Nome => &mut self.nome,
+        }
+    }
+
+    /// Primary values followed by the non-empty aliases; a field may repeat.
+    pub fn values(&self) -> Vec<(RecordField, &str)> {
+        let mut values = self.fields();
+        values.extend(
+            self.aliases
+                .iter()
+                .filter(|(_, value)| !value.is_empty())
+                .map(|(field, value)| (*field, value.as_str())),
+        );
+        values
+    }
 }
 
 #[derive(Hash, Eq, PartialEq, Clone, Debug, serde::Deserialize)]
 pub struct StructuredQuery<F> {
     pub fields: Vec<(F, String)>,
+    /// Occurrence of each clause in `fields`, by position; clauses past its
+    /// end are `Should`.
+    #[serde(default)]
+    pub occur: Vec<Occur>,
+    /// Hard constraints: candidates must hold every full token of the text in
+    /// that field. Filters narrow Round 1 and are not scored.
+    #[serde(default)]
+    pub filters: Vec<(F, String)>,
+    /// Keep only candidates where the field is empty or missing, or only
+    /// those where it is not. Unscored, like filters.
+    #[serde(default)]
+    pub presence: Vec<(F, FieldPresence)>,
     pub top_k: usize,
     pub blocking_k: usize,
+    /// External record id, resolved through the id map before fuzzy search.
+    #[serde(default)]
+    pub external_id: Option<String>,
+    /// Stop working on the query past this instant, returning what was scored so far.
+    #[serde(skip)]
+    pub deadline: Option<std::time::Instant>,
+    #[serde(skip)]
+    pub cancel: Option<cancel::CancelToken>,
+    /// Candidates must match at least this many of the query's distinctive tokens.
+    #[serde(default)]
+    pub min_should_match: Option<MinShouldMatch>,
+    /// Named settings bundle overriding the engine's for this query.
+    #[serde(default)]
+    pub preset: Option<engine::QueryPreset>,
+    /// Embedding of the query, blended into the ranking by the engine's
+    /// [`VectorReranker`](vectors::VectorReranker) when it has one.
+    #[serde(default)]
+    pub vector: Option<vectors::QueryVector>,
+    /// How much this search logs, instead of the engine's verbosity.
+    #[serde(default)]
+    pub verbosity: Option<verbosity::Verbosity>,
+}
+
+/// How a field clause takes part in the search.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, serde::Deserialize)]
+pub enum Occur {
+    /// Scored, and candidates must hold every full token of the clause.
+    Must,
+    /// Scored and used to find candidates, without being required.
+    #[default]
+    Should,
+    /// Candidates holding every full token of the clause are dropped. Not scored.
+    MustNot,
+}
+
+/// Whether a presence clause keeps documents with or without tokens in a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+pub enum FieldPresence {
+    Empty,
+    NotEmpty,
+}
+
+/// How many distinctive query tokens a document must match to become a candidate.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize)]
+pub enum MinShouldMatch {
+    Count(usize),
+    /// Share of the distinctive tokens, 0 to 100, rounded up.
+    Percent(f32),
+}
+
+impl Eq for MinShouldMatch {}
+
+impl std::hash::Hash for MinShouldMatch {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        match self {
+            MinShouldMatch::Count(count) => (0u8, *count as u64).hash(state),
+            MinShouldMatch::Percent(percent) => (1u8, percent.to_bits() as u64).hash(state),
+        }
+    }
+}
+
+impl MinShouldMatch {
+    /// Parses "3" as a count and "75%" as a percentage.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let spec = spec.trim();
+        match spec.strip_suffix('%') {
+            Some(percent) => percent.trim().parse().ok().map(MinShouldMatch::Percent),
+            None => spec.parse().ok().map(MinShouldMatch::Count),
+        }
+    }
+
+    /// Required matches out of `total` distinctive tokens, between 1 and `total`.
+    pub fn required(&self, total: usize) -> usize {
+        let required = match *self {
+            MinShouldMatch::Count(count) => count,
+            MinShouldMatch::Percent(percent) => (percent / 100.0 * total as f32).ceil() as usize,
+        };
+        required.clamp(1, total.max(1))
+    }
+}
+
+impl<F> Default for StructuredQuery<F> {
+    fn default() -> Self {
+        Self {
+            fields: Vec::new(),
+            occur: Vec::new(),
+            filters: Vec::new(),
+            presence: Vec::new(),
+            top_k: engine::DEFAULT_TOP_K,
+            blocking_k: engine::DEFAULT_BLOCKING_K,
+            external_id: None,
+            deadline: None,
+            cancel: None,
+            min_should_match: None,
+            preset: None,
+            vector: None,
+            verbosity: None,
+        }
+    }
+}
+
+impl<F> StructuredQuery<F> {
+    /// Sets the deadline `timeout` from now.
+    pub fn with_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.deadline = Some(std::time::Instant::now() + timeout);
+        self
+    }
+
+    /// Occurrence of `fields[clause]`.
+    pub fn occur(&self, clause: usize) -> Occur {
+        self.occur.get(clause).copied().unwrap_or_default()
+    }
+
+    /// The `Must` and `Should` clauses of `fields`, the ones scored.
+    pub fn scored_fields(&self) -> impl Iterator<Item = &(F, String)> {
+        self.fields
+            .iter()
+            .enumerate()
+            .filter(|(clause, _)| self.occur(*clause) != Occur::MustNot)
+            .map(|(_, field)| field)
+    }
+
+    /// True once the deadline has passed or the query was cancelled.
+    pub fn is_interrupted(&self) -> bool {
+        self.deadline.is_some_and(|deadline| std::time::Instant::now() >= deadline)
+            || self.cancel.as_ref().is_some_and(|cancel| cancel.is_cancelled())
+    }
+
+    /// Rejects queries outside `limits` before any work is done.
+    pub fn validate(&self, limits: &QueryLimits) -> Result<(), error::LfasError> {
+        use error::LfasError::InvalidQuery;
+
+        if self.top_k == 0 || self.top_k > limits.max_top_k {
+            return Err(InvalidQuery(format!(
+                "top_k must be between 1 and {}, got {}",
+                limits.max_top_k, self.top_k
+            )));
+        }
+        if self.blocking_k == 0 || self.blocking_k > limits.max_blocking_k {
+            return Err(InvalidQuery(format!(
+                "blocking_k must be between 1 and {}, got {}",
+                limits.max_blocking_k, self.blocking_k
+            )));
+        }
+        if self.occur.len() > self.fields.len() {
+            return Err(InvalidQuery(format!(
+                "{} occurrences given for {} field clauses",
+                self.occur.len(),
+                self.fields.len()
+            )));
+        }
+        let clauses = self.fields.len() + self.filters.len() + self.presence.len();
+        if clauses > limits.max_clauses {
+            return Err(InvalidQuery(format!(
+                "at most {} field clauses are allowed, got {}",
+                limits.max_clauses, clauses
+            )));
+        }
+        if let Some(MinShouldMatch::Percent(percent)) = self.min_should_match {
+            if !(0.0..=100.0).contains(&percent) {
+                return Err(InvalidQuery(format!(
+                    "min_should_match must be between 0% and 100%, got {}%",
+                    percent
+                )));
+            }
+        }
+        if let Some((i, (_, text))) = self
+            .fields
+            .iter()
+            .chain(&self.filters)
+            .enumerate()
+            .find(|(_, (_, text))| text.len() > limits.max_text_len)
+        {
+            return Err(InvalidQuery(format!(
+                "clause {} is {} bytes long, the limit is {}",
+                i,
+                text.len(),
+                limits.max_text_len
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Upper bounds enforced on every query, so a buggy caller can't DOS the engine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QueryLimits {
+    pub max_top_k: usize,
+    pub max_blocking_k: usize,
+    pub max_clauses: usize,
+    /// Maximum length in bytes of a single clause text.
+    pub max_text_len: usize,
+}
+
+impl Default for QueryLimits {
+    fn default() -> Self {
+        Self {
+            max_top_k: 10_000,
+            max_blocking_k: 1_000_000,
+            max_clauses: 32,
+            max_text_len: 1024,
+        }
+    }
+}
+
+/// Hits of a search that may have been cut short by its deadline or cancellation.
+#[derive(Debug, Default)]
+pub struct SearchResults {
+    pub hits: Vec<SearchHit>,
+    /// True when scoring stopped early; `hits` then only reflect the terms
+    /// scored before the interruption (possibly none).
+    pub interrupted: bool,
+    pub diagnostics: QueryDiagnostics,
+}
 
+/// How a search went, for explaining slow or empty results without the logs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryDiagnostics {
+    /// Round 1 candidate set size.
+    pub candidates: u64,
+    /// (field, token) pairs whose postings built the candidate set; fields
+    /// are in their `Debug` form.
+    pub round1_tokens: Vec<(String, String)>,
+    /// True when no distinctive token matched and the fallback policy ran.
+    pub fallback: bool,
+    /// True when the query was answered by exact external id lookup.
+    pub exact: bool,
+    /// True when a full CEP matched few enough documents to rank them
+    /// without BM25F (`cep_shortcut`).
+    pub cep_shortcut: bool,
+    /// Scored hits dropped as duplicates of a better one (`collapse_fields`).
+    pub collapsed: u64,
+    pub round1: std::time::Duration,
+    pub round2: std::time::Duration,
+    pub total: std::time::Duration,
 }
 
 #[derive(Debug)]
 pub struct SearchHit {
-    pub doc_id: usize,
+    pub doc_id: DocId,
     pub score: f32,
+    /// True when the hit was resolved by exact external id lookup.
+    pub exact: bool,
+    /// BM25F contribution of each matched field (in its `Debug` form), only
+    /// filled when the engine's `field_scores` option is on.
+    pub field_scores: Vec<(String, f32)>,
 }
 
 pub trait AddressSearcher<F> {
-    fn search(&self, query: StructuredQuery<F>) -> Vec<SearchHit>;
+    fn search(&self, query: StructuredQuery<F>) -> Result<Vec<SearchHit>, error::LfasError>;
 }
@@ -0,0 +1,328 @@
+use clap::{Args, Parser, Subcommand};
+use lfas::engine::{DEFAULT_BLOCKING_K, DEFAULT_TOP_K, QueryPreset, SearchEngine};
+use lfas::error::LfasError;
+use lfas::eval::{DEFAULT_EVAL_K, evaluate, load_pairs, load_records};
+use lfas::merge::IndexMerger;
+use lfas::storage::{InMemoryStorage, LmdbOptions, LmdbStorage, PostingsStorage};
+use lfas::{MinShouldMatch, RecordField, StructuredQuery};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(name = "lfas", about = "Field-aware address search")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Index a corpus in memory and score labeled query pairs against it
+    Eval {
+        /// CSV with an `id` column plus field columns (rua, municipio, ...)
+        #[arg(long)]
+        corpus: PathBuf,
+        /// CSV with query_id, candidate_id, label and the query field columns
+        #[arg(long)]
+        pairs: PathBuf,
+        #[arg(long, default_value_t = DEFAULT_EVAL_K)]
+        k: usize,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Run one structured query and print the hits as JSON lines
+    Search {
+        #[command(flatten)]
+        source: SearchSource,
+        #[command(flatten)]
+        query: QueryArgs,
+        /// Metadata file written by `save_metadata`; rebuilt from the index when absent
+        #[arg(long, requires = "index")]
+        metadata: Option<PathBuf>,
+        #[arg(long, default_value_t = DEFAULT_TOP_K)]
+        top_k: usize,
+        #[arg(long, default_value_t = DEFAULT_BLOCKING_K)]
+        blocking_k: usize,
+        /// Token count ("3") or share ("75%") a candidate must match
+        #[arg(long, value_parser = parse_min_should_match)]
+        min_should_match: Option<MinShouldMatch>,
+        /// Settings bundle for the query: strict, balanced or fuzzy
+        #[arg(long, value_parser = parse_preset)]
+        preset: Option<QueryPreset>,
+        /// Impacts written by `build-impacts`; ignored when stale
+        #[arg(long, requires = "index")]
+        impacts: Option<PathBuf>,
+    },
+    /// Precompute quantized scores of every posting with the default scorer.
+    /// Run again whenever the index or the scorer parameters change.
+    BuildImpacts {
+        /// LMDB index directory, opened read-only
+        #[arg(long)]
+        index: PathBuf,
+        /// Metadata file written by `save_metadata`; rebuilt from the index when absent
+        #[arg(long)]
+        metadata: Option<PathBuf>,
+        /// File the impacts are written to
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Merge indexes, e.g. built per state, into a new one. The doc ids of
+    /// each input move up past those of the inputs before it.
+    Merge {
+        /// Directory of the merged index; must not exist yet
+        #[arg(long)]
+        out: PathBuf,
+        /// Directory for spill files, `<out>.runs` when absent
+        #[arg(long)]
+        spill_dir: Option<PathBuf>,
+        /// LMDB index directories, opened read-only
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+    },
+}
+
+#[derive(Args)]
+#[group(required = true, multiple = false)]
+struct SearchSource {
+    /// LMDB index directory, opened read-only
+    #[arg(long)]
+    index: Option<PathBuf>,
+    /// CSV corpus indexed in memory instead; hits then include the stored doc
+    #[arg(long)]
+    corpus: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct QueryArgs {
+    /// JSON object of field name -> text, merged under the field flags
+    #[arg(long)]
+    query_json: Option<String>,
+    /// External record id, resolved exactly when indexed
+    #[arg(long)]
+    id: Option<String>,
+    #[arg(long)]
+    estado: Option<String>,
+    #[arg(long)]
+    municipio: Option<String>,
+    #[arg(long)]
+    bairro: Option<String>,
+    #[arg(long)]
+    cep: Option<String>,
+    #[arg(long)]
+    tipo_logradouro: Option<String>,
+    #[arg(long)]
+    rua: Option<String>,
+    #[arg(long)]
+    numero: Option<String>,
+    #[arg(long)]
+    complemento: Option<String>,
+    #[arg(long)]
+    nome: Option<String>,
+}
+
+impl QueryArgs {
+    /// Field flags win over the same key in `--query-json`.
+    fn into_query(self) -> Result<StructuredQuery<RecordField>, LfasError> {
+        let mut values: BTreeMap<String, String> = match &self.query_json {
+            Some(json) => serde_json::from_str(json)
+                .map_err(|e| LfasError::InvalidQuery(format!("--query-json: {}", e)))?,
+            None => BTreeMap::new(),
+        };
+        let flags = [
+            ("id", self.id),
+            ("estado", self.estado),
+            ("municipio", self.municipio),
+            ("bairro", self.bairro),
+            ("cep", self.cep),
+            ("tipo_logradouro", self.tipo_logradouro),
+            ("rua", self.rua),
+            ("numero", self.numero),
+            ("complemento", self.complemento),
+            ("nome", self.nome),
+        ];
+        for (name, value) in flags {
+            if let Some(value) = value {
+                values.insert(name.to_string(), value);
+            }
+        }
+
+        let mut query = StructuredQuery::default();
+        for (name, text) in values {
+            if name == "id" {
+                query.external_id = Some(text);
+                continue;
+            }
+            let field = RecordField::from_name(&name)
+                .ok_or_else(|| LfasError::Schema(format!("Unknown field '{}'", name)))?;
+            query.fields.push((field, text));
+        }
+        if query.fields.is_empty() && query.external_id.is_none() {
+            return Err(LfasError::InvalidQuery(
+                "no query fields given, use --rua, --cep, ... or --query-json".into(),
+            ));
+        }
+        Ok(query)
+    }
+}
+
+fn parse_min_should_match(value: &str) -> Result<MinShouldMatch, String> {
+    MinShouldMatch::parse(value)
+        .ok_or_else(|| format!("expected a count or a percentage, got '{}'", value))
+}
+
+fn parse_preset(value: &str) -> Result<QueryPreset, String> {
+    QueryPreset::from_name(value)
+        .ok_or_else(|| format!("expected strict, balanced or fuzzy, got '{}'", value))
+}
+
+/// Prints one JSON object per hit: doc id, score, whether it was an exact id
+/// match, and the stored field values when the engine has them.
+fn print_hits<S>(
+    engine: &SearchEngine<RecordField, S>,
+    query: StructuredQuery<RecordField>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    S: PostingsStorage<RecordField>,
+{
+    for (hit, fields) in engine.search_with_fields(query, &RecordField::ALL)? {
+        let doc: Option<BTreeMap<&str, &str>> = (!fields.is_empty()).then(|| {
+            fields
+                .iter()
+                .map(|(field, value)| (field.name(), value.as_str()))
+                .collect()
+        });
+        let line = serde_json::json!({
+            "doc_id": hit.doc_id,
+            "score": hit.score,
+            "exact": hit.exact,
+            "doc": doc,
+        });
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Opens an LMDB index read-only with its metadata loaded from `metadata`,
+/// or rebuilt from the index when absent.
+fn open_index(
+    index: &Path,
+    metadata: Option<PathBuf>,
+) -> Result<SearchEngine<RecordField, LmdbStorage<RecordField>>, Box<dyn std::error::Error>> {
+    let options = LmdbOptions::new().read_only(true);
+    let storage = LmdbStorage::<RecordField>::open_with_options(index, options)?;
+    let mut engine = SearchEngine::with_storage(storage);
+    match metadata {
+        Some(path) => {
+            let reader = BufReader::new(File::open(path)?);
+            engine.metadata = bincode::deserialize_from(reader)?;
+        }
+        None => {
+            engine.refresh()?;
+        }
+    }
+    Ok(engine)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    env_logger::init();
+
+    match Cli::parse().command {
+        Command::Eval {
+            corpus,
+            pairs,
+            k,
+            json,
+        } => {
+            let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+            for (doc_id, record) in load_records(&corpus)?.iter().enumerate() {
+                engine.index_record(doc_id, record)?;
+            }
+
+            let queries = load_pairs(&pairs)?;
+            let report = evaluate(&engine, &queries, k)?;
+
+            if json {
+                println!("{}", serde_json::to_string(&report)?);
+            } else {
+                println!("queries:          {}", report.queries);
+                println!("precision@{}:     {:.4}", k, report.precision_at_k);
+                println!("recall@{}:        {:.4}", k, report.recall_at_k);
+                println!("mrr:              {:.4}", report.mrr);
+                println!("non-match@{}:     {:.4}", k, report.non_match_rate_at_k);
+            }
+        }
+        Command::Search {
+            source,
+            query,
+            metadata,
+            top_k,
+            blocking_k,
+            min_should_match,
+            preset,
+            impacts,
+        } => {
+            let query = StructuredQuery {
+                top_k,
+                blocking_k,
+                min_should_match,
+                preset,
+                ..query.into_query()?
+            };
+
+            if let Some(corpus) = source.corpus {
+                let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+                for (doc_id, record) in load_records(&corpus)?.iter().enumerate() {
+                    engine.index_record(doc_id, record)?;
+                }
+                print_hits(&engine, query)?;
+            } else if let Some(index) = source.index {
+                let mut engine = open_index(&index, metadata)?;
+                if let Some(path) = impacts {
+                    let reader = BufReader::new(File::open(path)?);
+                    engine.impacts = Some(bincode::deserialize_from(reader)?);
+                }
+                print_hits(&engine, query)?;
+            }
+        }
+        Command::BuildImpacts {
+            index,
+            metadata,
+            out,
+        } => {
+            let mut engine = open_index(&index, metadata)?;
+            let postings = engine.rebuild_impacts()?;
+            if let Some(impacts) = &engine.impacts {
+                bincode::serialize_into(BufWriter::new(File::create(&out)?), impacts)?;
+            }
+            println!("{} postings scored into {}", postings, out.display());
+        }
+        Command::Merge {
+            out,
+            spill_dir,
+            inputs,
+        } => {
+            let spill_dir = spill_dir.unwrap_or_else(|| {
+                let mut name = out.clone().into_os_string();
+                name.push(".runs");
+                PathBuf::from(name)
+            });
+            let report = IndexMerger::new(&spill_dir).merge(&inputs, &out);
+            let _ = std::fs::remove_dir(&spill_dir);
+            let report = report?;
+            for (input, offset) in inputs.iter().zip(&report.offsets) {
+                println!("{}: doc ids from {}", input.display(), offset);
+            }
+            println!(
+                "{} docs, {} terms merged into {}",
+                report.docs,
+                report.terms,
+                out.display()
+            );
+        }
+    }
+
+    Ok(())
+}
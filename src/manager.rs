@@ -0,0 +1,205 @@
+//! Zero-downtime index rebuilds: a new index is built next to the live one and
+//! swapped in atomically while in-flight searches finish on the old engine.
+
+use crate::docstore::DocStore;
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::storage::{LmdbOptions, LmdbStorage};
+use crate::timing::Timer;
+use crate::{DocId, RecordField, SearchHit, StructuredQuery};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tracing::{info, warn};
+
+pub type LmdbEngine = SearchEngine<RecordField, LmdbStorage<RecordField>>;
+
+/// Suffix of the staging directory a new index is built in, next to its final path.
+pub const STAGING_SUFFIX: &str = "building";
+
+/// The engine currently serving searches. Searches hold an `Arc` to it, so a
+/// swap never pulls the LMDB env out from under a running query.
+pub struct ActiveIndex {
+    pub engine: LmdbEngine,
+    pub path: PathBuf,
+    /// Bumped by every successful [`IndexManager::build_and_swap`].
+    pub generation: u64,
+}
+
+/// Owns the active index and replaces it with freshly built ones.
+pub struct IndexManager {
+    active: RwLock<Arc<ActiveIndex>>,
+    options: LmdbOptions,
+}
+
+impl IndexManager {
+    /// Serves the existing index at `path`, rebuilding its metadata from storage.
+    pub fn open(path: &Path, options: LmdbOptions) -> Result<Self, LfasError> {
+        let engine = open_engine(path, &options)?;
+        Ok(Self {
+            active: RwLock::new(Arc::new(ActiveIndex {
+                engine,
+                path: path.to_path_buf(),
+                generation: 0,
+            })),
+            options,
+        })
+    }
+
+    /// The engine to run a search against; keep the `Arc` for the whole search.
+    pub fn current(&self) -> Result<Arc<ActiveIndex>, LfasError> {
+        Ok(self.active.read()?.clone())
+    }
+
+    pub fn generation(&self) -> Result<u64, LfasError> {
+        Ok(self.current()?.generation)
+    }
+
+    pub fn execute(
+        &self,
+        query: StructuredQuery<RecordField>,
+    ) -> Result<Vec<SearchHit>, LfasError> {
+        let active = self.current()?;
+        let blocking_k = query.blocking_k;
+        active.engine.execute(query, blocking_k)
+    }
+
+    /// Builds a new index at `new_path` with `build`, validates it and makes it
+    /// the active one. `new_path` must not exist yet; the index is written to a
+    /// staging directory beside it and renamed into place once complete, so a
+    /// failed build never leaves a partial index at `new_path`. The old engine
+    /// is dropped when its last in-flight search returns. Returns the new
+    /// generation.
+    pub fn build_and_swap(
+        &self,
+        new_path: &Path,
+        build: impl FnOnce(&mut LmdbEngine) -> Result<(), LfasError>,
+    ) -> Result<u64, LfasError> {
+        let timer = Timer::new("IndexManager::build_and_swap");
+
+        if new_path.exists() {
+            return Err(LfasError::Storage(format!(
+                "Index path {:?} already exists",
+                new_path
+            )));
+        }
+        let staging = staging_path(new_path);
+        if staging.exists() {
+            warn!("[SWAP] Removing stale staging directory {:?}", staging);
+            std::fs::remove_dir_all(&staging)?;
+        }
+
+        info!("[SWAP] Building new index in {:?}", staging);
+        let built = match build_staged(&staging, &self.options, build) {
+            Ok(built) => built,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(&staging);
+                return Err(e);
+            }
+        };
+
+        std::fs::rename(&staging, new_path)?;
+
+        let engine = match open_validated(new_path, &self.options, built) {
+            Ok(engine) => engine,
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(new_path);
+                return Err(e);
+            }
+        };
+
+        let generation = {
+            let mut active = self.active.write()?;
+            let generation = active.generation + 1;
+            *active = Arc::new(ActiveIndex {
+                engine,
+                path: new_path.to_path_buf(),
+                generation,
+            });
+            generation
+        };
+
+        drop(timer);
+        info!(
+            "[SWAP] Index {:?} active at generation {}",
+            new_path, generation
+        );
+        Ok(generation)
+    }
+}
+
+/// `<name>.building` in the same parent directory, so the final rename stays
+/// on one filesystem and is atomic.
+fn staging_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(STAGING_SUFFIX);
+    path.with_file_name(name)
+}
+
+fn open_engine(path: &Path, options: &LmdbOptions) -> Result<LmdbEngine, LfasError> {
+    let storage = LmdbStorage::open_with_options(path, options.clone())?;
+    let mut engine = SearchEngine::with_storage(storage);
+    engine.refresh()?;
+    Ok(engine)
+}
+
+/// In-memory state of a built engine that storage cannot rebuild.
+struct BuiltState {
+    id_map: HashMap<String, DocId>,
+    docs: DocStore<RecordField>,
+    timestamps: HashMap<DocId, i64>,
+    terms: usize,
+}
+
+/// Runs `build` on a fresh engine at `staging`, commits postings and config,
+/// then closes the env (and its writer lock) so the directory can be renamed.
+fn build_staged(
+    staging: &Path,
+    options: &LmdbOptions,
+    build: impl FnOnce(&mut LmdbEngine) -> Result<(), LfasError>,
+) -> Result<BuiltState, LfasError> {
+    let storage = LmdbStorage::open_with_options(staging, options.clone().read_only(false))?;
+    let mut engine = SearchEngine::with_storage(storage);
+    build(&mut engine)?;
+    engine.save_config()?;
+
+    Ok(BuiltState {
+        id_map: std::mem::take(&mut engine.id_map),
+        docs: std::mem::take(&mut engine.docs),
+        timestamps: std::mem::take(&mut engine.metadata.timestamps),
+        terms: engine.metadata.term_df.len(),
+    })
+}
+
+/// Reopens the renamed index and checks every term made it to disk before
+/// restoring the built engine's in-memory state.
+fn open_validated(
+    path: &Path,
+    options: &LmdbOptions,
+    built: BuiltState,
+) -> Result<LmdbEngine, LfasError> {
+    let storage = LmdbStorage::open_with_options(path, options.clone())?;
+    let mut engine = SearchEngine::with_storage(storage);
+    // Scanned rather than loaded from the committed snapshot, so the checks
+    // below measure what actually reached the disk
+    let generation = engine.index.storage.generation()?;
+    engine.rebuild_metadata(|_| {})?;
+    engine.synced_generation = Some(generation);
+    if engine.metadata.total_docs == 0 {
+        return Err(LfasError::Storage(format!("New index {:?} is empty", path)));
+    }
+    if engine.metadata.term_df.len() != built.terms {
+        return Err(LfasError::Storage(format!(
+            "New index {:?} has {} terms on disk, expected {}",
+            path,
+            engine.metadata.term_df.len(),
+            built.terms
+        )));
+    }
+
+    engine.set_id_map(built.id_map);
+    engine.docs = built.docs;
+    engine.metadata.timestamps = built.timestamps;
+    Ok(engine)
+}
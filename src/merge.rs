@@ -0,0 +1,260 @@
+//! Merging of existing indexes into a new one, e.g. indexes built per state
+//! in parallel. Each input keeps its doc ids, moved up past those of the
+//! inputs before it. Posting lists of the same term are unioned through an
+//! [`IndexBuilder`], so the merge runs within its memory budget however
+//! large the inputs are; dfs, lengths and id maps are combined in memory,
+//! and stored field columns are copied one input at a time.
+
+use crate::builder::{DEFAULT_MEMORY_BUDGET, IndexBuilder};
+use crate::config::EngineConfig;
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::manager::LmdbEngine;
+use crate::metadata::FieldMetadata;
+use crate::postings::Postings;
+use crate::storage::{LmdbOptions, LmdbStorage, PostingsStorage};
+use crate::timing::Timer;
+use crate::{DocId, RecordField};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    pub docs: usize,
+    /// Distinct (field, term) posting lists written.
+    pub terms: usize,
+    /// (field, term, doc) entries across all posting lists.
+    pub tuples: u64,
+    /// What each input's doc ids were moved up by, in input order.
+    pub offsets: Vec<DocId>,
+    /// External ids found in more than one input. The doc of the last input
+    /// holding one keeps it.
+    pub duplicate_ids: usize,
+}
+
+/// Merges LMDB indexes into a new one. Stored field columns are copied with
+/// the doc ids moved up likewise; vectors aren't part of an index and aren't
+/// merged, re-key them with [`MergeReport::offsets`].
+pub struct IndexMerger {
+    spill_dir: PathBuf,
+    memory_budget: usize,
+    options: LmdbOptions,
+}
+
+impl IndexMerger {
+    /// Spill files of the postings union go to `spill_dir`.
+    pub fn new(spill_dir: &Path) -> Self {
+        Self {
+            spill_dir: spill_dir.to_path_buf(),
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            options: LmdbOptions::new(),
+        }
+    }
+
+    pub fn memory_budget(mut self, bytes: usize) -> Self {
+        self.memory_budget = bytes;
+        self
+    }
+
+    /// Environment options of the output index.
+    pub fn options(mut self, options: LmdbOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Writes the union of `inputs`, opened read-only, to a new index at
+    /// `output`, which must not exist yet. The config comes from the first
+    /// input that has one. A failed merge leaves nothing at `output`.
+    pub fn merge(&self, inputs: &[PathBuf], output: &Path) -> Result<MergeReport, LfasError> {
+        let timer = Timer::new("IndexMerger::merge");
+
+        if inputs.is_empty() {
+            return Err(LfasError::Storage("No indexes to merge".into()));
+        }
+        if output.exists() {
+            return Err(LfasError::Storage(format!(
+                "Index path {:?} already exists",
+                output
+            )));
+        }
+
+        let mut builder = IndexBuilder::new(&self.spill_dir)?.memory_budget(self.memory_budget);
+        let mut metadata = FieldMetadata::new();
+        let mut ids = Vec::new();
+        let mut config: Option<Vec<u8>> = None;
+        let mut offsets = Vec::with_capacity(inputs.len());
+        // One past the largest doc id taken so far
+        let mut next: u64 = 0;
+
+        for path in inputs {
+            let mut input = open_input(path)?;
+            let offset = DocId::try_from(next)?;
+            let storage = &input.index.storage;
+
+            let input_ids = storage.read_id_map()?;
+            let mut last = input
+                .metadata
+                .lengths
+                .keys()
+                .chain(input.metadata.timestamps.keys())
+                .chain(input.metadata.collapse_keys.keys())
+                .chain(input_ids.iter().map(|(_, doc_id)| doc_id))
+                .max()
+                .copied();
+            if let Some(doc_id) = last {
+                DocId::try_from(next + u64::from(doc_id.get()))?;
+            }
+
+            storage
+                .scan(|field, term, bytes| {
+                    let postings: Postings = bincode::deserialize(bytes)?;
+                    let Some(max) = postings.bitmap().max() else {
+                        return Ok(());
+                    };
+                    DocId::try_from(next + u64::from(max))?;
+                    last = last.max(Some(DocId::new(max)));
+                    builder.add_postings(
+                        field,
+                        term,
+                        postings
+                            .iter()
+                            .map(|(doc_id, tf)| (DocId::new(doc_id.get() + offset.get()), tf)),
+                    )
+                })
+                .map_err(LfasError::storage)?;
+
+            ids.extend(input_ids.into_iter().map(|(external_id, doc_id)| {
+                (external_id, DocId::new(doc_id.get() + offset.get()))
+            }));
+            match (storage.read_config()?, &config) {
+                (Some(bytes), None) => config = Some(bytes),
+                (Some(bytes), Some(first)) if bytes != *first => {
+                    warn!(
+                        "[MERGE] {:?} was saved with another config, keeping the first",
+                        path
+                    );
+                }
+                _ => {}
+            }
+
+            info!(
+                "[MERGE] {:?}: {} docs from doc id {}",
+                path, input.metadata.total_docs, offset
+            );
+            metadata.append(std::mem::take(&mut input.metadata), offset.get());
+            offsets.push(offset);
+            if let Some(doc_id) = last {
+                next += u64::from(doc_id.get()) + 1;
+            }
+        }
+
+        let stored: Vec<(&Path, DocId)> = inputs
+            .iter()
+            .map(PathBuf::as_path)
+            .zip(offsets.iter().copied())
+            .collect();
+        let merged = write_output(
+            output,
+            &self.options,
+            builder,
+            metadata,
+            ids,
+            config,
+            &stored,
+        );
+        let report = match merged {
+            Ok(report) => MergeReport { offsets, ..report },
+            Err(e) => {
+                let _ = std::fs::remove_dir_all(output);
+                return Err(e);
+            }
+        };
+
+        drop(timer);
+        info!(
+            "[MERGE] Merged {} indexes into {:?}: {} docs, {} terms",
+            inputs.len(),
+            output,
+            report.docs,
+            report.terms
+        );
+        Ok(report)
+    }
+}
+
+/// Copies the stored field columns of each (input, offset) into `storage`
+/// under the shifted doc ids, one input per commit.
+fn copy_stored(
+    storage: &mut LmdbStorage<RecordField>,
+    inputs: &[(&Path, DocId)],
+) -> Result<(), LfasError> {
+    for (path, offset) in inputs {
+        let input = LmdbStorage::<RecordField>::open_with_options(
+            path,
+            LmdbOptions::new().read_only(true),
+        )?;
+        for field in RecordField::ALL {
+            for (doc_id, value) in input.stored_column(field)? {
+                let doc_id = DocId::new(doc_id.get() + offset.get());
+                storage.write_stored(doc_id, field, Some(&value))?;
+            }
+        }
+        storage.flush()?;
+    }
+    Ok(())
+}
+
+fn open_input(path: &Path) -> Result<LmdbEngine, LfasError> {
+    let storage = LmdbStorage::open_with_options(path, LmdbOptions::new().read_only(true))?;
+    let mut engine = SearchEngine::with_storage(storage);
+    engine.refresh()?;
+    Ok(engine)
+}
+
+/// Bulk loads the unioned postings into a fresh index at `output` and
+/// commits the merged metadata, id map, stored columns and config with it.
+fn write_output(
+    output: &Path,
+    options: &LmdbOptions,
+    builder: IndexBuilder<RecordField>,
+    metadata: FieldMetadata<RecordField>,
+    ids: Vec<(String, DocId)>,
+    config: Option<Vec<u8>>,
+    stored: &[(&Path, DocId)],
+) -> Result<MergeReport, LfasError> {
+    let mut storage = LmdbStorage::open_with_options(output, options.clone().read_only(false))?;
+    let built = builder.finish(&mut storage)?;
+
+    let mut engine = SearchEngine::with_storage(storage);
+    if let Some(bytes) = config {
+        engine.apply_config(EngineConfig::from_bytes(&bytes)?);
+    }
+    engine.metadata = metadata;
+
+    let mut seen = HashSet::new();
+    let mut duplicates = 0;
+    for (external_id, doc_id) in ids {
+        if !seen.insert(external_id.clone()) {
+            duplicates += 1;
+        }
+        engine.map_external_id(external_id, doc_id)?;
+    }
+    if duplicates > 0 {
+        warn!(
+            "[MERGE] {} external ids are held by several inputs, the last one wins",
+            duplicates
+        );
+    }
+
+    copy_stored(&mut engine.index.storage, stored)?;
+    engine.recompute_term_stats()?;
+    engine.save_config()?;
+    Ok(MergeReport {
+        docs: engine.metadata.total_docs,
+        terms: built.terms,
+        tuples: built.tuples,
+        offsets: Vec::new(),
+        duplicate_ids: duplicates,
+    })
+}
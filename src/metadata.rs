@@ -1,11 +1,39 @@
 use serde::{Deserialize, Serialize};
 
 use crate::DocId;
-use std::collections::HashMap;
+use crate::error::LfasError;
+use roaring::RoaringBitmap;
+use std::collections::{BTreeMap, HashMap};
 use std::hash::Hash;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Disk-backed home for document frequencies and document lengths, for
+/// corpora whose metadata doesn't fit in RAM. See [`FieldMetadata::spill_to`].
+pub trait MetadataStore<F>: Send + Sync {
+    fn get_df(&self, field: &F, term: &str) -> Result<Option<usize>, LfasError>;
+
+    fn doc_length(&self, doc_id: DocId, field: &F) -> Result<Option<usize>, LfasError>;
+
+    /// Overwrites the stored df of each (field, term).
+    fn put_dfs(&self, dfs: Vec<((F, String), usize)>) -> Result<(), LfasError>;
+
+    /// Overwrites the stored lengths of each document's fields.
+    fn put_lengths(&self, lengths: Vec<(DocId, HashMap<F, usize>)>) -> Result<(), LfasError>;
+}
+
+/// Read-only document lengths kept outside `FieldMetadata::lengths`, such as
+/// the bitpacked columns persisted with an LMDB index.
+pub trait DocLengths<F>: Send + Sync {
+    fn doc_length(&self, doc_id: DocId, field: &F) -> Result<Option<usize>, LfasError>;
+}
 
 /// Keeps track of document lengths and global field stats.
 #[derive(Serialize, Deserialize)]
+#[serde(bound(
+    serialize = "F: Serialize + Hash + Eq + Clone",
+    deserialize = "F: Deserialize<'de> + Hash + Eq + Clone"
+))]
 pub struct FieldMetadata<F> 
 where 
     F: Hash + Eq + Clone
@@ -18,6 +46,130 @@ where
     pub total_docs: usize,
     /// Document frequency: (field, term) -> count
     pub term_df: HashMap<(F, String), usize>,
+    /// doc_id -> last-updated unix time, for recency boosting
+    #[serde(default)]
+    pub timestamps: HashMap<DocId, i64>,
+    /// Per-term maxima for score upper bounds: (field, term) -> stats
+    #[serde(default)]
+    pub term_stats: HashMap<(F, String), TermStats>,
+    /// doc_id -> hash of its `collapse_fields` values, for result deduplication
+    #[serde(default)]
+    pub collapse_keys: HashMap<DocId, u64>,
+    /// field -> docs with no token in that field, for presence clauses
+    #[serde(default)]
+    pub empty_fields: HashMap<F, RoaringBitmap>,
+    /// field -> how many documents hold each token count, for length percentiles
+    #[serde(default)]
+    pub length_histograms: HashMap<F, LengthHistogram>,
+    /// Where spilled dfs and lengths live. Entries in `term_df` and `lengths`
+    /// take precedence over it. Not serialized: re-attach after loading.
+    #[serde(skip)]
+    pub store: Option<Arc<dyn MetadataStore<F>>>,
+    /// Persisted length columns, read instead of `store` for lengths missing
+    /// from `lengths`. Not serialized either.
+    #[serde(skip)]
+    pub length_columns: Option<Arc<dyn DocLengths<F>>>,
+}
+
+/// Largest term frequency of a term across its postings, and the largest
+/// field-weighted, length-normalized tf (the BM25F pseudo-frequency before
+/// `k1` saturation). Maintained incrementally while indexing against the
+/// average lengths of the moment; `SearchEngine::recompute_term_stats` makes
+/// them exact again.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct TermStats {
+    pub max_tf: u32,
+    pub max_weighted_tf: f32,
+}
+
+impl TermStats {
+    pub fn observe(&mut self, tf: u32, weighted_tf: f32) {
+        self.max_tf = self.max_tf.max(tf);
+        self.max_weighted_tf = self.max_weighted_tf.max(weighted_tf);
+    }
+}
+
+/// Documents per token count of one field. Documents with no token in the
+/// field are left out, so sparse fields aren't summarized by their zeros.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LengthHistogram {
+    counts: BTreeMap<usize, usize>,
+    docs: usize,
+}
+
+impl LengthHistogram {
+    pub fn add(&mut self, length: usize) {
+        if length > 0 {
+            *self.counts.entry(length).or_insert(0) += 1;
+            self.docs += 1;
+        }
+    }
+
+    pub fn remove(&mut self, length: usize) {
+        let Some(count) = self.counts.get_mut(&length) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.counts.remove(&length);
+        }
+        self.docs -= 1;
+    }
+
+    /// Adds the counts of `other`, e.g. of another index being merged in.
+    pub fn merge(&mut self, other: &LengthHistogram) {
+        for (&length, &count) in &other.counts {
+            *self.counts.entry(length).or_insert(0) += count;
+        }
+        self.docs += other.docs;
+    }
+
+    /// Documents counted, i.e. with at least one token in the field.
+    pub fn docs(&self) -> usize {
+        self.docs
+    }
+
+    /// Smallest length at least a fraction `p` of the documents don't
+    /// exceed, None when no document is counted.
+    pub fn percentile(&self, p: f32) -> Option<usize> {
+        let rank = ((p.clamp(0.0, 1.0) * self.docs as f32).ceil() as usize).max(1);
+        let mut seen = 0;
+        for (&length, &count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                return Some(length);
+            }
+        }
+        None
+    }
+
+    pub fn stats(&self) -> Option<LengthStats> {
+        let max = *self.counts.keys().next_back()?;
+        let total: usize = self
+            .counts
+            .iter()
+            .map(|(length, count)| length * count)
+            .sum();
+        Some(LengthStats {
+            docs: self.docs,
+            mean: total as f32 / self.docs as f32,
+            p50: self.percentile(0.5)?,
+            p90: self.percentile(0.9)?,
+            p99: self.percentile(0.99)?,
+            max,
+        })
+    }
+}
+
+/// Summary of a [`LengthHistogram`], in tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct LengthStats {
+    pub docs: usize,
+    pub mean: f32,
+    pub p50: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize,
 }
 
 impl<F> FieldMetadata<F>
@@ -30,11 +182,170 @@ where
             total_field_lengths: HashMap::new(),
             total_docs: 0,
             term_df: HashMap::new(),
+            timestamps: HashMap::new(),
+            term_stats: HashMap::new(),
+            collapse_keys: HashMap::new(),
+            empty_fields: HashMap::new(),
+            length_histograms: HashMap::new(),
+            store: None,
+            length_columns: None,
         }
     }
 
     pub fn get_df(&self, field: &F, term: &str) -> usize {
-        self.term_df.get(&(field.clone(), term.to_string())).cloned().unwrap_or(0)
+        let key = (field.clone(), term.to_string());
+        match (self.term_df.get(&key), &self.store) {
+            (Some(&df), _) => df,
+            (None, Some(store)) => store
+                .get_df(field, term)
+                .unwrap_or_else(|e| {
+                    warn!("[METADATA] df lookup failed: {}", e);
+                    None
+                })
+                .unwrap_or(0),
+            (None, None) => 0,
+        }
+    }
+
+    /// Mutable df of (field, term), seeded from the store on first access.
+    pub fn df_entry(&mut self, field: F, term: String) -> &mut usize {
+        let store = &self.store;
+        self.term_df
+            .entry((field, term))
+            .or_insert_with_key(|(field, term)| match store {
+                Some(store) => store.get_df(field, term).ok().flatten().unwrap_or(0),
+                None => 0,
+            })
+    }
+
+    /// Records whether `doc_id` holds no token in `field`.
+    pub fn set_field_empty(&mut self, doc_id: DocId, field: &F, empty: bool) {
+        if empty {
+            self.empty_fields
+                .entry(field.clone())
+                .or_default()
+                .insert(doc_id.get());
+        } else if let Some(docs) = self.empty_fields.get_mut(field) {
+            docs.remove(doc_id.get());
+        }
+    }
+
+    /// Moves a document's `field` from `old` tokens to `new` in the length
+    /// histogram; 0 stands for a missing field.
+    pub fn record_length(&mut self, field: &F, old: usize, new: usize) {
+        if old == new {
+            return;
+        }
+        let histogram = self.length_histograms.entry(field.clone()).or_default();
+        histogram.remove(old);
+        histogram.add(new);
+    }
+
+    /// Folds the metadata of another index into this one, its doc ids moved
+    /// up by `offset`: dfs, field totals and document counts are summed,
+    /// per-document entries and histograms carried over. Term stats keep
+    /// the larger maxima; `SearchEngine::recompute_term_stats` makes them
+    /// exact against the combined lengths. Every shifted id must fit a
+    /// [`DocId`].
+    pub fn append(&mut self, other: FieldMetadata<F>, offset: u32) {
+        let shift = |doc_id: DocId| DocId::new(doc_id.get() + offset);
+
+        for (doc_id, lengths) in other.lengths {
+            self.lengths.insert(shift(doc_id), lengths);
+        }
+        for (field, total) in other.total_field_lengths {
+            *self.total_field_lengths.entry(field).or_insert(0) += total;
+        }
+        self.total_docs += other.total_docs;
+        for (key, df) in other.term_df {
+            *self.term_df.entry(key).or_insert(0) += df;
+        }
+        for (doc_id, timestamp) in other.timestamps {
+            self.timestamps.insert(shift(doc_id), timestamp);
+        }
+        for (key, stats) in other.term_stats {
+            let merged = self.term_stats.entry(key).or_default();
+            merged.observe(stats.max_tf, stats.max_weighted_tf);
+        }
+        for (doc_id, key) in other.collapse_keys {
+            self.collapse_keys.insert(shift(doc_id), key);
+        }
+        for (field, docs) in other.empty_fields {
+            self.empty_fields
+                .entry(field)
+                .or_default()
+                .extend(docs.iter().map(|doc_id| doc_id + offset));
+        }
+        for (field, histogram) in &other.length_histograms {
+            self.length_histograms
+                .entry(field.clone())
+                .or_default()
+                .merge(histogram);
+        }
+    }
+
+    /// Length at percentile `p` (a fraction) of the documents holding
+    /// `field`, None when none does.
+    pub fn length_percentile(&self, field: &F, p: f32) -> Option<usize> {
+        self.length_histograms.get(field)?.percentile(p)
+    }
+
+    /// Length percentiles of every field with a non-empty histogram.
+    pub fn length_stats(&self) -> HashMap<F, LengthStats> {
+        self.length_histograms
+            .iter()
+            .filter_map(|(field, histogram)| Some((field.clone(), histogram.stats()?)))
+            .collect()
+    }
+
+    /// Token count of `field` in `doc_id`, 0 if unknown.
+    pub fn doc_length(&self, doc_id: DocId, field: &F) -> usize {
+        if let Some(&length) = self
+            .lengths
+            .get(&doc_id)
+            .and_then(|fields| fields.get(field))
+        {
+            return length;
+        }
+        let persisted = match (&self.length_columns, &self.store) {
+            (Some(columns), _) => columns.doc_length(doc_id, field),
+            (None, Some(store)) => store.doc_length(doc_id, field),
+            (None, None) => return 0,
+        };
+        persisted
+            .unwrap_or_else(|e| {
+                warn!("[METADATA] Length lookup failed: {}", e);
+                None
+            })
+            .unwrap_or(0)
+    }
+
+    /// Moves every in-memory df and document length into `store` and keeps
+    /// reading them from there. Lookups go through the store from then on, so
+    /// anything iterating `term_df` (spelling, warming) only sees terms
+    /// touched since the spill. Spilling into the same store again after
+    /// deserializing metadata re-attaches it.
+    pub fn spill_to(&mut self, store: Arc<dyn MetadataStore<F>>) -> Result<(), LfasError> {
+        store.put_dfs(self.term_df.drain().collect())?;
+        store.put_lengths(self.lengths.drain().collect())?;
+        self.term_df.shrink_to_fit();
+        self.lengths.shrink_to_fit();
+        self.store = Some(store);
+        Ok(())
+    }
+
+    pub fn get_term_stats(&self, field: &F, term: &str) -> Option<TermStats> {
+        self.term_stats
+            .get(&(field.clone(), term.to_string()))
+            .copied()
+    }
+
+    /// Average length of `field`, 1.0 for an empty index.
+    pub fn avg_field_length(&self, field: &F) -> f32 {
+        match (self.total_field_lengths.get(field), self.total_docs) {
+            (Some(&total), docs) if docs > 0 => total as f32 / docs as f32,
+            _ => 1.0,
+        }
     }
 }
 
@@ -0,0 +1,283 @@
+//! In-process metrics: query latency, candidate set sizes, postings lookups
+//! and indexing throughput, read through [`MetricsRegistry::snapshot`].
+
+use serde::Serialize;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds (inclusive) of the query latency buckets, in milliseconds.
+pub const LATENCY_BUCKETS_MS: &[f64] = &[
+    1.0, 2.5, 5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0,
+];
+
+/// Upper bounds (inclusive) of the candidate set size buckets.
+pub const CANDIDATE_BUCKETS: &[f64] = &[10.0, 100.0, 1_000.0, 10_000.0, 100_000.0, 1_000_000.0];
+
+/// Fixed-bucket histogram; `counts[i]` holds observations in `(bounds[i-1], bounds[i]]`
+/// and the last slot everything above the highest bound.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    pub bounds: Vec<f64>,
+    pub counts: Vec<u64>,
+    pub sum: f64,
+    pub count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &[f64]) -> Self {
+        Self {
+            bounds: bounds.to_vec(),
+            counts: vec![0; bounds.len() + 1],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self.bounds.partition_point(|bound| *bound < value);
+        self.counts[bucket] += 1;
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            0.0
+        } else {
+            self.sum / self.count as f64
+        }
+    }
+
+    /// Upper bound of the bucket holding the `q` quantile (`f64::INFINITY`
+    /// past the last bound, 0 when empty).
+    pub fn quantile(&self, q: f64) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let rank = (q.clamp(0.0, 1.0) * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return self.bounds.get(i).copied().unwrap_or(f64::INFINITY);
+            }
+        }
+        f64::INFINITY
+    }
+
+    /// Cumulative `(le, count)` pairs, Prometheus style, ending with `+Inf`.
+    pub fn cumulative(&self) -> Vec<(f64, u64)> {
+        let mut total = 0;
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                total += count;
+                (self.bounds.get(i).copied().unwrap_or(f64::INFINITY), total)
+            })
+            .collect()
+    }
+}
+
+/// Point-in-time copy of every metric.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MetricsSnapshot {
+    pub queries_total: u64,
+    /// Queries stopped by their deadline or cancel token.
+    pub queries_interrupted: u64,
+    /// Searches turned away by load shedding.
+    pub queries_rejected: u64,
+    pub query_latency_ms: Histogram,
+    /// Time admitted searches waited for a concurrency slot.
+    pub queue_wait_ms: Histogram,
+    pub candidate_set_size: Histogram,
+    /// Round-1 postings lookups that found (hits) or missed (misses) a term.
+    pub postings_hits: u64,
+    pub postings_misses: u64,
+    pub docs_indexed: u64,
+    pub index_batches: u64,
+    pub index_seconds: f64,
+}
+
+impl Default for MetricsSnapshot {
+    fn default() -> Self {
+        Self {
+            queries_total: 0,
+            queries_interrupted: 0,
+            queries_rejected: 0,
+            query_latency_ms: Histogram::new(LATENCY_BUCKETS_MS),
+            queue_wait_ms: Histogram::new(LATENCY_BUCKETS_MS),
+            candidate_set_size: Histogram::new(CANDIDATE_BUCKETS),
+            postings_hits: 0,
+            postings_misses: 0,
+            docs_indexed: 0,
+            index_batches: 0,
+            index_seconds: 0.0,
+        }
+    }
+}
+
+impl MetricsSnapshot {
+    /// Share of postings lookups that found the term; 0 before any lookup.
+    pub fn postings_hit_rate(&self) -> f64 {
+        let lookups = self.postings_hits + self.postings_misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.postings_hits as f64 / lookups as f64
+        }
+    }
+
+    /// Documents indexed per second of time spent in `index_tokenized`.
+    pub fn index_docs_per_sec(&self) -> f64 {
+        if self.index_seconds > 0.0 {
+            self.docs_indexed as f64 / self.index_seconds
+        } else {
+            0.0
+        }
+    }
+
+    /// Prometheus text exposition format, metric names prefixed with `lfas_`.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("queries_total", "Searches executed.", self.queries_total),
+            (
+                "queries_interrupted_total",
+                "Searches cut short by a deadline or cancellation.",
+                self.queries_interrupted,
+            ),
+            (
+                "queries_rejected_total",
+                "Searches rejected by load shedding.",
+                self.queries_rejected,
+            ),
+            (
+                "postings_hits_total",
+                "Postings lookups that found the term.",
+                self.postings_hits,
+            ),
+            (
+                "postings_misses_total",
+                "Postings lookups for absent terms.",
+                self.postings_misses,
+            ),
+            (
+                "docs_indexed_total",
+                "Documents indexed.",
+                self.docs_indexed,
+            ),
+            (
+                "index_batches_total",
+                "Index batches written.",
+                self.index_batches,
+            ),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP lfas_{} {}", name, help);
+            let _ = writeln!(out, "# TYPE lfas_{} counter", name);
+            let _ = writeln!(out, "lfas_{} {}", name, value);
+        }
+
+        let _ = writeln!(out, "# HELP lfas_index_seconds_total Time spent indexing.");
+        let _ = writeln!(out, "# TYPE lfas_index_seconds_total counter");
+        let _ = writeln!(out, "lfas_index_seconds_total {}", self.index_seconds);
+
+        write_histogram(
+            &mut out,
+            "query_latency_ms",
+            "Search latency in milliseconds.",
+            &self.query_latency_ms,
+        );
+        write_histogram(
+            &mut out,
+            "queue_wait_ms",
+            "Time searches waited for a concurrency slot, in milliseconds.",
+            &self.queue_wait_ms,
+        );
+        write_histogram(
+            &mut out,
+            "candidate_set_size",
+            "Round-1 candidates per search.",
+            &self.candidate_set_size,
+        );
+        out
+    }
+}
+
+fn write_histogram(out: &mut String, name: &str, help: &str, histogram: &Histogram) {
+    let _ = writeln!(out, "# HELP lfas_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE lfas_{} histogram", name);
+    for (le, count) in histogram.cumulative() {
+        let le = if le.is_infinite() {
+            "+Inf".to_string()
+        } else {
+            le.to_string()
+        };
+        let _ = writeln!(out, "lfas_{}_bucket{{le=\"{}\"}} {}", name, le, count);
+    }
+    let _ = writeln!(out, "lfas_{}_sum {}", name, histogram.sum);
+    let _ = writeln!(out, "lfas_{}_count {}", name, histogram.count);
+}
+
+/// Thread-safe collector owned by the engine; searches record through `&self`.
+#[derive(Debug, Default)]
+pub struct MetricsRegistry {
+    inner: Mutex<MetricsSnapshot>,
+}
+
+impl MetricsRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update(&self, f: impl FnOnce(&mut MetricsSnapshot)) {
+        // A poisoned lock only means a panic mid-update; metrics stay usable
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut inner);
+    }
+
+    pub fn record_query(&self, latency: Duration, interrupted: bool) {
+        self.update(|m| {
+            m.queries_total += 1;
+            m.queries_interrupted += interrupted as u64;
+            m.query_latency_ms.observe(latency.as_secs_f64() * 1000.0);
+        });
+    }
+
+    pub fn record_queue_wait(&self, waited: Duration) {
+        self.update(|m| m.queue_wait_ms.observe(waited.as_secs_f64() * 1000.0));
+    }
+
+    pub fn record_rejection(&self) {
+        self.update(|m| m.queries_rejected += 1);
+    }
+
+    pub fn record_candidates(&self, candidates: u64) {
+        self.update(|m| m.candidate_set_size.observe(candidates as f64));
+    }
+
+    pub fn record_postings_lookups(&self, hits: u64, misses: u64) {
+        self.update(|m| {
+            m.postings_hits += hits;
+            m.postings_misses += misses;
+        });
+    }
+
+    pub fn record_index_batch(&self, docs: u64, elapsed: Duration) {
+        self.update(|m| {
+            m.docs_indexed += docs;
+            m.index_batches += 1;
+            m.index_seconds += elapsed.as_secs_f64();
+        });
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    pub fn reset(&self) {
+        self.update(|m| *m = MetricsSnapshot::default());
+    }
+}
@@ -1,51 +1,168 @@
 use crate::DocId;
 use roaring::RoaringBitmap;
+use roaring::bitmap::{IntoIter, Iter};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
 
+/// Below one shared document in this many postings, [`Intersection`] finds
+/// each tf by rank instead of walking the postings.
+const GALLOP_RATIO: u64 = 16;
+
+/// A term's documents and their term frequencies.
+///
+/// Every iterator over a `Postings` yields documents in ascending doc id
+/// order, the order [`Self::frequencies`] is stored in.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Postings {
     bitmap: RoaringBitmap,
-    /// Term Frequency: How many times a term appears in a specific document.
-    frequencies: HashMap<DocId, u32>,
+    /// Term Frequency: How many times a term appears in a specific document,
+    /// aligned with the bitmap's ascending iteration order.
+    frequencies: Vec<u32>,
 }
 
 impl Postings {
     pub fn new() -> Self {
         Self {
             bitmap: RoaringBitmap::new(),
-            frequencies: HashMap::new(),
+            frequencies: Vec::new(),
+        }
+    }
+
+    /// Builds postings from `(doc_id, tf)` pairs, fastest when sorted by doc id.
+    pub fn from_sorted(entries: impl IntoIterator<Item = (DocId, u32)>) -> Self {
+        let mut postings = Self::new();
+        for (doc_id, tf) in entries {
+            if postings.bitmap.try_push(doc_id.get()).is_ok() {
+                postings.frequencies.push(tf);
+            } else {
+                postings.add_occurrences(doc_id, tf);
+            }
         }
+        postings
+    }
+
+    /// Slot of `doc_id` in `frequencies`, if the document is present.
+    #[inline]
+    fn position(&self, doc_id: DocId) -> Option<usize> {
+        let doc_id = doc_id.get();
+        self.bitmap
+            .contains(doc_id)
+            .then(|| self.bitmap.rank(doc_id) as usize - 1)
     }
 
     /// Records an occurrence of a term in a document.
     pub fn add_occurrence(&mut self, doc_id: DocId) {
-        self.bitmap.insert(doc_id as u32);
-        *self.frequencies.entry(doc_id).or_insert(0) += 1;
+        self.add_occurrences(doc_id, 1);
+    }
+
+    /// Records `tf` occurrences of a term in a document.
+    pub fn add_occurrences(&mut self, doc_id: DocId, tf: u32) {
+        match self.position(doc_id) {
+            Some(pos) => self.frequencies[pos] += tf,
+            None => {
+                self.bitmap.insert(doc_id.get());
+                // Usually an append, doc ids are mostly indexed in order
+                let pos = self.bitmap.rank(doc_id.get()) as usize - 1;
+                self.frequencies.insert(pos, tf);
+            }
+        }
+    }
+
+    /// Drops `doc_id`, returning its term frequency (0 when absent).
+    pub fn remove(&mut self, doc_id: DocId) -> u32 {
+        let Some(pos) = self.position(doc_id) else {
+            return 0;
+        };
+        self.bitmap.remove(doc_id.get());
+        self.frequencies.remove(pos)
     }
 
     /// Merges another Postings list into this one (useful for parallel indexing).
     pub fn merge(&mut self, other: Postings) {
-        self.bitmap |= other.bitmap;
-        for (doc_id, count) in other.frequencies {
-            *self.frequencies.entry(doc_id).or_insert(0) += count;
+        let mut merged = Vec::with_capacity(self.frequencies.len() + other.frequencies.len());
+        let mut ours = self.iter().peekable();
+        let mut theirs = other.iter().peekable();
+
+        loop {
+            let next = match (ours.peek(), theirs.peek()) {
+                (Some(&(a, tf_a)), Some(&(b, tf_b))) => match a.cmp(&b) {
+                    std::cmp::Ordering::Less => ours.next().map(|_| tf_a),
+                    std::cmp::Ordering::Greater => theirs.next().map(|_| tf_b),
+                    std::cmp::Ordering::Equal => {
+                        ours.next();
+                        theirs.next();
+                        Some(tf_a + tf_b)
+                    }
+                },
+                (Some(_), None) => ours.next().map(|(_, tf)| tf),
+                (None, Some(_)) => theirs.next().map(|(_, tf)| tf),
+                (None, None) => None,
+            };
+            match next {
+                Some(tf) => merged.push(tf),
+                None => break,
+            }
         }
+        drop((ours, theirs));
+
+        self.bitmap |= other.bitmap;
+        self.frequencies = merged;
     }
 
     pub fn bitmap(&self) -> &RoaringBitmap {
         &self.bitmap
     }
 
+    /// Term frequency of `doc_id`, 0 when absent. Ranks the bitmap, no hashing.
+    #[inline]
+    pub fn tf(&self, doc_id: DocId) -> u32 {
+        self.position(doc_id).map_or(0, |pos| self.frequencies[pos])
+    }
+
     pub fn term_frequency(&self, doc_id: DocId) -> u32 {
-        *self.frequencies.get(&doc_id).unwrap_or(&0)
+        self.tf(doc_id)
     }
 
-    pub fn frequencies(&self) -> &HashMap<DocId, u32> {
+    /// Frequencies in ascending doc id order, parallel to [`Self::bitmap`].
+    pub fn frequencies(&self) -> &[u32] {
         &self.frequencies
     }
 
+    /// `(doc_id, tf)` pairs in ascending doc id order.
+    pub fn iter(&self) -> impl Iterator<Item = (DocId, u32)> + '_ {
+        self.bitmap
+            .iter()
+            .map(DocId::new)
+            .zip(self.frequencies.iter().copied())
+    }
+
+    /// `(doc_id, tf)` pairs for the documents also in `docs`, in ascending
+    /// doc id order, with no per-document `contains` check.
+    ///
+    /// The bitmaps are intersected container by container first, which costs
+    /// O(containers) with word-parallel ops and never visits a document
+    /// outside the intersection. Each tf is then found one of two ways:
+    /// - when the intersection holds at least 1/16 of the postings, by one
+    ///   pass over the postings in step with it: O(len) for the whole walk;
+    /// - otherwise by galloping to each shared document with a rank query:
+    ///   O(log len) per document.
+    pub fn intersect_iter(&self, docs: &RoaringBitmap) -> Intersection<'_> {
+        let shared = &self.bitmap & docs;
+        let walk = (shared.len() * GALLOP_RATIO >= self.bitmap.len()).then(|| self.bitmap.iter());
+        Intersection {
+            shared: shared.into_iter(),
+            walk,
+            pos: 0,
+            postings: self,
+        }
+    }
+
+    /// Same as [`Self::intersect_iter`].
+    pub fn iter_within(&self, docs: &RoaringBitmap) -> Intersection<'_> {
+        self.intersect_iter(docs)
+    }
+
     pub fn contains(&self, doc_id: DocId) -> bool {
-        self.bitmap.contains(doc_id as u32)
+        self.bitmap.contains(doc_id.get())
     }
 
     pub fn len(&self) -> usize {
@@ -57,6 +174,38 @@ impl Postings {
     }
 }
 
+/// Iterator returned by [`Postings::intersect_iter`].
+pub struct Intersection<'a> {
+    shared: IntoIter,
+    /// Postings walked in step with `shared`; `None` when galloping
+    walk: Option<Iter<'a>>,
+    /// Slot in `frequencies` of the next document `walk` yields
+    pos: usize,
+    postings: &'a Postings,
+}
+
+impl Iterator for Intersection<'_> {
+    type Item = (DocId, u32);
+
+    fn next(&mut self) -> Option<(DocId, u32)> {
+        let doc_id = self.shared.next()?;
+        let pos = match &mut self.walk {
+            Some(walk) => {
+                // Shared docs are in the postings, so the walk always finds it
+                let skipped = walk.position(|posted| posted == doc_id)?;
+                self.pos += skipped + 1;
+                self.pos - 1
+            }
+            None => self.postings.bitmap.rank(doc_id) as usize - 1,
+        };
+        Some((DocId::new(doc_id), self.postings.frequencies[pos]))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.shared.size_hint()
+    }
+}
+
 impl Default for Postings {
     fn default() -> Self {
         Self::new()
@@ -0,0 +1,72 @@
+//! Build provenance persisted alongside the postings, so a deployed index can
+//! be traced back to the dataset and settings that produced it.
+
+use crate::shard::{STABLE_HASH_SEED, stable_hash_extend};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A file the index was built from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SourceFile {
+    pub path: String,
+    pub bytes: u64,
+    /// FNV-1a over the file contents.
+    pub hash: u64,
+}
+
+impl SourceFile {
+    /// Reads `path` once to size and hash it.
+    pub fn from_path(path: &Path) -> io::Result<Self> {
+        let mut file = File::open(path)?;
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut hash = STABLE_HASH_SEED;
+        let mut bytes = 0u64;
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hash = stable_hash_extend(hash, &buf[..read]);
+            bytes += read as u64;
+        }
+
+        Ok(Self {
+            path: path.to_string_lossy().into_owned(),
+            bytes,
+            hash,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Provenance {
+    /// Every source recorded so far, across incremental builds.
+    pub sources: Vec<SourceFile>,
+    /// Documents in the index when the provenance was saved.
+    pub record_count: usize,
+    /// Seconds since the Unix epoch.
+    pub built_at: u64,
+    pub crate_version: String,
+    /// Hash of the tokenizer settings and per-field token rules.
+    pub tokenizer_hash: u64,
+}
+
+impl Provenance {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
+        bincode::serialize(self)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
@@ -1,26 +1,700 @@
-use crate::engine;
-use crate::storage::PostingsStorage;
+use crate::admission::ConcurrencyLimits;
+use crate::builder::{BuildProgress, DEFAULT_PROGRESS_INTERVAL, ProgressReporter};
+use crate::cancel::CancelToken;
+use crate::candidates::{
+    CandidateGenerator, DistinctiveUnion, FieldIntersection, NgramOverlap, RareTokens,
+};
+use crate::compare::{ComparisonEngine, ComparisonReport, DEFAULT_DIVERGENCE_TAU};
+use crate::config::ConfigReload;
+use crate::cooccurrence::QueryExpansion;
+use crate::decompound::{DEFAULT_MAX_PARTS, DEFAULT_MIN_PART_LEN, Decompounder};
+use crate::engine::{
+    self, BlockingStrategy, CommonTerms, FallbackPolicy, HitIter, MatchDecision, QueryPreset,
+    Searcher, TokenizedDoc,
+};
+use crate::engine_builder::EngineBuilder;
+use crate::error::LfasError;
+use crate::provenance::Provenance;
+use crate::score_cache::{DEFAULT_SCORE_CACHE_CAPACITY, ScoreCache};
+use crate::scorer::{FieldGroup, RecencyDecay, TfOptions};
+use crate::shard::{ShardKey, ShardedEngine};
+use crate::similarity::{self, SimilarityMetric, SimilarityReranker};
+use crate::slowlog::{DEFAULT_SLOW_QUERY_CAPACITY, SlowQuery, SlowQueryLog};
+use crate::spelling::{self, SpellIndex};
+use crate::storage::{
+    DEFAULT_METADATA_CACHE, LmdbMetadataStore, LmdbOptions, PostingsStorage, SyncMode,
+};
 use crate::timing::Timer;
-use crate::tokenizer::tokenize;
-use crate::{RecordField, StructuredQuery, engine::SearchEngine, storage::LmdbStorage};
+use crate::tokenizer::{
+    CompositePattern, Diacritics, FieldTokenRules, Locale, NgramMode, Synonyms, TermPolicy,
+    TokenFilter, TokenTrace, TokenizerConfig, tokenize_debug,
+};
+use crate::validation::{RecordValidator, ValidationPolicy, ValidationReport};
+use crate::vectors::{
+    DEFAULT_RERANK_DEPTH, DEFAULT_VECTOR_ALPHA, QueryVector, VectorReranker, VectorStore,
+};
+use crate::verbosity::{self, Verbosity, query_info};
+use crate::{
+    DocId, MinShouldMatch, QueryDiagnostics, Record, RecordField, SearchHit, StructuredQuery,
+    engine::SearchEngine,
+    storage::LmdbStorage,
+};
 use bincode::{deserialize_from, serialize_into};
-use log::{debug, info};
-use once_cell::sync::Lazy;
+use numpy::{IntoPyArray, PyArray1, PyReadonlyArray2};
+use roaring::RoaringBitmap;
+use pyo3::exceptions::{PyIOError, PyRuntimeError, PyValueError};
 use pyo3::prelude::*;
+use pyo3::types::{PyBytes, PyDict, PyInt, PyList};
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufReader, BufWriter};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
+use tracing::{debug, info, warn};
+
+type Engine = SearchEngine<RecordField, LmdbStorage<RecordField>>;
+
+pyo3::create_exception!(
+    lfas,
+    OverloadedError,
+    PyRuntimeError,
+    "Search rejected by load shedding; retry later."
+);
+
+impl From<LfasError> for PyErr {
+    fn from(e: LfasError) -> Self {
+        match e {
+            LfasError::Storage(_) => PyIOError::new_err(e.to_string()),
+            LfasError::Serialization(_)
+            | LfasError::Schema(_)
+            | LfasError::InvalidQuery(_)
+            | LfasError::DocIdOverflow(_) => PyValueError::new_err(e.to_string()),
+            LfasError::Overloaded(_) => OverloadedError::new_err(e.to_string()),
+            LfasError::LockPoisoned | LfasError::NotInitialized => {
+                PyRuntimeError::new_err(e.to_string())
+            }
+        }
+    }
+}
+
+/// Doc ids passed from Python are range-checked on extraction, so a too
+/// large id raises ValueError before anything is indexed.
+impl<'py> FromPyObject<'py> for DocId {
+    fn extract_bound(ob: &Bound<'py, PyAny>) -> PyResult<Self> {
+        Ok(DocId::try_from(ob.extract::<u64>()?)?)
+    }
+}
+
+impl<'py> IntoPyObject<'py> for DocId {
+    type Target = PyInt;
+    type Output = Bound<'py, PyInt>;
+    type Error = std::convert::Infallible;
+
+    fn into_pyobject(self, py: Python<'py>) -> Result<Self::Output, Self::Error> {
+        self.get().into_pyobject(py)
+    }
+}
+
+fn parse_term_policy(name: &str) -> PyResult<TermPolicy> {
+    match name.to_lowercase().as_str() {
+        "drop" => Ok(TermPolicy::Drop),
+        "keep" => Ok(TermPolicy::Keep),
+        "demote" => Ok(TermPolicy::Demote),
+        other => Err(PyValueError::new_err(format!("Unknown term policy: {}", other))),
+    }
+}
+
+/// `min_should_match` as given from Python: an int count or a string like "75%".
+#[derive(FromPyObject)]
+enum MinShouldMatchArg {
+    Count(usize),
+    Spec(String),
+}
 
-// Use RwLock for concurrent reads (searches)
-static GLOBAL_ENGINE: Lazy<
-    Arc<RwLock<Option<SearchEngine<RecordField, LmdbStorage<RecordField>>>>>,
-> = Lazy::new(|| Arc::new(RwLock::new(None)));
+impl MinShouldMatchArg {
+    fn parse(self) -> PyResult<MinShouldMatch> {
+        match self {
+            MinShouldMatchArg::Count(count) => Ok(MinShouldMatch::Count(count)),
+            MinShouldMatchArg::Spec(spec) => MinShouldMatch::parse(&spec).ok_or_else(|| {
+                PyValueError::new_err(format!("Invalid min_should_match: {}", spec))
+            }),
+        }
+    }
+}
+
+fn parse_preset(name: &str) -> PyResult<QueryPreset> {
+    QueryPreset::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unknown preset '{}', expected strict, balanced or fuzzy",
+            name
+        ))
+    })
+}
+
+fn parse_verbosity(name: &str) -> PyResult<Verbosity> {
+    Verbosity::from_name(name).ok_or_else(|| {
+        PyValueError::new_err(format!(
+            "Unknown verbosity '{}', expected silent, summary or debug",
+            name
+        ))
+    })
+}
+
+fn parse_locale(name: &str) -> PyResult<Locale> {
+    Locale::from_name(name)
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown locale: {}", name)))
+}
+
+/// N-gram tokenizer config without composite patterns.
+fn ngram_tokenizer(mode: &str, n: usize, stride: usize, locale: &str) -> PyResult<TokenizerConfig> {
+    let locale = parse_locale(locale)?;
+    let ngram_mode = match mode.to_lowercase().as_str() {
+        "chunked" => NgramMode::Chunked,
+        "sliding" => NgramMode::Sliding,
+        other => {
+            return Err(PyValueError::new_err(format!("Unknown n-gram mode: {}", other)));
+        }
+    };
+    Ok(TokenizerConfig {
+        ngram_mode,
+        ngram_n: n,
+        ngram_stride: stride,
+        locale,
+        composites: Vec::new(),
+    })
+}
+
+fn field_values(values: HashMap<String, f32>) -> PyResult<Vec<(RecordField, f32)>> {
+    values
+        .into_iter()
+        .map(|(name, value)| {
+            RecordField::from_name(&name)
+                .map(|field| (field, value))
+                .ok_or_else(|| PyValueError::new_err(format!("Unknown field '{}'", name)))
+        })
+        .collect()
+}
 
 #[pyclass]
 pub struct PySearchEngine {
+    // RwLock for concurrent reads (searches)
+    engine: RwLock<Engine>,
+    /// Serializes `index_batch` and `index_dict`. `index_batch` writes
+    /// postings under the engine's read lock, so two indexers must not
+    /// interleave.
+    writer: Mutex<()>,
     custom_weights: Option<HashMap<RecordField, f32>>,
     custom_b_values: Option<HashMap<RecordField, f32>>,
+    custom_tf_options: Option<HashMap<RecordField, TfOptions>>,
+    validator: Option<RecordValidator>,
+}
+
+/// A record dict value: one string, or a list of alternative values.
+#[derive(FromPyObject)]
+enum FieldValue {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl FieldValue {
+    fn into_values(self) -> Vec<String> {
+        match self {
+            FieldValue::One(value) => vec![value],
+            FieldValue::Many(values) => values,
+        }
+    }
+}
+
+/// The first value of a list becomes the field, the rest its aliases.
+fn record_from_dict(record_dict: HashMap<String, FieldValue>) -> Record {
+    let mut record = Record::default();
+
+    for (key, value) in record_dict {
+        let mut values = value.into_values().into_iter();
+        let primary = values.next().unwrap_or_default();
+
+        if key == "id" {
+            record.id = primary;
+        } else if let Some(field) = RecordField::from_name(&key) {
+            *record.field_mut(field) = primary;
+            record.aliases.extend(values.map(|value| (field, value)));
+        }
+    }
+    record
+}
+
+/// Splits a Python query dict into field clauses and the optional "id" key.
+fn parse_query_dict(
+    query_dict: HashMap<String, String>,
+) -> (Vec<(RecordField, String)>, Option<String>) {
+    let mut query_fields = Vec::new();
+    let mut external_id = None;
+
+    for (key, text) in query_dict {
+        if text.trim().is_empty() {
+            continue;
+        }
+
+        if key == "id" {
+            external_id = Some(text);
+            continue;
+        }
+
+        info!("[RUST] Processing field: {} = '{}'", key, text);
+        let field = match RecordField::from_name(&key) {
+            Some(f) => f,
+            None => continue,
+        };
+        query_fields.push((field, text));
+    }
+
+    (query_fields, external_id)
+}
+
+/// Python name of a field given in its `Debug` form, as engine reports carry it.
+fn field_name(debug_name: &str) -> String {
+    RecordField::ALL
+        .into_iter()
+        .find(|field| format!("{:?}", field) == debug_name)
+        .map_or_else(|| debug_name.to_lowercase(), |field| field.name().to_string())
+}
+
+/// Diagnostics of one search as a dict: "candidates", "round1_tokens" as
+/// (field, token) pairs, "fallback", "exact", "cep_shortcut" and per-stage
+/// timings in ms.
+fn diagnostics_dict<'py>(
+    py: Python<'py>,
+    diagnostics: &QueryDiagnostics,
+) -> PyResult<Bound<'py, PyDict>> {
+    let ms = |duration: std::time::Duration| duration.as_secs_f64() * 1000.0;
+    let dict = PyDict::new(py);
+    dict.set_item("candidates", diagnostics.candidates)?;
+    dict.set_item(
+        "round1_tokens",
+        diagnostics
+            .round1_tokens
+            .iter()
+            .map(|(field, token)| (field_name(field), token.clone()))
+            .collect::<Vec<_>>(),
+    )?;
+    dict.set_item("fallback", diagnostics.fallback)?;
+    dict.set_item("exact", diagnostics.exact)?;
+    dict.set_item("cep_shortcut", diagnostics.cep_shortcut)?;
+    dict.set_item("collapsed", diagnostics.collapsed)?;
+    dict.set_item("round1_ms", ms(diagnostics.round1))?;
+    dict.set_item("round2_ms", ms(diagnostics.round2))?;
+    dict.set_item("total_ms", ms(diagnostics.total))?;
+    Ok(dict)
+}
+
+/// A shadow-read comparison as a dict: "queries", "mean_kendall_tau",
+/// "top1_agreement", "divergent", "candidate_errors" and "divergences", one
+/// dict per kept divergent query with its "query" fields, the "control" and
+/// "candidate" doc ids in rank order, "kendall_tau" and "top1_agrees".
+fn comparison_dict<'py>(
+    py: Python<'py>,
+    report: &ComparisonReport<RecordField>,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("queries", report.queries)?;
+    dict.set_item("mean_kendall_tau", report.mean_kendall_tau)?;
+    dict.set_item("top1_agreement", report.top1_agreement)?;
+    dict.set_item("divergent", report.divergent)?;
+    dict.set_item("candidate_errors", report.candidate_errors)?;
+    let divergences = PyList::empty(py);
+    for divergence in &report.divergences {
+        let item = PyDict::new(py);
+        let query: HashMap<String, String> = divergence
+            .fields
+            .iter()
+            .map(|(field, text)| (field.name().to_string(), text.clone()))
+            .collect();
+        item.set_item("query", query)?;
+        item.set_item("control", divergence.control.clone())?;
+        item.set_item("candidate", divergence.candidate.clone())?;
+        item.set_item("kendall_tau", divergence.kendall_tau)?;
+        item.set_item("top1_agrees", divergence.top1_agrees)?;
+        divergences.append(item)?;
+    }
+    dict.set_item("divergences", divergences)?;
+    Ok(dict)
+}
+
+/// A build progress report as a dict: "docs", "tokens", "flushes",
+/// "elapsed_s" and "eta_s" (None without an expected total).
+fn build_progress_dict<'py>(
+    py: Python<'py>,
+    progress: &BuildProgress,
+) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    dict.set_item("docs", progress.docs)?;
+    dict.set_item("tokens", progress.tokens)?;
+    dict.set_item("flushes", progress.flushes)?;
+    dict.set_item("elapsed_s", progress.elapsed.as_secs_f64())?;
+    dict.set_item("eta_s", progress.eta.map(|eta| eta.as_secs_f64()))?;
+    Ok(dict)
+}
+
+/// Reporter calling the Python `callback` with [`build_progress_dict`]. The
+/// first error it raises is kept in `error` and later reports are skipped.
+fn python_progress<'a>(
+    py: Python<'a>,
+    callback: Option<Py<PyAny>>,
+    every: usize,
+    error: &'a mut Option<PyErr>,
+) -> Option<ProgressReporter<'a>> {
+    let callback = callback?;
+    Some(ProgressReporter::new(every, move |progress| {
+        if error.is_some() {
+            return;
+        }
+        let called = build_progress_dict(py, progress).and_then(|dict| callback.call1(py, (dict,)));
+        if let Err(e) = called {
+            *error = Some(e);
+        }
+    }))
+}
+
+/// Build provenance as a dict; "sources" is a list of dicts with "path",
+/// "bytes" and "hash".
+fn provenance_dict<'py>(py: Python<'py>, provenance: &Provenance) -> PyResult<Bound<'py, PyDict>> {
+    let sources = PyList::empty(py);
+    for source in &provenance.sources {
+        let entry = PyDict::new(py);
+        entry.set_item("path", &source.path)?;
+        entry.set_item("bytes", source.bytes)?;
+        entry.set_item("hash", source.hash)?;
+        sources.append(entry)?;
+    }
+
+    let dict = PyDict::new(py);
+    dict.set_item("sources", sources)?;
+    dict.set_item("record_count", provenance.record_count)?;
+    dict.set_item("built_at", provenance.built_at)?;
+    dict.set_item("crate_version", &provenance.crate_version)?;
+    dict.set_item("tokenizer_hash", provenance.tokenizer_hash)?;
+    Ok(dict)
+}
+
+/// Token traces as dicts with "token", "start", "end" (byte offsets into the
+/// text, None for restored tokens), "class" and "rule".
+fn slow_query_dict<'py>(py: Python<'py>, entry: &SlowQuery) -> PyResult<Bound<'py, PyDict>> {
+    let fields = PyList::empty(py);
+    for stats in &entry.fields {
+        let dict = PyDict::new(py);
+        dict.set_item("field", field_name(&stats.field))?;
+        dict.set_item("chars", stats.chars)?;
+        dict.set_item("tokens", stats.tokens)?;
+        dict.set_item("ngrams", stats.ngrams)?;
+        dict.set_item("max_df", stats.max_df)?;
+        fields.append(dict)?;
+    }
+
+    let dict = PyDict::new(py);
+    dict.set_item("finished_at", entry.finished_at)?;
+    dict.set_item("sampled", entry.sampled)?;
+    dict.set_item("total_ms", entry.total_ms)?;
+    dict.set_item("round1_ms", entry.round1_ms)?;
+    dict.set_item("round2_ms", entry.round2_ms)?;
+    dict.set_item("candidates", entry.candidates)?;
+    dict.set_item("round1_tokens", entry.round1_tokens)?;
+    dict.set_item("hits", entry.hits)?;
+    dict.set_item("fallback", entry.fallback)?;
+    dict.set_item("exact", entry.exact)?;
+    dict.set_item("cep_shortcut", entry.cep_shortcut)?;
+    dict.set_item("interrupted", entry.interrupted)?;
+    dict.set_item("fields", fields)?;
+    Ok(dict)
+}
+
+fn token_traces_list<'py>(py: Python<'py>, traces: &[TokenTrace]) -> PyResult<Bound<'py, PyList>> {
+    let list = PyList::empty(py);
+    for trace in traces {
+        let entry = PyDict::new(py);
+        entry.set_item("token", &trace.token)?;
+        entry.set_item("start", trace.span.as_ref().map(|span| span.start))?;
+        entry.set_item("end", trace.span.as_ref().map(|span| span.end))?;
+        entry.set_item("class", trace.class.name())?;
+        entry.set_item("rule", trace.rule.name())?;
+        list.append(entry)?;
+    }
+    Ok(list)
+}
+
+/// A batch's validation report: "checked", "rejected", "fixed" counts and
+/// "issues", a list of dicts with "doc_id", "field", "kind" and "value".
+fn validation_report_dict<'py>(
+    py: Python<'py>,
+    report: &ValidationReport,
+) -> PyResult<Bound<'py, PyDict>> {
+    let issues = PyList::empty(py);
+    for issue in &report.issues {
+        let item = PyDict::new(py);
+        item.set_item("doc_id", issue.doc_id)?;
+        item.set_item("field", issue.field.name())?;
+        item.set_item("kind", issue.kind.name())?;
+        item.set_item("value", &issue.value)?;
+        issues.append(item)?;
+    }
+
+    let dict = PyDict::new(py);
+    dict.set_item("checked", report.checked)?;
+    dict.set_item("rejected", report.rejected)?;
+    dict.set_item("fixed", report.fixed)?;
+    dict.set_item("issues", issues)?;
+    Ok(dict)
+}
+
+fn lmdb_options(
+    map_size: Option<usize>,
+    max_map_size: Option<usize>,
+    max_readers: Option<u32>,
+    sync_mode: Option<&str>,
+    read_only: bool,
+    fallback_read_only: bool,
+) -> PyResult<LmdbOptions> {
+    let mut options = LmdbOptions::new()
+        .read_only(read_only)
+        .fallback_read_only(fallback_read_only);
+    if let Some(map_size) = map_size {
+        options = options.map_size(map_size);
+    }
+    if let Some(max_map_size) = max_map_size {
+        options = options.max_map_size(max_map_size);
+    }
+    if let Some(max_readers) = max_readers {
+        options = options.max_readers(max_readers);
+    }
+    if let Some(mode) = sync_mode {
+        options = options.sync_mode(match mode.to_lowercase().as_str() {
+            "full" => SyncMode::Full,
+            "no_meta_sync" => SyncMode::NoMetaSync,
+            "no_sync" => SyncMode::NoSync,
+            "async" => SyncMode::Async,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown sync_mode '{}', expected full, no_meta_sync, no_sync or async",
+                    other
+                )));
+            }
+        });
+    }
+    Ok(options)
+}
+
+impl PySearchEngine {
+    /// Runs `f` against this instance's engine under a read lock.
+    fn with_engine<T>(&self, f: impl FnOnce(&Engine) -> PyResult<T>) -> PyResult<T> {
+        let engine = self.engine.read().map_err(LfasError::from)?;
+        f(&engine)
+    }
+
+    /// Runs `f` against this instance's engine under a write lock.
+    fn with_engine_mut<T>(&self, f: impl FnOnce(&mut Engine) -> PyResult<T>) -> PyResult<T> {
+        let mut engine = self.engine.write().map_err(LfasError::from)?;
+        f(&mut engine)
+    }
+
+    /// Scoring overrides plus, on read-only replicas, a metadata refresh when
+    /// the writer process has committed since the last search.
+    fn prepare_search(&self, engine: &mut Engine) -> PyResult<()> {
+        if engine.index.storage.is_read_only() {
+            engine.refresh()?;
+        }
+        self.apply_custom_scoring(engine);
+        Ok(())
+    }
+
+    /// Whether `prepare_search` has anything to apply.
+    fn needs_prepare(&self, engine: &Engine) -> PyResult<bool> {
+        if engine.index.storage.is_read_only() {
+            let generation = engine.index.storage.generation().map_err(LfasError::from)?;
+            if engine.synced_generation != Some(generation) {
+                return Ok(true);
+            }
+        }
+        Ok(self
+            .custom_weights
+            .as_ref()
+            .is_some_and(|weights| *weights != engine.scorer.field_weights)
+            || self
+                .custom_b_values
+                .as_ref()
+                .is_some_and(|b_values| *b_values != engine.scorer.field_b)
+            || self
+                .custom_tf_options
+                .as_ref()
+                .is_some_and(|tf_options| *tf_options != engine.scorer.field_tf))
+    }
+
+    /// Runs `f` under the read lock, taking the write lock beforehand only
+    /// when `prepare_search` has something to apply. Searches then run
+    /// alongside each other and alongside `index_batch`.
+    fn with_search_engine<T>(&self, f: impl FnOnce(&Engine) -> PyResult<T>) -> PyResult<T> {
+        if self.with_engine(|engine| self.needs_prepare(engine))? {
+            self.with_engine_mut(|engine| self.prepare_search(engine))?;
+        }
+        self.with_engine(f)
+    }
+
+    /// Validates and indexes `records`, see `index_batch`. Also returns the
+    /// postings written, one per (field, term, doc).
+    fn index_records(
+        &self,
+        py: Python<'_>,
+        records: Vec<(DocId, HashMap<String, FieldValue>)>,
+    ) -> PyResult<(Option<ValidationReport>, u64)> {
+        let started = std::time::Instant::now();
+        let records: Vec<(DocId, Record)> = records
+            .into_iter()
+            .map(|(doc_id, record_dict)| (doc_id, record_from_dict(record_dict)))
+            .collect();
+
+        py.detach(|| -> PyResult<_> {
+            let (records, report) = match &self.validator {
+                Some(validator) => {
+                    let (records, report) = validator.validate_batch(records);
+                    (records, Some(report))
+                }
+                None => (records, None),
+            };
+            let doc_count = records.len() as u64;
+            let _writer = self.writer.lock().map_err(LfasError::from)?;
+
+            // Tokenize and aggregate off the engine lock: (Field, Term) -> List of DocIds
+            // This drastically reduces trips to the LMDB
+            let analyzer = self.with_engine(|engine| Ok(engine.analyzer()))?;
+            let docs: Vec<TokenizedDoc<RecordField>> = records
+                .iter()
+                .map(|(doc_id, record)| TokenizedDoc::from_record_with(*doc_id, record, &analyzer))
+                .collect();
+            let mut batch_accumulator: HashMap<(RecordField, String), Vec<(DocId, u32)>> =
+                HashMap::new();
+            for doc in &docs {
+                for (field, tfs) in &doc.fields {
+                    for (term, tf) in tfs {
+                        batch_accumulator
+                            .entry((*field, term.clone()))
+                            .or_default()
+                            .push((doc.doc_id, *tf));
+                    }
+                }
+            }
+
+            // Batch writing to Storage
+            // Now we only perform ONE read and ONE write per single term in the batch
+            let tokens = self.with_engine(|engine| {
+                let mut tokens = 0;
+                for ((field, term), mut doc_tfs) in batch_accumulator {
+                    doc_tfs.sort_unstable();
+                    doc_tfs.dedup_by_key(|(doc_id, _)| *doc_id);
+                    tokens += doc_tfs.len() as u64;
+
+                    let mut postings = engine
+                        .index
+                        .storage
+                        .get(field, &term)
+                        .map_err(LfasError::from)?
+                        .unwrap_or_else(crate::postings::Postings::new);
+
+                    for (id, tf) in doc_tfs {
+                        postings.add_occurrences(id, tf);
+                    }
+
+                    // The LmdbStorage write buffer has its own lock
+                    engine
+                        .index
+                        .storage
+                        .put_shared(field, term, postings)
+                        .map_err(LfasError::from)?;
+                }
+                Ok(tokens)
+            })?;
+
+            // Only the metadata update holds the write lock
+            self.with_engine_mut(|engine| {
+                engine.record_tokenized(docs)?;
+                engine.metrics.record_index_batch(doc_count, started.elapsed());
+                Ok(())
+            })?;
+            Ok((report, tokens))
+        })
+    }
+
+    /// `{"rua": 2.0, ...}` with every name checked, for `reload_config`.
+    fn field_map(&self, values: HashMap<String, f32>) -> PyResult<HashMap<RecordField, f32>> {
+        values
+            .into_iter()
+            .map(|(name, value)| match self.map_field(&name) {
+                Some(field) => Ok((field, value)),
+                None => Err(PyValueError::new_err(format!("Unknown field '{}'", name))),
+            })
+            .collect()
+    }
+
+    fn parse_reload(
+        &self,
+        config: HashMap<String, Bound<'_, PyAny>>,
+    ) -> PyResult<ConfigReload<RecordField>> {
+        let mut reload = ConfigReload::default();
+        for (key, value) in config {
+            match key.as_str() {
+                "k1" => reload.k1 = Some(value.extract()?),
+                "field_weights" => reload.field_weights = self.field_map(value.extract()?)?,
+                "field_b" => reload.field_b = self.field_map(value.extract()?)?,
+                "fallback" => {
+                    let mut fallback = FallbackPolicy::default();
+                    let options: HashMap<String, Bound<'_, PyAny>> = value.extract()?;
+                    for (option, value) in options {
+                        match option.as_str() {
+                            "enabled" => fallback.enabled = value.extract()?,
+                            "rare_tokens" => fallback.rare_tokens = value.extract()?,
+                            "max_df" => fallback.max_df = value.extract()?,
+                            "ngrams" => fallback.ngrams = value.extract()?,
+                            other => {
+                                return Err(PyValueError::new_err(format!(
+                                    "Unknown fallback option '{}'",
+                                    other
+                                )));
+                            }
+                        }
+                    }
+                    reload.fallback = Some(fallback);
+                }
+                "synonyms" => {
+                    let mut synonyms = Synonyms::new();
+                    let entries: HashMap<String, Vec<String>> = value.extract()?;
+                    for (token, equivalents) in entries {
+                        synonyms.insert(&token, equivalents);
+                    }
+                    reload.synonyms = Some(synonyms);
+                }
+                other => {
+                    return Err(PyValueError::new_err(format!(
+                        "Unknown config key '{}', expected k1, field_weights, field_b, fallback or synonyms",
+                        other
+                    )));
+                }
+            }
+        }
+        Ok(reload)
+    }
+
+    fn apply_custom_scoring(&self, engine: &mut Engine) {
+        if let Some(ref weights) = self.custom_weights {
+            info!("[RUST] Applying custom weights for search");
+            engine.scorer.field_weights = weights.clone();
+        }
+
+        if let Some(ref b_values) = self.custom_b_values {
+            info!("[RUST] Applying custom b-values for search");
+            engine.scorer.field_b = b_values.clone();
+        }
+
+        if let Some(ref tf_options) = self.custom_tf_options {
+            info!("[RUST] Applying custom tf options for search");
+            engine.scorer.field_tf = tf_options.clone();
+        }
+    }
 }
 
 #[pymethods]
@@ -30,30 +704,93 @@ impl PySearchEngine {
         let _ = pyo3_log::try_init();
     }
 
+    /// Upgrade an index directory to the current on-disk format.
+    /// Returns `(from_version, to_version)`.
+    #[staticmethod]
+    fn migrate_index(path: &str) -> PyResult<(u32, u32)> {
+        let report = crate::storage::migrate::migrate(std::path::Path::new(path))
+            .map_err(LfasError::from)?;
+        Ok((report.from, report.to))
+    }
+
+    /// Opens the index at `path` with its saved config, then applies
+    /// `schema`, `k1`, `field_weights`, `field_b` and `tokenizer` (a
+    /// `(mode, n, stride, locale)` tuple as in `set_tokenizer_config`,
+    /// composite patterns cleared) on top. A `tokenizer` other than the one
+    /// the index was built with raises ValueError.
+    ///
+    /// Each instance owns its engine: settings changed on one never reach
+    /// another, and an index directory is open in one instance at a time.
+    /// An index has one writer process; with `fallback_read_only` a second
+    /// process opens it read-only instead of failing. With `max_map_size` a
+    /// full map doubles up to that size instead of failing the flush;
+    /// searches running meanwhile wait out the regrowth.
     #[new]
-    fn new() -> Self {
+    #[pyo3(signature = (path, map_size=None, max_map_size=None, max_readers=None, sync_mode=None, read_only=false, fallback_read_only=false, schema=None, k1=None, field_weights=None, field_b=None, tokenizer=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        path: &str,
+        map_size: Option<usize>,
+        max_map_size: Option<usize>,
+        max_readers: Option<u32>,
+        sync_mode: Option<&str>,
+        read_only: bool,
+        fallback_read_only: bool,
+        schema: Option<Vec<String>>,
+        k1: Option<f32>,
+        field_weights: Option<HashMap<String, f32>>,
+        field_b: Option<HashMap<String, f32>>,
+        tokenizer: Option<(String, usize, usize, String)>,
+    ) -> PyResult<Self> {
         info!("[RUST] PySearchEngine::new() called");
         let timer = Timer::new("PySearchEngine::new");
 
-        // Use write lock only for initialization
-        let mut global = GLOBAL_ENGINE.write().unwrap();
-        if global.is_none() {
-            info!("[RUST] Creating new LMDB storage (first time)");
-            let storage = LmdbStorage::<RecordField>::open(std::path::Path::new("./lmdb_data"))
-                .expect("Failed to open LMDB storage");
-            *global = Some(engine::SearchEngine::with_storage(storage));
-        } else {
-            info!("[RUST] Reusing existing LMDB storage");
+        let options = lmdb_options(
+            map_size,
+            max_map_size,
+            max_readers,
+            sync_mode,
+            read_only,
+            fallback_read_only,
+        )?;
+
+        let mut builder = EngineBuilder::new(std::path::Path::new(path)).options(options);
+        if let Some(schema) = schema {
+            let fields = schema
+                .iter()
+                .map(|name| {
+                    RecordField::from_name(name)
+                        .ok_or_else(|| PyValueError::new_err(format!("Unknown field '{}'", name)))
+                })
+                .collect::<PyResult<Vec<_>>>()?;
+            builder = builder.schema(fields);
+        }
+        if let Some(k1) = k1 {
+            builder = builder.k1(k1);
+        }
+        if let Some(weights) = field_weights {
+            builder = builder.field_weights(field_values(weights)?);
+        }
+        if let Some(b_values) = field_b {
+            builder = builder.field_b(field_values(b_values)?);
+        }
+        if let Some((mode, n, stride, locale)) = tokenizer {
+            builder = builder.tokenizer(ngram_tokenizer(&mode, n, stride, &locale)?);
         }
-        drop(global); // Release write lock immediately
+
+        let engine = builder.build()?;
 
         drop(timer);
         info!("[RUST] PySearchEngine created successfully");
 
-        PySearchEngine {
+        Ok(PySearchEngine {
+            engine: RwLock::new(engine),
+            writer: Mutex::new(()),
             custom_weights: None,
             custom_b_values: None,
-        }
+            custom_tf_options: None,
+            validator: None,
+        })
     }
 
     fn set_field_weights(&mut self, weights: HashMap<String, f32>) {
@@ -68,11 +805,11 @@ impl PySearchEngine {
             }
         }
 
-        self.custom_weights = Some(field_weights);
         info!(
             "[RUST] Custom weights configured for {} fields",
-            self.custom_weights.as_ref().unwrap().len()
+            field_weights.len()
         );
+        self.custom_weights = Some(field_weights);
     }
 
     fn set_field_b_values(&mut self, b_values: HashMap<String, f32>) {
@@ -87,251 +824,834 @@ impl PySearchEngine {
             }
         }
 
-        self.custom_b_values = Some(field_b);
         info!(
             "[RUST] Custom b-values configured for {} fields",
-            self.custom_b_values.as_ref().unwrap().len()
+            field_b.len()
         );
+        self.custom_b_values = Some(field_b);
     }
 
-    /// Reset to default weights
-    fn reset_weights(&mut self) {
-        self.custom_weights = None;
-        self.custom_b_values = None;
-        info!("[RUST] Reset to default weights");
-    }
+    /// Per-field tf saturation: `{"nome": (max_tf, sublinear)}`, where `max_tf`
+    /// may be None to skip clamping and `sublinear` switches to `1 + ln(tf)`.
+    fn set_field_tf_options(&mut self, options: HashMap<String, (Option<u32>, bool)>) {
+        let mut field_tf = HashMap::new();
 
-    /// Get current weights configuration
-    fn get_weights(&self) -> HashMap<String, f32> {
-        let global = GLOBAL_ENGINE.read().unwrap();
-        let engine = global.as_ref().expect("Engine not initialized");
+        for (field_name, (max_tf, sublinear)) in options {
+            if let Some(field) = self.map_field(&field_name) {
+                let tf_options = TfOptions { max_tf, sublinear };
+                field_tf.insert(field, tf_options);
+                info!("[RUST] Set tf options for {:?}: {:?}", field, tf_options);
+            } else {
+                info!("[RUST] Warning: Unknown field '{}'", field_name);
+            }
+        }
 
-        let weights = if let Some(ref custom) = self.custom_weights {
-            custom.clone()
-        } else {
-            engine.scorer.field_weights.clone()
-        };
+        info!(
+            "[RUST] Custom tf options configured for {} fields",
+            field_tf.len()
+        );
+        self.custom_tf_options = Some(field_tf);
+    }
 
-        weights
-            .into_iter()
-            .map(|(field, weight)| (format!("{:?}", field).to_lowercase(), weight))
-            .collect()
+    /// Scoring weight of weak 3-gram query tokens relative to full tokens (1.0).
+    fn set_ngram_weight(&mut self, weight: f32) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.ngram_weight = weight;
+            info!("[RUST] N-gram weight set to {}", weight);
+            Ok(())
+        })
     }
 
-    fn map_field(&self, field_name: &str) -> Option<RecordField> {
-        match field_name.to_lowercase().as_str() {
-            "estado" => Some(RecordField::Estado),
-            "municipio" => Some(RecordField::Municipio),
-            "bairro" => Some(RecordField::Bairro),
-            "cep" => Some(RecordField::Cep),
-            "tipo_logradouro" => Some(RecordField::TipoLogradouro),
-            "rua" => Some(RecordField::Rua),
-            "numero" => Some(RecordField::Numero),
-            "complemento" => Some(RecordField::Complemento),
-            "nome" => Some(RecordField::Nome),
-            _ => None,
-        }
-    }
-
-    fn index_batch(&mut self, records: Vec<(usize, HashMap<String, String>)>) {
-        let mut global = GLOBAL_ENGINE.write().unwrap(); // Write lock for indexing
-        let engine = global.as_mut().expect("Engine not initialized");
-
-        // In-memory aggregation: (Field, Term) -> List of DocIds
-        // This drastically reduces trips to the LMDB
-        let mut batch_accumulator: HashMap<(RecordField, String), Vec<usize>> = HashMap::new();
-
-        for (doc_id, record_dict) in records {
-            for (field_name, value) in record_dict {
-                if let Some(field) = self.map_field(&field_name) {
-                    for term in tokenize(&value) {
-                        batch_accumulator
-                            .entry((field, term))
-                            .or_default()
-                            .push(doc_id);
-                    }
-                }
-            }
-            engine.metadata.total_docs += 1;
-        }
+    /// When enabled, `search_complex` hits become `(doc_id, score, field_scores)`
+    /// with each matched field's share of the BM25F score, for weight tuning.
+    fn set_field_scores(&mut self, enabled: bool) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.field_scores = enabled;
+            info!("[RUST] Per-field scores {}", if enabled { "enabled" } else { "disabled" });
+            Ok(())
+        })
+    }
 
-        // Batch writing to Storage
-        // Now we only perform ONE read and ONE write per single term in the batch
-        for ((field, term), mut doc_ids) in batch_accumulator {
-            doc_ids.sort_unstable();
-            doc_ids.dedup();
+    /// When a query holds a full CEP (`00000-000`) found in at most
+    /// `max_docs` documents, rank just those by the share of the query's
+    /// tokens they hold and skip BM25F. Pass `max_docs=None` to turn it off.
+    #[pyo3(signature = (max_docs=Some(50)))]
+    fn set_cep_shortcut(&mut self, max_docs: Option<usize>) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.cep_shortcut = max_docs;
+            info!("[RUST] CEP shortcut set to {:?}", engine.cep_shortcut);
+            Ok(())
+        })
+    }
 
-            let mut postings = engine
-                .index
-                .storage
-                .get(field, &term)
-                .unwrap_or_default()
-                .unwrap_or_else(crate::postings::Postings::new);
+    /// Collapse hits of documents sharing the normalized values of `fields`
+    /// (e.g. ["cep", "numero", "rua"]) into the best-scoring one. Keys are
+    /// taken while indexing, so set this before indexing; [] disables it.
+    fn set_collapse_fields(&mut self, fields: Vec<String>) -> PyResult<()> {
+        let fields = fields
+            .iter()
+            .map(|name| {
+                RecordField::from_name(name)
+                    .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", name)))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        self.with_engine_mut(|engine| {
+            info!("[RUST] Collapse fields set to {:?}", fields);
+            engine.collapse_fields = fields;
+            Ok(())
+        })
+    }
 
-            for id in doc_ids {
-                postings.add_occurrence(id);
-            }
+    /// Bonus added to a hit's score scaled by the fraction of query fields it
+    /// matches; 0 (the default) disables it.
+    fn set_coverage_boost(&mut self, boost: f32) -> PyResult<()> {
+        if boost.is_nan() || boost < 0.0 {
+            return Err(PyValueError::new_err("coverage boost must be non-negative"));
+        }
+        self.with_engine_mut(|engine| {
+            engine.scorer.coverage_boost = boost;
+            info!("[RUST] Coverage boost set to {}", boost);
+            Ok(())
+        })
+    }
 
-            let key = (field, term.clone());
-            engine.metadata.term_df.insert(key, postings.len());
+    /// Field groups as `(name, fields, weight)`; each group's weight multiplies
+    /// the score of its fields, e.g. `[("address", ["rua", "numero"], 2.0),
+    /// ("name", ["nome"], 0.5)]`. An empty list removes them.
+    fn set_field_groups(&mut self, groups: Vec<(String, Vec<String>, f32)>) -> PyResult<()> {
+        let groups = groups
+            .into_iter()
+            .map(|(name, fields, weight)| {
+                if weight.is_nan() || weight < 0.0 {
+                    return Err(PyValueError::new_err(format!(
+                        "group '{}' weight must be non-negative",
+                        name
+                    )));
+                }
+                let fields = fields
+                    .iter()
+                    .map(|field| {
+                        RecordField::from_name(field).ok_or_else(|| {
+                            PyValueError::new_err(format!("Unknown field: {}", field))
+                        })
+                    })
+                    .collect::<PyResult<Vec<_>>>()?;
+                Ok(FieldGroup::new(name, fields, weight))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        self.with_engine_mut(|engine| {
+            info!("[RUST] Field groups set to {:?}", groups);
+            engine.scorer.field_groups = groups;
+            Ok(())
+        })
+    }
 
-            // The LmdbStorage we have already has a WriteBuffer,
-            // so this will be extremely fast.
-            engine.index.storage.put(field, term, postings).unwrap();
+    /// Scores field lengths above this percentile of their field (a fraction,
+    /// e.g. 0.99) as the percentile; None turns clipping off.
+    #[pyo3(signature = (percentile=None))]
+    fn set_length_clip(&mut self, percentile: Option<f32>) -> PyResult<()> {
+        if percentile.is_some_and(|p| !(p > 0.0 && p <= 1.0)) {
+            return Err(PyValueError::new_err("length clip must be in (0, 1]"));
         }
+        self.with_engine_mut(|engine| {
+            engine.scorer.length_clip = percentile;
+            info!("[RUST] Length clip set to {:?}", percentile);
+            Ok(())
+        })
     }
 
-    fn index_dict(&mut self, doc_id: usize, record_dict: HashMap<String, String>) {
-        let mut global = GLOBAL_ENGINE.write().unwrap(); // Write lock for indexing
-        let engine = global.as_mut().expect("Engine not initialized");
+    /// Weak n-gram extraction: `mode` is "chunked" or "sliding"; `stride` only
+    /// applies to sliding windows. `locale` ("pt-BR", "es" or "en") picks the
+    /// stopwords and address types. Composite patterns are kept. Changing any
+    /// of it requires reindexing.
+    #[pyo3(signature = (mode="chunked", n=3, stride=1, locale="pt-BR"))]
+    fn set_tokenizer_config(
+        &mut self,
+        mode: &str,
+        n: usize,
+        stride: usize,
+        locale: &str,
+    ) -> PyResult<()> {
+        let tokenizer = ngram_tokenizer(mode, n, stride, locale)?;
+        self.with_engine_mut(|engine| {
+            engine.tokenizer = TokenizerConfig {
+                composites: std::mem::take(&mut engine.tokenizer.composites),
+                ..tokenizer
+            };
+            info!("[RUST] Tokenizer config set to {:?}", engine.tokenizer);
+            Ok(())
+        })
+    }
 
-        if doc_id % 10000 == 0 {
-            info!(
-                "[RUST] Indexing doc_id: {} (Total docs: {})",
-                doc_id, engine.metadata.total_docs
-            );
-        }
+    /// Extra two-token composites made distinctive, as `(first_words,
+    /// second_regex)` pairs: `(["quadra", "qd"], r"\d+")` turns "Quadra 5"
+    /// into the token "quadra 5". The regex must match the whole second
+    /// token. Replaces the current patterns; changing them requires reindexing.
+    fn set_composite_patterns(&mut self, patterns: Vec<(Vec<String>, String)>) -> PyResult<()> {
+        let composites = patterns
+            .iter()
+            .map(|(first, second)| CompositePattern::new(first, second))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| PyValueError::new_err(format!("Invalid composite pattern: {}", e)))?;
+        self.with_engine_mut(|engine| {
+            engine.tokenizer.composites = composites;
+            info!("[RUST] Composite patterns set to {:?}", patterns);
+            Ok(())
+        })
+    }
 
-        let mut field_count = 0;
-        let mut token_count = 0;
+    /// Stopword and address-type handling for one field: each policy is
+    /// "drop", "keep" or "demote"; `locale` overrides the engine's for this
+    /// field. `diacritics` is "fold" (strip accents), "keep" or "both" (index
+    /// the accented spelling next to the folded one). Tokens shorter than
+    /// `min_len` characters or in `blacklist` are dropped; all-digit tokens
+    /// are kept whatever their length unless `numbers` is false. Changing it
+    /// requires reindexing.
+    #[pyo3(signature = (field, stopwords="drop", address_types="keep", demoted_weight=0.3, locale=None, diacritics="fold", min_len=0, numbers=true, blacklist=Vec::new()))]
+    #[allow(clippy::too_many_arguments)]
+    fn set_field_token_rules(
+        &mut self,
+        field: &str,
+        stopwords: &str,
+        address_types: &str,
+        demoted_weight: f32,
+        locale: Option<&str>,
+        diacritics: &str,
+        min_len: usize,
+        numbers: bool,
+        blacklist: Vec<String>,
+    ) -> PyResult<()> {
+        let field = self
+            .map_field(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", field)))?;
+        let rules = FieldTokenRules {
+            stopwords: parse_term_policy(stopwords)?,
+            address_types: parse_term_policy(address_types)?,
+            demoted_weight,
+            locale: locale.map(parse_locale).transpose()?,
+            diacritics: Diacritics::from_name(diacritics).ok_or_else(|| {
+                PyValueError::new_err(format!("Unknown diacritics mode: {}", diacritics))
+            })?,
+            filter: TokenFilter::new(min_len)
+                .with_numbers(numbers)
+                .with_blacklist(blacklist),
+        };
 
-        // Track unique terms by document
-        let mut doc_terms: HashMap<(RecordField, String), bool> = HashMap::new();
+        self.with_engine_mut(|engine| {
+            info!("[RUST] Token rules for {:?} set to {:?}", field, rules);
+            engine.field_rules.insert(field, rules);
+            Ok(())
+        })
+    }
 
-        for (key, text) in record_dict {
-            let field = match self.map_field(&key) {
-                Some(f) => f,
-                None => continue,
-            };
+    /// Multiply scores by an exponential decay on document age: a record
+    /// `half_life_days` old keeps half its score, never less than `floor`.
+    /// `None` turns recency boosting off.
+    #[pyo3(signature = (half_life_days, floor=0.0, reference=None))]
+    fn set_recency_decay(
+        &mut self,
+        half_life_days: Option<f64>,
+        floor: f32,
+        reference: Option<i64>,
+    ) -> PyResult<()> {
+        let recency = half_life_days.map(|days| RecencyDecay {
+            half_life_secs: days * 86_400.0,
+            reference,
+            floor,
+        });
+        self.with_engine_mut(|engine| {
+            engine.scorer.recency = recency;
+            info!("[RUST] Recency decay set to {:?}", recency);
+            Ok(())
+        })
+    }
 
-            let tokens = tokenize(&text);
-            let this_field_tokens = tokens.len();
-            token_count += this_field_tokens;
-            field_count += 1;
+    /// Last-updated unix timestamps per doc_id, for recency boosting.
+    fn set_doc_timestamps(&mut self, timestamps: HashMap<DocId, i64>) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            for (doc_id, timestamp) in timestamps {
+                engine.set_doc_timestamp(doc_id, timestamp);
+            }
+            Ok(())
+        })
+    }
 
-            for token in tokens {
-                engine.index.add_term(doc_id, field, token.clone());
-                doc_terms.insert((field, token), true);
+    /// Round 1 candidate generation: "union" of distinctive tokens (default),
+    /// "field_intersection", where all tokens of a field must co-occur, or
+    /// "rarest_first", which adds distinctive tokens rarest first until
+    /// `blocking_k` candidates are found.
+    fn set_blocking_strategy(&mut self, strategy: &str) -> PyResult<()> {
+        let blocking = match strategy.to_lowercase().as_str() {
+            "union" => BlockingStrategy::Union,
+            "field_intersection" => BlockingStrategy::FieldIntersection,
+            "rarest_first" => BlockingStrategy::RarestFirst,
+            other => {
+                return Err(PyValueError::new_err(format!(
+                    "Unknown blocking strategy: {}",
+                    other
+                )));
             }
+        };
+        self.with_engine_mut(|engine| {
+            engine.blocking = blocking;
+            info!("[RUST] Blocking strategy set to {:?}", blocking);
+            Ok(())
+        })
+    }
 
-            engine
-                .metadata
-                .lengths
-                .entry(doc_id)
-                .or_default()
-                .insert(field, this_field_tokens);
-            *engine
-                .metadata
-                .total_field_lengths
-                .entry(field)
-                .or_insert(0) += this_field_tokens;
+    /// Replace the blocking strategy by the union of several generators:
+    /// "distinctive", "field_intersection", "rare_tokens" and "ngram". An
+    /// empty list goes back to the blocking strategy.
+    fn set_candidate_generators(&mut self, names: Vec<String>) -> PyResult<()> {
+        let mut generators: Vec<Arc<dyn CandidateGenerator<RecordField>>> = Vec::new();
+        for name in &names {
+            let generator: Arc<dyn CandidateGenerator<RecordField>> =
+                match name.to_lowercase().as_str() {
+                    "distinctive" => Arc::new(DistinctiveUnion),
+                    "field_intersection" => Arc::new(FieldIntersection),
+                    "rare_tokens" => Arc::new(RareTokens::default()),
+                    "ngram" => Arc::new(NgramOverlap::default()),
+                    other => {
+                        return Err(PyValueError::new_err(format!(
+                            "Unknown candidate generator: {}",
+                            other
+                        )));
+                    }
+                };
+            generators.push(generator);
         }
+        self.with_engine_mut(|engine| {
+            engine.generators = generators;
+            info!("[RUST] Candidate generators set to {:?}", names);
+            Ok(())
+        })
+    }
 
-        for (key, _) in doc_terms {
-            *engine.metadata.term_df.entry(key).or_insert(0) += 1;
-        }
+    /// Whether the rarest-token fallback may use n-gram postings.
+    fn set_ngram_fallback(&mut self, enabled: bool) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.fallback.ngrams = enabled;
+            info!("[RUST] N-gram fallback {}", if enabled { "enabled" } else { "disabled" });
+            Ok(())
+        })
+    }
 
-        if doc_id >= engine.metadata.total_docs {
-            engine.metadata.total_docs = doc_id + 1;
-        }
+    /// What to do when no distinctive token finds candidates: seed them from
+    /// the `rare_tokens` rarest query tokens with df at most `max_df`, or, with
+    /// `enabled=False`, return no hits.
+    #[pyo3(signature = (enabled=true, rare_tokens=engine::DEFAULT_FALLBACK_TOKENS, max_df=None, ngrams=true))]
+    fn set_fallback_policy(
+        &mut self,
+        enabled: bool,
+        rare_tokens: usize,
+        max_df: Option<usize>,
+        ngrams: bool,
+    ) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.fallback = FallbackPolicy {
+                enabled,
+                rare_tokens,
+                max_df,
+                ngrams,
+            };
+            info!("[RUST] Fallback policy set to {:?}", engine.fallback);
+            Ok(())
+        })
+    }
 
-        if doc_id == 0 {
-            info!(
-                "[INDEX] First doc indexed: {} fields, {} tokens",
-                field_count, token_count
-            );
+    /// Treat terms found in more than `max_df_ratio` of the documents as
+    /// weak: they don't select candidates and score at most `weight`. Only
+    /// applies once the index holds `min_docs` documents. Pass
+    /// `max_df_ratio=None` to turn it off.
+    #[pyo3(signature = (max_df_ratio=Some(0.2), weight=0.3, min_docs=1000))]
+    fn set_common_terms(
+        &mut self,
+        max_df_ratio: Option<f32>,
+        weight: f32,
+        min_docs: usize,
+    ) -> PyResult<()> {
+        if let Some(ratio) = max_df_ratio {
+            if !(ratio > 0.0 && ratio <= 1.0) {
+                return Err(PyValueError::new_err(format!(
+                    "max_df_ratio must be in (0, 1], got {}",
+                    ratio
+                )));
+            }
         }
+        self.with_engine_mut(|engine| {
+            engine.common_terms = max_df_ratio.map(|max_df_ratio| CommonTerms {
+                max_df_ratio,
+                weight,
+                min_docs,
+            });
+            info!("[RUST] Common terms set to {:?}", engine.common_terms);
+            Ok(())
+        })
     }
 
-    fn flush(&mut self) -> PyResult<()> {
-        info!("[RUST] Flushing buffered writes to disk...");
-        let timer = Timer::new("flush");
-
-        let mut global = GLOBAL_ENGINE.write().unwrap(); // Write lock for flush
-        let engine = global.as_mut().expect("Engine not initialized");
-
-        engine.index.storage.flush().map_err(|e| {
-            pyo3::exceptions::PyRuntimeError::new_err(format!("Flush failed: {}", e))
+    /// Validate records before `index_batch`/`index_dict`. `policy` is "reject",
+    /// "fix" or "raw"; `required` lists the fields that must not be empty
+    /// (default rua, municipio, estado). Pass `policy=None` to stop validating.
+    #[pyo3(signature = (policy=Some("fix"), required=None))]
+    fn set_record_validator(
+        &mut self,
+        policy: Option<&str>,
+        required: Option<Vec<String>>,
+    ) -> PyResult<()> {
+        let Some(policy) = policy else {
+            self.validator = None;
+            return Ok(());
+        };
+        let policy = ValidationPolicy::from_name(policy).ok_or_else(|| {
+            PyValueError::new_err(format!("Unknown validation policy: {}", policy))
         })?;
-
-        drop(timer);
-        info!("[RUST] Flush complete");
+        let mut validator = RecordValidator::new(policy);
+        if let Some(required) = required {
+            let fields = required
+                .iter()
+                .map(|name| {
+                    RecordField::from_name(name)
+                        .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", name)))
+                })
+                .collect::<PyResult<Vec<_>>>()?;
+            validator = validator.required(fields);
+        }
+        info!("[RUST] Record validator set to {:?}", validator);
+        self.validator = Some(validator);
         Ok(())
     }
 
-    fn search_complex(
-        &self,
-        query_dict: HashMap<String, String>,
-        top_k: usize,
-        blocking_k: usize,
-    ) -> Vec<(usize, f32)> {
-        info!("[RUST] search_complex called");
-        info!("[RUST] Query dict size: {}", query_dict.len());
-        info!("[RUST] top_k: {}", top_k);
-
-        let total_timer = Timer::new("search_complex::total");
+    /// Override the engine's query limits; omitted values keep their current setting.
+    #[pyo3(signature = (max_top_k=None, max_blocking_k=None, max_clauses=None, max_text_len=None))]
+    fn set_query_limits(
+        &mut self,
+        max_top_k: Option<usize>,
+        max_blocking_k: Option<usize>,
+        max_clauses: Option<usize>,
+        max_text_len: Option<usize>,
+    ) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            let limits = &mut engine.limits;
+            limits.max_top_k = max_top_k.unwrap_or(limits.max_top_k);
+            limits.max_blocking_k = max_blocking_k.unwrap_or(limits.max_blocking_k);
+            limits.max_clauses = max_clauses.unwrap_or(limits.max_clauses);
+            limits.max_text_len = max_text_len.unwrap_or(limits.max_text_len);
+            info!("[RUST] Query limits set to {:?}", limits);
+            Ok(())
+        })
+    }
 
-        let parse_timer = Timer::new("search_complex::parse_query");
-        let mut query_fields = Vec::new();
+    /// Cap concurrent searches at `max_concurrent`, with up to `max_queued`
+    /// more waiting; further searches raise `OverloadedError` at once.
+    /// `max_concurrent=None` removes the cap.
+    #[pyo3(signature = (max_concurrent=None, max_queued=0))]
+    fn set_concurrency_limits(
+        &mut self,
+        max_concurrent: Option<usize>,
+        max_queued: usize,
+    ) -> PyResult<()> {
+        let limits = max_concurrent.map(|max_concurrent| ConcurrencyLimits {
+            max_concurrent,
+            max_queued,
+        });
+        self.with_engine_mut(|engine| {
+            engine.set_concurrency_limits(limits);
+            info!("[RUST] Concurrency limits set to {:?}", limits);
+            Ok(())
+        })
+    }
 
-        for (key, text) in query_dict {
-            if text.trim().is_empty() {
-                continue;
-            }
+    /// Reset to default weights
+    fn reset_weights(&mut self) {
+        self.custom_weights = None;
+        self.custom_b_values = None;
+        self.custom_tf_options = None;
+        info!("[RUST] Reset to default weights");
+    }
 
-            info!("[RUST] Processing field: {} = '{}'", key, text);
-            let field = match self.map_field(&key) {
-                Some(f) => f,
-                None => continue,
+    /// Get current weights configuration
+    fn get_weights(&self) -> PyResult<HashMap<String, f32>> {
+        self.with_engine(|engine| {
+            let weights = if let Some(ref custom) = self.custom_weights {
+                custom.clone()
+            } else {
+                engine.scorer.field_weights.clone()
             };
-            query_fields.push((field, text));
-        }
+
+            Ok(weights
+                .into_iter()
+                .map(|(field, weight)| (format!("{:?}", field).to_lowercase(), weight))
+                .collect())
+        })
+    }
+
+    fn map_field(&self, field_name: &str) -> Option<RecordField> {
+        RecordField::from_name(field_name)
+    }
+
+    /// Field values may be strings or lists of strings (aliases). With a
+    /// record validator set, returns its report for the batch.
+    ///
+    /// Runs with the GIL released. Tokenization happens outside the engine
+    /// lock and postings are written under the read lock, so searches on
+    /// other threads proceed; only the final bookkeeping takes the write lock.
+    fn index_batch<'py>(
+        &self,
+        py: Python<'py>,
+        records: Vec<(DocId, HashMap<String, FieldValue>)>,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let (report, _) = self.index_records(py, records)?;
+        report
+            .map(|report| validation_report_dict(py, &report))
+            .transpose()
+    }
+
+    /// Indexes an iterable of `(doc_id, record_dict)` pairs, e.g. rows read
+    /// from a large CSV, through `index_batch` in batches of `batch_size`
+    /// without loading it all. `progress` is called every `progress_every`
+    /// records and once at the end with a dict of "docs", "tokens",
+    /// "flushes", "elapsed_s" and "eta_s"; pass the expected record count as
+    /// `total` to get an ETA. An exception raised by `progress` is re-raised
+    /// once the stream is indexed. With a record validator set, returns its
+    /// report for the whole stream.
+    #[pyo3(signature = (
+        records,
+        batch_size=10_000,
+        progress=None,
+        progress_every=DEFAULT_PROGRESS_INTERVAL,
+        total=None
+    ))]
+    fn index_stream<'py>(
+        &self,
+        py: Python<'py>,
+        records: &Bound<'py, PyAny>,
+        batch_size: usize,
+        progress: Option<Py<PyAny>>,
+        progress_every: usize,
+        total: Option<usize>,
+    ) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let generation = || {
+            self.with_engine(|engine| Ok(engine.index.storage.generation().map_err(LfasError::from)?))
+        };
+        let first_generation = generation()?;
+        let mut callback_error = None;
+        let mut reporter =
+            python_progress(py, progress, progress_every, &mut callback_error).map(|reporter| {
+                match total {
+                    Some(total) => reporter.expected_docs(total),
+                    None => reporter,
+                }
+            });
+
+        let batch_size = batch_size.max(1);
+        let mut report: Option<ValidationReport> = None;
+        let (mut docs, mut tokens) = (0, 0);
+        let mut batch = Vec::with_capacity(batch_size);
+        let mut items = records.try_iter()?;
+        loop {
+            let item = items.next().transpose()?;
+            let done = item.is_none();
+            if let Some(item) = item {
+                batch.push(item.extract::<(DocId, HashMap<String, FieldValue>)>()?);
+            }
+            if batch.len() >= batch_size || (done && !batch.is_empty()) {
+                docs += batch.len();
+                let (batch_report, batch_tokens) =
+                    self.index_records(py, std::mem::take(&mut batch))?;
+                tokens += batch_tokens;
+                match (&mut report, batch_report) {
+                    (Some(report), Some(batch_report)) => report.merge(batch_report),
+                    (None, batch_report) => report = batch_report,
+                    (Some(_), None) => {}
+                }
+                if let Some(reporter) = &mut reporter {
+                    let flushes = (generation()? - first_generation) as usize;
+                    reporter.observe(docs, tokens, flushes);
+                }
+            }
+            if done {
+                break;
+            }
+        }
+        if let Some(reporter) = &mut reporter {
+            let flushes = (generation()? - first_generation) as usize;
+            reporter.report(docs, tokens, flushes);
+        }
+        drop(reporter);
+        info!("[RUST] Indexed {} streamed records", docs);
+
+        if let Some(e) = callback_error {
+            return Err(e);
+        }
+        report
+            .map(|report| validation_report_dict(py, &report))
+            .transpose()
+    }
+
+    /// Field values may be strings or lists of strings (aliases).
+    fn index_dict(&mut self, doc_id: DocId, record_dict: HashMap<String, FieldValue>) -> PyResult<()> {
+        let _writer = self.writer.lock().map_err(LfasError::from)?;
+        self.with_engine_mut(|engine| {
+            if doc_id.get() % 10000 == 0 {
+                info!(
+                    "[RUST] Indexing doc_id: {} (Total docs: {})",
+                    doc_id, engine.metadata.total_docs
+                );
+            }
+
+            let mut field_count = 0;
+            let mut token_count = 0;
+
+            let mut record = record_from_dict(record_dict);
+            if let Some(validator) = &self.validator {
+                let (keep, issues) = validator.validate(doc_id, &mut record);
+                if !keep {
+                    warn!(
+                        "[RUST] Rejected doc_id {}: {} validation issues",
+                        doc_id,
+                        issues.len()
+                    );
+                    return Ok(());
+                }
+            }
+            if !record.id.is_empty() {
+                engine.map_external_id(record.id.clone(), doc_id)?;
+            }
+
+            // Track unique terms by document
+            let mut doc_terms: HashMap<(RecordField, String), bool> = HashMap::new();
+
+            let doc = TokenizedDoc::from_record_with(doc_id, &record, &engine.analyzer());
+            engine.set_collapse_key(doc_id, &doc.stored);
+            engine.store_fields(doc_id, doc.stored)?;
+
+            let mut terms = Vec::new();
+            for (field, tfs) in doc.fields {
+                if tfs.is_empty() {
+                    continue;
+                }
+
+                let this_field_tokens = tfs.len();
+                token_count += this_field_tokens;
+                field_count += 1;
+
+                for (token, tf) in tfs {
+                    terms.push((field, token.clone(), tf));
+                    doc_terms.insert((field, token), true);
+                }
+
+                engine
+                    .metadata
+                    .lengths
+                    .entry(doc_id)
+                    .or_default()
+                    .insert(field, this_field_tokens);
+                engine.metadata.record_length(&field, 0, this_field_tokens);
+                *engine
+                    .metadata
+                    .total_field_lengths
+                    .entry(field)
+                    .or_insert(0) += this_field_tokens;
+            }
+
+            engine.index.add_batch(vec![(doc_id, terms)])?;
+
+            for (key, _) in doc_terms {
+                *engine.metadata.df_entry(key.0, key.1) += 1;
+            }
+
+            if doc_id.index() >= engine.metadata.total_docs {
+                engine.metadata.total_docs = doc_id.index() + 1;
+            }
+
+            if doc_id == 0 {
+                info!(
+                    "[INDEX] First doc indexed: {} fields, {} tokens",
+                    field_count, token_count
+                );
+            }
+            Ok(())
+        })
+    }
+
+    /// Commits buffered writes; on writable indexes the engine config and
+    /// metadata are committed with them in one transaction.
+    fn flush(&mut self) -> PyResult<()> {
+        info!("[RUST] Flushing buffered writes to disk...");
+        let timer = Timer::new("flush");
+
+        self.with_engine_mut(|engine| {
+            if engine.index.storage.is_read_only() {
+                return engine
+                    .index
+                    .storage
+                    .flush()
+                    .map_err(|e| PyRuntimeError::new_err(format!("Flush failed: {}", e)));
+            }
+            self.apply_custom_scoring(engine);
+            engine
+                .save_config()
+                .map_err(|e| PyRuntimeError::new_err(format!("Flush failed: {}", e)))
+        })?;
+
+        drop(timer);
+        info!("[RUST] Flush complete");
+        Ok(())
+    }
+
+    /// Flushes and pins the committed index for a long job: the returned
+    /// `Searcher` keeps its results while indexing goes on here.
+    fn searcher(&mut self) -> PyResult<PySearcher> {
+        self.with_engine_mut(|engine| {
+            self.apply_custom_scoring(engine);
+            Ok(PySearcher {
+                inner: Some(engine.searcher()?),
+            })
+        })
+    }
+
+    /// Shadow-reads `queries`, dicts like `search_complex`'s, against the
+    /// current scoring and a candidate overriding `field_weights`, `field_b`
+    /// and/or `k1`, both over the committed index. Returns how far the
+    /// candidate's rankings stray, see `comparison_dict`; nothing is changed.
+    #[pyo3(signature = (queries, top_k=10, field_weights=None, field_b=None, k1=None, divergence_tau=DEFAULT_DIVERGENCE_TAU))]
+    #[allow(clippy::too_many_arguments)]
+    fn compare_scoring<'py>(
+        &mut self,
+        py: Python<'py>,
+        queries: Vec<HashMap<String, String>>,
+        top_k: usize,
+        field_weights: Option<HashMap<String, f32>>,
+        field_b: Option<HashMap<String, f32>>,
+        k1: Option<f32>,
+        divergence_tau: f64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let (control, mut candidate) = self.with_engine_mut(|engine| {
+            self.apply_custom_scoring(engine);
+            Ok((engine.searcher()?, engine.searcher()?))
+        })?;
+        let field = |name: &str| {
+            self.map_field(name)
+                .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", name)))
+        };
+        for (name, weight) in field_weights.unwrap_or_default() {
+            candidate.scorer.field_weights.insert(field(&name)?, weight);
+        }
+        for (name, b) in field_b.unwrap_or_default() {
+            candidate.scorer.field_b.insert(field(&name)?, b);
+        }
+        if let Some(k1) = k1 {
+            candidate.scorer.k1 = k1;
+        }
+
+        let queries: Vec<StructuredQuery<RecordField>> = queries
+            .into_iter()
+            .map(|query_dict| {
+                let (fields, external_id) = parse_query_dict(query_dict);
+                StructuredQuery {
+                    fields,
+                    top_k,
+                    external_id,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let comparison =
+            ComparisonEngine::new(control, candidate).with_divergence_tau(divergence_tau);
+        let report = py.detach(|| comparison.run(queries))?;
+        comparison_dict(py, &report)
+    }
+
+    /// `min_should_match` is a count (3) or a percentage ("75%") of the query's
+    /// distinctive tokens a candidate must match. `preset` ("strict",
+    /// "balanced" or "fuzzy") runs the query with that settings bundle instead
+    /// of the engine's. With `diagnostics=True` the result is a
+    /// `(hits, diagnostics)` tuple, see `diagnostics_dict`; with
+    /// `set_field_scores(True)` each hit carries a third item, see there.
+    /// `vector`, the query's embedding, is blended into the ranking once
+    /// `load_vectors` has been called. `verbosity` overrides the engine's
+    /// (see `set_verbosity`) for this search.
+    #[pyo3(signature = (query_dict, top_k, blocking_k, min_should_match=None, diagnostics=false, preset=None, vector=None, verbosity=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn search_complex<'py>(
+        &self,
+        py: Python<'py>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        blocking_k: usize,
+        min_should_match: Option<MinShouldMatchArg>,
+        diagnostics: bool,
+        preset: Option<&str>,
+        vector: Option<Vec<f32>>,
+        verbosity: Option<&str>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let min_should_match = min_should_match.map(MinShouldMatchArg::parse).transpose()?;
+        let preset = preset.map(parse_preset).transpose()?;
+        let verbosity = match verbosity {
+            Some(name) => parse_verbosity(name)?,
+            None => self.with_search_engine(|engine| Ok(engine.verbosity))?,
+        };
+        let _verbosity = verbosity::scoped(verbosity);
+        query_info!("[RUST] search_complex called");
+        query_info!("[RUST] Query dict size: {}", query_dict.len());
+        query_info!("[RUST] top_k: {}", top_k);
+
+        let total_timer = Timer::new("search_complex::total");
+
+        let parse_timer = Timer::new("search_complex::parse_query");
+        let (query_fields, external_id) = parse_query_dict(query_dict);
         drop(parse_timer);
 
-        info!(
+        query_info!(
             "[RUST] Total query fields after parsing: {}",
             query_fields.len()
         );
 
-        if query_fields.is_empty() {
-            info!("[RUST] No valid query fields, returning empty results");
-            return Vec::new();
+        let with_diagnostics =
+            |hits: Bound<'py, PyAny>, stats: &QueryDiagnostics| -> PyResult<Bound<'py, PyAny>> {
+                if diagnostics {
+                    Ok((hits, diagnostics_dict(py, stats)?)
+                        .into_pyobject(py)?
+                        .into_any())
+                } else {
+                    Ok(hits)
+                }
+            };
+
+        if query_fields.is_empty() && external_id.is_none() {
+            query_info!("[RUST] No valid query fields, returning empty results");
+            return with_diagnostics(PyList::empty(py).into_any(), &QueryDiagnostics::default());
         }
 
         let query = StructuredQuery {
             fields: query_fields,
             top_k,
             blocking_k,
+            external_id,
+            min_should_match,
+            preset,
+            vector: vector.map(QueryVector),
+            verbosity: Some(verbosity),
+            ..Default::default()
         };
 
-        info!("[RUST] Executing search with blocking_k={}", blocking_k);
+        query_info!("[RUST] Executing search with blocking_k={}", blocking_k);
 
         let exec_timer = Timer::new("search_complex::execute");
 
-        // Use READ lock for searching (allows concurrent searches)
-        let mut global = GLOBAL_ENGINE.write().unwrap();
-        let engine = global.as_mut().expect("Engine not initialized");
-
-        // Apply custom weights if configured
-        if let Some(ref weights) = self.custom_weights {
-            info!("[RUST] Applying custom weights for search");
-            engine.scorer.field_weights = weights.clone();
-        }
-
-        if let Some(ref b_values) = self.custom_b_values {
-            info!("[RUST] Applying custom b-values for search");
-            engine.scorer.field_b = b_values.clone();
-        }
-
-        let results: Vec<(usize, f32)> = engine
-            .execute(query, blocking_k)
-            .into_iter()
+        // Read lock: concurrent searches and indexing don't block each other
+        let (search, field_scores) = self.with_search_engine(|engine| {
+            Ok((engine.execute_interruptible(query)?, engine.field_scores))
+        })?;
+        let results: Vec<(DocId, f32)> = search
+            .hits
+            .iter()
             .map(|hit| (hit.doc_id, hit.score))
             .collect();
 
         drop(exec_timer);
 
-        info!("[RUST] Search returned {} results", results.len());
+        query_info!("[RUST] Search returned {} results", results.len());
 
         for (i, (doc_id, score)) in results.iter().take(10).enumerate() {
             debug!(
@@ -343,48 +1663,1413 @@ impl PySearchEngine {
         }
 
         drop(total_timer);
-        info!("[RUST] Returning {} results to Python", results.len());
+        query_info!("[RUST] Returning {} results to Python", results.len());
+
+        let hits = if field_scores {
+            search
+                .hits
+                .iter()
+                .map(|hit| {
+                    let fields: HashMap<String, f32> = hit
+                        .field_scores
+                        .iter()
+                        .map(|(field, score)| (field_name(field), *score))
+                        .collect();
+                    (hit.doc_id, hit.score, fields)
+                })
+                .collect::<Vec<_>>()
+                .into_pyobject(py)?
+        } else {
+            results.into_pyobject(py)?
+        };
+        with_diagnostics(hits, &search.diagnostics)
+    }
+
+    /// Search bounded by `timeout_ms` and/or a `CancelToken`. Returns a dict with
+    /// "hits" and "interrupted"; interrupted searches return the hits scored so
+    /// far. The GIL is released meanwhile so other threads can cancel.
+    #[pyo3(signature = (query_dict, top_k, blocking_k=engine::DEFAULT_BLOCKING_K, timeout_ms=None, cancel=None))]
+    fn search_with_timeout<'py>(
+        &self,
+        py: Python<'py>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        blocking_k: usize,
+        timeout_ms: Option<u64>,
+        cancel: Option<PyRef<'py, PyCancelToken>>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let mut query = StructuredQuery {
+            fields,
+            top_k,
+            blocking_k,
+            external_id,
+            cancel: cancel.map(|token| token.inner.clone()),
+            ..Default::default()
+        };
+        if let Some(timeout_ms) = timeout_ms {
+            query = query.with_timeout(std::time::Duration::from_millis(timeout_ms));
+        }
+
+        let results = py.detach(|| {
+            self.with_search_engine(|engine| Ok(engine.execute_interruptible(query)?))
+        })?;
+        if results.interrupted {
+            info!("[RUST] Search interrupted, returning {} partial hits", results.hits.len());
+        }
 
-        results
+        let hits: Vec<(DocId, f32)> = results
+            .hits
+            .iter()
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect();
+
+        let result = PyDict::new(py);
+        result.set_item("hits", hits)?;
+        result.set_item("interrupted", results.interrupted)?;
+        Ok(result)
     }
 
-    fn get_total_docs(&self) -> usize {
-        let global = GLOBAL_ENGINE.read().unwrap(); // Read lock
-        let engine = global.as_ref().expect("Engine not initialized");
-        engine.metadata.total_docs
+    /// Like `search_complex`, but returns an iterator of `(doc_id, score)`
+    /// tuples in score order, created as they are consumed. Meant for large
+    /// `top_k` (e.g. dedup runs), where building every hit at once is costly.
+    #[pyo3(signature = (query_dict, top_k, blocking_k=engine::DEFAULT_BLOCKING_K))]
+    fn search_iter(
+        &self,
+        py: Python<'_>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        blocking_k: usize,
+    ) -> PyResult<PyHitIterator> {
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            blocking_k,
+            external_id,
+            ..Default::default()
+        };
+
+        let inner =
+            py.detach(|| self.with_search_engine(|engine| Ok(engine.execute_iter(query)?)))?;
+        info!("[RUST] Streaming {} hits to Python", inner.len());
+        Ok(PyHitIterator { inner })
     }
 
-    fn get_stats(&self) -> String {
-        let global = GLOBAL_ENGINE.read().unwrap(); // Read lock
-        let engine = global.as_ref().expect("Engine not initialized");
-        format!("Total docs indexed: {}", engine.metadata.total_docs)
+    /// Candidate doc ids of a query without scoring, as a sorted numpy uint32
+    /// array; meant for blocking in Splink/dedupe-style pipelines.
+    #[pyo3(signature = (query_dict, min_should_match=None, preset=None))]
+    fn candidates<'py>(
+        &self,
+        py: Python<'py>,
+        query_dict: HashMap<String, String>,
+        min_should_match: Option<MinShouldMatchArg>,
+        preset: Option<&str>,
+    ) -> PyResult<Bound<'py, PyArray1<u32>>> {
+        let min_should_match = min_should_match.map(MinShouldMatchArg::parse).transpose()?;
+        let preset = preset.map(parse_preset).transpose()?;
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            external_id,
+            min_should_match,
+            preset,
+            ..Default::default()
+        };
+
+        let candidates = self.with_search_engine(|engine| Ok(engine.candidates(&query)?))?;
+
+        Ok(candidates.iter().collect::<Vec<u32>>().into_pyarray(py))
     }
 
-    fn save_metadata(&self, path: &str) -> PyResult<()> {
-        let global = GLOBAL_ENGINE.read().unwrap(); // Read lock
-        let engine = global.as_ref().expect("Engine not initialized");
+    /// Counts of each stored value of `field` (e.g. "municipio") among the
+    /// query's candidates, as a dict ordered from most to least frequent.
+    #[pyo3(signature = (query_dict, field, min_should_match=None))]
+    fn facets<'py>(
+        &self,
+        py: Python<'py>,
+        query_dict: HashMap<String, String>,
+        field: &str,
+        min_should_match: Option<MinShouldMatchArg>,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let facet_field = RecordField::from_name(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", field)))?;
+        let min_should_match = min_should_match.map(MinShouldMatchArg::parse).transpose()?;
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            external_id,
+            min_should_match,
+            ..Default::default()
+        };
+
+        let facets = self.with_search_engine(|engine| Ok(engine.facets(&query, facet_field)?))?;
+
+        let dict = PyDict::new(py);
+        for (value, count) in facets {
+            dict.set_item(value, count)?;
+        }
+        Ok(dict)
+    }
+
+    /// Doc ids whose `field` holds `term` as a `Bitmap`. Terms are matched as
+    /// indexed: lowercase, without accents.
+    fn bitmap_for(&self, field: &str, term: &str) -> PyResult<PyBitmap> {
+        let field = RecordField::from_name(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", field)))?;
+        let inner = self.with_engine(|engine| Ok(engine.bitmap_for(field, term)))?;
+        Ok(PyBitmap { inner })
+    }
+
+    /// Approximate search scoring a random sample of the candidates. Returns a dict
+    /// with "hits", "approximate", candidate counts and a score "distribution".
+    #[pyo3(signature = (query_dict, top_k, sample_size=1000, seed=0))]
+    fn search_preview<'py>(
+        &self,
+        py: Python<'py>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        sample_size: usize,
+        seed: u64,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            external_id,
+            ..Default::default()
+        };
+
+        let preview = self.with_search_engine(|engine| {
+            Ok(engine.execute_preview(query, sample_size, seed)?)
+        })?;
+
+        let hits: Vec<(DocId, f32)> = preview
+            .hits
+            .iter()
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect();
+
+        let distribution = PyDict::new(py);
+        distribution.set_item("min", preview.distribution.min)?;
+        distribution.set_item("median", preview.distribution.median)?;
+        distribution.set_item("p90", preview.distribution.p90)?;
+        distribution.set_item("max", preview.distribution.max)?;
+
+        let result = PyDict::new(py);
+        result.set_item("hits", hits)?;
+        result.set_item("approximate", preview.approximate)?;
+        result.set_item("total_candidates", preview.total_candidates)?;
+        result.set_item("sampled_candidates", preview.sampled_candidates)?;
+        result.set_item("estimated_matches", preview.estimated_matches)?;
+        result.set_item("distribution", distribution)?;
+        Ok(result)
+    }
+
+    /// Search, then rerank the top-k by string similarity against the stored
+    /// fields. `metric` is "jaro_winkler" or "trigram"; `alpha` weighs the
+    /// similarity against the normalized BM25F score.
+    #[pyo3(signature = (query_dict, top_k, metric="jaro_winkler", alpha=similarity::DEFAULT_ALPHA, blocking_k=engine::DEFAULT_BLOCKING_K))]
+    fn search_similar(
+        &self,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        metric: &str,
+        alpha: f32,
+        blocking_k: usize,
+    ) -> PyResult<Vec<(DocId, f32)>> {
+        let metric = SimilarityMetric::from_name(metric)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown similarity metric: {}", metric)))?;
+        let reranker = SimilarityReranker::new(metric, alpha);
+
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            blocking_k,
+            external_id,
+            ..Default::default()
+        };
+
+        self.with_search_engine(|engine| {
+            Ok(engine
+                .execute_similar(query, &reranker)?
+                .into_iter()
+                .map(|hit| (hit.doc_id, hit.score))
+                .collect())
+        })
+    }
+
+    /// Match decision for one record: `("accepted", [(doc_id, score)])`,
+    /// `("review", [(doc_id, score), ...])` or `("no_match", [])`. Scores are
+    /// normalized to [0, 1]; see `SearchEngine::match_one`.
+    #[pyo3(signature = (query_dict, accept_threshold=0.8, review_threshold=0.5, top_k=10))]
+    fn match_one(
+        &self,
+        query_dict: HashMap<String, String>,
+        accept_threshold: f32,
+        review_threshold: f32,
+        top_k: usize,
+    ) -> PyResult<(&'static str, Vec<(DocId, f32)>)> {
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            external_id,
+            ..Default::default()
+        };
+
+        self.with_search_engine(|engine| {
+            let decision = engine.match_one(query, accept_threshold, review_threshold)?;
+            Ok(match decision {
+                MatchDecision::Accepted(hit) => ("accepted", vec![(hit.doc_id, hit.score)]),
+                MatchDecision::Review(hits) => (
+                    "review",
+                    hits.into_iter()
+                        .map(|hit| (hit.doc_id, hit.score))
+                        .collect(),
+                ),
+                MatchDecision::NoMatch => ("no_match", Vec::new()),
+            })
+        })
+    }
+
+    /// Search, then hand the top-k to `rerank`. The callback receives a list of
+    /// dicts with "doc_id", "score", "exact", "doc" (stored fields) and "matched"
+    /// (field -> matched query tokens) and must return a list of (doc_id, score).
+    #[pyo3(signature = (query_dict, top_k, rerank, blocking_k=engine::DEFAULT_BLOCKING_K))]
+    fn search_with_rerank(
+        &self,
+        py: Python<'_>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        rerank: Py<PyAny>,
+        blocking_k: usize,
+    ) -> PyResult<Vec<(DocId, f32)>> {
+        let (fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            blocking_k,
+            external_id,
+            ..Default::default()
+        };
+
+        self.with_search_engine(|engine| {
+            let hits = engine.execute_with_rerank(query, |candidates| -> PyResult<_> {
+                let items = PyList::empty(py);
+                let mut exact_ids = Vec::new();
+
+                for candidate in &candidates {
+                    if candidate.hit.exact {
+                        exact_ids.push(candidate.hit.doc_id);
+                    }
+
+                    let doc: HashMap<&str, &str> = candidate
+                        .doc
+                        .map(|doc| doc.iter().map(|(f, v)| (f.name(), v.as_str())).collect())
+                        .unwrap_or_default();
+                    let matched: HashMap<&str, Vec<String>> = candidate
+                        .matched
+                        .iter()
+                        .map(|(f, tokens)| (f.name(), tokens.clone()))
+                        .collect();
+
+                    let item = PyDict::new(py);
+                    item.set_item("doc_id", candidate.hit.doc_id)?;
+                    item.set_item("score", candidate.hit.score)?;
+                    item.set_item("exact", candidate.hit.exact)?;
+                    item.set_item("doc", doc)?;
+                    item.set_item("matched", matched)?;
+                    items.append(item)?;
+                }
+
+                let reranked: Vec<(DocId, f32)> = rerank.call1(py, (items,))?.extract(py)?;
+                Ok(reranked
+                    .into_iter()
+                    .map(|(doc_id, score)| SearchHit {
+                        doc_id,
+                        score,
+                        exact: exact_ids.contains(&doc_id),
+                        field_scores: Vec::new(),
+                    })
+                    .collect())
+            })?;
+
+            Ok(hits.into_iter().map(|hit| (hit.doc_id, hit.score)).collect())
+        })
+    }
+
+    /// Search returning only the stored values of `fields` for each hit, as
+    /// dicts with "doc_id", "score", "exact" and "doc". On a reopened LMDB
+    /// index the values are read from its per-field columns, only the
+    /// requested ones.
+    #[pyo3(signature = (query_dict, top_k, fields, blocking_k=engine::DEFAULT_BLOCKING_K))]
+    fn search_with_fields<'py>(
+        &self,
+        py: Python<'py>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        fields: Vec<String>,
+        blocking_k: usize,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let fields = fields
+            .iter()
+            .map(|name| {
+                self.map_field(name)
+                    .ok_or_else(|| PyValueError::new_err(format!("Unknown field '{}'", name)))
+            })
+            .collect::<PyResult<Vec<_>>>()?;
+        let (query_fields, external_id) = parse_query_dict(query_dict);
+        let query = StructuredQuery {
+            fields: query_fields,
+            top_k,
+            blocking_k,
+            external_id,
+            ..Default::default()
+        };
+
+        let hits =
+            self.with_search_engine(|engine| Ok(engine.search_with_fields(query, &fields)?))?;
+        let items = PyList::empty(py);
+        for (hit, doc) in hits {
+            let doc: HashMap<&str, String> = doc
+                .into_iter()
+                .map(|(field, value)| (field.name(), value))
+                .collect();
+            let item = PyDict::new(py);
+            item.set_item("doc_id", hit.doc_id)?;
+            item.set_item("score", hit.score)?;
+            item.set_item("exact", hit.exact)?;
+            item.set_item("doc", doc)?;
+            items.append(item)?;
+        }
+        Ok(items)
+    }
+
+    /// Match a whole record dict; an indexed "id" short-circuits to that doc.
+    fn search_record(
+        &self,
+        record_dict: HashMap<String, FieldValue>,
+        top_k: usize,
+    ) -> PyResult<Vec<(DocId, f32)>> {
+        info!("[RUST] search_record called");
+        let record = record_from_dict(record_dict);
+
+        self.with_search_engine(|engine| {
+            Ok(engine
+                .search_record(&record, top_k)?
+                .into_iter()
+                .map(|hit| (hit.doc_id, hit.score))
+                .collect())
+        })
+    }
 
-        let file = File::create(path)?;
-        let writer = BufWriter::new(file);
-        serialize_into(writer, &engine.metadata)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))
+    /// Bulk-index a Parquet file whose columns are named after the fields.
+    /// Returns the number of indexed rows. `progress` is called every
+    /// `progress_every` rows and at the end, like in `index_stream`; the ETA
+    /// comes from the file's row count.
+    #[cfg(feature = "parquet")]
+    #[pyo3(signature = (path, first_doc_id=DocId::default(), progress=None, progress_every=DEFAULT_PROGRESS_INTERVAL))]
+    fn index_parquet(
+        &mut self,
+        py: Python<'_>,
+        path: &str,
+        first_doc_id: DocId,
+        progress: Option<Py<PyAny>>,
+        progress_every: usize,
+    ) -> PyResult<usize> {
+        let mapping = crate::ingest::ColumnMapping::default();
+        let mut callback_error = None;
+        let indexed = self.with_engine_mut(|engine| {
+            Ok(crate::ingest::index_parquet(
+                engine,
+                std::path::Path::new(path),
+                &mapping,
+                first_doc_id,
+                python_progress(py, progress, progress_every, &mut callback_error),
+            )?)
+        })?;
+        match callback_error {
+            Some(e) => Err(e),
+            None => Ok(indexed),
+        }
+    }
+
+    /// Recompute metadata from the stored postings (e.g. after losing metadata.bin).
+    /// `progress` is called with `(terms_scanned, docs_seen)`.
+    #[pyo3(signature = (progress=None))]
+    fn rebuild_metadata(&mut self, py: Python<'_>, progress: Option<Py<PyAny>>) -> PyResult<()> {
+        let mut callback_error = None;
+
+        self.with_engine_mut(|engine| {
+            engine.rebuild_metadata(|p| {
+                let Some(ref callback) = progress else {
+                    return;
+                };
+                if callback_error.is_some() {
+                    return;
+                }
+                if let Err(e) = callback.call1(py, (p.terms_scanned, p.docs_seen)) {
+                    callback_error = Some(e);
+                }
+            })?;
+            Ok(())
+        })?;
+
+        match callback_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+
+    /// Replace one field of an indexed document, e.g. to fix a CEP typo,
+    /// without reindexing the whole record.
+    fn update_field(&mut self, doc_id: DocId, field: &str, text: &str) -> PyResult<()> {
+        let field = self
+            .map_field(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field '{}'", field)))?;
+        self.with_engine_mut(|engine| Ok(engine.update_field(doc_id, field, text)?))
+    }
+
+    /// Internal doc id of the record indexed with `external_id`, or None.
+    fn doc_id_for(&self, external_id: &str) -> PyResult<Option<DocId>> {
+        self.with_engine(|engine| Ok(engine.doc_id_for(external_id)))
+    }
+
+    /// External id of the record indexed as `doc_id`, or None.
+    fn external_id_for(&self, doc_id: DocId) -> PyResult<Option<String>> {
+        self.with_engine(|engine| Ok(engine.external_id_for(doc_id).map(str::to_string)))
+    }
+
+    /// Remove the record indexed with `external_id` from the index. Returns
+    /// False when no record has that id. Call `flush` to commit.
+    fn delete_by_external_id(&mut self, external_id: &str) -> PyResult<bool> {
+        let _writer = self.writer.lock().map_err(LfasError::from)?;
+        self.with_engine_mut(|engine| Ok(engine.delete_by_external_id(external_id)?))
+    }
+
+    /// Preload the postings of the `top_n` most frequent terms; with `touch_pages`
+    /// also read the whole index once. Call after loading or rebuilding metadata.
+    /// Returns `(cached_terms, touched_bytes)`.
+    #[pyo3(signature = (top_n=engine::DEFAULT_WARM_TERMS, touch_pages=false))]
+    fn warm(&mut self, top_n: usize, touch_pages: bool) -> PyResult<(usize, u64)> {
+        let report = self.with_engine_mut(|engine| Ok(engine.warm(top_n, touch_pages)?))?;
+        Ok((report.cached_terms, report.touched_bytes))
+    }
+
+    /// Projected size of an index of `total_count` records like `records`:
+    /// a dict with "unique_terms", "postings", "vocabulary_growth",
+    /// "postings_bytes", "metadata_bytes" and "map_size", the LMDB map size
+    /// to open the index with.
+    fn estimate<'py>(
+        &self,
+        py: Python<'py>,
+        records: Vec<HashMap<String, FieldValue>>,
+        total_count: usize,
+    ) -> PyResult<Bound<'py, PyDict>> {
+        let records: Vec<Record> = records.into_iter().map(record_from_dict).collect();
+        let estimate = self.with_engine(|engine| Ok(engine.estimate(&records, total_count)))?;
+
+        let dict = PyDict::new(py);
+        dict.set_item("sample_docs", estimate.sample_docs)?;
+        dict.set_item("total_docs", estimate.total_docs)?;
+        dict.set_item("unique_terms", estimate.unique_terms)?;
+        dict.set_item("postings", estimate.postings)?;
+        dict.set_item("vocabulary_growth", estimate.vocabulary_growth)?;
+        dict.set_item("postings_bytes", estimate.postings_bytes)?;
+        dict.set_item("metadata_bytes", estimate.metadata_bytes)?;
+        dict.set_item("map_size", estimate.map_size)?;
+        Ok(dict)
+    }
+
+    /// Recompute per-term max tf and max weighted tf from the stored postings.
+    /// `index_batch` does not track them; call this after loading.
+    fn recompute_term_stats(&mut self) -> PyResult<()> {
+        self.with_engine_mut(|engine| Ok(engine.recompute_term_stats()?))
+    }
+
+    /// `(max_tf, max_weighted_tf)` of a term, or None when it has no stats.
+    fn get_term_stats(&self, field: &str, term: &str) -> PyResult<Option<(u32, f32)>> {
+        let field = self
+            .map_field(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field '{}'", field)))?;
+        self.with_engine(|engine| {
+            Ok(engine
+                .metadata
+                .get_term_stats(&field, term)
+                .map(|stats| (stats.max_tf, stats.max_weighted_tf)))
+        })
+    }
+
+    fn get_total_docs(&self) -> PyResult<usize> {
+        self.with_engine(|engine| Ok(engine.metadata.total_docs))
+    }
+
+    fn get_stats(&self) -> PyResult<String> {
+        self.with_engine(|engine| Ok(format!("Total docs indexed: {}", engine.metadata.total_docs)))
+    }
+
+    /// Token count percentiles per field, over the documents holding it:
+    /// `{field: {"docs", "mean", "p50", "p90", "p99", "max"}}`.
+    fn get_length_stats<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let stats = self.with_engine(|engine| Ok(engine.metadata.length_stats()))?;
+        let result = PyDict::new(py);
+        for (field, stats) in stats {
+            let dict = PyDict::new(py);
+            dict.set_item("docs", stats.docs)?;
+            dict.set_item("mean", stats.mean)?;
+            dict.set_item("p50", stats.p50)?;
+            dict.set_item("p90", stats.p90)?;
+            dict.set_item("p99", stats.p99)?;
+            dict.set_item("max", stats.max)?;
+            result.set_item(field.name(), dict)?;
+        }
+        Ok(result)
+    }
+
+    /// Query latency and candidate histograms, postings hit rate and indexing
+    /// throughput as a dict.
+    fn get_metrics<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let metrics = self.with_engine(|engine| Ok(engine.metrics()))?;
+
+        let histogram = |histogram: &crate::metrics::Histogram| -> PyResult<Bound<'py, PyDict>> {
+            let dict = PyDict::new(py);
+            dict.set_item("count", histogram.count)?;
+            dict.set_item("sum", histogram.sum)?;
+            dict.set_item("mean", histogram.mean())?;
+            dict.set_item("p50", histogram.quantile(0.5))?;
+            dict.set_item("p90", histogram.quantile(0.9))?;
+            dict.set_item("p99", histogram.quantile(0.99))?;
+            dict.set_item("buckets", histogram.cumulative())?;
+            Ok(dict)
+        };
+
+        let result = PyDict::new(py);
+        result.set_item("queries_total", metrics.queries_total)?;
+        result.set_item("queries_interrupted", metrics.queries_interrupted)?;
+        result.set_item("queries_rejected", metrics.queries_rejected)?;
+        result.set_item("query_latency_ms", histogram(&metrics.query_latency_ms)?)?;
+        result.set_item("queue_wait_ms", histogram(&metrics.queue_wait_ms)?)?;
+        result.set_item("candidate_set_size", histogram(&metrics.candidate_set_size)?)?;
+        result.set_item("postings_hit_rate", metrics.postings_hit_rate())?;
+        result.set_item("docs_indexed", metrics.docs_indexed)?;
+        result.set_item("index_docs_per_sec", metrics.index_docs_per_sec())?;
+        Ok(result)
+    }
+
+    /// Metrics in the Prometheus text exposition format.
+    fn get_metrics_prometheus(&self) -> PyResult<String> {
+        self.with_engine(|engine| Ok(engine.metrics().to_prometheus()))
+    }
+
+    fn reset_metrics(&self) -> PyResult<()> {
+        self.with_engine(|engine| {
+            engine.metrics.reset();
+            Ok(())
+        })
+    }
+
+    /// How much searches log: "silent", "summary" (one line per search with
+    /// its counts and timings) or "debug" (every stage, the default).
+    /// `search_complex` takes a `verbosity` of its own for one search.
+    fn set_verbosity(&mut self, verbosity: &str) -> PyResult<()> {
+        let verbosity = parse_verbosity(verbosity)?;
+        self.with_engine_mut(|engine| {
+            engine.verbosity = verbosity;
+            info!("[RUST] Search verbosity set to {:?}", verbosity);
+            Ok(())
+        })
+    }
+
+    /// Log searches slower than `threshold_ms`, and every `sample_every`-th
+    /// search when given, keeping the last `capacity`. Entries hold stage
+    /// timings, candidate counts and per-field token stats, never the query
+    /// text. `threshold_ms=None` turns the log off.
+    #[pyo3(signature = (threshold_ms=Some(100.0), capacity=DEFAULT_SLOW_QUERY_CAPACITY, sample_every=None))]
+    fn set_slow_query_log(
+        &mut self,
+        threshold_ms: Option<f64>,
+        capacity: usize,
+        sample_every: Option<u64>,
+    ) -> PyResult<()> {
+        let log = match threshold_ms {
+            Some(ms) if !(ms.is_finite() && ms >= 0.0) => {
+                return Err(PyValueError::new_err(format!(
+                    "threshold_ms must be non-negative, got {}",
+                    ms
+                )));
+            }
+            Some(ms) => {
+                let log = SlowQueryLog::new(std::time::Duration::from_secs_f64(ms / 1000.0))
+                    .with_capacity(capacity);
+                Some(match sample_every {
+                    Some(n) => log.with_sample_every(n),
+                    None => log,
+                })
+            }
+            None => None,
+        };
+        self.with_engine_mut(|engine| {
+            info!(
+                "[RUST] Slow query log {}",
+                match &log {
+                    Some(log) => format!("at {:?}", log.threshold()),
+                    None => "off".to_string(),
+                }
+            );
+            engine.slow_log = log;
+            Ok(())
+        })
+    }
+
+    /// Logged searches, oldest first, as dicts.
+    fn slow_queries<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyList>> {
+        let entries = self.with_engine(|engine| Ok(engine.slow_queries()))?;
+        let list = PyList::empty(py);
+        for entry in &entries {
+            list.append(slow_query_dict(py, entry)?)?;
+        }
+        Ok(list)
+    }
+
+    /// Write the logged searches to `path` as a JSON array. Returns how many.
+    fn dump_slow_queries(&self, py: Python<'_>, path: &str) -> PyResult<usize> {
+        let entries = self.slow_queries(py)?;
+        let json: String = py
+            .import("json")?
+            .call_method1("dumps", (&entries,))?
+            .extract()?;
+        std::fs::write(path, json).map_err(|e| PyIOError::new_err(format!("{}: {}", path, e)))?;
+        Ok(entries.len())
+    }
+
+    fn clear_slow_queries(&self) -> PyResult<()> {
+        self.with_engine(|engine| {
+            if let Some(log) = &engine.slow_log {
+                log.clear();
+            }
+            Ok(())
+        })
+    }
+
+    /// Build the spell correction dictionary from the indexed terms.
+    #[pyo3(signature = (max_distance=spelling::DEFAULT_MAX_EDIT_DISTANCE))]
+    fn build_spell_index(&mut self, max_distance: usize) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.build_spell_index(max_distance);
+            info!("[RUST] Spell index built (max distance {})", max_distance);
+            Ok(())
+        })
+    }
+
+    /// Every token `text` yields, in text order, including the ones the field
+    /// rules drop: see `token_traces_list` for the entries. Classes are
+    /// "distinctive", "scored", "demoted", "weak" (n-grams) and "dropped".
+    /// Without `field` the default field rules apply.
+    #[pyo3(signature = (text, field=None))]
+    fn tokenize_debug<'py>(
+        &self,
+        py: Python<'py>,
+        text: &str,
+        field: Option<&str>,
+    ) -> PyResult<Bound<'py, PyList>> {
+        let field = field
+            .map(|name| {
+                self.map_field(name)
+                    .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", name)))
+            })
+            .transpose()?;
+        let traces = self.with_engine(|engine| {
+            Ok(match field {
+                Some(field) => engine.tokenize_debug(field, text),
+                None => tokenize_debug(text, &engine.tokenizer, &FieldTokenRules::default()),
+            })
+        })?;
+        token_traces_list(py, &traces)
+    }
+
+    /// Returns `(term, distance, df)` tuples, closest and most frequent first.
+    fn suggest_corrections(&self, field: &str, token: &str) -> PyResult<Vec<(String, usize, usize)>> {
+        let field = self
+            .map_field(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", field)))?;
+
+        self.with_engine(|engine| {
+            Ok(engine
+                .suggest_corrections(field, token)
+                .into_iter()
+                .map(|s| (s.term, s.distance, s.df))
+                .collect())
+        })
+    }
+
+    /// `(term, doc_count)` tuples of the terms indexed in `field` starting
+    /// with `prefix`, alphabetically, e.g. to fill a filter dropdown.
+    #[pyo3(signature = (field, prefix="", limit=100))]
+    fn field_values(
+        &self,
+        py: Python<'_>,
+        field: &str,
+        prefix: &str,
+        limit: usize,
+    ) -> PyResult<Vec<(String, usize)>> {
+        let field = self
+            .map_field(field)
+            .ok_or_else(|| PyValueError::new_err(format!("Unknown field: {}", field)))?;
+        py.detach(|| self.with_engine(|engine| Ok(engine.field_values(field, prefix, limit)?)))
+    }
+
+    /// Auto-correct query tokens that are not in the dictionary. Needs a spell index.
+    fn set_auto_correct(&mut self, enabled: bool) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            if enabled && engine.spelling.is_none() {
+                engine.build_spell_index(spelling::DEFAULT_MAX_EDIT_DISTANCE);
+            }
+            engine.auto_correct = enabled;
+            info!("[RUST] Auto-correct {}", if enabled { "enabled" } else { "disabled" });
+            Ok(())
+        })
+    }
+
+    /// Score query words not in the index ("ruamauriti") as the two up to
+    /// `max_parts` indexed terms they run together, each at least
+    /// `min_part_len` characters long. `max_parts=None` turns it off.
+    #[pyo3(signature = (max_parts=Some(DEFAULT_MAX_PARTS), min_part_len=DEFAULT_MIN_PART_LEN))]
+    fn set_decompounding(&mut self, max_parts: Option<usize>, min_part_len: usize) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.decompounder =
+                max_parts.map(|max_parts| Decompounder::new(max_parts, min_part_len));
+            info!("[RUST] Decompounding set to {:?}", engine.decompounder);
+            Ok(())
+        })
+    }
+
+    /// Build the per-field term co-occurrence counts used by query expansion.
+    fn build_cooccurrence(&mut self) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.build_cooccurrence()?;
+            Ok(())
+        })
+    }
+
+    /// Add the `terms` most frequent co-occurring terms of each query token,
+    /// scored at `weight`. `terms=0` disables expansion. Builds the
+    /// co-occurrence counts if needed.
+    #[pyo3(signature = (terms=3, weight=0.3, min_count=2))]
+    fn set_query_expansion(&mut self, terms: usize, weight: f32, min_count: u32) -> PyResult<()> {
+        if !(0.0..=1.0).contains(&weight) {
+            return Err(PyValueError::new_err(format!(
+                "weight must be between 0 and 1, got {}",
+                weight
+            )));
+        }
+        self.with_engine_mut(|engine| {
+            if terms == 0 {
+                engine.expansion = None;
+                info!("[RUST] Query expansion disabled");
+                return Ok(());
+            }
+            if engine.cooccurrence.is_none() {
+                engine.build_cooccurrence()?;
+            }
+            engine.expansion = Some(QueryExpansion {
+                terms,
+                weight,
+                min_count,
+            });
+            info!(
+                "[RUST] Query expansion: {} terms at weight {}, min count {}",
+                terms, weight, min_count
+            );
+            Ok(())
+        })
+    }
+
+    fn save_spell_index(&self, path: &str) -> PyResult<()> {
+        self.with_engine(|engine| {
+            let spelling = engine.spelling.as_ref().ok_or(LfasError::NotInitialized)?;
+            spelling.save(std::path::Path::new(path))?;
+            Ok(())
+        })
+    }
+
+    fn load_spell_index(&mut self, path: &str) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.spelling = Some(SpellIndex::load(std::path::Path::new(path))?);
+            Ok(())
+        })
+    }
+
+    /// Rebuild metadata if the index changed since the last sync. Returns
+    /// whether anything was rebuilt. Searches on read-only engines do this
+    /// automatically.
+    fn refresh(&mut self) -> PyResult<bool> {
+        self.with_engine_mut(|engine| Ok(engine.refresh()?))
+    }
+
+    /// Number of committed write batches in the LMDB index.
+    fn get_generation(&self) -> PyResult<u64> {
+        self.with_engine(|engine| Ok(engine.index.storage.generation().map_err(LfasError::from)?))
+    }
+
+    /// Store weights, b-values, tokenizer and field rules inside the index.
+    fn save_config(&mut self) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            self.apply_custom_scoring(engine);
+            engine.save_config()?;
+            info!("[RUST] Engine config saved with the index");
+            Ok(())
+        })
+    }
+
+    /// Apply the config stored inside the index, replacing any custom weights.
+    /// Returns false when the index has none.
+    fn load_config(&mut self) -> PyResult<bool> {
+        let loaded = self.with_engine_mut(|engine| Ok(engine.load_config()?))?;
+        if loaded {
+            self.custom_weights = None;
+            self.custom_b_values = None;
+            self.custom_tf_options = None;
+        }
+        Ok(loaded)
+    }
+
+    /// Swap k1, field weights, b-values, the fallback policy and query
+    /// synonyms on the live engine, without reopening the index. `config` is
+    /// a dict, or the path of a JSON file holding one, with any of the keys
+    /// `k1`, `field_weights`, `field_b`, `fallback` (a dict with the
+    /// arguments of `set_fallback_policy`, defaults for those left out) and
+    /// `synonyms` (`{"av": ["avenida"], ...}`, replacing the current ones).
+    /// Fields not listed keep their weight and b-value. Everything is checked first and
+    /// applied at once between two searches; pending `set_field_weights`
+    /// and `set_field_b_values` overrides are folded in.
+    fn reload_config(&mut self, py: Python<'_>, config: &Bound<'_, PyAny>) -> PyResult<()> {
+        let config = match config.extract::<String>() {
+            Ok(path) => {
+                let text = std::fs::read_to_string(&path)
+                    .map_err(|e| PyIOError::new_err(format!("{}: {}", path, e)))?;
+                py.import("json")?.call_method1("loads", (text,))?
+            }
+            Err(_) => config.clone(),
+        };
+        let reload = self.parse_reload(config.extract()?)?;
+
+        self.with_engine_mut(|engine| {
+            self.apply_custom_scoring(engine);
+            Ok(engine.reload_config(reload)?)
+        })?;
+        self.custom_weights = None;
+        self.custom_b_values = None;
+        self.custom_tf_options = None;
+        Ok(())
+    }
+
+    /// Hash `path` and record it as a source of this build. Parquet files
+    /// given to `index_parquet` are recorded automatically.
+    fn record_source(&mut self, path: &str) -> PyResult<()> {
+        self.with_engine_mut(|engine| Ok(engine.record_source(std::path::Path::new(path))?))
+    }
+
+    /// Store the build provenance (sources, record count, timestamp, crate
+    /// version, tokenizer hash) inside the index and return it as a dict.
+    fn save_provenance<'py>(&mut self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let provenance = self.with_engine_mut(|engine| Ok(engine.save_provenance()?))?;
+        provenance_dict(py, &provenance)
+    }
+
+    /// The provenance stored inside the index, or None if none was saved.
+    fn provenance<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let provenance = self.with_engine(|engine| Ok(engine.provenance()?))?;
+        provenance
+            .map(|provenance| provenance_dict(py, &provenance))
+            .transpose()
+    }
+
+    fn save_metadata(&self, path: &str) -> PyResult<()> {
+        self.with_engine(|engine| {
+            let file = File::create(path)?;
+            let writer = BufWriter::new(file);
+            serialize_into(writer, &engine.metadata).map_err(LfasError::from)?;
+            Ok(())
+        })
     }
 
     fn load_metadata(&mut self, path: &str) -> PyResult<()> {
-        let mut global = GLOBAL_ENGINE.write().unwrap(); // Write lock
-        let engine = global.as_mut().expect("Engine not initialized");
+        self.with_engine_mut(|engine| {
+            let file = File::open(path)?;
+            let reader = BufReader::new(file);
+            engine.metadata = deserialize_from(reader).map_err(LfasError::from)?;
+            Ok(())
+        })
+    }
+
+    /// Precomputes the quantized score of every posting with the current
+    /// weights. Returns the number of postings. Rebuild after changing any
+    /// scorer parameter or indexing; stale impacts are ignored.
+    fn rebuild_impacts(&mut self) -> PyResult<usize> {
+        self.with_engine_mut(|engine| Ok(engine.rebuild_impacts()?))
+    }
+
+    fn save_impacts(&self, path: &str) -> PyResult<()> {
+        self.with_engine(|engine| {
+            let Some(impacts) = &engine.impacts else {
+                return Err(PyValueError::new_err("No impacts built"));
+            };
+            let file = File::create(path)?;
+            let writer = BufWriter::new(file);
+            serialize_into(writer, impacts).map_err(LfasError::from)?;
+            Ok(())
+        })
+    }
+
+    fn load_impacts(&mut self, path: &str) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            let file = File::open(path)?;
+            let reader = BufReader::new(file);
+            engine.impacts = Some(deserialize_from(reader).map_err(LfasError::from)?);
+            Ok(())
+        })
+    }
+
+    fn clear_impacts(&mut self) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.impacts = None;
+            Ok(())
+        })
+    }
+
+    /// Memoize idfs and length normalizations across searches, up to
+    /// `capacity` entries of each; worth it when the same terms and documents
+    /// are scored over and over, as in a dedup run. Scores don't change.
+    /// The cache empties itself when the index or scorer settings change.
+    /// `capacity=None` turns it off.
+    #[pyo3(signature = (capacity=Some(DEFAULT_SCORE_CACHE_CAPACITY)))]
+    fn set_score_cache(&mut self, capacity: Option<usize>) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.score_cache = capacity.map(ScoreCache::new);
+            info!("[RUST] Score cache capacity set to {:?}", capacity);
+            Ok(())
+        })
+    }
 
-        let file = File::open(path)?;
-        let reader = BufReader::new(file);
-        engine.metadata = deserialize_from(reader)
-            .map_err(|e| PyErr::new::<pyo3::exceptions::PyIOError, _>(e.to_string()))?;
+    /// Dict of "terms" and "norms" (entries held), "hits", "misses" and
+    /// "invalidations", or None when the cache is off.
+    fn score_cache_stats<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        let stats = self.with_engine(|engine| Ok(engine.score_cache.as_ref().map(ScoreCache::stats)))?;
+        let Some(stats) = stats else {
+            return Ok(None);
+        };
+        let dict = PyDict::new(py);
+        dict.set_item("terms", stats.terms)?;
+        dict.set_item("norms", stats.norms)?;
+        dict.set_item("hits", stats.hits)?;
+        dict.set_item("misses", stats.misses)?;
+        dict.set_item("invalidations", stats.invalidations)?;
+        Ok(Some(dict))
+    }
+
+    /// Writes document embeddings as a matrix file for `load_vectors`: row
+    /// `i` of the 2-D float32 array is the vector of doc id `i`, all zeros
+    /// for documents without one.
+    #[staticmethod]
+    fn save_vectors(path: &str, vectors: PyReadonlyArray2<'_, f32>) -> PyResult<()> {
+        let vectors = vectors.as_array();
+        let rows = vectors
+            .rows()
+            .into_iter()
+            .enumerate()
+            .map(|(doc_id, row)| Ok((DocId::try_from(doc_id)?, row.to_vec())))
+            .collect::<Result<Vec<_>, LfasError>>()?;
+        let store = VectorStore::from_vectors(vectors.ncols(), rows)?;
+        store.save(std::path::Path::new(path))?;
         Ok(())
     }
+
+    /// Memory-maps the embeddings at `path` and blends their cosine
+    /// similarity with queries given a `vector`: the best `depth` BM25F hits
+    /// are rescored by `alpha * cosine + (1 - alpha) * bm25 / max_bm25`.
+    #[pyo3(signature = (path, alpha=DEFAULT_VECTOR_ALPHA, depth=DEFAULT_RERANK_DEPTH))]
+    fn load_vectors(&mut self, path: &str, alpha: f32, depth: usize) -> PyResult<()> {
+        let store = VectorStore::open(std::path::Path::new(path))?;
+        self.with_engine_mut(|engine| {
+            info!(
+                "[RUST] Loaded {} vectors of {} dimensions",
+                store.len(),
+                store.dims()
+            );
+            engine.vectors = Some(
+                VectorReranker::new(store)
+                    .with_alpha(alpha)
+                    .with_depth(depth),
+            );
+            Ok(())
+        })
+    }
+
+    fn clear_vectors(&mut self) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            engine.vectors = None;
+            Ok(())
+        })
+    }
+
+    /// Moves document frequencies and lengths into an LMDB store at `path`
+    /// (not the index directory) and reads them from there with a small
+    /// cache. Call again after `load_metadata` to re-attach the store.
+    #[pyo3(signature = (path, cache_size=None))]
+    fn spill_metadata(&mut self, path: &str, cache_size: Option<usize>) -> PyResult<()> {
+        self.with_engine_mut(|engine| {
+            let store = LmdbMetadataStore::open(std::path::Path::new(path), LmdbOptions::new())
+                .map_err(LfasError::from)?
+                .cache_capacity(cache_size.unwrap_or(DEFAULT_METADATA_CACHE));
+            engine.metadata.spill_to(Arc::new(store))?;
+            info!("[METADATA] Spilled term_df and document lengths to {}", path);
+            Ok(())
+        })
+    }
+
+    /// Writes document lengths into the index as bitpacked columns. Returns
+    /// the number of columns written.
+    fn persist_length_columns(&self) -> PyResult<usize> {
+        self.with_engine(|engine| Ok(engine.persist_length_columns()?))
+    }
+
+    /// Reads document lengths from the persisted columns instead of memory.
+    /// Returns False when none were persisted. Call again after
+    /// `load_metadata` or `rebuild_metadata`.
+    fn use_length_columns(&mut self) -> PyResult<bool> {
+        self.with_engine_mut(|engine| Ok(engine.use_length_columns()?))
+    }
+}
+
+type Sharded = ShardedEngine<LmdbStorage<RecordField>>;
+
+fn parse_shard_key(name: &str) -> PyResult<ShardKey> {
+    if name.eq_ignore_ascii_case("hash") {
+        return Ok(ShardKey::Hash);
+    }
+    RecordField::from_name(name)
+        .map(ShardKey::Field)
+        .ok_or_else(|| PyValueError::new_err(format!("Unknown shard key: {}", name)))
+}
+
+/// An index split across `shard_count` LMDB environments under `path`, with
+/// the indexing and search methods of `PySearchEngine`. `key` is "hash" or a
+/// field name such as "estado".
+#[pyclass]
+pub struct PyShardedEngine {
+    inner: RwLock<Sharded>,
+}
+
+#[pymethods]
+impl PyShardedEngine {
+    #[new]
+    #[pyo3(signature = (path="./lmdb_shards", shard_count=4, key="hash", map_size=None, max_map_size=None, max_readers=None, sync_mode=None, read_only=false, fallback_read_only=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        path: &str,
+        shard_count: usize,
+        key: &str,
+        map_size: Option<usize>,
+        max_map_size: Option<usize>,
+        max_readers: Option<u32>,
+        sync_mode: Option<&str>,
+        read_only: bool,
+        fallback_read_only: bool,
+    ) -> PyResult<Self> {
+        let options = lmdb_options(
+            map_size,
+            max_map_size,
+            max_readers,
+            sync_mode,
+            read_only,
+            fallback_read_only,
+        )?;
+        let engine = ShardedEngine::open(
+            std::path::Path::new(path),
+            shard_count,
+            parse_shard_key(key)?,
+            options,
+        )?;
+        Ok(Self {
+            inner: RwLock::new(engine),
+        })
+    }
+
+    fn shard_count(&self) -> PyResult<usize> {
+        Ok(self.inner.read().map_err(LfasError::from)?.shard_count())
+    }
+
+    /// Field values may be strings or lists of strings (aliases). Runs with
+    /// the GIL released.
+    fn index_batch(
+        &self,
+        py: Python<'_>,
+        records: Vec<(usize, HashMap<String, FieldValue>)>,
+    ) -> PyResult<()> {
+        let records: Vec<_> = records
+            .into_iter()
+            .map(|(doc_id, record_dict)| (doc_id, record_from_dict(record_dict)))
+            .collect();
+        py.detach(|| {
+            let mut engine = self.inner.write().map_err(LfasError::from)?;
+            Ok(engine.index_batch(&records)?)
+        })
+    }
+
+    fn index_dict(&self, doc_id: usize, record_dict: HashMap<String, FieldValue>) -> PyResult<()> {
+        let record = record_from_dict(record_dict);
+        let mut engine = self.inner.write().map_err(LfasError::from)?;
+        Ok(engine.index_record(doc_id, &record)?)
+    }
+
+    fn flush(&self) -> PyResult<()> {
+        let mut engine = self.inner.write().map_err(LfasError::from)?;
+        Ok(engine.flush()?)
+    }
+
+    /// Same as `PySearchEngine.search_complex`; the shards are searched in
+    /// parallel with the GIL released.
+    #[pyo3(signature = (query_dict, top_k, blocking_k, min_should_match=None, preset=None))]
+    fn search_complex(
+        &self,
+        py: Python<'_>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        blocking_k: usize,
+        min_should_match: Option<MinShouldMatchArg>,
+        preset: Option<&str>,
+    ) -> PyResult<Vec<(DocId, f32)>> {
+        let min_should_match = min_should_match.map(MinShouldMatchArg::parse).transpose()?;
+        let preset = preset.map(parse_preset).transpose()?;
+        let (fields, external_id) = parse_query_dict(query_dict);
+        if fields.is_empty() && external_id.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            blocking_k,
+            external_id,
+            min_should_match,
+            preset,
+            ..Default::default()
+        };
+        let hits = py.detach(|| {
+            let engine = self.inner.read().map_err(LfasError::from)?;
+            engine.execute(query)
+        })?;
+        Ok(hits.into_iter().map(|hit| (hit.doc_id, hit.score)).collect())
+    }
+
+    fn get_total_docs(&self) -> PyResult<usize> {
+        Ok(self.inner.read().map_err(LfasError::from)?.total_docs())
+    }
+}
+
+/// Cancels a `search_with_timeout` call from another thread.
+#[pyclass(name = "CancelToken")]
+pub struct PyCancelToken {
+    inner: CancelToken,
+}
+
+#[pymethods]
+impl PyCancelToken {
+    #[new]
+    fn new() -> Self {
+        Self {
+            inner: CancelToken::new(),
+        }
+    }
+
+    fn cancel(&self) {
+        self.inner.cancel();
+    }
+
+    fn is_cancelled(&self) -> bool {
+        self.inner.is_cancelled()
+    }
+}
+
+/// Hits of `search_iter`, yielded as `(doc_id, score)` tuples.
+#[pyclass(name = "HitIterator")]
+pub struct PyHitIterator {
+    inner: HitIter<RecordField>,
+}
+
+#[pymethods]
+impl PyHitIterator {
+    fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __next__(&mut self) -> Option<(DocId, f32)> {
+        self.inner.next().map(|hit| (hit.doc_id, hit.score))
+    }
+
+    fn __length_hint__(&self) -> usize {
+        self.inner.len()
+    }
+}
+
+/// One pinned generation of the index, from `PySearchEngine.searcher()`.
+/// The generation is retired on `close()` or once the searcher is collected.
+#[pyclass(name = "Searcher")]
+pub struct PySearcher {
+    inner: Option<Searcher<RecordField>>,
+}
+
+impl PySearcher {
+    fn searcher(&self) -> PyResult<&Searcher<RecordField>> {
+        self.inner
+            .as_ref()
+            .ok_or_else(|| PyRuntimeError::new_err("Searcher is closed"))
+    }
+}
+
+#[pymethods]
+impl PySearcher {
+    fn generation(&self) -> PyResult<u64> {
+        let storage = &self.searcher()?.index.storage;
+        Ok(storage.generation().map_err(LfasError::from)?)
+    }
+
+    /// Same as `PySearchEngine.search_complex`, against the pinned
+    /// generation, with the GIL released.
+    #[pyo3(signature = (query_dict, top_k, blocking_k, min_should_match=None, preset=None))]
+    fn search_complex(
+        &self,
+        py: Python<'_>,
+        query_dict: HashMap<String, String>,
+        top_k: usize,
+        blocking_k: usize,
+        min_should_match: Option<MinShouldMatchArg>,
+        preset: Option<&str>,
+    ) -> PyResult<Vec<(DocId, f32)>> {
+        let min_should_match = min_should_match.map(MinShouldMatchArg::parse).transpose()?;
+        let preset = preset.map(parse_preset).transpose()?;
+        let (fields, external_id) = parse_query_dict(query_dict);
+        if fields.is_empty() && external_id.is_none() {
+            return Ok(Vec::new());
+        }
+
+        let query = StructuredQuery {
+            fields,
+            top_k,
+            blocking_k,
+            external_id,
+            min_should_match,
+            preset,
+            ..Default::default()
+        };
+        let searcher = self.searcher()?;
+        let hits = py.detach(|| searcher.execute(query, blocking_k))?;
+        Ok(hits.into_iter().map(|hit| (hit.doc_id, hit.score)).collect())
+    }
+
+    fn get_total_docs(&self) -> PyResult<usize> {
+        Ok(self.searcher()?.metadata.total_docs)
+    }
+
+    /// Releases the pinned generation.
+    fn close(&mut self) {
+        self.inner = None;
+    }
+}
+
+/// A roaring bitmap of doc ids, for composing custom blocking logic.
+/// Combine with `and_`/`or_`/`and_not` or the `&`, `|` and `-` operators.
+#[pyclass(name = "Bitmap")]
+#[derive(Clone, Default)]
+pub struct PyBitmap {
+    inner: RoaringBitmap,
+}
+
+#[pymethods]
+impl PyBitmap {
+    #[new]
+    #[pyo3(signature = (doc_ids=None))]
+    fn new(doc_ids: Option<Vec<u32>>) -> Self {
+        Self {
+            inner: doc_ids.unwrap_or_default().into_iter().collect(),
+        }
+    }
+
+    /// Reads a bitmap in the portable roaring serialization format.
+    #[staticmethod]
+    fn from_bytes(data: &[u8]) -> PyResult<Self> {
+        let inner = RoaringBitmap::deserialize_from(data)
+            .map_err(|e| PyValueError::new_err(format!("Invalid roaring bitmap: {}", e)))?;
+        Ok(Self { inner })
+    }
+
+    /// The portable roaring serialization format, readable by other roaring
+    /// libraries (e.g. `pyroaring.BitMap.deserialize`).
+    fn to_bytes<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyBytes>> {
+        let mut bytes = Vec::with_capacity(self.inner.serialized_size());
+        self.inner.serialize_into(&mut bytes)?;
+        Ok(PyBytes::new(py, &bytes))
+    }
+
+    /// Sorted doc ids as a numpy uint32 array.
+    fn to_numpy<'py>(&self, py: Python<'py>) -> Bound<'py, PyArray1<u32>> {
+        self.inner.iter().collect::<Vec<u32>>().into_pyarray(py)
+    }
+
+    fn and_(&self, other: &PyBitmap) -> Self {
+        Self {
+            inner: &self.inner & &other.inner,
+        }
+    }
+
+    fn or_(&self, other: &PyBitmap) -> Self {
+        Self {
+            inner: &self.inner | &other.inner,
+        }
+    }
+
+    fn and_not(&self, other: &PyBitmap) -> Self {
+        Self {
+            inner: &self.inner - &other.inner,
+        }
+    }
+
+    fn __and__(&self, other: &PyBitmap) -> Self {
+        self.and_(other)
+    }
+
+    fn __or__(&self, other: &PyBitmap) -> Self {
+        self.or_(other)
+    }
+
+    fn __sub__(&self, other: &PyBitmap) -> Self {
+        self.and_not(other)
+    }
+
+    fn __len__(&self) -> usize {
+        self.inner.len() as usize
+    }
+
+    fn __contains__(&self, doc_id: u32) -> bool {
+        self.inner.contains(doc_id)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Bitmap(len={})", self.inner.len())
+    }
 }
 
 #[pymodule]
 fn lfas(m: &Bound<'_, PyModule>) -> PyResult<()> {
     info!("[RUST] PySearchEngine class registered");
     m.add_class::<PySearchEngine>()?;
+    m.add_class::<PyShardedEngine>()?;
+    m.add_class::<PyCancelToken>()?;
+    m.add_class::<PyHitIterator>()?;
+    m.add_class::<PySearcher>()?;
+    m.add_class::<PyBitmap>()?;
+    m.add("OverloadedError", m.py().get_type::<OverloadedError>())?;
     Ok(())
 }
@@ -0,0 +1,177 @@
+//! Fluent construction of [`StructuredQuery`], checked before it reaches the
+//! engine: `Query::new().field(Rua, "mauriti").filter(Estado, "PA").build()`.
+
+use crate::cancel::CancelToken;
+use crate::engine::QueryPreset;
+use crate::error::LfasError;
+use crate::{FieldPresence, MinShouldMatch, Occur, QueryLimits, StructuredQuery};
+use std::time::Duration;
+
+/// Shorter name for [`QueryBuilder`].
+pub type Query<F> = QueryBuilder<F>;
+
+#[derive(Debug, Clone)]
+pub struct QueryBuilder<F> {
+    query: StructuredQuery<F>,
+    timeout: Option<Duration>,
+    limits: QueryLimits,
+}
+
+impl<F> Default for QueryBuilder<F> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<F> QueryBuilder<F> {
+    pub fn new() -> Self {
+        Self {
+            query: StructuredQuery::default(),
+            timeout: None,
+            limits: QueryLimits::default(),
+        }
+    }
+
+    /// Adds a scored clause. A field may appear in several clauses.
+    pub fn field(self, field: F, text: impl Into<String>) -> Self {
+        self.clause(field, text, Occur::Should)
+    }
+
+    /// Adds a scored clause candidates must match: they hold every full
+    /// token of `text` in `field`.
+    pub fn must(self, field: F, text: impl Into<String>) -> Self {
+        self.clause(field, text, Occur::Must)
+    }
+
+    /// Drops documents holding every full token of `text` in `field`, e.g.
+    /// the same street in another city. Not scored.
+    pub fn must_not(self, field: F, text: impl Into<String>) -> Self {
+        self.clause(field, text, Occur::MustNot)
+    }
+
+    /// Adds a clause of the given occurrence.
+    pub fn clause(mut self, field: F, text: impl Into<String>, occur: Occur) -> Self {
+        if occur != Occur::Should {
+            // Clauses past the end of `occur` are Should
+            self.query
+                .occur
+                .resize(self.query.fields.len(), Occur::Should);
+            self.query.occur.push(occur);
+        }
+        self.query.fields.push((field, text.into()));
+        self
+    }
+
+    /// Adds a hard constraint: only documents holding every full token of
+    /// `text` in `field` are kept. Filters are not scored.
+    pub fn filter(mut self, field: F, text: impl Into<String>) -> Self {
+        self.query.filters.push((field, text.into()));
+        self
+    }
+
+    /// Keeps only documents where `field` is empty or missing. Not scored.
+    #[allow(clippy::wrong_self_convention)]
+    pub fn is_empty(mut self, field: F) -> Self {
+        self.query.presence.push((field, FieldPresence::Empty));
+        self
+    }
+
+    /// Keeps only documents with at least one token in `field`. Not scored.
+    pub fn not_empty(mut self, field: F) -> Self {
+        self.query.presence.push((field, FieldPresence::NotEmpty));
+        self
+    }
+
+    pub fn top_k(mut self, top_k: usize) -> Self {
+        self.query.top_k = top_k;
+        self
+    }
+
+    pub fn blocking_k(mut self, blocking_k: usize) -> Self {
+        self.query.blocking_k = blocking_k;
+        self
+    }
+
+    /// Tries an exact external id lookup before the fuzzy search.
+    pub fn external_id(mut self, id: impl Into<String>) -> Self {
+        self.query.external_id = Some(id.into());
+        self
+    }
+
+    pub fn min_should_match(mut self, min_should_match: MinShouldMatch) -> Self {
+        self.query.min_should_match = Some(min_should_match);
+        self
+    }
+
+    pub fn preset(mut self, preset: QueryPreset) -> Self {
+        self.query.preset = Some(preset);
+        self
+    }
+
+    /// Embedding of the query, for an engine with a
+    /// [`VectorReranker`](crate::vectors::VectorReranker).
+    pub fn vector(mut self, vector: Vec<f32>) -> Self {
+        self.query.vector = Some(vector.into());
+        self
+    }
+
+    /// `true` selects [`QueryPreset::Fuzzy`]; `false` clears any preset, so
+    /// the engine's own settings apply.
+    pub fn fuzzy(mut self, fuzzy: bool) -> Self {
+        self.query.preset = fuzzy.then_some(QueryPreset::Fuzzy);
+        self
+    }
+
+    /// Deadline counted from [`build`](Self::build).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn cancel(mut self, cancel: CancelToken) -> Self {
+        self.query.cancel = Some(cancel);
+        self
+    }
+
+    /// Limits checked by `build`, e.g. the engine's own; defaults otherwise.
+    pub fn limits(mut self, limits: QueryLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Rejects blank clauses, queries with nothing to search for and
+    /// anything outside the limits.
+    pub fn build(self) -> Result<StructuredQuery<F>, LfasError> {
+        let Self {
+            mut query,
+            timeout,
+            limits,
+        } = self;
+
+        if let Some(i) = query
+            .fields
+            .iter()
+            .position(|(_, text)| text.trim().is_empty())
+        {
+            return Err(LfasError::InvalidQuery(format!("clause {} is empty", i)));
+        }
+        if let Some(i) = query
+            .filters
+            .iter()
+            .position(|(_, text)| text.trim().is_empty())
+        {
+            return Err(LfasError::InvalidQuery(format!("filter {} is empty", i)));
+        }
+        if query.scored_fields().next().is_none() && query.external_id.is_none() {
+            return Err(LfasError::InvalidQuery(
+                "a query needs a scored field clause or an external id".to_string(),
+            ));
+        }
+        query.validate(&limits)?;
+
+        if let Some(timeout) = timeout {
+            query = query.with_timeout(timeout);
+        }
+        Ok(query)
+    }
+}
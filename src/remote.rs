@@ -0,0 +1,152 @@
+//! Opening prebuilt indexes straight from object storage. An index archive is
+//! a [bundle](crate::bundle), or any zstd-compressed tar of the LMDB directory
+//! with a [`MANIFEST_FILE`] at its root. Each object version is downloaded
+//! and unpacked once into the local cache, checked against the manifest, and
+//! opened read-only from there.
+
+use crate::RecordField;
+use crate::bundle::{cache_entry, install, local_version};
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::storage::LmdbStorage;
+use crate::timing::Timer;
+use object_store::ObjectStore;
+use object_store::aws::AmazonS3Builder;
+use object_store::path::Path as ObjectPath;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tokio_stream::StreamExt;
+use tracing::info;
+
+pub use crate::bundle::{
+    CACHE_DIR_ENV, MANIFEST_FILE, default_cache_dir, pack, verify_manifest, write_manifest,
+};
+
+fn remote_error(e: impl std::fmt::Display) -> LfasError {
+    LfasError::Storage(format!("remote index: {}", e))
+}
+
+/// Where an index archive lives.
+enum Source {
+    S3 { bucket: String, key: String },
+    Local(PathBuf),
+}
+
+impl Source {
+    /// `s3://bucket/key`, `file:///path` or a plain path.
+    fn parse(uri: &str) -> Result<Self, LfasError> {
+        if let Some(rest) = uri.strip_prefix("s3://") {
+            return match rest.split_once('/') {
+                Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => Ok(Source::S3 {
+                    bucket: bucket.to_string(),
+                    key: key.to_string(),
+                }),
+                _ => Err(remote_error(format!(
+                    "expected s3://bucket/key, got '{}'",
+                    uri
+                ))),
+            };
+        }
+        if let Some(path) = uri.strip_prefix("file://") {
+            return Ok(Source::Local(PathBuf::from(path)));
+        }
+        if uri.contains("://") {
+            return Err(remote_error(format!("unsupported scheme in '{}'", uri)));
+        }
+        Ok(Source::Local(PathBuf::from(uri)))
+    }
+
+    /// Identifies the current object version, so a replaced archive gets a
+    /// fresh cache entry: the ETag on S3, size and mtime locally.
+    fn version(&self, runtime: &tokio::runtime::Runtime) -> Result<String, LfasError> {
+        match self {
+            Source::S3 { bucket, key } => {
+                let store = s3_store(bucket)?;
+                let meta = runtime
+                    .block_on(store.head(&ObjectPath::from(key.as_str())))
+                    .map_err(remote_error)?;
+                Ok(meta
+                    .e_tag
+                    .unwrap_or_else(|| format!("{}:{}", meta.size, meta.last_modified)))
+            }
+            Source::Local(path) => local_version(path),
+        }
+    }
+
+    /// Streams the archive to `target` without holding it in memory.
+    fn download(&self, runtime: &tokio::runtime::Runtime, target: &Path) -> Result<u64, LfasError> {
+        match self {
+            Source::S3 { bucket, key } => {
+                let store = s3_store(bucket)?;
+                runtime.block_on(async {
+                    let mut stream = store
+                        .get(&ObjectPath::from(key.as_str()))
+                        .await
+                        .map_err(remote_error)?
+                        .into_stream();
+                    let mut file = BufWriter::new(File::create(target)?);
+                    let mut bytes = 0u64;
+                    while let Some(chunk) = stream.next().await {
+                        let chunk = chunk.map_err(remote_error)?;
+                        file.write_all(&chunk)?;
+                        bytes += chunk.len() as u64;
+                    }
+                    file.flush()?;
+                    Ok::<_, LfasError>(bytes)
+                })
+            }
+            Source::Local(path) => Ok(fs::copy(path, target)?),
+        }
+    }
+}
+
+/// Credentials and region come from the usual `AWS_*` environment variables.
+fn s3_store(bucket: &str) -> Result<impl ObjectStore, LfasError> {
+    AmazonS3Builder::from_env()
+        .with_bucket_name(bucket)
+        .build()
+        .map_err(remote_error)
+}
+
+/// Downloads and unpacks the archive at `uri` under `cache_dir`, unless this
+/// version of it is already there, and returns the index directory. Entries
+/// only appear in the cache once their manifest checks out.
+pub fn fetch(uri: &str, cache_dir: &Path) -> Result<PathBuf, LfasError> {
+    let source = Source::parse(uri)?;
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let version = source.version(&runtime)?;
+    let entry = cache_entry(uri, &version);
+    let target = cache_dir.join(&entry);
+    if target.is_dir() {
+        info!("[REMOTE] Using cached {} at {}", uri, target.display());
+        return Ok(target);
+    }
+
+    let timer = Timer::new("remote::fetch");
+    fs::create_dir_all(cache_dir)?;
+    let archive = cache_dir.join(format!("{}.tar.zst.partial", entry));
+    let installed = source.download(&runtime, &archive).and_then(|bytes| {
+        info!("[REMOTE] Downloaded {} ({} bytes)", uri, bytes);
+        install(&archive, cache_dir, &entry)
+    });
+    let _ = fs::remove_file(&archive);
+    drop(timer);
+    installed
+}
+
+impl SearchEngine<RecordField, LmdbStorage<RecordField>> {
+    /// Opens the index archive at `uri` read-only, through the cache in
+    /// [`default_cache_dir`]. Blocks while downloading; don't call it from
+    /// inside an async runtime.
+    pub fn open_uri(uri: &str) -> Result<Self, LfasError> {
+        Self::open_uri_with_cache(uri, &default_cache_dir())
+    }
+
+    /// Like [`open_uri`](Self::open_uri), caching under `cache_dir`.
+    pub fn open_uri_with_cache(uri: &str, cache_dir: &Path) -> Result<Self, LfasError> {
+        Self::open_unpacked(&fetch(uri, cache_dir)?)
+    }
+}
@@ -0,0 +1,225 @@
+//! Memoized parts of BM25F for workloads that score the same terms and
+//! documents over and over, such as a dedup run matching every record of a
+//! corpus against the index. Per (field, term) it keeps the idf, the static
+//! part of a term's contribution, and per (doc, field) the length
+//! normalization denominator `1 + b * (dl / avgdl - 1)`, so repeated queries
+//! skip the df and document length lookups (LMDB reads once the metadata is
+//! spilled). Scores come out the same as without the cache. Everything is
+//! dropped when the index generation or the statistics and scorer settings
+//! the values came from change.
+
+use crate::DocId;
+use crate::metadata::FieldMetadata;
+use crate::scorer::BM25FScorer;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::RwLock;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Entries kept per kind before that kind is cleared.
+pub const DEFAULT_SCORE_CACHE_CAPACITY: usize = 1 << 20;
+
+/// Index generation, corpus statistics and scorer settings cached values
+/// were computed from. Field and group weights and k1 are applied on top of
+/// the cached values.
+#[derive(Debug)]
+struct CacheStamp<F> {
+    generation: u64,
+    total_docs: usize,
+    total_field_lengths: HashMap<F, usize>,
+    field_b: HashMap<F, f32>,
+    length_clip: Option<f32>,
+}
+
+impl<F> CacheStamp<F>
+where
+    F: Hash + Eq + Clone + Copy + Ord,
+{
+    fn of(generation: u64, scorer: &BM25FScorer<F>, metadata: &FieldMetadata<F>) -> Self {
+        Self {
+            generation,
+            total_docs: metadata.total_docs,
+            total_field_lengths: metadata.total_field_lengths.clone(),
+            field_b: scorer.field_b.clone(),
+            length_clip: scorer.length_clip,
+        }
+    }
+
+    fn matches(
+        &self,
+        generation: u64,
+        scorer: &BM25FScorer<F>,
+        metadata: &FieldMetadata<F>,
+    ) -> bool {
+        self.generation == generation
+            && self.total_docs == metadata.total_docs
+            && self.total_field_lengths == metadata.total_field_lengths
+            && self.field_b == scorer.field_b
+            && self.length_clip == scorer.length_clip
+    }
+}
+
+#[derive(Debug)]
+struct CacheState<F> {
+    stamp: Option<CacheStamp<F>>,
+    /// (field, term) -> idf
+    terms: HashMap<(F, String), f32>,
+    /// (doc, field) -> length normalization denominator
+    norms: HashMap<(DocId, F), f32>,
+}
+
+/// Counters of a [`ScoreCache`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+pub struct ScoreCacheStats {
+    pub terms: usize,
+    pub norms: usize,
+    pub hits: u64,
+    pub misses: u64,
+    /// Times the cache was emptied because its inputs changed.
+    pub invalidations: u64,
+}
+
+/// Shared by every search of an engine through `&self`.
+#[derive(Debug)]
+pub struct ScoreCache<F> {
+    capacity: usize,
+    state: RwLock<CacheState<F>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    invalidations: AtomicU64,
+}
+
+impl<F> Default for ScoreCache<F> {
+    fn default() -> Self {
+        Self::new(DEFAULT_SCORE_CACHE_CAPACITY)
+    }
+}
+
+impl<F> ScoreCache<F> {
+    /// Keeps up to `capacity` entries of each kind; a kind is cleared when
+    /// it fills up.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            state: RwLock::new(CacheState {
+                stamp: None,
+                terms: HashMap::new(),
+                norms: HashMap::new(),
+            }),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            invalidations: AtomicU64::new(0),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<F> ScoreCache<F>
+where
+    F: Hash + Eq + Clone + Copy + Ord,
+{
+    /// Empties the cache unless its values were computed at `generation`
+    /// with the current statistics and scorer settings. Called before each
+    /// scoring pass.
+    pub fn sync(&self, generation: u64, scorer: &BM25FScorer<F>, metadata: &FieldMetadata<F>) {
+        let current = self
+            .read()
+            .stamp
+            .as_ref()
+            .is_some_and(|stamp| stamp.matches(generation, scorer, metadata));
+        if current {
+            return;
+        }
+        let mut state = self.write();
+        state.terms.clear();
+        state.norms.clear();
+        state.stamp = Some(CacheStamp::of(generation, scorer, metadata));
+        self.invalidations.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Drops every entry, for changes the stamp can't see, such as a
+    /// document rewritten in place before the next flush.
+    pub fn clear(&self) {
+        let mut state = self.write();
+        state.terms.clear();
+        state.norms.clear();
+        state.stamp = None;
+    }
+
+    /// Idf of `term` in `field`, computed by `compute` on a miss.
+    pub fn idf(&self, field: F, term: &str, compute: impl FnOnce() -> f32) -> f32 {
+        let key = (field, term.to_string());
+        if let Some(&idf) = self.read().terms.get(&key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return idf;
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let idf = compute();
+        let mut state = self.write();
+        if state.terms.len() >= self.capacity {
+            state.terms.clear();
+        }
+        state.terms.insert(key, idf);
+        idf
+    }
+
+    /// Normalization denominators of `field` in `docs`, in order. Misses
+    /// are computed by `compute` outside the lock and stored in one go.
+    pub fn norms(&self, field: F, docs: &[DocId], compute: impl Fn(DocId) -> f32) -> Vec<f32> {
+        let mut norms = Vec::with_capacity(docs.len());
+        let mut missing = Vec::new();
+        {
+            let state = self.read();
+            for (i, &doc_id) in docs.iter().enumerate() {
+                match state.norms.get(&(doc_id, field)) {
+                    Some(&norm) => norms.push(norm),
+                    None => {
+                        norms.push(0.0);
+                        missing.push(i);
+                    }
+                }
+            }
+        }
+        self.hits
+            .fetch_add((docs.len() - missing.len()) as u64, Ordering::Relaxed);
+        if missing.is_empty() {
+            return norms;
+        }
+        self.misses
+            .fetch_add(missing.len() as u64, Ordering::Relaxed);
+        for &i in &missing {
+            norms[i] = compute(docs[i]);
+        }
+        let mut state = self.write();
+        if state.norms.len() + missing.len() > self.capacity {
+            state.norms.clear();
+        }
+        for i in missing {
+            state.norms.insert((docs[i], field), norms[i]);
+        }
+        norms
+    }
+
+    pub fn stats(&self) -> ScoreCacheStats {
+        let state = self.read();
+        ScoreCacheStats {
+            terms: state.terms.len(),
+            norms: state.norms.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            invalidations: self.invalidations.load(Ordering::Relaxed),
+        }
+    }
+
+    // A poisoned lock only means a panic mid-insert; the entries stay usable
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, CacheState<F>> {
+        self.state.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, CacheState<F>> {
+        self.state.write().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -1,12 +1,211 @@
+use crate::RecordField;
+use crate::config::EngineConfig;
+use crate::engine::SearchEngine;
+use crate::error::LfasError;
+use crate::eval::{EvalQuery, EvalReport, evaluate};
 use crate::postings::Postings;
+use crate::score_cache::ScoreCache;
+use crate::tokenizer::is_ngram_key;
 use crate::{DocId, index::InvertedIndex, metadata::FieldMetadata, storage::PostingsStorage};
 use roaring::RoaringBitmap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+/// Per-field term frequency saturation, applied before field weighting.
+#[derive(Debug, Clone, Copy, PartialEq, Default, serde::Serialize, serde::Deserialize)]
+pub struct TfOptions {
+    /// Frequencies above this are clamped.
+    pub max_tf: Option<u32>,
+    /// Use `1 + ln(tf)` instead of the raw frequency.
+    pub sublinear: bool,
+}
+
+impl TfOptions {
+    pub fn apply(&self, tf: u32) -> f32 {
+        let tf = self.max_tf.map_or(tf, |max| tf.min(max));
+        if self.sublinear && tf > 0 {
+            1.0 + (tf as f32).ln()
+        } else {
+            tf as f32
+        }
+    }
+}
+
+/// Exponential recency decay applied to the final BM25F score: a document
+/// `half_life_secs` older than `reference` keeps half its score.
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct RecencyDecay {
+    pub half_life_secs: f64,
+    /// Unix time ages are measured from; `None` means the time of the query.
+    pub reference: Option<i64>,
+    /// Lowest multiplier, so very old records still rank by relevance.
+    pub floor: f32,
+}
+
+impl RecencyDecay {
+    pub fn new(half_life_secs: f64) -> Self {
+        Self {
+            half_life_secs,
+            reference: None,
+            floor: 0.0,
+        }
+    }
+
+    /// Multiplier for a document stamped `timestamp` (unix seconds). Undated
+    /// documents and future timestamps are not penalized.
+    pub fn factor(&self, timestamp: Option<i64>, now: i64) -> f32 {
+        let Some(timestamp) = timestamp else {
+            return 1.0;
+        };
+        if self.half_life_secs <= 0.0 {
+            return 1.0;
+        }
+        let age = (self.reference.unwrap_or(now) - timestamp).max(0) as f64;
+        let decay = (-std::f64::consts::LN_2 * age / self.half_life_secs).exp() as f32;
+        decay.max(self.floor)
+    }
+}
+
+/// Named set of fields scored as a unit, e.g. the address fields against the
+/// business name. `weight` multiplies the score contribution of each of its
+/// fields on top of the per-field BM25F weight.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FieldGroup<F> {
+    pub name: String,
+    pub fields: Vec<F>,
+    pub weight: f32,
+}
+
+impl<F> FieldGroup<F> {
+    pub fn new(name: impl Into<String>, fields: Vec<F>, weight: f32) -> Self {
+        Self {
+            name: name.into(),
+            fields,
+            weight,
+        }
+    }
+}
+
+/// Documents whose contributions [`bm25f_contributions`] computes in one
+/// step: eight f32 fill a 256-bit AVX register.
+pub const SCORE_LANES: usize = 8;
+
+/// Everything a term's BM25F contribution needs besides each document's tf
+/// and field length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TermParams {
+    /// Field weight, applied to the tf before saturation.
+    pub weight: f32,
+    pub b: f32,
+    pub avgdl: f32,
+    pub k1: f32,
+    /// Multiplies the saturated tf: idf times token and group weights.
+    pub scale: f32,
+}
+
+/// Contributions of one term to a batch of documents laid out as struct of
+/// arrays: `out[i]` is the score added to the document with tf `tfs[i]`
+/// (after [`TfOptions`]) and field length `dls[i]`. Works through
+/// [`SCORE_LANES`] documents at a time with no branches or lookups, a loop
+/// the compiler turns into SIMD; the tail is done one by one.
+pub fn bm25f_contributions(tfs: &[f32], dls: &[f32], params: &TermParams, out: &mut [f32]) {
+    let TermParams {
+        weight,
+        b,
+        avgdl,
+        k1,
+        scale,
+    } = *params;
+    in_lanes(tfs, dls, out, |tf, dl| {
+        let weighted_tf = (tf * weight) / (1.0 + b * (dl / avgdl - 1.0));
+        scale * (weighted_tf / (k1 + weighted_tf))
+    });
+}
+
+/// [`bm25f_contributions`] with each document's length normalization
+/// denominator, `1 + b * (dl / avgdl - 1)`, given in `norms` instead of its
+/// length, as kept by a [`ScoreCache`]. `b` and `avgdl` are not used.
+pub fn bm25f_contributions_normalized(
+    tfs: &[f32],
+    norms: &[f32],
+    params: &TermParams,
+    out: &mut [f32],
+) {
+    let TermParams {
+        weight, k1, scale, ..
+    } = *params;
+    in_lanes(tfs, norms, out, |tf, norm| {
+        let weighted_tf = (tf * weight) / norm;
+        scale * (weighted_tf / (k1 + weighted_tf))
+    });
+}
+
+/// `out[i] = f(xs[i], ys[i])`, [`SCORE_LANES`] at a time.
+#[inline(always)]
+fn in_lanes(xs: &[f32], ys: &[f32], out: &mut [f32], f: impl Fn(f32, f32) -> f32) {
+    assert!(xs.len() == ys.len() && ys.len() == out.len());
+    let mut out_chunks = out.chunks_exact_mut(SCORE_LANES);
+    let mut x_chunks = xs.chunks_exact(SCORE_LANES);
+    let mut y_chunks = ys.chunks_exact(SCORE_LANES);
+    for ((out, xs), ys) in (&mut out_chunks).zip(&mut x_chunks).zip(&mut y_chunks) {
+        for lane in 0..SCORE_LANES {
+            out[lane] = f(xs[lane], ys[lane]);
+        }
+    }
+    let tail = out_chunks.into_remainder().iter_mut();
+    for ((out, x), y) in tail.zip(x_chunks.remainder()).zip(y_chunks.remainder()) {
+        *out = f(*x, *y);
+    }
+}
+
+/// One term's candidates, gathered so [`bm25f_contributions`] can score them
+/// together. Reused across terms to keep the allocations.
+#[derive(Default)]
+struct ScoreBatch {
+    docs: Vec<DocId>,
+    tfs: Vec<f32>,
+    dls: Vec<f32>,
+    contributions: Vec<f32>,
+}
+
+impl ScoreBatch {
+    fn clear(&mut self) {
+        self.docs.clear();
+        self.tfs.clear();
+        self.dls.clear();
+    }
+
+    fn score(&mut self, params: &TermParams) {
+        self.contributions.resize(self.docs.len(), 0.0);
+        bm25f_contributions(&self.tfs, &self.dls, params, &mut self.contributions);
+    }
+
+    /// Like `score`, with `dls` holding normalization denominators.
+    fn score_normalized(&mut self, params: &TermParams) {
+        self.contributions.resize(self.docs.len(), 0.0);
+        bm25f_contributions_normalized(&self.tfs, &self.dls, params, &mut self.contributions);
+    }
+}
+
+#[derive(Clone)]
 pub struct BM25FScorer<F> {
     pub k1: f32,
     pub field_weights: HashMap<F, f32>,
     pub field_b: HashMap<F, f32>,
+    /// Fields without an entry use the raw term frequency.
+    pub field_tf: HashMap<F, TfOptions>,
+    /// Boost fresher documents by their `FieldMetadata::timestamps` entry.
+    pub recency: Option<RecencyDecay>,
+    /// Added to a document's score scaled by the fraction of query fields it
+    /// matches with a full token, so matches spread over the expected fields
+    /// beat several matches in one. 0 disables it.
+    pub coverage_boost: f32,
+    /// A field in several groups gets the product of their weights; fields
+    /// in none keep their score.
+    pub field_groups: Vec<FieldGroup<F>>,
+    /// Percentile (a fraction, e.g. 0.99) of each field's length histogram
+    /// that longer fields are scored as, so a few very long values don't
+    /// flatten everyone else's length normalization. avgdl is unchanged.
+    pub length_clip: Option<f32>,
 }
 
 impl<F> BM25FScorer<F>
@@ -23,36 +222,149 @@ where
     where
         S: PostingsStorage<F>,
     {
-        self.score_taat_cached(matches, query_tokens, index, metadata)
+        let weighted: Vec<(F, String, f32)> = query_tokens
+            .iter()
+            .map(|(field, token)| (*field, token.clone(), 1.0))
+            .collect();
+        self.score_taat_cached(matches, &weighted, index, metadata, &|| false, None, None)
+            .0
+    }
+
+    /// Like [`score`](Self::score), but each token's contribution is multiplied
+    /// by its weight (e.g. weak n-grams below full tokens).
+    pub fn score_weighted<S>(
+        &self,
+        matches: RoaringBitmap,
+        query_tokens: &[(F, String, f32)],
+        index: &InvertedIndex<F, S>,
+        metadata: &FieldMetadata<F>,
+    ) -> Vec<(DocId, f32)>
+    where
+        S: PostingsStorage<F>,
+    {
+        self.score_taat_cached(
+            matches,
+            query_tokens,
+            index,
+            metadata,
+            &|| false,
+            None,
+            None,
+        )
+        .0
+    }
+
+    /// Like [`score_weighted`](Self::score_weighted), but checks `interrupted`
+    /// before each query token and stops early when it returns true. The flag
+    /// in the result tells whether the scores are partial.
+    pub fn score_weighted_until<S>(
+        &self,
+        matches: RoaringBitmap,
+        query_tokens: &[(F, String, f32)],
+        index: &InvertedIndex<F, S>,
+        metadata: &FieldMetadata<F>,
+        interrupted: &dyn Fn() -> bool,
+    ) -> (Vec<(DocId, f32)>, bool)
+    where
+        S: PostingsStorage<F>,
+    {
+        self.score_taat_cached(
+            matches,
+            query_tokens,
+            index,
+            metadata,
+            interrupted,
+            None,
+            None,
+        )
+    }
+
+    /// Like [`score_weighted_until`](Self::score_weighted_until), taking idfs
+    /// and length normalizations from `cache` and adding the missing ones.
+    /// The cache must have been [`sync`](ScoreCache::sync)ed for this index
+    /// and scorer.
+    pub fn score_cached_until<S>(
+        &self,
+        matches: RoaringBitmap,
+        query_tokens: &[(F, String, f32)],
+        index: &InvertedIndex<F, S>,
+        metadata: &FieldMetadata<F>,
+        cache: &ScoreCache<F>,
+        interrupted: &dyn Fn() -> bool,
+    ) -> (Vec<(DocId, f32)>, bool)
+    where
+        S: PostingsStorage<F>,
+    {
+        self.score_taat_cached(
+            matches,
+            query_tokens,
+            index,
+            metadata,
+            interrupted,
+            None,
+            Some(cache),
+        )
+    }
+
+    /// Like [`score_weighted_until`](Self::score_weighted_until), also returning
+    /// each scored document's BM25F sum split by field, before the coverage
+    /// boost and recency decay.
+    pub fn score_fields_until<S>(
+        &self,
+        matches: RoaringBitmap,
+        query_tokens: &[(F, String, f32)],
+        index: &InvertedIndex<F, S>,
+        metadata: &FieldMetadata<F>,
+        interrupted: &dyn Fn() -> bool,
+    ) -> (Vec<(DocId, f32)>, bool, HashMap<DocId, Vec<(F, f32)>>)
+    where
+        S: PostingsStorage<F>,
+    {
+        let mut field_sums = HashMap::new();
+        let (scores, stopped) = self.score_taat_cached(
+            matches,
+            query_tokens,
+            index,
+            metadata,
+            interrupted,
+            Some(&mut field_sums),
+            None,
+        );
+        (scores, stopped, field_sums)
     }
 
     /// Score term-at-a-time with BATCH transaction optimization
+    #[allow(clippy::too_many_arguments)]
     fn score_taat_cached<S>(
         &self,
         candidates: RoaringBitmap,
-        query_tokens: &[(F, String)],
+        query_tokens: &[(F, String, f32)],
         index: &InvertedIndex<F, S>,
         metadata: &FieldMetadata<F>,
-    ) -> Vec<(DocId, f32)>
+        interrupted: &dyn Fn() -> bool,
+        mut field_sums: Option<&mut HashMap<DocId, Vec<(F, f32)>>>,
+        score_cache: Option<&ScoreCache<F>>,
+    ) -> (Vec<(DocId, f32)>, bool)
     where
         S: PostingsStorage<F>,
     {
         use crate::timing::Timer;
-        use log::{debug, info};
+        use crate::verbosity::{query_debug, query_info};
+        use tracing::{field, info_span};
 
         let cache_timer = Timer::new("term-at-a-time::cache_postings");
         
         // Use batch operation with single transaction
         let query_list: Vec<(F, String)> = query_tokens.iter()
-            .map(|(f, t)| (*f, t.clone()))
+            .map(|(f, t, _)| (*f, t.clone()))
             .collect();
         
         let mut postings_cache: HashMap<(F, String), Postings> = HashMap::new();
         
         // Try batch operation first (works for LMDB)
-        match index.storage.get_batch(&query_list) {
+        match index.get_postings_batch(&query_list) {
             Ok(results) => {
-                info!("[SCORER] Using BATCH operation - single transaction for {} terms", query_list.len());
+                query_info!("[SCORER] Using BATCH operation - single transaction for {} terms", query_list.len());
                 for (query, postings_opt) in query_list.iter().zip(results) {
                     if let Some(postings) = postings_opt {
                         postings_cache.insert(query.clone(), postings);
@@ -61,8 +373,8 @@ where
             }
             Err(_) => {
                 // Fallback for storage types without batch support
-                info!("[SCORER] Batch failed, falling back to individual gets");
-                for (field, term) in query_tokens {
+                query_info!("[SCORER] Batch failed, falling back to individual gets");
+                for (field, term, _) in query_tokens {
                     if let Some(postings) = index.get_postings(*field, term) {
                         postings_cache.insert((*field, term.clone()), postings);
                     }
@@ -71,19 +383,25 @@ where
         }
         
         drop(cache_timer);
-        info!("[SCORER] Cached {} postings in memory", postings_cache.len());
+        query_info!("[SCORER] Cached {} postings in memory", postings_cache.len());
 
         let avg_timer = Timer::new("term-at-a-time::precompute");
         let avg_lengths = self.calculate_avg_lengths(metadata);
+        let length_caps = self.length_caps(query_tokens, metadata);
         let mut idf_cache: HashMap<(F, String), f32> = HashMap::new();
-        for (field, term) in query_tokens {
+        for (field, term, _) in query_tokens {
             let key = (*field, term.clone());
-            let idf = self.calculate_idf(term, *field, metadata);
+            let idf = match score_cache {
+                Some(cache) => {
+                    cache.idf(*field, term, || self.calculate_idf(term, *field, metadata))
+                }
+                None => self.calculate_idf(term, *field, metadata),
+            };
             idf_cache.insert(key, idf);
         }
         
         drop(avg_timer);
-        debug!("[SCORER] Precomputed {} IDF values", idf_cache.len());
+        query_debug!("[SCORER] Precomputed {} IDF values", idf_cache.len());
 
         // Score accumulator - only allocate for candidates
         let score_timer = Timer::new("term-at-a-time::accumulate_scores");
@@ -91,9 +409,18 @@ where
         
         let mut term_hits = 0u64;
         let mut term_misses = 0u64;
+        let mut stopped = false;
+        // (doc, field) pairs matched by a full token, only tracked for the coverage boost
+        let mut covered: HashSet<(DocId, F)> = HashSet::new();
+        let mut batch = ScoreBatch::default();
 
         // For each term, update scores of ALL matching candidates at once
-        for (field, term) in query_tokens {
+        for (field, term, token_weight) in query_tokens {
+            if interrupted() {
+                query_info!("[SCORER] Interrupted before scoring '{}'", term);
+                stopped = true;
+                break;
+            }
             let key = (*field, term.clone());
             
             let Some(postings) = postings_cache.get(&key) else {
@@ -108,51 +435,114 @@ where
             let weight = *self.field_weights.get(field).unwrap_or(&1.0);
             let b = *self.field_b.get(field).unwrap_or(&0.75);
             let avgdl = *avg_lengths.get(field).unwrap_or(&1.0);
+            let tf_options = self.field_tf.get(field).copied().unwrap_or_default();
+            let max_dl = length_caps.get(field).copied().unwrap_or(f32::INFINITY);
+            let params = TermParams {
+                weight,
+                b,
+                avgdl,
+                k1: self.k1,
+                scale: self.group_weight(*field) * token_weight * idf,
+            };
             
-            // Iterate through posting list once, update all matching candidates
-            for doc_id in postings.bitmap().iter() {
-                let doc_id = doc_id as usize;
-                
-                // Skip if not in candidate set
-                if !candidates.contains(doc_id as u32) {
-                    continue;
+            // Only the candidates holding this term, via bitmap intersection,
+            // with document lengths from memory or the metadata store, or their
+            // normalizations from the score cache
+            batch.clear();
+            let dl = |doc_id| (metadata.doc_length(doc_id, field) as f32).min(max_dl);
+            match score_cache {
+                Some(cache) => {
+                    for (doc_id, tf) in postings.intersect_iter(&candidates) {
+                        batch.docs.push(doc_id);
+                        batch.tfs.push(tf_options.apply(tf));
+                    }
+                    batch.dls = cache.norms(*field, &batch.docs, |doc_id| {
+                        1.0 + b * (dl(doc_id) / avgdl - 1.0)
+                    });
+                    batch.score_normalized(&params);
                 }
-                
-                // Get term frequency from cached posting
-                let tf = *postings.frequencies().get(&doc_id).unwrap_or(&0);
-                
-                // Get document length (this is in-memory metadata)
-                let dl = *metadata.lengths
-                    .get(&doc_id)
-                    .and_then(|fields| fields.get(field))
-                    .unwrap_or(&0) as f32;
-                
-                // BM25F calculation
-                let weighted_tf = (tf as f32 * weight) / (1.0 + b * (dl / avgdl - 1.0));
-                let contribution = idf * (weighted_tf / (self.k1 + weighted_tf));
-                
+                None => {
+                    for (doc_id, tf) in postings.intersect_iter(&candidates) {
+                        batch.docs.push(doc_id);
+                        batch.tfs.push(tf_options.apply(tf));
+                        batch.dls.push(dl(doc_id));
+                    }
+                    // BM25F calculation, a batch of lanes at a time
+                    batch.score(&params);
+                }
+            }
+            
+            for (&doc_id, &contribution) in batch.docs.iter().zip(&batch.contributions) {
                 // Accumulate score
                 *accumulators.entry(doc_id).or_insert(0.0) += contribution;
+                if let Some(field_sums) = field_sums.as_mut() {
+                    let sums = field_sums.entry(doc_id).or_default();
+                    match sums.iter_mut().find(|(f, _)| f == field) {
+                        Some((_, sum)) => *sum += contribution,
+                        None => sums.push((*field, contribution)),
+                    }
+                }
+                if self.coverage_boost > 0.0 && !is_ngram_key(term) {
+                    covered.insert((doc_id, *field));
+                }
             }
         }
         
         drop(score_timer);
         
-        debug!(
+        query_debug!(
             "[SCORER] Stats: {} term hits, {} term misses",
             term_hits, term_misses
         );
         
-        info!("[SCORER] Accumulated scores for {} documents", accumulators.len());
+        query_info!("[SCORER] Accumulated scores for {} documents", accumulators.len());
+
+        if !covered.is_empty() {
+            let query_fields = query_tokens
+                .iter()
+                .map(|(field, _, _)| *field)
+                .collect::<HashSet<F>>()
+                .len() as f32;
+            let mut fields_matched: HashMap<DocId, u32> = HashMap::new();
+            for (doc_id, _) in covered {
+                *fields_matched.entry(doc_id).or_insert(0) += 1;
+            }
+            for (doc_id, matched) in fields_matched {
+                if let Some(score) = accumulators.get_mut(&doc_id) {
+                    *score += self.coverage_boost * matched as f32 / query_fields;
+                }
+            }
+        }
 
-        // Sort results
-        let sort_timer = Timer::new("term-at-a-time::sort_results");
+        if let Some(recency) = &self.recency {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs() as i64);
+            for (doc_id, score) in accumulators.iter_mut() {
+                *score *= recency.factor(metadata.timestamps.get(doc_id).copied(), now);
+            }
+        }
+
+        // Sort results: descending score, ties broken by ascending doc_id so the
+        // output doesn't depend on HashMap iteration order
+        let sort_timer = Timer::in_span(
+            "term-at-a-time::sort_results",
+            info_span!(
+                "sort",
+                results = accumulators.len(),
+                elapsed_ms = field::Empty
+            ),
+        );
         let mut scores: Vec<_> = accumulators.into_iter().collect();
-        scores.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scores.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
         drop(sort_timer);
 
         if !scores.is_empty() {
-            info!(
+            query_info!(
                 "[SCORER] Complete: {} documents scored, top: {:.4}, median: {:.4}, bottom: {:.4}",
                 scores.len(),
                 scores.first().map(|(_, s)| *s).unwrap_or(0.0),
@@ -161,7 +551,116 @@ where
             );
         }
 
-        scores
+        (scores, stopped)
+    }
+
+    #[inline]
+    fn normalize_tf(tf: f32, weight: f32, b: f32, dl: f32, avgdl: f32) -> f32 {
+        (tf * weight) / (1.0 + b * (dl / avgdl - 1.0))
+    }
+
+    /// Field-weighted, length-normalized tf of a term occurring `tf` times in
+    /// a `field` of length `dl`, before `k1` saturation.
+    pub fn weighted_tf(&self, field: F, tf: u32, dl: f32, avgdl: f32) -> f32 {
+        let weight = *self.field_weights.get(&field).unwrap_or(&1.0);
+        let b = *self.field_b.get(&field).unwrap_or(&0.75);
+        let tf_options = self.field_tf.get(&field).copied().unwrap_or_default();
+        Self::normalize_tf(tf_options.apply(tf), weight, b, dl, avgdl)
+    }
+
+    /// Score `tf` occurrences of `term` add to a document whose `field` holds
+    /// `dl` tokens, for a query token of weight 1.
+    pub fn contribution(
+        &self,
+        field: F,
+        term: &str,
+        tf: u32,
+        dl: f32,
+        metadata: &FieldMetadata<F>,
+    ) -> f32 {
+        let dl = self.clip_length(field, dl, metadata);
+        let weighted_tf = self.weighted_tf(field, tf, dl, metadata.avg_field_length(&field));
+        let idf = self.calculate_idf(term, field, metadata);
+        self.group_weight(field) * idf * (weighted_tf / (self.k1 + weighted_tf))
+    }
+
+    /// Product of the weights of the groups holding `field`, 1.0 in none.
+    pub fn group_weight(&self, field: F) -> f32 {
+        self.field_groups
+            .iter()
+            .filter(|group| group.fields.contains(&field))
+            .map(|group| group.weight)
+            .product()
+    }
+
+    /// `dl` capped at the [`length_clip`](Self::length_clip) percentile of
+    /// `field`; unchanged without a clip or a histogram for the field.
+    pub fn clip_length(&self, field: F, dl: f32, metadata: &FieldMetadata<F>) -> f32 {
+        self.length_clip
+            .and_then(|p| metadata.length_percentile(&field, p))
+            .map_or(dl, |cap| dl.min(cap as f32))
+    }
+
+    /// Length caps of the fields in `query_tokens` under the clip, computed
+    /// once per query instead of per document.
+    fn length_caps(
+        &self,
+        query_tokens: &[(F, String, f32)],
+        metadata: &FieldMetadata<F>,
+    ) -> HashMap<F, f32> {
+        let Some(p) = self.length_clip else {
+            return HashMap::new();
+        };
+        query_tokens
+            .iter()
+            .filter_map(|(field, _, _)| {
+                Some((*field, metadata.length_percentile(field, p)? as f32))
+            })
+            .collect()
+    }
+
+    /// Highest contribution a single (field, term) can add to a document's
+    /// score, from its `TermStats`; `None` when the term has no stats.
+    /// Under a length clip, a clipped document may outscore the recorded
+    /// maximum, so the bound also covers `max_tf` at the clipped length.
+    pub fn term_upper_bound(&self, field: F, term: &str, metadata: &FieldMetadata<F>) -> Option<f32> {
+        let stats = metadata.get_term_stats(&field, term)?;
+        let idf = self.calculate_idf(term, field, metadata);
+        let mut weighted_tf = stats.max_weighted_tf;
+        if let Some(cap) = self
+            .length_clip
+            .and_then(|p| metadata.length_percentile(&field, p))
+        {
+            let avgdl = metadata.avg_field_length(&field);
+            weighted_tf = weighted_tf.max(self.weighted_tf(field, stats.max_tf, cap as f32, avgdl));
+        }
+        Some(self.group_weight(field) * idf * (weighted_tf / (self.k1 + weighted_tf)))
+    }
+
+    /// Score of a document whose fields hold exactly the query's values: each
+    /// token once, at the query's own field lengths, every field covered.
+    /// Dividing by it puts the scores of different queries on one scale.
+    pub fn self_score(
+        &self,
+        query_tokens: &[(F, String, f32)],
+        field_lengths: &HashMap<F, usize>,
+        metadata: &FieldMetadata<F>,
+    ) -> f32 {
+        let mut score = 0.0;
+        for (field, term, token_weight) in query_tokens {
+            let dl = field_lengths.get(field).copied().unwrap_or(1) as f32;
+            let dl = self.clip_length(*field, dl, metadata);
+            let weighted_tf = self.weighted_tf(*field, 1, dl, metadata.avg_field_length(field));
+            let idf = self.calculate_idf(term, *field, metadata);
+            score += self.group_weight(*field)
+                * token_weight
+                * idf
+                * (weighted_tf / (self.k1 + weighted_tf));
+        }
+        if self.coverage_boost > 0.0 && !query_tokens.is_empty() {
+            score += self.coverage_boost;
+        }
+        score
     }
 
     fn calculate_avg_lengths(
@@ -189,3 +688,175 @@ where
         ((total_docs - df + 0.5) / (df + 0.5) + 1.0).ln()
     }
 }
+
+/// Candidate values searched by [`tune`]. Parameters without candidates keep
+/// the engine's current value.
+#[derive(Debug, Clone)]
+pub struct ParamGrid<F> {
+    pub k1: Vec<f32>,
+    pub field_b: HashMap<F, Vec<f32>>,
+    pub field_weights: HashMap<F, Vec<f32>>,
+    /// Passes over every parameter; stops early after a pass that changes nothing.
+    pub max_rounds: usize,
+}
+
+impl<F> Default for ParamGrid<F> {
+    fn default() -> Self {
+        Self {
+            k1: Vec::new(),
+            field_b: HashMap::new(),
+            field_weights: HashMap::new(),
+            max_rounds: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TuneReport {
+    /// The engine's config with the best parameters found.
+    pub config: EngineConfig<RecordField>,
+    pub report: EvalReport,
+    /// Metrics of the settings before tuning.
+    pub baseline: EvalReport,
+    /// Settings evaluated, the baseline included.
+    pub evaluations: usize,
+}
+
+/// One tunable scorer parameter.
+#[derive(Debug, Clone, Copy)]
+enum Param<F> {
+    K1,
+    B(F),
+    Weight(F),
+}
+
+impl<F> Param<F>
+where
+    F: std::hash::Hash + Eq + Copy,
+{
+    fn get(&self, scorer: &BM25FScorer<F>) -> f32 {
+        match self {
+            Param::K1 => scorer.k1,
+            Param::B(field) => *scorer.field_b.get(field).unwrap_or(&0.75),
+            Param::Weight(field) => *scorer.field_weights.get(field).unwrap_or(&1.0),
+        }
+    }
+
+    fn set(&self, scorer: &mut BM25FScorer<F>, value: f32) {
+        match self {
+            Param::K1 => scorer.k1 = value,
+            Param::B(field) => {
+                scorer.field_b.insert(*field, value);
+            }
+            Param::Weight(field) => {
+                scorer.field_weights.insert(*field, value);
+            }
+        }
+    }
+}
+
+/// Higher MRR wins, then recall, then fewer labeled non-matches retrieved.
+fn ranks_above(a: &EvalReport, b: &EvalReport) -> bool {
+    let key = |r: &EvalReport| (r.mrr, r.recall_at_k, -r.non_match_rate_at_k);
+    key(a) > key(b)
+}
+
+/// Coordinate descent over `grid` against labeled queries: each parameter in
+/// turn takes the candidate value with the best [`evaluate`] report while the
+/// others stay at their best so far. Cheaper than the full grid, which grows
+/// with the product of every candidate list.
+///
+/// The engine's settings are restored before returning; apply the result
+/// with `engine.apply_config(tuned.config)`.
+pub fn tune<S>(
+    engine: &mut SearchEngine<RecordField, S>,
+    queries: &[EvalQuery],
+    grid: &ParamGrid<RecordField>,
+    k: usize,
+) -> Result<TuneReport, LfasError>
+where
+    S: PostingsStorage<RecordField>,
+{
+    use tracing::info;
+
+    let mut params: Vec<(Param<RecordField>, &[f32])> = vec![(Param::K1, grid.k1.as_slice())];
+    let mut b_fields: Vec<_> = grid.field_b.iter().collect();
+    b_fields.sort_by_key(|(field, _)| **field);
+    params.extend(
+        b_fields
+            .into_iter()
+            .map(|(f, values)| (Param::B(*f), values.as_slice())),
+    );
+    let mut weight_fields: Vec<_> = grid.field_weights.iter().collect();
+    weight_fields.sort_by_key(|(field, _)| **field);
+    params.extend(
+        weight_fields
+            .into_iter()
+            .map(|(f, values)| (Param::Weight(*f), values.as_slice())),
+    );
+
+    let original = engine.config();
+    let result = descend(engine, queries, &params, grid.max_rounds, k);
+    let config = engine.config();
+    engine.apply_config(original);
+    let (baseline, report, evaluations) = result?;
+
+    info!(
+        "[TUNE] {} evaluations, MRR {:.4} -> {:.4}",
+        evaluations, baseline.mrr, report.mrr
+    );
+    Ok(TuneReport {
+        config,
+        report,
+        baseline,
+        evaluations,
+    })
+}
+
+/// Leaves the best parameters on `engine.scorer`.
+fn descend<S>(
+    engine: &mut SearchEngine<RecordField, S>,
+    queries: &[EvalQuery],
+    params: &[(Param<RecordField>, &[f32])],
+    max_rounds: usize,
+    k: usize,
+) -> Result<(EvalReport, EvalReport, usize), LfasError>
+where
+    S: PostingsStorage<RecordField>,
+{
+    use tracing::debug;
+
+    let baseline = evaluate(engine, queries, k)?;
+    let mut best = baseline.clone();
+    let mut evaluations = 1;
+
+    for round in 0..max_rounds {
+        let mut improved = false;
+        for (param, values) in params {
+            let mut held = param.get(&engine.scorer);
+            for &value in *values {
+                if value == held {
+                    continue;
+                }
+                param.set(&mut engine.scorer, value);
+                let report = evaluate(engine, queries, k)?;
+                evaluations += 1;
+                if ranks_above(&report, &best) {
+                    debug!(
+                        "[TUNE] Round {}: {:?} = {} (MRR {:.4})",
+                        round, param, value, report.mrr
+                    );
+                    best = report;
+                    held = value;
+                    improved = true;
+                }
+            }
+            param.set(&mut engine.scorer, held);
+        }
+        if !improved {
+            break;
+        }
+    }
+
+    Ok((baseline, best, evaluations))
+}
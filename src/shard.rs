@@ -0,0 +1,243 @@
+//! One logical index split across several storages, e.g. to keep each LMDB
+//! env under its map size. Documents are routed to a shard by doc id hash or
+//! by the value of one field; searches fan out to the shards in parallel and
+//! the per-shard top-k lists are merged.
+
+use crate::engine::{SearchEngine, TokenizedDoc};
+use crate::error::LfasError;
+use crate::storage::{LmdbOptions, LmdbStorage, PostingsStorage};
+use crate::timing::Timer;
+use crate::tokenizer::normalize;
+use crate::{DocId, Record, RecordField, SearchHit, StructuredQuery};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use tracing::info;
+
+/// Layout file written next to the shard directories.
+pub const SHARD_LAYOUT_FILE: &str = "shards.bin";
+
+/// How documents are assigned to shards.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ShardKey {
+    /// Spread evenly by doc id; every search visits every shard.
+    #[default]
+    Hash,
+    /// Group by the normalized value of a field (e.g. Estado). Queries that
+    /// set the field only visit its shard.
+    Field(RecordField),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+struct ShardLayout {
+    count: usize,
+    key: ShardKey,
+}
+
+/// FNV-1a offset basis; the starting state for [`stable_hash_extend`].
+pub(crate) const STABLE_HASH_SEED: u64 = 0xcbf29ce484222325;
+
+/// FNV-1a, stable across processes and Rust versions unlike `DefaultHasher`.
+pub(crate) fn stable_hash(bytes: &[u8]) -> u64 {
+    stable_hash_extend(STABLE_HASH_SEED, bytes)
+}
+
+/// Continues a [`stable_hash`] over more bytes, for hashing streamed input.
+pub(crate) fn stable_hash_extend(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Shard-local scores use each shard's own document frequencies, so ranking
+/// across shards is exact only when shards have similar term distributions.
+pub struct ShardedEngine<S>
+where
+    S: PostingsStorage<RecordField>,
+{
+    pub shards: Vec<SearchEngine<RecordField, S>>,
+    pub key: ShardKey,
+}
+
+impl<S> ShardedEngine<S>
+where
+    S: PostingsStorage<RecordField> + Send + Sync,
+{
+    pub fn new(shards: Vec<SearchEngine<RecordField, S>>, key: ShardKey) -> Result<Self, LfasError> {
+        if shards.is_empty() {
+            return Err(LfasError::Schema("a sharded engine needs at least one shard".into()));
+        }
+        Ok(Self { shards, key })
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    fn shard_for_value(&self, value: &str) -> usize {
+        (stable_hash(normalize(value).as_bytes()) % self.shards.len() as u64) as usize
+    }
+
+    /// Shard a document belongs to.
+    pub fn shard_for(&self, doc_id: DocId, record: &Record) -> usize {
+        match self.key {
+            ShardKey::Hash => {
+                (stable_hash(&u64::from(doc_id.get()).to_le_bytes()) % self.shards.len() as u64) as usize
+            }
+            ShardKey::Field(field) => self.shard_for_value(record.field(field)),
+        }
+    }
+
+    /// Shards that can hold matches for `query`.
+    fn target_shards(&self, query: &StructuredQuery<RecordField>) -> Vec<usize> {
+        if let ShardKey::Field(key_field) = self.key {
+            let routed = query
+                .scored_fields()
+                .find(|(field, text)| *field == key_field && !text.trim().is_empty());
+            if let Some((_, text)) = routed {
+                return vec![self.shard_for_value(text)];
+            }
+        }
+        (0..self.shards.len()).collect()
+    }
+
+    pub fn index_record(&mut self, doc_id: usize, record: &Record) -> Result<(), LfasError> {
+        let shard = self.shard_for(DocId::try_from(doc_id)?, record);
+        self.shards[shard].index_record(doc_id, record)
+    }
+
+    /// Routes a batch and indexes each shard's part with one `index_tokenized`.
+    /// An out-of-range doc id fails the batch before any shard is written.
+    pub fn index_batch(&mut self, records: &[(usize, Record)]) -> Result<(), LfasError> {
+        let mut per_shard: Vec<Vec<TokenizedDoc<RecordField>>> =
+            (0..self.shards.len()).map(|_| Vec::new()).collect();
+        let analyzer = self.shards[0].analyzer();
+        for (doc_id, record) in records {
+            let doc_id = DocId::try_from(*doc_id)?;
+            let shard = self.shard_for(doc_id, record);
+            per_shard[shard].push(TokenizedDoc::from_record_with(doc_id, record, &analyzer));
+        }
+
+        for (shard, docs) in self.shards.iter_mut().zip(per_shard) {
+            if !docs.is_empty() {
+                shard.index_tokenized(docs)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Commits each shard's postings together with its metadata.
+    pub fn flush(&mut self) -> Result<(), LfasError> {
+        for shard in &mut self.shards {
+            shard.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn total_docs(&self) -> usize {
+        self.shards.iter().map(|shard| shard.metadata.total_docs).sum()
+    }
+
+    /// Searches the relevant shards on scoped threads and merges their hits
+    /// by score into the query's `top_k`.
+    pub fn execute(&self, query: StructuredQuery<RecordField>) -> Result<Vec<SearchHit>, LfasError> {
+        let timer = Timer::new("ShardedEngine::execute");
+        let targets = self.target_shards(&query);
+        let top_k = query.top_k;
+
+        let results: Vec<Result<Vec<SearchHit>, LfasError>> = if targets.len() == 1 {
+            let blocking_k = query.blocking_k;
+            vec![self.shards[targets[0]].execute(query, blocking_k)]
+        } else {
+            std::thread::scope(|scope| {
+                let handles: Vec<_> = targets
+                    .iter()
+                    .map(|&shard| {
+                        let query = query.clone();
+                        scope.spawn(move || {
+                            let blocking_k = query.blocking_k;
+                            self.shards[shard].execute(query, blocking_k)
+                        })
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|handle| {
+                        handle.join().unwrap_or_else(|_| {
+                            Err(LfasError::Storage("shard search panicked".into()))
+                        })
+                    })
+                    .collect()
+            })
+        };
+
+        let mut hits = Vec::new();
+        for result in results {
+            hits.extend(result?);
+        }
+        // Exact id hits first, then by score, ties by doc id as in a single engine
+        hits.sort_by(|a, b| {
+            b.exact
+                .cmp(&a.exact)
+                .then(b.score.total_cmp(&a.score))
+                .then(a.doc_id.cmp(&b.doc_id))
+        });
+        hits.truncate(top_k);
+
+        drop(timer);
+        info!(
+            "[SHARD] {} hits merged from {} of {} shards",
+            hits.len(),
+            targets.len(),
+            self.shards.len()
+        );
+        Ok(hits)
+    }
+}
+
+impl ShardedEngine<LmdbStorage<RecordField>> {
+    /// Opens (or creates) `shard_count` LMDB shards under `dir` as `shard-<i>`.
+    /// The count and key are recorded in [`SHARD_LAYOUT_FILE`]; reopening with
+    /// a different layout fails, since documents would be looked up in the
+    /// wrong shards. Each shard's metadata is loaded from its last flush, or
+    /// rebuilt from its postings when that snapshot is stale.
+    pub fn open(
+        dir: &Path,
+        shard_count: usize,
+        key: ShardKey,
+        options: LmdbOptions,
+    ) -> Result<Self, LfasError> {
+        let layout = ShardLayout {
+            count: shard_count,
+            key,
+        };
+        let layout_path = dir.join(SHARD_LAYOUT_FILE);
+        if layout_path.exists() {
+            let stored: ShardLayout =
+                bincode::deserialize_from(BufReader::new(File::open(&layout_path)?))?;
+            if stored != layout {
+                return Err(LfasError::Schema(format!(
+                    "{:?} was sharded as {:?}, not {:?}",
+                    dir, stored, layout
+                )));
+            }
+        } else if !options.read_only {
+            std::fs::create_dir_all(dir)?;
+            bincode::serialize_into(BufWriter::new(File::create(&layout_path)?), &layout)?;
+        }
+
+        let shards = (0..shard_count)
+            .map(|i| {
+                let storage =
+                    LmdbStorage::open_with_options(&dir.join(format!("shard-{}", i)), options.clone())?;
+                let mut engine = SearchEngine::with_storage(storage);
+                engine.refresh()?;
+                Ok(engine)
+            })
+            .collect::<Result<Vec<_>, LfasError>>()?;
+
+        info!("[SHARD] Opened {} shards under {:?} by {:?}", shard_count, dir, key);
+        Self::new(shards, key)
+    }
+}
@@ -0,0 +1,226 @@
+//! String similarity reranking on top of BM25F.
+//!
+//! Term matching misses transpositions and small typos ("Mauirti" vs
+//! "Mauriti"); this stage compares the raw query strings with the stored
+//! document fields and blends that similarity into the first-stage score.
+
+use crate::SearchHit;
+use crate::docstore::VALUE_SEPARATOR;
+use crate::engine::RerankCandidate;
+use crate::tokenizer::normalize;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub const DEFAULT_ALPHA: f32 = 0.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SimilarityMetric {
+    #[default]
+    JaroWinkler,
+    TrigramCosine,
+}
+
+impl SimilarityMetric {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "jaro_winkler" | "jaro-winkler" | "jw" => Some(SimilarityMetric::JaroWinkler),
+            "trigram" | "trigram_cosine" => Some(SimilarityMetric::TrigramCosine),
+            _ => None,
+        }
+    }
+
+    /// Similarity in [0, 1] between two already normalized strings.
+    pub fn similarity(&self, a: &str, b: &str) -> f32 {
+        match self {
+            SimilarityMetric::JaroWinkler => jaro_winkler(a, b),
+            SimilarityMetric::TrigramCosine => trigram_cosine(a, b),
+        }
+    }
+}
+
+pub fn jaro(a: &str, b: &str) -> f32 {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    if a.is_empty() && b.is_empty() {
+        return 1.0;
+    }
+    if a.is_empty() || b.is_empty() {
+        return 0.0;
+    }
+
+    let window = (a.len().max(b.len()) / 2).saturating_sub(1);
+    let mut a_matched = vec![false; a.len()];
+    let mut b_matched = vec![false; b.len()];
+    let mut matches = 0usize;
+
+    for (i, ca) in a.iter().enumerate() {
+        let start = i.saturating_sub(window);
+        let end = (i + window + 1).min(b.len());
+        for j in start..end {
+            if !b_matched[j] && b[j] == *ca {
+                a_matched[i] = true;
+                b_matched[j] = true;
+                matches += 1;
+                break;
+            }
+        }
+    }
+
+    if matches == 0 {
+        return 0.0;
+    }
+
+    let mut transpositions = 0usize;
+    let mut k = 0;
+    for (i, ca) in a.iter().enumerate() {
+        if !a_matched[i] {
+            continue;
+        }
+        while !b_matched[k] {
+            k += 1;
+        }
+        if *ca != b[k] {
+            transpositions += 1;
+        }
+        k += 1;
+    }
+
+    let m = matches as f32;
+    let t = (transpositions / 2) as f32;
+    (m / a.len() as f32 + m / b.len() as f32 + (m - t) / m) / 3.0
+}
+
+/// Jaro similarity boosted by the common prefix (up to 4 chars, scale 0.1).
+pub fn jaro_winkler(a: &str, b: &str) -> f32 {
+    let jaro = jaro(a, b);
+    let prefix = a
+        .chars()
+        .zip(b.chars())
+        .take(4)
+        .take_while(|(x, y)| x == y)
+        .count() as f32;
+
+    jaro + prefix * 0.1 * (1.0 - jaro)
+}
+
+fn trigrams(text: &str) -> HashMap<String, u32> {
+    // Pad so short strings and word boundaries still produce grams
+    let padded: Vec<char> = format!("  {} ", text).chars().collect();
+    let mut grams = HashMap::new();
+    for window in padded.windows(3) {
+        *grams.entry(window.iter().collect()).or_insert(0) += 1;
+    }
+    grams
+}
+
+/// Cosine similarity between character trigram count vectors.
+pub fn trigram_cosine(a: &str, b: &str) -> f32 {
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 1.0 } else { 0.0 };
+    }
+
+    let ga = trigrams(a);
+    let gb = trigrams(b);
+
+    let dot: u32 = ga
+        .iter()
+        .filter_map(|(gram, ca)| gb.get(gram).map(|cb| ca * cb))
+        .sum();
+    let norm = |g: &HashMap<String, u32>| (g.values().map(|c| (c * c) as f32).sum::<f32>()).sqrt();
+
+    dot as f32 / (norm(&ga) * norm(&gb))
+}
+
+/// Blends BM25F with string similarity:
+/// `alpha * similarity + (1 - alpha) * bm25 / max_bm25`.
+#[derive(Debug, Clone, Copy)]
+pub struct SimilarityReranker {
+    pub metric: SimilarityMetric,
+    /// Weight of the similarity term, in [0, 1].
+    pub alpha: f32,
+}
+
+impl Default for SimilarityReranker {
+    fn default() -> Self {
+        Self {
+            metric: SimilarityMetric::default(),
+            alpha: DEFAULT_ALPHA,
+        }
+    }
+}
+
+impl SimilarityReranker {
+    pub fn new(metric: SimilarityMetric, alpha: f32) -> Self {
+        Self {
+            metric,
+            alpha: alpha.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Mean similarity over the non-empty query fields; missing stored fields count as 0.
+    pub fn field_similarity<F>(&self, query: &[(F, String)], doc: Option<&HashMap<F, String>>) -> f32
+    where
+        F: Hash + Eq,
+    {
+        let mut total = 0.0;
+        let mut fields = 0;
+
+        for (field, text) in query {
+            let text = normalize(text.trim());
+            if text.is_empty() {
+                continue;
+            }
+            fields += 1;
+            // Multi-value fields match on their closest value
+            if let Some(value) = doc.and_then(|doc| doc.get(field)) {
+                total += value
+                    .split(VALUE_SEPARATOR)
+                    .map(|value| self.metric.similarity(&text, &normalize(value.trim())))
+                    .fold(0.0, f32::max);
+            }
+        }
+
+        if fields == 0 { 0.0 } else { total / fields as f32 }
+    }
+
+    /// Rescores candidates in place. Exact external id hits stay on top.
+    pub fn rerank<F>(&self, query: &[(F, String)], candidates: Vec<RerankCandidate<'_, F>>) -> Vec<SearchHit>
+    where
+        F: Hash + Eq,
+    {
+        let max_score = candidates
+            .iter()
+            .filter(|c| !c.hit.exact)
+            .map(|c| c.hit.score)
+            .fold(0.0f32, f32::max);
+
+        let mut hits: Vec<SearchHit> = candidates
+            .into_iter()
+            .map(|candidate| {
+                if candidate.hit.exact {
+                    return candidate.hit;
+                }
+                let bm25 = if max_score > 0.0 {
+                    candidate.hit.score / max_score
+                } else {
+                    0.0
+                };
+                let similarity = self.field_similarity(query, candidate.doc);
+                SearchHit {
+                    score: self.alpha * similarity + (1.0 - self.alpha) * bm25,
+                    ..candidate.hit
+                }
+            })
+            .collect();
+
+        hits.sort_by(|a, b| {
+            b.exact
+                .cmp(&a.exact)
+                .then(b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal))
+                .then(a.doc_id.cmp(&b.doc_id))
+        });
+        hits
+    }
+}
+
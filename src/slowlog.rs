@@ -0,0 +1,125 @@
+//! Slow-query log: searches slower than a threshold, and optionally every
+//! n-th search as a baseline sample, kept in a ring buffer with their stage
+//! timings, candidate set size and per-field token stats. Query text is not
+//! kept, only counts and document frequencies, so the log can be shared
+//! without leaking addresses.
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Entries kept before the oldest are dropped.
+pub const DEFAULT_SLOW_QUERY_CAPACITY: usize = 256;
+
+/// What one queried field looked like, without its text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldTokenStats {
+    /// Field in its `Debug` form.
+    pub field: String,
+    /// Characters of the query text.
+    pub chars: usize,
+    /// Full tokens, n-grams excluded.
+    pub tokens: usize,
+    pub ngrams: usize,
+    /// Largest document frequency among the full tokens.
+    pub max_df: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SlowQuery {
+    /// Unix time the search finished, in seconds.
+    pub finished_at: u64,
+    /// True when recorded as a sample rather than for its latency.
+    pub sampled: bool,
+    pub total_ms: f64,
+    pub round1_ms: f64,
+    pub round2_ms: f64,
+    pub candidates: u64,
+    /// Tokens whose postings built the candidate set.
+    pub round1_tokens: usize,
+    pub hits: usize,
+    pub fallback: bool,
+    pub exact: bool,
+    pub cep_shortcut: bool,
+    pub interrupted: bool,
+    pub fields: Vec<FieldTokenStats>,
+}
+
+/// Ring buffer of [`SlowQuery`] entries, recorded through `&self` by every
+/// search of an engine.
+#[derive(Debug)]
+pub struct SlowQueryLog {
+    threshold: Duration,
+    capacity: usize,
+    sample_every: Option<u64>,
+    seen: AtomicU64,
+    entries: Mutex<VecDeque<SlowQuery>>,
+}
+
+impl SlowQueryLog {
+    /// Logs searches taking longer than `threshold`.
+    pub fn new(threshold: Duration) -> Self {
+        Self {
+            threshold,
+            capacity: DEFAULT_SLOW_QUERY_CAPACITY,
+            sample_every: None,
+            seen: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+
+    /// Also logs every `n`-th search whatever its latency, so slow ones can
+    /// be compared with typical ones.
+    pub fn with_sample_every(mut self, n: u64) -> Self {
+        self.sample_every = Some(n.max(1));
+        self
+    }
+
+    pub fn threshold(&self) -> Duration {
+        self.threshold
+    }
+
+    /// Counts a search that took `elapsed` and says whether to log it:
+    /// `Some(false)` for a slow one, `Some(true)` for a sample, None to
+    /// skip it.
+    pub fn should_record(&self, elapsed: Duration) -> Option<bool> {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed) + 1;
+        if elapsed > self.threshold {
+            Some(false)
+        } else if self.sample_every.is_some_and(|n| seen.is_multiple_of(n)) {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// Appends `entry`, dropping the oldest one when full.
+    pub fn push(&self, entry: SlowQuery) {
+        // A poisoned lock only means a panic mid-push; the entries stay usable
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+    }
+
+    /// Logged searches, oldest first.
+    pub fn entries(&self) -> Vec<SlowQuery> {
+        let entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        entries.iter().cloned().collect()
+    }
+
+    pub fn clear(&self) {
+        self.entries
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+}
@@ -0,0 +1,205 @@
+//! SymSpell-style spell correction over the indexed term dictionary.
+//!
+//! Every dictionary term is expanded into its deletion variants up to the
+//! maximum edit distance. A query token is expanded the same way, and any
+//! shared variant yields a candidate that is then verified with a real
+//! edit distance.
+
+use crate::error::LfasError;
+use crate::metadata::FieldMetadata;
+use crate::tokenizer::is_ngram_key;
+use bincode::{deserialize_from, serialize_into};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+
+pub const DEFAULT_MAX_EDIT_DISTANCE: usize = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub term: String,
+    pub distance: usize,
+    pub df: usize,
+}
+
+/// Per-field deletion dictionary.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpellIndex<F>
+where
+    F: Hash + Eq,
+{
+    max_distance: usize,
+    /// field -> deletion variant -> dictionary terms producing it
+    deletes: HashMap<F, HashMap<String, Vec<String>>>,
+}
+
+impl<F> SpellIndex<F>
+where
+    F: Hash + Eq + Clone + Copy,
+{
+    pub fn new(max_distance: usize) -> Self {
+        Self {
+            max_distance,
+            deletes: HashMap::new(),
+        }
+    }
+
+    /// Builds the dictionary from every correctable term in the metadata.
+    pub fn build(metadata: &FieldMetadata<F>, max_distance: usize) -> Self {
+        let mut index = Self::new(max_distance);
+        for (field, term) in metadata.term_df.keys() {
+            index.insert(*field, term);
+        }
+        index
+    }
+
+    /// Only plain words are corrected: n-grams, numbers and composite
+    /// tokens ("rua 3", CEPs) are left alone.
+    pub fn is_correctable(term: &str) -> bool {
+        !is_ngram_key(term) && term.chars().all(|c| c.is_alphabetic())
+    }
+
+    pub fn max_distance(&self) -> usize {
+        self.max_distance
+    }
+
+    pub fn insert(&mut self, field: F, term: &str) {
+        if !Self::is_correctable(term) {
+            return;
+        }
+        let variants = self.deletes.entry(field).or_default();
+        for variant in deletion_variants(term, self.max_distance) {
+            let terms = variants.entry(variant).or_default();
+            if !terms.iter().any(|t| t == term) {
+                terms.push(term.to_string());
+            }
+        }
+    }
+
+    /// Indexed terms within the edit distance of `token`, closest first, then
+    /// by descending df.
+    pub fn suggest(&self, field: F, token: &str, metadata: &FieldMetadata<F>) -> Vec<Suggestion> {
+        let Some(variants) = self.deletes.get(&field) else {
+            return Vec::new();
+        };
+
+        let mut seen = HashSet::new();
+        let mut suggestions = Vec::new();
+
+        for variant in deletion_variants(token, self.max_distance) {
+            let Some(terms) = variants.get(&variant) else {
+                continue;
+            };
+            for term in terms {
+                if !seen.insert(term.as_str()) {
+                    continue;
+                }
+                let distance = edit_distance(token, term);
+                let df = metadata.get_df(&field, term);
+                if distance <= self.max_distance && df > 0 {
+                    suggestions.push(Suggestion {
+                        term: term.clone(),
+                        distance,
+                        df,
+                    });
+                }
+            }
+        }
+
+        suggestions.sort_by(|a, b| {
+            a.distance
+                .cmp(&b.distance)
+                .then(b.df.cmp(&a.df))
+                .then(a.term.cmp(&b.term))
+        });
+        suggestions
+    }
+
+    /// Closest indexed replacement for a token that is not in the dictionary.
+    pub fn correct(&self, field: F, token: &str, metadata: &FieldMetadata<F>) -> Option<String> {
+        if !Self::is_correctable(token) || metadata.get_df(&field, token) > 0 {
+            return None;
+        }
+        self.suggest(field, token, metadata)
+            .into_iter()
+            .next()
+            .map(|suggestion| suggestion.term)
+    }
+}
+
+impl<F> SpellIndex<F>
+where
+    F: Hash + Eq + Serialize + DeserializeOwned,
+{
+    pub fn save(&self, path: &Path) -> Result<(), LfasError> {
+        let writer = BufWriter::new(File::create(path)?);
+        serialize_into(writer, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, LfasError> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(deserialize_from(reader)?)
+    }
+}
+
+/// The word itself plus every string reachable by deleting up to
+/// `max_distance` characters.
+fn deletion_variants(word: &str, max_distance: usize) -> HashSet<String> {
+    let mut variants = HashSet::from([word.to_string()]);
+    let mut frontier = vec![word.to_string()];
+
+    for _ in 0..max_distance {
+        let mut next = Vec::new();
+        for current in &frontier {
+            let chars: Vec<char> = current.chars().collect();
+            if chars.len() <= 1 {
+                continue;
+            }
+            for i in 0..chars.len() {
+                let variant: String = chars
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .map(|(_, c)| c)
+                    .collect();
+                if variants.insert(variant.clone()) {
+                    next.push(variant);
+                }
+            }
+        }
+        frontier = next;
+    }
+    variants
+}
+
+/// Optimal string alignment distance (Levenshtein plus adjacent transpositions).
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    let mut d = vec![vec![0usize; b.len() + 1]; a.len() + 1];
+
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for j in 0..=b.len() {
+        d[0][j] = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
@@ -0,0 +1,270 @@
+//! Stable on-disk ids for field types.
+//!
+//! Postings keys used to embed the bincode encoding of the field, which for an
+//! enum is its variant ordinal: inserting a variant shifted every key after it.
+//! Keys now carry a `u16` id assigned the first time a field is written, and
+//! the id -> variant name registry is stored in the `meta` database.
+
+use super::lmdb::LmdbError;
+use serde::de::IntoDeserializer;
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::ser::{Error as _, Impossible};
+use serde::{Deserialize, Serialize, Serializer, de::DeserializeOwned};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+
+pub type FieldId = u16;
+
+/// How a registry entry identifies its field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldName {
+    /// Variant name, stable across reorderings of the enum.
+    Named(String),
+    /// Enum ordinal recorded by the v3 -> v4 migration, which runs without the
+    /// field type. Pinned to a name the first time a writer opens the index.
+    Ordinal(u32),
+}
+
+/// Bidirectional field <-> id map for one index.
+#[derive(Clone)]
+pub(crate) struct FieldRegistry<F> {
+    entries: BTreeMap<FieldId, FieldName>,
+    ids: HashMap<F, FieldId>,
+    fields: HashMap<FieldId, F>,
+    /// Entries changed since the last commit
+    dirty: bool,
+}
+
+impl<F> FieldRegistry<F>
+where
+    F: Hash + Eq + Copy + Serialize + DeserializeOwned,
+{
+    /// Resolves persisted entries against `F`. Names that no longer exist in
+    /// `F` keep their id reserved but map to no field.
+    pub fn load(entries: Vec<(FieldId, FieldName)>) -> Result<Self, LmdbError> {
+        let mut registry = Self {
+            entries: BTreeMap::new(),
+            ids: HashMap::new(),
+            fields: HashMap::new(),
+            dirty: false,
+        };
+
+        for (id, name) in entries {
+            let field = match &name {
+                FieldName::Named(name) => field_from_name(name),
+                FieldName::Ordinal(ordinal) => {
+                    bincode::deserialize::<F>(&ordinal.to_le_bytes()).ok()
+                }
+            };
+            let name = match (name, field) {
+                (FieldName::Ordinal(_), Some(field)) => {
+                    registry.dirty = true;
+                    FieldName::Named(variant_name(&field)?)
+                }
+                (name, _) => name,
+            };
+            if let Some(field) = field {
+                registry.ids.insert(field, id);
+                registry.fields.insert(id, field);
+            }
+            registry.entries.insert(id, name);
+        }
+        Ok(registry)
+    }
+
+    pub fn id(&self, field: F) -> Option<FieldId> {
+        self.ids.get(&field).copied()
+    }
+
+    pub fn field(&self, id: FieldId) -> Option<F> {
+        self.fields.get(&id).copied()
+    }
+
+    /// Id of `field`, assigning the next free one on first use.
+    pub fn register(&mut self, field: F) -> Result<FieldId, LmdbError> {
+        if let Some(id) = self.id(field) {
+            return Ok(id);
+        }
+        let id = match self.entries.keys().next_back() {
+            Some(&last) => last.checked_add(1).ok_or_else(|| {
+                LmdbError::SerializationError(bincode::Error::new(bincode::ErrorKind::Custom(
+                    "field id space exhausted".into(),
+                )))
+            })?,
+            None => 0,
+        };
+        self.entries
+            .insert(id, FieldName::Named(variant_name(&field)?));
+        self.ids.insert(field, id);
+        self.fields.insert(id, field);
+        self.dirty = true;
+        Ok(id)
+    }
+
+    /// Entries to persist, if any changed since the last call.
+    pub fn take_dirty(&mut self) -> Option<Vec<(FieldId, FieldName)>> {
+        if !self.dirty {
+            return None;
+        }
+        self.dirty = false;
+        Some(
+            self.entries
+                .iter()
+                .map(|(id, name)| (*id, name.clone()))
+                .collect(),
+        )
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Makes the next `take_dirty` return the entries again, after a write
+    /// of them failed.
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+}
+
+fn field_from_name<F: DeserializeOwned>(name: &str) -> Option<F> {
+    let deserializer: StrDeserializer<ValueError> = name.into_deserializer();
+    F::deserialize(deserializer).ok()
+}
+
+/// Serde name of a unit enum variant (or the value of a string field).
+pub fn variant_name<F: Serialize>(field: &F) -> Result<String, LmdbError> {
+    field.serialize(NameSerializer).map_err(|e| {
+        LmdbError::SerializationError(bincode::Error::new(bincode::ErrorKind::Custom(
+            e.to_string(),
+        )))
+    })
+}
+
+/// Captures the variant name of a unit enum; any other shape is an error.
+struct NameSerializer;
+
+const UNSUPPORTED: &str = "fields must be unit enum variants or strings";
+
+macro_rules! reject {
+    ($($method:ident($($arg:ty),*)),* $(,)?) => {
+        $(
+            fn $method(self, $(_: $arg),*) -> Result<String, ValueError> {
+                Err(ValueError::custom(UNSUPPORTED))
+            }
+        )*
+    };
+}
+
+impl Serializer for NameSerializer {
+    type Ok = String;
+    type Error = ValueError;
+    type SerializeSeq = Impossible<String, ValueError>;
+    type SerializeTuple = Impossible<String, ValueError>;
+    type SerializeTupleStruct = Impossible<String, ValueError>;
+    type SerializeTupleVariant = Impossible<String, ValueError>;
+    type SerializeMap = Impossible<String, ValueError>;
+    type SerializeStruct = Impossible<String, ValueError>;
+    type SerializeStructVariant = Impossible<String, ValueError>;
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<String, ValueError> {
+        Ok(variant.to_string())
+    }
+
+    fn serialize_str(self, value: &str) -> Result<String, ValueError> {
+        Ok(value.to_string())
+    }
+
+    reject!(
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_bytes(&[u8]),
+        serialize_none(),
+        serialize_unit(),
+        serialize_unit_struct(&'static str),
+    );
+
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<String, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<String, ValueError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: &T,
+    ) -> Result<String, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleStruct, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeTupleVariant, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStruct, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        _: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Self::SerializeStructVariant, ValueError> {
+        Err(ValueError::custom(UNSUPPORTED))
+    }
+}
@@ -0,0 +1,143 @@
+//! Document lengths persisted next to the postings as bitpacked columns, one
+//! LMDB value per field. A lookup decodes a single slot straight out of the
+//! memory map, so read-mostly deployments can drop `FieldMetadata::lengths`.
+//!
+//! A column is a 5-byte header (bit width as `u8`, slot count as `u32` LE)
+//! followed by the lengths of doc ids `0..count`, each `width` bits wide,
+//! least significant bit first. Lengths saturate at `u16::MAX`.
+
+use super::fields::variant_name;
+use super::lmdb::{LmdbError, MapReadTxn};
+use crate::DocId;
+use crate::error::LfasError;
+use crate::metadata::DocLengths;
+use heed::types::{Bytes, Str};
+use heed::{Database, Env};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+
+pub(crate) const LENGTH_COLUMNS_DB: &str = "length_columns";
+const HEADER_LEN: usize = 5;
+
+/// Packs `values` at the narrowest width that holds the largest one.
+pub(crate) fn pack(values: &[u16]) -> Vec<u8> {
+    let max = values.iter().copied().max().unwrap_or(0);
+    let width = (u16::BITS - max.leading_zeros()) as usize;
+
+    let mut column = Vec::with_capacity(HEADER_LEN + (values.len() * width).div_ceil(8));
+    column.push(width as u8);
+    column.extend_from_slice(&(values.len() as u32).to_le_bytes());
+    column.resize(HEADER_LEN + (values.len() * width).div_ceil(8), 0);
+
+    let data = &mut column[HEADER_LEN..];
+    for (i, &value) in values.iter().enumerate() {
+        let bit = i * width;
+        // A value of at most 16 bits starting mid-byte spans at most 3 bytes
+        let shifted = (value as u32) << (bit % 8);
+        for (k, byte) in data[bit / 8..].iter_mut().take(3).enumerate() {
+            *byte |= (shifted >> (8 * k)) as u8;
+        }
+    }
+    column
+}
+
+/// Slot `index` of a packed column, None past its end.
+pub(crate) fn unpack(column: &[u8], index: usize) -> Option<u16> {
+    let (&width, rest) = column.split_first()?;
+    let count = u32::from_le_bytes(rest.get(..4)?.try_into().ok()?) as usize;
+    if index >= count {
+        return None;
+    }
+    let width = width as usize;
+    if width == 0 {
+        return Some(0);
+    }
+
+    let bit = index * width;
+    let word = column
+        .get(HEADER_LEN + bit / 8..)?
+        .iter()
+        .take(3)
+        .enumerate()
+        .fold(0u32, |word, (k, &byte)| word | ((byte as u32) << (8 * k)));
+    Some(((word >> (bit % 8)) & ((1 << width) - 1)) as u16)
+}
+
+/// Builds one column per field, with a slot for every doc id up to the
+/// largest one seen.
+pub(crate) fn build_columns<F>(lengths: &HashMap<DocId, HashMap<F, usize>>) -> HashMap<F, Vec<u16>>
+where
+    F: Hash + Eq + Clone,
+{
+    let slots = lengths.keys().max().map_or(0, |max| max.index() + 1);
+    let mut columns: HashMap<F, Vec<u16>> = HashMap::new();
+    for (&doc_id, fields) in lengths {
+        for (field, &length) in fields {
+            let column = columns
+                .entry(field.clone())
+                .or_insert_with(|| vec![0; slots]);
+            column[doc_id.index()] = length.min(u16::MAX as usize) as u16;
+        }
+    }
+    columns
+}
+
+/// Reads the columns written by `LmdbStorage::write_length_columns`.
+pub struct LmdbLengthColumns<F> {
+    env: Env,
+    db: Database<Str, Bytes>,
+    names: RwLock<HashMap<F, String>>,
+    /// The storage's map lock, held shared by every read txn
+    map_lock: Arc<RwLock<()>>,
+}
+
+impl<F> LmdbLengthColumns<F>
+where
+    F: Hash + Eq + Clone + Serialize,
+{
+    pub(crate) fn new(env: Env, db: Database<Str, Bytes>, map_lock: Arc<RwLock<()>>) -> Self {
+        Self {
+            env,
+            db,
+            names: RwLock::new(HashMap::new()),
+            map_lock,
+        }
+    }
+
+    fn name(&self, field: &F) -> Result<String, LmdbError> {
+        if let Some(name) = self
+            .names
+            .read()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .get(field)
+        {
+            return Ok(name.clone());
+        }
+        let name = variant_name(field)?;
+        self.names
+            .write()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .insert(field.clone(), name.clone());
+        Ok(name)
+    }
+
+    fn lookup(&self, doc_id: DocId, field: &F) -> Result<Option<usize>, LmdbError> {
+        let name = self.name(field)?;
+        let rtxn = MapReadTxn::new(&self.env, &self.map_lock)?;
+        let column = self.db.get(&rtxn, &name).map_err(LmdbError::HeedError)?;
+        Ok(column
+            .and_then(|column| unpack(column, doc_id.index()))
+            .map(usize::from))
+    }
+}
+
+impl<F> DocLengths<F> for LmdbLengthColumns<F>
+where
+    F: Hash + Eq + Clone + Serialize + Send + Sync,
+{
+    fn doc_length(&self, doc_id: DocId, field: &F) -> Result<Option<usize>, LfasError> {
+        Ok(self.lookup(doc_id, field)?)
+    }
+}
@@ -1,27 +1,55 @@
 use super::PostingsStorage;
+use super::fields::{FieldId, FieldName, FieldRegistry, variant_name};
+use super::length_columns::{LENGTH_COLUMNS_DB, LmdbLengthColumns, build_columns, pack};
+use super::migrate::{
+    FORMAT_VERSION, META_DB, POSTINGS_DB, detect_version, read_config, read_field_ids,
+    read_generation, read_metadata, read_provenance, write_config, write_field_ids,
+    write_generation, write_metadata, write_provenance, write_version,
+};
+use super::snapshot::{Generations, LmdbSnapshot};
+use crate::DocId;
 use crate::postings::Postings;
 use heed::types::{Bytes, Str};
-use heed::{Database, Env, EnvOpenOptions, RoTxn};
-use once_cell::sync::Lazy;
+use heed::{Database, Env, EnvFlags, EnvOpenOptions, FlagSetMode, PutFlags, RoTxn, RwTxn, WithTls};
 use serde::{Serialize, de::DeserializeOwned};
-use std::fs::create_dir_all;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{File, OpenOptions, TryLockError, create_dir_all};
 use std::hash::Hash;
 use std::marker::PhantomData;
-use std::path::Path;
-use std::sync::Mutex;
-
-static OPEN_ENVS: Lazy<Mutex<std::collections::HashSet<std::path::PathBuf>>> =
-    Lazy::new(|| Mutex::new(std::collections::HashSet::new()));
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard};
+use tracing::{info, warn};
 
 pub const BATCH_SIZE: usize = 100_000;
 pub const MAP_SIZE: usize = 10 * 1024 * 1024 * 1024; // 10GB
-pub const NUM_DBS: u32 = 10;
+/// Named databases: postings, meta, id map, length columns and one per
+/// stored field, with room to spare.
+pub const NUM_DBS: u32 = 32;
+pub const MAX_READERS: u32 = 126;
+/// Map sizes set when growing are rounded down to this, a multiple of any page size.
+const MAP_SIZE_ALIGN: usize = 64 * 1024;
+/// Advisory lock file held by the single writer of an index directory.
+pub const WRITER_LOCK_FILE: &str = "writer.lock";
+/// External id -> little-endian u64 doc id, created by the first write.
+const ID_MAP_DB: &str = "id_map";
+/// Prefix of the per-field stored value databases: big-endian u32 doc id ->
+/// value, one database per field so reads only touch requested columns.
+const STORED_DB_PREFIX: &str = "stored:";
 
 #[derive(Debug)]
 pub enum LmdbError {
     HeedError(heed::Error),
     SerializationError(bincode::Error),
     CallbackError(String),
+    LockPoisoned,
+    ReadOnly,
+    VersionMismatch { found: u32, expected: u32 },
+    /// Another process (or handle) holds the writer lock of this index.
+    WriterLocked(PathBuf),
+    /// A bulk load key did not sort after the previous (or an existing) key.
+    UnsortedKey(String),
 }
 
 impl std::fmt::Display for LmdbError {
@@ -30,25 +58,186 @@ impl std::fmt::Display for LmdbError {
             LmdbError::HeedError(e) => write!(f, "LMDB error: {}", e),
             LmdbError::SerializationError(e) => write!(f, "Serialization error: {}", e),
             LmdbError::CallbackError(e) => write!(f, "Callback error: {}", e),
+            LmdbError::LockPoisoned => write!(f, "Write buffer lock poisoned"),
+            LmdbError::ReadOnly => write!(f, "Storage was opened read-only"),
+            LmdbError::VersionMismatch { found, expected } => write!(
+                f,
+                "Index format version {} is not supported (expected {}), run storage::migrate",
+                found, expected
+            ),
+            LmdbError::WriterLocked(path) => write!(
+                f,
+                "Index {:?} is already open for writing by another process",
+                path
+            ),
+            LmdbError::UnsortedKey(key) => write!(
+                f,
+                "Bulk load key {:?} is out of order or already in the index",
+                key
+            ),
         }
     }
 }
 
 impl std::error::Error for LmdbError {}
 
+/// Durability trade-off for write transactions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SyncMode {
+    /// fsync data and meta pages on every commit (LMDB default).
+    #[default]
+    Full,
+    /// Skip the meta page fsync; a crash may roll back the last commit.
+    NoMetaSync,
+    /// Never fsync on commit; durability is left to the OS.
+    NoSync,
+    /// Writable memory map flushed asynchronously.
+    Async,
+}
+
+impl SyncMode {
+    fn flags(self) -> EnvFlags {
+        match self {
+            SyncMode::Full => EnvFlags::empty(),
+            SyncMode::NoMetaSync => EnvFlags::NO_META_SYNC,
+            SyncMode::NoSync => EnvFlags::NO_SYNC,
+            SyncMode::Async => EnvFlags::WRITE_MAP | EnvFlags::MAP_ASYNC,
+        }
+    }
+}
+
+/// Environment options for [`LmdbStorage::open_with_options`].
+#[derive(Debug, Clone)]
+pub struct LmdbOptions {
+    pub map_size: usize,
+    pub max_readers: u32,
+    pub max_dbs: u32,
+    pub sync_mode: SyncMode,
+    pub read_only: bool,
+    /// Open read-only instead of failing when another writer holds the lock.
+    pub fallback_read_only: bool,
+    pub batch_size: usize,
+    /// Ceiling for growing the map when a flush fills it; `None` keeps
+    /// `map_size` fixed.
+    pub max_map_size: Option<usize>,
+}
+
+impl Default for LmdbOptions {
+    fn default() -> Self {
+        Self {
+            map_size: MAP_SIZE,
+            max_readers: MAX_READERS,
+            max_dbs: NUM_DBS,
+            sync_mode: SyncMode::Full,
+            read_only: false,
+            fallback_read_only: false,
+            batch_size: BATCH_SIZE,
+            max_map_size: None,
+        }
+    }
+}
+
+impl LmdbOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map_size(mut self, map_size: usize) -> Self {
+        self.map_size = map_size;
+        self
+    }
+
+    pub fn max_readers(mut self, max_readers: u32) -> Self {
+        self.max_readers = max_readers;
+        self
+    }
+
+    pub fn max_dbs(mut self, max_dbs: u32) -> Self {
+        self.max_dbs = max_dbs;
+        self
+    }
+
+    pub fn sync_mode(mut self, sync_mode: SyncMode) -> Self {
+        self.sync_mode = sync_mode;
+        self
+    }
+
+    /// Open without write access, e.g. for a query replica next to a writer
+    /// process. Readers see every commit; see [`LmdbStorage::generation`].
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// When the writer lock is taken, open as a read-only replica rather than
+    /// returning [`LmdbError::WriterLocked`].
+    pub fn fallback_read_only(mut self, fallback_read_only: bool) -> Self {
+        self.fallback_read_only = fallback_read_only;
+        self
+    }
+
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// Double the map whenever a flush fills it, up to `max_map_size`, and
+    /// replay the flush instead of failing with `MDB_MAP_FULL`. Growing
+    /// waits for the read txns open on the storage, its snapshots and length
+    /// columns to end, and holds new ones off until the map is remapped.
+    pub fn max_map_size(mut self, max_map_size: usize) -> Self {
+        self.max_map_size = Some(max_map_size);
+        self
+    }
+}
+
+/// A read txn that keeps [`LmdbStorage`]'s map from being grown while it is
+/// open. LMDB can't remap an env under a live txn.
+pub(crate) struct MapReadTxn<'e> {
+    // Declared first so it ends before the guard is released
+    txn: RoTxn<'e, WithTls>,
+    _map: RwLockReadGuard<'e, ()>,
+}
+
+impl<'e> MapReadTxn<'e> {
+    pub(crate) fn new(env: &'e Env, map_lock: &'e RwLock<()>) -> Result<Self, LmdbError> {
+        let map = map_lock.read().map_err(|_| LmdbError::LockPoisoned)?;
+        let txn = env.read_txn().map_err(LmdbError::HeedError)?;
+        Ok(Self { txn, _map: map })
+    }
+
+    pub(crate) fn commit(self) -> Result<(), LmdbError> {
+        self.txn.commit().map_err(LmdbError::HeedError)
+    }
+}
+
+impl<'e> Deref for MapReadTxn<'e> {
+    type Target = RoTxn<'e, WithTls>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.txn
+    }
+}
+
+/// Postings staged until the next flush, keyed so that a later write of a
+/// term replaces the earlier one and commits go in key order.
 struct WriteBuffer {
-    entries: Vec<(String, Vec<u8>)>,
+    entries: BTreeMap<String, Vec<u8>>,
 }
 
 impl WriteBuffer {
-    fn with_capacity(capacity: usize) -> Self {
+    fn new() -> Self {
         Self {
-            entries: Vec::with_capacity(capacity),
+            entries: BTreeMap::new(),
         }
     }
 
     fn push(&mut self, key: String, value: Vec<u8>) {
-        self.entries.push((key, value));
+        self.entries.insert(key, value);
+    }
+
+    fn get(&self, key: &str) -> Option<&[u8]> {
+        self.entries.get(key).map(Vec::as_slice)
     }
 
     fn len(&self) -> usize {
@@ -59,15 +248,25 @@ impl WriteBuffer {
         self.entries.is_empty()
     }
 
-    fn sort(&mut self) {
-        self.entries.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    fn iter(&self) -> impl Iterator<Item = (&String, &Vec<u8>)> {
+        self.entries.iter()
     }
 
-    fn drain(&mut self) -> std::vec::Drain<'_, (String, Vec<u8>)> {
-        self.entries.drain(..)
+    fn clear(&mut self) {
+        self.entries.clear();
     }
 }
 
+/// Everything besides postings that a flush commits.
+struct Pending<F> {
+    field_ids: Option<Vec<(FieldId, FieldName)>>,
+    config: Option<Vec<u8>>,
+    provenance: Option<Vec<u8>>,
+    metadata: Option<Vec<u8>>,
+    ids: Vec<(String, Option<DocId>)>,
+    stored: Vec<(F, DocId, Option<String>)>,
+}
+
 /// High-performance LMDB storage with transaction reuse
 pub struct LmdbStorage<F>
 where
@@ -75,82 +274,674 @@ where
 {
     env: Env,
     db: Database<Str, Bytes>,
+    /// Index-level records (format version, ...). Absent on legacy read-only opens.
+    meta: Option<Database<Str, Bytes>>,
     _phantom: PhantomData<F>,
     write_buffer: Mutex<WriteBuffer>,
+    /// Engine config staged by `write_config`, committed with the next flush
+    pending_config: Mutex<Option<Vec<u8>>>,
+    /// Build provenance staged by `write_provenance`, committed likewise
+    pending_provenance: Mutex<Option<Vec<u8>>>,
+    /// Metadata snapshot staged by `write_metadata`, committed likewise and
+    /// stamped with the generation of that commit
+    pending_metadata: Mutex<Option<Vec<u8>>>,
+    /// External id changes staged by `write_external_id`, committed likewise
+    pending_ids: Mutex<Vec<(String, Option<DocId>)>>,
+    /// Stored field values staged by `write_stored`, committed likewise
+    pending_stored: Mutex<Vec<(F, DocId, Option<String>)>>,
+    /// Stable field ids used in keys; new ids are committed with the next flush
+    fields: RwLock<FieldRegistry<F>>,
+    /// Generations pinned by live snapshots and the key versions they read
+    generations: Arc<RwLock<Generations>>,
+    /// Shared by every read txn on the env, here and in the snapshots and
+    /// length columns handed out; taken exclusively to grow the map
+    map_lock: Arc<RwLock<()>>,
     batch_size: usize,
+    max_map_size: Option<usize>,
+    read_only: bool,
+    /// Held for the lifetime of a writable storage, released on drop
+    _writer_lock: Option<File>,
 }
 
 impl<F> LmdbStorage<F>
 where
     F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned,
 {
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    /// Directory of the LMDB env.
+    pub fn path(&self) -> &Path {
+        self.env.path()
+    }
+
     pub fn flush(&self) -> Result<(), LmdbError> {
-        let mut buffer = self.write_buffer.lock().unwrap();
-        if buffer.is_empty() {
+        if self.read_only {
+            return Ok(());
+        }
+        let mut buffer = self.write_buffer.lock().map_err(|_| LmdbError::LockPoisoned)?;
+        let mut pending_config = self.pending_config.lock().map_err(|_| LmdbError::LockPoisoned)?;
+        let mut pending_provenance = self
+            .pending_provenance
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)?;
+        let mut pending_metadata = self
+            .pending_metadata
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)?;
+        let mut pending_ids = self.pending_ids.lock().map_err(|_| LmdbError::LockPoisoned)?;
+        let mut pending_stored = self
+            .pending_stored
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)?;
+        let mut fields = self.fields.write().map_err(|_| LmdbError::LockPoisoned)?;
+        if buffer.is_empty()
+            && pending_config.is_none()
+            && pending_provenance.is_none()
+            && pending_metadata.is_none()
+            && pending_ids.is_empty()
+            && pending_stored.is_empty()
+            && !fields.is_dirty()
+        {
             return Ok(());
         }
 
-        buffer.sort();
+        let pending = Pending {
+            field_ids: fields.take_dirty(),
+            config: pending_config.take(),
+            provenance: pending_provenance.take(),
+            metadata: pending_metadata.take(),
+            ids: std::mem::take(&mut *pending_ids),
+            stored: std::mem::take(&mut *pending_stored),
+        };
 
+        // A full map aborts the txn; the same writes are replayed once it has grown
+        let committed = loop {
+            let result = self.commit(&buffer, &pending);
+            if !matches!(
+                result,
+                Err(LmdbError::HeedError(heed::Error::Mdb(
+                    heed::MdbError::MapFull
+                )))
+            ) {
+                break result;
+            }
+            match self.grow_map() {
+                Ok(true) => {}
+                Ok(false) => break result,
+                Err(e) => break Err(e),
+            }
+        };
+
+        // Nothing reached the disk on failure, so the next flush retries it all
+        if committed.is_ok() {
+            buffer.clear();
+        } else {
+            if pending.field_ids.is_some() {
+                fields.mark_dirty();
+            }
+            *pending_config = pending.config;
+            *pending_provenance = pending.provenance;
+            *pending_metadata = pending.metadata;
+            *pending_ids = pending.ids;
+            *pending_stored = pending.stored;
+        }
+        committed
+    }
+
+    fn commit(&self, buffer: &WriteBuffer, pending: &Pending<F>) -> Result<(), LmdbError> {
+        // Held through the commit so snapshots never read postings newer than
+        // the history recorded for them
+        let mut generations = self.generations.write().map_err(|_| LmdbError::LockPoisoned)?;
         let mut wtxn = self.env.write_txn().map_err(LmdbError::HeedError)?;
 
-        for (key, value_bytes) in buffer.drain() {
+        let mut previous = Vec::new();
+        for (key, value_bytes) in buffer.iter() {
+            if generations.is_pinned() {
+                let bytes = self.db.get(&wtxn, key).map_err(LmdbError::HeedError)?;
+                previous.push((key.clone(), bytes.map(<[u8]>::to_vec)));
+            }
             self.db
-                .put(&mut wtxn, &key, &value_bytes)
+                .put(&mut wtxn, key, value_bytes)
+                .map_err(LmdbError::HeedError)?;
+        }
+
+        if !pending.ids.is_empty() {
+            let ids: Database<Str, Bytes> = self
+                .env
+                .create_database(&mut wtxn, Some(ID_MAP_DB))
                 .map_err(LmdbError::HeedError)?;
+            for (external_id, doc_id) in &pending.ids {
+                match doc_id {
+                    Some(doc_id) => {
+                        ids.put(&mut wtxn, external_id, &u64::from(doc_id.get()).to_le_bytes())
+                    }
+                    None => ids.delete(&mut wtxn, external_id).map(|_| ()),
+                }
+                .map_err(LmdbError::HeedError)?;
+            }
+        }
+
+        let mut columns: HashMap<F, (String, Database<Bytes, Str>)> = HashMap::new();
+        for (field, doc_id, value) in &pending.stored {
+            let (name, db) = match columns.entry(*field) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let name = stored_db_name(field)?;
+                    let db = self
+                        .env
+                        .create_database(&mut wtxn, Some(&name))
+                        .map_err(LmdbError::HeedError)?;
+                    entry.insert((name, db))
+                }
+            };
+            let key = doc_id.get().to_be_bytes();
+            if generations.is_pinned() {
+                let value = db.get(&wtxn, &key).map_err(LmdbError::HeedError)?;
+                let value = value.map(|value| value.as_bytes().to_vec());
+                previous.push((stored_key(name, *doc_id), value));
+            }
+            match value {
+                Some(value) => db.put(&mut wtxn, &key, value),
+                None => db.delete(&mut wtxn, &key).map(|_| ()),
+            }
+            .map_err(LmdbError::HeedError)?;
+        }
+
+        // Bumped in the same txn so readers never see new postings with an old generation
+        let mut committed = None;
+        if let Some(meta) = &self.meta {
+            if let Some(entries) = &pending.field_ids {
+                write_field_ids(meta, &mut wtxn, entries)?;
+            }
+            if let Some(config) = &pending.config {
+                write_config(meta, &mut wtxn, config)?;
+            }
+            if let Some(provenance) = &pending.provenance {
+                write_provenance(meta, &mut wtxn, provenance)?;
+            }
+            let generation = read_generation(meta, &wtxn)? + 1;
+            write_generation(meta, &mut wtxn, generation)?;
+            if let Some(snapshot) = &pending.metadata {
+                write_metadata(meta, &mut wtxn, generation, snapshot)?;
+            }
+            committed = Some(generation);
         }
 
         wtxn.commit().map_err(LmdbError::HeedError)?;
+        if let Some(generation) = committed {
+            for (key, bytes) in previous {
+                generations.record(generation, key, bytes);
+            }
+        }
         Ok(())
     }
 
-    #[inline]
-    fn encode_key(field: F, term: &str) -> Result<String, bincode::Error> {
-        let field_bytes = bincode::serialize(&field)?;
-        let mut key = String::with_capacity(field_bytes.len() * 2 + 1 + term.len());
+    /// Doubles the map size, up to [`LmdbOptions::max_map_size`]. Returns
+    /// false when growth is off or the ceiling is reached.
+    fn grow_map(&self) -> Result<bool, LmdbError> {
+        let Some(max_map_size) = self.max_map_size else {
+            return Ok(false);
+        };
+        let current = self.env.info().map_size;
+        // Whole pages, whatever the OS page size
+        let grown = current.saturating_mul(2).min(max_map_size) / MAP_SIZE_ALIGN * MAP_SIZE_ALIGN;
+        if grown <= current {
+            warn!(
+                "[LMDB] Map full at {} bytes, the max_map_size ceiling",
+                current
+            );
+            return Ok(false);
+        }
+        // Write txns only run under the write buffer lock, which our flush
+        // holds, and read txns under the map lock. Readers take no other lock
+        // of the storage while in a txn, so waiting for them can't deadlock.
+        let _map = self.map_lock.write().map_err(|_| LmdbError::LockPoisoned)?;
+        // Safety: no txn is open on the env while both locks are held
+        unsafe { self.env.resize(grown) }.map_err(LmdbError::HeedError)?;
+        info!("[LMDB] Map full, grown from {} to {} bytes", current, grown);
+        Ok(true)
+    }
+
+    /// Writes postings straight to LMDB with `MDB_APPEND`, bypassing the
+    /// write buffer, with fsync disabled until a single sync at the end. Meant
+    /// for initial loads: `entries` must be sorted by (field, term) without
+    /// duplicates, and every key must sort after those already in the index,
+    /// otherwise the load stops with [`LmdbError::UnsortedKey`]. Fields first
+    /// seen here get ascending ids, so sorted input yields sorted keys.
+    /// Every `batch_size` entries are committed as a generation of their own,
+    /// and batches committed before an error stay in the index, synced like
+    /// a complete load. Engine metadata is not updated; run
+    /// `rebuild_metadata` afterwards.
+    pub fn bulk_load<I>(&mut self, entries: I) -> Result<usize, LmdbError>
+    where
+        I: IntoIterator<Item = (F, String, Postings)>,
+    {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        self.flush()?;
 
-        for &byte in &field_bytes {
-            use std::fmt::Write;
-            write!(&mut key, "{:02x}", byte).unwrap();
+        let was_no_sync = self
+            .env
+            .flags()
+            .map_err(LmdbError::HeedError)?
+            .is_some_and(|flags| flags.contains(EnvFlags::NO_SYNC));
+        // Safety: `&mut self` keeps every other user of this env out
+        unsafe {
+            self.env
+                .set_flags(EnvFlags::NO_SYNC, FlagSetMode::Enable)
+                .map_err(LmdbError::HeedError)?;
         }
 
-        key.push(':');
-        key.push_str(term);
-        Ok(key)
+        let loaded = self.append_entries(entries);
+
+        let restored = if was_no_sync {
+            Ok(())
+        } else {
+            unsafe {
+                self.env
+                    .set_flags(EnvFlags::NO_SYNC, FlagSetMode::Disable)
+                    .map_err(LmdbError::HeedError)
+            }
+        };
+        // Also after a failed load, whose committed batches stay
+        let synced = self.env.force_sync().map_err(LmdbError::HeedError);
+        let loaded = loaded?;
+        restored?;
+        synced?;
+        Ok(loaded)
+    }
+
+    fn append_entries<I>(&mut self, entries: I) -> Result<usize, LmdbError>
+    where
+        I: IntoIterator<Item = (F, String, Postings)>,
+    {
+        let fields = self.fields.get_mut().map_err(|_| LmdbError::LockPoisoned)?;
+        let mut wtxn = self.env.write_txn().map_err(LmdbError::HeedError)?;
+        let mut last_key: Option<String> = None;
+        let mut in_txn = 0;
+        let mut loaded = 0;
+        // Keys appended under a pin, recorded as new with their batch's generation
+        let pinned = self
+            .generations
+            .read()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .is_pinned();
+        let mut appended = Vec::new();
+
+        for (field, term, postings) in entries {
+            let key = Self::encode_key(fields.register(field)?, &term);
+            if last_key.as_ref().is_some_and(|last| key <= *last) {
+                return Err(LmdbError::UnsortedKey(key));
+            }
+            let value_bytes =
+                bincode::serialize(&postings).map_err(LmdbError::SerializationError)?;
+            match self
+                .db
+                .put_with_flags(&mut wtxn, PutFlags::APPEND, &key, &value_bytes)
+            {
+                Err(heed::Error::Mdb(heed::MdbError::KeyExist)) => {
+                    return Err(LmdbError::UnsortedKey(key));
+                }
+                result => result.map_err(LmdbError::HeedError)?,
+            }
+            if pinned {
+                appended.push(key.clone());
+            }
+            last_key = Some(key);
+            loaded += 1;
+
+            in_txn += 1;
+            if in_txn == self.batch_size {
+                let batch = std::mem::take(&mut appended);
+                Self::commit_appended(self.meta.as_ref(), fields, &self.generations, wtxn, batch)?;
+                wtxn = self.env.write_txn().map_err(LmdbError::HeedError)?;
+                in_txn = 0;
+            }
+        }
+
+        Self::commit_appended(self.meta.as_ref(), fields, &self.generations, wtxn, appended)?;
+        Ok(loaded)
+    }
+
+    /// Commits a batch of `append_entries` as the next generation, together
+    /// with the ids of the fields it registered, so that every committed key
+    /// can be resolved and replicas see each batch land.
+    fn commit_appended(
+        meta: Option<&Database<Str, Bytes>>,
+        fields: &mut FieldRegistry<F>,
+        generations: &RwLock<Generations>,
+        wtxn: RwTxn,
+        appended: Vec<String>,
+    ) -> Result<(), LmdbError> {
+        // Held through the commit, as in `commit`
+        let mut generations = generations.write().map_err(|_| LmdbError::LockPoisoned)?;
+        let field_ids = meta.and_then(|_| fields.take_dirty());
+        match Self::commit_batch(meta, field_ids.as_deref(), wtxn) {
+            Ok(generation) => {
+                generations.record_new(generation, appended);
+                Ok(())
+            }
+            Err(e) => {
+                // Written by the next commit instead
+                if field_ids.is_some() {
+                    fields.mark_dirty();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Stamps `wtxn` with the next generation and `field_ids`, then commits
+    /// it. Returns the generation.
+    fn commit_batch(
+        meta: Option<&Database<Str, Bytes>>,
+        field_ids: Option<&[(FieldId, FieldName)]>,
+        mut wtxn: RwTxn,
+    ) -> Result<u64, LmdbError> {
+        let Some(meta) = meta else {
+            wtxn.commit().map_err(LmdbError::HeedError)?;
+            return Ok(0);
+        };
+        if let Some(entries) = field_ids {
+            write_field_ids(meta, &mut wtxn, entries)?;
+        }
+        let generation = read_generation(meta, &wtxn)? + 1;
+        write_generation(meta, &mut wtxn, generation)?;
+        wtxn.commit().map_err(LmdbError::HeedError)?;
+        Ok(generation)
+    }
+
+    /// [`PostingsStorage::put`] through a shared reference. The write buffer
+    /// and field registry are behind their own locks, so a writer holding
+    /// the storage shared (e.g. under an engine read lock) can stage
+    /// postings while readers keep searching.
+    pub fn put_shared(&self, field: F, term: String, postings: Postings) -> Result<(), LmdbError> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+
+        let id = self
+            .fields
+            .write()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .register(field)?;
+        let key = Self::encode_key(id, &term);
+        let value_bytes = bincode::serialize(&postings).map_err(LmdbError::SerializationError)?;
+
+        {
+            let mut buffer = self.write_buffer.lock().map_err(|_| LmdbError::LockPoisoned)?;
+            buffer.push(key, value_bytes);
+            if buffer.len() < self.batch_size {
+                return Ok(());
+            }
+        }
+
+        self.flush()
+    }
+
+    /// Engine config committed with the index, if one was ever saved.
+    pub fn read_config(&self) -> Result<Option<Vec<u8>>, LmdbError> {
+        let Some(meta) = &self.meta else {
+            return Ok(None);
+        };
+        let rtxn = self.read_txn()?;
+        read_config(meta, &rtxn)
+    }
+
+    /// Build provenance committed with the index, if one was ever saved.
+    pub fn read_provenance(&self) -> Result<Option<Vec<u8>>, LmdbError> {
+        let Some(meta) = &self.meta else {
+            return Ok(None);
+        };
+        let rtxn = self.read_txn()?;
+        read_provenance(meta, &rtxn)
+    }
+
+    /// External id map committed with the index; empty if none was written.
+    pub fn read_id_map(&self) -> Result<Vec<(String, DocId)>, LmdbError> {
+        let rtxn = self.read_txn()?;
+        let db: Option<Database<Str, Bytes>> = self
+            .env
+            .open_database(&rtxn, Some(ID_MAP_DB))
+            .map_err(LmdbError::HeedError)?;
+        let Some(db) = db else {
+            return Ok(Vec::new());
+        };
+
+        let mut ids = Vec::new();
+        for entry in db.iter(&rtxn).map_err(LmdbError::HeedError)? {
+            let (external_id, bytes) = entry.map_err(LmdbError::HeedError)?;
+            let bytes: [u8; 8] = bytes.try_into().map_err(|_| {
+                LmdbError::CallbackError(format!(
+                    "Corrupt doc id for external id '{}'",
+                    external_id
+                ))
+            })?;
+            let doc_id = DocId::try_from(u64::from_le_bytes(bytes))
+                .map_err(|e| LmdbError::CallbackError(e.to_string()))?;
+            ids.push((external_id.to_string(), doc_id));
+        }
+        Ok(ids)
+    }
+
+    /// Committed values of `fields` for each of `doc_ids`, in order, read
+    /// column by column in one txn. Fields never stored are skipped.
+    pub fn read_stored(
+        &self,
+        doc_ids: &[DocId],
+        fields: &[F],
+    ) -> Result<Vec<HashMap<F, String>>, LmdbError> {
+        let rtxn = self.read_txn()?;
+        let mut docs = vec![HashMap::new(); doc_ids.len()];
+        for field in fields {
+            let db: Option<Database<Bytes, Str>> = self
+                .env
+                .open_database(&rtxn, Some(&stored_db_name(field)?))
+                .map_err(LmdbError::HeedError)?;
+            let Some(db) = db else {
+                continue;
+            };
+            for (doc, doc_id) in docs.iter_mut().zip(doc_ids) {
+                let value = db
+                    .get(&rtxn, &doc_id.get().to_be_bytes())
+                    .map_err(LmdbError::HeedError)?;
+                if let Some(value) = value {
+                    doc.insert(*field, value.to_string());
+                }
+            }
+        }
+        Ok(docs)
+    }
+
+    /// Every committed (doc id, value) of one stored field, by doc id.
+    pub fn stored_column(&self, field: F) -> Result<Vec<(DocId, String)>, LmdbError> {
+        let name = stored_db_name(&field)?;
+        let rtxn = self.read_txn()?;
+        let db: Option<Database<Bytes, Str>> = self
+            .env
+            .open_database(&rtxn, Some(&name))
+            .map_err(LmdbError::HeedError)?;
+        let Some(db) = db else {
+            return Ok(Vec::new());
+        };
+
+        let mut column = Vec::new();
+        for entry in db.iter(&rtxn).map_err(LmdbError::HeedError)? {
+            let (key, value) = entry.map_err(LmdbError::HeedError)?;
+            let key: [u8; 4] = key
+                .try_into()
+                .map_err(|_| LmdbError::CallbackError(format!("Corrupt doc id in {}", name)))?;
+            column.push((DocId::new(u32::from_be_bytes(key)), value.to_string()));
+        }
+        Ok(column)
+    }
+
+    /// Replaces the persisted length columns with `lengths`, one bitpacked
+    /// column per field, committed in its own txn. Returns the number of
+    /// columns written.
+    pub fn write_length_columns(
+        &self,
+        lengths: &HashMap<DocId, HashMap<F, usize>>,
+    ) -> Result<usize, LmdbError> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        let columns = build_columns(lengths);
+
+        // Write txns run under the buffer lock, see `grow_map`
+        let _buffer = self.write_buffer.lock().map_err(|_| LmdbError::LockPoisoned)?;
+        let mut wtxn = self.env.write_txn().map_err(LmdbError::HeedError)?;
+        let db: Database<Str, Bytes> = self
+            .env
+            .create_database(&mut wtxn, Some(LENGTH_COLUMNS_DB))
+            .map_err(LmdbError::HeedError)?;
+        db.clear(&mut wtxn).map_err(LmdbError::HeedError)?;
+        for (field, column) in &columns {
+            db.put(&mut wtxn, &variant_name(field)?, &pack(column))
+                .map_err(LmdbError::HeedError)?;
+        }
+        wtxn.commit().map_err(LmdbError::HeedError)?;
+        Ok(columns.len())
+    }
+
+    /// Reader over the persisted length columns, None if none were written.
+    pub fn length_columns(&self) -> Result<Option<LmdbLengthColumns<F>>, LmdbError> {
+        let rtxn = self.read_txn()?;
+        let db: Option<Database<Str, Bytes>> = self
+            .env
+            .open_database(&rtxn, Some(LENGTH_COLUMNS_DB))
+            .map_err(LmdbError::HeedError)?;
+        rtxn.commit()?;
+        Ok(db.map(|db| LmdbLengthColumns::new(self.env.clone(), db, Arc::clone(&self.map_lock))))
+    }
+
+    /// Metadata snapshot committed with the index and the generation it was
+    /// committed at, if one was ever saved.
+    pub fn read_metadata(&self) -> Result<Option<(u64, Vec<u8>)>, LmdbError> {
+        let Some(meta) = &self.meta else {
+            return Ok(None);
+        };
+        let rtxn = self.read_txn()?;
+        read_metadata(meta, &rtxn)
+    }
+
+    /// Number of committed write batches. Replicas compare it to decide when
+    /// their in-memory metadata is stale.
+    pub fn generation(&self) -> Result<u64, LmdbError> {
+        let Some(meta) = &self.meta else {
+            return Ok(0);
+        };
+        let rtxn = self.read_txn()?;
+        read_generation(meta, &rtxn)
+    }
+
+    /// Pins the last committed generation: the returned view keeps reading
+    /// it while this storage commits newer ones, until it is dropped. Staged
+    /// writes are not part of it; flush first. Only writers can pin, since
+    /// commits of another process can't be held back.
+    pub fn snapshot(&self) -> Result<LmdbSnapshot<F>, LmdbError> {
+        let Some(meta) = self.meta.as_ref().filter(|_| !self.read_only) else {
+            return Err(LmdbError::ReadOnly);
+        };
+        // Locked in flush's order; together they keep commits out until the
+        // pin is registered
+        let fields = self.fields.read().map_err(|_| LmdbError::LockPoisoned)?;
+        let mut generations = self.generations.write().map_err(|_| LmdbError::LockPoisoned)?;
+        let rtxn = self.read_txn()?;
+        let generation = read_generation(meta, &rtxn)?;
+        let metadata = read_metadata(meta, &rtxn)?
+            .filter(|(committed, _)| *committed == generation)
+            .map(|(_, snapshot)| snapshot);
+        drop(rtxn);
+
+        generations.pin(generation);
+        info!("[LMDB] Pinned generation {}", generation);
+        Ok(LmdbSnapshot::new(
+            self.env.clone(),
+            self.db,
+            fields.clone(),
+            generation,
+            metadata,
+            Arc::clone(&self.generations),
+            Arc::clone(&self.map_lock),
+        ))
+    }
+
+    /// Generations held by live snapshots, oldest first.
+    pub fn pinned_generations(&self) -> Result<Vec<u64>, LmdbError> {
+        Ok(self
+            .generations
+            .read()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .pinned())
     }
 
     #[inline]
-    fn decode_key(key: &str) -> Result<(F, String), bincode::Error> {
-        let colon_pos = key.find(':').ok_or_else(|| {
+    pub(super) fn encode_key(id: FieldId, term: &str) -> String {
+        format!("{:04x}:{}", id, term)
+    }
+
+    pub(super) fn decode_key(key: &str) -> Result<(FieldId, &str), bincode::Error> {
+        let (id_hex, term) = key.split_once(':').ok_or_else(|| {
             bincode::Error::new(bincode::ErrorKind::Custom("Missing colon".into()))
         })?;
+        let id = FieldId::from_str_radix(id_hex, 16)
+            .map_err(|e| bincode::Error::new(bincode::ErrorKind::Custom(e.to_string())))?;
+        Ok((id, term))
+    }
 
-        let field_hex = &key[..colon_pos];
-        let term = &key[colon_pos + 1..];
+    /// Read txn that holds off growing the map until it ends. Don't take any
+    /// other lock of the storage while it is open, see `grow_map`.
+    fn read_txn(&self) -> Result<MapReadTxn<'_>, LmdbError> {
+        MapReadTxn::new(&self.env, &self.map_lock)
+    }
 
-        let mut field_bytes = Vec::with_capacity(field_hex.len() / 2);
-        for chunk in field_hex.as_bytes().chunks(2) {
-            let hex_str = std::str::from_utf8(chunk)
-                .map_err(|e| bincode::Error::new(bincode::ErrorKind::Custom(e.to_string())))?;
-            let byte = u8::from_str_radix(hex_str, 16)
-                .map_err(|e| bincode::Error::new(bincode::ErrorKind::Custom(e.to_string())))?;
-            field_bytes.push(byte);
-        }
+    /// Reloads the field registry a writer may have extended since we opened.
+    fn reload_fields(&self) -> Result<(), LmdbError> {
+        let Some(meta) = &self.meta else {
+            return Ok(());
+        };
+        let rtxn = self.read_txn()?;
+        let ids = read_field_ids(meta, &rtxn)?;
+        drop(rtxn);
+        *self.fields.write().map_err(|_| LmdbError::LockPoisoned)? = FieldRegistry::load(ids)?;
+        Ok(())
+    }
 
-        let field: F = bincode::deserialize(&field_bytes)?;
-        Ok((field, term.to_string()))
+    /// Id of `field`, `None` when it was never written to this index.
+    fn field_id(&self, field: F) -> Result<Option<FieldId>, LmdbError> {
+        let id = self.fields.read().map_err(|_| LmdbError::LockPoisoned)?.id(field);
+        if id.is_some() || !self.read_only {
+            return Ok(id);
+        }
+        self.reload_fields()?;
+        Ok(self.fields.read().map_err(|_| LmdbError::LockPoisoned)?.id(field))
     }
 
-    // Get with existing transaction (for batch operations)
-    fn get_with_txn(
-        &self,
-        txn: &RoTxn,
-        field: F,
-        term: &str,
-    ) -> Result<Option<Postings>, LmdbError> {
-        let key = Self::encode_key(field, term).map_err(LmdbError::SerializationError)?;
+    /// Key of `field`'s `term` and its staged postings, if any. Staged
+    /// postings are newer than the committed ones, and indexing reads them
+    /// back to add to them.
+    fn resolve(&self, field: F, term: &str) -> Result<Option<Resolved>, LmdbError> {
+        let Some(id) = self.field_id(field)? else {
+            return Ok(None);
+        };
+        let key = Self::encode_key(id, term);
+        let buffer = self.write_buffer.lock().map_err(|_| LmdbError::LockPoisoned)?;
+        match buffer.get(&key) {
+            Some(bytes) => {
+                let postings: Postings =
+                    bincode::deserialize(bytes).map_err(LmdbError::SerializationError)?;
+                Ok(Some(Resolved::Staged(postings)))
+            }
+            None => Ok(Some(Resolved::Committed(key))),
+        }
+    }
 
-        match self.db.get(txn, &key).map_err(LmdbError::HeedError)? {
+    fn get_committed(&self, txn: &RoTxn, key: &str) -> Result<Option<Postings>, LmdbError> {
+        match self.db.get(txn, key).map_err(LmdbError::HeedError)? {
             Some(bytes) => {
                 let postings: Postings =
                     bincode::deserialize(bytes).map_err(LmdbError::SerializationError)?;
@@ -162,11 +953,19 @@ where
 
     // Batch get operation with single transaction
     pub fn get_batch(&self, queries: &[(F, String)]) -> Result<Vec<Option<Postings>>, LmdbError> {
-        let rtxn = self.env.read_txn().map_err(LmdbError::HeedError)?;
+        let resolved = queries
+            .iter()
+            .map(|(field, term)| self.resolve(*field, term))
+            .collect::<Result<Vec<_>, _>>()?;
 
+        let rtxn = self.read_txn()?;
         let mut results = Vec::with_capacity(queries.len());
-        for (field, term) in queries {
-            results.push(self.get_with_txn(&rtxn, *field, term)?);
+        for resolved in resolved {
+            results.push(match resolved {
+                Some(Resolved::Staged(postings)) => Some(postings),
+                Some(Resolved::Committed(key)) => self.get_committed(&rtxn, &key)?,
+                None => None,
+            });
         }
 
         Ok(results)
@@ -179,48 +978,192 @@ where
     where
         E: std::fmt::Display,
     {
-        let rtxn = self.env.read_txn().map_err(LmdbError::HeedError)?;
+        // Copied before the txn, which must not wait on the registry lock
+        let mut fields = self.fields.read().map_err(|_| LmdbError::LockPoisoned)?.clone();
+        let rtxn = self.read_txn()?;
+        // A writer may have extended the registry since we opened
+        if let Some(meta) = self.meta.as_ref().filter(|_| self.read_only) {
+            fields = FieldRegistry::load(read_field_ids(meta, &rtxn)?)?;
+        }
         for result in self.db.iter(&rtxn).map_err(LmdbError::HeedError)? {
             let (key_str, value_bytes) = result.map_err(LmdbError::HeedError)?;
-            let (field, term) = Self::decode_key(key_str).map_err(LmdbError::SerializationError)?;
-            callback(field, &term, value_bytes)
+            let (id, term) = Self::decode_key(key_str).map_err(LmdbError::SerializationError)?;
+            // Postings of fields removed from `F` stay on disk but are unreachable
+            let Some(field) = fields.field(id) else {
+                continue;
+            };
+            callback(field, term, value_bytes)
                 .map_err(|e| LmdbError::CallbackError(e.to_string()))?;
         }
         Ok(())
     }
 }
 
+/// Where [`LmdbStorage::resolve`] found a term's postings.
+enum Resolved {
+    Staged(Postings),
+    Committed(String),
+}
+
 impl<F> LmdbStorage<F>
 where
     F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned + 'static + std::fmt::Debug,
 {
-    pub fn open(path: &Path) -> Result<Self, heed::Error> {
+    pub fn open(path: &Path) -> Result<Self, LmdbError> {
         Self::open_with_batch_size(path, BATCH_SIZE)
     }
 
-    pub fn open_with_batch_size(path: &Path, batch_size: usize) -> Result<Self, heed::Error> {
-        create_dir_all(path)?;
+    pub fn open_with_batch_size(path: &Path, batch_size: usize) -> Result<Self, LmdbError> {
+        Self::open_with_options(path, LmdbOptions::new().batch_size(batch_size))
+    }
 
-        let env = unsafe {
-            EnvOpenOptions::new()
-                .map_size(MAP_SIZE)
-                .max_dbs(NUM_DBS)
-                .max_readers(126) // Increase max concurrent readers
-                .open(path)?
+    /// Opens (or creates) the index, refusing layouts other than [`FORMAT_VERSION`].
+    ///
+    /// An index has a single writer and any number of readers, across processes.
+    /// Writable opens take an exclusive lock on [`WRITER_LOCK_FILE`]; if another
+    /// writer holds it the open fails with [`LmdbError::WriterLocked`], or falls
+    /// back to read-only when [`LmdbOptions::fallback_read_only`] is set.
+    pub fn open_with_options(path: &Path, mut options: LmdbOptions) -> Result<Self, LmdbError> {
+        let writer_lock = if options.read_only {
+            None
+        } else {
+            match acquire_writer_lock(path) {
+                Ok(lock) => Some(lock),
+                Err(LmdbError::WriterLocked(locked)) if options.fallback_read_only => {
+                    warn!(
+                        "[LMDB] Writer lock on {:?} is taken, opening read-only",
+                        locked
+                    );
+                    options.read_only = true;
+                    None
+                }
+                Err(e) => return Err(e),
+            }
         };
 
-        let mut wtxn = env.write_txn()?;
-        let db = env.create_database(&mut wtxn, Some("postings"))?;
-        wtxn.commit()?;
+        let env = open_env(path, &options).map_err(LmdbError::HeedError)?;
+
+        let (db, meta, version) = if options.read_only {
+            // A read-only env cannot create databases, the index must already exist.
+            let rtxn = env.read_txn().map_err(LmdbError::HeedError)?;
+            let db = env
+                .open_database(&rtxn, Some(POSTINGS_DB))
+                .map_err(LmdbError::HeedError)?
+                .ok_or(LmdbError::HeedError(heed::Error::Mdb(heed::MdbError::NotFound)))?;
+            let meta = env
+                .open_database(&rtxn, Some(META_DB))
+                .map_err(LmdbError::HeedError)?;
+            let version = detect_version(meta.as_ref(), Some(&db), &rtxn)?;
+            rtxn.commit().map_err(LmdbError::HeedError)?;
+            (db, meta, version)
+        } else {
+            let mut wtxn = env.write_txn().map_err(LmdbError::HeedError)?;
+            let db = env
+                .create_database(&mut wtxn, Some(POSTINGS_DB))
+                .map_err(LmdbError::HeedError)?;
+            let meta = env
+                .create_database(&mut wtxn, Some(META_DB))
+                .map_err(LmdbError::HeedError)?;
+            let version = detect_version(Some(&meta), Some(&db), &wtxn)?;
+            if version == FORMAT_VERSION {
+                write_version(&meta, &mut wtxn, version)?;
+            }
+            wtxn.commit().map_err(LmdbError::HeedError)?;
+            (db, Some(meta), version)
+        };
+
+        if version != FORMAT_VERSION {
+            return Err(LmdbError::VersionMismatch {
+                found: version,
+                expected: FORMAT_VERSION,
+            });
+        }
+
+        let field_ids = match &meta {
+            Some(meta) => {
+                let rtxn = env.read_txn().map_err(LmdbError::HeedError)?;
+                read_field_ids(meta, &rtxn)?
+            }
+            None => Vec::new(),
+        };
+        let fields = FieldRegistry::load(field_ids)?;
 
         Ok(Self {
             env,
             db,
+            meta,
             _phantom: PhantomData,
-            write_buffer: Mutex::new(WriteBuffer::with_capacity(batch_size)),
-            batch_size,
+            write_buffer: Mutex::new(WriteBuffer::new()),
+            pending_config: Mutex::new(None),
+            pending_provenance: Mutex::new(None),
+            pending_metadata: Mutex::new(None),
+            pending_ids: Mutex::new(Vec::new()),
+            pending_stored: Mutex::new(Vec::new()),
+            fields: RwLock::new(fields),
+            generations: Arc::new(RwLock::new(Generations::default())),
+            map_lock: Arc::new(RwLock::new(())),
+            batch_size: options.batch_size,
+            max_map_size: options.max_map_size,
+            read_only: options.read_only,
+            _writer_lock: writer_lock,
         })
     }
+
+    /// On-disk layout version of this index.
+    pub fn format_version(&self) -> Result<u32, LmdbError> {
+        let rtxn = self.read_txn()?;
+        detect_version(self.meta.as_ref(), Some(&self.db), &rtxn)
+    }
+}
+
+/// Name of the named database holding the stored values of `field`.
+pub(super) fn stored_db_name<F: Serialize>(field: &F) -> Result<String, LmdbError> {
+    Ok(format!("{}{}", STORED_DB_PREFIX, variant_name(field)?))
+}
+
+/// Key of a stored value in the snapshot history, apart from postings keys,
+/// which start with a hex field id.
+pub(super) fn stored_key(db_name: &str, doc_id: DocId) -> String {
+    format!("{}/{}", db_name, doc_id.get())
+}
+
+/// Takes the exclusive writer lock of an index directory without blocking.
+/// The lock is advisory and tied to the returned file; dropping it releases
+/// the lock, as does the process exiting.
+pub(crate) fn acquire_writer_lock(path: &Path) -> Result<File, LmdbError> {
+    create_dir_all(path).map_err(|e| LmdbError::HeedError(e.into()))?;
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.join(WRITER_LOCK_FILE))
+        .map_err(|e| LmdbError::HeedError(e.into()))?;
+
+    match file.try_lock() {
+        Ok(()) => Ok(file),
+        Err(TryLockError::WouldBlock) => Err(LmdbError::WriterLocked(path.to_path_buf())),
+        Err(TryLockError::Error(e)) => Err(LmdbError::HeedError(e.into())),
+    }
+}
+
+pub(crate) fn open_env(path: &Path, options: &LmdbOptions) -> Result<Env, heed::Error> {
+    let mut flags = options.sync_mode.flags();
+    if options.read_only {
+        flags |= EnvFlags::READ_ONLY;
+    } else {
+        create_dir_all(path)?;
+    }
+
+    let mut env_options = EnvOpenOptions::new();
+    env_options
+        .map_size(options.map_size)
+        .max_dbs(options.max_dbs)
+        .max_readers(options.max_readers);
+
+    unsafe {
+        env_options.flags(flags);
+        env_options.open(path)
+    }
 }
 
 impl<F> PostingsStorage<F> for LmdbStorage<F>
@@ -230,29 +1173,24 @@ where
     type Error = LmdbError;
 
     fn get(&self, field: F, term: &str) -> Result<Option<Postings>, Self::Error> {
-        // Create transaction only when needed, drops immediately
-        let rtxn = self.env.read_txn().map_err(LmdbError::HeedError)?;
-        self.get_with_txn(&rtxn, field, term)
+        match self.resolve(field, term)? {
+            Some(Resolved::Staged(postings)) => Ok(Some(postings)),
+            // Create transaction only when needed, drops immediately
+            Some(Resolved::Committed(key)) => self.get_committed(&*self.read_txn()?, &key),
+            None => Ok(None),
+        }
     }
 
     fn put(&mut self, field: F, term: String, postings: Postings) -> Result<(), Self::Error> {
-        let key = Self::encode_key(field, &term).map_err(LmdbError::SerializationError)?;
-        let value_bytes = bincode::serialize(&postings).map_err(LmdbError::SerializationError)?;
-
-        {
-            let mut buffer = self.write_buffer.lock().unwrap();
-            buffer.push(key, value_bytes);
-            if buffer.len() < self.batch_size {
-                return Ok(());
-            }
-        }
-
-        self.flush()
+        self.put_shared(field, term, postings)
     }
 
     fn contains(&self, field: F, term: &str) -> Result<bool, Self::Error> {
-        let key = Self::encode_key(field, term).map_err(LmdbError::SerializationError)?;
-        let rtxn = self.env.read_txn().map_err(LmdbError::HeedError)?;
+        let Some(id) = self.field_id(field)? else {
+            return Ok(false);
+        };
+        let key = Self::encode_key(id, term);
+        let rtxn = self.read_txn()?;
         Ok(self
             .db
             .get(&rtxn, &key)
@@ -285,6 +1223,96 @@ where
     fn flush(&mut self) -> Result<(), Self::Error> {
         LmdbStorage::flush(self)
     }
+
+    fn generation(&self) -> Result<u64, Self::Error> {
+        LmdbStorage::generation(self)
+    }
+
+    fn read_config(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        LmdbStorage::read_config(self)
+    }
+
+    fn write_config(&mut self, config: Vec<u8>) -> Result<(), Self::Error> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        *self.pending_config.lock().map_err(|_| LmdbError::LockPoisoned)? = Some(config);
+        Ok(())
+    }
+
+    fn read_provenance(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        LmdbStorage::read_provenance(self)
+    }
+
+    fn write_provenance(&mut self, provenance: Vec<u8>) -> Result<(), Self::Error> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        *self
+            .pending_provenance
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)? = Some(provenance);
+        Ok(())
+    }
+
+    fn read_metadata(&self) -> Result<Option<(u64, Vec<u8>)>, Self::Error> {
+        LmdbStorage::read_metadata(self)
+    }
+
+    fn write_metadata(&mut self, snapshot: Vec<u8>) -> Result<(), Self::Error> {
+        // Staged by every engine flush, which is a no-op on read-only opens
+        if self.read_only {
+            return Ok(());
+        }
+        *self
+            .pending_metadata
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)? = Some(snapshot);
+        Ok(())
+    }
+
+    fn read_id_map(&self) -> Result<Vec<(String, DocId)>, Self::Error> {
+        LmdbStorage::read_id_map(self)
+    }
+
+    fn write_external_id(
+        &mut self,
+        external_id: &str,
+        doc_id: Option<DocId>,
+    ) -> Result<(), Self::Error> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        self.pending_ids
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .push((external_id.to_string(), doc_id));
+        Ok(())
+    }
+
+    fn write_stored(
+        &mut self,
+        doc_id: DocId,
+        field: F,
+        value: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        self.pending_stored
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .push((field, doc_id, value.map(str::to_string)));
+        Ok(())
+    }
+
+    fn read_stored(
+        &self,
+        doc_ids: &[DocId],
+        fields: &[F],
+    ) -> Result<Option<Vec<HashMap<F, String>>>, Self::Error> {
+        LmdbStorage::read_stored(self, doc_ids, fields).map(Some)
+    }
 }
 
 impl<F> Drop for LmdbStorage<F>
@@ -293,10 +1321,5 @@ where
 {
     fn drop(&mut self) {
         let _ = self.flush();
-
-        if let Ok(path) = self.env.path().canonicalize() {
-            let mut envs = OPEN_ENVS.lock().unwrap();
-            envs.remove(&path);
-        }
     }
 }
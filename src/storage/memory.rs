@@ -8,6 +8,9 @@ where
     F: Hash + Eq + Clone + Ord + Copy,
 {
     data: BTreeMap<(F, String), Postings>,
+    config: Option<Vec<u8>>,
+    provenance: Option<Vec<u8>>,
+    metadata: Option<Vec<u8>>,
 }
 
 impl<F> InMemoryStorage<F>
@@ -17,6 +20,9 @@ where
     pub fn new() -> Self {
         Self {
             data: BTreeMap::new(),
+            config: None,
+            provenance: None,
+            metadata: None,
         }
     }
 }
@@ -79,6 +85,33 @@ where
         Ok(())
     }
 
+    fn read_config(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.config.clone())
+    }
+
+    fn write_config(&mut self, config: Vec<u8>) -> Result<(), Self::Error> {
+        self.config = Some(config);
+        Ok(())
+    }
+
+    fn read_provenance(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(self.provenance.clone())
+    }
+
+    fn write_provenance(&mut self, provenance: Vec<u8>) -> Result<(), Self::Error> {
+        self.provenance = Some(provenance);
+        Ok(())
+    }
+
+    fn read_metadata(&self) -> Result<Option<(u64, Vec<u8>)>, Self::Error> {
+        Ok(self.metadata.clone().map(|snapshot| (0, snapshot)))
+    }
+
+    fn write_metadata(&mut self, snapshot: Vec<u8>) -> Result<(), Self::Error> {
+        self.metadata = Some(snapshot);
+        Ok(())
+    }
+
     // Batch operation (uses default trait implementation which is fine for in-memory)
     fn get_batch(&self, queries: &[(F, String)]) -> Result<Vec<Option<Postings>>, Self::Error> {
         let mut results = Vec::with_capacity(queries.len());
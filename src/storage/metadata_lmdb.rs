@@ -0,0 +1,208 @@
+//! LMDB home for spilled document frequencies and document lengths.
+//!
+//! Keys use the field's variant name, like the postings field registry, so
+//! reordering the field enum doesn't invalidate a store. Values are `u64` LE.
+
+use super::fields::variant_name;
+use super::lmdb::{LmdbError, LmdbOptions, acquire_writer_lock, open_env};
+use crate::DocId;
+use crate::error::LfasError;
+use crate::metadata::MetadataStore;
+use heed::types::Bytes;
+use heed::{Database, Env};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::File;
+use std::hash::Hash;
+use std::path::Path;
+use std::sync::Mutex;
+
+const DF_DB: &str = "term_df";
+const LENGTHS_DB: &str = "doc_lengths";
+/// Lookups kept in memory before the cache is dropped and refilled.
+pub const DEFAULT_METADATA_CACHE: usize = 100_000;
+
+struct Cache<F> {
+    names: HashMap<F, String>,
+    values: HashMap<Vec<u8>, Option<usize>>,
+}
+
+pub struct LmdbMetadataStore<F> {
+    env: Env,
+    dfs: Database<Bytes, Bytes>,
+    lengths: Database<Bytes, Bytes>,
+    cache: Mutex<Cache<F>>,
+    cache_capacity: usize,
+    read_only: bool,
+    _writer_lock: Option<File>,
+}
+
+impl<F> LmdbMetadataStore<F>
+where
+    F: Hash + Eq + Clone + Serialize + Send,
+{
+    /// Opens (or creates) a store in its own directory, which must not be
+    /// the postings index directory.
+    pub fn open(path: &Path, options: LmdbOptions) -> Result<Self, LmdbError> {
+        let writer_lock = if options.read_only {
+            None
+        } else {
+            Some(acquire_writer_lock(path)?)
+        };
+        let env = open_env(path, &options).map_err(LmdbError::HeedError)?;
+
+        let (dfs, lengths) = if options.read_only {
+            let rtxn = env.read_txn().map_err(LmdbError::HeedError)?;
+            let missing = || LmdbError::HeedError(heed::Error::Mdb(heed::MdbError::NotFound));
+            let dfs = env
+                .open_database(&rtxn, Some(DF_DB))
+                .map_err(LmdbError::HeedError)?
+                .ok_or_else(missing)?;
+            let lengths = env
+                .open_database(&rtxn, Some(LENGTHS_DB))
+                .map_err(LmdbError::HeedError)?
+                .ok_or_else(missing)?;
+            rtxn.commit().map_err(LmdbError::HeedError)?;
+            (dfs, lengths)
+        } else {
+            let mut wtxn = env.write_txn().map_err(LmdbError::HeedError)?;
+            let dfs = env
+                .create_database(&mut wtxn, Some(DF_DB))
+                .map_err(LmdbError::HeedError)?;
+            let lengths = env
+                .create_database(&mut wtxn, Some(LENGTHS_DB))
+                .map_err(LmdbError::HeedError)?;
+            wtxn.commit().map_err(LmdbError::HeedError)?;
+            (dfs, lengths)
+        };
+
+        Ok(Self {
+            env,
+            dfs,
+            lengths,
+            cache: Mutex::new(Cache {
+                names: HashMap::new(),
+                values: HashMap::new(),
+            }),
+            cache_capacity: DEFAULT_METADATA_CACHE,
+            read_only: options.read_only,
+            _writer_lock: writer_lock,
+        })
+    }
+
+    pub fn cache_capacity(mut self, capacity: usize) -> Self {
+        self.cache_capacity = capacity;
+        self
+    }
+
+    fn name<'a>(names: &'a mut HashMap<F, String>, field: &F) -> Result<&'a str, LmdbError> {
+        if !names.contains_key(field) {
+            names.insert(field.clone(), variant_name(field)?);
+        }
+        Ok(&names[field])
+    }
+
+    fn df_key(names: &mut HashMap<F, String>, field: &F, term: &str) -> Result<Vec<u8>, LmdbError> {
+        let mut key = Self::name(names, field)?.as_bytes().to_vec();
+        key.push(0);
+        key.extend_from_slice(term.as_bytes());
+        Ok(key)
+    }
+
+    fn length_key(
+        names: &mut HashMap<F, String>,
+        doc_id: DocId,
+        field: &F,
+    ) -> Result<Vec<u8>, LmdbError> {
+        let mut key = u64::from(doc_id.get()).to_be_bytes().to_vec();
+        key.extend_from_slice(Self::name(names, field)?.as_bytes());
+        Ok(key)
+    }
+
+    /// Cached read of `key` from `db`.
+    fn lookup(
+        &self,
+        db: Database<Bytes, Bytes>,
+        key: impl FnOnce(&mut HashMap<F, String>) -> Result<Vec<u8>, LmdbError>,
+    ) -> Result<Option<usize>, LmdbError> {
+        let mut cache = self.cache.lock().map_err(|_| LmdbError::LockPoisoned)?;
+        let key = key(&mut cache.names)?;
+        if let Some(&value) = cache.values.get(&key) {
+            return Ok(value);
+        }
+
+        let rtxn = self.env.read_txn().map_err(LmdbError::HeedError)?;
+        let value = db
+            .get(&rtxn, &key)
+            .map_err(LmdbError::HeedError)?
+            .map(decode_count);
+        if cache.values.len() >= self.cache_capacity {
+            cache.values.clear();
+        }
+        cache.values.insert(key, value);
+        Ok(value)
+    }
+
+    fn write(
+        &self,
+        db: Database<Bytes, Bytes>,
+        entries: impl Iterator<Item = (Vec<u8>, usize)>,
+    ) -> Result<(), LmdbError> {
+        if self.read_only {
+            return Err(LmdbError::ReadOnly);
+        }
+        let mut wtxn = self.env.write_txn().map_err(LmdbError::HeedError)?;
+        for (key, value) in entries {
+            db.put(&mut wtxn, &key, &(value as u64).to_le_bytes())
+                .map_err(LmdbError::HeedError)?;
+        }
+        wtxn.commit().map_err(LmdbError::HeedError)?;
+        // Cached values may be stale now
+        self.cache
+            .lock()
+            .map_err(|_| LmdbError::LockPoisoned)?
+            .values
+            .clear();
+        Ok(())
+    }
+}
+
+fn decode_count(bytes: &[u8]) -> usize {
+    let mut buf = [0u8; 8];
+    let len = bytes.len().min(8);
+    buf[..len].copy_from_slice(&bytes[..len]);
+    u64::from_le_bytes(buf) as usize
+}
+
+impl<F> MetadataStore<F> for LmdbMetadataStore<F>
+where
+    F: Hash + Eq + Clone + Serialize + Send,
+{
+    fn get_df(&self, field: &F, term: &str) -> Result<Option<usize>, LfasError> {
+        Ok(self.lookup(self.dfs, |names| Self::df_key(names, field, term))?)
+    }
+
+    fn doc_length(&self, doc_id: DocId, field: &F) -> Result<Option<usize>, LfasError> {
+        Ok(self.lookup(self.lengths, |names| Self::length_key(names, doc_id, field))?)
+    }
+
+    fn put_dfs(&self, dfs: Vec<((F, String), usize)>) -> Result<(), LfasError> {
+        let mut names = HashMap::new();
+        let entries = dfs
+            .into_iter()
+            .map(|((field, term), df)| Ok((Self::df_key(&mut names, &field, &term)?, df)))
+            .collect::<Result<Vec<_>, LmdbError>>()?;
+        Ok(self.write(self.dfs, entries.into_iter())?)
+    }
+
+    fn put_lengths(&self, lengths: Vec<(DocId, HashMap<F, usize>)>) -> Result<(), LfasError> {
+        let mut names = HashMap::new();
+        let mut entries = Vec::new();
+        for (doc_id, fields) in lengths {
+            for (field, length) in fields {
+                entries.push((Self::length_key(&mut names, doc_id, &field)?, length));
+            }
+        }
+        Ok(self.write(self.lengths, entries.into_iter())?)
+    }
+}
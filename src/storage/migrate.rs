@@ -0,0 +1,358 @@
+//! On-disk format versioning for LMDB indexes.
+//!
+//! Every index stores its layout version in the `meta` database. Opening an
+//! index whose version differs from [`FORMAT_VERSION`] fails; [`migrate`]
+//! upgrades older layouts in place, one step at a time.
+
+use super::fields::{FieldId, FieldName};
+use super::lmdb::{LmdbError, LmdbOptions, acquire_writer_lock, open_env};
+use crate::DocId;
+use crate::postings::Postings;
+use crate::tokenizer::{NGRAM_LEN, ngram_key};
+use heed::types::{Bytes, Str};
+use heed::{Database, Env, RoTxn, RwTxn};
+use roaring::RoaringBitmap;
+use serde::Deserialize;
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+use tracing::info;
+
+/// Layout version written by this build.
+pub const FORMAT_VERSION: u32 = 4;
+
+pub(crate) const META_DB: &str = "meta";
+pub(crate) const POSTINGS_DB: &str = "postings";
+const VERSION_KEY: &str = "format_version";
+const GENERATION_KEY: &str = "generation";
+const CONFIG_KEY: &str = "engine_config";
+const PROVENANCE_KEY: &str = "provenance";
+const METADATA_KEY: &str = "metadata";
+const FIELD_IDS_KEY: &str = "field_ids";
+
+/// A single upgrade step from `version` to `version + 1`, run inside one write txn.
+type Migration = fn(&Env, &mut RwTxn) -> Result<(), LmdbError>;
+
+/// Indexed by source version.
+const MIGRATIONS: &[Migration] = &[
+    // v0 -> v1: unversioned indexes share the v1 hex-key layout, only the stamp is missing
+    |_, _| Ok(()),
+    // v1 -> v2: n-grams moved to their own namespace
+    copy_ngrams_to_namespace,
+    // v2 -> v3: term frequencies stored as a doc-aligned vector instead of a map
+    rewrite_frequencies_as_vec,
+    // v3 -> v4: keys carry registered field ids instead of bincode enum ordinals
+    rekey_field_ids,
+];
+
+/// Every 3-byte term may have been an n-gram, and since each full 3-byte token
+/// is also its own n-gram, its postings are exactly the n-gram postings. The
+/// originals stay in place; run `rebuild_metadata` afterwards.
+fn copy_ngrams_to_namespace(env: &Env, wtxn: &mut RwTxn) -> Result<(), LmdbError> {
+    let Some(postings): Option<Database<Str, Bytes>> = env
+        .open_database(wtxn, Some(POSTINGS_DB))
+        .map_err(LmdbError::HeedError)?
+    else {
+        return Ok(());
+    };
+
+    let mut copies = Vec::new();
+    for entry in postings.iter(wtxn).map_err(LmdbError::HeedError)? {
+        let (key, bytes) = entry.map_err(LmdbError::HeedError)?;
+        if let Some((field_hex, term)) = key.split_once(':') {
+            if term.len() == NGRAM_LEN {
+                copies.push((format!("{}:{}", field_hex, ngram_key(term)), bytes.to_vec()));
+            }
+        }
+    }
+
+    info!("[MIGRATE] Copying {} n-gram postings", copies.len());
+    for (key, bytes) in copies {
+        postings
+            .put(wtxn, &key, &bytes)
+            .map_err(LmdbError::HeedError)?;
+    }
+    Ok(())
+}
+
+/// Postings as serialized up to v2.
+#[derive(Deserialize)]
+struct PostingsV2 {
+    #[allow(dead_code)]
+    bitmap: RoaringBitmap,
+    frequencies: HashMap<DocId, u32>,
+}
+
+fn rewrite_frequencies_as_vec(env: &Env, wtxn: &mut RwTxn) -> Result<(), LmdbError> {
+    let Some(postings): Option<Database<Str, Bytes>> = env
+        .open_database(wtxn, Some(POSTINGS_DB))
+        .map_err(LmdbError::HeedError)?
+    else {
+        return Ok(());
+    };
+
+    let mut rewrites = Vec::new();
+    for entry in postings.iter(wtxn).map_err(LmdbError::HeedError)? {
+        let (key, bytes) = entry.map_err(LmdbError::HeedError)?;
+        let legacy: PostingsV2 =
+            bincode::deserialize(bytes).map_err(LmdbError::SerializationError)?;
+        let mut frequencies: Vec<(DocId, u32)> = legacy.frequencies.into_iter().collect();
+        frequencies.sort_unstable_by_key(|(doc_id, _)| *doc_id);
+        let bytes = bincode::serialize(&Postings::from_sorted(frequencies))
+            .map_err(LmdbError::SerializationError)?;
+        rewrites.push((key.to_string(), bytes));
+    }
+
+    info!("[MIGRATE] Rewriting {} postings", rewrites.len());
+    for (key, bytes) in rewrites {
+        postings
+            .put(wtxn, &key, &bytes)
+            .map_err(LmdbError::HeedError)?;
+    }
+    Ok(())
+}
+
+/// Rewrites `<hex bincode field>:term` keys as `<hex u16 id>:term`, reusing
+/// each enum ordinal as its id. The field type isn't known here, so ordinals
+/// are recorded as such and pinned to variant names by the next writable open.
+fn rekey_field_ids(env: &Env, wtxn: &mut RwTxn) -> Result<(), LmdbError> {
+    let Some(postings): Option<Database<Str, Bytes>> = env
+        .open_database(wtxn, Some(POSTINGS_DB))
+        .map_err(LmdbError::HeedError)?
+    else {
+        return Ok(());
+    };
+
+    let corrupt = |key: &str| LmdbError::CallbackError(format!("unexpected v3 key '{}'", key));
+    let mut entries = Vec::new();
+    let mut ordinals = BTreeSet::new();
+    for entry in postings.iter(wtxn).map_err(LmdbError::HeedError)? {
+        let (key, bytes) = entry.map_err(LmdbError::HeedError)?;
+        let (field_hex, term) = key.split_once(':').ok_or_else(|| corrupt(key))?;
+        // Unit enum variants encode as a little-endian u32 ordinal
+        let ordinal = u32::from_str_radix(field_hex, 16)
+            .ok()
+            .filter(|_| field_hex.len() == 8)
+            .map(u32::swap_bytes)
+            .and_then(|ordinal| FieldId::try_from(ordinal).ok())
+            .ok_or_else(|| corrupt(key))?;
+        ordinals.insert(ordinal);
+        entries.push((format!("{:04x}:{}", ordinal, term), bytes.to_vec()));
+    }
+
+    info!(
+        "[MIGRATE] Re-keying {} postings across {} fields",
+        entries.len(),
+        ordinals.len()
+    );
+    postings.clear(wtxn).map_err(LmdbError::HeedError)?;
+    for (key, bytes) in entries {
+        postings
+            .put(wtxn, &key, &bytes)
+            .map_err(LmdbError::HeedError)?;
+    }
+
+    let meta: Database<Str, Bytes> = env
+        .create_database(wtxn, Some(META_DB))
+        .map_err(LmdbError::HeedError)?;
+    let field_ids: Vec<(FieldId, FieldName)> = ordinals
+        .into_iter()
+        .map(|ordinal| (ordinal, FieldName::Ordinal(ordinal as u32)))
+        .collect();
+    write_field_ids(&meta, wtxn, &field_ids)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+}
+
+pub(crate) fn read_version(
+    meta: &Database<Str, Bytes>,
+    rtxn: &RoTxn,
+) -> Result<Option<u32>, LmdbError> {
+    let Some(bytes) = meta.get(rtxn, VERSION_KEY).map_err(LmdbError::HeedError)? else {
+        return Ok(None);
+    };
+    let bytes: [u8; 4] = bytes
+        .try_into()
+        .map_err(|_| LmdbError::CallbackError("corrupt format version record".into()))?;
+    Ok(Some(u32::from_le_bytes(bytes)))
+}
+
+pub(crate) fn write_version(
+    meta: &Database<Str, Bytes>,
+    wtxn: &mut RwTxn,
+    version: u32,
+) -> Result<(), LmdbError> {
+    meta.put(wtxn, VERSION_KEY, &version.to_le_bytes())
+        .map_err(LmdbError::HeedError)
+}
+
+pub(crate) fn read_generation(meta: &Database<Str, Bytes>, rtxn: &RoTxn) -> Result<u64, LmdbError> {
+    let Some(bytes) = meta.get(rtxn, GENERATION_KEY).map_err(LmdbError::HeedError)? else {
+        return Ok(0);
+    };
+    let bytes: [u8; 8] = bytes
+        .try_into()
+        .map_err(|_| LmdbError::CallbackError("corrupt generation record".into()))?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn write_generation(
+    meta: &Database<Str, Bytes>,
+    wtxn: &mut RwTxn,
+    generation: u64,
+) -> Result<(), LmdbError> {
+    meta.put(wtxn, GENERATION_KEY, &generation.to_le_bytes())
+        .map_err(LmdbError::HeedError)
+}
+
+pub(crate) fn read_config(
+    meta: &Database<Str, Bytes>,
+    rtxn: &RoTxn,
+) -> Result<Option<Vec<u8>>, LmdbError> {
+    Ok(meta
+        .get(rtxn, CONFIG_KEY)
+        .map_err(LmdbError::HeedError)?
+        .map(<[u8]>::to_vec))
+}
+
+pub(crate) fn write_config(
+    meta: &Database<Str, Bytes>,
+    wtxn: &mut RwTxn,
+    config: &[u8],
+) -> Result<(), LmdbError> {
+    meta.put(wtxn, CONFIG_KEY, config)
+        .map_err(LmdbError::HeedError)
+}
+
+pub(crate) fn read_provenance(
+    meta: &Database<Str, Bytes>,
+    rtxn: &RoTxn,
+) -> Result<Option<Vec<u8>>, LmdbError> {
+    Ok(meta
+        .get(rtxn, PROVENANCE_KEY)
+        .map_err(LmdbError::HeedError)?
+        .map(<[u8]>::to_vec))
+}
+
+pub(crate) fn write_provenance(
+    meta: &Database<Str, Bytes>,
+    wtxn: &mut RwTxn,
+    provenance: &[u8],
+) -> Result<(), LmdbError> {
+    meta.put(wtxn, PROVENANCE_KEY, provenance)
+        .map_err(LmdbError::HeedError)
+}
+
+/// The metadata snapshot and the generation it was committed at, stored as
+/// the generation (`u64` LE) followed by the snapshot.
+pub(crate) fn read_metadata(
+    meta: &Database<Str, Bytes>,
+    rtxn: &RoTxn,
+) -> Result<Option<(u64, Vec<u8>)>, LmdbError> {
+    let Some(bytes) = meta.get(rtxn, METADATA_KEY).map_err(LmdbError::HeedError)? else {
+        return Ok(None);
+    };
+    let (generation, snapshot) = bytes
+        .split_first_chunk::<8>()
+        .ok_or_else(|| LmdbError::CallbackError("corrupt metadata record".into()))?;
+    Ok(Some((u64::from_le_bytes(*generation), snapshot.to_vec())))
+}
+
+pub(crate) fn write_metadata(
+    meta: &Database<Str, Bytes>,
+    wtxn: &mut RwTxn,
+    generation: u64,
+    snapshot: &[u8],
+) -> Result<(), LmdbError> {
+    let mut bytes = Vec::with_capacity(8 + snapshot.len());
+    bytes.extend_from_slice(&generation.to_le_bytes());
+    bytes.extend_from_slice(snapshot);
+    meta.put(wtxn, METADATA_KEY, &bytes)
+        .map_err(LmdbError::HeedError)
+}
+
+pub(crate) fn read_field_ids(
+    meta: &Database<Str, Bytes>,
+    rtxn: &RoTxn,
+) -> Result<Vec<(FieldId, FieldName)>, LmdbError> {
+    match meta.get(rtxn, FIELD_IDS_KEY).map_err(LmdbError::HeedError)? {
+        Some(bytes) => bincode::deserialize(bytes).map_err(LmdbError::SerializationError),
+        None => Ok(Vec::new()),
+    }
+}
+
+pub(crate) fn write_field_ids(
+    meta: &Database<Str, Bytes>,
+    wtxn: &mut RwTxn,
+    field_ids: &[(FieldId, FieldName)],
+) -> Result<(), LmdbError> {
+    let bytes = bincode::serialize(field_ids).map_err(LmdbError::SerializationError)?;
+    meta.put(wtxn, FIELD_IDS_KEY, &bytes)
+        .map_err(LmdbError::HeedError)
+}
+
+/// Version of an index: the stamped one, `FORMAT_VERSION` for a brand new
+/// (empty) index, or 0 for legacy unversioned data.
+pub(crate) fn detect_version(
+    meta: Option<&Database<Str, Bytes>>,
+    postings: Option<&Database<Str, Bytes>>,
+    rtxn: &RoTxn,
+) -> Result<u32, LmdbError> {
+    if let Some(meta) = meta {
+        if let Some(version) = read_version(meta, rtxn)? {
+            return Ok(version);
+        }
+    }
+
+    let is_empty = match postings {
+        Some(db) => db.is_empty(rtxn).map_err(LmdbError::HeedError)?,
+        None => true,
+    };
+    Ok(if is_empty { FORMAT_VERSION } else { 0 })
+}
+
+/// Upgrades the index at `path` to [`FORMAT_VERSION`] in place.
+pub fn migrate(path: &Path) -> Result<MigrationReport, LmdbError> {
+    migrate_with_options(path, LmdbOptions::new())
+}
+
+pub fn migrate_with_options(
+    path: &Path,
+    options: LmdbOptions,
+) -> Result<MigrationReport, LmdbError> {
+    // Migrating under a live writer would race its commits
+    let _writer_lock = acquire_writer_lock(path)?;
+    let env = open_env(path, &options.read_only(false)).map_err(LmdbError::HeedError)?;
+
+    let mut wtxn = env.write_txn().map_err(LmdbError::HeedError)?;
+    let meta: Database<Str, Bytes> = env
+        .create_database(&mut wtxn, Some(META_DB))
+        .map_err(LmdbError::HeedError)?;
+    let postings: Option<Database<Str, Bytes>> = env
+        .open_database(&wtxn, Some(POSTINGS_DB))
+        .map_err(LmdbError::HeedError)?;
+
+    let from = detect_version(Some(&meta), postings.as_ref(), &wtxn)?;
+    if from > FORMAT_VERSION {
+        return Err(LmdbError::VersionMismatch {
+            found: from,
+            expected: FORMAT_VERSION,
+        });
+    }
+
+    for version in from..FORMAT_VERSION {
+        info!("[MIGRATE] Upgrading index {:?} from v{} to v{}", path, version, version + 1);
+        MIGRATIONS[version as usize](&env, &mut wtxn)?;
+    }
+
+    write_version(&meta, &mut wtxn, FORMAT_VERSION)?;
+    wtxn.commit().map_err(LmdbError::HeedError)?;
+
+    Ok(MigrationReport {
+        from,
+        to: FORMAT_VERSION,
+    })
+}
@@ -1,10 +1,20 @@
+mod fields;
+mod length_columns;
 mod lmdb;
 mod memory;
+mod metadata_lmdb;
+pub mod migrate;
+mod snapshot;
 
-pub use lmdb::{LmdbError, LmdbStorage};
+pub use length_columns::LmdbLengthColumns;
+pub use lmdb::{LmdbError, LmdbOptions, LmdbStorage, SyncMode};
 pub use memory::InMemoryStorage;
+pub use metadata_lmdb::{DEFAULT_METADATA_CACHE, LmdbMetadataStore};
+pub use snapshot::LmdbSnapshot;
 
+use crate::DocId;
 use crate::postings::Postings;
+use std::collections::HashMap;
 use std::hash::Hash;
 
 pub trait PostingsStorage<F>
@@ -38,6 +48,81 @@ where
         Ok(())
     }
 
+    /// Counter bumped by every committed write batch; 0 for storages that
+    /// can't be shared between processes.
+    fn generation(&self) -> Result<u64, Self::Error> {
+        Ok(0)
+    }
+
+    /// Serialized engine config stored with the index, if any.
+    fn read_config(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Replaces the stored engine config; may only be durable after `flush`.
+    fn write_config(&mut self, _config: Vec<u8>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Serialized build provenance stored with the index, if any.
+    fn read_provenance(&self) -> Result<Option<Vec<u8>>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Replaces the stored build provenance; may only be durable after `flush`.
+    fn write_provenance(&mut self, _provenance: Vec<u8>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Serialized metadata snapshot stored with the index, if any, with the
+    /// generation it was committed at.
+    fn read_metadata(&self) -> Result<Option<(u64, Vec<u8>)>, Self::Error> {
+        Ok(None)
+    }
+
+    /// Replaces the stored metadata snapshot. Committed by the next `flush`
+    /// in the same transaction as the postings, stamped with its generation.
+    fn write_metadata(&mut self, _snapshot: Vec<u8>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Every (external id, doc id) pair stored with the index.
+    fn read_id_map(&self) -> Result<Vec<(String, DocId)>, Self::Error> {
+        Ok(Vec::new())
+    }
+
+    /// Maps `external_id` to `doc_id`, or unmaps it on `None`; may only be
+    /// durable after `flush`.
+    fn write_external_id(
+        &mut self,
+        _external_id: &str,
+        _doc_id: Option<DocId>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Stages the stored value of one field of a document, or its removal
+    /// on `None`; may only be durable after `flush`.
+    fn write_stored(
+        &mut self,
+        _doc_id: DocId,
+        _field: F,
+        _value: Option<&str>,
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    /// Committed values of `fields` for each of `doc_ids`, in order; a
+    /// document without any of them gets an empty map. Only the requested
+    /// fields are read. None when the storage keeps no stored values.
+    fn read_stored(
+        &self,
+        _doc_ids: &[DocId],
+        _fields: &[F],
+    ) -> Result<Option<Vec<HashMap<F, String>>>, Self::Error> {
+        Ok(None)
+    }
+
     /// Batch get with single transaction
     fn get_batch(&self, queries: &[(F, String)]) -> Result<Vec<Option<Postings>>, Self::Error> {
         // Default: fallback to individual gets (for in-memory storage)
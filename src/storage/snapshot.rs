@@ -0,0 +1,278 @@
+//! Generation-pinned reads for long jobs that need stable results while
+//! indexing continues. An [`LmdbSnapshot`] sees the index as it was at the
+//! generation it pinned: while any snapshot is alive, every commit keeps the
+//! previous bytes of the keys it overwrites, and those versions are dropped
+//! once no snapshot of an older generation is left.
+
+use super::PostingsStorage;
+use super::fields::FieldRegistry;
+use super::lmdb::{LmdbError, LmdbStorage, MapReadTxn, stored_db_name, stored_key};
+use crate::DocId;
+use crate::postings::Postings;
+use heed::types::{Bytes, Str};
+use heed::{Database, Env};
+use serde::{Serialize, de::DeserializeOwned};
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::sync::{Arc, RwLock};
+use tracing::info;
+
+/// Pinned generations and the key versions they still read.
+#[derive(Debug, Default)]
+pub(crate) struct Generations {
+    /// generation -> live snapshots pinned to it
+    pins: BTreeMap<u64, usize>,
+    /// generation -> bytes each key had before that generation's commit,
+    /// `None` for keys it created
+    history: BTreeMap<u64, HashMap<String, Option<Vec<u8>>>>,
+}
+
+impl Generations {
+    pub fn is_pinned(&self) -> bool {
+        !self.pins.is_empty()
+    }
+
+    pub fn pinned(&self) -> Vec<u64> {
+        self.pins.keys().copied().collect()
+    }
+
+    pub fn pin(&mut self, generation: u64) {
+        *self.pins.entry(generation).or_insert(0) += 1;
+    }
+
+    /// Releases one pin of `generation`. Once none is left, returns the
+    /// number of key versions no remaining pin reads anymore.
+    fn unpin(&mut self, generation: u64) -> Option<usize> {
+        let count = self.pins.get_mut(&generation)?;
+        *count -= 1;
+        if *count > 0 {
+            return None;
+        }
+        self.pins.remove(&generation);
+
+        // A pin at `p` reads the history of every generation after `p`
+        let kept = match self.pins.keys().next() {
+            Some(&oldest) => self.history.split_off(&(oldest + 1)),
+            None => BTreeMap::new(),
+        };
+        let retired = std::mem::replace(&mut self.history, kept);
+        Some(retired.values().map(HashMap::len).sum())
+    }
+
+    /// Records the bytes `key` had before the commit of `generation`. The
+    /// first record of a key wins, later writes in the same generation don't
+    /// replace its pre-image.
+    pub fn record(&mut self, generation: u64, key: String, previous: Option<Vec<u8>>) {
+        self.history
+            .entry(generation)
+            .or_default()
+            .entry(key)
+            .or_insert(previous);
+    }
+
+    /// Records `keys` as created by the commit of `generation`.
+    pub fn record_new(&mut self, generation: u64, keys: impl IntoIterator<Item = String>) {
+        for key in keys {
+            self.record(generation, key, None);
+        }
+    }
+
+    /// Bytes of `key` as of `generation` if a later commit changed it:
+    /// `Some(None)` when the key didn't exist yet, `None` when unchanged.
+    fn version(&self, generation: u64, key: &str) -> Option<Option<&[u8]>> {
+        self.history
+            .range(generation + 1..)
+            .find_map(|(_, keys)| keys.get(key))
+            .map(Option::as_deref)
+    }
+}
+
+/// Read-only view of an [`LmdbStorage`] as of one generation, taken with
+/// [`LmdbStorage::snapshot`]. The pin is released on drop.
+pub struct LmdbSnapshot<F>
+where
+    F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned,
+{
+    env: Env,
+    db: Database<Str, Bytes>,
+    /// Field ids as of the pin; fields registered later have no keys here
+    fields: FieldRegistry<F>,
+    generation: u64,
+    /// Metadata snapshot committed with the pinned generation, if any
+    metadata: Option<Vec<u8>>,
+    generations: Arc<RwLock<Generations>>,
+    /// The storage's map lock, held shared by every read txn
+    map_lock: Arc<RwLock<()>>,
+}
+
+impl<F> LmdbSnapshot<F>
+where
+    F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned,
+{
+    /// `generation` must already be pinned in `generations`; the pin is
+    /// released on drop.
+    pub(super) fn new(
+        env: Env,
+        db: Database<Str, Bytes>,
+        fields: FieldRegistry<F>,
+        generation: u64,
+        metadata: Option<Vec<u8>>,
+        generations: Arc<RwLock<Generations>>,
+        map_lock: Arc<RwLock<()>>,
+    ) -> Self {
+        Self {
+            env,
+            db,
+            fields,
+            generation,
+            metadata,
+            generations,
+            map_lock,
+        }
+    }
+}
+
+impl<F> PostingsStorage<F> for LmdbSnapshot<F>
+where
+    F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned,
+{
+    type Error = LmdbError;
+
+    fn get(&self, field: F, term: &str) -> Result<Option<Postings>, Self::Error> {
+        let Some(id) = self.fields.id(field) else {
+            return Ok(None);
+        };
+        let key = LmdbStorage::<F>::encode_key(id, term);
+        // Held over the read so no commit lands between history and postings
+        let generations = self
+            .generations
+            .read()
+            .map_err(|_| LmdbError::LockPoisoned)?;
+        let rtxn = MapReadTxn::new(&self.env, &self.map_lock)?;
+        let bytes = match generations.version(self.generation, &key) {
+            Some(previous) => previous,
+            None => self.db.get(&rtxn, &key).map_err(LmdbError::HeedError)?,
+        };
+        bytes
+            .map(bincode::deserialize)
+            .transpose()
+            .map_err(LmdbError::SerializationError)
+    }
+
+    fn put(&mut self, _field: F, _term: String, _postings: Postings) -> Result<(), Self::Error> {
+        Err(LmdbError::ReadOnly)
+    }
+
+    fn contains(&self, field: F, term: &str) -> Result<bool, Self::Error> {
+        Ok(self.get(field, term)?.is_some())
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = Result<((F, String), Postings), Self::Error>> + '_> {
+        let mut results = Vec::new();
+        if let Err(e) = self.scan(|field, term, bytes| {
+            let postings: Postings = bincode::deserialize(bytes).map_err(|e| e.to_string())?;
+            results.push(Ok(((field, term.to_string()), postings)));
+            Ok::<_, String>(())
+        }) {
+            results.push(Err(e));
+        }
+        Box::new(results.into_iter())
+    }
+
+    fn scan<E>(
+        &self,
+        mut callback: impl FnMut(F, &str, &[u8]) -> Result<(), E>,
+    ) -> Result<(), Self::Error>
+    where
+        E: std::fmt::Display,
+    {
+        let generations = self
+            .generations
+            .read()
+            .map_err(|_| LmdbError::LockPoisoned)?;
+        let rtxn = MapReadTxn::new(&self.env, &self.map_lock)?;
+        for result in self.db.iter(&rtxn).map_err(LmdbError::HeedError)? {
+            let (key, current) = result.map_err(LmdbError::HeedError)?;
+            let bytes = match generations.version(self.generation, key) {
+                Some(Some(previous)) => previous,
+                Some(None) => continue,
+                None => current,
+            };
+            let (id, term) =
+                LmdbStorage::<F>::decode_key(key).map_err(LmdbError::SerializationError)?;
+            let Some(field) = self.fields.field(id) else {
+                continue;
+            };
+            callback(field, term, bytes).map_err(|e| LmdbError::CallbackError(e.to_string()))?;
+        }
+        Ok(())
+    }
+
+    fn generation(&self) -> Result<u64, Self::Error> {
+        Ok(self.generation)
+    }
+
+    fn read_metadata(&self) -> Result<Option<(u64, Vec<u8>)>, Self::Error> {
+        Ok(self
+            .metadata
+            .clone()
+            .map(|snapshot| (self.generation, snapshot)))
+    }
+
+    fn read_stored(
+        &self,
+        doc_ids: &[DocId],
+        fields: &[F],
+    ) -> Result<Option<Vec<HashMap<F, String>>>, Self::Error> {
+        // Held over the reads so no commit lands between history and values
+        let generations = self
+            .generations
+            .read()
+            .map_err(|_| LmdbError::LockPoisoned)?;
+        let rtxn = MapReadTxn::new(&self.env, &self.map_lock)?;
+        let mut docs = vec![HashMap::new(); doc_ids.len()];
+        for field in fields {
+            let name = stored_db_name(field)?;
+            let db: Option<Database<Bytes, Str>> = self
+                .env
+                .open_database(&rtxn, Some(&name))
+                .map_err(LmdbError::HeedError)?;
+            for (doc, doc_id) in docs.iter_mut().zip(doc_ids) {
+                let key = stored_key(&name, *doc_id);
+                let value = match generations.version(self.generation, &key) {
+                    Some(previous) => previous
+                        .map(|bytes| String::from_utf8(bytes.to_vec()))
+                        .transpose()
+                        .map_err(|e| LmdbError::CallbackError(e.to_string()))?,
+                    None => match db {
+                        Some(db) => db
+                            .get(&rtxn, &doc_id.get().to_be_bytes())
+                            .map_err(LmdbError::HeedError)?
+                            .map(str::to_string),
+                        None => None,
+                    },
+                };
+                if let Some(value) = value {
+                    doc.insert(*field, value);
+                }
+            }
+        }
+        Ok(Some(docs))
+    }
+}
+
+impl<F> Drop for LmdbSnapshot<F>
+where
+    F: Hash + Eq + Clone + Ord + Copy + Serialize + DeserializeOwned,
+{
+    fn drop(&mut self) {
+        // Release even if a panicking reader poisoned the lock
+        let mut generations = self.generations.write().unwrap_or_else(|e| e.into_inner());
+        if let Some(released) = generations.unpin(self.generation) {
+            info!(
+                "[LMDB] Retired generation {}, {} old key versions released",
+                self.generation, released
+            );
+        }
+    }
+}
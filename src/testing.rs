@@ -0,0 +1,424 @@
+//! Synthetic Brazilian addresses for tests, benches and the eval harness.
+//!
+//! Records look like the ones the engine is tuned for: a UF code, one of its
+//! municipalities, a CEP inside the state's range, a logradouro type and a
+//! street named the way Brazilian streets are. Queries derived from them
+//! carry the noise real input has: abbreviated types, dropped accents,
+//! typos and missing fields. The same seed always yields the same data.
+
+use crate::eval::EvalQuery;
+use crate::{Record, RecordField};
+use rand::rngs::StdRng;
+use rand::seq::IndexedRandom;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use unicode_normalization::UnicodeNormalization;
+
+/// CEP ranges per UF, as the first and last five-digit prefixes. States
+/// whose range is split appear more than once.
+pub const CEP_RANGES: [(&str, u32, u32); 30] = [
+    ("SP", 1000, 19999),
+    ("RJ", 20000, 28999),
+    ("ES", 29000, 29999),
+    ("MG", 30000, 39999),
+    ("BA", 40000, 48999),
+    ("SE", 49000, 49999),
+    ("PE", 50000, 56999),
+    ("AL", 57000, 57999),
+    ("PB", 58000, 58999),
+    ("RN", 59000, 59999),
+    ("CE", 60000, 63999),
+    ("PI", 64000, 64999),
+    ("MA", 65000, 65999),
+    ("PA", 66000, 68899),
+    ("AP", 68900, 68999),
+    ("AM", 69000, 69299),
+    ("RR", 69300, 69399),
+    ("AM", 69400, 69899),
+    ("AC", 69900, 69999),
+    ("DF", 70000, 72799),
+    ("GO", 72800, 72999),
+    ("DF", 73000, 73699),
+    ("GO", 73700, 76799),
+    ("RO", 76800, 76999),
+    ("TO", 77000, 77999),
+    ("MT", 78000, 78899),
+    ("MS", 79000, 79999),
+    ("PR", 80000, 87999),
+    ("SC", 88000, 89999),
+    ("RS", 90000, 99999),
+];
+
+/// Logradouro types with their usual abbreviation.
+pub const LOGRADOURO_TYPES: [(&str, &str); 12] = [
+    ("Rua", "R"),
+    ("Avenida", "Av"),
+    ("Travessa", "Tv"),
+    ("Passagem", "Psg"),
+    ("Alameda", "Al"),
+    ("Praça", "Pc"),
+    ("Estrada", "Est"),
+    ("Rodovia", "Rod"),
+    ("Vila", "Vl"),
+    ("Quadra", "Qd"),
+    ("Largo", "Lg"),
+    ("Beco", "Bc"),
+];
+
+const MUNICIPIOS: [(&str, &[&str]); 27] = [
+    ("AC", &["Rio Branco", "Cruzeiro do Sul"]),
+    ("AL", &["Maceió", "Arapiraca"]),
+    ("AP", &["Macapá", "Santana"]),
+    ("AM", &["Manaus", "Parintins"]),
+    (
+        "BA",
+        &["Salvador", "Feira de Santana", "Vitória da Conquista"],
+    ),
+    ("CE", &["Fortaleza", "Juazeiro do Norte"]),
+    ("DF", &["Brasília"]),
+    ("ES", &["Vitória", "Vila Velha"]),
+    ("GO", &["Goiânia", "Anápolis"]),
+    ("MA", &["São Luís", "Imperatriz"]),
+    ("MT", &["Cuiabá", "Rondonópolis"]),
+    ("MS", &["Campo Grande", "Dourados"]),
+    ("MG", &["Belo Horizonte", "Uberlândia", "Juiz de Fora"]),
+    ("PA", &["Belém", "Ananindeua", "Santarém"]),
+    ("PB", &["João Pessoa", "Campina Grande"]),
+    ("PR", &["Curitiba", "Londrina"]),
+    ("PE", &["Recife", "Olinda"]),
+    ("PI", &["Teresina", "Parnaíba"]),
+    ("RJ", &["Rio de Janeiro", "Niterói"]),
+    ("RN", &["Natal", "Mossoró"]),
+    ("RS", &["Porto Alegre", "Caxias do Sul"]),
+    ("RO", &["Porto Velho", "Ji-Paraná"]),
+    ("RR", &["Boa Vista"]),
+    ("SC", &["Florianópolis", "Joinville"]),
+    ("SP", &["São Paulo", "Campinas", "Santos"]),
+    ("SE", &["Aracaju"]),
+    ("TO", &["Palmas", "Araguaína"]),
+];
+
+const BAIRROS: [&str; 20] = [
+    "Centro",
+    "Jardim América",
+    "Vila Nova",
+    "Boa Vista",
+    "Santa Luzia",
+    "São José",
+    "Nazaré",
+    "Umarizal",
+    "Pedreira",
+    "Marco",
+    "Liberdade",
+    "Bela Vista",
+    "Cidade Nova",
+    "Jardim das Flores",
+    "Parque Industrial",
+    "Alto da Boa Vista",
+    "Santo Antônio",
+    "Conjunto Esperança",
+    "Vila Operária",
+    "Nossa Senhora das Graças",
+];
+
+const STREET_NAMES: [&str; 30] = [
+    "Tiradentes",
+    "Sete de Setembro",
+    "Quinze de Novembro",
+    "Dom Pedro II",
+    "Marechal Deodoro",
+    "Barão do Rio Branco",
+    "Getúlio Vargas",
+    "Santos Dumont",
+    "José Bonifácio",
+    "Duque de Caxias",
+    "Padre Eutíquio",
+    "Governador José Malcher",
+    "Almirante Barroso",
+    "Nossa Senhora de Nazaré",
+    "Boaventura da Silva",
+    "Senador Lemos",
+    "Conselheiro Furtado",
+    "Antônio Barreto",
+    "Presidente Kennedy",
+    "Rui Barbosa",
+    "Castro Alves",
+    "Machado de Assis",
+    "Frei Caneca",
+    "São Jerônimo",
+    "Mauriti",
+    "Dr. Freitas",
+    "Professora Maria Conceição",
+    "Cônego Jerônimo Pimentel",
+    "Tamandaré",
+    "Visconde de Souza Franco",
+];
+
+const COMPLEMENTOS: [&str; 6] = [
+    "Apto 101", "Casa 2", "Bloco B", "Sala 305", "Fundos", "Loja 1",
+];
+
+/// UF whose CEP range holds `cep` ("66095-000" or "66095000").
+pub fn cep_state(cep: &str) -> Option<&'static str> {
+    let digits: String = cep.chars().filter(|c| c.is_ascii_digit()).collect();
+    if digits.len() != 8 {
+        return None;
+    }
+    let prefix: u32 = digits[..5].parse().ok()?;
+    CEP_RANGES
+        .iter()
+        .find(|(_, first, last)| (*first..=*last).contains(&prefix))
+        .map(|(uf, _, _)| *uf)
+}
+
+/// Abbreviation of a logradouro type, in any case and with or without
+/// accents.
+pub fn abbreviation(tipo: &str) -> Option<&'static str> {
+    let tipo = strip_accents(tipo).to_lowercase();
+    LOGRADOURO_TYPES
+        .iter()
+        .find(|(full, _)| strip_accents(full).to_lowercase() == tipo)
+        .map(|(_, short)| *short)
+}
+
+/// Drops diacritics, keeping the case: "São José" -> "Sao Jose".
+pub fn strip_accents(text: &str) -> String {
+    text.nfd()
+        .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
+        .collect()
+}
+
+/// Kinds of typo [`inject_typo`] makes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Typo {
+    /// Two adjacent letters swapped.
+    Transpose,
+    /// A letter left out.
+    Omit,
+    /// A letter typed twice.
+    Double,
+    /// A letter replaced by another.
+    Substitute,
+}
+
+impl Typo {
+    pub const ALL: [Typo; 4] = [Typo::Transpose, Typo::Omit, Typo::Double, Typo::Substitute];
+}
+
+/// Applies `typo` to one letter of `text`, picked by `rng`. Text with too
+/// few letters for the typo comes back unchanged.
+pub fn inject_typo(text: &str, typo: Typo, rng: &mut impl Rng) -> String {
+    let mut chars: Vec<char> = text.chars().collect();
+    let letters: Vec<usize> = (0..chars.len())
+        .filter(|&i| chars[i].is_alphabetic())
+        .collect();
+    let pairs: Vec<usize> = letters
+        .iter()
+        .copied()
+        .filter(|&i| {
+            chars
+                .get(i + 1)
+                .is_some_and(|c| c.is_alphabetic() && *c != chars[i])
+        })
+        .collect();
+    let candidates = if typo == Typo::Transpose {
+        &pairs
+    } else {
+        &letters
+    };
+    let Some(&i) = candidates.choose(rng) else {
+        return text.to_string();
+    };
+    match typo {
+        Typo::Transpose => chars.swap(i, i + 1),
+        Typo::Omit => {
+            chars.remove(i);
+        }
+        Typo::Double => chars.insert(i, chars[i]),
+        Typo::Substitute => {
+            let current = chars[i].to_ascii_lowercase();
+            let mut replacement = rng.random_range(b'a'..=b'z') as char;
+            if replacement == current {
+                replacement = if current == 'z' {
+                    'a'
+                } else {
+                    (current as u8 + 1) as char
+                };
+            }
+            chars[i] = if chars[i].is_uppercase() {
+                replacement.to_ascii_uppercase()
+            } else {
+                replacement
+            };
+        }
+    }
+    chars.into_iter().collect()
+}
+
+/// Deterministic source of synthetic records and of noisy queries for them.
+#[derive(Debug, Clone)]
+pub struct AddressGenerator {
+    rng: StdRng,
+    next_id: usize,
+    abbreviation_rate: f64,
+    accent_drop_rate: f64,
+    typo_rate: f64,
+    field_drop_rate: f64,
+}
+
+impl AddressGenerator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            next_id: 0,
+            abbreviation_rate: 0.5,
+            accent_drop_rate: 0.5,
+            typo_rate: 0.3,
+            field_drop_rate: 0.2,
+        }
+    }
+
+    /// Chance a query abbreviates the logradouro type.
+    pub fn abbreviation_rate(mut self, rate: f64) -> Self {
+        self.abbreviation_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Chance a query is typed without accents.
+    pub fn accent_drop_rate(mut self, rate: f64) -> Self {
+        self.accent_drop_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Chance of a typo in each of the street, bairro and municipality of a
+    /// query.
+    pub fn typo_rate(mut self, rate: f64) -> Self {
+        self.typo_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Chance a query leaves out each of its bairro, CEP and complemento.
+    pub fn field_drop_rate(mut self, rate: f64) -> Self {
+        self.field_drop_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// A CEP inside the range of `uf`, formatted "NNNNN-NNN".
+    pub fn cep(&mut self, uf: &str) -> Option<String> {
+        let ranges: Vec<_> = CEP_RANGES
+            .iter()
+            .filter(|(code, _, _)| *code == uf)
+            .collect();
+        let &&(_, first, last) = ranges.choose(&mut self.rng)?;
+        let prefix = self.rng.random_range(first..=last);
+        let suffix = self.rng.random_range(0..1000);
+        Some(format!("{:05}-{:03}", prefix, suffix))
+    }
+
+    /// Next record, with ids "syn-000000", "syn-000001", ...
+    pub fn record(&mut self) -> Record {
+        let id = format!("syn-{:06}", self.next_id);
+        self.next_id += 1;
+
+        let &(uf, municipios) = MUNICIPIOS.choose(&mut self.rng).unwrap();
+        let &(tipo, _) = LOGRADOURO_TYPES.choose(&mut self.rng).unwrap();
+        let numero = if self.rng.random_bool(0.05) {
+            "S/N".to_string()
+        } else {
+            self.rng.random_range(1..=3000).to_string()
+        };
+        let complemento = if self.rng.random_bool(0.3) {
+            COMPLEMENTOS.choose(&mut self.rng).unwrap().to_string()
+        } else {
+            String::new()
+        };
+
+        Record {
+            id,
+            estado: uf.to_string(),
+            municipio: municipios.choose(&mut self.rng).unwrap().to_string(),
+            bairro: BAIRROS.choose(&mut self.rng).unwrap().to_string(),
+            cep: self.cep(uf).unwrap(),
+            tipo_logradouro: tipo.to_string(),
+            rua: STREET_NAMES.choose(&mut self.rng).unwrap().to_string(),
+            numero,
+            complemento,
+            ..Default::default()
+        }
+    }
+
+    pub fn records(&mut self, n: usize) -> Vec<Record> {
+        (0..n).map(|_| self.record()).collect()
+    }
+
+    /// The record as someone looking it up might type it. The id is kept.
+    pub fn noisy(&mut self, record: &Record) -> Record {
+        let mut query = record.clone();
+        let abbreviate = self.rng.random_bool(self.abbreviation_rate);
+        if let Some(short) = abbreviation(&query.tipo_logradouro).filter(|_| abbreviate) {
+            query.tipo_logradouro = short.to_string();
+        }
+        for field in [
+            RecordField::Bairro,
+            RecordField::Cep,
+            RecordField::Complemento,
+        ] {
+            if self.rng.random_bool(self.field_drop_rate) {
+                query.field_mut(field).clear();
+            }
+        }
+        for field in [
+            RecordField::Rua,
+            RecordField::Bairro,
+            RecordField::Municipio,
+        ] {
+            if self.rng.random_bool(self.typo_rate) {
+                let typo = *Typo::ALL.choose(&mut self.rng).unwrap();
+                let value = inject_typo(query.field(field), typo, &mut self.rng);
+                *query.field_mut(field) = value;
+            }
+        }
+        if self.rng.random_bool(self.accent_drop_rate) {
+            for field in [
+                RecordField::Rua,
+                RecordField::Bairro,
+                RecordField::Municipio,
+            ] {
+                let value = strip_accents(query.field(field));
+                *query.field_mut(field) = value;
+            }
+        }
+        query
+    }
+
+    /// `n` labeled queries over `corpus`, for [`crate::eval::evaluate`].
+    /// Each is a noisy copy of a corpus record, which is its one match;
+    /// records on a street of the same name elsewhere are its non-matches.
+    pub fn eval_queries(&mut self, corpus: &[Record], n: usize) -> Vec<EvalQuery> {
+        let mut by_street: HashMap<&str, Vec<&Record>> = HashMap::new();
+        for record in corpus {
+            by_street
+                .entry(record.rua.as_str())
+                .or_default()
+                .push(record);
+        }
+        (0..n)
+            .filter_map(|i| {
+                let target = corpus.choose(&mut self.rng)?;
+                let non_relevant: HashSet<String> = by_street[target.rua.as_str()]
+                    .iter()
+                    .filter(|other| other.municipio != target.municipio)
+                    .map(|other| other.id.clone())
+                    .collect();
+                let mut query = self.noisy(target);
+                query.id.clear();
+                Some(EvalQuery {
+                    query_id: format!("q{}", i),
+                    query,
+                    relevant: HashSet::from([target.id.clone()]),
+                    non_relevant,
+                })
+            })
+            .collect()
+    }
+}
@@ -1,22 +1,45 @@
-use log::info;
+use crate::verbosity;
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
+use tracing::span::EnteredSpan;
+use tracing::{Span, field, info, info_span};
 
-/// Simple performance timer for measuring operation durations
+/// Simple performance timer for measuring operation durations. Its span is
+/// entered from creation until drop, so everything logged meanwhile nests
+/// under it; on drop the span records `elapsed_ms` and the `[TIMING]` line
+/// is emitted inside it.
 #[derive(Debug)]
 pub struct Timer {
     start: Instant,
     label: String,
+    span: EnteredSpan,
+    logged: bool,
 }
 
 impl Timer {
+    /// Times `label` in a generic `timer` span.
     pub fn new(label: impl Into<String>) -> Self {
+        let label = label.into();
+        let span = info_span!("timer", label = %label, elapsed_ms = field::Empty);
+        Self::in_span(label, span)
+    }
+
+    /// Times `label` in `span`, one of the named pipeline stages. The span
+    /// should declare an `elapsed_ms` field for the duration to be recorded.
+    pub fn in_span(label: impl Into<String>, span: Span) -> Self {
         Self {
             start: Instant::now(),
             label: label.into(),
+            span: span.entered(),
+            logged: false,
         }
     }
 
+    /// The entered span, for recording fields declared as `field::Empty`.
+    pub fn span(&self) -> &Span {
+        &self.span
+    }
+
     pub fn elapsed(&self) -> Duration {
         self.start.elapsed()
     }
@@ -25,23 +48,47 @@ impl Timer {
         self.elapsed().as_secs_f64() * 1000.0
     }
 
+    /// Records the duration on the span and logs it, unless a search
+    /// running below [`Verbosity::Debug`](crate::verbosity::Verbosity) is timed.
     pub fn log(&self) {
-        info!("[TIMING] {} took {:.2}ms", self.label, self.elapsed_ms());
+        let ms = self.elapsed_ms();
+        self.span.record("elapsed_ms", ms);
+        if !verbosity::current().details() {
+            return;
+        }
+        info!(elapsed_ms = ms, "[TIMING] {} took {:.2}ms", self.label, ms);
     }
 
     pub fn log_with_rate(&self, count: usize) {
         let ms = self.elapsed_ms();
         let rate = count as f64 / (ms / 1000.0);
+        self.span.record("elapsed_ms", ms);
+        if !verbosity::current().details() {
+            return;
+        }
         info!(
+            elapsed_ms = ms,
+            items = count,
             "[TIMING] {} took {:.2}ms ({} items, {:.0} items/sec)",
-            self.label, ms, count, rate
+            self.label,
+            ms,
+            count,
+            rate
         );
     }
+
+    /// Logs with the rate and ends the timer without logging again on drop.
+    pub fn finish_with_rate(mut self, count: usize) {
+        self.log_with_rate(count);
+        self.logged = true;
+    }
 }
 
 impl Drop for Timer {
     fn drop(&mut self) {
-        self.log();
+        if !self.logged {
+            self.log();
+        }
     }
 }
 
@@ -104,8 +151,7 @@ macro_rules! time_with_count {
     ($label:expr, $count:expr, $code:block) => {{
         let timer = $crate::timing::Timer::new($label);
         let result = $code;
-        timer.log_with_rate($count);
-        std::mem::forget(timer); // Prevent Drop from logging again
+        timer.finish_with_rate($count);
         result
     }};
 }
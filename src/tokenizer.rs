@@ -1,6 +1,7 @@
 use lazy_static::lazy_static;
 use regex::{Regex, RegexBuilder};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::ops::Range;
 use stopwords::{Language, NLTK, Stopwords};
 use unicode_normalization::UnicodeNormalization;
 
@@ -267,84 +268,764 @@ pub const ADDRESS_TYPE: &[&str] = &[
     "cj",
 ];
 
+pub const CUSTOM_STOPWORDS_ES: &[&str] = &[
+    "de", "del", "la", "las", "el", "los", "en", "y", "un", "una", "unos", "unas", "por", "para",
+    "con", "sin", "sobre", "entre", "hasta", "desde", "al",
+];
+
+pub const ADDRESS_TYPE_ES: &[&str] = &[
+    "calle",
+    "avenida",
+    "carrera",
+    "pasaje",
+    "camino",
+    "plaza",
+    "paseo",
+    "ruta",
+    "carretera",
+    "callejon",
+    "diagonal",
+    "transversal",
+    "autopista",
+    "alameda",
+    "circunvalar",
+    "manzana",
+    "av",
+    "cl",
+    "cra",
+    "kr",
+    "dg",
+    "tv",
+];
+
+pub const HIGHWAY_PREFIX_ES: &[&str] = &["km", "ruta"];
+
+pub const CUSTOM_STOPWORDS_EN: &[&str] = &["of", "the", "and", "at", "on", "in", "by", "to"];
+
+pub const ADDRESS_TYPE_EN: &[&str] = &[
+    "street",
+    "avenue",
+    "road",
+    "boulevard",
+    "lane",
+    "drive",
+    "court",
+    "place",
+    "way",
+    "highway",
+    "route",
+    "square",
+    "terrace",
+    "parkway",
+    "circle",
+    "st",
+    "ave",
+    "rd",
+    "blvd",
+    "ln",
+    "dr",
+    "ct",
+    "pl",
+    "hwy",
+];
+
+pub const HIGHWAY_PREFIX_EN: &[&str] = &["hwy", "route", "interstate", "us", "i"];
+
+/// Language of the addresses in a field or engine: picks the stopword lists,
+/// address types, highway prefixes and locale-specific normalization fixups.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, serde::Serialize, serde::Deserialize)]
+pub enum Locale {
+    #[default]
+    PtBr,
+    Es,
+    En,
+}
+
+/// Word lists of one [`Locale`].
+#[derive(Debug)]
+pub struct Lexicon {
+    pub stopwords: HashSet<String>,
+    pub address_types: HashSet<&'static str>,
+    pub highway_prefixes: HashSet<&'static str>,
+}
+
+impl Lexicon {
+    fn new(
+        language: Language,
+        custom_stopwords: &[&str],
+        address_types: &[&'static str],
+        highway_prefixes: &[&'static str],
+    ) -> Self {
+        // Use a fallback empty set if NLTK fails to load
+        let mut stopwords: HashSet<String> = NLTK::stopwords(language)
+            .unwrap_or_default()
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        stopwords.extend(custom_stopwords.iter().map(|s| s.to_string()));
+
+        Self {
+            stopwords,
+            address_types: address_types.iter().copied().collect(),
+            highway_prefixes: highway_prefixes.iter().copied().collect(),
+        }
+    }
+}
+
+impl Locale {
+    /// Accepts "pt-BR"/"pt", "es" and "en", case-insensitively.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().replace('_', "-").as_str() {
+            "pt-br" | "pt" => Some(Locale::PtBr),
+            "es" => Some(Locale::Es),
+            "en" => Some(Locale::En),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Locale::PtBr => "pt-BR",
+            Locale::Es => "es",
+            Locale::En => "en",
+        }
+    }
+
+    pub fn lexicon(&self) -> &'static Lexicon {
+        match self {
+            Locale::PtBr => &LEXICON_PT_BR,
+            Locale::Es => &LEXICON_ES,
+            Locale::En => &LEXICON_EN,
+        }
+    }
+
+    pub fn is_stopword(&self, token: &str) -> bool {
+        self.lexicon().stopwords.contains(token)
+    }
+
+    pub fn is_address_type(&self, token: &str) -> bool {
+        self.lexicon().address_types.contains(token)
+    }
+
+    /// Tokens the accent folding would lose: "Pará" (the state) folds to the
+    /// stopword "para", so pt-BR adds it back as a plain token.
+    fn restored_tokens(&self, text: &str) -> Vec<String> {
+        match self {
+            Locale::PtBr if text.to_lowercase().contains("pará") => vec!["para".to_string()],
+            _ => Vec::new(),
+        }
+    }
+}
+
 lazy_static! {
-    static ref RE: Regex = RegexBuilder::new(r"\d{5}-\d{3}|S/N|\d+|[a-z]+").case_insensitive(true).build().unwrap();
+    static ref RE: Regex = RegexBuilder::new(r"\d{5}-\d{3}|S/N|[a-z0-9]+(?:'[a-z0-9]+)*").case_insensitive(true).build().unwrap();
+    static ref RE_ACCENTED: Regex = RegexBuilder::new(r"\d{5}-\d{3}|S/N|(?:\p{Latin}\p{M}*|\d)+(?:['’ʼ](?:\p{Latin}\p{M}*|\d)+)*").case_insensitive(true).build().unwrap();
     static ref RE_CEP: Regex = RegexBuilder::new(r"\d{5}-?\d{3}").case_insensitive(true).build().unwrap();
+    static ref RE_FULL_CEP: Regex = Regex::new(r"^\d{5}-\d{3}$").unwrap();
     static ref RE_NUMBER: Regex = RegexBuilder::new(r"\d+|sn|s/n").case_insensitive(true).build().unwrap();
-    static ref STOP_WORDS_SET: HashSet<&'static str> = CUSTOM_STOPWORDS.iter().copied().collect();
     static ref RE_STREET_NUMBER: Regex = Regex::new(r"^\d+$").unwrap();
     static ref RE_SHORT_NUMBER: Regex = Regex::new(r"\d{1,3}").unwrap();
-    static ref ADDRESS_TYPE_SET: HashSet<&'static str> = ADDRESS_TYPE.iter().copied().collect();
     static ref UFS_SET: HashSet<&'static str> = FEDERATIVE_UNITS.iter().copied().collect();
-    static ref HIGHWAY_PREFIX_SET: HashSet<&'static str> = HIGHWAY_PREFIX.iter().copied().collect();
 
-
-    static ref NLTK_STOPS: HashSet<String> = {
-        let language = Language::Portuguese;
-        // Use a fallback empty set if NLTK fails to load
-        NLTK::stopwords(language).unwrap_or_default().iter().map(|s| s.to_string()).collect()
-    };
+    static ref LEXICON_PT_BR: Lexicon =
+        Lexicon::new(Language::Portuguese, CUSTOM_STOPWORDS, ADDRESS_TYPE, HIGHWAY_PREFIX);
+    static ref LEXICON_ES: Lexicon =
+        Lexicon::new(Language::Spanish, CUSTOM_STOPWORDS_ES, ADDRESS_TYPE_ES, HIGHWAY_PREFIX_ES);
+    static ref LEXICON_EN: Lexicon =
+        Lexicon::new(Language::English, CUSTOM_STOPWORDS_EN, ADDRESS_TYPE_EN, HIGHWAY_PREFIX_EN);
 }
 
 pub fn extract_weak_tokens(tokens: &HashSet<String>, n: usize) -> HashSet<String> {
+    extract_ngrams(tokens, n, n)
+}
+
+/// Byte n-grams of each token, starting every `stride` bytes. A stride equal
+/// to `n` gives non-overlapping chunks, a stride of 1 every substring.
+pub fn extract_ngrams(tokens: &HashSet<String>, n: usize, stride: usize) -> HashSet<String> {
     let mut weak_tokens = HashSet::new();
+    let stride = stride.max(1);
 
     for token in tokens {
         let bytes = token.as_bytes();
-        if bytes.len() >= n {
+        if n > 0 && bytes.len() >= n {
             let mut i = 0;
             while i + n <= bytes.len() {
                 if let Ok(slice) = std::str::from_utf8(&bytes[i..i + n]) {
                     weak_tokens.insert(slice.to_string());
                 }
-                i += n;
+                i += stride;
             }
         }
     }
     weak_tokens
 }
 
+/// How weak n-grams are cut out of full tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum NgramMode {
+    /// Non-overlapping chunks ("mauriti" -> "mau", "rit").
+    #[default]
+    Chunked,
+    /// Overlapping windows every `ngram_stride` bytes ("mau", "aur", "uri", ...).
+    Sliding,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TokenizerConfig {
+    pub ngram_mode: NgramMode,
+    pub ngram_n: usize,
+    /// Only used by [`NgramMode::Sliding`].
+    pub ngram_stride: usize,
+    /// Fields without a locale of their own use this one.
+    #[serde(default)]
+    pub locale: Locale,
+    /// Two-token composites made distinctive on top of the built-in
+    /// address type and highway ones.
+    #[serde(default)]
+    pub composites: Vec<CompositePattern>,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        Self {
+            ngram_mode: NgramMode::Chunked,
+            ngram_n: NGRAM_LEN,
+            ngram_stride: 1,
+            locale: Locale::default(),
+            composites: Vec::new(),
+        }
+    }
+}
+
+impl TokenizerConfig {
+    pub fn sliding(n: usize, stride: usize) -> Self {
+        Self {
+            ngram_mode: NgramMode::Sliding,
+            ngram_n: n,
+            ngram_stride: stride,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = locale;
+        self
+    }
+
+    pub fn with_composite(mut self, pattern: CompositePattern) -> Self {
+        self.composites.push(pattern);
+        self
+    }
+
+    pub fn ngrams(&self, tokens: &HashSet<String>) -> HashSet<String> {
+        match self.ngram_mode {
+            NgramMode::Chunked => extract_ngrams(tokens, self.ngram_n, self.ngram_n),
+            NgramMode::Sliding => extract_ngrams(tokens, self.ngram_n, self.ngram_stride),
+        }
+    }
+
+    fn is_composite(&self, first: &str, second: &str) -> bool {
+        self.composites
+            .iter()
+            .any(|pattern| pattern.matches(first, second))
+    }
+}
+
+/// A user-defined composite like "quadra 5" or "lote 12": a token from
+/// `first` followed by a token matching `second` forms a distinctive token.
+/// Both are matched against normalized tokens.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "CompositeSpec", into = "CompositeSpec")]
+pub struct CompositePattern {
+    first: BTreeSet<String>,
+    second: Regex,
+}
+
+/// Serialized form of a [`CompositePattern`].
+#[derive(serde::Serialize, serde::Deserialize)]
+struct CompositeSpec {
+    first: BTreeSet<String>,
+    second: String,
+}
+
+impl CompositePattern {
+    /// `second` must match the whole token, e.g. `r"\d+"`.
+    pub fn new<I, T>(first: I, second: &str) -> Result<Self, regex::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        Ok(Self {
+            first: first
+                .into_iter()
+                .map(|word| normalize(word.as_ref().trim()))
+                .collect(),
+            second: Regex::new(&format!("^(?:{})$", second))?,
+        })
+    }
+
+    pub fn first(&self) -> &BTreeSet<String> {
+        &self.first
+    }
+
+    /// The regex as given, without the anchors added by [`new`](Self::new).
+    pub fn second(&self) -> &str {
+        let anchored = self.second.as_str();
+        &anchored[4..anchored.len() - 2]
+    }
+
+    pub fn matches(&self, first: &str, second: &str) -> bool {
+        self.first.contains(first) && self.second.is_match(second)
+    }
+}
+
+impl PartialEq for CompositePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.first == other.first && self.second.as_str() == other.second.as_str()
+    }
+}
+
+impl Eq for CompositePattern {}
+
+impl TryFrom<CompositeSpec> for CompositePattern {
+    type Error = regex::Error;
+
+    fn try_from(spec: CompositeSpec) -> Result<Self, regex::Error> {
+        Self::new(spec.first, &spec.second)
+    }
+}
+
+impl From<CompositePattern> for CompositeSpec {
+    fn from(pattern: CompositePattern) -> Self {
+        Self {
+            second: pattern.second().to_string(),
+            first: pattern.first,
+        }
+    }
+}
+
+/// Prefix that puts weak n-grams in their own key namespace, so "rua" the
+/// word and "rua" the 3-gram of "ruas" get separate postings and df.
+pub const NGRAM_PREFIX: &str = "#";
+
+/// Byte length of the weak n-grams.
+pub const NGRAM_LEN: usize = 3;
+
+pub fn ngram_key(gram: &str) -> String {
+    format!("{}{}", NGRAM_PREFIX, gram)
+}
+
+pub fn is_ngram_key(token: &str) -> bool {
+    token.starts_with(NGRAM_PREFIX)
+}
+
+/// Whether `token` is a whole CEP as the tokenizer emits it (`00000-000`).
+pub fn is_cep(token: &str) -> bool {
+    RE_FULL_CEP.is_match(token)
+}
+
+/// Default scoring weight of weak n-gram tokens relative to full tokens.
+pub const DEFAULT_NGRAM_WEIGHT: f32 = 0.3;
+
+/// Default scoring weight of tokens demoted by a [`FieldTokenRules`] policy.
+pub const DEFAULT_DEMOTED_WEIGHT: f32 = 0.3;
+
 pub struct TokenSet {
     pub distinctive: HashSet<String>, // For candidate filtering
     pub all: HashSet<String>,         // For scoring
+    pub weak: HashSet<String>,        // Subset of `all` in the n-gram namespace
+    pub demoted: HashSet<String>,     // Subset of `all` scored at `demoted_weight`
+    pub demoted_weight: f32,
+    pub tfs: HashMap<String, u32>,    // Occurrences in the text of each token of `all`
 }
 
-pub fn tokenize_structured(text: &str) -> TokenSet {
-    let normalized: String = text
+impl TokenSet {
+    /// Scoring weight of each token: 1.0 for full tokens, `ngram_weight` for
+    /// weak ones and `demoted_weight` for demoted ones.
+    pub fn weighted(&self, ngram_weight: f32) -> Vec<(String, f32)> {
+        self.all
+            .iter()
+            .map(|token| {
+                let weight = if self.weak.contains(token) {
+                    ngram_weight
+                } else if self.demoted.contains(token) {
+                    self.demoted_weight
+                } else {
+                    1.0
+                };
+                (token.clone(), weight)
+            })
+            .collect()
+    }
+}
+
+/// Lowercases and strips diacritics, the same folding applied before tokenizing.
+/// Letters without a decomposition are spelled in ASCII too, see [`ascii_fold`].
+pub fn normalize(text: &str) -> String {
+    let mut folded = String::with_capacity(text.len());
+    for c in text
         .nfd()
         .filter(|c| !unicode_normalization::char::is_combining_mark(*c))
-        .collect::<String>()
-        .to_lowercase();
+    {
+        match ascii_fold(c) {
+            Some(ascii) => folded.push_str(ascii),
+            None => folded.push(c),
+        }
+    }
+    folded.to_lowercase()
+}
 
-    let mut tokens_list: Vec<String> = RE
-        .find_iter(&normalized)
-        .map(|m| m.as_str().to_string())
-        .filter(|token| !STOP_WORDS_SET.contains(token.as_str()) && !NLTK_STOPS.contains(token))
-        .collect();
+/// ASCII spelling of characters that stripping marks leaves alone, whatever
+/// the language: "ß", "æ", "ø", "ł" and the like, ordinal indicators ("1ª",
+/// "3º", and the degree sign often typed for the latter) and typographic
+/// apostrophes, which become "'".
+fn ascii_fold(c: char) -> Option<&'static str> {
+    Some(match c {
+        'ß' => "ss",
+        'æ' | 'Æ' => "ae",
+        'œ' | 'Œ' => "oe",
+        'ø' | 'Ø' => "o",
+        'ł' | 'Ł' => "l",
+        'đ' | 'Đ' | 'ð' | 'Ð' => "d",
+        'þ' | 'Þ' => "th",
+        'ı' => "i",
+        'ª' => "a",
+        'º' | '°' => "o",
+        '\u{2018}' | '\u{2019}' | '\u{02bc}' | '`' | '´' => "'",
+        _ => return None,
+    })
+}
+
+/// A word found by [`segment`].
+struct Segment {
+    text: String,
+    /// Byte range in the segmented text.
+    range: Range<usize>,
+    /// A compound spelled whole next to its parts. It is indexed, but kept
+    /// out of the composites so that "rua 12b" still makes "rua 12".
+    joined: bool,
+}
+
+/// Splits `text`, normalized or, with `accented`, only lowercased, into
+/// words. CEPs and "s/n" are one word each. Letters and digits form a word
+/// with any apostrophes inside it; a word mixing letters with digits or
+/// holding an apostrophe ("66b", "km8", "d'ajuda", "1ª" once folded) yields
+/// its letter and digit runs followed by the whole word without
+/// apostrophes ("66", "b", "66b"). Accented words are composed to NFC.
+fn segment(text: &str, accented: bool) -> Vec<Segment> {
+    let words: &Regex = if accented { &RE_ACCENTED } else { &RE };
+    let compose = |word: &str| -> String {
+        if accented {
+            word.nfc().collect()
+        } else {
+            word.to_string()
+        }
+    };
+
+    let mut segments = Vec::new();
+    for m in words.find_iter(text) {
+        let word = m.as_str();
+        if word.contains(['-', '/']) {
+            segments.push(Segment {
+                text: word.to_string(),
+                range: m.range(),
+                joined: false,
+            });
+            continue;
+        }
+
+        // Runs of digits, of letters (with their marks), split at apostrophes
+        let mut runs: Vec<Range<usize>> = Vec::new();
+        let mut run_digits = false;
+        for (i, c) in word.char_indices() {
+            if is_apostrophe(c) {
+                continue;
+            }
+            let digit = c.is_ascii_digit();
+            let end = i + c.len_utf8();
+            match runs.last_mut() {
+                Some(run)
+                    if run.end == i
+                        && (digit == run_digits
+                            || unicode_normalization::char::is_combining_mark(c)) =>
+                {
+                    run.end = end;
+                }
+                _ => {
+                    runs.push(i..end);
+                    run_digits = digit;
+                }
+            }
+        }
+
+        if runs.len() > 1 {
+            for run in &runs {
+                segments.push(Segment {
+                    text: compose(&word[run.clone()]),
+                    range: m.start() + run.start..m.start() + run.end,
+                    joined: false,
+                });
+            }
+        }
+        let whole: String = word.chars().filter(|c| !is_apostrophe(*c)).collect();
+        segments.push(Segment {
+            text: compose(&whole),
+            range: m.range(),
+            joined: runs.len() > 1,
+        });
+    }
+    segments
+}
+
+fn is_apostrophe(c: char) -> bool {
+    matches!(c, '\'' | '\u{2019}' | '\u{02bc}')
+}
+
+/// Lowercased tokens of `text` with their accents, composed to NFC ("pará").
+fn accented_tokens(text: &str) -> Vec<String> {
+    segment(&text.to_lowercase(), true)
+        .into_iter()
+        .map(|segment| segment.text)
+        .collect()
+}
+
+/// Stopword check for the default (pt-BR) locale.
+pub fn is_stopword(token: &str) -> bool {
+    Locale::default().is_stopword(token)
+}
+
+/// What the tokenizer does with a class of words (stopwords, address types).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TermPolicy {
+    /// Remove from the field's tokens.
+    Drop,
+    /// Index and score like any other token.
+    Keep,
+    /// Index, but score with [`FieldTokenRules::demoted_weight`].
+    Demote,
+}
+
+/// How a field's tokens treat diacritics.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+pub enum Diacritics {
+    /// Strip them: "Pará" and "para" are the same token.
+    #[default]
+    Fold,
+    /// Keep them: "pará" and "para" are different tokens.
+    Keep,
+    /// Folded tokens plus, for accented words, the accented spelling as an
+    /// extra scored token. "para" finds "Pará" and "pará" ranks it higher.
+    Both,
+}
+
+impl Diacritics {
+    /// Accepts "fold", "keep" and "both", case-insensitively.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "fold" => Some(Diacritics::Fold),
+            "keep" => Some(Diacritics::Keep),
+            "both" => Some(Diacritics::Both),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Diacritics::Fold => "fold",
+            Diacritics::Keep => "keep",
+            Diacritics::Both => "both",
+        }
+    }
+}
+
+/// Which single tokens a field indexes. Tokens are filtered after the
+/// composites are formed, so a dropped "r" still makes "r 12".
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TokenFilter {
+    /// Shorter tokens, in characters, are dropped.
+    pub min_len: usize,
+    /// Keep all-digit tokens regardless of `min_len` ("7"); off drops them.
+    pub numbers: bool,
+    /// Normalized tokens dropped whatever their length.
+    pub blacklist: BTreeSet<String>,
+}
+
+impl Default for TokenFilter {
+    /// Keeps every token.
+    fn default() -> Self {
+        Self {
+            min_len: 0,
+            numbers: true,
+            blacklist: BTreeSet::new(),
+        }
+    }
+}
+
+impl TokenFilter {
+    pub fn new(min_len: usize) -> Self {
+        Self {
+            min_len,
+            ..Self::default()
+        }
+    }
+
+    /// Drops single letters and the two-letter street type abbreviations
+    /// ("av", "tv", "qd"), which match far more than they distinguish.
+    pub fn address() -> Self {
+        Self::new(2).with_blacklist(ADDRESS_TYPE.iter().filter(|t| t.len() <= 2))
+    }
+
+    pub fn with_numbers(mut self, numbers: bool) -> Self {
+        self.numbers = numbers;
+        self
+    }
+
+    /// Adds `tokens` to the blacklist, normalized like indexed text.
+    pub fn with_blacklist<I, T>(mut self, tokens: I) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        self.blacklist
+            .extend(tokens.into_iter().map(|token| normalize(token.as_ref())));
+        self
+    }
+
+    pub fn keeps(&self, token: &str) -> bool {
+        if token.bytes().all(|b| b.is_ascii_digit()) {
+            return self.numbers;
+        }
+        token.chars().count() >= self.min_len && !self.blacklist.contains(token)
+    }
+}
+
+/// Per-field filtering rules. The default drops stopwords and keeps address
+/// types, which suits most fields; place names ("Alto Alegre", "Campo Limpo")
+/// want both kept.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FieldTokenRules {
+    pub stopwords: TermPolicy,
+    pub address_types: TermPolicy,
+    pub demoted_weight: f32,
+    /// Overrides [`TokenizerConfig::locale`] for this field.
+    pub locale: Option<Locale>,
+    #[serde(default)]
+    pub diacritics: Diacritics,
+    #[serde(default)]
+    pub filter: TokenFilter,
+}
+
+impl Default for FieldTokenRules {
+    fn default() -> Self {
+        Self {
+            stopwords: TermPolicy::Drop,
+            address_types: TermPolicy::Keep,
+            demoted_weight: DEFAULT_DEMOTED_WEIGHT,
+            locale: None,
+            diacritics: Diacritics::Fold,
+            filter: TokenFilter::default(),
+        }
+    }
+}
+
+impl FieldTokenRules {
+    pub fn new(stopwords: TermPolicy, address_types: TermPolicy) -> Self {
+        Self {
+            stopwords,
+            address_types,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_locale(mut self, locale: Locale) -> Self {
+        self.locale = Some(locale);
+        self
+    }
+
+    pub fn with_diacritics(mut self, diacritics: Diacritics) -> Self {
+        self.diacritics = diacritics;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: TokenFilter) -> Self {
+        self.filter = filter;
+        self
+    }
 
-    if text.to_lowercase().contains("pará") {
-        tokens_list.push("para".to_string());
+    fn policy(&self, token: &str, locale: Locale) -> TermPolicy {
+        if locale.is_stopword(token) {
+            self.stopwords
+        } else if locale.is_address_type(token) {
+            self.address_types
+        } else {
+            TermPolicy::Keep
+        }
+    }
+}
+
+pub fn tokenize_structured(text: &str) -> TokenSet {
+    tokenize_structured_with(text, &TokenizerConfig::default())
+}
+
+pub fn tokenize_structured_with(text: &str, config: &TokenizerConfig) -> TokenSet {
+    tokenize_field(text, config, &FieldTokenRules::default())
+}
+
+pub fn tokenize_field(text: &str, config: &TokenizerConfig, rules: &FieldTokenRules) -> TokenSet {
+    let locale = rules.locale.unwrap_or(config.locale);
+    let lexicon = locale.lexicon();
+    let words: Vec<Segment> = match rules.diacritics {
+        Diacritics::Keep => segment(&text.to_lowercase(), true),
+        Diacritics::Fold | Diacritics::Both => segment(&normalize(text), false),
+    }
+    .into_iter()
+    .filter(|word| rules.stopwords != TermPolicy::Drop || !lexicon.stopwords.contains(&word.text))
+    .collect();
+    // Positions of whole compounds, which don't form composites
+    let joined: HashSet<usize> = (0..words.len()).filter(|&i| words[i].joined).collect();
+    let mut tokens_list: Vec<String> = words.into_iter().map(|word| word.text).collect();
+
+    // Kept accents lose nothing to restore
+    let restored_from = tokens_list.len();
+    if rules.diacritics != Diacritics::Keep {
+        tokens_list.extend(locale.restored_tokens(text));
     }
 
     let mut distinctive_tokens = HashSet::new();
     let mut all_tokens = HashSet::new();
+    let mut demoted_tokens = HashSet::new();
+    let mut tfs: HashMap<String, u32> = HashMap::new();
 
     // Process Strong/Distinctive Tokens (N-grams, phrases)
-    for window in tokens_list.windows(2) {
+    let composable: Vec<&String> = tokens_list
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !joined.contains(i))
+        .map(|(_, token)| token)
+        .collect();
+    for window in composable.windows(2) {
         let first = &window[0];
         let second = &window[1];
 
-        if ADDRESS_TYPE_SET.contains(first.as_str()) && RE_STREET_NUMBER.is_match(second) {
-            distinctive_tokens.insert(format!("{} {}", first, second));
-        }
-
-        if HIGHWAY_PREFIX_SET.contains(first.as_str()) && RE_SHORT_NUMBER.is_match(second) {
-            distinctive_tokens.insert(format!("{} {}", first, second));
+        let composite = (lexicon.address_types.contains(first.as_str())
+            && RE_STREET_NUMBER.is_match(second))
+            || (lexicon.highway_prefixes.contains(first.as_str())
+                && RE_SHORT_NUMBER.is_match(second))
+            || config.is_composite(first, second);
+        if composite {
+            let token = format!("{} {}", first, second);
+            *tfs.entry(token.clone()).or_insert(0) += 1;
+            distinctive_tokens.insert(token);
         }
     }
 
     // Identity & Specialized Tokens (distinctive)
-    for t in &tokens_list {
+    for (i, t) in tokens_list.iter().enumerate() {
+        // Like dropped address types, filtered tokens already made their composites
+        if !rules.filter.keeps(t) {
+            continue;
+        }
+        *tfs.entry(t.clone()).or_insert(0) += 1;
         if RE_CEP.is_match(t) || UFS_SET.contains(t.as_str()) {
             distinctive_tokens.insert(t.clone());
         }
@@ -352,21 +1033,475 @@ pub fn tokenize_structured(text: &str) -> TokenSet {
             // House numbers are distinctive
             distinctive_tokens.insert(t.clone());
         }
+
+        // Address types still formed the composites above even when dropped here;
+        // restored tokens ("para") are kept even when they spell a stopword
+        let policy = if i >= restored_from {
+            TermPolicy::Keep
+        } else {
+            rules.policy(t, locale)
+        };
+        match policy {
+            TermPolicy::Drop => continue,
+            TermPolicy::Demote => {
+                demoted_tokens.insert(t.clone());
+            }
+            TermPolicy::Keep => {}
+        }
         all_tokens.insert(t.clone());
     }
 
     // Weak Tokens (for scoring only, not filtering)
-    let weak_tokens = extract_weak_tokens(&all_tokens, 3);
-    all_tokens.extend(weak_tokens);
+    let weak_tokens: HashSet<String> = config
+        .ngrams(&all_tokens)
+        .iter()
+        .map(|gram| ngram_key(gram))
+        .collect();
+    all_tokens.extend(weak_tokens.iter().cloned());
+
+    // Accented spellings next to the folded ones, scored but not n-grammed
+    if rules.diacritics == Diacritics::Both {
+        for token in accented_tokens(text) {
+            let folded = normalize(&token);
+            if folded == token || !all_tokens.contains(&folded) {
+                continue;
+            }
+            if demoted_tokens.contains(&folded) {
+                demoted_tokens.insert(token.clone());
+            }
+            *tfs.entry(token.clone()).or_insert(0) += 1;
+            all_tokens.insert(token);
+        }
+    }
 
     // Copy distinctive tokens to all_tokens
     all_tokens.extend(distinctive_tokens.clone());
+    demoted_tokens.retain(|t| !distinctive_tokens.contains(t));
+
+    // Dropped tokens were counted too; n-grams occur once
+    tfs.retain(|token, _| all_tokens.contains(token));
+    for token in &all_tokens {
+        tfs.entry(token.clone()).or_insert(1);
+    }
 
     TokenSet {
         distinctive: distinctive_tokens,
         all: all_tokens,
+        weak: weak_tokens,
+        demoted: demoted_tokens,
+        demoted_weight: rules.demoted_weight,
+        tfs,
+    }
+}
+
+/// How [`tokenize_debug`] classifies a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    /// Generates candidates in round 1 and is scored.
+    Distinctive,
+    /// A full token, scored only.
+    Scored,
+    /// Scored at the field's [`FieldTokenRules::demoted_weight`].
+    Demoted,
+    /// An n-gram, scored at the n-gram weight.
+    Weak,
+    /// Removed by the field's rules; neither indexed nor scored.
+    Dropped,
+}
+
+/// The tokenizer step that produced or classified a token.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenRule {
+    Word,
+    Cep,
+    FederativeUnit,
+    Number,
+    Stopword,
+    AddressType,
+    /// Address type followed by a street number ("rua 12").
+    AddressTypeNumber,
+    /// Highway prefix followed by a short number ("br 316").
+    HighwayNumber,
+    /// Matched by one of [`TokenizerConfig::composites`] ("quadra 5").
+    Composite,
+    /// Added back by the locale after accent folding ("Pará").
+    Restored,
+    /// Accented spelling kept next to the folded token ([`Diacritics::Both`]).
+    Accented,
+    Ngram,
+    /// Removed by the field's [`TokenFilter`].
+    Filtered,
+}
+
+impl TokenClass {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenClass::Distinctive => "distinctive",
+            TokenClass::Scored => "scored",
+            TokenClass::Demoted => "demoted",
+            TokenClass::Weak => "weak",
+            TokenClass::Dropped => "dropped",
+        }
     }
 }
+
+impl TokenRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenRule::Word => "word",
+            TokenRule::Cep => "cep",
+            TokenRule::FederativeUnit => "federative_unit",
+            TokenRule::Number => "number",
+            TokenRule::Stopword => "stopword",
+            TokenRule::AddressType => "address_type",
+            TokenRule::AddressTypeNumber => "address_type_number",
+            TokenRule::HighwayNumber => "highway_number",
+            TokenRule::Composite => "composite",
+            TokenRule::Restored => "restored",
+            TokenRule::Accented => "accented",
+            TokenRule::Ngram => "ngram",
+            TokenRule::Filtered => "filtered",
+        }
+    }
+}
+
+/// One token seen by [`tokenize_debug`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenTrace {
+    /// As keyed in the index; n-grams carry [`NGRAM_PREFIX`].
+    pub token: String,
+    /// Byte range in the original text. None for restored tokens, which
+    /// don't come from one place in it.
+    pub span: Option<Range<usize>>,
+    pub class: TokenClass,
+    pub rule: TokenRule,
+}
+
+/// [`normalize`] (or only lowercasing, without `fold`) plus, for each byte
+/// of the result, the byte offset in `text` of the character it came from.
+fn normalize_with_offsets(text: &str, fold: bool) -> (String, Vec<usize>) {
+    let mut normalized = String::with_capacity(text.len());
+    let mut offsets = Vec::with_capacity(text.len());
+    for (start, c) in text.char_indices() {
+        let folded = if fold {
+            normalize(c.encode_utf8(&mut [0; 4]))
+        } else {
+            c.to_lowercase().collect()
+        };
+        offsets.extend(std::iter::repeat_n(start, folded.len()));
+        normalized.push_str(&folded);
+    }
+    (normalized, offsets)
+}
+
+/// Byte range in `text` of the characters behind `range` of a string built
+/// by [`normalize_with_offsets`].
+fn source_range(text: &str, offsets: &[usize], range: Range<usize>) -> Range<usize> {
+    let last = offsets[range.end - 1];
+    let last_len = text[last..].chars().next().map_or(0, char::len_utf8);
+    offsets[range.start]..last + last_len
+}
+
+/// Tokenizes `text` like [`tokenize_field`], reporting every token it emits
+/// and every token the rules drop, in text order, with where it came from
+/// and why. The emitted tokens equal [`TokenSet::all`]; slower, for
+/// explaining a query, not for indexing.
+pub fn tokenize_debug(
+    text: &str,
+    config: &TokenizerConfig,
+    rules: &FieldTokenRules,
+) -> Vec<TokenTrace> {
+    let locale = rules.locale.unwrap_or(config.locale);
+    let lexicon = locale.lexicon();
+    let keep_accents = rules.diacritics == Diacritics::Keep;
+    let (normalized, offsets) = normalize_with_offsets(text, !keep_accents);
+    // Normalized byte range -> byte range of the source characters
+    let source_span = |range: Range<usize>| source_range(text, &offsets, range);
+
+    let mut traces = Vec::new();
+    let mut tokens: Vec<(String, Option<Range<usize>>)> = Vec::new();
+    // Positions of whole compounds, which don't form composites
+    let mut joined = HashSet::new();
+    for word in segment(&normalized, keep_accents) {
+        if rules.stopwords == TermPolicy::Drop && lexicon.stopwords.contains(&word.text) {
+            traces.push(TokenTrace {
+                token: word.text,
+                span: Some(source_span(word.range)),
+                class: TokenClass::Dropped,
+                rule: TokenRule::Stopword,
+            });
+        } else {
+            if word.joined {
+                joined.insert(tokens.len());
+            }
+            tokens.push((word.text, Some(word.range)));
+        }
+    }
+    if !keep_accents {
+        tokens.extend(
+            locale
+                .restored_tokens(text)
+                .into_iter()
+                .map(|token| (token, None)),
+        );
+    }
+
+    let composable: Vec<&(String, Option<Range<usize>>)> = tokens
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !joined.contains(i))
+        .map(|(_, token)| token)
+        .collect();
+    for window in composable.windows(2) {
+        let ((first, first_range), (second, second_range)) = (window[0], window[1]);
+        let rule = if lexicon.address_types.contains(first.as_str())
+            && RE_STREET_NUMBER.is_match(second)
+        {
+            TokenRule::AddressTypeNumber
+        } else if lexicon.highway_prefixes.contains(first.as_str())
+            && RE_SHORT_NUMBER.is_match(second)
+        {
+            TokenRule::HighwayNumber
+        } else if config.is_composite(first, second) {
+            TokenRule::Composite
+        } else {
+            continue;
+        };
+        let span = match (first_range, second_range) {
+            (Some(first), Some(second)) => Some(source_span(first.start..second.end)),
+            _ => None,
+        };
+        traces.push(TokenTrace {
+            token: format!("{} {}", first, second),
+            span,
+            class: TokenClass::Distinctive,
+            rule,
+        });
+    }
+
+    let ngram_stride = match config.ngram_mode {
+        NgramMode::Chunked => config.ngram_n,
+        NgramMode::Sliding => config.ngram_stride,
+    }
+    .max(1);
+    for (token, range) in &tokens {
+        if !rules.filter.keeps(token) {
+            traces.push(TokenTrace {
+                token: token.clone(),
+                span: range.clone().map(&source_span),
+                class: TokenClass::Dropped,
+                rule: TokenRule::Filtered,
+            });
+            continue;
+        }
+        let distinctive = if RE_CEP.is_match(token) {
+            Some(TokenRule::Cep)
+        } else if UFS_SET.contains(token.as_str()) {
+            Some(TokenRule::FederativeUnit)
+        } else if RE_NUMBER.is_match(token) {
+            Some(TokenRule::Number)
+        } else {
+            None
+        };
+        let rule = if range.is_none() {
+            TokenRule::Restored
+        } else if locale.is_stopword(token) {
+            TokenRule::Stopword
+        } else if locale.is_address_type(token) {
+            TokenRule::AddressType
+        } else {
+            TokenRule::Word
+        };
+        let policy = if range.is_none() {
+            TermPolicy::Keep
+        } else {
+            rules.policy(token, locale)
+        };
+        let (class, rule) = match (distinctive, policy) {
+            (Some(distinctive), _) => (TokenClass::Distinctive, distinctive),
+            (None, TermPolicy::Drop) => (TokenClass::Dropped, rule),
+            (None, TermPolicy::Demote) => (TokenClass::Demoted, rule),
+            (None, TermPolicy::Keep) => (TokenClass::Scored, rule),
+        };
+        traces.push(TokenTrace {
+            token: token.clone(),
+            span: range.clone().map(&source_span),
+            class,
+            rule,
+        });
+
+        // Tokens dropped by policy are not n-grammed, even distinctive ones
+        if policy == TermPolicy::Drop || config.ngram_n == 0 {
+            continue;
+        }
+        let mut i = 0;
+        while i + config.ngram_n <= token.len() {
+            if let Some(gram) = token.get(i..i + config.ngram_n) {
+                traces.push(TokenTrace {
+                    token: ngram_key(gram),
+                    span: range.as_ref().map(|range| {
+                        source_span(range.start + i..range.start + i + config.ngram_n)
+                    }),
+                    class: TokenClass::Weak,
+                    rule: TokenRule::Ngram,
+                });
+            }
+            i += ngram_stride;
+        }
+    }
+
+    if rules.diacritics == Diacritics::Both {
+        let (lowered, lowered_offsets) = normalize_with_offsets(text, false);
+        for word in segment(&lowered, true) {
+            let token = word.text;
+            let folded = normalize(&token);
+            if folded == token {
+                continue;
+            }
+            // A dropped stopword can share its spelling with a restored token
+            let emitted = traces
+                .iter()
+                .find(|trace| trace.token == folded && trace.class != TokenClass::Dropped);
+            let class = match emitted {
+                Some(trace) if trace.class == TokenClass::Demoted => TokenClass::Demoted,
+                Some(_) => TokenClass::Scored,
+                None => continue,
+            };
+            traces.push(TokenTrace {
+                token,
+                span: Some(source_range(text, &lowered_offsets, word.range)),
+                class,
+                rule: TokenRule::Accented,
+            });
+        }
+    }
+
+    traces.sort_by_key(|trace| trace.span.as_ref().map_or(usize::MAX, |span| span.start));
+    traces
+}
+
+/// Query-time synonyms: a query token also searches each of its synonyms,
+/// as a full token of the same field. Indexed text doesn't go through them,
+/// so they change without reindexing. One-way; list both tokens of a pair
+/// to make it two-way.
+#[derive(Debug, Clone, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct Synonyms(BTreeMap<String, BTreeSet<String>>);
+
+impl Synonyms {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds `synonyms` to those of `token`, all normalized like query text.
+    pub fn insert<I, T>(&mut self, token: &str, synonyms: I)
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<str>,
+    {
+        let synonyms = synonyms.into_iter().map(|synonym| normalize(synonym.as_ref().trim()));
+        self.0.entry(normalize(token.trim())).or_default().extend(synonyms);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &BTreeSet<String>)> {
+        self.0.iter()
+    }
+
+    /// Adds the synonyms of the full tokens of `tokens`, each distinctive
+    /// and demoted like the token it stands for.
+    pub fn expand(&self, tokens: &mut TokenSet) {
+        if self.0.is_empty() {
+            return;
+        }
+        let mut added = Vec::new();
+        for token in tokens.all.difference(&tokens.weak) {
+            for synonym in self.0.get(token).into_iter().flatten() {
+                added.push((token.clone(), synonym.clone()));
+            }
+        }
+        for (token, synonym) in added {
+            if tokens.distinctive.contains(&token) {
+                tokens.distinctive.insert(synonym.clone());
+            }
+            if tokens.demoted.contains(&token) {
+                tokens.demoted.insert(synonym.clone());
+            }
+            tokens.all.insert(synonym);
+        }
+    }
+}
+
+/// Tokenizer config plus per-field rules: everything needed to tokenize a
+/// field the same way at index and query time. Cheap to clone off the engine
+/// for tokenizing outside its lock.
+#[derive(Debug, Clone)]
+pub struct FieldAnalyzer<F>
+where
+    F: std::hash::Hash + Eq,
+{
+    pub config: TokenizerConfig,
+    pub rules: std::collections::HashMap<F, FieldTokenRules>,
+    /// Only applied by [`FieldAnalyzer::analyze_query`]
+    pub synonyms: Synonyms,
+}
+
+impl<F> Default for FieldAnalyzer<F>
+where
+    F: std::hash::Hash + Eq,
+{
+    fn default() -> Self {
+        Self {
+            config: TokenizerConfig::default(),
+            rules: std::collections::HashMap::new(),
+            synonyms: Synonyms::default(),
+        }
+    }
+}
+
+impl<F> FieldAnalyzer<F>
+where
+    F: std::hash::Hash + Eq,
+{
+    pub fn rules_for(&self, field: &F) -> FieldTokenRules {
+        self.rules.get(field).cloned().unwrap_or_default()
+    }
+
+    pub fn analyze(&self, field: &F, text: &str) -> TokenSet {
+        tokenize_field(text, &self.config, &self.rules_for(field))
+    }
+
+    /// Tokens of `text` as a query searches them, synonyms included.
+    pub fn analyze_query(&self, field: &F, text: &str) -> TokenSet {
+        let mut tokens = self.analyze(field, text);
+        self.synonyms.expand(&mut tokens);
+        tokens
+    }
+
+    pub fn tokens(&self, field: &F, text: &str) -> HashSet<String> {
+        self.analyze(field, text).all
+    }
+
+    /// The tokens of `text` with their term frequencies, as indexed.
+    pub fn term_frequencies(&self, field: &F, text: &str) -> HashMap<String, u32> {
+        self.analyze(field, text).tfs
+    }
+
+    pub fn debug(&self, field: &F, text: &str) -> Vec<TokenTrace> {
+        tokenize_debug(text, &self.config, &self.rules_for(field))
+    }
+}
+
 pub fn tokenize(text: &str) -> HashSet<String> {
     tokenize_structured(text).all
 }
+
+pub fn tokenize_with(text: &str, config: &TokenizerConfig) -> HashSet<String> {
+    tokenize_structured_with(text, config).all
+}
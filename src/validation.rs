@@ -0,0 +1,285 @@
+//! Checks and cleans records before they are indexed: CEP format, UF codes,
+//! mandatory fields and stray whitespace. A [`ValidationPolicy`] decides
+//! whether problem records are dropped, repaired or indexed as they came.
+
+use crate::tokenizer::normalize;
+use crate::{DocId, Record, RecordField};
+use serde::Serialize;
+use tracing::warn;
+
+/// The 27 federative units as (code, normalized name).
+pub const UFS: [(&str, &str); 27] = [
+    ("AC", "acre"),
+    ("AL", "alagoas"),
+    ("AP", "amapa"),
+    ("AM", "amazonas"),
+    ("BA", "bahia"),
+    ("CE", "ceara"),
+    ("DF", "distrito federal"),
+    ("ES", "espirito santo"),
+    ("GO", "goias"),
+    ("MA", "maranhao"),
+    ("MT", "mato grosso"),
+    ("MS", "mato grosso do sul"),
+    ("MG", "minas gerais"),
+    ("PA", "para"),
+    ("PB", "paraiba"),
+    ("PR", "parana"),
+    ("PE", "pernambuco"),
+    ("PI", "piaui"),
+    ("RJ", "rio de janeiro"),
+    ("RN", "rio grande do norte"),
+    ("RS", "rio grande do sul"),
+    ("RO", "rondonia"),
+    ("RR", "roraima"),
+    ("SC", "santa catarina"),
+    ("SP", "sao paulo"),
+    ("SE", "sergipe"),
+    ("TO", "tocantins"),
+];
+
+/// What happens to a record with issues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValidationPolicy {
+    /// Records with any issue are not indexed.
+    Reject,
+    /// Fixable issues are repaired; the record is indexed either way.
+    #[default]
+    FixAndIndex,
+    /// Records are indexed unchanged; issues are only reported.
+    IndexRaw,
+}
+
+impl ValidationPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "reject" => Some(ValidationPolicy::Reject),
+            "fix" | "fix_and_index" => Some(ValidationPolicy::FixAndIndex),
+            "raw" | "index_raw" => Some(ValidationPolicy::IndexRaw),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum IssueKind {
+    /// A mandatory field is empty.
+    Missing,
+    /// Leading, trailing or repeated whitespace.
+    Whitespace,
+    /// Eight digits, but not written as `00000-000`.
+    CepFormat,
+    /// Not eight digits.
+    InvalidCep,
+    /// A known state written as lowercase code or full name.
+    UfFormat,
+    /// Not a known state.
+    InvalidUf,
+}
+
+impl IssueKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IssueKind::Missing => "missing",
+            IssueKind::Whitespace => "whitespace",
+            IssueKind::CepFormat => "cep_format",
+            IssueKind::InvalidCep => "invalid_cep",
+            IssueKind::UfFormat => "uf_format",
+            IssueKind::InvalidUf => "invalid_uf",
+        }
+    }
+
+    /// Whether [`ValidationPolicy::FixAndIndex`] can repair it.
+    pub fn is_fixable(&self) -> bool {
+        matches!(
+            self,
+            IssueKind::Whitespace | IssueKind::CepFormat | IssueKind::UfFormat
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationIssue {
+    pub doc_id: DocId,
+    pub field: RecordField,
+    pub kind: IssueKind,
+    /// The value as it was received.
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub checked: usize,
+    /// Records left out of the batch.
+    pub rejected: usize,
+    /// Records changed before indexing.
+    pub fixed: usize,
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Adds the counts and issues of a later batch.
+    pub fn merge(&mut self, other: ValidationReport) {
+        self.checked += other.checked;
+        self.rejected += other.rejected;
+        self.fixed += other.fixed;
+        self.issues.extend(other.issues);
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RecordValidator {
+    pub policy: ValidationPolicy,
+    /// Fields that must not be empty.
+    pub required: Vec<RecordField>,
+}
+
+impl Default for RecordValidator {
+    fn default() -> Self {
+        Self::new(ValidationPolicy::default())
+    }
+}
+
+/// Trims and collapses runs of whitespace into one space.
+fn collapse_whitespace(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// `00000-000` when `value` holds exactly eight digits besides separators.
+fn format_cep(value: &str) -> Option<String> {
+    if !value
+        .chars()
+        .all(|c| c.is_ascii_digit() || matches!(c, '-' | '.' | ' '))
+    {
+        return None;
+    }
+    let digits: String = value.chars().filter(char::is_ascii_digit).collect();
+    (digits.len() == 8).then(|| format!("{}-{}", &digits[..5], &digits[5..]))
+}
+
+/// UF code of a state given by code or name, in any case or accentuation.
+fn uf_code(value: &str) -> Option<&'static str> {
+    let normalized = normalize(value.trim());
+    UFS.iter()
+        .find(|(code, name)| normalized == code.to_lowercase() || normalized == *name)
+        .map(|(code, _)| *code)
+}
+
+impl RecordValidator {
+    /// Requires Rua, Municipio and Estado.
+    pub fn new(policy: ValidationPolicy) -> Self {
+        Self {
+            policy,
+            required: vec![
+                RecordField::Rua,
+                RecordField::Municipio,
+                RecordField::Estado,
+            ],
+        }
+    }
+
+    pub fn required(mut self, fields: Vec<RecordField>) -> Self {
+        self.required = fields;
+        self
+    }
+
+    /// Issues of `record`, each with the value it would be fixed to (if any).
+    fn inspect(&self, doc_id: DocId, record: &Record) -> Vec<(ValidationIssue, Option<String>)> {
+        let mut found = Vec::new();
+        let mut issue = |field: RecordField, kind: IssueKind, fixed: Option<String>| {
+            let value = record.field(field).to_string();
+            found.push((
+                ValidationIssue {
+                    doc_id,
+                    field,
+                    kind,
+                    value,
+                },
+                fixed,
+            ));
+        };
+
+        for (field, value) in record.fields() {
+            if value.trim().is_empty() {
+                if self.required.contains(&field) {
+                    issue(field, IssueKind::Missing, None);
+                }
+                continue;
+            }
+
+            let collapsed = collapse_whitespace(value);
+            match field {
+                RecordField::Cep => match format_cep(value) {
+                    Some(cep) if cep == value => {}
+                    Some(cep) => issue(field, IssueKind::CepFormat, Some(cep)),
+                    None => issue(field, IssueKind::InvalidCep, None),
+                },
+                RecordField::Estado => match uf_code(value) {
+                    Some(code) if code == value => {}
+                    Some(code) => issue(field, IssueKind::UfFormat, Some(code.to_string())),
+                    None => issue(field, IssueKind::InvalidUf, None),
+                },
+                _ if collapsed != value => issue(field, IssueKind::Whitespace, Some(collapsed)),
+                _ => {}
+            }
+        }
+        found
+    }
+
+    /// Validates one record in place. Returns whether it should be indexed,
+    /// along with its issues.
+    pub fn validate(&self, doc_id: DocId, record: &mut Record) -> (bool, Vec<ValidationIssue>) {
+        let found = self.inspect(doc_id, record);
+        let keep = self.policy != ValidationPolicy::Reject || found.is_empty();
+
+        if self.policy == ValidationPolicy::FixAndIndex {
+            for (issue, fixed) in &found {
+                if let Some(fixed) = fixed {
+                    *record.field_mut(issue.field) = fixed.clone();
+                }
+            }
+            for (_, alias) in &mut record.aliases {
+                *alias = collapse_whitespace(alias);
+            }
+        }
+        (keep, found.into_iter().map(|(issue, _)| issue).collect())
+    }
+
+    /// Validates a batch, returning the records to index and a report.
+    pub fn validate_batch(
+        &self,
+        records: Vec<(DocId, Record)>,
+    ) -> (Vec<(DocId, Record)>, ValidationReport) {
+        let mut report = ValidationReport {
+            checked: records.len(),
+            ..Default::default()
+        };
+        let mut accepted = Vec::with_capacity(records.len());
+
+        for (doc_id, mut record) in records {
+            let (keep, issues) = self.validate(doc_id, &mut record);
+            if !keep {
+                report.rejected += 1;
+            } else if self.policy == ValidationPolicy::FixAndIndex
+                && issues.iter().any(|issue| issue.kind.is_fixable())
+            {
+                report.fixed += 1;
+            }
+            report.issues.extend(issues);
+            if keep {
+                accepted.push((doc_id, record));
+            }
+        }
+
+        if !report.issues.is_empty() {
+            warn!(
+                "[VALIDATION] {} issues in {} records ({} rejected, {} fixed)",
+                report.issues.len(),
+                report.checked,
+                report.rejected,
+                report.fixed
+            );
+        }
+        (accepted, report)
+    }
+}
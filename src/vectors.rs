@@ -0,0 +1,295 @@
+//! Embedding reranking on top of BM25F. Each document may carry one float
+//! vector (e.g. from a sentence model), kept in a matrix with a row per doc
+//! id; a query carrying a vector gets its top hits rescored by
+//! `alpha * cosine + (1 - alpha) * bm25 / max_bm25`.
+//!
+//! The matrix file is an 8-byte header (dimensions and row count as `u32`
+//! LE) followed by the rows as `f32` LE, and is memory-mapped when opened.
+//! An all-zero row means the document has no vector.
+
+use crate::DocId;
+use crate::error::LfasError;
+use memmap2::Mmap;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::ops::Deref;
+use std::path::Path;
+use std::sync::Arc;
+
+/// Weight of the cosine similarity in the blended score.
+pub const DEFAULT_VECTOR_ALPHA: f32 = 0.3;
+
+/// BM25F hits rescored per query; the rest are cut.
+pub const DEFAULT_RERANK_DEPTH: usize = 100;
+
+const HEADER_LEN: usize = 8;
+
+/// A query embedding. Compared bitwise for `Eq` and `Hash`, like
+/// [`MinShouldMatch::Percent`](crate::MinShouldMatch::Percent).
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(transparent)]
+pub struct QueryVector(pub Vec<f32>);
+
+impl Eq for QueryVector {}
+
+impl std::hash::Hash for QueryVector {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        for value in &self.0 {
+            value.to_bits().hash(state);
+        }
+    }
+}
+
+impl From<Vec<f32>> for QueryVector {
+    fn from(vector: Vec<f32>) -> Self {
+        QueryVector(vector)
+    }
+}
+
+enum Matrix {
+    Owned(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for Matrix {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            Matrix::Owned(bytes) => bytes.as_slice(),
+            Matrix::Mapped(map) => &map[..],
+        }
+    }
+}
+
+/// One vector per doc id, in memory or memory-mapped from a matrix file.
+pub struct VectorStore {
+    dims: usize,
+    rows: usize,
+    data: Matrix,
+}
+
+impl std::fmt::Debug for VectorStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VectorStore")
+            .field("dims", &self.dims)
+            .field("rows", &self.rows)
+            .field("mapped", &matches!(self.data, Matrix::Mapped(_)))
+            .finish()
+    }
+}
+
+impl VectorStore {
+    /// Packs `vectors` into an in-memory matrix with a row for every doc id
+    /// up to the largest; documents left out get an all-zero row.
+    pub fn from_vectors<I>(dims: usize, vectors: I) -> Result<Self, LfasError>
+    where
+        I: IntoIterator<Item = (DocId, Vec<f32>)>,
+    {
+        let mut rows: Vec<(DocId, Vec<f32>)> = Vec::new();
+        for (doc_id, vector) in vectors {
+            if vector.len() != dims {
+                return Err(LfasError::Schema(format!(
+                    "vector of doc {} has {} dimensions, expected {}",
+                    doc_id,
+                    vector.len(),
+                    dims
+                )));
+            }
+            rows.push((doc_id, vector));
+        }
+        let count = rows
+            .iter()
+            .map(|(doc_id, _)| doc_id.index() + 1)
+            .max()
+            .unwrap_or(0);
+
+        let row_len = dims * 4;
+        let mut data = header(dims, count)?;
+        data.resize(HEADER_LEN + count * row_len, 0);
+        for (doc_id, vector) in rows {
+            let start = HEADER_LEN + doc_id.index() * row_len;
+            for (slot, value) in data[start..start + row_len].chunks_exact_mut(4).zip(vector) {
+                slot.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        Ok(Self {
+            dims,
+            rows: count,
+            data: Matrix::Owned(data),
+        })
+    }
+
+    /// Memory-maps a matrix file written by [`save`](Self::save). The file
+    /// must not change while the store is open.
+    pub fn open(path: &Path) -> Result<Self, LfasError> {
+        let file = File::open(path)?;
+        // Safety: the matrix is only read, and callers must not modify the file while it's mapped
+        let map = unsafe { Mmap::map(&file)? };
+
+        let word = |at: usize| -> Option<usize> {
+            Some(u32::from_le_bytes(map.get(at..at + 4)?.try_into().ok()?) as usize)
+        };
+        let (Some(dims), Some(rows)) = (word(0), word(4)) else {
+            return Err(LfasError::Schema(format!(
+                "{} is too short for a vector matrix",
+                path.display()
+            )));
+        };
+        let expected = HEADER_LEN + rows * dims * 4;
+        if map.len() != expected {
+            return Err(LfasError::Schema(format!(
+                "{} holds {} bytes, expected {} for {} rows of {} dimensions",
+                path.display(),
+                map.len(),
+                expected,
+                rows,
+                dims
+            )));
+        }
+
+        Ok(Self {
+            dims,
+            rows,
+            data: Matrix::Mapped(map),
+        })
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), LfasError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(&self.data)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn dims(&self) -> usize {
+        self.dims
+    }
+
+    /// Rows in the matrix, i.e. the largest doc id with a vector plus one.
+    pub fn len(&self) -> usize {
+        self.rows
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows == 0
+    }
+
+    fn row(&self, doc_id: DocId) -> Option<impl Iterator<Item = f32> + '_> {
+        if doc_id.index() >= self.rows {
+            return None;
+        }
+        let start = HEADER_LEN + doc_id.index() * self.dims * 4;
+        let bytes = &self.data[start..start + self.dims * 4];
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap_or_default())),
+        )
+    }
+
+    /// The vector of `doc_id`, None when it has none.
+    pub fn vector(&self, doc_id: DocId) -> Option<Vec<f32>> {
+        let vector: Vec<f32> = self.row(doc_id)?.collect();
+        vector.iter().any(|value| *value != 0.0).then_some(vector)
+    }
+
+    /// Cosine similarity between `query` and the vector of `doc_id`, None
+    /// when the document has no vector or `query` is all zeros.
+    pub fn cosine(&self, doc_id: DocId, query: &[f32]) -> Option<f32> {
+        let (mut dot, mut doc_norm, mut query_norm) = (0.0f32, 0.0f32, 0.0f32);
+        for (value, q) in self.row(doc_id)?.zip(query) {
+            dot += value * q;
+            doc_norm += value * value;
+            query_norm += q * q;
+        }
+        if doc_norm == 0.0 || query_norm == 0.0 {
+            return None;
+        }
+        Some(dot / (doc_norm.sqrt() * query_norm.sqrt()))
+    }
+}
+
+fn header(dims: usize, rows: usize) -> Result<Vec<u8>, LfasError> {
+    let word = |value: usize, what: &str| {
+        u32::try_from(value)
+            .map_err(|_| LfasError::Schema(format!("too many {} for a vector matrix", what)))
+    };
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(&word(dims, "dimensions")?.to_le_bytes());
+    header.extend_from_slice(&word(rows, "rows")?.to_le_bytes());
+    Ok(header)
+}
+
+/// Blends the cosine similarity between query and document vectors into the
+/// BM25F ranking of queries that carry a [`QueryVector`]. Cheap to clone: the
+/// store is shared.
+#[derive(Debug, Clone)]
+pub struct VectorReranker {
+    pub store: Arc<VectorStore>,
+    /// Weight of the cosine term, in [0, 1].
+    pub alpha: f32,
+    /// BM25F hits rescored, at least the query's `top_k`.
+    pub depth: usize,
+}
+
+impl VectorReranker {
+    pub fn new(store: VectorStore) -> Self {
+        Self {
+            store: Arc::new(store),
+            alpha: DEFAULT_VECTOR_ALPHA,
+            depth: DEFAULT_RERANK_DEPTH,
+        }
+    }
+
+    pub fn with_alpha(mut self, alpha: f32) -> Self {
+        self.alpha = alpha.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_depth(mut self, depth: usize) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    /// Rescores the best `depth.max(top_k)` of `scored`, sorted by descending
+    /// score, and drops the rest. Documents without a vector, and negative
+    /// similarities, add nothing to the BM25F part.
+    pub fn rerank(
+        &self,
+        query: &[f32],
+        mut scored: Vec<(DocId, f32)>,
+        top_k: usize,
+    ) -> Result<Vec<(DocId, f32)>, LfasError> {
+        if query.len() != self.store.dims() {
+            return Err(LfasError::InvalidQuery(format!(
+                "query vector has {} dimensions, the index {}",
+                query.len(),
+                self.store.dims()
+            )));
+        }
+        scored.truncate(self.depth.max(top_k));
+        let max_score = scored
+            .iter()
+            .map(|(_, score)| *score)
+            .fold(0.0f32, f32::max);
+
+        for (doc_id, score) in scored.iter_mut() {
+            let bm25 = if max_score > 0.0 {
+                *score / max_score
+            } else {
+                0.0
+            };
+            let cosine = self.store.cosine(*doc_id, query).unwrap_or(0.0).max(0.0);
+            *score = self.alpha * cosine + (1.0 - self.alpha) * bm25;
+        }
+        scored.sort_by(|a, b| {
+            b.1.partial_cmp(&a.1)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then(a.0.cmp(&b.0))
+        });
+        Ok(scored)
+    }
+}
@@ -0,0 +1,102 @@
+//! How much a search logs. Every stage of a search logs what it did, which
+//! helps while tuning relevance but floods production logs. A [`Verbosity`]
+//! set on the engine, or on one query, cuts that down to one summary line
+//! per search, or to nothing. It holds for the thread running the search,
+//! so logging outside searches (indexing, loading) is left as it is.
+
+use std::cell::Cell;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Default,
+    PartialEq,
+    Eq,
+    Hash,
+    PartialOrd,
+    Ord,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Verbosity {
+    /// Nothing, slow-query warnings included.
+    Silent,
+    /// One structured line per search: hits, candidates, timings and how
+    /// the search was answered.
+    Summary,
+    /// Every stage of the search, then the summary line.
+    #[default]
+    Debug,
+}
+
+impl Verbosity {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "silent" => Some(Verbosity::Silent),
+            "summary" => Some(Verbosity::Summary),
+            "debug" => Some(Verbosity::Debug),
+            _ => None,
+        }
+    }
+
+    /// Whether the per-stage lines are logged.
+    pub fn details(self) -> bool {
+        self == Verbosity::Debug
+    }
+
+    /// Whether the summary line is logged.
+    pub fn summary(self) -> bool {
+        self != Verbosity::Silent
+    }
+}
+
+thread_local! {
+    static CURRENT: Cell<Option<Verbosity>> = const { Cell::new(None) };
+}
+
+/// Verbosity of the search running on this thread; [`Verbosity::Debug`]
+/// outside of one.
+pub fn current() -> Verbosity {
+    CURRENT.with(|current| current.get()).unwrap_or_default()
+}
+
+/// Sets this thread's verbosity until the guard is dropped, which restores
+/// the previous one.
+pub fn scoped(verbosity: Verbosity) -> VerbosityGuard {
+    let previous = CURRENT.with(|current| current.replace(Some(verbosity)));
+    VerbosityGuard { previous }
+}
+
+#[must_use = "the verbosity is reset when the guard is dropped"]
+#[derive(Debug)]
+pub struct VerbosityGuard {
+    previous: Option<Verbosity>,
+}
+
+impl Drop for VerbosityGuard {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.previous));
+    }
+}
+
+/// `info!` for a per-stage search line, skipped below [`Verbosity::Debug`].
+macro_rules! query_info {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::current().details() {
+            tracing::info!($($arg)*);
+        }
+    };
+}
+
+/// `debug!` for a per-stage search line, skipped below [`Verbosity::Debug`].
+macro_rules! query_debug {
+    ($($arg:tt)*) => {
+        if $crate::verbosity::current().details() {
+            tracing::debug!($($arg)*);
+        }
+    };
+}
+
+pub(crate) use {query_debug, query_info};
@@ -0,0 +1,47 @@
+#![cfg(feature = "tokio")]
+
+use lfas::async_engine::AsyncSearchEngine;
+use lfas::engine::SearchEngine;
+use lfas::storage::InMemoryStorage;
+use lfas::{Record, RecordField, StructuredQuery};
+
+fn query(rua: &str) -> StructuredQuery<RecordField> {
+    StructuredQuery {
+        fields: vec![(RecordField::Rua, rua.to_string())],
+        ..Default::default()
+    }
+}
+
+#[tokio::test]
+async fn test_async_index_and_search() {
+    let engine = AsyncSearchEngine::new(SearchEngine::with_storage(InMemoryStorage::new()));
+    let records = vec![
+        (0, Record { rua: "Mauriti".into(), ..Default::default() }),
+        (1, Record { rua: "Pedreira".into(), ..Default::default() }),
+    ];
+    engine.index_batch(records).await.unwrap();
+    engine.flush().await.unwrap();
+
+    let hits = engine.search(query("Pedreira")).await.unwrap();
+    assert_eq!(hits[0].doc_id, 1);
+
+    // Clones share the engine and can search concurrently
+    let other = engine.clone();
+    let (mauriti, pedreira) = tokio::join!(
+        engine.search(query("Mauriti")),
+        other.search(query("Pedreira"))
+    );
+    assert_eq!(mauriti.unwrap()[0].doc_id, 0);
+    assert_eq!(pedreira.unwrap()[0].doc_id, 1);
+
+    let batch = engine
+        .search_batch(vec![query("Pedreira"), query("Mauriti")])
+        .await
+        .unwrap();
+    assert_eq!(batch.len(), 2);
+    assert_eq!(batch[0][0].doc_id, 1);
+    assert_eq!(batch[1][0].doc_id, 0);
+
+    let total = engine.read(|engine| Ok(engine.metadata.total_docs)).await.unwrap();
+    assert_eq!(total, 2);
+}
@@ -0,0 +1,154 @@
+use lfas::builder::{BuildProgress, IndexBuilder, ProgressReporter};
+use lfas::engine::{SearchEngine, TokenizedDoc};
+use lfas::merge::IndexMerger;
+use lfas::storage::{InMemoryStorage, LmdbStorage};
+use lfas::{DocId, Record, RecordField, StructuredQuery};
+use tempfile::tempdir;
+
+fn records() -> Vec<Record> {
+    [
+        "Rua Mauriti",
+        "Rua Pedreira",
+        "Travessa Mauriti",
+        "Avenida Pedro Miranda",
+    ]
+    .iter()
+    .map(|rua| Record {
+        rua: rua.to_string(),
+        municipio: "Belém".into(),
+        ..Default::default()
+    })
+    .collect()
+}
+
+#[test]
+fn test_sorted_build_matches_incremental_index() {
+    let mut reference = SearchEngine::with_storage(InMemoryStorage::new());
+    let dir = tempdir().unwrap();
+    let mut builder = IndexBuilder::new(&dir.path().join("spill"))
+        .unwrap()
+        .memory_budget(1);
+    for (doc_id, record) in records().iter().enumerate() {
+        reference.index_record(doc_id, record).unwrap();
+        let doc = TokenizedDoc::from_record_with(DocId::new(doc_id as u32), record, &reference.analyzer());
+        builder.add(&doc).unwrap();
+    }
+
+    let mut storage = LmdbStorage::<RecordField>::open(&dir.path().join("index")).unwrap();
+    let report = builder.finish(&mut storage).unwrap();
+    assert_eq!(report.docs, 4);
+    // A budget of one byte spills after every document
+    assert_eq!(report.runs, 4);
+    assert_eq!(report.terms, reference.metadata.term_df.len());
+    assert!(
+        std::fs::read_dir(dir.path().join("spill"))
+            .unwrap()
+            .next()
+            .is_none()
+    );
+
+    let mut built = SearchEngine::with_storage(storage);
+    built.rebuild_metadata(|_| {}).unwrap();
+    assert_eq!(built.metadata.total_docs, 4);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let expected = reference.execute(query.clone(), query.blocking_k).unwrap();
+    let hits = built.execute(query.clone(), query.blocking_k).unwrap();
+    let ids = |hits: &[lfas::SearchHit]| hits.iter().map(|hit| hit.doc_id).collect::<Vec<_>>();
+    assert_eq!(ids(&hits), ids(&expected));
+}
+
+#[test]
+fn test_builder_reports_progress() {
+    use std::sync::{Arc, Mutex};
+
+    let reports: Arc<Mutex<Vec<BuildProgress>>> = Arc::default();
+    let sink = Arc::clone(&reports);
+    let reporter = ProgressReporter::new(3, move |progress| sink.lock().unwrap().push(*progress))
+        .expected_docs(8);
+
+    let dir = tempdir().unwrap();
+    let mut builder = IndexBuilder::new(&dir.path().join("spill"))
+        .unwrap()
+        .memory_budget(1)
+        .progress(reporter);
+    let analyzer = SearchEngine::with_storage(InMemoryStorage::new()).analyzer();
+    let records = records();
+    for doc_id in 0..8 {
+        let doc = TokenizedDoc::from_record_with(DocId::new(doc_id), &records[doc_id as usize % 4], &analyzer);
+        builder.add(&doc).unwrap();
+    }
+    let mut storage = LmdbStorage::<RecordField>::open(&dir.path().join("index")).unwrap();
+    builder.finish(&mut storage).unwrap();
+
+    let reports = reports.lock().unwrap();
+    // Every third document, then once after the merge
+    assert_eq!(
+        reports.iter().map(|p| p.docs).collect::<Vec<_>>(),
+        vec![3, 6, 8]
+    );
+    assert_eq!(reports[0].flushes, 3);
+    assert!(
+        reports
+            .windows(2)
+            .all(|pair| pair[0].tokens < pair[1].tokens)
+    );
+    assert!(reports[0].eta.is_some());
+    assert_eq!(reports[2].eta, Some(std::time::Duration::ZERO));
+}
+
+#[test]
+fn test_merge_offsets_doc_ids_and_sums_stats() {
+    let dir = tempdir().unwrap();
+    let inputs = [dir.path().join("pa"), dir.path().join("am")];
+    for (path, prefix) in inputs.iter().zip(["pa", "am"]) {
+        let storage = LmdbStorage::<RecordField>::open(path).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        for (doc_id, record) in records().into_iter().enumerate() {
+            let record = Record {
+                id: format!("{}-{}", prefix, doc_id),
+                ..record
+            };
+            engine.index_record(doc_id, &record).unwrap();
+        }
+        engine.save_config().unwrap();
+    }
+
+    let output = dir.path().join("merged");
+    let merger = IndexMerger::new(&dir.path().join("spill")).memory_budget(1);
+    let report = merger.merge(&inputs, &output).unwrap();
+    assert_eq!(report.docs, 8);
+    assert_eq!(report.offsets, vec![DocId::new(0), DocId::new(4)]);
+    assert_eq!(report.duplicate_ids, 0);
+    assert!(merger.merge(&inputs, &output).is_err());
+
+    let storage = LmdbStorage::<RecordField>::open(&output).unwrap();
+    let mut merged = SearchEngine::with_storage(storage);
+    assert!(merged.load_committed_metadata().unwrap());
+    assert_eq!(merged.metadata.total_docs, 8);
+    assert_eq!(merged.metadata.get_df(&RecordField::Rua, "mauriti"), 4);
+    assert_eq!(
+        merged.metadata.doc_length(DocId::new(7), &RecordField::Rua),
+        merged.metadata.doc_length(DocId::new(3), &RecordField::Rua)
+    );
+    assert!(merged.metadata.doc_length(DocId::new(7), &RecordField::Rua) > 0);
+    assert_eq!(merged.doc_id_for("pa-1"), Some(DocId::new(1)));
+    assert_eq!(merged.doc_id_for("am-1"), Some(DocId::new(5)));
+    let docs = merged
+        .fetch_fields(&[DocId::new(1), DocId::new(5)], &[RecordField::Rua])
+        .unwrap();
+    assert!(!docs[0].is_empty());
+    assert_eq!(docs[0], docs[1]);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Rua Pedreira".to_string())],
+        ..Default::default()
+    };
+    let hits = merged.execute(query.clone(), query.blocking_k).unwrap();
+    let mut top: Vec<DocId> = hits.iter().take(2).map(|hit| hit.doc_id).collect();
+    top.sort();
+    assert_eq!(top, vec![1, 5]);
+}
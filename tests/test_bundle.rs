@@ -0,0 +1,59 @@
+#![cfg(feature = "bundle")]
+
+use lfas::engine::SearchEngine;
+use lfas::storage::LmdbStorage;
+use lfas::{DocId, Record, RecordField, StructuredQuery};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_bundle_round_trip() {
+    let dir = tempdir().unwrap();
+    let bundle = dir.path().join("index.lfas");
+    let cache = dir.path().join("cache");
+
+    {
+        let storage = LmdbStorage::<RecordField>::open(&dir.path().join("index")).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        let records = [
+            Record {
+                id: "101".into(),
+                rua: "Mauriti".into(),
+                numero: "31".into(),
+                ..Default::default()
+            },
+            Record {
+                id: "102".into(),
+                rua: "Pedreira".into(),
+                numero: "12".into(),
+                ..Default::default()
+            },
+        ];
+        for (doc_id, record) in records.iter().enumerate() {
+            engine.index_record(doc_id, record).unwrap();
+        }
+        engine.scorer.k1 = 1.6;
+        engine.export_bundle(&bundle).unwrap();
+    }
+    assert!(bundle.is_file());
+
+    let engine = SearchEngine::open_bundle_with_cache(&bundle, &cache).unwrap();
+    assert!(engine.index.storage.is_read_only());
+    assert_eq!(engine.scorer.k1, 1.6);
+    assert_eq!(engine.metadata.total_docs, 2);
+    assert_eq!(engine.id_map.get("102"), Some(&DocId::new(1)));
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        external_id: Some("102".to_string()),
+        ..Default::default()
+    };
+    let hits = engine.execute(query, 10).unwrap();
+    assert_eq!(hits[0].doc_id, 1);
+    assert!(hits[0].exact);
+    drop(engine);
+
+    // Reopening reuses the unpacked copy
+    SearchEngine::open_bundle_with_cache(&bundle, &cache).unwrap();
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 1);
+}
@@ -0,0 +1,73 @@
+use lfas::RecordField::{Municipio, Rua};
+use lfas::compare::{ComparisonEngine, kendall_tau};
+use lfas::engine::SearchEngine;
+use lfas::storage::InMemoryStorage;
+use lfas::{DocId, Record, RecordField, StructuredQuery};
+
+fn engine() -> SearchEngine<RecordField, InMemoryStorage<RecordField>> {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [("Pedreira", "Santarem"), ("Castanha", "Marituba")];
+    for (doc_id, (rua, municipio)) in records.iter().enumerate() {
+        let record = Record {
+            rua: rua.to_string(),
+            municipio: municipio.to_string(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    engine
+}
+
+fn query() -> StructuredQuery<RecordField> {
+    StructuredQuery {
+        fields: vec![
+            (Rua, "Pedreira".to_string()),
+            (Municipio, "Marituba".to_string()),
+        ],
+        top_k: 2,
+        ..Default::default()
+    }
+}
+
+fn ids<const N: usize>(ids: [u32; N]) -> Vec<DocId> {
+    ids.map(DocId::new).to_vec()
+}
+
+#[test]
+fn test_kendall_tau() {
+    assert_eq!(kendall_tau(&ids([1, 2, 3]), &ids([1, 2, 3])), 1.0);
+    assert_eq!(kendall_tau(&ids([1, 2, 3]), &ids([3, 2, 1])), -1.0);
+    // 3 and 2 each tie below the ranking that misses them
+    assert!((kendall_tau(&ids([1, 2]), &ids([1, 3])) - 1.0 / 3.0).abs() < 1e-9);
+    assert_eq!(kendall_tau(&ids([]), &ids([])), 1.0);
+    assert_eq!(kendall_tau(&ids([1, 2]), &ids([])), 0.0);
+}
+
+#[test]
+fn test_comparison_reports_ranking_divergences() {
+    let same = ComparisonEngine::new(engine(), engine());
+    let report = same.run(vec![query(); 3]).unwrap();
+    assert_eq!(report.queries, 3);
+    assert_eq!(report.mean_kendall_tau, 1.0);
+    assert_eq!(report.top1_agreement, 1.0);
+    assert_eq!(report.divergent, 0);
+
+    // Outweighing rua flips the two documents
+    let mut candidate = engine();
+    candidate.scorer.field_weights.insert(Municipio, 8.0);
+    let comparison = ComparisonEngine::new(engine(), candidate).with_max_divergences(1);
+    let hits = comparison.execute(query()).unwrap();
+    assert_eq!(hits[0].doc_id, 0);
+
+    let report = comparison.run(vec![query()]).unwrap();
+    assert_eq!(report.queries, 2);
+    assert_eq!(report.mean_kendall_tau, -1.0);
+    assert_eq!(report.top1_agreement, 0.0);
+    assert_eq!(report.divergent, 2);
+    assert_eq!(report.divergences.len(), 1);
+    assert_eq!(report.divergences[0].control, vec![0, 1]);
+    assert_eq!(report.divergences[0].candidate, vec![1, 0]);
+
+    comparison.reset();
+    assert_eq!(comparison.report().queries, 0);
+}
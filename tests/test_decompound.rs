@@ -0,0 +1,84 @@
+use lfas::decompound::Decompounder;
+use lfas::engine::SearchEngine;
+use lfas::storage::InMemoryStorage;
+use lfas::{Record, RecordField, SearchHit, StructuredQuery};
+
+fn engine_with_streets(
+    streets: &[&str],
+) -> SearchEngine<RecordField, InMemoryStorage<RecordField>> {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in streets.iter().enumerate() {
+        let record = Record {
+            rua: rua.to_string(),
+            numero: "31".into(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    engine
+}
+
+#[test]
+fn test_split_prefers_well_attested_parts() {
+    let engine = engine_with_streets(&[
+        "Rua Mauriti",
+        "Rua Pedreira",
+        "Rua Ma",
+        "Avenida Almirante Barroso",
+        "Travessa Uriti",
+    ]);
+    let decompounder = Decompounder::default();
+    let split = |token: &str| {
+        decompounder
+            .split(RecordField::Rua, token, &engine.metadata)
+            .map(|split| split.parts)
+    };
+
+    assert_eq!(
+        split("ruamauriti"),
+        Some(vec!["rua".into(), "mauriti".into()])
+    );
+    assert_eq!(
+        split("avenidaalmirantebarroso"),
+        Some(vec!["avenida".into(), "almirante".into(), "barroso".into()])
+    );
+    // Indexed words, unknown parts and other fields are left alone
+    assert_eq!(split("mauriti"), None);
+    assert_eq!(split("ruaxyzzy"), None);
+    assert_eq!(
+        decompounder.split(RecordField::Bairro, "ruamauriti", &engine.metadata),
+        None
+    );
+    // Two parts at most
+    assert_eq!(
+        Decompounder::new(2, 2).split(
+            RecordField::Rua,
+            "avenidaalmirantebarroso",
+            &engine.metadata
+        ),
+        None
+    );
+}
+
+#[test]
+fn test_run_together_query_finds_the_street() {
+    let mut engine = engine_with_streets(&["Rua Mauriti", "Rua Pedreira", "Avenida Pedro Miranda"]);
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "ruamauriti31".to_string())],
+        top_k: 3,
+        ..Default::default()
+    };
+
+    let score_of_mauriti = |hits: &[SearchHit]| {
+        hits.iter()
+            .find(|hit| hit.doc_id.index() == 0)
+            .map_or(0.0, |hit| hit.score)
+    };
+    let before = engine.execute(query.clone(), 100).unwrap();
+
+    engine.decompounder = Some(Decompounder::default());
+    let hits = engine.execute(query, 100).unwrap();
+    assert_eq!(hits[0].doc_id.index(), 0);
+    assert!(hits[0].score > hits[1].score);
+    assert!(score_of_mauriti(&hits) > score_of_mauriti(&before));
+}
@@ -1,11 +1,14 @@
-use lfas::engine::SearchEngine;
+use lfas::config::ConfigReload;
+use lfas::engine::{FallbackPolicy, SearchEngine, TokenizedDoc};
+use lfas::error::LfasError;
 use lfas::index::InvertedIndex;
 use lfas::metadata::FieldMetadata;
-use lfas::scorer::BM25FScorer;
+use lfas::scorer::{BM25FScorer, FieldGroup, SCORE_LANES, TermParams, bm25f_contributions};
 use lfas::storage::InMemoryStorage;
-use lfas::tokenizer::tokenize;
-use lfas::{Record, RecordField, StructuredQuery};
+use lfas::tokenizer::{Synonyms, tokenize};
+use lfas::{DocId, Record, RecordField, StructuredQuery};
 use std::collections::HashMap;
+use std::sync::Arc;
 
 #[test]
 fn test_structured_address_search() {
@@ -27,6 +30,7 @@ fn test_structured_address_search() {
         numero: "31".into(),
         complemento: "".into(),
         nome: "Edificio Metropolitan".into(),
+        aliases: vec![],
     };
 
     let address_2 = Record {
@@ -40,10 +44,12 @@ fn test_structured_address_search() {
         numero: "500".into(),
         complemento: "Lote B".into(),
         nome: "Mercado Municipal".into(),
+        aliases: vec![],
     };
 
     let dataset = vec![address_1, address_2];
     for (internal_id, record) in dataset.iter().enumerate() {
+        let internal_id = DocId::new(internal_id as u32);
         metadata.total_docs += 1;
         let doc_meta = metadata.lengths.entry(internal_id).or_default();
 
@@ -53,7 +59,7 @@ fn test_structured_address_search() {
             *metadata.total_field_lengths.entry(field).or_insert(0) += tokens.len();
 
             for token in tokens {
-                index.add_term(internal_id, field, token.clone());
+                index.add_term(internal_id, field, token.clone()).unwrap();
                 let key = (field, token);
                 *metadata.term_df.entry(key).or_insert(0) += 1;
             }
@@ -65,15 +71,20 @@ fn test_structured_address_search() {
     field_weights.insert(RecordField::Municipio, 1.0);
     field_weights.insert(RecordField::Cep, 5.0);
 
-    let engine = SearchEngine {
+    let engine = SearchEngine::new(
         index,
         metadata,
-        scorer: BM25FScorer {
+        BM25FScorer {
             k1: 1.2,
             field_weights,
             field_b: HashMap::new(),
+            field_tf: HashMap::new(),
+            recency: None,
+            coverage_boost: 0.0,
+            field_groups: Vec::new(),
+            length_clip: None,
         },
-    };
+    );
 
     // Test 1: CEP Search (Distinctive)
     println!("\n=== Test 1: CEP Search (Distinctive) ===");
@@ -81,9 +92,10 @@ fn test_structured_address_search() {
         fields: vec![(RecordField::Cep, "66095-000".to_string())],
         top_k: 5,
         blocking_k: 10_000,
+        ..Default::default()
     };
 
-    let results_cep = engine.execute(query_cep, 10);
+    let results_cep = engine.execute(query_cep, 10).unwrap();
     println!("CEP Search Results:");
     for (i, hit) in results_cep.iter().enumerate() {
         println!("{}. Document {} (Score: {})", i + 1, hit.doc_id, hit.score);
@@ -100,9 +112,10 @@ fn test_structured_address_search() {
         fields: vec![(RecordField::Municipio, "Belem".to_string())],
         top_k: 5,
         blocking_k: 10_000,
+        ..Default::default()
     };
 
-    let results_municipio_only = engine.execute(query_municipio_only, 10);
+    let results_municipio_only = engine.execute(query_municipio_only, 10).unwrap();
     println!("Municipio Only Search Results:");
     for (i, hit) in results_municipio_only.iter().enumerate() {
         println!("{}. Document {} (Score: {})", i + 1, hit.doc_id, hit.score);
@@ -125,9 +138,10 @@ fn test_structured_address_search() {
         ],
         top_k: 5,
         blocking_k: 10_000,
+        ..Default::default()
     };
 
-    let results_municipio = engine.execute(query_municipio, 10);
+    let results_municipio = engine.execute(query_municipio, 10).unwrap();
     println!("Municipio + Number Search Results:");
     for (i, hit) in results_municipio.iter().enumerate() {
         println!("{}. Document {} (Score: {})", i + 1, hit.doc_id, hit.score);
@@ -151,9 +165,10 @@ fn test_structured_address_search() {
         ],
         top_k: 5,
         blocking_k: 10_000,
+        ..Default::default()
     };
 
-    let results_combined = engine.execute(query_combined, 10);
+    let results_combined = engine.execute(query_combined, 10).unwrap();
     println!("Combined Search Results:");
     for (i, hit) in results_combined.iter().enumerate() {
         println!("{}. Document {} (Score: {})", i + 1, hit.doc_id, hit.score);
@@ -183,3 +198,1600 @@ fn test_structured_address_search() {
         );
     }
 }
+
+#[test]
+fn test_search_record() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    let address_1 = Record {
+        id: "101".into(),
+        estado: "PA".into(),
+        municipio: "Belem".into(),
+        bairro: "Marco".into(),
+        cep: "66095-000".into(),
+        tipo_logradouro: "Passagem".into(),
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        complemento: "".into(),
+        nome: "Edificio Metropolitan".into(),
+        aliases: vec![],
+    };
+
+    let address_2 = Record {
+        id: "102".into(),
+        estado: "PA".into(),
+        municipio: "Ananindeua".into(),
+        bairro: "Centro".into(),
+        cep: "67000-000".into(),
+        tipo_logradouro: "Rua".into(),
+        rua: "Mauriti".into(),
+        numero: "500".into(),
+        complemento: "Lote B".into(),
+        nome: "Mercado Municipal".into(),
+        aliases: vec![],
+    };
+
+    engine.index_record(0, &address_1).unwrap();
+    engine.index_record(1, &address_2).unwrap();
+
+    // Known id resolves directly
+    let by_id = engine.search_record(&address_2, 5).unwrap();
+    assert_eq!(by_id.len(), 1);
+    assert_eq!(by_id[0].doc_id, 1);
+    assert!(by_id[0].exact);
+
+    // Unknown id falls back to fuzzy matching on the non-empty fields
+    let probe = Record {
+        id: "999".into(),
+        municipio: "Belem".into(),
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    let fuzzy = engine.search_record(&probe, 5).unwrap();
+    assert!(!fuzzy.is_empty());
+    assert_eq!(fuzzy[0].doc_id, 0);
+    assert!(!fuzzy[0].exact);
+
+    // Plain structured queries can carry the id too
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        external_id: Some("101".into()),
+        ..Default::default()
+    };
+    let hits = engine.execute(query, 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].doc_id, 0);
+    assert!(hits[0].exact);
+
+    // A record with no content yields nothing
+    assert!(engine.search_record(&Record::default(), 5).unwrap().is_empty());
+}
+
+#[test]
+fn test_rebuild_metadata_from_storage() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    let record = Record {
+        id: "1".into(),
+        municipio: "Belem".into(),
+        rua: "Travessa Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    engine.index_record(0, &record).unwrap();
+    engine.index_record(1, &Record { numero: "500".into(), ..record.clone() }).unwrap();
+
+    let expected_df = engine.metadata.get_df(&RecordField::Rua, "mauriti");
+    let expected_len = engine.metadata.lengths[&DocId::new(0)][&RecordField::Rua];
+    let expected_total = engine.metadata.total_field_lengths[&RecordField::Rua];
+
+    engine.metadata = FieldMetadata::new();
+
+    let mut reports = Vec::new();
+    engine.rebuild_metadata(|p| reports.push(p)).unwrap();
+
+    assert_eq!(engine.metadata.total_docs, 2);
+    assert_eq!(engine.metadata.get_df(&RecordField::Rua, "mauriti"), expected_df);
+    assert_eq!(engine.metadata.lengths[&DocId::new(0)][&RecordField::Rua], expected_len);
+    assert_eq!(engine.metadata.total_field_lengths[&RecordField::Rua], expected_total);
+    assert_eq!(reports.last().unwrap().docs_seen, 2);
+}
+
+#[test]
+fn test_execute_preview_samples_large_candidate_sets() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    for i in 0..200 {
+        let record = Record {
+            municipio: "Belem".into(),
+            numero: "31".into(),
+            rua: if i % 2 == 0 { "Mauriti".into() } else { "Pedro Miranda".into() },
+            ..Default::default()
+        };
+        engine.index_record(i, &record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Numero, "31".to_string()),
+            (RecordField::Rua, "Mauriti".to_string()),
+        ],
+        top_k: 5,
+        ..Default::default()
+    };
+
+    let preview = engine.execute_preview(query.clone(), 50, 7).unwrap();
+    assert!(preview.approximate);
+    assert_eq!(preview.total_candidates, 200);
+    assert_eq!(preview.sampled_candidates, 50);
+    assert_eq!(preview.estimated_matches, 200);
+    assert_eq!(preview.hits.len(), 5);
+    assert!(preview.distribution.max >= preview.distribution.median);
+
+    // Same seed gives the same sample
+    let again = engine.execute_preview(query.clone(), 50, 7).unwrap();
+    assert_eq!(again.sampled_candidates, preview.sampled_candidates);
+    assert!((again.distribution.median - preview.distribution.median).abs() < 1e-4);
+
+    // Small candidate sets are scored exhaustively
+    let exhaustive = engine.execute_preview(query, 1_000, 7).unwrap();
+    assert!(!exhaustive.approximate);
+    assert_eq!(exhaustive.sampled_candidates, 200);
+}
+
+#[test]
+fn test_query_validation() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.limits.max_top_k = 100;
+
+    let query = |top_k: usize, text: String| StructuredQuery {
+        fields: vec![(RecordField::Rua, text)],
+        top_k,
+        ..Default::default()
+    };
+
+    assert!(engine.execute(query(10, "Mauriti".into()), 10).is_ok());
+    assert!(matches!(
+        engine.execute(query(1_000_000_000, "Mauriti".into()), 10),
+        Err(LfasError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        engine.execute(query(0, "Mauriti".into()), 10),
+        Err(LfasError::InvalidQuery(_))
+    ));
+    assert!(matches!(
+        engine.execute(query(10, "a".repeat(1 << 20)), 10),
+        Err(LfasError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_ties_are_broken_by_doc_id() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    let record = Record {
+        municipio: "Belem".into(),
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    for doc_id in [7, 3, 11, 5, 0] {
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauriti".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+
+    for _ in 0..5 {
+        let ids: Vec<_> = engine
+            .execute(query.clone(), 10)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.doc_id)
+            .collect();
+        assert_eq!(ids, vec![0, 3, 5, 7, 11]);
+    }
+}
+
+#[test]
+fn test_doc_ids_past_u32_are_rejected() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let record = Record {
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+
+    let last = DocId::MAX.index();
+    engine.index_record(last, &record).unwrap();
+    assert!(matches!(
+        engine.index_record(last + 1, &record),
+        Err(LfasError::DocIdOverflow(id)) if id == last as u64 + 1
+    ));
+    assert_eq!(engine.metadata.total_docs, 1);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Numero, "31".to_string())],
+        ..Default::default()
+    };
+    let hits = engine.execute(query, 10).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].doc_id, DocId::MAX);
+
+    // Serialized as a u64, like the usize ids before it
+    let bytes = bincode::serialize(&DocId::MAX).unwrap();
+    assert_eq!(bytes, bincode::serialize(&u64::from(u32::MAX)).unwrap());
+    assert_eq!(bincode::deserialize::<DocId>(&bytes).unwrap(), DocId::MAX);
+    let too_large = bincode::serialize(&(u64::from(u32::MAX) + 1)).unwrap();
+    assert!(bincode::deserialize::<DocId>(&too_large).is_err());
+}
+
+#[test]
+fn test_execute_with_rerank() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    let record = Record {
+        municipio: "Belem".into(),
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    engine.index_record(0, &record).unwrap();
+    engine
+        .index_record(1, &Record { rua: "Mauriti Nova".into(), ..record.clone() })
+        .unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauriti".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+
+    // Prefer the doc whose stored street is longest, discarding BM25F order
+    let hits = engine
+        .execute_with_rerank(query, |mut candidates| {
+            for candidate in &candidates {
+                let doc = candidate.doc.expect("stored doc");
+                assert_eq!(doc[&RecordField::Numero], "31");
+                assert!(candidate.matched[&RecordField::Rua].contains(&"mauriti".to_string()));
+            }
+            candidates.sort_by_key(|c| std::cmp::Reverse(c.doc.unwrap()[&RecordField::Rua].len()));
+            Ok::<_, LfasError>(candidates.into_iter().map(|c| c.hit).collect())
+        })
+        .unwrap();
+
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].doc_id, 1);
+}
+
+#[test]
+fn test_field_tf_saturation() {
+    use lfas::scorer::TfOptions;
+
+    assert_eq!(TfOptions::default().apply(4), 4.0);
+    assert_eq!(TfOptions { max_tf: Some(2), sublinear: false }.apply(4), 2.0);
+    assert!((TfOptions { max_tf: None, sublinear: true }.apply(4) - (1.0 + 4f32.ln())).abs() < 1e-6);
+
+    // Doc 0 repeats the token five times, doc 1 twice; lengths are equal
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let docs = [(0, "Joao Joao Joao Joao Joao"), (1, "Joao Joao")]
+        .into_iter()
+        .map(|(doc_id, nome)| {
+            let record = Record { nome: nome.into(), ..Default::default() };
+            TokenizedDoc::from_record(DocId::new(doc_id), &record)
+        })
+        .collect();
+    engine.index_tokenized(docs).unwrap();
+    let postings = engine.index.get_postings(RecordField::Nome, "joao").unwrap();
+    assert_eq!((postings.tf(DocId::new(0)), postings.tf(DocId::new(1))), (5, 2));
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Nome, "Joao".to_string())],
+        ..Default::default()
+    };
+    let scores = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+        assert_eq!(hits.len(), 2);
+        (hits[0].doc_id, hits[0].score - hits[1].score)
+    };
+
+    let (top, raw_gap) = scores(&engine);
+    assert_eq!(top, 0);
+    assert!(raw_gap > 0.0);
+
+    engine.scorer.field_tf.insert(RecordField::Nome, TfOptions { max_tf: Some(1), sublinear: false });
+    assert!(scores(&engine).1.abs() < 1e-6);
+
+    engine.scorer.field_tf.insert(RecordField::Nome, TfOptions { max_tf: None, sublinear: true });
+    let (top, sublinear_gap) = scores(&engine);
+    assert_eq!(top, 0);
+    assert!(sublinear_gap < raw_gap);
+}
+
+#[test]
+fn test_batched_contributions_match_per_doc_bm25f() {
+    let mut scorer = BM25FScorer {
+        k1: 1.2,
+        field_weights: HashMap::new(),
+        field_b: HashMap::new(),
+        field_tf: HashMap::new(),
+        recency: None,
+        coverage_boost: 0.0,
+        field_groups: Vec::new(),
+        length_clip: None,
+    };
+    scorer.field_weights.insert(RecordField::Nome, 2.0);
+    scorer.field_b.insert(RecordField::Nome, 0.5);
+    let params = TermParams { weight: 2.0, b: 0.5, avgdl: 3.0, k1: 1.2, scale: 0.8 };
+
+    // 19 documents: two full batches of lanes and a tail
+    assert_eq!(SCORE_LANES, 8);
+    let tfs: Vec<u32> = (0..19).map(|i| i % 4 + 1).collect();
+    let dls: Vec<f32> = (0..19).map(|i| (i % 5 + 1) as f32).collect();
+    let mut out = vec![0.0; 19];
+    let tf_values: Vec<f32> = tfs.iter().map(|&tf| tf as f32).collect();
+    bm25f_contributions(&tf_values, &dls, &params, &mut out);
+
+    for i in 0..19 {
+        let weighted_tf = scorer.weighted_tf(RecordField::Nome, tfs[i], dls[i], 3.0);
+        let expected = 0.8 * (weighted_tf / (1.2 + weighted_tf));
+        assert!((out[i] - expected).abs() < 1e-6, "doc {}: {} vs {}", i, out[i], expected);
+    }
+}
+
+#[test]
+fn test_ngram_weight_scales_weak_matches() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine
+        .index_record(0, &Record { rua: "Mauritania".into(), ..Default::default() })
+        .unwrap();
+
+    // Only the 3-grams "mau" and "rit" are shared with the stored street
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+
+    engine.ngram_weight = 1.0;
+    let full = engine.execute(query.clone(), query.blocking_k).unwrap();
+    engine.ngram_weight = 0.3;
+    let weighted = engine.execute(query.clone(), query.blocking_k).unwrap();
+
+    assert_eq!(full.len(), 1);
+    assert!((weighted[0].score - 0.3 * full[0].score).abs() < 1e-5);
+}
+
+#[test]
+fn test_ngram_fallback_can_be_disabled() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine
+        .index_record(0, &Record { rua: "Mauritania".into(), ..Default::default() })
+        .unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+
+    // No full token matches, so candidates only come from n-gram postings
+    assert_eq!(engine.execute(query.clone(), query.blocking_k).unwrap().len(), 1);
+    engine.fallback.ngrams = false;
+    assert!(engine.execute(query.clone(), query.blocking_k).unwrap().is_empty());
+}
+
+#[test]
+fn test_fallback_policy() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in ["Mauritania", "Maura", "Mauriti Lopes"].iter().enumerate() {
+        engine
+            .index_record(doc_id, &Record { rua: rua.to_string(), ..Default::default() })
+            .unwrap();
+    }
+
+    // "Maurits" has no full token match, so Round 1 falls back to n-grams
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Maurits".to_string())],
+        ..Default::default()
+    };
+    let default_hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(default_hits.len(), 3);
+
+    // N-grams shared by every document are too common to seed candidates
+    engine.fallback.max_df = Some(2);
+    let capped = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert!(!capped.is_empty() && capped.len() < 3);
+
+    engine.fallback = FallbackPolicy::disabled();
+    assert!(engine.execute(query.clone(), query.blocking_k).unwrap().is_empty());
+}
+
+#[test]
+fn test_reload_config_swaps_scoring_all_or_nothing() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.index_record(0, &Record { rua: "Mauriti".into(), ..Default::default() }).unwrap();
+    engine.index_record(1, &Record { bairro: "Mauriti".into(), ..Default::default() }).unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauriti".to_string()),
+            (RecordField::Bairro, "Mauriti".to_string()),
+        ],
+        ..Default::default()
+    };
+    let top = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        engine.execute(query.clone(), query.blocking_k).unwrap()[0].doc_id
+    };
+
+    let reload = |field: RecordField, weight: f32| ConfigReload {
+        field_weights: HashMap::from([(field, weight)]),
+        ..Default::default()
+    };
+    engine.reload_config(reload(RecordField::Bairro, 50.0)).unwrap();
+    assert_eq!(top(&engine), DocId::new(1));
+    engine.reload_config(reload(RecordField::Rua, 100.0)).unwrap();
+    assert_eq!(top(&engine), DocId::new(0));
+    assert_eq!(engine.scorer.field_weights[&RecordField::Bairro], 50.0);
+
+    // One bad value and nothing is applied
+    let invalid = ConfigReload {
+        k1: Some(2.0),
+        field_b: HashMap::from([(RecordField::Rua, 1.5)]),
+        ..reload(RecordField::Bairro, 500.0)
+    };
+    assert!(matches!(engine.reload_config(invalid), Err(LfasError::Schema(_))));
+    assert_eq!(top(&engine), DocId::new(0));
+    assert_ne!(engine.scorer.k1, 2.0);
+
+    let disabled = ConfigReload {
+        fallback: Some(FallbackPolicy::disabled()),
+        ..Default::default()
+    };
+    engine.reload_config(disabled).unwrap();
+    let typo = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Maurits".to_string())],
+        ..Default::default()
+    };
+    assert!(engine.execute(typo.clone(), typo.blocking_k).unwrap().is_empty());
+}
+
+#[test]
+fn test_reloaded_synonyms_change_hits() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.index_record(0, &Record { bairro: "Umarizal".into(), ..Default::default() }).unwrap();
+    engine.index_record(1, &Record { bairro: "Marco".into(), ..Default::default() }).unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Bairro, "Centro".to_string())],
+        ..Default::default()
+    };
+    let hits = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+        hits.iter().map(|hit| hit.doc_id).collect::<Vec<_>>()
+    };
+    assert!(hits(&engine).is_empty());
+
+    let mut synonyms = Synonyms::new();
+    synonyms.insert("CENTRO", ["Umarizal"]);
+    let reload = ConfigReload {
+        synonyms: Some(synonyms),
+        ..Default::default()
+    };
+    engine.reload_config(reload).unwrap();
+    assert_eq!(hits(&engine), vec![DocId::new(0)]);
+
+    // Synonyms of several words are rejected and the current ones kept
+    let mut phrase = Synonyms::new();
+    phrase.insert("centro", ["cidade velha"]);
+    let invalid = ConfigReload {
+        synonyms: Some(phrase),
+        ..Default::default()
+    };
+    assert!(matches!(engine.reload_config(invalid), Err(LfasError::Schema(_))));
+    assert_eq!(hits(&engine), vec![DocId::new(0)]);
+
+    let cleared = ConfigReload {
+        synonyms: Some(Synonyms::new()),
+        ..Default::default()
+    };
+    engine.reload_config(cleared).unwrap();
+    assert!(hits(&engine).is_empty());
+}
+
+#[test]
+fn test_query_diagnostics() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine
+        .index_record(0, &Record { rua: "Mauritania".into(), ..Default::default() })
+        .unwrap();
+    engine
+        .index_record(1, &Record { rua: "Pedreira".into(), numero: "31".into(), ..Default::default() })
+        .unwrap();
+
+    let query = |field: RecordField, text: &str| StructuredQuery {
+        fields: vec![(field, text.to_string())],
+        ..Default::default()
+    };
+
+    // House numbers are distinctive, plain words are not
+    let direct = engine.execute_interruptible(query(RecordField::Numero, "31")).unwrap().diagnostics;
+    assert_eq!(direct.candidates, 1);
+    assert_eq!(direct.round1_tokens, vec![("Numero".to_string(), "31".to_string())]);
+    assert!(!direct.fallback && !direct.exact);
+    assert!(direct.total >= direct.round1 + direct.round2);
+
+    // No full token matches "Mauriti", its n-grams seed the candidates
+    let fallback = engine.execute_interruptible(query(RecordField::Rua, "Mauriti")).unwrap().diagnostics;
+    assert!(fallback.fallback);
+    assert_eq!(fallback.candidates, 1);
+    assert!(fallback.round1_tokens.iter().all(|(_, token)| token.starts_with('#')));
+}
+
+#[test]
+fn test_coverage_boost_rewards_matching_more_fields() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let spread = Record { rua: "Mauriti".into(), numero: "31".into(), ..Default::default() };
+    let single = Record { rua: "Pedreira".into(), numero: "31".into(), ..Default::default() };
+    engine.index_record(0, &spread).unwrap();
+    engine.index_record(1, &single).unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauriti".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+    let scores = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+        let score = |doc_id| hits.iter().find(|hit| hit.doc_id == doc_id).unwrap().score;
+        (score(0), score(1))
+    };
+
+    let (spread_plain, single_plain) = scores(&engine);
+    engine.scorer.coverage_boost = 2.0;
+    let (spread_boosted, single_boosted) = scores(&engine);
+
+    // Both query fields matched vs one of two
+    assert!((spread_boosted - spread_plain - 2.0).abs() < 1e-5);
+    assert!((single_boosted - single_plain - 1.0).abs() < 1e-5);
+}
+
+#[test]
+fn test_field_group_weights_multiply_field_scores() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let address = Record { rua: "Mauriti".into(), ..Default::default() };
+    let business = Record { nome: "Padaria Mauriti".into(), ..Default::default() };
+    engine.index_record(0, &address).unwrap();
+    engine.index_record(1, &business).unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauriti".to_string()),
+            (RecordField::Nome, "Padaria".to_string()),
+        ],
+        ..Default::default()
+    };
+    let scores = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+        let score = |doc_id| hits.iter().find(|hit| hit.doc_id == doc_id).unwrap().score;
+        (score(0), score(1))
+    };
+
+    let (address_plain, business_plain) = scores(&engine);
+    engine.scorer.field_groups = vec![
+        FieldGroup::new("address", vec![RecordField::Rua, RecordField::Numero], 2.0),
+        FieldGroup::new("name", vec![RecordField::Nome], 0.5),
+    ];
+    assert_eq!(engine.scorer.group_weight(RecordField::Cep), 1.0);
+    let (address_grouped, business_grouped) = scores(&engine);
+
+    assert!((address_grouped - 2.0 * address_plain).abs() < 1e-4);
+    assert!((business_grouped - 0.5 * business_plain).abs() < 1e-4);
+    assert_eq!(engine.config().field_groups, engine.scorer.field_groups);
+}
+
+#[test]
+fn test_impacts_replace_bm25f_until_parameters_change() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.index_record(0, &Record { rua: "Mauriti".into(), ..Default::default() }).unwrap();
+    engine
+        .index_record(1, &Record { rua: "Travessa Mauriti".into(), bairro: "Pedreira".into(), ..Default::default() })
+        .unwrap();
+    engine.index_record(2, &Record { rua: "Pedreira".into(), ..Default::default() }).unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let scores = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        let mut hits: Vec<(DocId, f32)> = engine
+            .execute(query.clone(), query.blocking_k)
+            .unwrap()
+            .iter()
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect();
+        hits.sort_by_key(|(doc_id, _)| *doc_id);
+        hits
+    };
+
+    let exact = scores(&engine);
+    assert!(engine.rebuild_impacts().unwrap() > 0);
+    let scale = engine.impacts.as_ref().unwrap().scale;
+    let quantized = scores(&engine);
+    assert_eq!(quantized.len(), exact.len());
+    for ((doc, exact), (quantized_doc, quantized)) in exact.iter().zip(&quantized) {
+        assert_eq!(doc, quantized_doc);
+        assert!((exact - quantized).abs() <= scale * 10.0);
+    }
+
+    // Searches read the stored impacts while they are current
+    engine.impacts.as_mut().unwrap().scale = 2.0 * scale;
+    for ((_, quantized), (_, doubled)) in quantized.iter().zip(&scores(&engine)) {
+        assert!((doubled - 2.0 * quantized).abs() < 1e-4);
+    }
+
+    // and ignore them once a weight changes
+    engine.scorer.field_weights.insert(RecordField::Rua, 3.0);
+    assert!(!engine.impacts.as_ref().unwrap().params.matches(&engine.scorer, &engine.metadata));
+    let reweighted = scores(&engine);
+    engine.impacts = None;
+    assert_eq!(reweighted, scores(&engine));
+}
+
+#[test]
+fn test_score_cache_keeps_scores_and_drops_stale_entries() {
+    use lfas::score_cache::ScoreCache;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.index_record(0, &Record { rua: "Mauriti".into(), numero: "31".into(), ..Default::default() }).unwrap();
+    engine
+        .index_record(1, &Record { rua: "Travessa Mauriti".into(), numero: "12".into(), ..Default::default() })
+        .unwrap();
+    engine.index_record(2, &Record { rua: "Pedreira".into(), ..Default::default() }).unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string()), (RecordField::Numero, "31".to_string())],
+        ..Default::default()
+    };
+    let scores = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        engine
+            .execute(query.clone(), query.blocking_k)
+            .unwrap()
+            .iter()
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect::<Vec<_>>()
+    };
+    let uncached = scores(&engine);
+
+    engine.score_cache = Some(ScoreCache::new(64));
+    assert_eq!(scores(&engine), uncached);
+    let first = engine.score_cache.as_ref().unwrap().stats();
+    assert!(first.terms > 0 && first.norms > 0);
+    assert_eq!(scores(&engine), uncached);
+    // The second search finds every value it looks up
+    let second = engine.score_cache.as_ref().unwrap().stats();
+    let lookups = first.hits + first.misses;
+    assert_eq!(second.misses, first.misses);
+    assert_eq!(second.hits, first.hits + lookups);
+
+    // A b-value or the corpus changing empties it before the next search
+    engine.scorer.field_b.insert(RecordField::Rua, 0.2);
+    let rescored = scores(&engine);
+    assert_eq!(engine.score_cache.as_ref().unwrap().stats().invalidations, 2);
+    engine.index_record(3, &Record { rua: "Mauriti".into(), ..Default::default() }).unwrap();
+    let grown = scores(&engine);
+    assert_eq!(engine.score_cache.as_ref().unwrap().stats().invalidations, 3);
+    engine.update_field(DocId::new(3), RecordField::Rua, "Pedreira").unwrap();
+    let updated = scores(&engine);
+
+    engine.score_cache = None;
+    engine.scorer.field_b.insert(RecordField::Rua, 0.2);
+    assert_eq!(updated, scores(&engine));
+    assert_ne!(rescored, uncached);
+    assert_ne!(grown, rescored);
+}
+
+#[test]
+fn test_field_scores_sum_to_hit_score() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let record = Record { rua: "Mauriti".into(), numero: "31".into(), ..Default::default() };
+    engine.index_record(0, &record).unwrap();
+    engine
+        .index_record(1, &Record { rua: "Pedreira".into(), ..Default::default() })
+        .unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauriti".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+
+    // Off by default
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert!(hits[0].field_scores.is_empty());
+
+    engine.field_scores = true;
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    let fields: Vec<&str> = hits[0].field_scores.iter().map(|(f, _)| f.as_str()).collect();
+    assert_eq!(fields.len(), 2);
+    assert!(fields.contains(&"Rua") && fields.contains(&"Numero"));
+    let total: f32 = hits[0].field_scores.iter().map(|(_, score)| score).sum();
+    assert!((total - hits[0].score).abs() < 1e-4);
+}
+
+#[test]
+fn test_multi_value_fields() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    // Official and popular name of the same market
+    let record = Record {
+        nome: "Mercado de Sao Bras".into(),
+        aliases: vec![
+            (RecordField::Nome, "Mercado Bolonha".into()),
+            (RecordField::Nome, "".into()),
+        ],
+        ..Default::default()
+    };
+    engine.index_record(0, &record).unwrap();
+    engine
+        .index_record(1, &Record { nome: "Feira do Acai".into(), ..Default::default() })
+        .unwrap();
+
+    // "mercado" appears in both values but counts once toward the length
+    let tokens: std::collections::HashSet<String> = tokenize("Mercado de Sao Bras")
+        .into_iter()
+        .chain(tokenize("Mercado Bolonha"))
+        .collect();
+    assert_eq!(engine.metadata.lengths[&DocId::new(0)][&RecordField::Nome], tokens.len());
+    assert_eq!(engine.metadata.get_df(&RecordField::Nome, "mercado"), 1);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Nome, "Bolonha".to_string())],
+        ..Default::default()
+    };
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits[0].doc_id, 0);
+
+    assert_eq!(
+        engine.docs.values(DocId::new(0), RecordField::Nome),
+        vec!["Mercado de Sao Bras", "Mercado Bolonha"]
+    );
+}
+
+#[test]
+fn test_record_tokenized_leaves_postings_to_the_caller() {
+    let records = [
+        Record {
+            id: "a".into(),
+            rua: "Rua Mauriti".into(),
+            bairro: "Marco".into(),
+            ..Default::default()
+        },
+        Record {
+            id: "b".into(),
+            rua: "Rua Boaventura".into(),
+            ..Default::default()
+        },
+    ];
+    let docs: Vec<TokenizedDoc<RecordField>> = records
+        .iter()
+        .enumerate()
+        .map(|(doc_id, record)| TokenizedDoc::from_record(DocId::new(doc_id as u32), record))
+        .collect();
+
+    let mut indexed = SearchEngine::with_storage(InMemoryStorage::new());
+    indexed.index_tokenized(docs.clone()).unwrap();
+
+    let mut recorded = SearchEngine::with_storage(InMemoryStorage::new());
+    let batch = recorded.record_tokenized(docs).unwrap();
+    assert!(
+        recorded
+            .index
+            .get_postings(RecordField::Rua, "mauriti")
+            .is_none()
+    );
+    recorded.index.add_batch(batch).unwrap();
+
+    assert_eq!(recorded.metadata.lengths, indexed.metadata.lengths);
+    assert_eq!(
+        recorded.metadata.total_field_lengths,
+        indexed.metadata.total_field_lengths
+    );
+    assert_eq!(recorded.metadata.term_df, indexed.metadata.term_df);
+    assert_eq!(
+        recorded.metadata.empty_fields,
+        indexed.metadata.empty_fields
+    );
+    assert_eq!(
+        recorded.metadata.term_stats.len(),
+        indexed.metadata.term_stats.len()
+    );
+    assert_eq!(recorded.doc_id_for("b"), Some(DocId::new(1)));
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let hits = recorded.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits[0].doc_id, 0);
+}
+
+#[test]
+fn test_query_deadline_and_cancellation() {
+    use lfas::cancel::CancelToken;
+    use std::time::{Duration, Instant};
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine
+        .index_record(0, &Record { rua: "Mauriti".into(), numero: "31".into(), ..Default::default() })
+        .unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti 31".to_string())],
+        ..Default::default()
+    };
+
+    let results = engine.execute_interruptible(query.clone()).unwrap();
+    assert!(!results.interrupted);
+    assert_eq!(results.hits.len(), 1);
+
+    let expired = StructuredQuery {
+        deadline: Some(Instant::now()),
+        ..query.clone()
+    };
+    let results = engine.execute_interruptible(expired).unwrap();
+    assert!(results.interrupted);
+    assert!(results.hits.is_empty());
+
+    let cancel = CancelToken::new();
+    let cancelled = StructuredQuery {
+        cancel: Some(cancel.clone()),
+        ..query.clone()
+    };
+    assert!(!engine.execute_interruptible(cancelled.clone()).unwrap().interrupted);
+    cancel.cancel();
+    assert!(engine.execute_interruptible(cancelled).unwrap().interrupted);
+
+    let generous = query.with_timeout(Duration::from_secs(60));
+    assert!(!engine.execute_interruptible(generous).unwrap().interrupted);
+}
+
+#[test]
+fn test_warm_caches_most_frequent_terms() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in ["Mauriti", "Mauriti", "Pedreira"].into_iter().enumerate() {
+        engine
+            .index_record(doc_id, &Record { rua: rua.into(), ..Default::default() })
+            .unwrap();
+    }
+
+    assert_eq!(engine.warm(1, false).unwrap().cached_terms, 1);
+
+    let report = engine.warm(100, true).unwrap();
+    assert_eq!(report.cached_terms, engine.metadata.term_df.len());
+    assert!(report.touched_bytes > 0);
+
+    // Writes after warming keep the cached copy current
+    engine
+        .index_record(3, &Record { rua: "Mauriti".into(), ..Default::default() })
+        .unwrap();
+    let postings = engine.index.get_postings(RecordField::Rua, "mauriti").unwrap();
+    assert_eq!(postings.len(), 3);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits.len(), 3);
+}
+
+#[test]
+fn test_recency_decay_ranks_fresh_records_first() {
+    use lfas::scorer::RecencyDecay;
+
+    const DAY: i64 = 86_400;
+    let now = 1_700_000_000;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for doc_id in 0..3 {
+        engine
+            .index_record(doc_id, &Record { rua: "Mauriti".into(), ..Default::default() })
+            .unwrap();
+    }
+    // doc 0 is stale, doc 1 fresh, doc 2 undated
+    engine.set_doc_timestamp(DocId::new(0), now - 365 * DAY);
+    engine.set_doc_timestamp(DocId::new(1), now - DAY);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let plain = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(plain[0].doc_id, 0);
+
+    engine.scorer.recency = Some(RecencyDecay {
+        reference: Some(now),
+        ..RecencyDecay::new((30 * DAY) as f64)
+    });
+    let boosted = engine.execute(query.clone(), query.blocking_k).unwrap();
+    let order: Vec<DocId> = boosted.iter().map(|hit| hit.doc_id).collect();
+    assert_eq!(order, vec![2, 1, 0]);
+
+    let decay = RecencyDecay::new((30 * DAY) as f64);
+    assert!((decay.factor(Some(now - 30 * DAY), now) - 0.5).abs() < 1e-6);
+    assert_eq!(decay.factor(None, now), 1.0);
+    assert_eq!(RecencyDecay { floor: 0.2, ..decay }.factor(Some(0), now), 0.2);
+
+    // Rebuilding metadata from storage keeps the timestamps
+    engine.rebuild_metadata(|_| {}).unwrap();
+    assert_eq!(engine.metadata.timestamps.len(), 2);
+}
+
+#[test]
+fn test_min_should_match_filters_candidates() {
+    use lfas::MinShouldMatch;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { cep: "66095-000".into(), numero: "31".into(), ..Default::default() },
+        // Shares only the CEP with the query
+        Record { cep: "66095-000".into(), numero: "900".into(), ..Default::default() },
+        Record { cep: "67000-000".into(), numero: "12".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Cep, "66095-000".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+    let lenient = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(lenient.len(), 2);
+
+    for min_should_match in [MinShouldMatch::Count(2), MinShouldMatch::Percent(100.0)] {
+        let strict = StructuredQuery {
+            min_should_match: Some(min_should_match),
+            ..query.clone()
+        };
+        let hits = engine.execute(strict.clone(), strict.blocking_k).unwrap();
+        assert_eq!(hits.iter().map(|hit| hit.doc_id).collect::<Vec<_>>(), vec![0]);
+    }
+
+    assert_eq!(MinShouldMatch::parse("75%"), Some(MinShouldMatch::Percent(75.0)));
+    assert_eq!(MinShouldMatch::parse("2"), Some(MinShouldMatch::Count(2)));
+    assert_eq!(MinShouldMatch::Percent(50.0).required(3), 2);
+    assert_eq!(MinShouldMatch::Count(9).required(3), 3);
+
+    let invalid = StructuredQuery {
+        min_should_match: Some(MinShouldMatch::Percent(150.0)),
+        ..query.clone()
+    };
+    assert!(matches!(
+        engine.execute(invalid, 10),
+        Err(LfasError::InvalidQuery(_))
+    ));
+}
+
+#[test]
+fn test_field_intersection_blocking() {
+    use lfas::engine::BlockingStrategy;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in ["Travessa Mauriti", "Travessa Piraja", "Avenida Mauriti"]
+        .into_iter()
+        .enumerate()
+    {
+        engine
+            .index_record(doc_id, &Record { rua: rua.into(), ..Default::default() })
+            .unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Travessa Mauriti Xpto".to_string())],
+        ..Default::default()
+    };
+    assert_eq!(engine.execute(query.clone(), query.blocking_k).unwrap().len(), 3);
+
+    // Both known tokens must co-occur; the unknown "xpto" is ignored
+    engine.blocking = BlockingStrategy::FieldIntersection;
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits.iter().map(|hit| hit.doc_id).collect::<Vec<_>>(), vec![0]);
+}
+
+#[test]
+fn test_rarest_first_blocking_stops_at_blocking_k() {
+    use lfas::engine::BlockingStrategy;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for doc_id in 0..5 {
+        let cep = if doc_id == 0 { "66095-000" } else { "" };
+        let record = Record { numero: "31".into(), cep: cep.into(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let query = |blocking_k| StructuredQuery {
+        fields: vec![
+            (RecordField::Numero, "31".to_string()),
+            (RecordField::Cep, "66095-000".to_string()),
+        ],
+        blocking_k,
+        ..Default::default()
+    };
+    let candidates = |engine: &SearchEngine<_, _>, blocking_k| {
+        let results = engine.execute_interruptible(query(blocking_k)).unwrap();
+        (results.diagnostics.candidates, results.diagnostics.round1_tokens)
+    };
+    assert_eq!(candidates(&engine, 1).0, 5);
+
+    // The rare CEP fills blocking_k on its own, the common number is skipped
+    engine.blocking = BlockingStrategy::RarestFirst;
+    let (count, tokens) = candidates(&engine, 1);
+    assert_eq!(count, 1);
+    assert_eq!(tokens, vec![("Cep".to_string(), "66095-000".to_string())]);
+    assert_eq!(candidates(&engine, 100).0, 5);
+}
+
+#[test]
+fn test_query_presets() {
+    use lfas::MinShouldMatch;
+    use lfas::engine::{BlockingStrategy, QueryPreset};
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in ["Travessa Mauriti", "Travessa Piraja", "Avenida Mauriti"]
+        .into_iter()
+        .enumerate()
+    {
+        engine
+            .index_record(doc_id, &Record { rua: rua.into(), ..Default::default() })
+            .unwrap();
+    }
+
+    let query = |preset| StructuredQuery {
+        fields: vec![(RecordField::Rua, "Travessa Mauriti Xpto".to_string())],
+        preset,
+        ..Default::default()
+    };
+    let ids = |query: StructuredQuery<RecordField>| {
+        let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+        hits.iter().map(|hit| hit.doc_id).collect::<Vec<_>>()
+    };
+    assert_eq!(ids(query(Some(QueryPreset::Strict))), vec![0]);
+    assert_eq!(ids(query(Some(QueryPreset::Balanced))).len(), 3);
+    // The engine settings are untouched
+    assert_eq!(ids(query(None)).len(), 3);
+    assert_eq!(engine.blocking, BlockingStrategy::Union);
+
+    // A min_should_match on the query beats the preset's
+    let lenient = StructuredQuery {
+        min_should_match: Some(MinShouldMatch::Count(1)),
+        ..query(Some(QueryPreset::Strict))
+    };
+    let profile = engine.query_profile(&lenient);
+    assert_eq!(profile.min_should_match, Some(MinShouldMatch::Count(1)));
+    assert!(!profile.fallback);
+
+    assert_eq!(QueryPreset::from_name("Fuzzy"), Some(QueryPreset::Fuzzy));
+    assert!(QueryPreset::Fuzzy.profile().auto_correct);
+    assert_eq!(QueryPreset::from_name("loose"), None);
+}
+
+#[test]
+fn test_collapse_duplicate_addresses() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.collapse_fields = vec![RecordField::Cep, RecordField::Numero, RecordField::Rua];
+    let records = [
+        Record { rua: "Travessa Mauriti".into(), numero: "31".into(), cep: "66095-000".into(), ..Default::default() },
+        // Same address written differently
+        Record { rua: "TRAVESSA  MAURITI".into(), numero: "31".into(), cep: "66095-000".into(), bairro: "Marco".into(), ..Default::default() },
+        Record { rua: "Travessa Mauriti".into(), numero: "500".into(), cep: "66095-000".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+    assert_eq!(engine.metadata.collapse_keys[&DocId::new(0)], engine.metadata.collapse_keys[&DocId::new(1)]);
+    assert_ne!(engine.metadata.collapse_keys[&DocId::new(0)], engine.metadata.collapse_keys[&DocId::new(2)]);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Travessa Mauriti".to_string())],
+        ..Default::default()
+    };
+    let results = engine.execute_interruptible(query).unwrap();
+    let mut ids: Vec<_> = results.hits.iter().map(|hit| hit.doc_id).collect();
+    ids.sort();
+    assert_eq!(ids.len(), 2);
+    assert!(ids.contains(&DocId::new(2)));
+    assert_eq!(results.diagnostics.collapsed, 1);
+
+    // Keys survive a metadata rebuild
+    engine.rebuild_metadata(|_| {}).unwrap();
+    assert_eq!(engine.metadata.collapse_keys.len(), 3);
+}
+
+#[test]
+fn test_candidates_returns_round_one_bitmap() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { cep: "66095-000".into(), numero: "31".into(), ..Default::default() },
+        Record { cep: "66095-000".into(), numero: "900".into(), ..Default::default() },
+        Record { cep: "67000-000".into(), numero: "12".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Cep, "66095-000".to_string())],
+        top_k: 1,
+        ..Default::default()
+    };
+    let candidates = engine.candidates(&query).unwrap();
+    // top_k does not truncate the blocking output
+    assert_eq!(candidates.iter().collect::<Vec<_>>(), vec![0, 1]);
+}
+
+#[test]
+fn test_bitmap_for_composes_custom_blocking() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), municipio: "Belém".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), municipio: "Ananindeua".into(), ..Default::default() },
+        Record { rua: "Pedreira".into(), municipio: "Belém".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let mauriti = engine.bitmap_for(RecordField::Rua, "mauriti");
+    let belem = engine.bitmap_for(RecordField::Municipio, "belem");
+    assert_eq!((&mauriti & &belem).iter().collect::<Vec<_>>(), vec![0]);
+    assert_eq!((&mauriti - &belem).iter().collect::<Vec<_>>(), vec![1]);
+    assert_eq!((&mauriti | &belem).len(), 3);
+    assert!(engine.bitmap_for(RecordField::Rua, "Mauriti").is_empty());
+}
+
+#[test]
+fn test_facets_count_stored_values_of_candidates() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), municipio: "Belém".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), municipio: "Belém".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), municipio: "Ananindeua".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), ..Default::default() },
+        Record { rua: "Pedreira".into(), municipio: "Marituba".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let facets = engine.facets(&query, RecordField::Municipio).unwrap();
+    assert_eq!(
+        facets,
+        vec![("Belém".to_string(), 2), ("Ananindeua".to_string(), 1)]
+    );
+}
+
+#[test]
+fn test_term_stats_bound_scores() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), municipio: "Belem".into(), ..Default::default() },
+        Record { rua: "Travessa Mauriti Pedreira".into(), ..Default::default() },
+        Record { rua: "Pedreira".into(), municipio: "Belem".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+    engine.recompute_term_stats().unwrap();
+
+    let stats = engine.metadata.get_term_stats(&RecordField::Rua, "mauriti").unwrap();
+    assert_eq!(stats.max_tf, 1);
+    assert!(stats.max_weighted_tf > 0.0);
+    assert!(engine.metadata.get_term_stats(&RecordField::Rua, "inexistente").is_none());
+
+    // No single document can beat the sum of its terms' upper bounds
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let bound: f32 = engine
+        .analyze(RecordField::Rua, "Mauriti")
+        .weighted(engine.ngram_weight)
+        .iter()
+        .filter_map(|(token, weight)| {
+            engine
+                .scorer
+                .term_upper_bound(RecordField::Rua, token, &engine.metadata)
+                .map(|upper| upper * weight)
+        })
+        .sum();
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert!(!hits.is_empty());
+    assert!(hits.iter().all(|hit| hit.score <= bound + 1e-4));
+}
+
+#[test]
+fn test_term_stats_track_repeated_tokens() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine
+        .index_record(0, &Record { nome: "Maria Jose Maria".into(), ..Default::default() })
+        .unwrap();
+    engine
+        .index_record(1, &Record { nome: "Maria".into(), ..Default::default() })
+        .unwrap();
+
+    let stats = engine.metadata.get_term_stats(&RecordField::Nome, "maria").unwrap();
+    assert_eq!(stats.max_tf, 2);
+
+    // Recomputing from the postings finds the same tf
+    let incremental = stats.max_weighted_tf;
+    engine.recompute_term_stats().unwrap();
+    let stats = engine.metadata.get_term_stats(&RecordField::Nome, "maria").unwrap();
+    assert_eq!(stats.max_tf, 2);
+    assert!(stats.max_weighted_tf > 0.0 && incremental > 0.0);
+}
+
+#[test]
+fn test_update_field_patches_one_field() {
+    use lfas::docstore::DocStore;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), cep: "66095-000".into(), ..Default::default() },
+        Record { rua: "Pedreira".into(), cep: "66095-000".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    engine.update_field(DocId::new(0), RecordField::Cep, "66095-100").unwrap();
+    assert_eq!(engine.bitmap_for(RecordField::Cep, "66095-000").iter().collect::<Vec<_>>(), vec![1]);
+    assert_eq!(engine.bitmap_for(RecordField::Cep, "66095-100").iter().collect::<Vec<_>>(), vec![0]);
+    assert_eq!(engine.metadata.get_df(&RecordField::Cep, "66095-000"), 1);
+    assert_eq!(engine.docs.field(DocId::new(0), RecordField::Cep), Some("66095-100"));
+    // The rua postings are untouched
+    assert_eq!(engine.bitmap_for(RecordField::Rua, "mauriti").len(), 1);
+
+    // Without stored values the old tokens come from the postings
+    engine.docs = DocStore::new();
+    engine.update_field(DocId::new(1), RecordField::Cep, "").unwrap();
+    assert!(engine.bitmap_for(RecordField::Cep, "66095-000").is_empty());
+    assert_eq!(engine.metadata.doc_length(DocId::new(1), &RecordField::Cep), 0);
+
+    // The incremental bookkeeping matches a rebuild from the postings
+    let total = engine.metadata.total_field_lengths[&RecordField::Cep];
+    let empty = engine.metadata.empty_fields[&RecordField::Cep].clone();
+    engine.rebuild_metadata(|_| {}).unwrap();
+    assert_eq!(total, engine.metadata.total_field_lengths[&RecordField::Cep]);
+    assert_eq!(empty, engine.metadata.empty_fields[&RecordField::Cep]);
+    assert_eq!(engine.metadata.get_df(&RecordField::Cep, "66095-100"), 1);
+}
+
+#[test]
+fn test_query_expansion_adds_cooccurring_terms() {
+    use lfas::cooccurrence::{CooccurrenceIndex, QueryExpansion};
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    engine.cooccurrence = Some(CooccurrenceIndex::new());
+    for (doc_id, bairro) in ["Tapana Icoaraci", "Tapana Icoaraci", "Icoaraci"].iter().enumerate() {
+        let record = Record { bairro: bairro.to_string(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let counted = engine.cooccurrence.as_ref().unwrap().related(RecordField::Bairro, "tapana", 3, 2);
+    assert_eq!(counted, vec![("icoaraci".to_string(), 2)]);
+    // A rebuild from the postings counts the same pairs
+    engine.build_cooccurrence().unwrap();
+    let rebuilt = engine.cooccurrence.as_ref().unwrap().related(RecordField::Bairro, "tapana", 3, 2);
+    assert_eq!(rebuilt, counted);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Bairro, "Tapana".to_string())],
+        ..Default::default()
+    };
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits.len(), 2);
+
+    engine.expansion = Some(QueryExpansion::default());
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    let ids: Vec<_> = hits.iter().map(|hit| hit.doc_id).collect();
+    assert_eq!(ids.len(), 3);
+    // The expanded term only brings doc 2 in below the direct matches
+    assert_eq!(ids[2], 2);
+    assert!(hits[2].score < hits[1].score);
+}
+
+#[test]
+fn test_estimate_extrapolates_from_sample() {
+    let engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let sample: Vec<Record> = (0..100)
+        .map(|i| Record { rua: format!("Rua{}", i), estado: "PA".into(), ..Default::default() })
+        .collect();
+
+    let small = engine.estimate(&sample, 100);
+    let large = engine.estimate(&sample, 10_000);
+    assert_eq!(large.sample_docs, 100);
+    assert_eq!(large.postings, small.postings * 100);
+    // Every record brings a new street, "PA" is shared: growth close to linear
+    assert!(large.vocabulary_growth > 0.8 && large.vocabulary_growth <= 1.0);
+    assert!(large.unique_terms > small.unique_terms * 30);
+    assert!(large.unique_terms <= large.postings);
+    assert!(large.map_size >= 2 * (large.postings_bytes + large.metadata_bytes));
+    assert_eq!(large.map_size % (1 << 20), 0);
+
+    // A sample that repeats itself predicts no new terms
+    let repeated = vec![sample[0].clone(); 100];
+    let flat = engine.estimate(&repeated, 10_000);
+    assert_eq!(flat.vocabulary_growth, 0.0);
+    assert_eq!(flat.unique_terms, engine.estimate(&repeated, 100).unique_terms);
+
+    assert_eq!(engine.estimate(&[], 1_000).map_size, 0);
+}
+
+#[test]
+fn test_common_terms_skip_blocking_and_score_less() {
+    use lfas::engine::CommonTerms;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for doc_id in 0..10 {
+        let record = Record {
+            rua: "BR 316".into(),
+            numero: (31 + doc_id * 100).to_string(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "BR 316".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        top_k: 20,
+        ..Default::default()
+    };
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits.len(), 10);
+    let full_score = hits[0].score;
+
+    // "br 316" is in every document: it no longer blocks, and scores at 0.3
+    engine.common_terms = Some(CommonTerms { max_df_ratio: 0.5, weight: 0.3, min_docs: 5 });
+    assert!(engine.is_common_term(RecordField::Rua, "br 316"));
+    assert!(!engine.is_common_term(RecordField::Numero, "31"));
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].doc_id, 0);
+    assert!(hits[0].score < full_score);
+
+    // Too small an index to judge
+    engine.common_terms = Some(CommonTerms { min_docs: 100, ..CommonTerms::default() });
+    assert!(!engine.is_common_term(RecordField::Rua, "br 316"));
+}
+
+#[test]
+fn test_field_values_lists_terms_with_doc_counts() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, municipio) in ["Belém", "Belem", "Benevides", "Ananindeua"].iter().enumerate() {
+        let record = Record { municipio: municipio.to_string(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let values = engine.field_values(RecordField::Municipio, "", 10).unwrap();
+    assert_eq!(
+        values,
+        vec![("ananindeua".to_string(), 1), ("belem".to_string(), 2), ("benevides".to_string(), 1)]
+    );
+    assert_eq!(
+        engine.field_values(RecordField::Municipio, "Be", 1).unwrap(),
+        vec![("belem".to_string(), 2)]
+    );
+    assert!(engine.field_values(RecordField::Rua, "", 10).unwrap().is_empty());
+}
+
+#[test]
+fn test_match_one_decides_on_normalized_scores() {
+    use lfas::engine::MatchDecision;
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [("Mauriti", "31"), ("Mauriti", "500"), ("Pedreira", "12"), ("Pedreira", "12")];
+    for (doc_id, (rua, numero)) in records.iter().enumerate() {
+        let record = Record { rua: rua.to_string(), numero: numero.to_string(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    let query = |rua: &str, numero: &str| StructuredQuery {
+        fields: vec![(RecordField::Rua, rua.to_string()), (RecordField::Numero, numero.to_string())],
+        ..Default::default()
+    };
+
+    // A document holding exactly the query's values scores 1.0
+    match engine.match_one(query("Mauriti", "31"), 0.8, 0.3).unwrap() {
+        MatchDecision::Accepted(hit) => {
+            assert_eq!(hit.doc_id, 0);
+            assert!((hit.score - 1.0).abs() < 1e-4);
+        }
+        other => panic!("expected an accepted match, got {:?}", other),
+    }
+
+    // Two equally good candidates are left to review
+    match engine.match_one(query("Pedreira", "12"), 0.8, 0.3).unwrap() {
+        MatchDecision::Review(hits) => {
+            let mut ids: Vec<_> = hits.iter().map(|hit| hit.doc_id).collect();
+            ids.sort();
+            assert_eq!(ids, vec![2, 3]);
+        }
+        other => panic!("expected a review, got {:?}", other),
+    }
+
+    assert!(matches!(
+        engine.match_one(query("Xingu", "9999"), 0.8, 0.5).unwrap(),
+        MatchDecision::NoMatch
+    ));
+    assert!(engine.match_one(query("Mauriti", "31"), 0.5, 0.8).is_err());
+}
+
+#[test]
+fn test_candidate_generators_are_unioned() {
+    use lfas::candidates::{Block, CandidateGenerator, Clause, DistinctiveUnion, TermLookup};
+
+    // Blocks on every full token of the street, which isn't distinctive
+    struct StreetTokens;
+
+    impl CandidateGenerator<RecordField> for StreetTokens {
+        fn generate(
+            &self,
+            clauses: &[Clause<RecordField>],
+            index: &dyn TermLookup<RecordField>,
+            _blocking_k: usize,
+        ) -> Block<RecordField> {
+            let mut block = Block::default();
+            for clause in clauses.iter().filter(|clause| clause.field == RecordField::Rua) {
+                for token in clause.tokens.all.difference(&clause.tokens.weak) {
+                    if let Some(postings) = index.postings(clause.field, token) {
+                        block.docs |= postings.bitmap();
+                        block.tokens.push((clause.field, token.clone()));
+                    }
+                }
+            }
+            block
+        }
+    }
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [("Mauriti", "31"), ("Pedreira", "12"), ("Xingu", "500")];
+    for (doc_id, (rua, numero)) in records.iter().enumerate() {
+        let record = Record { rua: rua.to_string(), numero: numero.to_string(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string()), (RecordField::Numero, "12".to_string())],
+        ..Default::default()
+    };
+    let doc_ids = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>| {
+        let mut ids: Vec<_> = engine
+            .execute(query.clone(), query.blocking_k)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.doc_id)
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    engine.generators.push(Arc::new(DistinctiveUnion));
+    assert_eq!(doc_ids(&engine), vec![1]);
+
+    // Either generator's block is enough to be scored
+    engine.generators.push(Arc::new(StreetTokens));
+    assert_eq!(doc_ids(&engine), vec![0, 1]);
+}
+
+#[test]
+fn test_execute_iter_yields_the_same_hits_lazily() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for doc_id in 0..20 {
+        let record = Record { rua: "BR 316".into(), numero: (doc_id % 4).to_string(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "BR 316".to_string()), (RecordField::Numero, "1".to_string())],
+        top_k: 15,
+        ..Default::default()
+    };
+
+    let expected: Vec<_> = engine
+        .execute(query.clone(), query.blocking_k)
+        .unwrap()
+        .iter()
+        .map(|hit| (hit.doc_id, hit.score))
+        .collect();
+    assert_eq!(expected.len(), 15);
+
+    let mut hits = engine.execute_iter(query).unwrap();
+    assert_eq!(hits.len(), 15);
+    let first = hits.next().unwrap();
+    assert_eq!((first.doc_id, first.score), expected[0]);
+    assert_eq!(hits.len(), 14);
+    let rest: Vec<_> = hits.map(|hit| (hit.doc_id, hit.score)).collect();
+    assert_eq!(rest, expected[1..]);
+}
+
+#[test]
+fn test_cep_shortcut_ranks_few_cep_docs_without_bm25f() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let cep_docs = [
+        ("Avenida Pedro Miranda", "12"),
+        ("Travessa Mauriti", "900"),
+        ("Travessa Mauriti", "31"),
+        ("Avenida Almirante Barroso", "31"),
+    ];
+    for (doc_id, (rua, numero)) in cep_docs.into_iter().enumerate() {
+        let record = Record { rua: rua.into(), numero: numero.into(), cep: "66095-000".into(), ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    // The number alone puts every other document in Round 1
+    for doc_id in cep_docs.len()..2000 {
+        let cep = format!("{:05}-000", 10_000 + doc_id);
+        let record = Record { rua: "Travessa Mauriti".into(), numero: "31".into(), cep, ..Default::default() };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Cep, "66095-000".to_string()),
+            (RecordField::Rua, "Travessa Mauriti".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+    let run = |engine: &SearchEngine<_, _>| {
+        let results = engine.execute_interruptible(query.clone()).unwrap();
+        let total: std::time::Duration = (0..10)
+            .map(|_| engine.execute_interruptible(query.clone()).unwrap().diagnostics.total)
+            .sum();
+        (results, total)
+    };
+
+    let (full, full_time) = run(&engine);
+    assert!(!full.diagnostics.cep_shortcut);
+    assert!(full.diagnostics.candidates > 1000);
+    assert_eq!(full.hits[0].doc_id, 2);
+
+    engine.cep_shortcut = Some(10);
+    let (shortcut, shortcut_time) = run(&engine);
+    assert!(shortcut.diagnostics.cep_shortcut);
+    assert_eq!(shortcut.diagnostics.candidates, 4);
+    assert_eq!(shortcut.diagnostics.round2, std::time::Duration::ZERO);
+    assert_eq!(shortcut.hits.len(), 4);
+    assert_eq!(shortcut.hits[0].doc_id, 2);
+    assert_eq!(shortcut.hits[0].score, 1.0);
+    assert!(shortcut.hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+    assert!(
+        shortcut_time < full_time,
+        "shortcut took {:?}, full pipeline {:?}",
+        shortcut_time,
+        full_time
+    );
+
+    // A CEP in more documents than the threshold goes through both rounds
+    engine.cep_shortcut = Some(3);
+    let results = engine.execute_interruptible(query.clone()).unwrap();
+    assert!(!results.diagnostics.cep_shortcut);
+}
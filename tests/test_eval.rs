@@ -0,0 +1,109 @@
+use lfas::engine::SearchEngine;
+use lfas::eval::{evaluate, load_pairs, load_records};
+use lfas::storage::InMemoryStorage;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn test_eval_metrics_on_labeled_pairs() {
+    let dir = tempdir().unwrap();
+    let corpus = dir.path().join("corpus.csv");
+    let pairs = dir.path().join("pairs.csv");
+
+    fs::write(
+        &corpus,
+        "id,rua,numero,municipio\n\
+         a,Mauriti,31,Belem\n\
+         b,Mauriti,31,Ananindeua\n\
+         c,Pedreira,12,Ananindeua\n",
+    )
+    .unwrap();
+    fs::write(
+        &pairs,
+        "query_id,candidate_id,label,rua,numero,municipio\n\
+         q1,a,1,Mauriti,31,Belem\n\
+         q1,b,0,Mauriti,31,Belem\n\
+         q2,c,1,Pedreira,12,Ananindeua\n\
+         q3,x,1,Inexistente,999,\n",
+    )
+    .unwrap();
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, record) in load_records(&corpus).unwrap().iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let queries = load_pairs(&pairs).unwrap();
+    assert_eq!(queries.len(), 3);
+    assert_eq!(queries[0].query_id, "q1");
+    assert!(queries[0].non_relevant.contains("b"));
+
+    let report = evaluate(&engine, &queries, 1).unwrap();
+
+    // q1 and q2 rank their match first, q3's target doesn't exist
+    assert_eq!(report.queries, 3);
+    assert!((report.recall_at_k - 2.0 / 3.0).abs() < 1e-9);
+    assert!((report.mrr - 2.0 / 3.0).abs() < 1e-9);
+    assert!((report.precision_at_k - 2.0 / 3.0).abs() < 1e-9);
+    assert_eq!(report.non_match_rate_at_k, 0.0);
+
+    // With a deeper cutoff the labeled non-match of q1 shows up
+    let report = evaluate(&engine, &queries, 5).unwrap();
+    assert_eq!(report.non_match_rate_at_k, 1.0);
+    assert!((report.recall_at_k - 2.0 / 3.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_eval_rejects_bad_labels() {
+    let dir = tempdir().unwrap();
+    let pairs = dir.path().join("pairs.csv");
+    fs::write(&pairs, "query_id,candidate_id,label,rua\nq1,a,maybe,Mauriti\n").unwrap();
+
+    assert!(load_pairs(&pairs).is_err());
+}
+
+#[test]
+fn test_tune_finds_better_field_weights() {
+    use lfas::RecordField::Municipio;
+    use lfas::scorer::{ParamGrid, tune};
+
+    let dir = tempdir().unwrap();
+    let corpus = dir.path().join("corpus.csv");
+    let pairs = dir.path().join("pairs.csv");
+
+    // Each record matches one query field; only the municipio match is labeled
+    fs::write(
+        &corpus,
+        "id,rua,municipio\n\
+         a,Pedreira,Santarem\n\
+         b,Castanha,Marituba\n",
+    )
+    .unwrap();
+    fs::write(
+        &pairs,
+        "query_id,candidate_id,label,rua,municipio\n\
+         q1,b,1,Pedreira,Marituba\n",
+    )
+    .unwrap();
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, record) in load_records(&corpus).unwrap().iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+    let queries = load_pairs(&pairs).unwrap();
+
+    let mut grid = ParamGrid::default();
+    grid.field_weights.insert(Municipio, vec![3.0, 8.0]);
+    let tuned = tune(&mut engine, &queries, &grid, 2).unwrap();
+
+    // The default rua weight outranks municipio, so the match comes second
+    assert_eq!(tuned.baseline.mrr, 0.5);
+    assert_eq!(tuned.report.mrr, 1.0);
+    assert_eq!(tuned.config.field_weights[&Municipio], 8.0);
+    // Second round tries 3.0 again, finds nothing better and stops
+    assert_eq!(tuned.evaluations, 3);
+    // The engine keeps its settings until the result is applied
+    assert_eq!(engine.scorer.field_weights[&Municipio], 3.0);
+    engine.apply_config(tuned.config);
+    assert_eq!(evaluate(&engine, &queries, 2).unwrap().mrr, 1.0);
+}
@@ -0,0 +1,110 @@
+#![cfg(feature = "grpc")]
+
+use lfas::RecordField;
+use lfas::engine::SearchEngine;
+use lfas::grpc::SearchService;
+use lfas::grpc::proto::address_search_server::AddressSearch;
+use lfas::grpc::proto::{self, Alias, Clause, IndexRequest, SearchRequest, StatsRequest};
+use lfas::storage::InMemoryStorage;
+use std::sync::{Arc, RwLock};
+use tonic::{Code, Request, Status};
+
+fn service() -> SearchService<InMemoryStorage<RecordField>> {
+    let engine = SearchEngine::with_storage(InMemoryStorage::new());
+    SearchService::new(Arc::new(RwLock::new(engine)))
+}
+
+fn index_request(doc_id: u64, rua: &str, alias: Option<&str>) -> Result<IndexRequest, Status> {
+    Ok(IndexRequest {
+        doc_id,
+        record: Some(proto::Record {
+            id: format!("ext-{}", doc_id),
+            municipio: "Belem".into(),
+            rua: rua.into(),
+            aliases: alias
+                .map(|value| Alias {
+                    field: "rua".into(),
+                    value: value.into(),
+                })
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        }),
+    })
+}
+
+#[tokio::test]
+async fn test_grpc_index_search_explain_stats() {
+    let service = service();
+
+    let requests = vec![
+        index_request(0, "Mauriti", Some("Travessa do Chaco")),
+        index_request(1, "Pedreira", None),
+    ];
+    let summary = service
+        .index_stream(tokio_stream::iter(requests))
+        .await
+        .unwrap();
+    assert_eq!(summary.indexed, 2);
+    assert_eq!(summary.total_docs, 2);
+
+    let search = SearchRequest {
+        fields: vec![Clause {
+            field: "rua".into(),
+            text: "Chaco".into(),
+        }],
+        ..Default::default()
+    };
+    let hits = service
+        .search(Request::new(search.clone()))
+        .await
+        .unwrap()
+        .into_inner()
+        .hits;
+    assert_eq!(hits[0].doc_id, 0);
+
+    let results = service
+        .explain(Request::new(search))
+        .await
+        .unwrap()
+        .into_inner()
+        .results;
+    assert_eq!(results[0].doc["rua"].split('\u{1f}').count(), 2);
+    assert!(results[0]
+        .matched
+        .iter()
+        .any(|m| m.field == "rua" && m.tokens.contains(&"chaco".to_string())));
+
+    // The external id short-circuits to an exact hit
+    let by_id = SearchRequest {
+        external_id: Some("ext-1".into()),
+        ..Default::default()
+    };
+    let hits = service.search(Request::new(by_id)).await.unwrap().into_inner().hits;
+    assert!(hits[0].exact);
+    assert_eq!(hits[0].doc_id, 1);
+
+    let stats = service
+        .stats(Request::new(StatsRequest {}))
+        .await
+        .unwrap()
+        .into_inner();
+    assert_eq!(stats.total_docs, 2);
+    assert!(stats.total_terms > 0);
+    assert!(stats.avg_field_lengths["rua"] > 0.0);
+}
+
+#[tokio::test]
+async fn test_grpc_rejects_unknown_fields() {
+    let service = service();
+
+    let search = SearchRequest {
+        fields: vec![Clause {
+            field: "street".into(),
+            text: "Mauriti".into(),
+        }],
+        ..Default::default()
+    };
+    let status = service.search(Request::new(search)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+}
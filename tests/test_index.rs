@@ -1,3 +1,4 @@
+use lfas::DocId;
 use lfas::index::InvertedIndex;
 use lfas::storage::InMemoryStorage;
 use lfas::tokenizer::tokenize;
@@ -13,7 +14,7 @@ fn test_address_field_inverted_index() {
     let storage = InMemoryStorage::new();
     let mut idx = InvertedIndex::<AddressField, InMemoryStorage<AddressField>>::new(storage);
 
-    let doc1_id = 1;
+    let doc1_id = DocId::new(1);
     let addr1 = [
         (AddressField::Street, "Travessa Mauriti"),
         (AddressField::Municipality, "Belém"),
@@ -21,15 +22,15 @@ fn test_address_field_inverted_index() {
 
     for (field, text) in addr1 {
         for token in tokenize(text) {
-            idx.add_term(doc1_id, field, token);
+            idx.add_term(doc1_id, field, token).unwrap();
         }
     }
 
     let street_postings = idx
         .get_postings(AddressField::Street, "mauriti")
         .expect("Term not found");
-    assert!(street_postings.contains(1));
-    assert_eq!(*street_postings.frequencies.get(&1).unwrap(), 1);
+    assert!(street_postings.contains(DocId::new(1)));
+    assert_eq!(street_postings.tf(DocId::new(1)), 1);
 }
 
 #[test]
@@ -38,14 +39,14 @@ fn test_generic_set_operations() {
     let mut idx = InvertedIndex::<AddressField, InMemoryStorage<AddressField>>::new(storage);
 
     // Doc 1: Travessa Mauriti, Belém
-    idx.add_term(1, AddressField::Street, "travessa".to_string());
-    idx.add_term(1, AddressField::Street, "mauriti".to_string());
-    idx.add_term(1, AddressField::Municipality, "belem".to_string());
+    idx.add_term(DocId::new(1), AddressField::Street, "travessa".to_string()).unwrap();
+    idx.add_term(DocId::new(1), AddressField::Street, "mauriti".to_string()).unwrap();
+    idx.add_term(DocId::new(1), AddressField::Municipality, "belem".to_string()).unwrap();
 
     // Doc 2: Avenida Mauriti, Santarém
-    idx.add_term(2, AddressField::Street, "avenida".to_string());
-    idx.add_term(2, AddressField::Street, "mauriti".to_string());
-    idx.add_term(2, AddressField::Municipality, "santarem".to_string());
+    idx.add_term(DocId::new(2), AddressField::Street, "avenida".to_string()).unwrap();
+    idx.add_term(DocId::new(2), AddressField::Street, "mauriti".to_string()).unwrap();
+    idx.add_term(DocId::new(2), AddressField::Municipality, "santarem".to_string()).unwrap();
 
     // Intra-field Intersection (Street: avenida AND mauriti)
     let bm1 = idx.term_bitmap(AddressField::Street, "avenida");
@@ -75,3 +76,21 @@ fn test_generic_set_operations() {
     assert!(!inter_field.contains(2));
     assert_eq!(inter_field.len(), 1);
 }
+
+#[test]
+fn test_at_least_k_bitmaps() {
+    use roaring::RoaringBitmap;
+
+    type Index = InvertedIndex<AddressField, InMemoryStorage<AddressField>>;
+
+    let bitmaps: Vec<RoaringBitmap> = vec![
+        [1u32, 2, 3].into_iter().collect(),
+        [2u32, 3].into_iter().collect(),
+        [3u32, 4].into_iter().collect(),
+    ];
+
+    assert_eq!(Index::at_least(&bitmaps, 1).len(), 4);
+    assert_eq!(Index::at_least(&bitmaps, 2).iter().collect::<Vec<_>>(), vec![2, 3]);
+    assert_eq!(Index::at_least(&bitmaps, 3).iter().collect::<Vec<_>>(), vec![3]);
+    assert!(Index::at_least(&bitmaps, 4).is_empty());
+}
@@ -0,0 +1,766 @@
+use lfas::engine::SearchEngine;
+use lfas::{DocId, Record, RecordField, StructuredQuery};
+use lfas::postings::Postings;
+use lfas::storage::{LmdbError, LmdbOptions, LmdbStorage, PostingsStorage, SyncMode};
+use tempfile::tempdir;
+
+#[test]
+fn test_lmdb_options_and_read_only_open() {
+    let dir = tempdir().unwrap();
+
+    {
+        let options = LmdbOptions::new()
+            .map_size(64 * 1024 * 1024)
+            .sync_mode(SyncMode::NoSync)
+            .batch_size(1);
+        let mut storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+
+        let mut postings = Postings::new();
+        postings.add_occurrence(DocId::new(7));
+        storage.put(RecordField::Rua, "mauriti".into(), postings).unwrap();
+        PostingsStorage::flush(&mut storage).unwrap();
+    }
+
+    let options = LmdbOptions::new().map_size(64 * 1024 * 1024).read_only(true);
+    let mut reader = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+
+    assert!(reader.is_read_only());
+    let postings = reader.get(RecordField::Rua, "mauriti").unwrap().unwrap();
+    assert!(postings.contains(DocId::new(7)));
+    assert!(reader.put(RecordField::Rua, "novo".into(), Postings::new()).is_err());
+}
+
+#[test]
+fn test_format_version_check_and_migration() {
+    use heed::types::{Bytes, Str};
+    use heed::{Database, EnvOpenOptions};
+    use lfas::storage::LmdbError;
+    use lfas::storage::migrate::{FORMAT_VERSION, migrate};
+    use lfas::tokenizer::ngram_key;
+
+    // Fresh indexes are stamped with the current version
+    let fresh = tempdir().unwrap();
+    {
+        let storage = LmdbStorage::<RecordField>::open(fresh.path()).unwrap();
+        assert_eq!(storage.format_version().unwrap(), FORMAT_VERSION);
+    }
+
+    // Simulate a legacy index: postings written without any version record
+    let legacy = tempdir().unwrap();
+    {
+        let env = unsafe {
+            EnvOpenOptions::new()
+                .map_size(64 * 1024 * 1024)
+                .max_dbs(10)
+                .open(legacy.path())
+                .unwrap()
+        };
+        let mut wtxn = env.write_txn().unwrap();
+        let db: Database<Str, Bytes> = env.create_database(&mut wtxn, Some("postings")).unwrap();
+        // Pre-v3 postings layout: bitmap plus a doc id -> tf map
+        let bitmap: roaring::RoaringBitmap = [3u32].into_iter().collect();
+        let frequencies: std::collections::HashMap<usize, u32> = [(3, 2)].into_iter().collect();
+        let legacy_postings = bincode::serialize(&(bitmap, frequencies)).unwrap();
+        for term in ["mauriti", "mau"] {
+            let key = format!("{:02x}{:02x}{:02x}{:02x}:{}", 5, 0, 0, 0, term);
+            db.put(&mut wtxn, &key, &legacy_postings).unwrap();
+        }
+        wtxn.commit().unwrap();
+    }
+
+    assert!(matches!(
+        LmdbStorage::<RecordField>::open(legacy.path()),
+        Err(LmdbError::VersionMismatch { found: 0, .. })
+    ));
+
+    let report = migrate(legacy.path()).unwrap();
+    assert_eq!((report.from, report.to), (0, FORMAT_VERSION));
+
+    let storage = LmdbStorage::<RecordField>::open(legacy.path()).unwrap();
+    let postings = storage.get(RecordField::Rua, "mauriti").unwrap().unwrap();
+    assert!(postings.contains(DocId::new(3)));
+    assert_eq!(postings.tf(DocId::new(3)), 2);
+
+    // 3-byte terms were copied into the n-gram namespace
+    let ngram = storage.get(RecordField::Rua, &ngram_key("mau")).unwrap().unwrap();
+    assert!(ngram.contains(DocId::new(3)));
+    assert!(storage.get(RecordField::Rua, &ngram_key("mauriti")).unwrap().is_none());
+}
+
+const REPLICA_DIR_ENV: &str = "LFAS_REPLICA_TEST_DIR";
+
+/// Child half of `test_read_only_replica_sees_writer_commits`: indexes one
+/// more record from a separate process.
+#[test]
+#[ignore]
+fn replica_writer_process() {
+    let Ok(dir) = std::env::var(REPLICA_DIR_ENV) else {
+        return;
+    };
+
+    let storage = LmdbStorage::<RecordField>::open(std::path::Path::new(&dir)).unwrap();
+    let mut engine = SearchEngine::with_storage(storage);
+    let record = Record {
+        rua: "Pedreira".into(),
+        ..Default::default()
+    };
+    engine.index_record(1, &record).unwrap();
+    PostingsStorage::flush(&mut engine.index.storage).unwrap();
+}
+
+#[test]
+fn test_read_only_replica_sees_writer_commits() {
+    let dir = tempdir().unwrap();
+    {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        let record = Record {
+            rua: "Mauriti".into(),
+            ..Default::default()
+        };
+        engine.index_record(0, &record).unwrap();
+        PostingsStorage::flush(&mut engine.index.storage).unwrap();
+    }
+
+    let options = LmdbOptions::new().read_only(true);
+    let storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+    let mut replica = SearchEngine::with_storage(storage);
+
+    assert!(replica.refresh().unwrap());
+    assert!(!replica.refresh().unwrap());
+    assert_eq!(replica.metadata.total_docs, 1);
+    let generation = replica.index.storage.generation().unwrap();
+
+    // LMDB forbids opening one env twice per process, so the writer runs as a
+    // separate copy of this test binary
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["replica_writer_process", "--exact", "--ignored", "--nocapture"])
+        .env(REPLICA_DIR_ENV, dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    assert!(replica.index.storage.generation().unwrap() > generation);
+    assert!(replica.refresh().unwrap());
+    assert_eq!(replica.metadata.total_docs, 2);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Pedreira".to_string())],
+        ..Default::default()
+    };
+    let hits = replica.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits[0].doc_id, 1);
+}
+
+#[test]
+fn test_engine_config_travels_with_index() {
+    use lfas::tokenizer::TokenizerConfig;
+
+    let dir = tempdir().unwrap();
+    {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        assert!(engine.index.storage.read_config().unwrap().is_none());
+
+        engine.scorer.k1 = 1.5;
+        engine.scorer.field_weights.insert(RecordField::Rua, 7.0);
+        engine.tokenizer = TokenizerConfig::sliding(3, 1);
+        engine.fallback.ngrams = false;
+        engine
+            .index_record(
+                0,
+                &Record {
+                    rua: "Mauriti".into(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        engine.save_config().unwrap();
+    }
+
+    let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    let engine = SearchEngine::with_storage(storage);
+
+    assert_eq!(engine.scorer.k1, 1.5);
+    assert_eq!(engine.scorer.field_weights[&RecordField::Rua], 7.0);
+    assert_eq!(engine.tokenizer, TokenizerConfig::sliding(3, 1));
+    assert!(!engine.fallback.ngrams);
+    assert!(engine.config().schema.contains(&RecordField::Rua));
+    assert!(engine.index.storage.get(RecordField::Rua, "mauriti").unwrap().is_some());
+}
+
+const LOCKED_DIR_ENV: &str = "LFAS_LOCKED_TEST_DIR";
+
+/// Child half of `test_single_writer_lock_across_processes`: the parent holds
+/// the writer lock, so this process must be refused or fall back to read-only.
+#[test]
+#[ignore]
+fn locked_writer_process() {
+    use lfas::storage::LmdbError;
+
+    let Ok(dir) = std::env::var(LOCKED_DIR_ENV) else {
+        return;
+    };
+    let path = std::path::Path::new(&dir);
+
+    let refused = LmdbStorage::<RecordField>::open(path);
+    assert!(matches!(refused, Err(LmdbError::WriterLocked(_))));
+
+    let options = LmdbOptions::new().fallback_read_only(true);
+    let replica = LmdbStorage::<RecordField>::open_with_options(path, options).unwrap();
+    assert!(replica.is_read_only());
+}
+
+#[test]
+fn test_single_writer_lock_across_processes() {
+    use lfas::storage::LmdbError;
+
+    let dir = tempdir().unwrap();
+    let writer = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    assert!(!writer.is_read_only());
+
+    // A second handle is refused even within the same process
+    assert!(matches!(
+        LmdbStorage::<RecordField>::open(dir.path()),
+        Err(LmdbError::WriterLocked(_))
+    ));
+
+    let status = std::process::Command::new(std::env::current_exe().unwrap())
+        .args(["locked_writer_process", "--exact", "--ignored", "--nocapture"])
+        .env(LOCKED_DIR_ENV, dir.path())
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    // Dropping the writer releases the lock
+    drop(writer);
+    let writer = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    assert!(!writer.is_read_only());
+}
+
+#[test]
+fn test_build_and_swap_replaces_active_index() {
+    use lfas::error::LfasError;
+    use lfas::manager::IndexManager;
+
+    let root = tempdir().unwrap();
+    let old_path = root.path().join("v1");
+    {
+        let storage = LmdbStorage::<RecordField>::open(&old_path).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        let record = Record { rua: "Mauriti".into(), ..Default::default() };
+        engine.index_record(0, &record).unwrap();
+        PostingsStorage::flush(&mut engine.index.storage).unwrap();
+    }
+
+    let manager = IndexManager::open(&old_path, LmdbOptions::new()).unwrap();
+    let query = |rua: &str| StructuredQuery {
+        fields: vec![(RecordField::Rua, rua.to_string())],
+        ..Default::default()
+    };
+    assert_eq!(manager.execute(query("Mauriti")).unwrap().len(), 1);
+
+    // An in-flight search keeps the old engine alive across the swap
+    let in_flight = manager.current().unwrap();
+
+    let new_path = root.path().join("v2");
+    let generation = manager
+        .build_and_swap(&new_path, |engine| {
+            let record = Record { rua: "Pedreira".into(), ..Default::default() };
+            engine.index_record(0, &record)
+        })
+        .unwrap();
+    assert_eq!(generation, 1);
+    assert_eq!(manager.current().unwrap().path, new_path);
+    assert_eq!(manager.execute(query("Pedreira")).unwrap()[0].doc_id, 0);
+    assert!(manager.execute(query("Mauriti")).unwrap().is_empty());
+
+    let old_hits = in_flight.engine.execute(query("Mauriti"), 10_000).unwrap();
+    assert_eq!(old_hits.len(), 1);
+    drop(in_flight);
+
+    // A failed build leaves the active index and the target path untouched
+    let failed_path = root.path().join("v3");
+    let result = manager.build_and_swap(&failed_path, |_| {
+        Err(LfasError::Storage("source unavailable".into()))
+    });
+    assert!(result.is_err());
+    assert!(!failed_path.exists());
+    assert!(!root.path().join("v3.building").exists());
+    assert_eq!(manager.generation().unwrap(), 1);
+
+    assert!(manager.build_and_swap(&new_path, |_| Ok(())).is_err());
+}
+
+#[test]
+fn test_field_ids_survive_enum_reordering() {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Hash, Eq, PartialEq, Clone, Copy, Ord, PartialOrd, Debug, Serialize, Deserialize)]
+    enum FieldsV1 {
+        Rua,
+        Cep,
+    }
+
+    // A release that inserts a variant ahead of the existing ones
+    #[derive(Hash, Eq, PartialEq, Clone, Copy, Ord, PartialOrd, Debug, Serialize, Deserialize)]
+    enum FieldsV2 {
+        Bairro,
+        Rua,
+        Cep,
+    }
+
+    let dir = tempdir().unwrap();
+    {
+        let mut storage = LmdbStorage::<FieldsV1>::open(dir.path()).unwrap();
+        let mut postings = Postings::new();
+        postings.add_occurrence(DocId::new(1));
+        storage.put(FieldsV1::Cep, "66095000".into(), postings).unwrap();
+        PostingsStorage::flush(&mut storage).unwrap();
+    }
+
+    let mut storage = LmdbStorage::<FieldsV2>::open(dir.path()).unwrap();
+    assert!(storage.get(FieldsV2::Cep, "66095000").unwrap().unwrap().contains(DocId::new(1)));
+    assert!(storage.get(FieldsV2::Rua, "66095000").unwrap().is_none());
+
+    let mut postings = Postings::new();
+    postings.add_occurrence(DocId::new(2));
+    storage.put(FieldsV2::Bairro, "umarizal".into(), postings).unwrap();
+    PostingsStorage::flush(&mut storage).unwrap();
+
+    let mut fields: Vec<FieldsV2> = storage.iter().map(|entry| entry.unwrap().0.0).collect();
+    fields.sort();
+    assert_eq!(fields, vec![FieldsV2::Bairro, FieldsV2::Cep]);
+}
+
+#[test]
+fn test_bulk_load_appends_sorted_postings() {
+    let dir = tempdir().unwrap();
+    let postings = |doc_ids: &[u32]| {
+        let mut postings = Postings::new();
+        for doc_id in doc_ids {
+            postings.add_occurrence(DocId::new(*doc_id));
+        }
+        postings
+    };
+
+    let mut storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    let entries = vec![
+        (RecordField::Rua, "mauriti".to_string(), postings(&[0, 2])),
+        (RecordField::Rua, "pedreira".to_string(), postings(&[1])),
+        (RecordField::Municipio, "belem".to_string(), postings(&[0, 1, 2])),
+    ];
+    assert_eq!(storage.bulk_load(entries).unwrap(), 3);
+    assert_eq!(storage.generation().unwrap(), 1);
+
+    // Keys must keep growing, within one load and across loads
+    let unsorted = vec![
+        (RecordField::Municipio, "marituba".to_string(), postings(&[3])),
+        (RecordField::Municipio, "ananindeua".to_string(), postings(&[3])),
+    ];
+    assert!(matches!(
+        storage.bulk_load(unsorted),
+        Err(LmdbError::UnsortedKey(_))
+    ));
+    let existing = vec![(RecordField::Rua, "mauriti".to_string(), postings(&[3]))];
+    assert!(matches!(
+        storage.bulk_load(existing),
+        Err(LmdbError::UnsortedKey(_))
+    ));
+
+    let mut engine = SearchEngine::with_storage(storage);
+    engine.rebuild_metadata(|_| {}).unwrap();
+    assert_eq!(engine.metadata.get_df(&RecordField::Rua, "mauriti"), 2);
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Pedreira".to_string())],
+        ..Default::default()
+    };
+    let hits = engine.execute(query.clone(), query.blocking_k).unwrap();
+    assert_eq!(hits[0].doc_id, 1);
+}
+
+#[test]
+fn test_failed_bulk_load_keeps_committed_batches_resolvable() {
+    let dir = tempdir().unwrap();
+    let options = LmdbOptions::new().batch_size(2);
+    let mut storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+    let postings = |doc_id: u32| Postings::from_sorted([(DocId::new(doc_id), 1)]);
+
+    // Two batches go in before the unsorted key stops the load
+    let entries = vec![
+        (RecordField::Rua, "mauriti".to_string(), postings(0)),
+        (RecordField::Rua, "pedreira".to_string(), postings(1)),
+        (RecordField::Bairro, "marco".to_string(), postings(0)),
+        (RecordField::Bairro, "umarizal".to_string(), postings(1)),
+        (RecordField::Rua, "antonio".to_string(), postings(2)),
+    ];
+    assert!(matches!(storage.bulk_load(entries), Err(LmdbError::UnsortedKey(_))));
+    assert_eq!(storage.generation().unwrap(), 2);
+
+    // The files as a crash right after the load would leave them: no later
+    // flush wrote the id of the field first seen in the second batch
+    let copy = tempdir().unwrap();
+    std::fs::copy(dir.path().join("data.mdb"), copy.path().join("data.mdb")).unwrap();
+    let recovered = LmdbStorage::<RecordField>::open(copy.path()).unwrap();
+    assert_eq!(recovered.generation().unwrap(), 2);
+    assert!(recovered.get(RecordField::Bairro, "umarizal").unwrap().is_some());
+    assert!(recovered.get(RecordField::Rua, "antonio").unwrap().is_none());
+}
+
+#[test]
+fn test_provenance_travels_with_index() {
+    let dir = tempdir().unwrap();
+    let source = dir.path().join("enderecos.csv");
+    std::fs::write(&source, "id,rua\n1,Travessa Mauriti\n").unwrap();
+    let index_dir = dir.path().join("index");
+
+    let tokenizer_hash = {
+        let storage = LmdbStorage::<RecordField>::open(&index_dir).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        assert!(engine.provenance().unwrap().is_none());
+
+        engine.record_source(&source).unwrap();
+        engine
+            .index_record(
+                0,
+                &Record {
+                    rua: "Travessa Mauriti".into(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        let saved = engine.save_provenance().unwrap();
+        assert_eq!(saved.record_count, 1);
+        assert_eq!(saved.sources[0].bytes, 26);
+        saved.tokenizer_hash
+    };
+
+    let storage = LmdbStorage::<RecordField>::open(&index_dir).unwrap();
+    let mut engine = SearchEngine::with_storage(storage);
+    let provenance = engine.provenance().unwrap().unwrap();
+    assert_eq!(provenance.sources.len(), 1);
+    assert_eq!(provenance.crate_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(provenance.tokenizer_hash, tokenizer_hash);
+    assert_eq!(engine.tokenizer_hash().unwrap(), tokenizer_hash);
+
+    // Re-recording a changed file replaces its entry instead of adding one
+    std::fs::write(&source, "id,rua\n").unwrap();
+    engine.record_source(&source).unwrap();
+    let updated = engine.save_provenance().unwrap();
+    assert_eq!(updated.sources.len(), 1);
+    assert_ne!(updated.sources[0].hash, provenance.sources[0].hash);
+}
+
+#[test]
+fn test_flush_commits_metadata_with_postings() {
+    let dir = tempdir().unwrap();
+    let record = |rua: &str| Record {
+        rua: rua.into(),
+        ..Default::default()
+    };
+
+    let (df, length) = {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        assert!(!engine.load_committed_metadata().unwrap());
+        engine.index_record(0, &record("Travessa Mauriti")).unwrap();
+        engine.index_record(1, &record("Rua Mauriti")).unwrap();
+        engine.flush().unwrap();
+        (
+            engine.metadata.get_df(&RecordField::Rua, "mauriti"),
+            engine.metadata.doc_length(DocId::new(0), &RecordField::Rua),
+        )
+    };
+
+    {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        assert!(engine.load_committed_metadata().unwrap());
+        assert_eq!(engine.metadata.total_docs, 2);
+        assert_eq!(engine.metadata.get_df(&RecordField::Rua, "mauriti"), df);
+        assert_eq!(engine.metadata.doc_length(DocId::new(0), &RecordField::Rua), length);
+
+        // Postings committed without the metadata leave the snapshot behind
+        engine.index_record(2, &record("Avenida Mauriti")).unwrap();
+        PostingsStorage::flush(&mut engine.index.storage).unwrap();
+    }
+
+    let options = LmdbOptions::new().read_only(true);
+    let storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+    let mut replica = SearchEngine::with_storage(storage);
+    assert!(!replica.load_committed_metadata().unwrap());
+    assert_eq!(replica.metadata.total_docs, 0);
+
+    // The replica falls back to a scan
+    assert!(replica.refresh().unwrap());
+    assert_eq!(replica.metadata.total_docs, 3);
+    assert_eq!(
+        replica.metadata.get_df(&RecordField::Rua, "mauriti"),
+        df + 1
+    );
+}
+
+#[test]
+fn test_flush_grows_full_map_up_to_ceiling() {
+    let write = |options: LmdbOptions| {
+        let dir = tempdir().unwrap();
+        let mut storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+        for term in 0..500 {
+            let postings = Postings::from_sorted((0..2000).map(|doc_id| (DocId::new(doc_id), 1)));
+            storage.put(RecordField::Rua, format!("rua{}", term), postings).unwrap();
+        }
+        PostingsStorage::flush(&mut storage)?;
+        let postings = storage.get(RecordField::Rua, "rua499").unwrap().unwrap();
+        assert_eq!(postings.len(), 2000);
+        Ok::<_, LmdbError>(())
+    };
+
+    let small = LmdbOptions::new().map_size(1 << 20).batch_size(10_000);
+    assert!(write(small.clone()).is_err());
+    assert!(write(small.clone().max_map_size(64 << 20)).is_ok());
+    // A ceiling below what the data needs still fails
+    assert!(write(small.max_map_size(2 << 20)).is_err());
+}
+
+#[test]
+fn test_map_grows_while_other_threads_read() {
+    let dir = tempdir().unwrap();
+    let options = LmdbOptions::new()
+        .map_size(1 << 20)
+        .max_map_size(64 << 20)
+        .batch_size(10_000);
+    let storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+    let mut postings = Postings::new();
+    postings.add_occurrence(DocId::new(7));
+    storage.put_shared(RecordField::Rua, "mauriti".into(), postings).unwrap();
+    storage.flush().unwrap();
+
+    let snapshot = storage.snapshot().unwrap();
+    let done = std::sync::atomic::AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let reader = scope.spawn(|| {
+            let mut reads = 0;
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                let postings = storage.get(RecordField::Rua, "mauriti").unwrap().unwrap();
+                assert!(postings.contains(DocId::new(7)));
+                assert!(snapshot.contains(RecordField::Rua, "mauriti").unwrap());
+                storage.scan(|_, _, _| Ok::<_, String>(())).unwrap();
+                reads += 1;
+            }
+            reads
+        });
+
+        for term in 0..500 {
+            let postings = Postings::from_sorted((0..2000).map(|doc_id| (DocId::new(doc_id), 1)));
+            storage.put_shared(RecordField::Rua, format!("rua{}", term), postings).unwrap();
+        }
+        storage.flush().unwrap();
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        assert!(reader.join().unwrap() > 0);
+    });
+
+    assert_eq!(storage.get(RecordField::Rua, "rua499").unwrap().unwrap().len(), 2000);
+    assert!(!snapshot.contains(RecordField::Rua, "rua499").unwrap());
+}
+
+#[test]
+fn test_failed_flush_keeps_writes_staged() {
+    let dir = tempdir().unwrap();
+    let options = LmdbOptions::new().map_size(1 << 20).batch_size(10_000);
+    let mut storage = LmdbStorage::<RecordField>::open_with_options(dir.path(), options).unwrap();
+    for term in 0..500 {
+        let postings = Postings::from_sorted((0..2000).map(|doc_id| (DocId::new(doc_id), 1)));
+        storage.put(RecordField::Rua, format!("rua{}", term), postings).unwrap();
+    }
+    assert!(PostingsStorage::flush(&mut storage).is_err());
+    assert!(PostingsStorage::flush(&mut storage).is_err());
+
+    let postings = storage.get(RecordField::Rua, "rua499").unwrap().unwrap();
+    assert_eq!(postings.len(), 2000);
+}
+
+#[test]
+fn test_id_map_persists_and_deletes_by_external_id() {
+    let dir = tempdir().unwrap();
+    {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        for (doc_id, (id, numero)) in [("a-1", "31"), ("b-2", "31"), ("c-3", "12")].iter().enumerate() {
+            let record = Record { id: id.to_string(), rua: "Mauriti".into(), numero: numero.to_string(), ..Default::default() };
+            engine.index_record(doc_id, &record).unwrap();
+        }
+        engine.flush().unwrap();
+        assert_eq!(engine.doc_id_for("b-2"), Some(DocId::new(1)));
+        assert_eq!(engine.external_id_for(DocId::new(2)), Some("c-3"));
+
+        assert!(engine.delete_by_external_id("b-2").unwrap());
+        assert!(!engine.delete_by_external_id("b-2").unwrap());
+        assert_eq!(engine.metadata.total_docs, 2);
+        assert_eq!(engine.metadata.get_df(&RecordField::Numero, "31"), 1);
+        assert_eq!(engine.external_id_for(DocId::new(1)), None);
+    }
+
+    let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    let engine = SearchEngine::with_storage(storage);
+    assert_eq!(engine.doc_id_for("a-1"), Some(DocId::new(0)));
+    assert_eq!(engine.doc_id_for("b-2"), None);
+    assert_eq!(engine.external_id_for(DocId::new(2)), Some("c-3"));
+
+    let postings = engine.index.storage.get(RecordField::Rua, "mauriti").unwrap().unwrap();
+    assert!(postings.contains(DocId::new(0)));
+    assert!(!postings.contains(DocId::new(1)));
+}
+
+#[test]
+fn test_searcher_stays_on_pinned_generation() {
+    use lfas::cooccurrence::QueryExpansion;
+
+    let dir = tempdir().unwrap();
+    {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        engine.index_record(0, &Record { rua: "Travessa Mauriti".into(), numero: "31".into(), ..Default::default() }).unwrap();
+        engine.index_record(1, &Record { rua: "Rua Mauriti".into(), numero: "12".into(), ..Default::default() }).unwrap();
+        engine.flush().unwrap();
+    }
+    // Reopened, so stored fields are read from the storage rather than the doc store
+    let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    let mut engine = SearchEngine::with_storage(storage);
+    engine.refresh().unwrap();
+    engine.build_spell_index(1);
+    engine.auto_correct = true;
+    engine.build_cooccurrence().unwrap();
+    engine.expansion = Some(QueryExpansion { min_count: 1, ..Default::default() });
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let typo = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauritti Travessa".to_string())],
+        ..Default::default()
+    };
+    let hits = |engine_hits: Vec<lfas::SearchHit>| -> Vec<(DocId, f32)> {
+        engine_hits.iter().map(|hit| (hit.doc_id, hit.score)).collect()
+    };
+
+    let searcher = engine.searcher().unwrap();
+    let generation = searcher.index.storage.generation().unwrap();
+    assert_eq!(engine.index.storage.pinned_generations().unwrap(), vec![generation]);
+    let pinned = hits(searcher.execute(query.clone(), query.blocking_k).unwrap());
+    assert_eq!(pinned.len(), 2);
+    // Spelling and expansion carry over
+    let corrected = hits(searcher.execute(typo.clone(), typo.blocking_k).unwrap());
+    assert_eq!(corrected, hits(engine.execute(typo.clone(), typo.blocking_k).unwrap()));
+
+    // Indexing goes on: an existing term grows, new terms appear and a stored value changes
+    engine.index_record(2, &Record { rua: "Mauriti Pedreira".into(), ..Default::default() }).unwrap();
+    engine.update_field(DocId::new(0), RecordField::Numero, "99").unwrap();
+    engine.flush().unwrap();
+    assert_eq!(engine.execute(query.clone(), query.blocking_k).unwrap().len(), 3);
+
+    assert_eq!(hits(searcher.execute(query.clone(), query.blocking_k).unwrap()), pinned);
+    assert_eq!(hits(searcher.execute(typo.clone(), typo.blocking_k).unwrap()), corrected);
+    assert_eq!(searcher.metadata.total_docs, 2);
+    assert!(engine.index.storage.get(RecordField::Rua, "pedreira").unwrap().is_some());
+    assert!(searcher.index.storage.get(RecordField::Rua, "pedreira").unwrap().is_none());
+    assert!(searcher.index.storage.iter().count() < engine.index.storage.iter().count());
+
+    let numero = |hits: Vec<(lfas::SearchHit, std::collections::HashMap<RecordField, String>)>| {
+        let (_, doc) = hits.into_iter().find(|(hit, _)| hit.doc_id == DocId::new(0)).unwrap();
+        doc[&RecordField::Numero].clone()
+    };
+    assert_eq!(numero(searcher.search_with_fields(query.clone(), &[RecordField::Numero]).unwrap()), "31");
+    assert_eq!(numero(engine.search_with_fields(query.clone(), &[RecordField::Numero]).unwrap()), "99");
+
+    drop(searcher);
+    assert!(engine.index.storage.pinned_generations().unwrap().is_empty());
+}
+
+#[test]
+fn test_stored_fields_are_read_per_column_after_reopen() {
+    let dir = tempdir().unwrap();
+    {
+        let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        for (doc_id, (rua, numero)) in [("Mauriti", "31"), ("Mauriti", "12"), ("Pedro Miranda", "7")].iter().enumerate() {
+            let record = Record { rua: rua.to_string(), numero: numero.to_string(), municipio: "Belém".into(), bairro: "Marco".into(), ..Default::default() };
+            engine.index_record(doc_id, &record).unwrap();
+        }
+        engine.update_field(DocId::new(1), RecordField::Numero, "14").unwrap();
+        engine.flush().unwrap();
+    }
+
+    let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    let columns = storage.read_stored(&[DocId::new(0), DocId::new(1), DocId::new(9)], &[RecordField::Numero]).unwrap();
+    assert_eq!(columns[0].get(&RecordField::Numero).map(String::as_str), Some("31"));
+    assert_eq!(columns[1].get(&RecordField::Numero).map(String::as_str), Some("14"));
+    assert!(columns[2].is_empty());
+    assert_eq!(storage.stored_column(RecordField::Bairro).unwrap().len(), 3);
+
+    let mut engine = SearchEngine::with_storage(storage);
+    engine.refresh().unwrap();
+    assert!(engine.docs.is_empty());
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        top_k: 5,
+        ..Default::default()
+    };
+    let fields = [RecordField::Municipio, RecordField::Rua, RecordField::Numero];
+    let hits = engine.search_with_fields(query, &fields).unwrap();
+    assert_eq!(hits.len(), 2);
+    for (hit, doc) in &hits {
+        assert_eq!(doc.len(), 3, "doc {}", hit.doc_id);
+        assert_eq!(doc[&RecordField::Municipio], "Belém");
+        assert!(!doc.contains_key(&RecordField::Bairro));
+    }
+}
+
+#[test]
+fn test_engine_builder_applies_settings_over_saved_config() {
+    use lfas::engine_builder::EngineBuilder;
+    use lfas::error::LfasError;
+    use lfas::tokenizer::TokenizerConfig;
+
+    let dir = tempdir().unwrap();
+    {
+        let mut engine = EngineBuilder::new(dir.path())
+            .k1(1.5)
+            .field_weights([(RecordField::Rua, 7.0)])
+            .tokenizer(TokenizerConfig::sliding(3, 1))
+            .build()
+            .unwrap();
+        engine.index_record(0, &Record { rua: "Mauriti".into(), ..Default::default() }).unwrap();
+        engine.save_config().unwrap();
+    }
+
+    let engine = EngineBuilder::new(dir.path())
+        .schema([RecordField::Rua, RecordField::Numero, RecordField::Nome])
+        .field_weights([(RecordField::Numero, 12.0)])
+        .build()
+        .unwrap();
+    assert_eq!(engine.scorer.k1, 1.5);
+    assert_eq!(engine.tokenizer, TokenizerConfig::sliding(3, 1));
+    assert_eq!(engine.scorer.field_weights.len(), 3);
+    assert_eq!(engine.scorer.field_weights[&RecordField::Rua], 7.0);
+    assert_eq!(engine.scorer.field_weights[&RecordField::Numero], 12.0);
+    assert!(!engine.scorer.field_b.contains_key(&RecordField::Cep));
+    drop(engine);
+
+    let outside = EngineBuilder::new(dir.path())
+        .schema([RecordField::Rua])
+        .field_b([(RecordField::Bairro, 0.5)])
+        .build();
+    assert!(matches!(outside, Err(LfasError::Schema(_))));
+    assert!(EngineBuilder::new(dir.path()).k1(-1.0).build().is_err());
+
+    // The index keeps the tokenizer it was built with
+    let retokenized = EngineBuilder::new(dir.path())
+        .tokenizer(TokenizerConfig::default())
+        .build();
+    assert!(matches!(retokenized, Err(LfasError::Schema(_))));
+    let same = EngineBuilder::new(dir.path())
+        .tokenizer(TokenizerConfig::sliding(3, 1))
+        .build();
+    assert!(same.is_ok());
+}
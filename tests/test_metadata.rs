@@ -1,4 +1,11 @@
+use lfas::engine::SearchEngine;
 use lfas::metadata::FieldMetadata;
+use lfas::storage::{
+    InMemoryStorage, LmdbMetadataStore, LmdbOptions, LmdbStorage, PostingsStorage,
+};
+use lfas::{DocId, Record, RecordField, StructuredQuery};
+use std::sync::Arc;
+use tempfile::tempdir;
 
 #[derive(Hash, Eq, PartialEq, Clone, Copy, Ord, PartialOrd, Debug)]
 enum AddressField {
@@ -9,7 +16,7 @@ enum AddressField {
 #[test]
 fn test_field_metadata_tracking() {
     let mut meta = FieldMetadata::<AddressField>::new();
-    let doc_id = 101;
+    let doc_id = DocId::new(101);
 
     let fields = vec![
         (AddressField::Street, vec!["rua", "augusta"]),
@@ -31,3 +38,155 @@ fn test_field_metadata_tracking() {
     assert_eq!(meta.lengths[&doc_id][&AddressField::Street], 2);
     assert_eq!(meta.total_field_lengths[&AddressField::Neighborhood], 1);
 }
+
+#[test]
+fn test_spilled_metadata_scores_the_same() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let streets = ["Rua Mauriti", "Rua Pedreira", "Travessa Mauriti"];
+    for (doc_id, rua) in streets.iter().enumerate() {
+        let record = Record {
+            rua: rua.to_string(),
+            municipio: "Belém".into(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    let query = || StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let before = engine.execute(query(), 10).unwrap();
+    let df = engine.metadata.get_df(&RecordField::Rua, "mauriti");
+    let length = engine.metadata.doc_length(DocId::new(0), &RecordField::Rua);
+    assert!(df > 0 && length > 0);
+
+    let dir = tempdir().unwrap();
+    let store = LmdbMetadataStore::open(dir.path(), LmdbOptions::new()).unwrap();
+    engine.metadata.spill_to(Arc::new(store)).unwrap();
+    assert!(engine.metadata.term_df.is_empty());
+    assert!(engine.metadata.lengths.is_empty());
+    assert_eq!(engine.metadata.get_df(&RecordField::Rua, "mauriti"), df);
+    assert_eq!(engine.metadata.doc_length(DocId::new(0), &RecordField::Rua), length);
+
+    let after = engine.execute(query(), 10).unwrap();
+    assert_eq!(before.len(), after.len());
+    for (a, b) in before.iter().zip(&after) {
+        assert_eq!(a.doc_id, b.doc_id);
+        assert!((a.score - b.score).abs() < 1e-6);
+    }
+
+    // New documents start from the stored df
+    let record = Record {
+        rua: "Mauriti".into(),
+        ..Default::default()
+    };
+    engine.index_record(3, &record).unwrap();
+    assert_eq!(engine.metadata.get_df(&RecordField::Rua, "mauriti"), df + 1);
+}
+
+#[test]
+fn test_length_columns_replace_in_memory_lengths() {
+    let dir = tempdir().unwrap();
+    let storage = LmdbStorage::<RecordField>::open(dir.path()).unwrap();
+    let mut engine = SearchEngine::with_storage(storage);
+    assert!(!engine.use_length_columns().unwrap());
+
+    let streets = [
+        "Rua Mauriti",
+        "Avenida Almirante Barroso Mauriti Quadra Sete Lote Doze",
+        "Travessa Mauriti",
+    ];
+    for (doc_id, rua) in streets.iter().enumerate() {
+        let record = Record {
+            rua: rua.to_string(),
+            municipio: if doc_id == 1 {
+                String::new()
+            } else {
+                "Belém".into()
+            },
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    PostingsStorage::flush(&mut engine.index.storage).unwrap();
+
+    let query = || StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let before = engine.execute(query(), 10).unwrap();
+    let lengths: Vec<_> = (0..streets.len() as u32)
+        .map(|doc_id| {
+            (
+                engine.metadata.doc_length(DocId::new(doc_id), &RecordField::Rua),
+                engine.metadata.doc_length(DocId::new(doc_id), &RecordField::Municipio),
+            )
+        })
+        .collect();
+
+    assert_eq!(
+        engine.persist_length_columns().unwrap(),
+        RecordField::ALL.len()
+    );
+    assert!(engine.use_length_columns().unwrap());
+    assert!(engine.metadata.lengths.is_empty());
+    for (doc_id, &(rua, municipio)) in lengths.iter().enumerate() {
+        let doc_id = DocId::new(doc_id as u32);
+        assert_eq!(engine.metadata.doc_length(doc_id, &RecordField::Rua), rua);
+        assert_eq!(
+            engine.metadata.doc_length(doc_id, &RecordField::Municipio),
+            municipio
+        );
+    }
+    assert_eq!(engine.metadata.doc_length(DocId::new(99), &RecordField::Rua), 0);
+
+    let after = engine.execute(query(), 10).unwrap();
+    assert_eq!(before.len(), after.len());
+    for (a, b) in before.iter().zip(&after) {
+        assert_eq!(a.doc_id, b.doc_id);
+        assert!((a.score - b.score).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn test_length_percentiles_and_clipping() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let long = "Casa Fundos Bloco Norte Sala Vinte Andar Terreo Portao Verde Lado Direito Galpao";
+    for doc_id in 0..10 {
+        let record = Record {
+            complemento: if doc_id == 9 { long } else { "Casa Azul" }.into(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    let short = engine.metadata.doc_length(DocId::new(0), &RecordField::Complemento);
+    let longest = engine.metadata.doc_length(DocId::new(9), &RecordField::Complemento);
+    assert!(longest > short);
+
+    let stats = engine.metadata.length_stats()[&RecordField::Complemento];
+    assert_eq!(stats.docs, 10);
+    assert_eq!((stats.p50, stats.p90, stats.p99, stats.max), (short, short, longest, longest));
+    assert!(!engine.metadata.length_stats().contains_key(&RecordField::Rua));
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Complemento, "Casa".to_string())],
+        ..Default::default()
+    };
+    let score_of = |hits: &[lfas::SearchHit], doc_id: u32| {
+        hits.iter().find(|hit| hit.doc_id == doc_id).unwrap().score
+    };
+    let hits = engine.execute(query.clone(), 10).unwrap();
+    assert!(score_of(&hits, 9) < score_of(&hits, 0));
+
+    // Clipped at p90, the long value is scored at the common length
+    engine.scorer.length_clip = Some(0.9);
+    let clipped = engine.execute(query, 10).unwrap();
+    assert!((score_of(&clipped, 9) - score_of(&clipped, 0)).abs() < 1e-6);
+    assert!((score_of(&clipped, 0) - score_of(&hits, 0)).abs() < 1e-6);
+
+    engine
+        .update_field(DocId::new(9), RecordField::Complemento, "Casa Azul")
+        .unwrap();
+    let stats = engine.metadata.length_stats()[&RecordField::Complemento];
+    assert_eq!((stats.docs, stats.max), (10, short));
+}
@@ -0,0 +1,205 @@
+use lfas::admission::ConcurrencyLimits;
+use lfas::engine::SearchEngine;
+use lfas::error::LfasError;
+use lfas::metrics::Histogram;
+use lfas::slowlog::SlowQueryLog;
+use lfas::storage::InMemoryStorage;
+use lfas::{Record, RecordField, StructuredQuery};
+use std::time::Duration;
+
+#[test]
+fn test_histogram_buckets_and_quantiles() {
+    let mut histogram = Histogram::new(&[1.0, 10.0, 100.0]);
+    for value in [0.5, 1.0, 5.0, 50.0, 500.0] {
+        histogram.observe(value);
+    }
+
+    assert_eq!(histogram.counts, vec![2, 1, 1, 1]);
+    assert_eq!(histogram.count, 5);
+    assert_eq!(histogram.quantile(0.4), 1.0);
+    assert_eq!(histogram.quantile(0.6), 10.0);
+    assert!(histogram.quantile(1.0).is_infinite());
+    assert_eq!(
+        histogram.cumulative(),
+        vec![(1.0, 2), (10.0, 3), (100.0, 4), (f64::INFINITY, 5)]
+    );
+}
+
+#[test]
+fn test_engine_records_query_and_index_metrics() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in ["Mauriti", "Pedreira"].into_iter().enumerate() {
+        let record = Record {
+            rua: rua.into(),
+            numero: "31".into(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Numero, "31".to_string())],
+        ..Default::default()
+    };
+    engine.execute(query.clone(), query.blocking_k).unwrap();
+
+    let metrics = engine.metrics();
+    assert_eq!(metrics.queries_total, 1);
+    assert_eq!(metrics.queries_interrupted, 0);
+    assert_eq!(metrics.query_latency_ms.count, 1);
+    assert_eq!(metrics.candidate_set_size.sum, 2.0);
+    assert_eq!(metrics.postings_hit_rate(), 1.0);
+    assert_eq!(metrics.docs_indexed, 2);
+    assert_eq!(metrics.index_batches, 2);
+
+    let text = metrics.to_prometheus();
+    assert!(text.contains("lfas_queries_total 1"));
+    assert!(text.contains("lfas_candidate_set_size_bucket{le=\"10\"} 1"));
+    assert!(text.contains("lfas_query_latency_ms_bucket{le=\"+Inf\"} 1"));
+
+    engine.metrics.reset();
+    assert_eq!(engine.metrics().queries_total, 0);
+}
+
+#[test]
+fn test_searches_past_the_queue_are_rejected() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let record = Record {
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    engine.index_record(0, &record).unwrap();
+    engine.set_concurrency_limits(Some(ConcurrencyLimits {
+        max_concurrent: 1,
+        max_queued: 0,
+    }));
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Numero, "31".to_string())],
+        ..Default::default()
+    };
+    let admission = engine.admission.as_ref().unwrap();
+    let held = admission.acquire().unwrap();
+    let result = engine.execute(query.clone(), query.blocking_k);
+    assert!(matches!(result, Err(LfasError::Overloaded(_))));
+    assert_eq!(admission.running(), 1);
+
+    drop(held);
+    assert_eq!(
+        engine
+            .execute(query.clone(), query.blocking_k)
+            .unwrap()
+            .len(),
+        1
+    );
+    assert_eq!(admission.running(), 0);
+
+    let metrics = engine.metrics();
+    assert_eq!(metrics.queries_rejected, 1);
+    assert_eq!(metrics.queries_total, 1);
+    assert_eq!(metrics.queue_wait_ms.count, 1);
+    assert!(
+        metrics
+            .to_prometheus()
+            .contains("lfas_queries_rejected_total 1")
+    );
+}
+
+#[test]
+fn test_slow_query_log_keeps_stats_without_text() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in ["Mauriti", "Pedreira", "Mauriti Lopes"]
+        .into_iter()
+        .enumerate()
+    {
+        let record = Record {
+            rua: rua.into(),
+            numero: "31".into(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    let query = |rua: &str| StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, rua.to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+    assert!(engine.slow_queries().is_empty());
+
+    // Every search is slower than zero; the oldest fall out
+    engine.slow_log = Some(SlowQueryLog::new(Duration::ZERO).with_capacity(2));
+    for rua in ["Pedreira", "Mauriti", "Lopes"] {
+        engine.execute(query(rua), 0).unwrap();
+    }
+    let logged = engine.slow_queries();
+    assert_eq!(logged.len(), 2);
+    let first = &logged[0];
+    assert!(!first.sampled);
+    assert_eq!(first.hits, 3);
+    assert_eq!(first.candidates, 3);
+    assert!(first.total_ms >= first.round1_ms);
+    assert_eq!(first.fields[0].field, "Rua");
+    assert_eq!(first.fields[0].chars, 7);
+    assert_eq!(first.fields[0].max_df, 2);
+    assert_eq!(first.fields[1].max_df, 3);
+    assert!(!format!("{:?}", logged).contains("auriti"));
+
+    // Fast searches only get in as every second sample
+    engine.slow_log = Some(SlowQueryLog::new(Duration::from_secs(60)).with_sample_every(2));
+    for _ in 0..4 {
+        engine.execute_iter(query("Mauriti")).unwrap();
+    }
+    let sampled = engine.slow_queries();
+    assert_eq!(sampled.len(), 2);
+    assert!(sampled.iter().all(|entry| entry.sampled));
+    engine.slow_log.as_ref().unwrap().clear();
+    assert!(engine.slow_queries().is_empty());
+}
+
+#[test]
+fn test_verbosity_is_scoped_to_the_search() {
+    use lfas::verbosity::{self, Verbosity};
+
+    assert_eq!(Verbosity::from_name("Summary"), Some(Verbosity::Summary));
+    assert_eq!(Verbosity::from_name("loud"), None);
+    assert_eq!(verbosity::current(), Verbosity::Debug);
+    {
+        let _outer = verbosity::scoped(Verbosity::Summary);
+        {
+            let _inner = verbosity::scoped(Verbosity::Silent);
+            assert_eq!(verbosity::current(), Verbosity::Silent);
+        }
+        assert_eq!(verbosity::current(), Verbosity::Summary);
+    }
+    assert_eq!(verbosity::current(), Verbosity::Debug);
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let record = Record {
+        rua: "Mauriti".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    engine.index_record(0, &record).unwrap();
+    engine.verbosity = Verbosity::Silent;
+    engine.slow_log = Some(SlowQueryLog::new(Duration::ZERO));
+
+    // Silent searches still answer, count and reach the slow-query log
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        verbosity: Some(Verbosity::Summary),
+        ..Default::default()
+    };
+    let results = engine.execute_interruptible(query.clone()).unwrap();
+    assert_eq!(results.hits.len(), 1);
+    let hits: Vec<_> = engine
+        .execute_iter(StructuredQuery { verbosity: None, ..query })
+        .unwrap()
+        .collect();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(engine.slow_queries().len(), 2);
+    assert_eq!(engine.metrics().queries_total, 2);
+    assert_eq!(verbosity::current(), Verbosity::Debug);
+}
@@ -1,51 +1,105 @@
+use lfas::DocId;
 use lfas::postings::Postings;
 
+fn entries(postings: impl Iterator<Item = (DocId, u32)>) -> Vec<(u32, u32)> {
+    postings.map(|(doc_id, tf)| (doc_id.get(), tf)).collect()
+}
+
+fn from_sorted<const N: usize>(entries: [(u32, u32); N]) -> Postings {
+    Postings::from_sorted(entries.map(|(doc_id, tf)| (DocId::new(doc_id), tf)))
+}
+
 #[test]
 fn test_new_postings_is_empty() {
     let postings = Postings::new();
     assert_eq!(postings.len(), 0);
-    assert!(postings.frequencies.is_empty());
+    assert!(postings.frequencies().is_empty());
 }
 
 #[test]
 fn test_add_single_doc() {
     let mut postings = Postings::new();
-    let doc_id = 42;
+    let doc_id = DocId::new(42);
 
-    postings.add_doc(doc_id);
+    postings.add_occurrence(doc_id);
 
     assert!(postings.contains(doc_id));
     assert_eq!(postings.len(), 1);
-    assert_eq!(postings.frequencies.get(&doc_id), Some(&1));
+    assert_eq!(postings.tf(doc_id), 1);
 }
 
 #[test]
 fn test_add_multiple_occurrences_same_doc() {
     let mut postings = Postings::new();
-    let doc_id = 10;
+    let doc_id = DocId::new(10);
 
-    postings.add_doc(doc_id);
-    postings.add_doc(doc_id);
-    postings.add_doc(doc_id);
+    postings.add_occurrence(doc_id);
+    postings.add_occurrence(doc_id);
+    postings.add_occurrence(doc_id);
 
     assert_eq!(postings.len(), 1);
-    assert_eq!(postings.frequencies.get(&doc_id), Some(&3));
+    assert_eq!(postings.tf(doc_id), 3);
 }
 
 #[test]
 fn test_add_different_documents() {
     let mut postings = Postings::new();
-    postings.add_doc(1);
-    postings.add_doc(2);
+    postings.add_occurrence(DocId::new(1));
+    postings.add_occurrence(DocId::new(2));
 
     assert_eq!(postings.len(), 2);
-    assert!(postings.contains(1));
-    assert!(postings.contains(2));
-    assert_eq!(postings.frequencies.len(), 2);
+    assert!(postings.contains(DocId::new(1)));
+    assert!(postings.contains(DocId::new(2)));
+    assert_eq!(postings.frequencies().len(), 2);
 }
 
 #[test]
 fn test_contains_non_existent_doc() {
     let postings = Postings::new();
-    assert!(!postings.contains(999));
+    assert!(!postings.contains(DocId::new(999)));
+}
+
+#[test]
+fn test_frequencies_follow_doc_order() {
+    let mut postings = Postings::new();
+    for doc_id in [7, 2, 7, 40, 2, 7] {
+        postings.add_occurrence(DocId::new(doc_id));
+    }
+
+    assert_eq!(postings.frequencies(), &[2, 3, 1]);
+    assert_eq!(entries(postings.iter()), vec![(2, 2), (7, 3), (40, 1)]);
+    assert_eq!(postings.tf(DocId::new(7)), 3);
+    assert_eq!(postings.tf(DocId::new(8)), 0);
+
+    let other = from_sorted([(1, 4), (7, 1), (50, 2)]);
+    postings.merge(other);
+    assert_eq!(
+        entries(postings.iter()),
+        vec![(1, 4), (2, 2), (7, 4), (40, 1), (50, 2)]
+    );
+}
+
+#[test]
+fn test_iter_within_candidates() {
+    let postings = from_sorted([(1, 1), (5, 3), (9, 2), (12, 1)]);
+    let candidates: roaring::RoaringBitmap = [5u32, 9, 10].into_iter().collect();
+
+    assert_eq!(entries(postings.iter_within(&candidates)), vec![(5, 3), (9, 2)]);
+    assert_eq!(postings.iter_within(&roaring::RoaringBitmap::new()).count(), 0);
+}
+
+#[test]
+fn test_intersect_iter_walks_or_gallops_in_doc_order() {
+    let postings = Postings::from_sorted((0..1000u32).map(|i| (DocId::new(i * 3), i % 7 + 1)));
+
+    // Dense intersection: walked alongside the postings
+    let dense: roaring::RoaringBitmap = (0..3000u32).step_by(2).collect();
+    let walked: Vec<_> = postings.intersect_iter(&dense).collect();
+    assert_eq!(walked.len(), 500);
+    assert!(walked.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    assert!(walked.iter().all(|&(doc_id, tf)| tf == postings.tf(doc_id)));
+
+    // Sparse intersection: each tf found by rank
+    let sparse: roaring::RoaringBitmap = [3u32, 2997, 4000].into_iter().collect();
+    assert_eq!(entries(postings.intersect_iter(&sparse)), vec![(3, 2), (2997, 6)]);
 }
@@ -0,0 +1,75 @@
+"""Tests for the Python bindings.
+
+Run with `make test-python`, which builds the module into the current
+environment first.
+"""
+
+import tempfile
+import threading
+import unittest
+
+from lfas import PySearchEngine
+
+
+class TestConcurrentIndexing(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.engine = PySearchEngine(path=self.dir.name)
+
+    def tearDown(self):
+        del self.engine
+        self.dir.cleanup()
+
+    def test_search_complex_runs_while_index_batch_runs(self):
+        self.engine.index_batch([(0, {"rua": "Mauriti", "numero": "31"})])
+        records = [
+            (doc_id, {"rua": f"Pedreira {doc_id}", "numero": str(doc_id), "municipio": "Belem"})
+            for doc_id in range(1, 50_001)
+        ]
+
+        indexing = threading.Thread(target=self.engine.index_batch, args=(records,))
+        hits, errors = [], []
+
+        def search():
+            # index_batch releases the GIL, so this loop gets to run before it returns
+            while indexing.is_alive():
+                try:
+                    hits.append(self.engine.search_complex({"rua": "Mauriti"}, 10, 100))
+                except Exception as e:
+                    errors.append(e)
+                    return
+
+        searching = threading.Thread(target=search)
+        indexing.start()
+        searching.start()
+        indexing.join()
+        searching.join()
+
+        self.assertEqual(errors, [])
+        self.assertTrue(hits)
+        self.assertEqual(self.engine.search_complex({"numero": "50000"}, 1, 100)[0][0], 50_000)
+
+
+
+class TestReloadConfig(unittest.TestCase):
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.engine = PySearchEngine(path=self.dir.name)
+
+    def tearDown(self):
+        del self.engine
+        self.dir.cleanup()
+
+    def test_reloaded_synonyms_change_hits(self):
+        self.engine.index_batch([(0, {"bairro": "Umarizal"}), (1, {"bairro": "Marco"})])
+        self.assertEqual(self.engine.search_complex({"bairro": "Centro"}, 10, 100), [])
+
+        self.engine.reload_config({"synonyms": {"centro": ["umarizal"]}})
+        hits = self.engine.search_complex({"bairro": "Centro"}, 10, 100)
+        self.assertEqual([hit[0] for hit in hits], [0])
+
+        with self.assertRaises(ValueError):
+            self.engine.reload_config({"synonyms": {"centro": ["cidade velha"]}})
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,156 @@
+use lfas::RecordField::{Complemento, Estado, Municipio, Rua};
+use lfas::engine::{QueryPreset, SearchEngine};
+use lfas::error::LfasError;
+use lfas::query::Query;
+use lfas::storage::InMemoryStorage;
+use lfas::{MinShouldMatch, Occur, Record, RecordField, StructuredQuery};
+
+#[test]
+fn test_builder_produces_structured_query() {
+    let query = Query::new()
+        .field(Rua, "mauriti")
+        .filter(Estado, "PA")
+        .top_k(10)
+        .min_should_match(MinShouldMatch::Count(1))
+        .fuzzy(true)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        query,
+        StructuredQuery {
+            fields: vec![(Rua, "mauriti".to_string())],
+            filters: vec![(Estado, "PA".to_string())],
+            top_k: 10,
+            min_should_match: Some(MinShouldMatch::Count(1)),
+            preset: Some(QueryPreset::Fuzzy),
+            ..Default::default()
+        }
+    );
+    assert_eq!(Query::new().field(Rua, "x").fuzzy(false).build().unwrap().preset, None);
+}
+
+#[test]
+fn test_builder_rejects_invalid_input() {
+    let invalid = |result: Result<StructuredQuery<_>, LfasError>| {
+        matches!(result, Err(LfasError::InvalidQuery(_)))
+    };
+
+    assert!(invalid(Query::new().filter(Estado, "PA").build()));
+    assert!(invalid(Query::new().field(Rua, "  ").build()));
+    assert!(invalid(Query::new().field(Rua, "mauriti").filter(Estado, "").build()));
+    assert!(invalid(Query::new().field(Rua, "mauriti").top_k(0).build()));
+    assert!(invalid(
+        Query::new()
+            .field(Rua, "mauriti")
+            .min_should_match(MinShouldMatch::Percent(150.0))
+            .build()
+    ));
+    // An external id alone is enough
+    assert!(Query::<lfas::RecordField>::new().external_id("101").build().is_ok());
+}
+
+#[test]
+fn test_filters_narrow_candidates_without_scoring() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), estado: "PA".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), estado: "SP".into(), ..Default::default() },
+        Record { rua: "Pedreira".into(), estado: "PA".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let unfiltered = Query::new().field(Rua, "Mauriti").build().unwrap();
+    let filtered = Query::new().field(Rua, "Mauriti").filter(Estado, "PA").build().unwrap();
+
+    let all = engine.execute(unfiltered.clone(), unfiltered.blocking_k).unwrap();
+    let hits = engine.execute(filtered.clone(), filtered.blocking_k).unwrap();
+    assert_eq!(all.len(), 2);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].doc_id, 0);
+    // The filter clause adds nothing to the score
+    let unfiltered_score = all.iter().find(|hit| hit.doc_id == 0).unwrap().score;
+    assert!((hits[0].score - unfiltered_score).abs() < 1e-6);
+}
+
+#[test]
+fn test_must_and_must_not_clauses() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), municipio: "Belem".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), municipio: "Ananindeua".into(), ..Default::default() },
+        Record { rua: "Pedreira".into(), municipio: "Belem".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+    let doc_ids = |query: StructuredQuery<_>| {
+        let mut ids: Vec<_> = engine
+            .execute(query.clone(), query.blocking_k)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.doc_id)
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    let should = Query::new().field(Rua, "Mauriti").field(Municipio, "Belem").build().unwrap();
+    assert_eq!(doc_ids(should), vec![0, 1, 2]);
+    let must = Query::new().must(Rua, "Mauriti").field(Municipio, "Belem").build().unwrap();
+    assert_eq!(must.occur, vec![Occur::Must]);
+    assert_eq!(doc_ids(must), vec![0, 1]);
+
+    // Same street, another city
+    let elsewhere = Query::new().field(Rua, "Mauriti").must_not(Municipio, "Belem").build().unwrap();
+    assert_eq!(elsewhere.occur, vec![Occur::Should, Occur::MustNot]);
+    let hits = engine.execute(elsewhere.clone(), elsewhere.blocking_k).unwrap();
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].doc_id, 1);
+    // The excluded clause adds nothing to the score
+    let street = Query::new().field(Rua, "Mauriti").build().unwrap();
+    let street_hits = engine.execute(street.clone(), street.blocking_k).unwrap();
+    let street_score = street_hits.iter().find(|hit| hit.doc_id == 1).unwrap().score;
+    assert!((hits[0].score - street_score).abs() < 1e-6);
+
+    // Exclusions alone have nothing to search for
+    assert!(Query::new().must_not(Municipio, "Belem").build().is_err());
+    let dangling: StructuredQuery<RecordField> = StructuredQuery { occur: vec![Occur::Must], ..Default::default() };
+    assert!(dangling.validate(&engine.limits).is_err());
+}
+
+#[test]
+fn test_presence_clauses_split_on_empty_fields() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    let records = [
+        Record { rua: "Mauriti".into(), complemento: "Apto 101".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), ..Default::default() },
+        Record { rua: "Mauriti".into(), complemento: "  ".into(), ..Default::default() },
+    ];
+    for (doc_id, record) in records.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+
+    let doc_ids = |engine: &SearchEngine<RecordField, InMemoryStorage<RecordField>>,
+                   query: StructuredQuery<RecordField>| {
+        let mut ids: Vec<_> = engine
+            .execute(query.clone(), query.blocking_k)
+            .unwrap()
+            .iter()
+            .map(|hit| hit.doc_id)
+            .collect();
+        ids.sort();
+        ids
+    };
+    let empty = Query::new().field(Rua, "Mauriti").is_empty(Complemento).build().unwrap();
+    let not_empty = Query::new().field(Rua, "Mauriti").not_empty(Complemento).build().unwrap();
+    assert_eq!(doc_ids(&engine, empty.clone()), vec![1, 2]);
+    assert_eq!(doc_ids(&engine, not_empty.clone()), vec![0]);
+
+    // A rebuild from the postings derives the same bitmaps
+    engine.rebuild_metadata(|_| {}).unwrap();
+    assert_eq!(doc_ids(&engine, empty), vec![1, 2]);
+    assert_eq!(doc_ids(&engine, not_empty), vec![0]);
+}
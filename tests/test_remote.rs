@@ -0,0 +1,84 @@
+#![cfg(feature = "remote")]
+
+use lfas::engine::SearchEngine;
+use lfas::remote::{MANIFEST_FILE, pack, verify_manifest};
+use lfas::storage::LmdbStorage;
+use lfas::{Record, RecordField, StructuredQuery};
+use std::fs;
+use tempfile::tempdir;
+
+fn build_archive(dir: &std::path::Path) -> std::path::PathBuf {
+    let index = dir.join("index");
+    {
+        let storage = LmdbStorage::<RecordField>::open(&index).unwrap();
+        let mut engine = SearchEngine::with_storage(storage);
+        let record = Record {
+            rua: "Mauriti".into(),
+            numero: "31".into(),
+            ..Default::default()
+        };
+        engine.index_record(0, &record).unwrap();
+        engine.save_config().unwrap();
+    }
+
+    let archive = dir.join("index.tar.zst");
+    pack(&index, &archive).unwrap();
+    archive
+}
+
+#[test]
+fn test_open_uri_unpacks_verifies_and_caches() {
+    let dir = tempdir().unwrap();
+    let archive = build_archive(dir.path());
+    let cache = dir.path().join("cache");
+
+    let uri = format!("file://{}", archive.display());
+    let engine = SearchEngine::open_uri_with_cache(&uri, &cache).unwrap();
+    assert!(engine.index.storage.is_read_only());
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Mauriti".to_string())],
+        ..Default::default()
+    };
+    let hits = engine.execute(query, 10).unwrap();
+    assert_eq!(hits[0].doc_id, 0);
+
+    drop(engine);
+
+    // The same archive version is served from the cache
+    SearchEngine::open_uri_with_cache(&uri, &cache).unwrap();
+    let entries: Vec<_> = fs::read_dir(&cache).unwrap().collect();
+    assert_eq!(entries.len(), 1);
+    assert!(verify_manifest(&entries[0].as_ref().unwrap().path()).is_ok());
+}
+
+#[test]
+fn test_open_uri_rejects_bad_archives() {
+    let dir = tempdir().unwrap();
+    let index = dir.path().join("index");
+    build_archive(dir.path());
+    let cache = dir.path().join("cache");
+
+    // Archive the index with a manifest that no longer matches the data
+    let manifest = fs::read_to_string(index.join(MANIFEST_FILE)).unwrap();
+    let tampered = format!("{:016x}{}", 0, &manifest[16..]);
+    fs::write(index.join(MANIFEST_FILE), tampered).unwrap();
+    assert!(verify_manifest(&index).is_err());
+
+    let archive = dir.path().join("tampered.tar.zst");
+    let encoder = zstd::Encoder::new(fs::File::create(&archive).unwrap(), 0).unwrap();
+    let mut builder = tar::Builder::new(encoder);
+    for name in ["data.mdb", MANIFEST_FILE] {
+        builder
+            .append_path_with_name(index.join(name), name)
+            .unwrap();
+    }
+    builder.into_inner().unwrap().finish().unwrap();
+
+    assert!(SearchEngine::open_uri_with_cache(archive.to_str().unwrap(), &cache).is_err());
+    // Nothing half-checked is left in the cache
+    assert_eq!(fs::read_dir(&cache).unwrap().count(), 0);
+
+    assert!(SearchEngine::open_uri_with_cache("s3://bucket-only", &cache).is_err());
+    assert!(SearchEngine::open_uri_with_cache("ftp://host/index.tar.zst", &cache).is_err());
+}
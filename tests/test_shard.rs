@@ -0,0 +1,96 @@
+use lfas::engine::SearchEngine;
+use lfas::shard::{ShardKey, ShardedEngine};
+use lfas::storage::{InMemoryStorage, LmdbOptions};
+use lfas::{DocId, Record, RecordField, StructuredQuery};
+use tempfile::tempdir;
+
+fn record(estado: &str, rua: &str) -> Record {
+    Record {
+        estado: estado.into(),
+        rua: rua.into(),
+        ..Default::default()
+    }
+}
+
+fn query(fields: &[(RecordField, &str)], top_k: usize) -> StructuredQuery<RecordField> {
+    StructuredQuery {
+        fields: fields.iter().map(|(f, t)| (*f, t.to_string())).collect(),
+        top_k,
+        ..Default::default()
+    }
+}
+
+fn in_memory(count: usize, key: ShardKey) -> ShardedEngine<InMemoryStorage<RecordField>> {
+    let shards = (0..count)
+        .map(|_| SearchEngine::with_storage(InMemoryStorage::new()))
+        .collect();
+    ShardedEngine::new(shards, key).unwrap()
+}
+
+#[test]
+fn test_hash_sharding_merges_top_k() {
+    let mut engine = in_memory(3, ShardKey::Hash);
+    let records: Vec<_> = (0..30)
+        .map(|i| (i, record("SP", &format!("Rua Pedreira {}", i))))
+        .collect();
+    engine.index_batch(&records).unwrap();
+    engine.flush().unwrap();
+
+    assert_eq!(engine.total_docs(), 30);
+    assert!(engine.shards.iter().all(|shard| shard.metadata.total_docs > 0));
+
+    let hits = engine
+        .execute(query(&[(RecordField::Rua, "Pedreira")], 10))
+        .unwrap();
+    assert_eq!(hits.len(), 10);
+    assert!(hits.windows(2).all(|pair| pair[0].score >= pair[1].score));
+}
+
+#[test]
+fn test_field_sharding_routes_by_value() {
+    let mut engine = in_memory(3, ShardKey::Field(RecordField::Estado));
+    engine.index_record(0, &record("SP", "Rua Mauriti")).unwrap();
+    engine.index_record(1, &record("sp", "Rua Pedreira")).unwrap();
+    engine.index_record(2, &record("PA", "Rua Pedreira")).unwrap();
+
+    // Values are normalized before hashing, so "SP" and "sp" share a shard
+    let sp = engine.shard_for(DocId::new(0), &record("SP", ""));
+    assert_eq!(sp, engine.shard_for(DocId::new(1), &record("sp", "")));
+    assert_ne!(sp, engine.shard_for(DocId::new(2), &record("PA", "")));
+
+    let hits = engine
+        .execute(query(&[(RecordField::Estado, "SP"), (RecordField::Rua, "Pedreira")], 5))
+        .unwrap();
+    assert_eq!(hits[0].doc_id, 1);
+    assert!(hits.iter().all(|hit| hit.doc_id != 2));
+
+    // Without the key field every shard is searched
+    let hits = engine
+        .execute(query(&[(RecordField::Rua, "Pedreira")], 5))
+        .unwrap();
+    let mut ids: Vec<_> = hits.iter().map(|hit| hit.doc_id).collect();
+    ids.sort();
+    assert_eq!(ids, vec![1, 2]);
+}
+
+#[test]
+fn test_open_rejects_different_layout() {
+    let dir = tempdir().unwrap();
+    {
+        let mut engine =
+            ShardedEngine::open(dir.path(), 2, ShardKey::Hash, LmdbOptions::new()).unwrap();
+        engine.index_record(0, &record("SP", "Rua Mauriti")).unwrap();
+        engine.flush().unwrap();
+    }
+
+    let reopened = ShardedEngine::open(dir.path(), 2, ShardKey::Hash, LmdbOptions::new()).unwrap();
+    assert_eq!(reopened.total_docs(), 1);
+
+    assert!(ShardedEngine::open(dir.path(), 3, ShardKey::Hash, LmdbOptions::new()).is_err());
+}
+
+#[test]
+fn test_new_requires_a_shard() {
+    let shards: Vec<SearchEngine<RecordField, InMemoryStorage<RecordField>>> = Vec::new();
+    assert!(ShardedEngine::new(shards, ShardKey::Hash).is_err());
+}
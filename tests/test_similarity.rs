@@ -0,0 +1,63 @@
+use lfas::engine::SearchEngine;
+use lfas::similarity::{SimilarityMetric, SimilarityReranker, jaro_winkler, trigram_cosine};
+use lfas::storage::InMemoryStorage;
+use lfas::{Record, RecordField, StructuredQuery};
+
+#[test]
+fn test_similarity_metrics() {
+    assert_eq!(jaro_winkler("mauriti", "mauriti"), 1.0);
+    assert_eq!(jaro_winkler("", "mauriti"), 0.0);
+    // Classic reference value
+    assert!((jaro_winkler("martha", "marhta") - 0.9611).abs() < 1e-3);
+    // A transposition stays much closer than an unrelated street
+    assert!(jaro_winkler("mauriti", "mauirti") > jaro_winkler("mauriti", "marques"));
+
+    assert!((trigram_cosine("mauriti", "mauriti") - 1.0).abs() < 1e-6);
+    assert_eq!(trigram_cosine("abc", "xyz"), 0.0);
+    assert!(trigram_cosine("mauriti", "mauirti") > trigram_cosine("mauriti", "marques"));
+}
+
+#[test]
+fn test_similarity_rerank_prefers_closest_string() {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+
+    let base = Record {
+        municipio: "Belem".into(),
+        numero: "31".into(),
+        ..Default::default()
+    };
+    engine
+        .index_record(0, &Record { rua: "Mauriti Marques".into(), ..base.clone() })
+        .unwrap();
+    engine
+        .index_record(1, &Record { rua: "Mauriti".into(), ..base.clone() })
+        .unwrap();
+
+    let query = StructuredQuery {
+        fields: vec![
+            (RecordField::Rua, "Mauirti".to_string()),
+            (RecordField::Numero, "31".to_string()),
+        ],
+        ..Default::default()
+    };
+
+    for metric in [SimilarityMetric::JaroWinkler, SimilarityMetric::TrigramCosine] {
+        let hits = engine
+            .execute_similar(query.clone(), &SimilarityReranker::new(metric, 1.0))
+            .unwrap();
+        assert_eq!(hits.len(), 2);
+        assert_eq!(hits[0].doc_id, 1, "{:?}", metric);
+        assert!(hits[0].score > hits[1].score);
+    }
+
+    // alpha = 0 keeps the BM25F order, normalized to the best hit
+    let plain = engine.execute(query.clone(), query.blocking_k).unwrap();
+    let blended = engine
+        .execute_similar(query, &SimilarityReranker::new(SimilarityMetric::JaroWinkler, 0.0))
+        .unwrap();
+    assert_eq!(
+        plain.iter().map(|h| h.doc_id).collect::<Vec<_>>(),
+        blended.iter().map(|h| h.doc_id).collect::<Vec<_>>()
+    );
+    assert!((blended[0].score - 1.0).abs() < 1e-6);
+}
@@ -0,0 +1,90 @@
+use lfas::engine::SearchEngine;
+use lfas::spelling::{DEFAULT_MAX_EDIT_DISTANCE, SpellIndex, edit_distance};
+use lfas::storage::InMemoryStorage;
+use lfas::{Record, RecordField, StructuredQuery};
+use tempfile::tempdir;
+
+fn engine_with_streets(streets: &[&str]) -> SearchEngine<RecordField, InMemoryStorage<RecordField>> {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, rua) in streets.iter().enumerate() {
+        let record = Record {
+            rua: rua.to_string(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    engine
+}
+
+#[test]
+fn test_edit_distance() {
+    assert_eq!(edit_distance("mauriti", "mauriti"), 0);
+    assert_eq!(edit_distance("mauriti", "mauirti"), 1);
+    assert_eq!(edit_distance("mauriti", "mauritia"), 1);
+    assert_eq!(edit_distance("belem", "bolim"), 2);
+    assert_eq!(edit_distance("", "abc"), 3);
+}
+
+#[test]
+fn test_suggest_corrections() {
+    let mut engine = engine_with_streets(&["Mauriti", "Mauriti", "Maurity", "Almirante Barroso"]);
+
+    // Nothing until the dictionary is built
+    assert!(engine.suggest_corrections(RecordField::Rua, "mauirti").is_empty());
+
+    engine.build_spell_index(DEFAULT_MAX_EDIT_DISTANCE);
+    let suggestions = engine.suggest_corrections(RecordField::Rua, "Mauirti");
+    assert_eq!(suggestions[0].term, "mauriti");
+    assert_eq!(suggestions[0].distance, 1);
+    assert_eq!(suggestions[0].df, 2);
+    assert!(suggestions.iter().any(|s| s.term == "maurity"));
+    assert!(suggestions.iter().all(|s| s.term != "barroso"));
+
+    // Per-field: the street dictionary doesn't leak into other fields
+    assert!(engine.suggest_corrections(RecordField::Bairro, "mauirti").is_empty());
+
+    // Terms indexed after the build are picked up
+    engine
+        .index_record(10, &Record { rua: "Pedreira".into(), ..Default::default() })
+        .unwrap();
+    assert_eq!(engine.suggest_corrections(RecordField::Rua, "pedrera")[0].term, "pedreira");
+}
+
+#[test]
+fn test_auto_correct_zero_df_tokens() {
+    let mut engine = engine_with_streets(&["Mauriti", "Almirante Barroso"]);
+    engine.build_spell_index(DEFAULT_MAX_EDIT_DISTANCE);
+
+    let query = StructuredQuery {
+        fields: vec![(RecordField::Rua, "Barosso".to_string())],
+        ..Default::default()
+    };
+
+    let plain = engine.execute(query.clone(), query.blocking_k).unwrap();
+    engine.auto_correct = true;
+    let corrected = engine.execute(query.clone(), query.blocking_k).unwrap();
+
+    assert_eq!(corrected[0].doc_id, 1);
+    let plain_score = plain.iter().find(|h| h.doc_id == 1).map_or(0.0, |h| h.score);
+    assert!(corrected[0].score > plain_score);
+}
+
+#[test]
+fn test_spell_index_roundtrip() {
+    let engine = {
+        let mut engine = engine_with_streets(&["Mauriti"]);
+        engine.build_spell_index(1);
+        engine
+    };
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("spelling.bin");
+
+    engine.spelling.as_ref().unwrap().save(&path).unwrap();
+    let loaded: SpellIndex<RecordField> = SpellIndex::load(&path).unwrap();
+
+    assert_eq!(loaded.max_distance(), 1);
+    assert_eq!(
+        loaded.suggest(RecordField::Rua, "mauritti", &engine.metadata)[0].term,
+        "mauriti"
+    );
+}
@@ -0,0 +1,78 @@
+use lfas::engine::SearchEngine;
+use lfas::eval::evaluate;
+use lfas::storage::InMemoryStorage;
+use lfas::testing::{AddressGenerator, Typo, abbreviation, cep_state, inject_typo};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+#[test]
+fn test_generator_is_deterministic_and_ceps_match_states() {
+    let records = AddressGenerator::new(7).records(200);
+    assert_eq!(records, AddressGenerator::new(7).records(200));
+    assert_ne!(records, AddressGenerator::new(8).records(200));
+
+    assert_eq!(records[0].id, "syn-000000");
+    for record in &records {
+        assert_eq!(record.cep.len(), 9, "{}", record.cep);
+        assert_eq!(cep_state(&record.cep), Some(record.estado.as_str()));
+        assert!(abbreviation(&record.tipo_logradouro).is_some());
+        assert!(!record.rua.is_empty() && !record.municipio.is_empty());
+    }
+
+    assert_eq!(cep_state("66095-000"), Some("PA"));
+    assert_eq!(cep_state("01310100"), Some("SP"));
+    assert_eq!(cep_state("660950"), None);
+    assert_eq!(abbreviation("PRAÇA"), Some("Pc"));
+    assert_eq!(abbreviation("praca"), Some("Pc"));
+}
+
+#[test]
+fn test_typos_change_one_letter() {
+    let mut rng = StdRng::seed_from_u64(1);
+    for _ in 0..50 {
+        let transposed = inject_typo("Mauriti", Typo::Transpose, &mut rng);
+        assert_ne!(transposed, "Mauriti");
+        assert_eq!(transposed.len(), 7);
+        assert_eq!(
+            inject_typo("Mauriti", Typo::Omit, &mut rng).chars().count(),
+            6
+        );
+        assert_eq!(
+            inject_typo("Mauriti", Typo::Double, &mut rng)
+                .chars()
+                .count(),
+            8
+        );
+        assert_ne!(
+            inject_typo("Mauriti", Typo::Substitute, &mut rng),
+            "Mauriti"
+        );
+    }
+    assert_eq!(inject_typo("31", Typo::Omit, &mut rng), "31");
+    assert_eq!(inject_typo("aa", Typo::Transpose, &mut rng), "aa");
+}
+
+#[test]
+fn test_eval_queries_find_their_records() {
+    let mut generator = AddressGenerator::new(42);
+    let corpus = generator.records(500);
+    let queries = generator.eval_queries(&corpus, 100);
+    assert_eq!(queries.len(), 100);
+    for query in &queries {
+        assert!(query.query.id.is_empty());
+        assert_eq!(query.relevant.len(), 1);
+        assert!(
+            !query
+                .non_relevant
+                .iter()
+                .any(|id| query.relevant.contains(id))
+        );
+    }
+
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for (doc_id, record) in corpus.iter().enumerate() {
+        engine.index_record(doc_id, record).unwrap();
+    }
+    let report = evaluate(&engine, &queries, 10).unwrap();
+    assert!(report.recall_at_k > 0.8, "{:?}", report);
+}
@@ -1,4 +1,4 @@
-use lfas::tokenizer::{tokenize, tokenize_structured};
+use lfas::tokenizer::{ngram_key, tokenize, tokenize_structured};
 
 #[test]
 fn test_tokenizer_include_state_name() {
@@ -78,3 +78,355 @@ fn test_distinctive_vs_all_tokens() {
     assert!(token_set.all.contains(&"belem".to_string()));
     assert!(token_set.all.contains(&"travessa".to_string()));
 }
+
+#[test]
+fn test_weak_tokens_are_weighted_below_full_tokens() {
+    let token_set = tokenize_structured("Travessa Mauriti");
+
+    assert!(token_set.weak.contains(&ngram_key("mau")));
+    assert!(!token_set.weak.contains("mauriti"));
+    assert!(token_set.weak.is_subset(&token_set.all));
+
+    let weights: std::collections::HashMap<String, f32> =
+        token_set.weighted(0.3).into_iter().collect();
+    assert_eq!(weights["mauriti"], 1.0);
+    assert_eq!(weights[&ngram_key("mau")], 0.3);
+}
+
+#[test]
+fn test_ngrams_live_in_their_own_namespace() {
+    // "rua" is both a full token and the 3-gram of itself
+    let token_set = tokenize_structured("Rua Ruas");
+
+    assert!(token_set.all.contains("rua"));
+    assert!(token_set.all.contains(&ngram_key("rua")));
+    assert!(!token_set.weak.contains("rua"));
+    assert!(token_set.weak.iter().all(|t| lfas::tokenizer::is_ngram_key(t)));
+}
+
+#[test]
+fn test_sliding_window_ngrams() {
+    use lfas::tokenizer::{TokenizerConfig, tokenize_structured_with};
+
+    let chunked = tokenize_structured("Mauriti");
+    let sliding = tokenize_structured_with("Mauriti", &TokenizerConfig::sliding(3, 1));
+
+    for gram in ["mau", "rit"] {
+        assert!(chunked.weak.contains(&ngram_key(gram)));
+    }
+    assert!(!chunked.weak.contains(&ngram_key("aur")));
+
+    let expected: std::collections::HashSet<String> = ["mau", "aur", "uri", "rit", "iti"]
+        .iter()
+        .map(|gram| ngram_key(gram))
+        .collect();
+    assert_eq!(sliding.weak, expected);
+
+    // stride 2 keeps every other window
+    let strided = tokenize_structured_with("Mauriti", &TokenizerConfig::sliding(3, 2));
+    assert_eq!(strided.weak.len(), 3);
+}
+
+#[test]
+fn test_field_token_rules() {
+    use lfas::tokenizer::{FieldTokenRules, TermPolicy, TokenizerConfig, tokenize_field};
+
+    let config = TokenizerConfig::default();
+    let dropped = tokenize_field("Alto da Serra", &config, &FieldTokenRules::default());
+    let kept = tokenize_field(
+        "Alto da Serra",
+        &config,
+        &FieldTokenRules::new(TermPolicy::Keep, TermPolicy::Keep),
+    );
+    assert!(!dropped.all.contains("da"));
+    assert!(kept.all.contains("da"));
+
+    let rules = FieldTokenRules::new(TermPolicy::Drop, TermPolicy::Demote);
+    let street = tokenize_field("Travessa Mauriti", &config, &rules);
+    let weights: std::collections::HashMap<String, f32> = street.weighted(0.3).into_iter().collect();
+    assert_eq!(weights["travessa"], rules.demoted_weight);
+    assert_eq!(weights["mauriti"], 1.0);
+
+    let without_types = tokenize_field(
+        "Travessa Mauriti",
+        &config,
+        &FieldTokenRules::new(TermPolicy::Drop, TermPolicy::Drop),
+    );
+    assert!(!without_types.all.contains("travessa"));
+    assert!(without_types.all.contains("mauriti"));
+}
+
+#[test]
+fn test_token_filter_drops_short_and_blacklisted_tokens() {
+    use lfas::tokenizer::{
+        FieldTokenRules, TokenClass, TokenFilter, TokenRule, TokenizerConfig, tokenize_debug,
+        tokenize_field,
+    };
+
+    let config = TokenizerConfig::default();
+    let text = "Av 7 Q Bela";
+    let rules = FieldTokenRules::default().with_filter(TokenFilter::address());
+    let tokens = tokenize_field(text, &config, &rules);
+    assert!(!tokens.all.contains("av"));
+    assert!(!tokens.all.contains("q"));
+    assert!(tokens.all.contains("bela"));
+    // Numbers skip the length check, and filtered tokens still form composites
+    assert!(tokens.distinctive.contains("7"));
+    assert!(tokens.distinctive.contains("av 7"));
+    let unfiltered = tokenize_field(text, &config, &FieldTokenRules::default());
+    assert!(unfiltered.all.contains("q"));
+
+    let no_numbers =
+        FieldTokenRules::default().with_filter(TokenFilter::new(2).with_numbers(false));
+    let tokens = tokenize_field(text, &config, &no_numbers);
+    assert!(!tokens.all.contains("7"));
+    assert!(tokens.all.contains("av"));
+    assert!(tokens.all.contains("av 7"));
+
+    // Blacklisted words are normalized like the text
+    let blacklist = TokenFilter::default().with_blacklist(["Belá"]);
+    let rules = FieldTokenRules::default().with_filter(blacklist);
+    let tokens = tokenize_field(text, &config, &rules);
+    assert!(!tokens.all.contains("bela"));
+    assert!(tokens.all.contains("q"));
+
+    let address = FieldTokenRules::default().with_filter(TokenFilter::address());
+    let q = tokenize_debug(text, &config, &address)
+        .into_iter()
+        .find(|trace| trace.token == "q")
+        .unwrap();
+    assert_eq!(
+        (q.class, q.rule),
+        (TokenClass::Dropped, TokenRule::Filtered)
+    );
+}
+
+#[test]
+fn test_locale_lexicons() {
+    use lfas::tokenizer::{FieldTokenRules, Locale, TokenizerConfig, tokenize_field};
+
+    let pt = TokenizerConfig::default();
+    let es = TokenizerConfig::default().with_locale(Locale::Es);
+    let rules = FieldTokenRules::default();
+
+    // "del" and "la" are only stopwords in Spanish
+    let text = "Calle 45 del Sol, La Candelaria";
+    let pt_tokens = tokenize_field(text, &pt, &rules);
+    let es_tokens = tokenize_field(text, &es, &rules);
+    assert!(pt_tokens.all.contains("del"));
+    assert!(!es_tokens.all.contains("del"));
+    assert!(!es_tokens.all.contains("la"));
+
+    // Street type + number composites follow the locale's address types
+    assert!(es_tokens.distinctive.contains("calle 45"));
+    assert!(!pt_tokens.distinctive.contains("calle 45"));
+
+    // The "Pará" fixup is Portuguese only
+    assert!(tokenize_field("Belém, Pará", &pt, &rules).all.contains("para"));
+    assert!(!tokenize_field("Belém, Pará", &es, &rules).all.contains("para"));
+
+    // A field can override the engine locale
+    let en_field = rules.with_locale(Locale::En);
+    assert!(!tokenize_field("Avenue of the Americas", &pt, &en_field).all.contains("the"));
+
+    assert_eq!(Locale::from_name("pt_BR"), Some(Locale::PtBr));
+    assert_eq!(Locale::from_name("es").map(|l| l.name()), Some("es"));
+    assert_eq!(Locale::from_name("fr"), None);
+}
+
+#[test]
+fn test_tokenize_debug_explains_every_token() {
+    use lfas::tokenizer::{
+        FieldTokenRules, TermPolicy, TokenClass, TokenRule, TokenizerConfig, tokenize_debug,
+        tokenize_field,
+    };
+    use std::collections::HashSet;
+
+    let text = "Rua 12 de Belém, 66095-000";
+    let config = TokenizerConfig::default();
+    let rules = FieldTokenRules::default();
+    let traces = tokenize_debug(text, &config, &rules);
+
+    let emitted: HashSet<String> = traces
+        .iter()
+        .filter(|trace| trace.class != TokenClass::Dropped)
+        .map(|trace| trace.token.clone())
+        .collect();
+    assert_eq!(emitted, tokenize_field(text, &config, &rules).all);
+    let starts: Vec<usize> = traces
+        .iter()
+        .map(|trace| trace.span.as_ref().unwrap().start)
+        .collect();
+    assert!(starts.is_sorted());
+
+    let find = |token: &str| traces.iter().find(|trace| trace.token == token).unwrap();
+    let belem = find("belem");
+    assert_eq!(&text[belem.span.clone().unwrap()], "Belém");
+    assert_eq!(
+        (belem.class, belem.rule),
+        (TokenClass::Scored, TokenRule::Word)
+    );
+
+    let composite = find("rua 12");
+    assert_eq!(composite.span, Some(0..6));
+    assert_eq!(composite.class, TokenClass::Distinctive);
+    assert_eq!(composite.rule, TokenRule::AddressTypeNumber);
+    assert_eq!(
+        (find("de").class, find("de").rule),
+        (TokenClass::Dropped, TokenRule::Stopword)
+    );
+    assert_eq!(find("66095-000").rule, TokenRule::Cep);
+    assert_eq!(find("12").rule, TokenRule::Number);
+
+    let gram = find(&ngram_key("bel"));
+    assert_eq!(&text[gram.span.clone().unwrap()], "Bel");
+    assert_eq!(
+        (gram.class, gram.rule),
+        (TokenClass::Weak, TokenRule::Ngram)
+    );
+
+    let demoting = FieldTokenRules::new(TermPolicy::Drop, TermPolicy::Demote);
+    let rua = tokenize_debug(text, &config, &demoting)
+        .into_iter()
+        .find(|trace| trace.token == "rua")
+        .unwrap();
+    assert_eq!(
+        (rua.class, rua.rule),
+        (TokenClass::Demoted, TokenRule::AddressType)
+    );
+}
+
+#[test]
+fn test_composite_patterns_add_distinctive_tokens() {
+    use lfas::tokenizer::{
+        CompositePattern, FieldTokenRules, TokenRule, TokenizerConfig, tokenize_debug,
+        tokenize_field,
+    };
+
+    let text = "Bloco B, Apto 302";
+    let rules = FieldTokenRules::default();
+    let plain = tokenize_field(text, &TokenizerConfig::default(), &rules);
+    assert!(!plain.distinctive.contains("apto 302"));
+
+    let config = TokenizerConfig::default()
+        .with_composite(CompositePattern::new(["Apto", "ap"], r"\d+").unwrap())
+        .with_composite(CompositePattern::new(["bloco"], "[a-z]").unwrap());
+    let tokens = tokenize_field(text, &config, &rules);
+    assert!(tokens.distinctive.contains("apto 302"));
+    assert!(tokens.distinctive.contains("bloco b"));
+    assert!(tokens.all.contains("apto 302"));
+
+    // The regex has to match the whole second token
+    let strict =
+        TokenizerConfig::default().with_composite(CompositePattern::new(["apto"], r"\d").unwrap());
+    let tokens = tokenize_field(text, &strict, &rules);
+    assert!(!tokens.distinctive.contains("apto 302"));
+
+    let traces = tokenize_debug(text, &config, &rules);
+    let apto = traces
+        .iter()
+        .find(|trace| trace.token == "apto 302")
+        .unwrap();
+    assert_eq!(apto.rule, TokenRule::Composite);
+
+    // Patterns survive the engine config round trip
+    let bytes = bincode::serialize(&config).unwrap();
+    let restored: TokenizerConfig = bincode::deserialize(&bytes).unwrap();
+    assert_eq!(restored, config);
+    assert_eq!(restored.composites[0].second(), r"\d+");
+    assert!(CompositePattern::new(["apto"], "(").is_err());
+}
+
+#[test]
+fn test_diacritics_fold_keep_or_both() {
+    use lfas::tokenizer::{
+        Diacritics, FieldTokenRules, TokenClass, TokenRule, TokenizerConfig, tokenize_debug,
+        tokenize_field,
+    };
+    use std::collections::HashSet;
+
+    let text = "Conceição do Pará";
+    let config = TokenizerConfig::default();
+    let rules = |diacritics| FieldTokenRules::default().with_diacritics(diacritics);
+
+    let folded = tokenize_field(text, &config, &rules(Diacritics::Fold));
+    assert!(folded.all.contains("conceicao") && !folded.all.contains("conceição"));
+
+    let kept = tokenize_field(text, &config, &rules(Diacritics::Keep));
+    assert!(kept.all.contains("conceição") && kept.all.contains("pará"));
+    assert!(!kept.all.contains("conceicao") && !kept.all.contains("para"));
+
+    // Both spellings indexed, and either one in a query finds the field
+    let both = tokenize_field(text, &config, &rules(Diacritics::Both));
+    assert!(both.all.contains("conceicao") && both.all.contains("conceição"));
+    assert!(!both.distinctive.contains("conceição"));
+    let query = tokenize_field("Conceicao", &config, &rules(Diacritics::Both));
+    assert!(query.all.is_subset(&both.all));
+
+    for diacritics in [Diacritics::Keep, Diacritics::Both] {
+        let traces = tokenize_debug(text, &config, &rules(diacritics));
+        let emitted: HashSet<String> = traces
+            .iter()
+            .filter(|trace| trace.class != TokenClass::Dropped)
+            .map(|trace| trace.token.clone())
+            .collect();
+        assert_eq!(emitted, tokenize_field(text, &config, &rules(diacritics)).all);
+    }
+    let traces = tokenize_debug(text, &config, &rules(Diacritics::Both));
+    let accented = traces
+        .iter()
+        .find(|trace| trace.token == "conceição")
+        .unwrap();
+    assert_eq!(accented.rule, TokenRule::Accented);
+    assert_eq!(&text[accented.span.clone().unwrap()], "Conceição");
+}
+
+#[test]
+fn test_segmenter_keeps_alphanumerics_apostrophes_and_ordinals() {
+    use lfas::tokenizer::{
+        Diacritics, FieldTokenRules, TokenClass, TokenizerConfig, normalize, tokenize_debug,
+        tokenize_field,
+    };
+    use std::collections::HashSet;
+
+    let config = TokenizerConfig::default();
+    let tokens = |text: &str| tokenize_field(text, &config, &FieldTokenRules::default());
+
+    // Whole compounds next to their parts, so "66 b" still matches "66b"
+    let number = tokens("Rua 66b, km8");
+    for token in ["66b", "66", "b", "km8", "km", "8"] {
+        assert!(number.all.contains(token), "missing {}", token);
+    }
+    assert!(number.distinctive.contains("rua 66"));
+    assert!(number.distinctive.contains("km 8"));
+    assert!(!number.distinctive.contains("rua 66b"));
+
+    let saint = tokens("Arraial D'Ajuda, Sant’Ana");
+    for token in ["dajuda", "ajuda", "santana", "sant", "ana"] {
+        assert!(saint.all.contains(token), "missing {}", token);
+    }
+    assert_eq!(normalize("Sant’Ana"), "sant'ana");
+
+    let ordinals = tokens("1ª Travessa, 3º Andar, 3° Beco");
+    for token in ["1a", "1", "3o", "3"] {
+        assert!(ordinals.all.contains(token), "missing {}", token);
+    }
+    assert_eq!(tokens("3º").all, tokens("3o").all);
+    assert_eq!(normalize("Straße Łódź Ærø"), "strasse lodz aero");
+
+    let kept = tokenize_field(
+        "D'Ajuda 12b",
+        &config,
+        &FieldTokenRules::default().with_diacritics(Diacritics::Keep),
+    );
+    assert!(kept.all.contains("dajuda") && kept.all.contains("12b"));
+
+    for text in ["Rua 66b, km8", "Arraial D'Ajuda", "1ª Travessa"] {
+        let emitted: HashSet<String> = tokenize_debug(text, &config, &FieldTokenRules::default())
+            .into_iter()
+            .filter(|trace| trace.class != TokenClass::Dropped)
+            .map(|trace| trace.token)
+            .collect();
+        assert_eq!(emitted, tokens(text).all, "{}", text);
+    }
+}
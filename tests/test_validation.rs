@@ -0,0 +1,75 @@
+use lfas::validation::{IssueKind, RecordValidator, ValidationPolicy};
+use lfas::{DocId, Record, RecordField};
+
+fn record(estado: &str, cep: &str, rua: &str) -> Record {
+    Record {
+        estado: estado.into(),
+        cep: cep.into(),
+        rua: rua.into(),
+        municipio: "Belém".into(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_fix_and_index_normalizes_records() {
+    let validator = RecordValidator::new(ValidationPolicy::FixAndIndex);
+    let mut fixable = record(" pará ", "66095000", "  Rua   Mauriti ");
+
+    let (keep, issues) = validator.validate(DocId::new(0), &mut fixable);
+    assert!(keep);
+    let kinds: Vec<_> = issues.iter().map(|issue| issue.kind).collect();
+    assert_eq!(
+        kinds,
+        vec![IssueKind::UfFormat, IssueKind::CepFormat, IssueKind::Whitespace]
+    );
+    assert_eq!(fixable.estado, "PA");
+    assert_eq!(fixable.cep, "66095-000");
+    assert_eq!(fixable.rua, "Rua Mauriti");
+    // Issues keep the value as received
+    assert_eq!(issues[0].value, " pará ");
+
+    // Unfixable values are reported but the record is still indexed
+    let mut broken = record("XX", "123", "Rua Mauriti");
+    let (keep, issues) = validator.validate(DocId::new(1), &mut broken);
+    assert!(keep);
+    assert_eq!(issues.len(), 2);
+    assert_eq!(broken.cep, "123");
+}
+
+#[test]
+fn test_reject_and_raw_policies() {
+    let records = vec![
+        (DocId::new(0), record("PA", "66095-000", "Rua Mauriti")),
+        (DocId::new(1), record("pa", "66095-000", "Rua Mauriti")),
+        (DocId::new(2), record("PA", "66095-000", "")),
+    ];
+
+    let reject = RecordValidator::new(ValidationPolicy::Reject);
+    let (accepted, report) = reject.validate_batch(records.clone());
+    assert_eq!(accepted.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![0]);
+    assert_eq!(report.checked, 3);
+    assert_eq!(report.rejected, 2);
+    assert_eq!(report.issues[1].kind, IssueKind::Missing);
+    assert_eq!(report.issues[1].field, RecordField::Rua);
+
+    let raw = RecordValidator::new(ValidationPolicy::IndexRaw);
+    let (accepted, report) = raw.validate_batch(records);
+    assert_eq!(accepted.len(), 3);
+    assert_eq!(accepted[1].1.estado, "pa");
+    assert_eq!((report.rejected, report.fixed, report.issues.len()), (0, 0, 2));
+}
+
+#[test]
+fn test_required_fields_are_configurable() {
+    let validator =
+        RecordValidator::new(ValidationPolicy::Reject).required(vec![RecordField::Cep]);
+    let mut no_cep = Record {
+        rua: "Mauriti".into(),
+        ..Default::default()
+    };
+    let (keep, issues) = validator.validate(DocId::new(0), &mut no_cep);
+    assert!(!keep);
+    assert_eq!(issues[0].field, RecordField::Cep);
+    assert_eq!(issues[0].kind, IssueKind::Missing);
+}
@@ -0,0 +1,79 @@
+use lfas::RecordField::Rua;
+use lfas::engine::SearchEngine;
+use lfas::error::LfasError;
+use lfas::query::Query;
+use lfas::storage::InMemoryStorage;
+use lfas::vectors::{VectorReranker, VectorStore};
+use lfas::{DocId, Record, RecordField};
+use tempfile::tempdir;
+
+fn engine() -> SearchEngine<RecordField, InMemoryStorage<RecordField>> {
+    let mut engine = SearchEngine::with_storage(InMemoryStorage::new());
+    for doc_id in 0..4 {
+        let record = Record {
+            rua: "Mauriti".into(),
+            ..Default::default()
+        };
+        engine.index_record(doc_id, &record).unwrap();
+    }
+    engine
+}
+
+#[test]
+fn test_vector_store_round_trips_through_a_mapped_file() {
+    let vectors = vec![(DocId::new(0), vec![1.0, 0.0]), (DocId::new(2), vec![0.6, 0.8])];
+    let store = VectorStore::from_vectors(2, vectors).unwrap();
+    assert_eq!(store.len(), 3);
+    assert_eq!(store.vector(DocId::new(1)), None);
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("vectors.bin");
+    store.save(&path).unwrap();
+    let mapped = VectorStore::open(&path).unwrap();
+    assert_eq!(mapped.dims(), 2);
+    assert_eq!(mapped.vector(DocId::new(2)), Some(vec![0.6, 0.8]));
+    assert_eq!(mapped.vector(DocId::new(3)), None);
+    assert!((mapped.cosine(DocId::new(2), &[0.0, 2.0]).unwrap() - 0.8).abs() < 1e-6);
+    assert_eq!(mapped.cosine(DocId::new(1), &[0.0, 2.0]), None);
+
+    assert!(VectorStore::from_vectors(3, vec![(DocId::new(0), vec![1.0])]).is_err());
+    std::fs::write(&path, [2, 0, 0, 0, 9, 0, 0, 0]).unwrap();
+    assert!(VectorStore::open(&path).is_err());
+}
+
+#[test]
+fn test_vector_reranker_blends_cosine_into_ranking() {
+    let mut engine = engine();
+    let query = Query::new().field(Rua, "Mauriti").top_k(3);
+
+    // Equal BM25F scores rank by doc id
+    let hits = engine.execute(query.clone().build().unwrap(), 10).unwrap();
+    let ids: Vec<DocId> = hits.iter().map(|hit| hit.doc_id).collect();
+    assert_eq!(ids, vec![0, 1, 2]);
+
+    let vectors = vec![
+        (DocId::new(0), vec![1.0, 0.0]),
+        (DocId::new(1), vec![0.0, 1.0]),
+        (DocId::new(2), vec![0.6, 0.8]),
+    ];
+    let store = VectorStore::from_vectors(2, vectors).unwrap();
+    engine.vectors = Some(VectorReranker::new(store).with_alpha(0.5));
+
+    // Without a query vector nothing changes
+    let hits = engine.execute(query.clone().build().unwrap(), 10).unwrap();
+    assert_eq!(hits[0].doc_id, 0);
+
+    // Doc 3 has no vector and falls behind the ones pointing the query's way
+    let with_vector = query.clone().vector(vec![0.0, 1.0]).build().unwrap();
+    let hits = engine.execute(with_vector, 10).unwrap();
+    let ids: Vec<DocId> = hits.iter().map(|hit| hit.doc_id).collect();
+    assert_eq!(ids, vec![1, 2, 0]);
+    assert!((hits[0].score - 1.0).abs() < 1e-6);
+    assert!((hits[1].score - 0.9).abs() < 1e-6);
+
+    let wrong_dims = query.vector(vec![1.0, 0.0, 0.0]).build().unwrap();
+    assert!(matches!(
+        engine.execute(wrong_dims, 10),
+        Err(LfasError::InvalidQuery(_))
+    ));
+}